@@ -0,0 +1,65 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use rxml::Lexer;
+
+/// Generate a document which consists mostly of element and attribute
+/// markup.
+fn markup_document() -> Vec<u8> {
+	let mut doc = b"<?xml version='1.0'?><root xmlns='urn:example'>".to_vec();
+	for i in 0..4096 {
+		doc.extend_from_slice(
+			format!(
+				"<item id='{}' type=\"entry\" xml:lang='en'><name>n{}</name><value unit='ms'/></item>\n",
+				i, i
+			)
+			.as_bytes(),
+		);
+	}
+	doc.extend_from_slice(b"</root>");
+	doc
+}
+
+/// Generate a document which consists mostly of text.
+fn text_document() -> Vec<u8> {
+	let mut doc = b"<?xml version='1.0'?><root>".to_vec();
+	for _ in 0..256 {
+		doc.extend_from_slice(b"<p>");
+		for _ in 0..16 {
+			doc.extend_from_slice(
+				"Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua. Grüße! ".as_bytes(),
+			);
+		}
+		doc.extend_from_slice(b"</p>\n");
+	}
+	doc.extend_from_slice(b"</root>");
+	doc
+}
+
+fn lex_all(doc: &[u8]) -> usize {
+	let mut lexer = Lexer::new();
+	let mut buf = doc;
+	let mut n = 0usize;
+	while let Some(_) = lexer.lex_bytes(&mut buf, true).unwrap() {
+		n += 1;
+	}
+	n
+}
+
+fn lexer(c: &mut Criterion) {
+	let mut group = c.benchmark_group("lexer");
+
+	let markup = markup_document();
+	group.throughput(Throughput::Bytes(markup.len() as u64));
+	group.bench_function("markup", |b| {
+		b.iter(|| black_box(lex_all(black_box(&markup[..]))));
+	});
+
+	let text = text_document();
+	group.throughput(Throughput::Bytes(text.len() as u64));
+	group.bench_function("text", |b| {
+		b.iter(|| black_box(lex_all(black_box(&text[..]))));
+	});
+}
+
+criterion_group!(benches, lexer);
+criterion_main!(benches);
@@ -1,6 +1,6 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
-use rxml::{EventRead, FeedParser, PullParser};
+use rxml::{as_eof_flag, EventRead, FeedParser, PullParser};
 
 static HUGE_STANZA: &'static [u8] =  br#"<iq to='loadtest0@conference.example.com/63653b5f'
 id='b3JhE-1363385' type='set'><jingle xmlns='urn:xmpp:jingle:1' action='session-initiate'
@@ -2943,29 +2943,80 @@ fn huge_document(c: &mut Criterion) {
 		b.iter(|| {
 			evs.clear();
 			let mut p = FeedParser::default();
-			p.feed(&HUGE_STANZA[..]);
-			p.feed_eof();
-			assert!(p
-				.read_all_eof(|ev| {
-					evs.push(ev);
-				})
-				.unwrap());
+			assert!(as_eof_flag(p.parse_all(&mut &HUGE_STANZA[..], true, |ev| {
+				evs.push(ev);
+			}))
+			.unwrap());
 		});
 	});
 
 	group.bench_function("streamed", |b| {
 		let mut evs = Vec::with_capacity(1024);
 		let mut p = FeedParser::default();
-		p.feed(&b"<?xml version='1.0'?><root>"[..]);
+		assert!(
+			!as_eof_flag(p.parse_all(&mut &b"<?xml version='1.0'?><root>"[..], false, |_| ()))
+				.unwrap()
+		);
 
 		b.iter(|| {
 			evs.clear();
-			p.feed(&HUGE_STANZA[..]);
-			assert!(!p
-				.read_all_eof(|ev| {
+			assert!(
+				!as_eof_flag(p.parse_all(&mut &HUGE_STANZA[..], false, |ev| {
 					evs.push(ev);
-				})
-				.unwrap());
+				}))
+				.unwrap()
+			);
+		});
+	});
+}
+
+fn text_heavy_document(c: &mut Criterion) {
+	let mut group = c.benchmark_group("text_heavy_document");
+
+	let mut text_doc = b"<?xml version='1.0'?><root>".to_vec();
+	for i in 0..256 {
+		text_doc.extend_from_slice(b"<p>");
+		for _ in 0..16 {
+			text_doc.extend_from_slice(
+				"Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua. Grüße! ".as_bytes(),
+			);
+		}
+		if i % 2 == 0 {
+			text_doc.extend_from_slice(b"\n\t&amp; ");
+		}
+		text_doc.extend_from_slice(b"</p>");
+	}
+	text_doc.extend_from_slice(b"</root>");
+
+	group.bench_function("text", |b| {
+		b.iter(|| {
+			let mut p = FeedParser::default();
+			let mut n = 0usize;
+			assert!(as_eof_flag(p.parse_all(&mut &text_doc[..], true, |_| n += 1)).unwrap());
+			black_box(n);
+		});
+	});
+
+	let mut attr_doc = b"<?xml version='1.0'?><root>".to_vec();
+	for _ in 0..1024 {
+		attr_doc.extend_from_slice(b"<item a='");
+		for _ in 0..4 {
+			attr_doc.extend_from_slice(b"some reasonably long attribute value ");
+		}
+		attr_doc.extend_from_slice(b"' b=\"");
+		for _ in 0..4 {
+			attr_doc.extend_from_slice(b"and another one with &quot;quotes&quot; ");
+		}
+		attr_doc.extend_from_slice(b"\"/>");
+	}
+	attr_doc.extend_from_slice(b"</root>");
+
+	group.bench_function("attributes", |b| {
+		b.iter(|| {
+			let mut p = FeedParser::default();
+			let mut n = 0usize;
+			assert!(as_eof_flag(p.parse_all(&mut &attr_doc[..], true, |_| n += 1)).unwrap());
+			black_box(n);
 		});
 	});
 }
@@ -2973,6 +3024,6 @@ fn huge_document(c: &mut Criterion) {
 criterion_group! {
 	name = benches;
 	config = Criterion::default().sample_size(300);
-	targets = short_document, huge_document
+	targets = short_document, huge_document, text_heavy_document
 }
 criterion_main!(benches);
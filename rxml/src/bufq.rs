@@ -1,9 +1,69 @@
-use std::borrow::Cow;
-use std::collections::VecDeque;
+use alloc::borrow::Cow;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Deref;
+#[cfg(feature = "std")]
 use std::io;
 
+use bytes::Bytes;
+#[cfg(feature = "std")]
+use bytes::BytesMut;
+
+#[cfg(feature = "std")]
 pub const ERR_NODATA: &'static str = "no data in buffer";
 
+#[cfg(feature = "std")]
+const DEFAULT_READ_CAPACITY: usize = 8192;
+
+/// Error returned when data cannot be added to a [`BufferQueue`].
+///
+/// See [`BufferQueue::try_push()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PushError {
+	/// [`BufferQueue::push_eof()`] has been called already.
+	AfterEof,
+	/// The total length of the queued data would not fit into a `usize`.
+	LengthOverflow,
+}
+
+impl fmt::Display for PushError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::AfterEof => f.write_str("cannot push behind eof"),
+			Self::LengthOverflow => f.write_str("length overflow"),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PushError {}
+
+/// Storage of a single buffer in a [`BufferQueue`].
+enum Data<'x> {
+	Cow(Cow<'x, [u8]>),
+	Bytes(Bytes),
+}
+
+/// A single buffer in a [`BufferQueue`].
+struct Chunk<'x> {
+	data: Data<'x>,
+	/// Whether the buffer is known to be valid UTF-8.
+	utf8: bool,
+}
+
+impl<'x> Deref for Chunk<'x> {
+	type Target = [u8];
+
+	fn deref(&self) -> &[u8] {
+		match self.data {
+			Data::Cow(ref v) => v,
+			Data::Bytes(ref v) => v,
+		}
+	}
+}
+
 /**
 # Zero-copy buffered reader for a queue of byte slices.
 
@@ -12,9 +72,55 @@ approach, it turned out to be non-viable. In version 0.7.0, the
 [`BufferQueue`] was deprecated in favour of recommending the use of
 [`bytes::Buf`] with the redesigned [`PushDriver`] / [`FeedParser`]. *End of deprecation notice.*
 
-The BufferQueue acts as a [`std::io::BufRead`] and [`std::io::Read`]. The
-data which is to be read from it must be provided using the
-[`BufferQueue::push()`] method.
+The BufferQueue acts as a [`std::io::BufRead`], a [`std::io::Read`] and a
+[`bytes::Buf`] (the `io` traits are only implemented with the `std`
+feature). The data which is to be read from it must be provided using
+the [`BufferQueue::push()`] or [`BufferQueue::push_bytes()`] methods.
+
+As it implements [`bytes::Buf`], a BufferQueue can be passed directly to the
+[`PushDriver`] / [`FeedParser`]. This is useful if data arrives in multiple
+chunks which should be handed to the parser in one go.
+
+## Zero-copy operation with `Bytes`
+
+Borrowed slices tie the lifetime of the queue to the lifetime of the
+buffers, which is impractical in most network applications. Chunks which
+arrive as [`bytes::Bytes`] (as is common with tokio) can be pushed using
+[`BufferQueue::push_bytes()`] instead: they are reference-counted into the
+queue without copying and without lifetime restrictions, so that a
+`BufferQueue<'static>` can be kept around for the lifetime of a connection.
+
+## Text input
+
+Data which is already available as text (for example from WebSocket text
+frames) can be pushed with [`BufferQueue::push_str()`]. The lexer then
+knows that those buffers are valid UTF-8 and skips validating them again
+when the queue is passed to [`Lexer::lex_queue()`] or
+[`PushDriver::parse_queue()`], or read by a [`PullDriver`] created with
+[`PullDriver::from_queue()`].
+
+## Limiting the amount of buffered data
+
+A high-water mark can be configured using [`BufferQueue::set_limit()`].
+Pushing data is still possible when the limit is exceeded (data which has
+already been received has to go somewhere), but
+[`BufferQueue::is_full()`] will then return true. Callers should stop
+reading from their data source until enough data has been consumed from
+the queue, for instance by the parser. This prevents a peer from forcing
+an unbounded amount of data to be buffered.
+
+## Fragmentation
+
+Feeding many small buffers (for example, one per TCP segment) makes
+reading from the queue slow, as each buffer has to be handled separately.
+Using [`BufferQueue::set_coalesce_threshold()`], buffers smaller than a
+threshold are copied into a shared block instead of being queued
+individually. In addition, [`BufferQueue::compact()`] can be used to merge
+all queued data into a single buffer at a convenient time.
+
+**Note:** [`FeedParser::parse`] accepts any [`bytes::Buf`], including
+[`bytes::Bytes`] itself. A BufferQueue is only needed if multiple chunks
+have to be queued.
 
 When a buffer which has been pushed to the queue has been read completely, it
 is dropped. Users can read arbitrary subsequences of buffers at a time, but
@@ -26,6 +132,8 @@ When more data is being read than being pushed, a
 ## Example
 
 ```
+# #[cfg(feature = "std")]
+# fn main() {
 use std::io::{Read, ErrorKind};
 use rxml::{BufferQueue, Error};
 let mut bq = BufferQueue::new();
@@ -47,19 +155,47 @@ assert_eq!(bq.read(&mut buf).unwrap(), 4);
 assert_eq!(&buf[..4], b"2342");
 // zero-length read on eof
 assert_eq!(bq.read(&mut buf).unwrap(), 0);
+# }
+# #[cfg(not(feature = "std"))]
+# fn main() {}
+```
+
+## Example with `Bytes` and a parser
+
+```
+use bytes::Bytes;
+use rxml::{BufferQueue, FeedParser, ResolvedEvent};
+
+let mut bq = BufferQueue::new();
+bq.push_bytes(Bytes::from_static(b"<hello>Wor"));
+bq.push_bytes(Bytes::from_static(b"ld!</hello>"));
+bq.push_eof();
+let mut fp = FeedParser::default();
+let mut events = Vec::new();
+let eof = bq.eof_pushed();
+fp.parse_all(&mut bq, eof, |ev| events.push(ev)).unwrap();
+assert_eq!(events.len(), 3);
+assert_eq!(bq.len(), 0);
 ```
 
    [`PushDriver`]: crate::driver::PushDriver
+   [`PushDriver::parse_queue()`]: crate::driver::PushDriver::parse_queue
+   [`PullDriver`]: crate::driver::PullDriver
+   [`PullDriver::from_queue()`]: crate::driver::PullDriver::from_queue
+   [`Lexer::lex_queue()`]: crate::Lexer::lex_queue
    [`FeedParser`]: crate::FeedParser
+   [`FeedParser::parse`]: crate::driver::PushDriver::parse
 */
 #[deprecated(
 	since = "0.7.0",
 	note = "no replacement, `bytes::Buf` and the `FeedParser`/`PushDriver` may be helpful in many cases"
 )]
 pub struct BufferQueue<'x> {
-	q: VecDeque<Cow<'x, [u8]>>,
+	q: VecDeque<Chunk<'x>>,
 	offset: usize,
 	len: usize,
+	limit: Option<usize>,
+	coalesce_threshold: usize,
 	eof: bool,
 }
 
@@ -71,28 +207,298 @@ impl<'x> BufferQueue<'x> {
 			q: VecDeque::new(),
 			offset: 0,
 			len: 0,
+			limit: None,
+			coalesce_threshold: 0,
 			eof: false,
 		}
 	}
 
+	/// Create a new, empty buffer queue with a limit on the number of
+	/// buffered bytes.
+	///
+	/// See [`BufferQueue::set_limit()`] for details.
+	pub fn with_limit(limit: usize) -> Self {
+		let mut result = Self::new();
+		result.limit = Some(limit);
+		result
+	}
+
+	/// Configure the maximum number of bytes which should be buffered.
+	///
+	/// The limit is not enforced by [`BufferQueue::push()`]; instead,
+	/// [`BufferQueue::is_full()`] returns true if the limit has been reached
+	/// or exceeded, which should be used by the caller to stop reading from
+	/// the source. `None` disables the limit (the default).
+	pub fn set_limit(&mut self, limit: Option<usize>) {
+		self.limit = limit;
+	}
+
+	/// Return the currently configured limit, if any.
+	#[inline]
+	pub fn limit(&self) -> Option<usize> {
+		self.limit
+	}
+
+	/// Return true if the number of buffered bytes reached or exceeds the
+	/// configured limit.
+	///
+	/// Always returns false if no limit is configured.
+	#[inline]
+	pub fn is_full(&self) -> bool {
+		match self.limit {
+			Some(limit) => self.len >= limit,
+			None => false,
+		}
+	}
+
+	/// Number of bytes which can be pushed before the limit is reached.
+	///
+	/// Returns `None` if no limit is configured. This can be used to size
+	/// reads from the source.
+	#[inline]
+	pub fn remaining_capacity(&self) -> Option<usize> {
+		self.limit.map(|limit| limit.saturating_sub(self.len))
+	}
+
 	/// Add the given buffer to the end of the queue.
 	///
 	/// Any data which can be converted into a `[u8]` Cow can be passed.
 	///
 	/// # Panics
 	///
-	/// If [`BufferQueue::push_eof`] has been called.
+	/// If [`BufferQueue::push_eof`] has been called. Use
+	/// [`BufferQueue::try_push`] where that may happen, for instance because
+	/// data arriving and the end of a connection race against each other.
 	pub fn push<'a: 'x, T: Into<Cow<'a, [u8]>>>(&mut self, new: T) {
-		let new = new.into();
+		if let Err(e) = self.try_push(new) {
+			panic!("{}", e);
+		}
+	}
+
+	/// Add the given buffer to the end of the queue, unless the end of file
+	/// has been signalled already.
+	///
+	/// This is the non-panicking variant of [`BufferQueue::push`]. On
+	/// error, the queue is not modified.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::{BufferQueue, PushError};
+	/// let mut bq = BufferQueue::new();
+	/// bq.try_push(&b"foo"[..]).unwrap();
+	/// bq.push_eof();
+	/// assert_eq!(bq.try_push(&b"bar"[..]), Err(PushError::AfterEof));
+	/// assert_eq!(bq.len(), 3);
+	/// ```
+	pub fn try_push<'a: 'x, T: Into<Cow<'a, [u8]>>>(&mut self, new: T) -> Result<(), PushError> {
+		self.push_chunk(Chunk {
+			data: Data::Cow(new.into()),
+			utf8: false,
+		})
+	}
+
+	/// Add the given text to the end of the queue.
+	///
+	/// The data is handled like buffers added with [`BufferQueue::push()`],
+	/// but it is marked as valid UTF-8, so that the lexer does not need to
+	/// validate it again. See the [type-level documentation](Self) for
+	/// which consumers make use of that.
+	///
+	/// # Panics
+	///
+	/// If [`BufferQueue::push_eof`] has been called. See
+	/// [`BufferQueue::try_push_str`] for a non-panicking variant.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::{BufferQueue, FeedParser, ResolvedEvent};
+	///
+	/// let mut bq = BufferQueue::new();
+	/// bq.push_str("<hello>Wör");
+	/// bq.push_str(String::from("ld!</hello>"));
+	/// bq.push_eof();
+	/// let mut fp = FeedParser::default();
+	/// let mut events = Vec::new();
+	/// fp.parse_all_queue(&mut bq, true, |ev| events.push(ev)).unwrap();
+	/// match &events[1] {
+	/// 	ResolvedEvent::Text(_, text) => assert_eq!(text, "Wörld!"),
+	/// 	other => panic!("unexpected event: {:?}", other),
+	/// }
+	/// ```
+	pub fn push_str<'a: 'x, T: Into<Cow<'a, str>>>(&mut self, new: T) {
+		if let Err(e) = self.try_push_str(new) {
+			panic!("{}", e);
+		}
+	}
+
+	/// Add the given text to the end of the queue, unless the end of file
+	/// has been signalled already.
+	///
+	/// This is the non-panicking variant of [`BufferQueue::push_str`].
+	pub fn try_push_str<'a: 'x, T: Into<Cow<'a, str>>>(&mut self, new: T) -> Result<(), PushError> {
+		let data = match new.into() {
+			Cow::Borrowed(v) => Cow::Borrowed(v.as_bytes()),
+			Cow::Owned(v) => Cow::Owned(v.into_bytes()),
+		};
+		self.push_chunk(Chunk {
+			data: Data::Cow(data),
+			utf8: true,
+		})
+	}
+
+	/// Add the given reference-counted buffer to the end of the queue.
+	///
+	/// The data is not copied.
+	///
+	/// # Panics
+	///
+	/// If [`BufferQueue::push_eof`] has been called. See
+	/// [`BufferQueue::try_push_bytes`] for a non-panicking variant.
+	pub fn push_bytes(&mut self, new: Bytes) {
+		if let Err(e) = self.try_push_bytes(new) {
+			panic!("{}", e);
+		}
+	}
+
+	/// Add the given reference-counted buffer to the end of the queue,
+	/// unless the end of file has been signalled already.
+	///
+	/// This is the non-panicking variant of [`BufferQueue::push_bytes`].
+	pub fn try_push_bytes(&mut self, new: Bytes) -> Result<(), PushError> {
+		self.push_chunk(Chunk {
+			data: Data::Bytes(new),
+			utf8: false,
+		})
+	}
+
+	fn push_chunk(&mut self, new: Chunk<'x>) -> Result<(), PushError> {
 		if self.eof {
-			panic!("cannot push behind eof");
+			return Err(PushError::AfterEof);
+		}
+		if new.is_empty() {
+			return Ok(());
 		}
 		let new_len = match self.len.checked_add(new.len()) {
-			None => panic!("length overflow"),
+			None => return Err(PushError::LengthOverflow),
 			Some(v) => v,
 		};
-		self.q.push_back(new);
+		if new.len() < self.coalesce_threshold {
+			self.coalesce(&new, new.utf8);
+		} else {
+			self.q.push_back(new);
+		}
 		self.len = new_len;
+		Ok(())
+	}
+
+	/// Append a small buffer to an owned block at the end of the queue,
+	/// starting a new block if needed.
+	fn coalesce(&mut self, new: &[u8], utf8: bool) {
+		if let Some(Chunk {
+			data: Data::Cow(Cow::Owned(ref mut block)),
+			utf8: ref mut block_utf8,
+		}) = self.q.back_mut()
+		{
+			if block.len() < self.coalesce_threshold {
+				block.extend_from_slice(new);
+				*block_utf8 = *block_utf8 && utf8;
+				return;
+			}
+		}
+		let mut block = Vec::with_capacity(self.coalesce_threshold.max(new.len()));
+		block.extend_from_slice(new);
+		self.q.push_back(Chunk {
+			data: Data::Cow(Cow::Owned(block)),
+			utf8,
+		});
+	}
+
+	/// Configure the size below which pushed buffers are coalesced.
+	///
+	/// Buffers shorter than the given number of bytes are copied into an
+	/// owned block at the end of the queue, which is extended until it
+	/// reaches the threshold. Larger buffers are queued without copying.
+	///
+	/// Zero disables coalescing (the default). Buffers which have already
+	/// been pushed are not affected; use [`BufferQueue::compact()`] for
+	/// those.
+	pub fn set_coalesce_threshold(&mut self, threshold: usize) {
+		self.coalesce_threshold = threshold;
+	}
+
+	/// Return the currently configured coalescing threshold.
+	#[inline]
+	pub fn coalesce_threshold(&self) -> usize {
+		self.coalesce_threshold
+	}
+
+	/// Remove and return all unread data from the queue.
+	///
+	/// This is useful to recover data which has not been consumed by a
+	/// parser, for instance because it follows the end of the document. The
+	/// end-of-file marker is not affected.
+	pub fn take_remainder(&mut self) -> Vec<u8> {
+		let mut result = Vec::with_capacity(self.len);
+		for (i, chunk) in self.q.drain(..).enumerate() {
+			if i == 0 {
+				result.extend_from_slice(&chunk[self.offset..]);
+			} else {
+				result.extend_from_slice(&chunk);
+			}
+		}
+		self.offset = 0;
+		self.len = 0;
+		result
+	}
+
+	/// Number of separate buffers currently held by the queue.
+	#[inline]
+	pub fn buffer_count(&self) -> usize {
+		self.q.len()
+	}
+
+	/// Merge all unread data into a single contiguous buffer.
+	///
+	/// This copies the unread data and releases all queued buffers,
+	/// including the already-read part of a partially read buffer. It is a
+	/// no-op if the queue holds at most one buffer which has not been
+	/// partially read.
+	pub fn compact(&mut self) {
+		if self.q.len() <= 1 && self.offset == 0 {
+			return;
+		}
+		let mut block = Vec::with_capacity(self.len);
+		let mut utf8 = true;
+		for (i, chunk) in self.q.iter().enumerate() {
+			utf8 = utf8 && chunk.utf8;
+			if i == 0 {
+				block.extend_from_slice(&chunk[self.offset..]);
+			} else {
+				block.extend_from_slice(chunk);
+			}
+		}
+		debug_assert_eq!(block.len(), self.len);
+		self.q.clear();
+		self.offset = 0;
+		self.q.push_back(Chunk {
+			data: Data::Cow(Cow::Owned(block)),
+			utf8,
+		});
+	}
+
+	/// Return true if the buffer at the front of the queue has been pushed
+	/// as text.
+	///
+	/// The unread part of such a buffer is a suffix of a valid UTF-8
+	/// string.
+	#[inline]
+	pub(crate) fn chunk_is_utf8(&self) -> bool {
+		match self.q.front() {
+			Some(chunk) => chunk.utf8,
+			None => false,
+		}
 	}
 
 	/// Number of bytes which have been enqueued, but not read yet.
@@ -119,7 +525,8 @@ impl<'x> BufferQueue<'x> {
 	/// Return whether the end-of-file marker has been pushed to the queue
 	/// already.
 	///
-	/// [`BufferQueue::push()`] will panic if this function returns true.
+	/// [`BufferQueue::push()`] will panic if this function returns true, and
+	/// [`BufferQueue::try_push()`] will fail.
 	#[inline]
 	pub fn eof_pushed(&self) -> bool {
 		self.eof
@@ -133,10 +540,46 @@ impl<'x> BufferQueue<'x> {
 	#[inline]
 	pub fn clear(&mut self) {
 		self.q.clear();
+		self.offset = 0;
 		self.len = 0;
 	}
 }
 
+#[allow(deprecated)]
+impl bytes::Buf for BufferQueue<'_> {
+	fn remaining(&self) -> usize {
+		self.len
+	}
+
+	fn chunk(&self) -> &[u8] {
+		match self.q.front() {
+			None => &[],
+			Some(v) => &v[self.offset..],
+		}
+	}
+
+	/// Skip forward by `cnt` bytes.
+	///
+	/// In contrast to [`std::io::BufRead::consume`], this may cross buffer
+	/// boundaries.
+	///
+	/// # Panics
+	///
+	/// If `cnt` is larger than the number of bytes in the queue.
+	fn advance(&mut self, mut cnt: usize) {
+		if cnt > self.len {
+			panic!("attempt to consume beyond end of buffer");
+		}
+		while cnt > 0 {
+			let chunk_len = self.q.front().unwrap().len() - self.offset;
+			let step = cnt.min(chunk_len);
+			self.consume_front(step);
+			cnt -= step;
+		}
+	}
+}
+
+#[cfg(feature = "std")]
 #[allow(deprecated)]
 impl io::Read for BufferQueue<'_> {
 	/// Read from the buffer queue.
@@ -177,6 +620,7 @@ impl io::Read for BufferQueue<'_> {
 	}
 }
 
+#[cfg(feature = "std")]
 #[allow(deprecated)]
 impl io::BufRead for BufferQueue<'_> {
 	/// Return the current buffer contents
@@ -206,6 +650,14 @@ impl io::BufRead for BufferQueue<'_> {
 	///
 	/// If the `amt` bytes is larger than what `fill_buf()` would return.
 	fn consume(&mut self, amt: usize) {
+		self.consume_front(amt)
+	}
+}
+
+#[allow(deprecated)]
+impl BufferQueue<'_> {
+	/// Skip forward by `amt` bytes within the front buffer.
+	fn consume_front(&mut self, amt: usize) {
 		if amt == 0 {
 			return;
 		}
@@ -230,7 +682,157 @@ impl io::BufRead for BufferQueue<'_> {
 	}
 }
 
-#[cfg(test)]
+/**
+# Buffered reader on top of a [`BufferQueue`]
+
+The QueueReader turns any [`std::io::Read`] into a [`std::io::BufRead`], so
+that it can be used with a [`PullDriver`]. Whenever the queue runs empty,
+up to `capacity` bytes are read from the inner reader into a new
+[`bytes::Bytes`] buffer, which is then pushed to the queue. When the inner
+reader signals the end of file, it is pushed to the queue as well.
+
+The capacity bounds the amount of data read ahead, and thus the amount of
+data which has been taken from the inner reader without having been
+consumed yet. That data is not lost: [`QueueReader::into_inner()`] returns
+the queue together with the reader.
+
+I/O errors of the inner reader, including
+[`std::io::ErrorKind::WouldBlock`], are passed on unchanged, except for
+[`std::io::ErrorKind::Interrupted`], on which the read is retried.
+
+## Example
+
+```
+use rxml::{EventRead, PullParser};
+
+let mut pp = PullParser::from_reader_with_capacity(8, &b"<a>hello world</a>trailer"[..]);
+while !pp.is_document_complete() {
+	pp.read().unwrap();
+}
+// the data read ahead is kept in the queue, the rest is still in the reader
+let (rest, mut queue) = pp.into_inner().into_inner();
+assert_eq!(&queue.take_remainder()[..], b"traile");
+assert_eq!(rest, b"r");
+```
+
+   [`PullDriver`]: crate::driver::PullDriver
+*/
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+#[allow(deprecated)]
+pub struct QueueReader<R> {
+	inner: R,
+	queue: BufferQueue<'static>,
+	buf: BytesMut,
+	capacity: usize,
+}
+
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+impl<R: io::Read> QueueReader<R> {
+	/// Wrap a reader, using a default capacity of 8 kiB.
+	pub fn new(inner: R) -> Self {
+		Self::with_capacity(DEFAULT_READ_CAPACITY, inner)
+	}
+
+	/// Wrap a reader, reading at most `capacity` bytes at once.
+	///
+	/// # Panics
+	///
+	/// If `capacity` is zero.
+	pub fn with_capacity(capacity: usize, inner: R) -> Self {
+		assert!(capacity > 0, "capacity must be non-zero");
+		Self {
+			inner,
+			queue: BufferQueue::new(),
+			buf: BytesMut::new(),
+			capacity,
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+impl<R> QueueReader<R> {
+	/// Maximum number of bytes read from the inner reader at once.
+	pub fn capacity(&self) -> usize {
+		self.capacity
+	}
+
+	/// Access the inner reader.
+	pub fn get_ref(&self) -> &R {
+		&self.inner
+	}
+
+	/// Access the inner reader, mutably.
+	///
+	/// Reading from the inner reader directly while data is still queued
+	/// will reorder the input.
+	pub fn get_mut(&mut self) -> &mut R {
+		&mut self.inner
+	}
+
+	/// Access the queue holding the data which has been read, but not
+	/// consumed yet.
+	pub fn queue(&self) -> &BufferQueue<'static> {
+		&self.queue
+	}
+
+	/// Decompose the reader into the inner reader and the queue holding the
+	/// data which has been read, but not consumed yet.
+	pub fn into_inner(self) -> (R, BufferQueue<'static>) {
+		(self.inner, self.queue)
+	}
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read> QueueReader<R> {
+	fn fill_queue(&mut self) -> io::Result<()> {
+		if self.queue.len() > 0 || self.queue.eof_pushed() {
+			return Ok(());
+		}
+		// the buffer may be reused if all Bytes split off from it have been
+		// dropped already
+		self.buf.clear();
+		self.buf.resize(self.capacity, 0);
+		let n = loop {
+			match self.inner.read(&mut self.buf[..]) {
+				Ok(n) => break n,
+				Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+				Err(e) => return Err(e),
+			}
+		};
+		if n == 0 {
+			self.queue.push_eof();
+		} else {
+			self.buf.truncate(n);
+			self.queue.push_bytes(self.buf.split().freeze());
+		}
+		Ok(())
+	}
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read> io::Read for QueueReader<R> {
+	fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
+		self.fill_queue()?;
+		self.queue.read(dst)
+	}
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read> io::BufRead for QueueReader<R> {
+	fn fill_buf(&mut self) -> io::Result<&[u8]> {
+		self.fill_queue()?;
+		self.queue.fill_buf()
+	}
+
+	fn consume(&mut self, amt: usize) {
+		self.queue.consume(amt)
+	}
+}
+
+#[cfg(all(test, feature = "std"))]
 #[allow(deprecated)]
 mod tests {
 	use super::*;
@@ -332,6 +934,21 @@ mod tests {
 		bq.push(s2.to_vec());
 	}
 
+	#[test]
+	fn bufq_try_push_fails_after_eof() {
+		let mut bq = BufferQueue::new();
+		assert_eq!(bq.try_push(b"foo".to_vec()), Ok(()));
+		bq.push_eof();
+		assert_eq!(bq.try_push(b"bar".to_vec()), Err(PushError::AfterEof));
+		assert_eq!(
+			bq.try_push_bytes(Bytes::from_static(b"bar")),
+			Err(PushError::AfterEof)
+		);
+		// pushing nothing is an error, too, as it is still a mistake
+		assert_eq!(bq.try_push(&b""[..]), Err(PushError::AfterEof));
+		assert_eq!(bq.len(), 3);
+	}
+
 	#[test]
 	fn bufq_read_reduces_length() {
 		let s1 = b"foo";
@@ -593,4 +1210,268 @@ mod tests {
 		assert_eq!(bq.len(), 0);
 		assert_eq!(bq.fill_buf().unwrap(), b"");
 	}
+
+	#[test]
+	fn bufq_accepts_bytes_without_copying() {
+		let data = Bytes::from_static(b"foobar");
+		let ptr = data.as_ptr();
+		let mut bq = BufferQueue::new();
+		bq.push_bytes(data);
+		assert_eq!(bq.len(), 6);
+		let front = bq.fill_buf().unwrap();
+		assert_eq!(front, b"foobar");
+		assert_eq!(front.as_ptr(), ptr);
+	}
+
+	#[test]
+	fn bufq_mixes_bytes_and_slices() {
+		let mut bq = BufferQueue::new();
+		bq.push(&b"foo"[..]);
+		bq.push_bytes(Bytes::from_static(b"bar"));
+		let mut buf = [0; 3];
+		assert_eq!(bq.read(&mut buf[..]).unwrap(), 3);
+		assert_eq!(&buf[..], b"foo");
+		assert_eq!(bq.read(&mut buf[..]).unwrap(), 3);
+		assert_eq!(&buf[..], b"bar");
+	}
+
+	#[test]
+	fn bufq_buf_advance_crosses_buffer_boundaries() {
+		use bytes::Buf;
+
+		let mut bq = BufferQueue::new();
+		bq.push_bytes(Bytes::from_static(b"foo"));
+		bq.push_bytes(Bytes::from_static(b"bar"));
+		bq.push_bytes(Bytes::from_static(b"2342"));
+		assert_eq!(bq.remaining(), 10);
+		assert_eq!(Buf::chunk(&bq), b"foo");
+		bq.advance(4);
+		assert_eq!(bq.remaining(), 6);
+		assert_eq!(Buf::chunk(&bq), b"ar");
+		assert_eq!(bq.copy_to_bytes(6), &b"ar2342"[..]);
+		assert_eq!(Buf::chunk(&bq), b"");
+	}
+
+	#[test]
+	#[should_panic(expected = "attempt to consume beyond end of buffer")]
+	fn bufq_buf_advance_beyond_end_panics() {
+		use bytes::Buf;
+
+		let mut bq = BufferQueue::new();
+		bq.push_bytes(Bytes::from_static(b"foo"));
+		bq.advance(4);
+	}
+
+	#[test]
+	fn bufq_can_feed_parser() {
+		let mut bq = BufferQueue::new();
+		bq.push_bytes(Bytes::from_static(b"<a>fo"));
+		bq.push_bytes(Bytes::from_static(b"o</a>"));
+		let mut fp = crate::FeedParser::default();
+		let mut n = 0;
+		match crate::as_eof_flag(fp.parse_all(&mut bq, false, |_| n += 1)) {
+			Ok(false) => (),
+			other => panic!("unexpected parse result: {:?}", other),
+		}
+		assert_eq!(bq.len(), 0);
+		bq.push_eof();
+		match fp.parse_all(&mut bq, true, |_| n += 1) {
+			Ok(()) => (),
+			other => panic!("unexpected parse result: {:?}", other),
+		}
+		assert_eq!(n, 3);
+	}
+
+	#[test]
+	fn bufq_without_limit_is_never_full() {
+		let mut bq = BufferQueue::new();
+		bq.push(vec![0u8; 65536]);
+		assert!(!bq.is_full());
+		assert_eq!(bq.limit(), None);
+		assert_eq!(bq.remaining_capacity(), None);
+	}
+
+	#[test]
+	fn bufq_reports_full_at_limit() {
+		let mut bq = BufferQueue::with_limit(6);
+		assert_eq!(bq.remaining_capacity(), Some(6));
+		bq.push(&b"foo"[..]);
+		assert!(!bq.is_full());
+		assert_eq!(bq.remaining_capacity(), Some(3));
+		bq.push(&b"bar"[..]);
+		assert!(bq.is_full());
+		assert_eq!(bq.remaining_capacity(), Some(0));
+		// pushing beyond the limit is allowed
+		bq.push(&b"baz"[..]);
+		assert!(bq.is_full());
+		assert_eq!(bq.remaining_capacity(), Some(0));
+		let mut buf = [0u8; 3];
+		bq.read_exact(&mut buf[..]).unwrap();
+		bq.read_exact(&mut buf[..]).unwrap();
+		assert!(!bq.is_full());
+		assert_eq!(bq.remaining_capacity(), Some(3));
+	}
+
+	#[test]
+	fn bufq_limit_can_be_changed() {
+		let mut bq = BufferQueue::new();
+		bq.push(&b"foobar"[..]);
+		bq.set_limit(Some(4));
+		assert!(bq.is_full());
+		bq.set_limit(Some(7));
+		assert!(!bq.is_full());
+		bq.set_limit(None);
+		assert!(!bq.is_full());
+	}
+
+	#[test]
+	fn bufq_coalesces_small_buffers() {
+		let mut bq = BufferQueue::new();
+		bq.set_coalesce_threshold(8);
+		for chunk in b"foobarbaz".chunks(1) {
+			bq.push(chunk);
+		}
+		assert_eq!(bq.len(), 9);
+		assert_eq!(bq.buffer_count(), 2);
+		assert_eq!(bq.fill_buf().unwrap(), b"foobarba");
+		bq.push_bytes(Bytes::from_static(b"large enough"));
+		assert_eq!(bq.buffer_count(), 3);
+		let mut buf = Vec::new();
+		bq.push_eof();
+		bq.read_to_end(&mut buf).unwrap();
+		assert_eq!(&buf[..], b"foobarbazlarge enough");
+	}
+
+	#[test]
+	fn bufq_does_not_coalesce_by_default() {
+		let mut bq = BufferQueue::new();
+		assert_eq!(bq.coalesce_threshold(), 0);
+		for chunk in b"foo".chunks(1) {
+			bq.push(chunk);
+		}
+		assert_eq!(bq.buffer_count(), 3);
+	}
+
+	#[test]
+	fn bufq_compact_merges_unread_data() {
+		let mut bq = BufferQueue::new();
+		bq.push(&b"foo"[..]);
+		bq.push_bytes(Bytes::from_static(b"bar"));
+		bq.push(&b"baz"[..]);
+		bq.consume(2);
+		bq.compact();
+		assert_eq!(bq.buffer_count(), 1);
+		assert_eq!(bq.len(), 7);
+		assert_eq!(bq.fill_buf().unwrap(), b"obarbaz");
+		bq.consume(7);
+		bq.compact();
+		assert_eq!(bq.buffer_count(), 0);
+		assert_eq!(bq.len(), 0);
+	}
+
+	#[test]
+	fn bufq_take_remainder_returns_unread_data() {
+		let mut bq = BufferQueue::new();
+		bq.push(&b"foo"[..]);
+		bq.push_bytes(Bytes::from_static(b"bar"));
+		bq.consume(1);
+		assert_eq!(&bq.take_remainder()[..], b"oobar");
+		assert_eq!(bq.len(), 0);
+		assert_eq!(bq.buffer_count(), 0);
+		bq.push(&b"baz"[..]);
+		assert_eq!(bq.fill_buf().unwrap(), b"baz");
+	}
+
+	#[test]
+	fn bufq_tracks_text_chunks() {
+		let mut bq = BufferQueue::new();
+		assert!(!bq.chunk_is_utf8());
+		bq.push_str("foo");
+		bq.push(&b"bar"[..]);
+		bq.push_str(String::from("baz"));
+		assert!(bq.chunk_is_utf8());
+		bq.consume(3);
+		assert!(!bq.chunk_is_utf8());
+		bq.consume(3);
+		assert!(bq.chunk_is_utf8());
+		assert_eq!(bq.fill_buf().unwrap(), b"baz");
+	}
+
+	#[test]
+	fn bufq_coalesced_text_stays_text_only_if_unmixed() {
+		let mut bq = BufferQueue::new();
+		bq.set_coalesce_threshold(16);
+		bq.push_str("foo");
+		bq.push_str("bar");
+		assert_eq!(bq.buffer_count(), 1);
+		assert!(bq.chunk_is_utf8());
+		bq.push(&b"baz"[..]);
+		assert_eq!(bq.buffer_count(), 1);
+		assert!(!bq.chunk_is_utf8());
+	}
+
+	#[test]
+	fn bufq_compact_keeps_text_flag_only_if_all_text() {
+		let mut bq = BufferQueue::new();
+		bq.push_str("foo");
+		bq.push_str("bar");
+		bq.compact();
+		assert!(bq.chunk_is_utf8());
+		bq.push(&b"baz"[..]);
+		bq.compact();
+		assert!(!bq.chunk_is_utf8());
+	}
+
+	#[test]
+	fn queue_reader_reads_in_blocks_of_capacity() {
+		let mut r = QueueReader::with_capacity(3, &b"foobar2"[..]);
+		assert_eq!(r.fill_buf().unwrap(), b"foo");
+		r.consume(2);
+		assert_eq!(r.fill_buf().unwrap(), b"o");
+		r.consume(1);
+		assert_eq!(r.fill_buf().unwrap(), b"bar");
+		r.consume(3);
+		assert_eq!(r.fill_buf().unwrap(), b"2");
+		r.consume(1);
+		assert_eq!(r.fill_buf().unwrap(), b"");
+		assert!(r.queue().eof_pushed());
+	}
+
+	#[test]
+	fn queue_reader_passes_wouldblock() {
+		let mut r = QueueReader::new(BufferQueue::new());
+		match r.fill_buf() {
+			Err(e) if e.kind() == io::ErrorKind::WouldBlock => (),
+			other => panic!("unexpected fill_buf result: {:?}", other),
+		}
+		r.get_mut().push(&b"foo"[..]);
+		let mut buf = [0u8; 8];
+		assert_eq!(r.read(&mut buf[..]).unwrap(), 3);
+		assert_eq!(&buf[..3], b"foo");
+	}
+
+	#[test]
+	fn queue_reader_retries_on_interrupt() {
+		struct Interrupting(bool);
+
+		impl io::Read for Interrupting {
+			fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
+				self.0 = !self.0;
+				if self.0 {
+					return Err(io::ErrorKind::Interrupted.into());
+				}
+				dst[0] = b'x';
+				Ok(1)
+			}
+		}
+
+		let mut r = QueueReader::new(Interrupting(false));
+		assert_eq!(r.fill_buf().unwrap(), b"x");
+	}
+
+	#[test]
+	#[should_panic(expected = "capacity must be non-zero")]
+	fn queue_reader_rejects_zero_capacity() {
+		QueueReader::with_capacity(0, &b""[..]);
+	}
 }
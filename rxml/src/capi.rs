@@ -0,0 +1,583 @@
+/*!
+# C interface
+
+This module exposes a non-blocking parser to C code. All items are
+`#[repr(C)]` or opaque and use the `rxml_`/`Rxml` prefix, so that a header
+can be generated with [cbindgen](https://github.com/mozilla/cbindgen), for
+example using the `cbindgen.toml` shipped with the crate:
+
+```text
+cbindgen --config cbindgen.toml --crate rxml --output rxml.h
+```
+
+To link the parser into a C program, build rxml as static or dynamic
+library with the `capi` feature enabled, for instance with
+`cargo rustc --release --features capi --crate-type staticlib`.
+
+## Usage
+
+A parser is created with [`rxml_parser_new`] and destroyed with
+[`rxml_parser_free`]. Data is passed to the parser using
+[`rxml_parser_feed`], which copies the data, so that the caller can reuse its
+buffer immediately. The end of the document is signalled with
+[`rxml_parser_feed_eof`].
+
+Events are read one by one with [`rxml_parser_next`]. It returns
+[`RxmlStatus::NeedMore`] when all data fed so far has been processed and
+[`RxmlStatus::Eof`] after the end of the document.
+
+```c
+RxmlParser *parser = rxml_parser_new();
+RxmlEvent ev;
+rxml_parser_feed(parser, (const uint8_t*)buf, buflen);
+while (rxml_parser_next(parser, &ev) == RXML_STATUS_OK) {
+	if (ev.kind == RXML_EVENT_KIND_START_ELEMENT) {
+		printf("<%.*s>\n", (int)ev.local_name.len, ev.local_name.data);
+	}
+}
+rxml_parser_free(parser);
+```
+
+## Strings and lifetimes
+
+Strings are passed as [`RxmlStr`], a pointer and a length in bytes. They
+are valid UTF-8, but **not** NUL-terminated. The strings and the attribute
+array referenced by an [`RxmlEvent`] are owned by the parser and remain
+valid until the next call to [`rxml_parser_next`] or [`rxml_parser_free`]
+on the same parser.
+
+## Errors
+
+If parsing fails, [`RxmlStatus::Error`] is returned and the error can be
+inspected using [`rxml_parser_error_code`] (see
+[`ErrorCode`](crate::error::ErrorCode)) and [`rxml_parser_error_message`].
+Parsing errors are fatal: all further calls to [`rxml_parser_next`] return
+the same error.
+
+Panics never unwind into C code. If a call panics, it returns
+[`RxmlStatus::Panic`] (or null for [`rxml_parser_new`]) and the parser is
+poisoned: all further calls on it, except [`rxml_parser_free`], return
+[`RxmlStatus::Panic`] as well.
+*/
+use std::borrow::Cow;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+#[allow(deprecated)]
+use crate::bufq::BufferQueue;
+use crate::driver::FeedParser;
+use crate::error::Error;
+use crate::parser::{NamespaceName, ResolvedEvent};
+
+/// Result of a call into the C interface.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RxmlStatus {
+	/// The call succeeded. For [`rxml_parser_next`], an event has been
+	/// written to the output argument.
+	Ok = 0,
+	/// All data fed so far has been processed; more data is needed to
+	/// continue.
+	NeedMore = 1,
+	/// The end of the document has been reached.
+	Eof = 2,
+	/// The document is invalid or uses a forbidden construct.
+	Error = 3,
+	/// An argument was invalid, e.g. a required pointer was null or data was
+	/// fed after the end of file.
+	InvalidArgument = 4,
+	/// An internal error (a panic) occurred. The parser must not be used
+	/// anymore, except for freeing it.
+	Panic = 5,
+}
+
+/// Type of an [`RxmlEvent`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RxmlEventKind {
+	/// The XML declaration.
+	XmlDeclaration = 0,
+	/// Start of an element. The name and the attributes are set.
+	StartElement = 1,
+	/// End of an element.
+	EndElement = 2,
+	/// Character data. The text is set.
+	Text = 3,
+}
+
+/// Borrowed UTF-8 string, not NUL-terminated.
+///
+/// A string which is absent (such as the namespace of an element without
+/// namespace) has a null `data` pointer.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RxmlStr {
+	/// Pointer to the first byte.
+	pub data: *const u8,
+	/// Length in bytes.
+	pub len: usize,
+}
+
+impl RxmlStr {
+	const NULL: RxmlStr = RxmlStr {
+		data: ptr::null(),
+		len: 0,
+	};
+
+	fn new(s: &str) -> Self {
+		Self {
+			data: s.as_ptr(),
+			len: s.len(),
+		}
+	}
+
+	fn namespace(ns: &Option<NamespaceName>) -> Self {
+		match ns {
+			Some(ns) => Self::new(ns),
+			None => Self::NULL,
+		}
+	}
+}
+
+/// Attribute of an element.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RxmlAttribute {
+	/// Namespace URI of the attribute, or null.
+	pub namespace: RxmlStr,
+	/// Local name of the attribute.
+	pub local_name: RxmlStr,
+	/// Value of the attribute, with references already expanded.
+	pub value: RxmlStr,
+}
+
+/// Event read from the parser.
+///
+/// Fields which do not apply to the kind of event are null or zero.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RxmlEvent {
+	/// Type of the event.
+	pub kind: RxmlEventKind,
+	/// Number of input bytes which make up the event.
+	pub len: usize,
+	/// Namespace URI of the element, or null.
+	pub namespace: RxmlStr,
+	/// Local name of the element.
+	pub local_name: RxmlStr,
+	/// Character data of a text event.
+	pub text: RxmlStr,
+	/// Pointer to the first attribute of the element.
+	pub attributes: *const RxmlAttribute,
+	/// Number of attributes of the element.
+	pub attribute_count: usize,
+}
+
+impl RxmlEvent {
+	fn empty(kind: RxmlEventKind, len: usize) -> Self {
+		Self {
+			kind,
+			len,
+			namespace: RxmlStr::NULL,
+			local_name: RxmlStr::NULL,
+			text: RxmlStr::NULL,
+			attributes: ptr::null(),
+			attribute_count: 0,
+		}
+	}
+}
+
+/// Opaque parser handle for the C interface.
+#[allow(deprecated)]
+pub struct RxmlParser {
+	driver: FeedParser,
+	buffer: BufferQueue<'static>,
+	/// Event whose data is referenced by the last returned [`RxmlEvent`].
+	current: Option<ResolvedEvent>,
+	attributes: Vec<RxmlAttribute>,
+	error: Option<(u32, CString)>,
+	/// Set when a call panicked, as the state may be inconsistent.
+	poisoned: bool,
+}
+
+#[allow(deprecated)]
+impl RxmlParser {
+	fn new() -> Self {
+		Self {
+			driver: FeedParser::default(),
+			buffer: BufferQueue::new(),
+			current: None,
+			attributes: Vec::new(),
+			error: None,
+			poisoned: false,
+		}
+	}
+
+	fn set_error(&mut self, e: &Error) {
+		// errors are sticky, so later calls report the same error again;
+		// keep the first message to not invalidate pointers handed out
+		// by rxml_parser_error_message
+		if self.error.is_some() {
+			return;
+		}
+		// error messages never contain NUL bytes; if they did, an empty
+		// message is better than none at all
+		let msg = CString::new(e.to_string()).unwrap_or_default();
+		self.error = Some((e.code().as_u32(), msg));
+	}
+
+	fn next(&mut self) -> (RxmlStatus, Option<RxmlEvent>) {
+		self.current = None;
+		self.attributes.clear();
+		let eof = self.buffer.eof_pushed();
+		let ev = loop {
+			match self.driver.parse(&mut self.buffer, eof) {
+				Ok(Some(ResolvedEvent::StartPrefixMapping(..)))
+				| Ok(Some(ResolvedEvent::EndPrefixMapping(..))) => continue,
+				Ok(Some(ev)) => break ev,
+				Ok(None) => return (RxmlStatus::Eof, None),
+				Err(e) if e.is_would_block() => return (RxmlStatus::NeedMore, None),
+				Err(e) => {
+					self.set_error(&e);
+					return (RxmlStatus::Error, None);
+				}
+			}
+		};
+		let ev = self.current.insert(ev);
+		let result = match ev {
+			ResolvedEvent::XmlDeclaration(m, _) => {
+				RxmlEvent::empty(RxmlEventKind::XmlDeclaration, m.len())
+			}
+			ResolvedEvent::StartElement(m, (ns, name), attrs, _) => {
+				// the values must be borrowed from the event, which lives
+				// until the next call
+				attrs.expand_references();
+				self.attributes
+					.extend(attrs.iter().map(|((ns, name), value)| RxmlAttribute {
+						namespace: RxmlStr::namespace(ns),
+						local_name: RxmlStr::new(name),
+						value: match value {
+							Cow::Borrowed(value) => RxmlStr::new(value),
+							Cow::Owned(_) => unreachable!(),
+						},
+					}));
+				RxmlEvent {
+					namespace: RxmlStr::namespace(ns),
+					local_name: RxmlStr::new(name),
+					attributes: self.attributes.as_ptr(),
+					attribute_count: self.attributes.len(),
+					..RxmlEvent::empty(RxmlEventKind::StartElement, m.len())
+				}
+			}
+			ResolvedEvent::EndElement(m) => RxmlEvent::empty(RxmlEventKind::EndElement, m.len()),
+			ResolvedEvent::Text(m, text) => RxmlEvent {
+				text: RxmlStr::new(text),
+				..RxmlEvent::empty(RxmlEventKind::Text, m.len())
+			},
+			ResolvedEvent::StartPrefixMapping(..) | ResolvedEvent::EndPrefixMapping(..) => {
+				unreachable!()
+			}
+		};
+		(RxmlStatus::Ok, Some(result))
+	}
+}
+
+/// Call `f`, returning `on_panic` instead of unwinding into the caller if it
+/// panics.
+fn catch_panic<T>(on_panic: T, f: impl FnOnce() -> T) -> T {
+	panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(on_panic)
+}
+
+/// Call `f` with the parser behind `parser`, poisoning it if `f` panics.
+///
+/// # Safety
+///
+/// `parser` must be null or a valid parser.
+unsafe fn with_parser(
+	parser: *mut RxmlParser,
+	f: impl FnOnce(&mut RxmlParser) -> RxmlStatus,
+) -> RxmlStatus {
+	let parser = match parser.as_mut() {
+		Some(p) => p,
+		None => return RxmlStatus::InvalidArgument,
+	};
+	if parser.poisoned {
+		return RxmlStatus::Panic;
+	}
+	match panic::catch_unwind(AssertUnwindSafe(|| f(parser))) {
+		Ok(status) => status,
+		Err(_) => {
+			parser.poisoned = true;
+			RxmlStatus::Panic
+		}
+	}
+}
+
+/// Create a new parser.
+///
+/// The parser must be destroyed with [`rxml_parser_free`]. Returns null if
+/// the parser could not be created.
+#[no_mangle]
+pub extern "C" fn rxml_parser_new() -> *mut RxmlParser {
+	catch_panic(ptr::null_mut(), || {
+		Box::into_raw(Box::new(RxmlParser::new()))
+	})
+}
+
+/// Destroy a parser.
+///
+/// Passing null is allowed and does nothing.
+///
+/// # Safety
+///
+/// `parser` must be null or a pointer obtained from [`rxml_parser_new`]
+/// which has not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn rxml_parser_free(parser: *mut RxmlParser) {
+	if !parser.is_null() {
+		// the parser is gone either way; a panic while dropping it can
+		// only leak memory
+		catch_panic((), || drop(Box::from_raw(parser)));
+	}
+}
+
+/// Pass `len` bytes of the document to the parser.
+///
+/// The data is copied. Returns [`RxmlStatus::InvalidArgument`] if the end of
+/// file has already been fed.
+///
+/// # Safety
+///
+/// `parser` must be a valid parser. `data` must point to at least `len`
+/// readable bytes; it may be null if `len` is zero.
+#[no_mangle]
+pub unsafe extern "C" fn rxml_parser_feed(
+	parser: *mut RxmlParser,
+	data: *const u8,
+	len: usize,
+) -> RxmlStatus {
+	with_parser(parser, |parser| {
+		if data.is_null() && len > 0 {
+			return RxmlStatus::InvalidArgument;
+		}
+		let data = if len > 0 {
+			slice::from_raw_parts(data, len).to_vec()
+		} else {
+			Vec::new()
+		};
+		// this also fails if the end of file has been fed already
+		match parser.buffer.try_push(data) {
+			Ok(()) => RxmlStatus::Ok,
+			Err(_) => RxmlStatus::InvalidArgument,
+		}
+	})
+}
+
+/// Signal that the complete document has been fed to the parser.
+///
+/// # Safety
+///
+/// `parser` must be a valid parser.
+#[no_mangle]
+pub unsafe extern "C" fn rxml_parser_feed_eof(parser: *mut RxmlParser) -> RxmlStatus {
+	with_parser(parser, |parser| {
+		parser.buffer.push_eof();
+		RxmlStatus::Ok
+	})
+}
+
+/// Read the next event.
+///
+/// If [`RxmlStatus::Ok`] is returned, the event has been written to `event`.
+/// Data referenced by the event stays valid until the next call to this
+/// function or to [`rxml_parser_free`].
+///
+/// # Safety
+///
+/// `parser` must be a valid parser and `event` must be valid for writing an
+/// [`RxmlEvent`].
+#[no_mangle]
+pub unsafe extern "C" fn rxml_parser_next(
+	parser: *mut RxmlParser,
+	event: *mut RxmlEvent,
+) -> RxmlStatus {
+	with_parser(parser, |parser| {
+		if event.is_null() {
+			return RxmlStatus::InvalidArgument;
+		}
+		let (status, ev) = parser.next();
+		if let Some(ev) = ev {
+			event.write(ev);
+		}
+		status
+	})
+}
+
+/// Return the code of the last error, or zero if no error occurred.
+///
+/// The codes are the values of [`ErrorCode`](crate::error::ErrorCode).
+///
+/// # Safety
+///
+/// `parser` must be a valid parser.
+#[no_mangle]
+pub unsafe extern "C" fn rxml_parser_error_code(parser: *const RxmlParser) -> u32 {
+	catch_panic(0, || match parser.as_ref().and_then(|p| p.error.as_ref()) {
+		Some((code, _)) => *code,
+		None => 0,
+	})
+}
+
+/// Return a human-readable description of the last error as NUL-terminated
+/// string, or null if no error occurred.
+///
+/// The string is owned by the parser and valid until it is freed.
+///
+/// # Safety
+///
+/// `parser` must be a valid parser.
+#[no_mangle]
+pub unsafe extern "C" fn rxml_parser_error_message(parser: *const RxmlParser) -> *const c_char {
+	catch_panic(ptr::null(), || {
+		match parser.as_ref().and_then(|p| p.error.as_ref()) {
+			Some((_, msg)) => msg.as_ptr(),
+			None => ptr::null(),
+		}
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::ffi::CStr;
+	use std::mem::MaybeUninit;
+
+	use crate::error::ErrorCode;
+
+	unsafe fn as_str<'x>(s: RxmlStr) -> Option<&'x str> {
+		if s.data.is_null() {
+			None
+		} else {
+			Some(std::str::from_utf8(slice::from_raw_parts(s.data, s.len)).unwrap())
+		}
+	}
+
+	unsafe fn next(parser: *mut RxmlParser) -> (RxmlStatus, Option<RxmlEvent>) {
+		let mut ev = MaybeUninit::uninit();
+		match rxml_parser_next(parser, ev.as_mut_ptr()) {
+			RxmlStatus::Ok => (RxmlStatus::Ok, Some(ev.assume_init())),
+			other => (other, None),
+		}
+	}
+
+	#[test]
+	fn reads_events_from_split_input() {
+		unsafe {
+			let p = rxml_parser_new();
+			let doc = b"<?xml version='1.0'?><a xmlns='urn:x' b='c'>foo</a>";
+			assert_eq!(rxml_parser_feed(p, doc.as_ptr(), 30), RxmlStatus::Ok);
+			let ev = next(p).1.unwrap();
+			assert_eq!(ev.kind, RxmlEventKind::XmlDeclaration);
+			assert_eq!(next(p).0, RxmlStatus::NeedMore);
+			assert_eq!(
+				rxml_parser_feed(p, doc[30..].as_ptr(), doc.len() - 30),
+				RxmlStatus::Ok
+			);
+
+			let ev = next(p).1.unwrap();
+			assert_eq!(ev.kind, RxmlEventKind::StartElement);
+			assert_eq!(as_str(ev.namespace), Some("urn:x"));
+			assert_eq!(as_str(ev.local_name), Some("a"));
+			assert_eq!(ev.attribute_count, 1);
+			let attr = *ev.attributes;
+			assert_eq!(as_str(attr.namespace), None);
+			assert_eq!(as_str(attr.local_name), Some("b"));
+			assert_eq!(as_str(attr.value), Some("c"));
+
+			let ev = next(p).1.unwrap();
+			assert_eq!(ev.kind, RxmlEventKind::Text);
+			assert_eq!(as_str(ev.text), Some("foo"));
+
+			let ev = next(p).1.unwrap();
+			assert_eq!(ev.kind, RxmlEventKind::EndElement);
+			assert_eq!(rxml_parser_feed_eof(p), RxmlStatus::Ok);
+			assert_eq!(next(p).0, RxmlStatus::Eof);
+			assert_eq!(rxml_parser_error_code(p), 0);
+			assert!(rxml_parser_error_message(p).is_null());
+			rxml_parser_free(p);
+		}
+	}
+
+	#[test]
+	fn reports_errors() {
+		unsafe {
+			let p = rxml_parser_new();
+			let doc = b"<a></b>";
+			rxml_parser_feed(p, doc.as_ptr(), doc.len());
+			assert_eq!(next(p).1.unwrap().kind, RxmlEventKind::StartElement);
+			assert_eq!(next(p).0, RxmlStatus::Error);
+			assert_eq!(
+				rxml_parser_error_code(p),
+				ErrorCode::ElementMismatch.as_u32()
+			);
+			let msg_ptr = rxml_parser_error_message(p);
+			let msg = CStr::from_ptr(msg_ptr);
+			assert!(!msg.to_bytes().is_empty());
+			// errors are sticky
+			assert_eq!(next(p).0, RxmlStatus::Error);
+			// and the message pointer stays valid
+			assert_eq!(rxml_parser_error_message(p), msg_ptr);
+			rxml_parser_free(p);
+		}
+	}
+
+	#[test]
+	fn rejects_invalid_arguments() {
+		unsafe {
+			let p = rxml_parser_new();
+			assert_eq!(
+				rxml_parser_feed(p, ptr::null(), 1),
+				RxmlStatus::InvalidArgument
+			);
+			assert_eq!(rxml_parser_feed(p, ptr::null(), 0), RxmlStatus::Ok);
+			assert_eq!(
+				rxml_parser_next(p, ptr::null_mut()),
+				RxmlStatus::InvalidArgument
+			);
+			rxml_parser_feed_eof(p);
+			assert_eq!(
+				rxml_parser_feed(p, b"<a/>".as_ptr(), 4),
+				RxmlStatus::InvalidArgument
+			);
+			assert_eq!(
+				rxml_parser_next(ptr::null_mut(), ptr::null_mut()),
+				RxmlStatus::InvalidArgument
+			);
+			rxml_parser_free(p);
+			rxml_parser_free(ptr::null_mut());
+		}
+	}
+
+	#[test]
+	fn panics_poison_the_parser() {
+		unsafe {
+			let p = rxml_parser_new();
+			assert_eq!(
+				with_parser(p, |_| panic!("simulated bug")),
+				RxmlStatus::Panic
+			);
+			let doc = b"<a/>";
+			assert_eq!(
+				rxml_parser_feed(p, doc.as_ptr(), doc.len()),
+				RxmlStatus::Panic
+			);
+			assert_eq!(rxml_parser_feed_eof(p), RxmlStatus::Panic);
+			assert_eq!(next(p).0, RxmlStatus::Panic);
+			rxml_parser_free(p);
+		}
+	}
+}
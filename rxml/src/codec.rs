@@ -0,0 +1,225 @@
+/*!
+# Codec for use with tokio-util framed transports
+
+This module provides [`Codec`], which implements the
+[`tokio_util::codec::Decoder`] and [`tokio_util::codec::Encoder`] traits.
+This allows to plug rxml directly into [`tokio_util::codec::Framed`],
+[`tokio_util::codec::FramedRead`] and [`tokio_util::codec::FramedWrite`].
+
+Decoding produces [`ResolvedEvent`]s, encoding accepts either [`Item`]s or
+references to [`ResolvedEvent`]s.
+
+```
+use tokio_util::codec::Decoder;
+use rxml::codec::Codec;
+use rxml::ResolvedEvent;
+use bytes::BytesMut;
+
+let mut codec = Codec::new();
+let mut buf = BytesMut::from(&b"<?xml version='1.0'?><hello>"[..]);
+match codec.decode(&mut buf).unwrap() {
+	Some(ResolvedEvent::XmlDeclaration(..)) => (),
+	other => panic!("unexpected event: {:?}", other),
+}
+match codec.decode(&mut buf).unwrap() {
+	Some(ResolvedEvent::StartElement(_, (_, localname), _, _)) => assert_eq!(localname, "hello"),
+	other => panic!("unexpected event: {:?}", other),
+}
+// more data is needed
+assert!(codec.decode(&mut buf).unwrap().is_none());
+```
+*/
+use std::io;
+
+use bytes::BytesMut;
+
+use crate::driver::PushDriver;
+use crate::error::{Error, Result};
+use crate::parser::{Parse, Parser, ResolvedEvent};
+use crate::writer::{EncodeError, Encoder, Item, SimpleNamespaces, TrackNamespace};
+
+/// Bidirectional XML codec for framed transports.
+///
+/// The decoding half is driven by a [`PushDriver`], the encoding half by an
+/// [`Encoder`]. Both halves are independent: the state of the decoder does
+/// not affect the encoder or vice versa.
+///
+/// Errors from the encoder are reported as [`std::io::Error`] of kind
+/// [`std::io::ErrorKind::InvalidInput`], wrapping the [`EncodeError`].
+pub struct Codec<P: Parse = Parser, T = SimpleNamespaces> {
+	decoder: PushDriver<P>,
+	encoder: Encoder<T>,
+}
+
+impl Codec<Parser, SimpleNamespaces> {
+	/// Create a new codec with the default parser and encoder.
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+impl Default for Codec<Parser, SimpleNamespaces> {
+	fn default() -> Self {
+		Self::wrap(PushDriver::default(), Encoder::new())
+	}
+}
+
+impl<P: Parse, T> Codec<P, T> {
+	/// Compose a new codec from a decoder and an encoder.
+	pub fn wrap(decoder: PushDriver<P>, encoder: Encoder<T>) -> Self {
+		Self { decoder, encoder }
+	}
+
+	/// Access the decoding half.
+	pub fn decoder(&self) -> &PushDriver<P> {
+		&self.decoder
+	}
+
+	/// Access the decoding half, mutably.
+	pub fn decoder_mut(&mut self) -> &mut PushDriver<P> {
+		&mut self.decoder
+	}
+
+	/// Access the encoding half.
+	pub fn encoder(&self) -> &Encoder<T> {
+		&self.encoder
+	}
+
+	/// Access the encoding half, mutably.
+	pub fn encoder_mut(&mut self) -> &mut Encoder<T> {
+		&mut self.encoder
+	}
+
+	/// Decompose the codec into its decoding and encoding halves.
+	pub fn into_inner(self) -> (PushDriver<P>, Encoder<T>) {
+		(self.decoder, self.encoder)
+	}
+}
+
+fn map_encode_error(e: EncodeError) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidInput, e)
+}
+
+impl<P: Parse, T> tokio_util::codec::Decoder for Codec<P, T> {
+	type Item = P::Output;
+	type Error = Error;
+
+	fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>> {
+		match self.decoder.parse(src, false) {
+			Err(e) if e.is_would_block() => Ok(None),
+			other => other,
+		}
+	}
+
+	fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>> {
+		self.decoder.parse(src, true)
+	}
+}
+
+impl<'x, P: Parse, T: TrackNamespace> tokio_util::codec::Encoder<Item<'x>> for Codec<P, T> {
+	type Error = io::Error;
+
+	fn encode(&mut self, item: Item<'x>, dst: &mut BytesMut) -> io::Result<()> {
+		self.encoder
+			.encode_into_bytes(item, dst)
+			.map_err(map_encode_error)
+	}
+}
+
+impl<'x, P: Parse, T: TrackNamespace> tokio_util::codec::Encoder<&'x ResolvedEvent>
+	for Codec<P, T>
+{
+	type Error = io::Error;
+
+	fn encode(&mut self, ev: &'x ResolvedEvent, dst: &mut BytesMut) -> io::Result<()> {
+		self.encoder
+			.encode_event_into_bytes(ev, dst)
+			.map_err(map_encode_error)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::convert::TryInto;
+
+	use tokio_util::codec::{Decoder, Encoder as _};
+
+	#[test]
+	fn decode_returns_none_on_incomplete_input() {
+		let mut codec = Codec::new();
+		let mut buf = BytesMut::from(&b"<?xml version='1.0'?><a"[..]);
+		match codec.decode(&mut buf) {
+			Ok(Some(ResolvedEvent::XmlDeclaration(..))) => (),
+			other => panic!("unexpected decode result: {:?}", other),
+		}
+		match codec.decode(&mut buf) {
+			Ok(None) => (),
+			other => panic!("unexpected decode result: {:?}", other),
+		}
+		assert_eq!(buf.len(), 0);
+		buf.extend_from_slice(b"/>");
+		match codec.decode(&mut buf) {
+			Ok(Some(ResolvedEvent::StartElement(..))) => (),
+			other => panic!("unexpected decode result: {:?}", other),
+		}
+		match codec.decode(&mut buf) {
+			Ok(Some(ResolvedEvent::EndElement(..))) => (),
+			other => panic!("unexpected decode result: {:?}", other),
+		}
+		match codec.decode_eof(&mut buf) {
+			Ok(None) => (),
+			other => panic!("unexpected decode result: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn decode_eof_rejects_incomplete_document() {
+		let mut codec = Codec::new();
+		let mut buf = BytesMut::from(&b"<a>"[..]);
+		match codec.decode(&mut buf) {
+			Ok(Some(ResolvedEvent::StartElement(..))) => (),
+			other => panic!("unexpected decode result: {:?}", other),
+		}
+		match codec.decode_eof(&mut buf) {
+			Err(Error::Xml(_)) => (),
+			other => panic!("unexpected decode result: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn encode_items() {
+		let mut codec = Codec::new();
+		let mut buf = BytesMut::new();
+		codec
+			.encode(
+				Item::ElementHeadStart(None, "a".try_into().unwrap()),
+				&mut buf,
+			)
+			.unwrap();
+		codec.encode(Item::ElementFoot, &mut buf).unwrap();
+		assert_eq!(&buf[..], &b"<a/>"[..]);
+	}
+
+	#[test]
+	fn encode_errors_are_invalid_input() {
+		let mut codec = Codec::new();
+		let mut buf = BytesMut::new();
+		match codec.encode(Item::ElementFoot, &mut buf) {
+			Err(e) if e.kind() == io::ErrorKind::InvalidInput => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn encode_decoded_events() {
+		let mut codec = Codec::new();
+		let mut input = BytesMut::from(&b"<a x='y'>foo</a>"[..]);
+		let mut output = BytesMut::new();
+		while let Some(ev) = codec.decode_eof(&mut input).unwrap() {
+			codec.encode(&ev, &mut output).unwrap();
+		}
+		assert_eq!(&output[..], &b"<a x=\"y\">foo</a>"[..]);
+	}
+}
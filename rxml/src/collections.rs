@@ -0,0 +1,25 @@
+/*!
+# Map and set types for internal use
+
+With `std`, these are the hash-based collections. Without `std`, there is no
+`HashMap`, so the B-tree based collections from `alloc` take their place.
+Both offer the subset of the API which is used in this crate.
+*/
+#[cfg(not(feature = "std"))]
+pub(crate) use alloc::collections::{btree_map::Entry, BTreeMap as Map, BTreeSet as Set};
+#[cfg(feature = "std")]
+pub(crate) use std::collections::{hash_map::Entry, HashMap as Map, HashSet as Set};
+
+/// Create an empty set with room for at least `capacity` items, if the set
+/// type supports preallocation.
+#[cfg(feature = "std")]
+pub(crate) fn set_with_capacity<T>(capacity: usize) -> Set<T> {
+	Set::with_capacity(capacity)
+}
+
+/// Create an empty set with room for at least `capacity` items, if the set
+/// type supports preallocation.
+#[cfg(not(feature = "std"))]
+pub(crate) fn set_with_capacity<T>(_capacity: usize) -> Set<T> {
+	Set::new()
+}
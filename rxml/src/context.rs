@@ -1,14 +1,15 @@
-use std::borrow::Cow;
-use std::fmt;
+use alloc::borrow::Cow;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+#[cfg(feature = "std")]
+use std::collections::HashMap;
 
-#[cfg(all(feature = "shared_ns", not(feature = "mt")))]
-use std::cell::{RefCell, RefMut};
-#[cfg(all(feature = "shared_ns", not(feature = "mt")))]
-use std::rc::Weak;
-#[cfg(all(feature = "shared_ns", feature = "mt"))]
-use std::sync::{Mutex, MutexGuard, Weak};
+#[cfg(feature = "shared_ns")]
+use std::sync::Weak;
+#[cfg(feature = "std")]
+use std::sync::{Mutex, MutexGuard};
 
-use crate::parser::RcPtr;
+use crate::parser::{NamespaceName, RcPtr};
 use crate::strings;
 
 #[cfg(feature = "shared_ns")]
@@ -17,6 +18,28 @@ use weak_table;
 #[cfg(feature = "shared_ns")]
 type CDataWeakSet = weak_table::WeakHashSet<Weak<strings::CData>>;
 
+#[cfg(feature = "shared_ns")]
+type StrWeakSet = weak_table::WeakHashSet<Weak<str>>;
+
+#[cfg(feature = "shared_ns")]
+fn table_memory_used<T: ?Sized + Eq + Hash, F: Fn(&T) -> usize>(
+	set: &weak_table::WeakHashSet<Weak<T>>,
+	heap_len: F,
+) -> usize {
+	// each slot holds a weak pointer and a hash code
+	let slot_size = std::mem::size_of::<Option<(Weak<T>, u64)>>();
+	let mut total = set.capacity() * slot_size;
+	for item in set.iter() {
+		// strong count, weak count and the value itself
+		total += 2 * std::mem::size_of::<usize>() + std::mem::size_of_val(&*item) + heap_len(&item);
+	}
+	total
+}
+
+/// Namespace URIs registered via [`Context::register_namespace`].
+#[cfg(feature = "std")]
+type NamespaceRegistry = HashMap<strings::CData, NamespaceName>;
+
 /**
 # Shared context for multiple parsers
 
@@ -24,47 +47,82 @@ This context allows parsers to share data. This is useful in cases where many
 parsers are used in the same application, and all of them encountering similar
 data.
 
-As of writing, the context is only used to share namespace URIs encountered in
-XML documents, and only if the `shared_ns` feature is used for building.
+As of writing, the context is used to share namespace URIs as well as element
+and attribute names encountered in XML documents, and only if the `shared_ns`
+feature is used for building.
+
+Names are interned by the [`NamespaceResolver`](crate::NamespaceResolver)
+(and thus by all parsers using it) if they are too long to be stored inline
+(see the [`strings`](crate::strings) module). All events carrying the same
+long name then share one allocation, so that keeping them (e.g. in a tree) or
+cloning them does not copy the name again. The lexer still creates a
+temporary copy of each name it reads. Short names are never interned, as
+copying them is cheaper than looking them up.
+
+Independent of `shared_ns`, applications can register the namespace URIs
+they dispatch on with [`Context::register_namespace`]. All parsers using the
+context then resolve these URIs to the pointer held by the returned
+[`NamespaceHandle`], so that namespaces can be compared by identity:
+
+```
+# #[cfg(feature = "std")]
+# fn main() {
+use rxml::{CData, Context, FeedParser, Lexer, Parser, ResolvedEvent, WithContext};
+use rxml::parser::RcPtr;
+use std::convert::TryFrom;
+
+let ctx = RcPtr::new(Context::new());
+let client = ctx.register_namespace(CData::try_from("jabber:client").unwrap());
+let mut fp = FeedParser::wrap(Lexer::new(), Parser::with_context(ctx));
+let mut doc = &b"<message xmlns='jabber:client'/>"[..];
+fp.parse_all(&mut doc, true, |ev| match ev {
+	ResolvedEvent::StartElement(_, (ns, _), _, _) => assert!(client.matches(ns.as_ref())),
+	_ => (),
+}).unwrap();
+# }
+# #[cfg(not(feature = "std"))]
+# fn main() {}
+```
 
 Even though the context is internally mutable, it can safely be shared with
-an immutable reference between parsers. If the crate is built with the `mt`
-feature, the Context is Send and Sync, otherwise it is neither.
+an immutable reference between parsers. The Context is Send and Sync, so
+that parsers on different threads can share it.
 */
 pub struct Context {
-	#[cfg(all(feature = "shared_ns", feature = "mt"))]
+	#[cfg(feature = "std")]
+	registered: Mutex<NamespaceRegistry>,
+	#[cfg(feature = "shared_ns")]
 	nss: Mutex<CDataWeakSet>,
-	#[cfg(all(feature = "shared_ns", not(feature = "mt")))]
-	nss: RefCell<CDataWeakSet>,
+	#[cfg(feature = "shared_ns")]
+	names: Mutex<StrWeakSet>,
 }
 
 impl Context {
-	#[cfg(all(feature = "shared_ns", feature = "mt"))]
-	fn wrap_nss(nss: CDataWeakSet) -> Mutex<CDataWeakSet> {
-		return Mutex::new(nss);
-	}
-
-	#[cfg(all(feature = "shared_ns", not(feature = "mt")))]
-	fn wrap_nss(nss: CDataWeakSet) -> RefCell<CDataWeakSet> {
-		return RefCell::new(nss);
-	}
-
 	/// Create a new context
 	pub fn new() -> Context {
 		Context {
+			#[cfg(feature = "std")]
+			registered: Mutex::new(HashMap::new()),
+			#[cfg(feature = "shared_ns")]
+			nss: Mutex::new(weak_table::WeakHashSet::new()),
 			#[cfg(feature = "shared_ns")]
-			nss: Self::wrap_nss(weak_table::WeakHashSet::new()),
+			names: Mutex::new(weak_table::WeakHashSet::new()),
 		}
 	}
 
-	#[cfg(all(feature = "shared_ns", feature = "mt"))]
+	#[cfg(feature = "shared_ns")]
 	fn lock_nss<'a>(&'a self) -> MutexGuard<'a, CDataWeakSet> {
 		self.nss.lock().unwrap()
 	}
 
-	#[cfg(all(feature = "shared_ns", not(feature = "mt")))]
-	fn lock_nss<'a>(&'a self) -> RefMut<'a, CDataWeakSet> {
-		self.nss.borrow_mut()
+	#[cfg(feature = "shared_ns")]
+	fn lock_names<'a>(&'a self) -> MutexGuard<'a, StrWeakSet> {
+		self.names.lock().unwrap()
+	}
+
+	#[cfg(feature = "std")]
+	fn lock_registered<'a>(&'a self) -> MutexGuard<'a, NamespaceRegistry> {
+		self.registered.lock().unwrap()
 	}
 
 	/// Intern a piece of text
@@ -76,6 +134,9 @@ impl Context {
 	/// The optimal course is taken depending on whether the Cow is borrowed
 	/// or owned.
 	///
+	/// Namespace URIs registered with [`Context::register_namespace`] always
+	/// return the registered pointer, even without `shared_ns`.
+	///
 	/// To force expiry, call [`Context::release_temporaries`], although that
 	/// should only rarely be necessary and may be detrimental to performance.
 	pub fn intern_cdata<'a, T: Into<Cow<'a, strings::CDataStr>>>(
@@ -83,6 +144,19 @@ impl Context {
 		ns: T,
 	) -> RcPtr<strings::CData> {
 		let ns = ns.into();
+		#[cfg(feature = "std")]
+		{
+			let registered = self.lock_registered();
+			if !registered.is_empty() {
+				if let Some(ptr) = registered.get(&*ns) {
+					return ptr.clone();
+				}
+			}
+		}
+		self.intern_unregistered(ns)
+	}
+
+	fn intern_unregistered(&self, ns: Cow<'_, strings::CDataStr>) -> RcPtr<strings::CData> {
 		#[cfg(feature = "shared_ns")]
 		{
 			let mut nss = self.lock_nss();
@@ -99,18 +173,194 @@ impl Context {
 		return RcPtr::new(ns.into_owned());
 	}
 
+	#[cfg(feature = "shared_ns")]
+	fn intern_str(&self, s: &str) -> RcPtr<str> {
+		let mut names = self.lock_names();
+		match names.get(s) {
+			Some(ptr) => ptr,
+			None => {
+				let ptr: RcPtr<str> = s.into();
+				names.insert(ptr.clone());
+				ptr
+			}
+		}
+	}
+
+	/// Intern a name
+	///
+	/// The returned [`Name`](strings::Name) shares its storage with all other
+	/// names and NcNames interned in the context with the same value, so
+	/// that cloning it does not copy the string. When the last reference
+	/// expires, the string will be lazily removed from the internal storage.
+	///
+	/// Without `shared_ns`, the name is returned as owned copy.
+	pub fn intern_name<'a, T: Into<Cow<'a, strings::NameStr>>>(&self, name: T) -> strings::Name {
+		let name = name.into();
+		#[cfg(feature = "shared_ns")]
+		// SAFETY: the string has been validated as Name when it was created
+		return unsafe { strings::Name::from_shared_unchecked(self.intern_str(&name)) };
+		#[cfg(not(feature = "shared_ns"))]
+		return name.into_owned();
+	}
+
+	/// Intern an NcName
+	///
+	/// See [`Context::intern_name`]; the same storage is used for both, so
+	/// interning an NcName and a Name with the same value shares the
+	/// string.
+	pub fn intern_ncname<'a, T: Into<Cow<'a, strings::NcNameStr>>>(
+		&self,
+		name: T,
+	) -> strings::NcName {
+		let name = name.into();
+		#[cfg(feature = "shared_ns")]
+		// SAFETY: the string has been validated as NcName when it was created
+		return unsafe { strings::NcName::from_shared_unchecked(self.intern_str(&name)) };
+		#[cfg(not(feature = "shared_ns"))]
+		return name.into_owned();
+	}
+
+	/// Register a namespace URI and return its handle
+	///
+	/// The context keeps the namespace URI alive until it is dropped. All
+	/// parsers using the context resolve the namespace URI to the pointer
+	/// held by the handle, so that it can be compared with the namespace of
+	/// events using [`NamespaceHandle::matches`] instead of a string
+	/// comparison.
+	///
+	/// Registering the same namespace URI again returns an equal handle.
+	/// Events which were created before the registration may hold a
+	/// different pointer.
+	#[cfg(feature = "std")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+	pub fn register_namespace<'a, T: Into<Cow<'a, strings::CDataStr>>>(
+		&self,
+		ns: T,
+	) -> NamespaceHandle {
+		let ns = ns.into();
+		let mut registered = self.lock_registered();
+		if let Some(ptr) = registered.get(&*ns) {
+			return NamespaceHandle(ptr.clone());
+		}
+		let key = ns.clone().into_owned();
+		// reuse the pointer held by live events if the URI is already known
+		let ptr = self.intern_unregistered(ns);
+		registered.insert(key, ptr.clone());
+		NamespaceHandle(ptr)
+	}
+
+	/// Return the handle of a registered namespace URI
+	///
+	/// Returns `None` if the namespace URI has not been registered with
+	/// [`Context::register_namespace`].
+	#[cfg(feature = "std")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+	pub fn namespace_handle(&self, ns: &strings::CDataStr) -> Option<NamespaceHandle> {
+		self.lock_registered()
+			.get(ns)
+			.map(|ptr| NamespaceHandle(ptr.clone()))
+	}
+
 	/// Remove all unreferenced strings from storage and shrink the storage to
 	/// fit the requirements.
 	///
 	/// This should rarely be necessary to call. The internal storage will
 	/// prefer expiring unused strings over reallocating and will only
 	/// reallocate if necessary.
+	///
+	/// This is equivalent to [`Context::shrink_to_fit`].
 	pub fn release_temporaries(&self) {
+		self.shrink_to_fit();
+	}
+
+	/// Remove all unreferenced strings from storage, without releasing the
+	/// memory of the tables.
+	///
+	/// Returns the number of entries removed. Strings which are still
+	/// referenced elsewhere are kept. Returns zero if built without
+	/// `shared_ns`.
+	pub fn remove_expired(&self) -> usize {
+		#[cfg(feature = "shared_ns")]
+		{
+			let mut nss = self.lock_nss();
+			let mut names = self.lock_names();
+			let before = nss.len() + names.len();
+			nss.remove_expired();
+			names.remove_expired();
+			before - nss.len() - names.len()
+		}
+		#[cfg(not(feature = "shared_ns"))]
+		0
+	}
+
+	/// Remove all unreferenced strings from storage and shrink the tables
+	/// to the minimum capacity required for the remaining entries.
+	///
+	/// Long-lived contexts may call this periodically to return memory
+	/// after a burst of distinct strings (e.g. many different namespace
+	/// URIs) has passed.
+	pub fn shrink_to_fit(&self) {
 		#[cfg(feature = "shared_ns")]
 		{
 			let mut nss = self.lock_nss();
 			nss.remove_expired();
 			nss.shrink_to_fit();
+			let mut names = self.lock_names();
+			names.remove_expired();
+			names.shrink_to_fit();
+		}
+	}
+
+	/// Estimate the number of bytes of memory used by the interned data.
+	///
+	/// This accounts for the capacity of the internal tables and, for each
+	/// interned string which is still referenced, its reference-counted
+	/// allocation and its length. Allocator overhead and unused string
+	/// capacity are not included, so the actual memory use may be somewhat
+	/// higher. Unreferenced entries only count with their table slot; use
+	/// [`Context::remove_expired`] or [`Context::shrink_to_fit`] to
+	/// reclaim them.
+	///
+	/// Note that strings which are referenced elsewhere (e.g. by events
+	/// held by the application) are counted even though removing them from
+	/// the context would not free their memory.
+	///
+	/// Returns zero if built without `shared_ns`.
+	pub fn memory_used(&self) -> usize {
+		#[cfg(feature = "shared_ns")]
+		{
+			let nss = self.lock_nss();
+			let names = self.lock_names();
+			table_memory_used(&nss, |x| x.len()) + table_memory_used(&names, |_| 0)
+		}
+		#[cfg(not(feature = "shared_ns"))]
+		0
+	}
+
+	/// Forget all interned strings.
+	///
+	/// Pointers which have been handed out stay valid, but subsequent calls
+	/// to [`Context::intern_cdata`] will not return them anymore. Registered
+	/// namespace URIs are kept, so that their handles stay usable.
+	///
+	/// This also forgets all interned names; see [`Context::clear_names`].
+	pub fn clear(&self) {
+		#[cfg(feature = "shared_ns")]
+		{
+			self.lock_nss().clear();
+		}
+		self.clear_names();
+	}
+
+	/// Forget all interned names, keeping the interned CData strings.
+	///
+	/// Names which have been handed out stay valid, but subsequent calls to
+	/// [`Context::intern_name`] and [`Context::intern_ncname`] will not share
+	/// their storage anymore.
+	pub fn clear_names(&self) {
+		#[cfg(feature = "shared_ns")]
+		{
+			self.lock_names().clear();
 		}
 	}
 
@@ -141,6 +391,98 @@ impl Context {
 		#[cfg(not(feature = "shared_ns"))]
 		0
 	}
+
+	/// Return the number of names interned.
+	///
+	/// Names and NcNames share the same storage and are counted together.
+	/// Returns zero if built without `shared_ns`. Like [`Context::cdatas`],
+	/// this count includes names which are unreferenced.
+	pub fn names(&self) -> usize {
+		#[cfg(feature = "shared_ns")]
+		{
+			let names = self.lock_names();
+			names.len()
+		}
+		#[cfg(not(feature = "shared_ns"))]
+		0
+	}
+
+	/// Return the current capacity for the name internation structure
+	///
+	/// Returns zero if built without `shared_ns`.
+	pub fn name_capacity(&self) -> usize {
+		#[cfg(feature = "shared_ns")]
+		{
+			let names = self.lock_names();
+			names.capacity()
+		}
+		#[cfg(not(feature = "shared_ns"))]
+		0
+	}
+}
+
+/**
+# Handle to a registered namespace URI
+
+Handles are obtained from [`Context::register_namespace`]. They refer to the
+same pointer as the [`NamespaceName`]s which parsers using the context emit
+for the namespace URI, which allows comparing namespaces by identity.
+
+Handles compare equal if they refer to the same pointer. [`id()`](Self::id)
+exposes the pointer as an integer, for instance to use it as a key in a
+dispatch table.
+*/
+#[derive(Clone, Debug)]
+pub struct NamespaceHandle(NamespaceName);
+
+impl NamespaceHandle {
+	/// Return true if `ns` is the namespace name of this handle.
+	///
+	/// This is a pointer comparison. Namespace names which have not been
+	/// obtained from a parser using the context of this handle (or which
+	/// were created before the namespace URI was registered) do not match,
+	/// even if they are equal.
+	pub fn matches(&self, ns: Option<&NamespaceName>) -> bool {
+		match ns {
+			Some(ns) => RcPtr::ptr_eq(&self.0, ns),
+			None => false,
+		}
+	}
+
+	/// Return an integer identifying the namespace name.
+	///
+	/// The integer is unique among the namespace names which are alive at the
+	/// same time. It is equal to [`NamespaceHandle::id_of`] of the namespace
+	/// names emitted for the URI.
+	pub fn id(&self) -> usize {
+		Self::id_of(&self.0)
+	}
+
+	/// Return the integer identifying a namespace name.
+	///
+	/// See [`NamespaceHandle::id`].
+	pub fn id_of(ns: &NamespaceName) -> usize {
+		RcPtr::as_ptr(ns) as usize
+	}
+
+	/// Access the namespace name.
+	pub fn namespace_name(&self) -> &NamespaceName {
+		&self.0
+	}
+}
+
+impl PartialEq for NamespaceHandle {
+	fn eq(&self, other: &NamespaceHandle) -> bool {
+		RcPtr::ptr_eq(&self.0, &other.0)
+	}
+}
+
+impl Eq for NamespaceHandle {}
+
+impl Hash for NamespaceHandle {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.id().hash(state)
+	}
 }
 
 impl fmt::Debug for Context {
@@ -152,6 +494,9 @@ impl fmt::Debug for Context {
 			let nss = self.lock_nss();
 			f.field("nss.capacity()", &nss.capacity())
 				.field("nss.length()", &nss.len());
+			let names = self.lock_names();
+			f.field("names.capacity()", &names.capacity())
+				.field("names.length()", &names.len());
 		}
 		f.finish()
 	}
@@ -166,7 +511,93 @@ impl fmt::UpperHex for Context {
 			for item in nss.iter() {
 				f.entry(&(RcPtr::strong_count(&item) - 1, &*item));
 			}
+			let names = self.lock_names();
+			for item in names.iter() {
+				f.entry(&(RcPtr::strong_count(&item) - 1, &*item));
+			}
 		}
 		f.finish()
 	}
 }
+
+#[cfg(all(test, feature = "shared_ns"))]
+mod tests {
+	use super::*;
+
+	use std::convert::{TryFrom, TryInto};
+
+	#[test]
+	fn memory_used_accounts_for_interned_data() {
+		let ctx = Context::new();
+		let empty = ctx.memory_used();
+		let long = "x".repeat(1000);
+		let a = ctx.intern_cdata(strings::CData::try_from(long.as_str()).unwrap());
+		let used = ctx.memory_used();
+		assert!(used >= empty + 1000);
+		drop(a);
+		assert!(ctx.memory_used() < used);
+	}
+
+	#[test]
+	fn remove_expired_and_shrink_to_fit_evict_unused_entries() {
+		let ctx = Context::new();
+		let mut ptrs = Vec::new();
+		for i in 0..100 {
+			ptrs.push(ctx.intern_cdata(strings::CData::try_from(format!("urn:{}", i)).unwrap()));
+		}
+		assert_eq!(ctx.cdatas(), 100);
+		ptrs.retain(|x| x.ends_with('0'));
+		let capacity = ctx.cdata_capacity();
+		assert_eq!(ctx.remove_expired(), 90);
+		assert_eq!(ctx.cdatas(), 10);
+		assert_eq!(ctx.cdata_capacity(), capacity);
+		ctx.shrink_to_fit();
+		assert!(ctx.cdata_capacity() < capacity);
+		assert_eq!(ctx.cdatas(), 10);
+	}
+
+	#[test]
+	fn register_namespace_reuses_interned_pointer() {
+		let ctx = Context::new();
+		let ns: &strings::CDataStr = "urn:foo".try_into().unwrap();
+		let live = ctx.intern_cdata(ns);
+		let handle = ctx.register_namespace(ns);
+		assert!(handle.matches(Some(&live)));
+		ctx.clear();
+		assert_eq!(ctx.cdatas(), 0);
+		assert!(handle.matches(Some(&ctx.intern_cdata(ns))));
+		assert_eq!(ctx.namespace_handle(ns), Some(handle));
+	}
+
+	#[test]
+	fn intern_name_shares_storage() {
+		let ctx = Context::new();
+		let long = "a".repeat(100);
+		let a = ctx.intern_ncname(strings::NcName::try_from(long.as_str()).unwrap());
+		let b = ctx.intern_ncname(<&strings::NcNameStr>::try_from(long.as_str()).unwrap());
+		let c = ctx.intern_name(<&strings::NameStr>::try_from(long.as_str()).unwrap());
+		assert_eq!(a, b);
+		assert_eq!(a.as_ptr(), b.as_ptr());
+		assert_eq!(a.as_ptr(), c.as_ptr());
+		assert_eq!(a.clone().as_ptr(), a.as_ptr());
+		assert_eq!(a.clone().into_name().as_ptr(), a.as_ptr());
+		assert_eq!(ctx.names(), 1);
+	}
+
+	#[test]
+	fn clear_names_and_remove_expired_drop_interned_names() {
+		let ctx = Context::new();
+		let long = "a".repeat(100);
+		let a = ctx.intern_ncname(<&strings::NcNameStr>::try_from(long.as_str()).unwrap());
+		let b = ctx.intern_name(<&strings::NameStr>::try_from("b".repeat(100).as_str()).unwrap());
+		assert_eq!(ctx.names(), 2);
+		drop(b);
+		assert_eq!(ctx.remove_expired(), 1);
+		assert_eq!(ctx.names(), 1);
+		ctx.clear_names();
+		assert_eq!(ctx.names(), 0);
+		let c = ctx.intern_ncname(<&strings::NcNameStr>::try_from(long.as_str()).unwrap());
+		assert_eq!(a, c);
+		assert_ne!(a.as_ptr(), c.as_ptr());
+	}
+}
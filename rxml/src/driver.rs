@@ -8,13 +8,29 @@ top-level documentation.
    [`rxml`]: crate
 */
 
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+use core::ops::Range;
+#[cfg(feature = "std")]
 use std::io;
 
+#[allow(deprecated)]
+use crate::bufq::BufferQueue;
+#[cfg(feature = "std")]
+use crate::bufq::QueueReader;
 use crate::context::Context;
-use crate::error::{Error, Result};
-use crate::lexer::{Lexer, LexerOptions};
+use crate::error::{LocatedError, Result};
+use crate::lexer::Lexer;
+use crate::lexer::LexerOptions;
 use crate::parser;
-use crate::parser::{BufferLexerAdapter, LexerAdapter, Parse, Parser};
+#[cfg(feature = "std")]
+use crate::parser::LexerAdapter;
+use crate::parser::{
+	Attributes, BorrowedEvent, BufferLexerAdapter, EventMetrics, EventSink, Parse, Parser,
+	ResolvedEvent, ResolvedQName,
+};
+use crate::profile::Profile;
+use crate::strings::{CDataStr, NcNameStr};
 
 /**
 # Source for individual XML events
@@ -72,6 +88,364 @@ pub trait EventRead {
 	{
 		as_eof_flag(self.read_all(cb))
 	}
+
+	/// Skip events until the start of an element for which `predicate`
+	/// returns true.
+	///
+	/// The predicate is called with the name and the attributes of each
+	/// element, at any depth. The name and attributes of the first matching
+	/// element are returned, so that its content can be processed further,
+	/// for instance with [`tree::read_element`](crate::tree::read_element).
+	/// If the end of the document is reached without a match, `None` is
+	/// returned.
+	///
+	/// The [`PullParser`] provides an equivalent method which does not
+	/// construct events for the skipped data.
+	///
+	/// ```
+	/// # #[cfg(feature = "std")]
+	/// # fn main() {
+	/// use rxml::{EventRead, PullParser};
+	/// use rxml::tree;
+	///
+	/// let mut pp = PullParser::new(&b"<a><b id='1'/><c><b id='2'>x</b></c></a>"[..]);
+	/// let (name, attrs) = pp
+	/// 	.find_element(|_, attrs| attrs.attr(None, "id").map_or(false, |v| &*v == "2"))
+	/// 	.unwrap()
+	/// 	.unwrap();
+	/// let b = tree::read_element(&mut pp, name, attrs).unwrap();
+	/// assert_eq!(b.text(), "x");
+	/// assert!(pp.find_element(|_, _| true).unwrap().is_none());
+	/// # }
+	/// # #[cfg(not(feature = "std"))]
+	/// # fn main() {}
+	/// ```
+	///
+	/// Errors are handled as in [`read`](Self::read). As the predicate is
+	/// called for each element anew, the search can be resumed after a
+	/// retryable I/O error.
+	fn find_element<F>(&mut self, predicate: F) -> Result<Option<(ResolvedQName, Attributes)>>
+	where
+		Self: EventRead<Output = ResolvedEvent>,
+		F: FnMut(&ResolvedQName, &Attributes) -> bool,
+	{
+		FindElement::new(predicate, false).read_from(self)
+	}
+
+	/// Skip events until the start of a child element for which `predicate`
+	/// returns true.
+	///
+	/// This must be called after the start of an element has been read. It
+	/// works like [`find_element`](Self::find_element), but the predicate is
+	/// only called for the direct children of that element; their content is
+	/// skipped. If the end of the element is reached without a match, `None`
+	/// is returned and the end of the element has been consumed.
+	///
+	/// ```
+	/// # #[cfg(feature = "std")]
+	/// # fn main() {
+	/// use rxml::{EventRead, PullParser};
+	///
+	/// let mut pp = PullParser::new(&b"<a><b><c/></b><c n='1'/><c n='2'/></a>"[..]);
+	/// pp.read().unwrap();
+	/// let (_, attrs) = pp.find_in_children(|name, _| name.1 == "c").unwrap().unwrap();
+	/// assert_eq!(&*attrs.attr(None, "n").unwrap(), "1");
+	/// # }
+	/// # #[cfg(not(feature = "std"))]
+	/// # fn main() {}
+	/// ```
+	///
+	/// The depth within the element is not retained across errors, so the
+	/// search cannot be resumed after an I/O error. This is best used with
+	/// blocking readers, such as the [`PullParser`].
+	fn find_in_children<F>(&mut self, predicate: F) -> Result<Option<(ResolvedQName, Attributes)>>
+	where
+		Self: EventRead<Output = ResolvedEvent>,
+		F: FnMut(&ResolvedQName, &Attributes) -> bool,
+	{
+		FindElement::new(predicate, true).read_from(self)
+	}
+}
+
+/// State of [`EventRead::find_element`] and [`EventRead::find_in_children`].
+struct FindElement<F> {
+	predicate: F,
+	/// Only match elements at depth zero and stop at the end of the
+	/// enclosing element.
+	children_only: bool,
+	/// Number of open elements since the search started.
+	depth: usize,
+	/// Match found by the [`EventSink`] implementation.
+	found: Option<(ResolvedQName, Attributes)>,
+	/// Set once the end of the enclosing element has been reached.
+	done: bool,
+}
+
+impl<F: FnMut(&ResolvedQName, &Attributes) -> bool> FindElement<F> {
+	fn new(predicate: F, children_only: bool) -> Self {
+		Self {
+			predicate,
+			children_only,
+			depth: 0,
+			found: None,
+			done: false,
+		}
+	}
+
+	/// Process the start of an element and return whether it matches.
+	fn start(&mut self, name: &ResolvedQName, attrs: &Attributes) -> bool {
+		if (!self.children_only || self.depth == 0) && (self.predicate)(name, attrs) {
+			return true;
+		}
+		self.depth += 1;
+		false
+	}
+
+	/// Process the end of an element and return whether the search is over.
+	fn end(&mut self) -> bool {
+		match self.depth.checked_sub(1) {
+			Some(depth) => {
+				self.depth = depth;
+				false
+			}
+			None => self.children_only,
+		}
+	}
+
+	fn read_from<R: EventRead<Output = ResolvedEvent> + ?Sized>(
+		&mut self,
+		r: &mut R,
+	) -> Result<Option<(ResolvedQName, Attributes)>> {
+		loop {
+			match r.read()? {
+				Some(ResolvedEvent::StartElement(_, name, attrs, _)) => {
+					if self.start(&name, &attrs) {
+						return Ok(Some((name, attrs)));
+					}
+				}
+				Some(ResolvedEvent::EndElement(_)) => {
+					if self.end() {
+						return Ok(None);
+					}
+				}
+				Some(_) => (),
+				None => return Ok(None),
+			}
+		}
+	}
+}
+
+impl<F: FnMut(&ResolvedQName, &Attributes) -> bool> EventSink for FindElement<F> {
+	fn start_element(
+		&mut self,
+		_: &EventMetrics,
+		name: &ResolvedQName,
+		attrs: &Attributes,
+		_: Option<&NcNameStr>,
+	) {
+		if self.start(name, attrs) {
+			self.found = Some((name.clone(), attrs.clone()));
+		}
+	}
+
+	fn end_element(&mut self, _: &EventMetrics) {
+		self.done = self.end();
+	}
+
+	fn text(&mut self, _: &EventMetrics, _: &CDataStr) {}
+}
+
+/**
+Parse a complete document from a byte slice and return all its events.
+
+This is a shorthand for setting up a [`FeedParser`], feeding it `data` with
+the end-of-file flag set and collecting the events. It fails if `data` is
+not a complete, well-formed document.
+
+Text may be split into several [`ResolvedEvent::Text`] events, as with the
+other frontends.
+
+```
+use rxml::ResolvedEvent;
+
+let events = rxml::parse_events(b"<hello>World!</hello>").unwrap();
+assert_eq!(events.len(), 3);
+assert!(matches!(events[2], ResolvedEvent::EndElement(..)));
+assert!(rxml::parse_events(b"<hello>World!").is_err());
+```
+*/
+pub fn parse_events(mut data: &[u8]) -> Result<Vec<ResolvedEvent>> {
+	let mut fp = FeedParser::default();
+	let mut events = Vec::new();
+	fp.parse_all(&mut data, true, |ev| events.push(ev))?;
+	Ok(events)
+}
+
+/// Sink which discards all events, for [`validate`].
+struct Discard;
+
+impl EventSink for Discard {
+	fn start_element(
+		&mut self,
+		_: &EventMetrics,
+		_: &ResolvedQName,
+		_: &Attributes,
+		_: Option<&NcNameStr>,
+	) {
+	}
+
+	fn end_element(&mut self, _: &EventMetrics) {}
+
+	fn text(&mut self, _: &EventMetrics, _: &CDataStr) {}
+}
+
+/**
+Check that a complete document is well-formed and namespace-well-formed.
+
+The document is parsed like with [`parse_events`], but through the
+[`EventSink`] interface with a sink which discards everything, so that the
+events are not collected. Parsing still allocates like it does for any
+other use of the parser (e.g. for element names and attribute values);
+only text is passed to the sink straight from `data`. Only the verdict is
+returned; on failure, the error carries the byte offset at which it was
+detected and an excerpt of `data` around it.
+
+The same checks as in [`FeedParser::default()`] are applied, including its
+limits on token and attribute counts.
+
+```
+assert!(rxml::validate(b"<a xmlns='urn:x'><b/>text</a>").is_ok());
+
+let err = rxml::validate(b"<a><p:b/></a>").unwrap_err();
+assert_eq!(err.offset(), 9);
+assert!(err.error().is_wellformedness());
+```
+*/
+pub fn validate(mut data: &[u8]) -> core::result::Result<(), LocatedError> {
+	let input = data;
+	let mut fp = FeedParser::default();
+	fp.parse_all_into(&mut data, true, &mut Discard)
+		.map_err(|e| LocatedError::new(e, input, fp.get_lexer().position()))
+}
+
+/**
+# Parser for documents held completely in memory
+
+The `SliceParser` parses a complete document from a byte slice and returns
+[`BorrowedEvent`]s, which refer to the slice instead of copying data out of
+it where possible:
+
+* Text is returned as a slice of the input, without any allocation, as
+  long as it appears literally in the document. Text around references,
+  CDATA sections and some special characters (such as carriage returns)
+  is owned.
+* Element names, attribute names and attribute values are owned, as the
+  lexer and the namespace resolver produce them the same way as for the
+  [`FeedParser`]. Names of up to 23 bytes are stored inline and do not
+  allocate (see the [`strings`](crate::strings) module); longer names are
+  shared through the parser's [`Context`] if the `shared_ns` feature is
+  enabled. Attribute values are copied.
+
+This makes it a good fit for in-memory workloads, for instance documents
+which have been read from a file or a database as a whole. The events
+cannot outlive the input, but are otherwise independent of the parser; use
+[`BorrowedEvent::into_owned`] to detach them from the input.
+
+As the complete document is available, reaching the end of the slice is
+treated as the end of the document.
+
+```
+use std::borrow::Cow;
+use rxml::{BorrowedEvent, EventRead, SliceParser};
+
+let doc = b"<greeting xmlns='urn:example'>Hello &amp; welcome!</greeting>";
+let mut events = Vec::new();
+SliceParser::new(doc).read_all(|ev| events.push(ev)).unwrap();
+match &events[1] {
+	BorrowedEvent::Text(_, text) => {
+		assert!(matches!(text, Cow::Borrowed(_)));
+		assert_eq!(&**text, "Hello ");
+	}
+	other => panic!("unexpected event: {:?}", other),
+}
+let text: String = events.iter().filter_map(|ev| match ev {
+	BorrowedEvent::Text(_, text) => Some(text.to_string()),
+	_ => None,
+}).collect();
+assert_eq!(text, "Hello & welcome!");
+```
+*/
+pub struct SliceParser<'a> {
+	rest: &'a [u8],
+	driver: PushDriver<Parser>,
+}
+
+impl<'a> SliceParser<'a> {
+	/// Create a new parser with default options for the given document.
+	pub fn new(data: &'a [u8]) -> Self {
+		Self::with_options(data, LexerOptions::default())
+	}
+
+	/// Create a new parser for the given document while configuring the
+	/// lexer with the given options.
+	pub fn with_options(data: &'a [u8], options: LexerOptions) -> Self {
+		Self {
+			rest: data,
+			driver: PushDriver::wrap(Lexer::with_options(options), Parser::default()),
+		}
+	}
+
+	/// Create a new parser for the given document, configured according to
+	/// the given [`Profile`].
+	pub fn with_profile(data: &'a [u8], profile: Profile) -> Self {
+		Self {
+			rest: data,
+			driver: PushDriver::with_profile(profile),
+		}
+	}
+
+	/// Access the parser
+	pub fn get_parser(&self) -> &Parser {
+		self.driver.get_parser()
+	}
+
+	/// Access the parser, mutably
+	pub fn get_parser_mut(&mut self) -> &mut Parser {
+		self.driver.get_parser_mut()
+	}
+
+	/// Return the part of the input which has not been consumed yet.
+	///
+	/// After the end of the root element, this is the data following the
+	/// document, see [`PushDriver::parse`].
+	pub fn remainder(&self) -> &'a [u8] {
+		self.rest
+	}
+}
+
+impl<'a> EventRead for SliceParser<'a> {
+	type Output = BorrowedEvent<'a>;
+
+	/// Read a single event from the document.
+	///
+	/// If the end of the document has been reached, `None` is returned.
+	/// Errors are fatal and will be returned again on the next invocation.
+	fn read(&mut self) -> Result<Option<BorrowedEvent<'a>>> {
+		let rest = self.rest;
+		if let Some((metrics, text)) = self
+			.driver
+			.parser
+			.take_borrowed_text(&mut self.driver.lexer, rest)
+		{
+			self.rest = &rest[text.len()..];
+			return Ok(Some(BorrowedEvent::Text(metrics, Cow::Borrowed(text))));
+		}
+		let ev = match self.driver.parse(&mut self.rest, true)? {
+			Some(ev) => ev,
+			None => return Ok(None),
+		};
+		Ok(Some(ev.into()))
+	}
 }
 
 /**
@@ -82,7 +456,33 @@ This is a generic non-blocking push-based driver for objects implementing the
 
 In general, it is advised to use the [`FeedParser`] alias which specializes
 this struct for use with the default [`Parser`].
+
+## Checkpoints
+
+As the driver does not own the data it is fed with, its complete state can
+be snapshotted by cloning it (if the parser can be cloned, which is the case
+for the parsers of this crate). The clone continues from the same position
+independently of the original, which allows to try out different
+continuations of a document or to go back to an earlier point of a stream,
+provided that the data since then is still available.
+
+```
+use rxml::FeedParser;
+
+let mut fp = FeedParser::default();
+let mut events = Vec::new();
+rxml::as_eof_flag(fp.parse_all(&mut &b"<a>"[..], false, |ev| events.push(ev))).unwrap();
+
+let checkpoint = fp.clone();
+assert!(fp.parse_all(&mut &b"</b>"[..], true, |ev| events.push(ev)).is_err());
+
+// resume from the checkpoint with different data
+let mut fp = checkpoint;
+fp.parse_all(&mut &b"</a>"[..], true, |ev| events.push(ev)).unwrap();
+assert_eq!(events.len(), 2);
+```
 */
+#[derive(Clone)]
 pub struct PushDriver<P: Parse> {
 	parser: P,
 	lexer: Lexer,
@@ -97,7 +497,7 @@ pub struct PushDriver<P: Parse> {
 /// All other errors are passed through.
 pub fn as_eof_flag(r: Result<()>) -> Result<bool> {
 	match r {
-		Err(Error::IO(ioerr)) if ioerr.kind() == io::ErrorKind::WouldBlock => Ok(false),
+		Err(e) if e.is_would_block() => Ok(false),
 		Err(e) => Err(e),
 		Ok(()) => Ok(true),
 	}
@@ -145,15 +545,84 @@ impl<P: Parse> PushDriver<P> {
 	///
 	/// If the end of the buffer is reached while `at_eof` is false, an I/O
 	/// error of kind [`std::io::ErrorKind::WouldBlock`] is emitted.
+	///
+	/// # Data after the document
+	///
+	/// The driver does not take ownership of the data: bytes which have not
+	/// been consumed stay in `data`. Once the event closing the root
+	/// element has been returned (see [`is_document_complete()`]), no
+	/// further bytes are consumed unless `parse()` is called again. This
+	/// allows to recover data which follows the document, such as the
+	/// beginning of the next protocol frame.
+	///
+	/// When an error other than an I/O error is returned, `data` has been
+	/// advanced up to and including the offending byte.
+	///
+	///    [`is_document_complete()`]: Self::is_document_complete
 	pub fn parse<T: bytes::Buf>(
 		&mut self,
 		data: &mut T,
 		at_eof: bool,
+	) -> Result<Option<P::Output>> {
+		// SAFETY: no chunk is claimed to be valid UTF-8
+		unsafe { self.parse_with(data, at_eof, |_| false) }
+	}
+
+	/// Parse bytes from a [`BufferQueue`], until either an error occurs, a
+	/// valid event is emitted or EOF is reached.
+	///
+	/// This behaves like [`parse()`](Self::parse), except that buffers
+	/// which have been added to the queue as text (see
+	/// [`BufferQueue::push_str()`]) are not validated as UTF-8 again.
+	#[allow(deprecated)]
+	pub fn parse_queue(
+		&mut self,
+		data: &mut BufferQueue<'_>,
+		at_eof: bool,
+	) -> Result<Option<P::Output>> {
+		// SAFETY: the queue only claims chunks which were pushed as str to
+		// be valid
+		unsafe { self.parse_with(data, at_eof, BufferQueue::chunk_is_utf8) }
+	}
+
+	/// Parse all data from a [`BufferQueue`] and pass the generated events
+	/// to a callback.
+	///
+	/// This is the [`parse_all()`](Self::parse_all) equivalent of
+	/// [`parse_queue()`](Self::parse_queue).
+	#[allow(deprecated)]
+	pub fn parse_all_queue<F: FnMut(P::Output)>(
+		&mut self,
+		data: &mut BufferQueue<'_>,
+		at_eof: bool,
+		mut f: F,
+	) -> Result<()> {
+		loop {
+			match self.parse_queue(data, at_eof)? {
+				None => return Ok(()),
+				Some(ev) => f(ev),
+			}
+		}
+	}
+
+	/// Parse from `data`, skipping the UTF-8 validation for chunks for
+	/// which `is_utf8` returns true.
+	///
+	/// # Safety
+	///
+	/// `is_utf8` must only return true if the current chunk of `data` is
+	/// the unread part of a `str`.
+	pub(crate) unsafe fn parse_with<T: bytes::Buf>(
+		&mut self,
+		data: &mut T,
+		at_eof: bool,
+		is_utf8: fn(&T) -> bool,
 	) -> Result<Option<P::Output>> {
 		self.parser.parse(&mut BufferLexerAdapter {
 			lexer: &mut self.lexer,
 			buf: data,
 			eof: at_eof,
+			is_utf8,
 		})
 	}
 
@@ -184,6 +653,59 @@ impl<P: Parse> PushDriver<P> {
 		}
 	}
 
+	/// Return true if the end of the root element has been reached and all
+	/// its events have been returned.
+	///
+	/// See [`parse()`](Self::parse) for how to recover data following the
+	/// document.
+	pub fn is_document_complete(&self) -> bool {
+		self.parser.is_document_complete()
+	}
+
+	/// Parse all data from the given string and pass the generated events
+	/// to a callback.
+	///
+	/// This behaves like [`parse_all()`], except that the UTF-8 validation
+	/// of the input is skipped, as a `str` is already known to be valid.
+	/// This is useful if the input is already available as text, for
+	/// instance from a WebSocket text frame.
+	///
+	/// The string must be passed completely; if an event cannot be
+	/// completed, the lexer keeps the required data internally, as with
+	/// [`parse_all()`]. Data can be passed to the other parse functions
+	/// afterwards, and the other way round; it is then validated as usual.
+	///
+	/// ```
+	/// use rxml::{FeedParser, ResolvedEvent};
+	/// let mut fp = FeedParser::default();
+	/// let mut events = Vec::new();
+	/// let result = fp.parse_all_str("<hello>Wörld", false, |ev| events.push(ev));
+	/// assert!(rxml::as_eof_flag(result).is_ok());
+	/// fp.parse_all_str("!</hello>", true, |ev| events.push(ev)).unwrap();
+	/// match &events[1] {
+	/// 	ResolvedEvent::Text(_, text) => assert_eq!(text, "Wörld!"),
+	/// 	other => panic!("unexpected event: {:?}", other),
+	/// }
+	/// ```
+	///
+	///    [`parse_all()`]: Self::parse_all
+	pub fn parse_all_str<F: FnMut(P::Output)>(
+		&mut self,
+		data: &str,
+		at_eof: bool,
+		mut f: F,
+	) -> Result<()> {
+		let mut data = data.as_bytes();
+		loop {
+			// SAFETY: data is the unread part of a str
+			let ev = unsafe { self.parse_with(&mut data, at_eof, |_| true) }?;
+			match ev {
+				None => return Ok(()),
+				Some(ev) => f(ev),
+			}
+		}
+	}
+
 	/// Access the lexer
 	pub fn get_lexer(&self) -> &Lexer {
 		&self.lexer
@@ -218,6 +740,167 @@ impl<P: Parse> PushDriver<P> {
 		self.get_lexer_mut().release_temporaries();
 		self.get_parser_mut().release_temporaries();
 	}
+
+	/// Obtain a [`std::io::Write`] adapter which feeds all written bytes
+	/// into this driver.
+	///
+	/// Events are passed to the given callback. See [`FeedWriter`] for
+	/// details.
+	#[cfg(feature = "std")]
+	pub fn as_writer<F: FnMut(P::Output)>(&mut self, f: F) -> FeedWriter<'_, P, F> {
+		FeedWriter { driver: self, f }
+	}
+}
+
+impl PushDriver<Parser> {
+	/// Create a new push driver configured according to the given
+	/// [`Profile`].
+	pub fn with_profile(profile: Profile) -> Self {
+		Self::wrap(profile.lexer(), profile.parser())
+	}
+
+	/// Parse all data from the given buffer and pass the generated events,
+	/// together with the byte ranges of completed children of the root
+	/// element, to a callback.
+	///
+	/// This behaves like [`parse_all()`](Self::parse_all), but the range
+	/// returned by [`Parser::completed_child`] is passed along with each
+	/// event. It is `Some` exactly for the events which end a child of the
+	/// root element.
+	///
+	/// ```
+	/// use rxml::FeedParser;
+	///
+	/// let doc = &b"<stream><iq type='get'><ping/></iq></stream>"[..];
+	/// let mut fp = FeedParser::default();
+	/// let mut ranges = Vec::new();
+	/// fp.parse_all_framed(&mut &doc[..], true, |_, range| ranges.extend(range)).unwrap();
+	/// assert_eq!(ranges, [8..35]);
+	/// assert_eq!(&doc[8..35], b"<iq type='get'><ping/></iq>");
+	/// ```
+	pub fn parse_all_framed<T: bytes::Buf, F: FnMut(ResolvedEvent, Option<Range<u64>>)>(
+		&mut self,
+		data: &mut T,
+		at_eof: bool,
+		mut f: F,
+	) -> Result<()> {
+		loop {
+			match self.parse(data, at_eof)? {
+				None => return Ok(()),
+				Some(ev) => f(ev, self.parser.completed_child()),
+			}
+		}
+	}
+
+	/// Parse all data from the given buffer and pass the events to an
+	/// [`EventSink`].
+	///
+	/// This behaves like [`parse_all()`](Self::parse_all), but the events
+	/// are not materialized as [`ResolvedEvent`](crate::ResolvedEvent)s.
+	/// See [`EventSink`] for an example.
+	///
+	/// Character data is passed to the sink directly from `data` where
+	/// possible, so text may be split into more calls of
+	/// [`EventSink::text`] than with `parse_all()`.
+	pub fn parse_all_into<T: bytes::Buf, S: EventSink + ?Sized>(
+		&mut self,
+		data: &mut T,
+		at_eof: bool,
+		sink: &mut S,
+	) -> Result<()> {
+		loop {
+			// character data which is contiguous in the buffer is passed on
+			// without copying
+			let n = self
+				.parser
+				.parse_borrowed_text(&mut self.lexer, data.chunk(), sink);
+			if n > 0 {
+				data.advance(n);
+				continue;
+			}
+			let mut source = BufferLexerAdapter {
+				lexer: &mut self.lexer,
+				buf: data,
+				eof: at_eof,
+				is_utf8: |_| false,
+			};
+			if !self.parser.parse_into(&mut source, sink)? {
+				return Ok(());
+			}
+		}
+	}
+}
+
+/**
+# Write adapter for push drivers
+
+The [`FeedWriter`] implements [`std::io::Write`] and feeds all data written
+to it into a [`PushDriver`]. Events are handed to a callback as they are
+produced. This allows to use [`std::io::copy`] and other write-oriented
+interfaces with the parser without collecting the data in an intermediate
+buffer first.
+
+As the written data is always fully consumed, short writes do not occur.
+Parser errors are reported as [`std::io::Error`] of kind
+[`std::io::ErrorKind::InvalidData`], wrapping the original [`Error`]. After
+all data has been written, [`FeedWriter::finish()`] must be called to
+signal the end of the document and to detect truncated input.
+
+## Example
+
+```
+use rxml::{FeedParser, ResolvedEvent};
+use std::io;
+
+let mut src = &b"<?xml version='1.0'?><hello>World!</hello>"[..];
+let mut fp = FeedParser::default();
+let mut events = Vec::new();
+let mut w = fp.as_writer(|ev| events.push(ev));
+io::copy(&mut src, &mut w).unwrap();
+w.finish().unwrap();
+assert!(matches!(events[0], ResolvedEvent::XmlDeclaration(..)));
+assert_eq!(events.len(), 4);
+```
+*/
+#[cfg(feature = "std")]
+pub struct FeedWriter<'x, P: Parse, F> {
+	driver: &'x mut PushDriver<P>,
+	f: F,
+}
+
+#[cfg(feature = "std")]
+impl<'x, P: Parse, F: FnMut(P::Output)> FeedWriter<'x, P, F> {
+	/// Access the driver
+	pub fn get_driver(&self) -> &PushDriver<P> {
+		self.driver
+	}
+
+	/// Access the driver, mutably
+	pub fn get_driver_mut(&mut self) -> &mut PushDriver<P> {
+		self.driver
+	}
+
+	/// Signal the end of the document to the driver.
+	///
+	/// Any events which can be emitted because of this are passed to the
+	/// callback. If the document is incomplete, an error is returned.
+	pub fn finish(mut self) -> Result<()> {
+		self.driver.parse_all(&mut &[][..], true, &mut self.f)
+	}
+}
+
+#[cfg(feature = "std")]
+impl<'x, P: Parse, F: FnMut(P::Output)> io::Write for FeedWriter<'x, P, F> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		match as_eof_flag(self.driver.parse_all(&mut &buf[..], false, &mut self.f)) {
+			Ok(_) => Ok(buf.len()),
+			Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+		}
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
 }
 
 /**
@@ -229,11 +912,13 @@ This is a generic blocking pull-based driver for objects implementing the
 In general, it is advised to use the [`PullParser`] alias which specializes
 this struct for use with the default [`Parser`].
 */
+#[cfg(feature = "std")]
 pub struct PullDriver<T: io::BufRead, P: Parse> {
 	parser: P,
 	token_source: LexerAdapter<T>,
 }
 
+#[cfg(feature = "std")]
 impl<T: io::BufRead, P: Parse + Default> PullDriver<T, P> {
 	/// Create a new parser with default options, wrapping the given reader.
 	pub fn new(inner: T) -> Self {
@@ -247,6 +932,128 @@ impl<T: io::BufRead, P: Parse + Default> PullDriver<T, P> {
 	}
 }
 
+#[cfg(feature = "std")]
+impl<T: io::BufRead> PullDriver<T, Parser> {
+	/// Create a new parser wrapping the given reader, configured according
+	/// to the given [`Profile`].
+	pub fn with_profile(inner: T, profile: Profile) -> Self {
+		Self::wrap(inner, profile.lexer(), profile.parser())
+	}
+}
+
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+impl<'x, P: Parse + Default> PullDriver<BufferQueue<'x>, P> {
+	/// Create a new parser with default options, reading from a
+	/// [`BufferQueue`].
+	///
+	/// In contrast to [`new()`](Self::new), buffers which have been added
+	/// to the queue as text (see [`BufferQueue::push_str()`]) are not
+	/// validated as UTF-8 again.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::{BufferQueue, EventRead, PullParser, ResolvedEvent};
+	///
+	/// let mut bq = BufferQueue::new();
+	/// bq.push_str("<hello>Wörld!</hello>");
+	/// bq.push_eof();
+	/// let mut pp = PullParser::from_queue(bq);
+	/// let mut events = Vec::new();
+	/// pp.read_all(|ev| events.push(ev)).unwrap();
+	/// match &events[1] {
+	/// 	ResolvedEvent::Text(_, text) => assert_eq!(text, "Wörld!"),
+	/// 	other => panic!("unexpected event: {:?}", other),
+	/// }
+	/// ```
+	pub fn from_queue(inner: BufferQueue<'x>) -> Self {
+		Self::from_queue_with_options(inner, LexerOptions::default())
+	}
+
+	/// Create a new parser reading from a [`BufferQueue`], while
+	/// configuring the lexer with the given options.
+	///
+	/// See [`from_queue()`](Self::from_queue) for details.
+	pub fn from_queue_with_options(inner: BufferQueue<'x>, options: LexerOptions) -> Self {
+		Self {
+			token_source: LexerAdapter::with_queue(Lexer::with_options(options), inner),
+			parser: P::default(),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read, P: Parse + Default> PullDriver<io::BufReader<R>, P> {
+	/// Create a new parser with default options, reading from `inner`
+	/// through a [`BufReader`](io::BufReader) with the given capacity.
+	///
+	/// The capacity determines how much data is read from `inner` at once.
+	/// Small values reduce the memory footprint, large values reduce the
+	/// number of reads. The lexer's own token buffers are configured
+	/// independently via [`LexerOptions`]; use
+	/// [`with_capacity_and_options`](Self::with_capacity_and_options) to set
+	/// both.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::{PullParser, EventRead};
+	///
+	/// let mut pp = PullParser::with_capacity(16, &b"<a>hello world</a>"[..]);
+	/// let mut n = 0;
+	/// pp.read_all(|_| n += 1).unwrap();
+	/// assert!(n >= 3);
+	/// ```
+	pub fn with_capacity(capacity: usize, inner: R) -> Self {
+		Self::with_capacity_and_options(capacity, inner, LexerOptions::default())
+	}
+
+	/// Create a new parser reading from `inner` through a
+	/// [`BufReader`](io::BufReader) with the given capacity, while
+	/// configuring the lexer with the given options.
+	pub fn with_capacity_and_options(capacity: usize, inner: R, options: LexerOptions) -> Self {
+		Self::with_options(io::BufReader::with_capacity(capacity, inner), options)
+	}
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read, P: Parse + Default> PullDriver<QueueReader<R>, P> {
+	/// Create a new parser with default options, reading from any
+	/// [`io::Read`].
+	///
+	/// The data is buffered in a [`QueueReader`] with its default capacity.
+	/// Once the document is complete, data which has been read ahead can be
+	/// recovered from the queue (see [`into_inner()`](Self::into_inner)
+	/// and [`QueueReader::into_inner()`]).
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::{EventRead, PullParser};
+	///
+	/// let f = std::io::Cursor::new(b"<a>hello world</a>".to_vec());
+	/// let mut pp = PullParser::from_reader(f);
+	/// let mut n = 0;
+	/// pp.read_all(|_| n += 1).unwrap();
+	/// assert_eq!(n, 3);
+	/// ```
+	pub fn from_reader(inner: R) -> Self {
+		Self::new(QueueReader::new(inner))
+	}
+
+	/// Create a new parser with default options, reading at most
+	/// `capacity` bytes at once from `inner`.
+	///
+	/// To configure the lexer too, pass a
+	/// [`QueueReader::with_capacity()`] to
+	/// [`with_options()`](Self::with_options) instead.
+	pub fn from_reader_with_capacity(capacity: usize, inner: R) -> Self {
+		Self::new(QueueReader::with_capacity(capacity, inner))
+	}
+}
+
+#[cfg(feature = "std")]
 impl<T: io::BufRead, P: Parse> PullDriver<T, P> {
 	/// Create a fully customized parser from a lexer and a parser component.
 	pub fn wrap(inner: T, lexer: Lexer, parser: P) -> Self {
@@ -265,6 +1072,66 @@ impl<T: io::BufRead, P: Parse> PullDriver<T, P> {
 		self.token_source.get_mut()
 	}
 
+	/// Access the inner BufRead
+	///
+	/// This is the same as [`get_inner()`](Self::get_inner), named after
+	/// the accessors of the standard library readers.
+	pub fn get_ref(&self) -> &T {
+		self.token_source.get_ref()
+	}
+
+	/// Access the inner BufRead, mutably
+	///
+	/// Reading from the inner BufRead directly while the document is not
+	/// complete yet will corrupt the parser state.
+	pub fn get_mut(&mut self) -> &mut T {
+		self.token_source.get_mut()
+	}
+
+	/// Return true if the root element has been closed and all events
+	/// belonging to it have been returned.
+	///
+	/// See [`into_inner()`](Self::into_inner) for how to recover data
+	/// following the document.
+	pub fn is_document_complete(&self) -> bool {
+		self.parser.is_document_complete()
+	}
+
+	/// Decompose the driver and return the inner BufRead.
+	///
+	/// The lexer consumes bytes from the reader only as far as needed for
+	/// the tokens it has emitted so far. Once the event closing the root
+	/// element has been returned (see
+	/// [`is_document_complete()`](Self::is_document_complete)), nothing
+	/// beyond the end of the document has been consumed: any following
+	/// data, including whitespace, is still available from the returned
+	/// reader (or its buffer), so that the connection can be used for a
+	/// different protocol.
+	///
+	/// If the document is not complete, bytes belonging to a partially
+	/// read token have already been consumed from the reader and are lost
+	/// together with the lexer.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use std::io::BufRead;
+	/// use rxml::{EventRead, PullParser};
+	///
+	/// let mut pp = PullParser::new(&b"<a>foo</a>\nSTARTTLS"[..]);
+	/// while !pp.is_document_complete() {
+	/// 	pp.read().unwrap();
+	/// }
+	/// let mut rest = pp.into_inner();
+	/// let mut line = String::new();
+	/// rest.read_line(&mut line).unwrap();
+	/// rest.read_line(&mut line).unwrap();
+	/// assert_eq!(line, "\nSTARTTLS");
+	/// ```
+	pub fn into_inner(self) -> T {
+		self.token_source.into_inner().1
+	}
+
 	/// Access the lexer
 	pub fn get_lexer(&self) -> &Lexer {
 		self.token_source.get_lexer()
@@ -286,6 +1153,85 @@ impl<T: io::BufRead, P: Parse> PullDriver<T, P> {
 	}
 }
 
+#[cfg(feature = "std")]
+impl<T: io::BufRead> PullDriver<T, Parser> {
+	/// Read a single event and pass it to an [`EventSink`].
+	///
+	/// Returns false if the end of the document has been reached, true
+	/// otherwise. Error behaviour is identical to [`EventRead::read`].
+	///
+	/// Character data which is available contiguously in the buffer of the
+	/// source is passed to the sink directly from that buffer, without
+	/// copying it first. Long text may thus be split into more calls of
+	/// [`EventSink::text`] than with [`EventRead::read`].
+	pub fn read_into<S: EventSink + ?Sized>(&mut self, sink: &mut S) -> Result<bool> {
+		let (lexer, src) = self.token_source.parts_mut();
+		// errors are left to the regular path, which calls fill_buf again
+		if let Ok(buf) = src.fill_buf() {
+			let n = self.parser.parse_borrowed_text(lexer, buf, sink);
+			if n > 0 {
+				src.consume(n);
+				return Ok(true);
+			}
+		}
+		self.parser.parse_into(&mut self.token_source, sink)
+	}
+
+	/// Read all events and pass them to an [`EventSink`].
+	///
+	/// This behaves like [`EventRead::read_all`], but the events are not
+	/// materialized as [`ResolvedEvent`](crate::ResolvedEvent)s.
+	pub fn read_all_into<S: EventSink + ?Sized>(&mut self, sink: &mut S) -> Result<()> {
+		while self.read_into(sink)? {}
+		Ok(())
+	}
+
+	/// Skip events until the start of an element for which `predicate`
+	/// returns true.
+	///
+	/// This behaves like [`EventRead::find_element`], but the skipped data
+	/// is read through an [`EventSink`]: no events are constructed for it
+	/// and text is not copied out of the buffer of the source.
+	pub fn find_element<F>(&mut self, predicate: F) -> Result<Option<(ResolvedQName, Attributes)>>
+	where
+		F: FnMut(&ResolvedQName, &Attributes) -> bool,
+	{
+		self.find_into(FindElement::new(predicate, false))
+	}
+
+	/// Skip events until the start of a child element for which `predicate`
+	/// returns true.
+	///
+	/// This behaves like [`EventRead::find_in_children`], but avoids
+	/// constructing events for the skipped data, see
+	/// [`find_element`](Self::find_element).
+	pub fn find_in_children<F>(
+		&mut self,
+		predicate: F,
+	) -> Result<Option<(ResolvedQName, Attributes)>>
+	where
+		F: FnMut(&ResolvedQName, &Attributes) -> bool,
+	{
+		self.find_into(FindElement::new(predicate, true))
+	}
+
+	fn find_into<F>(
+		&mut self,
+		mut finder: FindElement<F>,
+	) -> Result<Option<(ResolvedQName, Attributes)>>
+	where
+		F: FnMut(&ResolvedQName, &Attributes) -> bool,
+	{
+		while finder.found.is_none() && !finder.done {
+			if !self.read_into(&mut finder)? {
+				break;
+			}
+		}
+		Ok(finder.found)
+	}
+}
+
+#[cfg(feature = "std")]
 impl<T: io::BufRead, P: Parse> EventRead for PullDriver<T, P> {
 	type Output = P::Output;
 
@@ -320,15 +1266,11 @@ trait.
 ## Example
 
 ```
-use rxml::{FeedParser, Error, ResolvedEvent, XmlVersion, EventRead};
-use std::io;
+use rxml::{FeedParser, ResolvedEvent, XmlVersion, EventRead};
 let doc = b"<?xml version='1.0'?><hello>World!</hello>";
 let mut fp = FeedParser::new();
 // We expect a WouldBlock, because the XML declaration is not complete yet
-assert!(matches!(
-	fp.parse(&mut &doc[..10], false).err().unwrap(),
-	Error::IO(e) if e.kind() == io::ErrorKind::WouldBlock
-));
+assert!(fp.parse(&mut &doc[..10], false).err().unwrap().is_would_block());
 
 // Now we pass the XML declaration (and some), so we expect a corresponding
 // event
@@ -354,7 +1296,9 @@ blockingly. The parser will block until the backing [`io::Read`] has enough
 data available (or returns an error).
 
 This is a type alias around a [`PullDriver`] and documentation for the API is
-found there.
+found there. The source needs to be an [`io::BufRead`]; plain [`io::Read`]
+implementations can be used with [`PullDriver::from_reader()`], which buffers
+the data in a [`QueueReader`](crate::QueueReader).
 
 Interaction with a `PullParser` should happen exclusively via the
 [`EventRead`] trait.
@@ -392,4 +1336,5 @@ one can use the [`PullDriver`] with a [`RawParser`]. Note the caveats in the
    [`RawParser`]: crate::parser::RawParser
    [`AsyncParser`]: crate::AsyncParser
 */
+#[cfg(feature = "std")]
 pub type PullParser<T> = PullDriver<T, Parser>;
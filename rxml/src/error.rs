@@ -4,15 +4,25 @@
 This module holds the error types returned by the various functions of this
 crate.
 */
+use alloc::boxed::Box;
+use alloc::string::String;
+#[cfg(feature = "miette")]
+use core::convert::TryFrom;
+use core::fmt;
+#[cfg(feature = "std")]
+use core::ops::Deref;
+use core::result::Result as StdResult;
+#[cfg(feature = "std")]
 use std::error;
-use std::fmt;
+#[cfg(feature = "std")]
 use std::io;
-use std::ops::Deref;
-use std::result::Result as StdResult;
+#[cfg(feature = "std")]
 use std::sync::Arc;
 
 use rxml_validation::Error as ValidationError;
 
+use crate::strings::{Name, NameStr, NcName, NcNameStr};
+
 pub(crate) use crate::errctx::*;
 
 /// Violation of a well-formedness or namespace-well-formedness constraint or
@@ -94,10 +104,50 @@ pub enum XmlError {
 
 	/// Declared namespace URI is empty
 	EmptyNamespaceUri,
+
+	/// The value of an `xml:id` attribute is not a valid NCName.
+	///
+	/// Only emitted if `xml:id` checking is enabled (see
+	/// [`NamespaceResolver::set_check_xml_ids`](crate::NamespaceResolver::set_check_xml_ids)).
+	InvalidXmlId,
+
+	/// The value of an `xml:id` attribute has already been used in the
+	/// document.
+	///
+	/// Only emitted if `xml:id` checking is enabled (see
+	/// [`NamespaceResolver::set_check_xml_ids`](crate::NamespaceResolver::set_check_xml_ids)).
+	DuplicateXmlId,
 }
 
+#[cfg(feature = "std")]
 impl error::Error for XmlError {}
 
+impl XmlError {
+	/// Return the stable code of this error.
+	pub fn code(&self) -> ErrorCode {
+		match self {
+			Self::InvalidEof(..) => ErrorCode::InvalidEof,
+			Self::UndeclaredEntity => ErrorCode::UndeclaredEntity,
+			Self::InvalidChar(..) => ErrorCode::InvalidChar,
+			Self::UnexpectedChar(..) => ErrorCode::UnexpectedChar,
+			Self::UnexpectedByte(..) => ErrorCode::UnexpectedByte,
+			Self::InvalidSyntax(..) => ErrorCode::InvalidSyntax,
+			Self::UnexpectedToken(..) => ErrorCode::UnexpectedToken,
+			Self::DuplicateAttribute => ErrorCode::DuplicateAttribute,
+			Self::ElementMismatch => ErrorCode::ElementMismatch,
+			Self::MultiColonName(..) => ErrorCode::MultiColonName,
+			Self::EmptyNamePart(..) => ErrorCode::EmptyNamePart,
+			Self::UndeclaredNamespacePrefix(..) => ErrorCode::UndeclaredNamespacePrefix,
+			Self::ReservedNamespacePrefix => ErrorCode::ReservedNamespacePrefix,
+			Self::ReservedNamespaceName => ErrorCode::ReservedNamespaceName,
+			Self::InvalidLocalName(..) => ErrorCode::InvalidLocalName,
+			Self::EmptyNamespaceUri => ErrorCode::EmptyNamespaceUri,
+			Self::InvalidXmlId => ErrorCode::InvalidXmlId,
+			Self::DuplicateXmlId => ErrorCode::DuplicateXmlId,
+		}
+	}
+}
+
 impl ErrorWithContext for XmlError {
 	fn with_context(self, ctx: &'static str) -> Self {
 		match self {
@@ -194,6 +244,8 @@ impl fmt::Display for XmlError {
 			Self::ReservedNamespaceName => f.write_str("reserved namespace URI"),
 			Self::InvalidLocalName(ctx) => write!(f, "local name is invalid {} name", ctx),
 			Self::EmptyNamespaceUri => write!(f, "namespace URI is empty"),
+			Self::InvalidXmlId => f.write_str("xml:id value is not a valid NCName"),
+			Self::DuplicateXmlId => f.write_str("duplicate xml:id value"),
 		}
 	}
 }
@@ -207,40 +259,213 @@ impl From<ValidationError> for XmlError {
 	}
 }
 
+/**
+# Namespace-well-formedness violation with details
+
+Wraps the [`XmlError`] describing a violated namespace constraint together
+with the prefix and the name involved and the location in the document, so
+that the problem can be explained to whoever wrote the document.
+
+```
+use rxml::{Error, FeedParser};
+
+let mut fp = FeedParser::default();
+let doc = b"<root xmlns='urn:x'><msg><ex:body>hi</ex:body></msg></root>";
+match fp.parse_all(&mut &doc[..], true, |_| ()) {
+	Err(Error::Namespace(e)) => {
+		assert_eq!(e.prefix().unwrap(), "ex");
+		assert_eq!(e.name().unwrap(), "ex:body");
+		assert!(!e.is_attribute());
+		assert_eq!(e.position(), Some(25));
+		assert_eq!(
+			e.to_string(),
+			"undeclared namespace prefix: prefix `ex` of element `ex:body` at byte 25",
+		);
+	}
+	other => panic!("unexpected result: {:?}", other),
+}
+```
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamespaceError {
+	error: XmlError,
+	prefix: Option<NcName>,
+	name: Option<Name>,
+	attribute: bool,
+	position: Option<u64>,
+}
+
+impl NamespaceError {
+	pub(crate) fn new(error: XmlError) -> Self {
+		Self {
+			error,
+			prefix: None,
+			name: None,
+			attribute: false,
+			position: None,
+		}
+	}
+
+	pub(crate) fn with_prefix(mut self, prefix: NcName) -> Self {
+		self.prefix = Some(prefix);
+		self
+	}
+
+	pub(crate) fn with_element(mut self, name: Name) -> Self {
+		self.name = Some(name);
+		self.attribute = false;
+		self
+	}
+
+	pub(crate) fn with_attribute(mut self, name: Name) -> Self {
+		self.name = Some(name);
+		self.attribute = true;
+		self
+	}
+
+	/// The violated constraint.
+	///
+	/// This is one of [`XmlError::UndeclaredNamespacePrefix`],
+	/// [`XmlError::ReservedNamespacePrefix`],
+	/// [`XmlError::ReservedNamespaceName`] and
+	/// [`XmlError::EmptyNamespaceUri`].
+	pub fn error(&self) -> XmlError {
+		self.error
+	}
+
+	/// Return the stable code of the violated constraint.
+	pub fn code(&self) -> ErrorCode {
+		self.error.code()
+	}
+
+	/// The offending prefix.
+	///
+	/// For undeclared prefixes, this is the prefix used in the name. For
+	/// invalid declarations, this is the declared prefix (`None` for
+	/// declarations of the default namespace).
+	pub fn prefix(&self) -> Option<&NcNameStr> {
+		self.prefix.as_deref()
+	}
+
+	/// The name of the element or attribute at which the violation
+	/// occurred, as written in the document (including its prefix).
+	///
+	/// For invalid namespace declarations, this is the name of the `xmlns`
+	/// attribute. This is `None` for errors which do not stem from a
+	/// document, e.g. from
+	/// [`NamespaceResolver::declare_prefix`](crate::NamespaceResolver::declare_prefix).
+	pub fn name(&self) -> Option<&NameStr> {
+		self.name.as_deref()
+	}
+
+	/// Return true if [`name`](Self::name) refers to an attribute and false
+	/// if it refers to an element.
+	pub fn is_attribute(&self) -> bool {
+		self.attribute
+	}
+
+	/// Byte offset, relative to the start of the document, at which the
+	/// element containing the violation starts.
+	///
+	/// Only the [`Parser`](crate::Parser) (and the frontends built on it)
+	/// keep track of positions; errors from lower-level components return
+	/// `None`.
+	pub fn position(&self) -> Option<u64> {
+		self.position
+	}
+}
+
+impl fmt::Display for NamespaceError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.error {
+			// the context is conveyed by the details below
+			XmlError::UndeclaredNamespacePrefix(_) => f.write_str("undeclared namespace prefix")?,
+			other => fmt::Display::fmt(&other, f)?,
+		}
+		let kind = if self.attribute {
+			"attribute"
+		} else {
+			"element"
+		};
+		match (self.prefix.as_ref(), self.name.as_ref()) {
+			(Some(prefix), Some(name)) => {
+				write!(f, ": prefix `{}` of {} `{}`", prefix, kind, name)?
+			}
+			(Some(prefix), None) => write!(f, ": prefix `{}`", prefix)?,
+			(None, Some(name)) => write!(f, ": {} `{}`", kind, name)?,
+			(None, None) => (),
+		}
+		if let Some(position) = self.position {
+			write!(f, " at byte {}", position)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(feature = "std")]
+impl error::Error for NamespaceError {
+	fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+		Some(&self.error)
+	}
+}
+
+#[cfg(feature = "std")]
+pub use std::io::ErrorKind as IoErrorKind;
+
+/// Kind of an I/O error.
+///
+/// Without the `std` feature, this replaces [`std::io::ErrorKind`]. The
+/// parser does not perform I/O itself in that case, so the only kind which
+/// occurs is running out of buffered data.
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum IoErrorKind {
+	/// The operation needs more data than is currently available.
+	WouldBlock,
+}
+
 /// [`std::sync::Arc`]-based around [`std::io::Error`] to allow cloning.
+#[cfg(feature = "std")]
 #[derive(Clone)]
 pub struct IOErrorWrapper(Arc<io::Error>);
 
+#[cfg(feature = "std")]
 impl IOErrorWrapper {
 	fn wrap(e: io::Error) -> IOErrorWrapper {
 		IOErrorWrapper(Arc::new(e))
 	}
 }
 
+#[cfg(feature = "std")]
 impl fmt::Debug for IOErrorWrapper {
 	fn fmt<'f>(&self, f: &'f mut fmt::Formatter) -> fmt::Result {
 		fmt::Debug::fmt(&**self, f)
 	}
 }
 
+#[cfg(feature = "std")]
 impl fmt::Display for IOErrorWrapper {
 	fn fmt<'f>(&self, f: &'f mut fmt::Formatter) -> fmt::Result {
 		fmt::Display::fmt(&**self, f)
 	}
 }
 
+#[cfg(feature = "std")]
 impl PartialEq for IOErrorWrapper {
 	fn eq(&self, other: &Self) -> bool {
 		Arc::ptr_eq(&self.0, &other.0)
 	}
 }
 
+#[cfg(feature = "std")]
 impl AsRef<io::Error> for IOErrorWrapper {
 	fn as_ref(&self) -> &io::Error {
 		&*self.0
 	}
 }
 
+#[cfg(feature = "std")]
 impl Deref for IOErrorWrapper {
 	type Target = io::Error;
 
@@ -249,12 +474,40 @@ impl Deref for IOErrorWrapper {
 	}
 }
 
+#[cfg(feature = "std")]
 impl std::borrow::Borrow<io::Error> for IOErrorWrapper {
 	fn borrow(&self) -> &io::Error {
 		&*self.0
 	}
 }
 
+/// Minimal I/O error for builds without the `std` feature.
+///
+/// It carries an [`IoErrorKind`] and a static message, and offers the same
+/// `kind()` accessor as [`std::io::Error`] so that code inspecting
+/// [`Error::IO`] works the same with and without `std`.
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IOErrorWrapper {
+	kind: IoErrorKind,
+	msg: &'static str,
+}
+
+#[cfg(not(feature = "std"))]
+impl IOErrorWrapper {
+	/// Kind of the error.
+	pub fn kind(&self) -> IoErrorKind {
+		self.kind
+	}
+}
+
+#[cfg(not(feature = "std"))]
+impl fmt::Display for IOErrorWrapper {
+	fn fmt<'f>(&self, f: &'f mut fmt::Formatter) -> fmt::Result {
+		f.write_str(self.msg)
+	}
+}
+
 /// Error types which may be returned from the parser or lexer.
 ///
 /// With the exception of [`Error::IO`], all errors are fatal and will be returned indefinitely from the parser or lexer after the first encounter.
@@ -271,15 +524,36 @@ pub enum Error {
 	InvalidUtf8Byte(u8),
 	/// An invalid Unicode scalar value was encountered during decoding.
 	InvalidChar(u32),
-	/// A violation of the XML 1.0 grammar or a well-formedness or
-	/// namespace-well-formedness constraint was encountered during parsing or
-	/// lexing.
+	/// A violation of the XML 1.0 grammar or a well-formedness constraint
+	/// was encountered during parsing or lexing.
 	Xml(XmlError),
+	/// A violation of a namespace-well-formedness constraint was
+	/// encountered during parsing.
+	///
+	/// Before 0.9.0, these were reported as [`Error::Xml`].
+	///
+	/// This is boxed to keep the size of [`Error`] small.
+	Namespace(Box<NamespaceError>),
 	/// A forbidden construct was encountered during lexing or parsing.
 	///
 	/// The string indicates the context and should not be interpreted by user
 	/// code.
 	RestrictedXml(&'static str),
+	/// The memory used by the parser exceeded the configured budget.
+	///
+	/// The value is the budget in bytes. See
+	/// [`Parser::set_memory_budget`](crate::Parser::set_memory_budget).
+	MemoryBudgetExceeded(usize),
+	/// The number of namespace declarations on an element or in the
+	/// document exceeded the configured limit.
+	///
+	/// The value is the limit which has been exceeded. See
+	/// [`Parser::set_max_namespace_declarations_per_element`] and
+	/// [`Parser::set_max_namespace_declarations`].
+	///
+	///   [`Parser::set_max_namespace_declarations_per_element`]: crate::Parser::set_max_namespace_declarations_per_element
+	///   [`Parser::set_max_namespace_declarations`]: crate::Parser::set_max_namespace_declarations
+	NamespaceDeclarationLimitExceeded(usize),
 }
 
 pub type Result<T> = StdResult<T, Error>;
@@ -289,24 +563,123 @@ pub(crate) trait ErrorWithContext {
 }
 
 impl Error {
+	#[cfg(feature = "std")]
 	pub fn io(e: io::Error) -> Error {
 		Error::IO(IOErrorWrapper::wrap(e))
 	}
 
+	/// Signal that more data is needed to continue.
+	#[cfg(feature = "std")]
+	pub(crate) fn would_block(msg: &'static str) -> Error {
+		Error::io(io::Error::new(io::ErrorKind::WouldBlock, msg))
+	}
+
+	/// Signal that more data is needed to continue.
+	#[cfg(not(feature = "std"))]
+	pub(crate) fn would_block(msg: &'static str) -> Error {
+		Error::IO(IOErrorWrapper {
+			kind: IoErrorKind::WouldBlock,
+			msg,
+		})
+	}
+
 	pub(crate) fn wfeof(ctx: &'static str) -> Error {
 		Self::Xml(XmlError::InvalidEof(ctx))
 	}
+
+	/// Record the position of a namespace error, unless it already has one.
+	pub(crate) fn with_position(self, position: u64) -> Error {
+		match self {
+			Self::Namespace(mut e) => {
+				e.position.get_or_insert(position);
+				Self::Namespace(e)
+			}
+			other => other,
+		}
+	}
+
+	/// Return true if this is an I/O error.
+	///
+	/// I/O errors are not fatal; the operation may be retried.
+	pub fn is_io(&self) -> bool {
+		matches!(self, Self::IO(_))
+	}
+
+	/// Return true if this is an I/O error of kind
+	/// [`IoErrorKind::WouldBlock`].
+	///
+	/// For the non-blocking frontends, this means that more data is needed
+	/// before parsing can continue.
+	pub fn is_would_block(&self) -> bool {
+		match self {
+			Self::IO(e) => e.kind() == IoErrorKind::WouldBlock,
+			_ => false,
+		}
+	}
+
+	/// Return true if the input is not well-formed XML.
+	///
+	/// This covers violations of the XML 1.0 grammar, of well-formedness and
+	/// namespace-well-formedness constraints and invalid encoding (invalid
+	/// UTF-8 or codepoints).
+	pub fn is_wellformedness(&self) -> bool {
+		matches!(
+			self,
+			Self::Xml(_) | Self::Namespace(_) | Self::InvalidUtf8Byte(_) | Self::InvalidChar(_)
+		)
+	}
+
+	/// Return true if the input is well-formed, but uses a construct which is
+	/// not supported by this crate (see [`Error::RestrictedXml`]) or exceeds
+	/// a configured limit.
+	pub fn is_restriction(&self) -> bool {
+		matches!(
+			self,
+			Self::RestrictedXml(_)
+				| Self::MemoryBudgetExceeded(_)
+				| Self::NamespaceDeclarationLimitExceeded(_)
+		)
+	}
+
+	/// Return true if the error is fatal, i.e. if the parser or lexer will
+	/// keep returning it.
+	///
+	/// This is the case for all errors except I/O errors.
+	pub fn is_fatal(&self) -> bool {
+		!self.is_io()
+	}
+
+	/// Return the stable code of this error.
+	pub fn code(&self) -> ErrorCode {
+		match self {
+			Self::IO(_) => ErrorCode::Io,
+			Self::InvalidUtf8Byte(_) => ErrorCode::InvalidUtf8Byte,
+			Self::InvalidChar(_) => ErrorCode::InvalidCodepoint,
+			Self::Xml(e) => e.code(),
+			Self::Namespace(e) => e.code(),
+			Self::RestrictedXml(_) => ErrorCode::RestrictedXml,
+			Self::MemoryBudgetExceeded(_) => ErrorCode::MemoryBudgetExceeded,
+			Self::NamespaceDeclarationLimitExceeded(_) => {
+				ErrorCode::NamespaceDeclarationLimitExceeded
+			}
+		}
+	}
 }
 
 impl ErrorWithContext for Error {
 	fn with_context(self, ctx: &'static str) -> Self {
 		match self {
 			Self::Xml(xe) => Self::Xml(xe.with_context(ctx)),
+			Self::Namespace(mut e) => {
+				e.error = e.error.with_context(ctx);
+				Self::Namespace(e)
+			}
 			other => other,
 		}
 	}
 }
 
+#[cfg(feature = "std")]
 impl From<io::Error> for Error {
 	fn from(e: io::Error) -> Error {
 		Error::io(e)
@@ -319,11 +692,24 @@ impl From<XmlError> for Error {
 	}
 }
 
+impl From<NamespaceError> for Error {
+	fn from(e: NamespaceError) -> Self {
+		Self::Namespace(Box::new(e))
+	}
+}
+
 impl fmt::Display for Error {
 	fn fmt<'f>(&self, f: &'f mut fmt::Formatter) -> fmt::Result {
 		match self {
 			Self::Xml(e) => write!(f, "xml error: {}", e),
+			Self::Namespace(e) => write!(f, "xml error: {}", e),
 			Self::RestrictedXml(msg) => write!(f, "restricted xml: {}", msg),
+			Self::MemoryBudgetExceeded(budget) => {
+				write!(f, "memory budget of {} bytes exceeded", budget)
+			}
+			Self::NamespaceDeclarationLimitExceeded(limit) => {
+				write!(f, "limit of {} namespace declarations exceeded", limit)
+			}
 			Self::InvalidUtf8Byte(b) => write!(f, "invalid utf-8 byte: \\x{:02x}", b),
 			Self::InvalidChar(ch) => write!(f, "invalid char: U+{:08x}", ch),
 			Self::IO(e) => write!(f, "I/O error: {}", e),
@@ -331,14 +717,461 @@ impl fmt::Display for Error {
 	}
 }
 
+#[cfg(feature = "std")]
 impl error::Error for Error {
 	fn source(&self) -> Option<&(dyn error::Error + 'static)> {
 		match self {
 			Self::IO(e) => Some(&**e),
 			Self::Xml(e) => Some(e),
-			Self::RestrictedXml(_) | Self::InvalidUtf8Byte(_) | Self::InvalidChar(_) => None,
+			Self::Namespace(e) => Some(&**e),
+			Self::RestrictedXml(_)
+			| Self::MemoryBudgetExceeded(_)
+			| Self::NamespaceDeclarationLimitExceeded(_)
+			| Self::InvalidUtf8Byte(_)
+			| Self::InvalidChar(_) => None,
+		}
+	}
+}
+
+macro_rules! error_codes {
+	($(#[$enum_meta:meta])* $enum_name:ident { $($(#[$meta:meta])* $variant:ident = $value:literal => $name:literal,)* }) => {
+		$(#[$enum_meta])*
+		#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+		#[non_exhaustive]
+		#[repr(u32)]
+		pub enum $enum_name {
+			$(
+				$(#[$meta])*
+				$variant = $value,
+			)*
+		}
+
+		impl $enum_name {
+			/// Numeric value of the code.
+			pub fn as_u32(self) -> u32 {
+				self as u32
+			}
+
+			/// Look up a code by its numeric value.
+			pub fn from_u32(v: u32) -> Option<Self> {
+				match v {
+					$($value => Some(Self::$variant),)*
+					_ => None,
+				}
+			}
+
+			/// Stable, machine-readable name of the code.
+			pub fn name(self) -> &'static str {
+				match self {
+					$(Self::$variant => $name,)*
+				}
+			}
+
+			#[cfg(all(test, feature = "std"))]
+			pub(crate) const ALL: &'static [$enum_name] = &[$(Self::$variant,)*];
+		}
+	};
+}
+
+error_codes! {
+/**
+# Stable error codes
+
+Each error returned by this crate maps to exactly one [`ErrorCode`]
+(see [`Error::code()`],
+[`EncodeError::code()`](crate::writer::EncodeError::code)). Unlike the
+error enums, the codes carry no payload and both their numeric values
+and their names are stable across releases: codes are never reused or
+renumbered, so they can be stored or mapped to protocol-level error
+conditions.
+
+New codes may be added in minor releases, which is why this enum is
+non-exhaustive.
+
+Codes below 100 are for errors not related to the XML grammar (I/O,
+encoding, restrictions), codes 100 to 199 correspond to
+[`XmlError`] and codes from 200 correspond to
+[`EncodeError`](crate::writer::EncodeError).
+
+```
+use rxml::{FeedParser, error::ErrorCode};
+
+let mut fp = FeedParser::default();
+let err = fp.parse_all(&mut &b"<a></b>"[..], true, |_| ()).unwrap_err();
+assert_eq!(err.code(), ErrorCode::ElementMismatch);
+assert_eq!(err.code().as_u32(), 108);
+assert_eq!(err.code().name(), "element_mismatch");
+assert_eq!(ErrorCode::from_u32(108), Some(ErrorCode::ElementMismatch));
+```
+*/
+ErrorCode {
+	/// [`Error::IO`]
+	Io = 1 => "io",
+	/// [`Error::InvalidUtf8Byte`]
+	InvalidUtf8Byte = 2 => "invalid_utf8_byte",
+	/// [`Error::InvalidChar`]
+	InvalidCodepoint = 3 => "invalid_codepoint",
+	/// [`Error::RestrictedXml`]
+	RestrictedXml = 4 => "restricted_xml",
+	/// [`Error::MemoryBudgetExceeded`]
+	MemoryBudgetExceeded = 5 => "memory_budget_exceeded",
+	/// [`Error::NamespaceDeclarationLimitExceeded`]
+	NamespaceDeclarationLimitExceeded = 6 => "namespace_declaration_limit_exceeded",
+	/// [`XmlError::InvalidEof`]
+	InvalidEof = 100 => "invalid_eof",
+	/// [`XmlError::UndeclaredEntity`]
+	UndeclaredEntity = 101 => "undeclared_entity",
+	/// [`XmlError::InvalidChar`]
+	InvalidChar = 102 => "invalid_char",
+	/// [`XmlError::UnexpectedChar`]
+	UnexpectedChar = 103 => "unexpected_char",
+	/// [`XmlError::UnexpectedByte`]
+	UnexpectedByte = 104 => "unexpected_byte",
+	/// [`XmlError::InvalidSyntax`]
+	InvalidSyntax = 105 => "invalid_syntax",
+	/// [`XmlError::UnexpectedToken`]
+	UnexpectedToken = 106 => "unexpected_token",
+	/// [`XmlError::DuplicateAttribute`]
+	DuplicateAttribute = 107 => "duplicate_attribute",
+	/// [`XmlError::ElementMismatch`]
+	ElementMismatch = 108 => "element_mismatch",
+	/// [`XmlError::MultiColonName`]
+	MultiColonName = 109 => "multi_colon_name",
+	/// [`XmlError::EmptyNamePart`]
+	EmptyNamePart = 110 => "empty_name_part",
+	/// [`XmlError::UndeclaredNamespacePrefix`]
+	UndeclaredNamespacePrefix = 111 => "undeclared_namespace_prefix",
+	/// [`XmlError::ReservedNamespacePrefix`]
+	ReservedNamespacePrefix = 112 => "reserved_namespace_prefix",
+	/// [`XmlError::ReservedNamespaceName`]
+	ReservedNamespaceName = 113 => "reserved_namespace_name",
+	/// [`XmlError::InvalidLocalName`]
+	InvalidLocalName = 114 => "invalid_local_name",
+	/// [`XmlError::EmptyNamespaceUri`]
+	EmptyNamespaceUri = 115 => "empty_namespace_uri",
+	/// [`XmlError::InvalidXmlId`]
+	InvalidXmlId = 116 => "invalid_xml_id",
+	/// [`XmlError::DuplicateXmlId`]
+	DuplicateXmlId = 117 => "duplicate_xml_id",
+	/// [`EncodeError::MisplacedXmlDeclaration`](crate::writer::EncodeError::MisplacedXmlDeclaration)
+	MisplacedXmlDeclaration = 200 => "misplaced_xml_declaration",
+	/// [`EncodeError::EndOfDocument`](crate::writer::EncodeError::EndOfDocument)
+	EndOfDocument = 201 => "end_of_document",
+	/// [`EncodeError::TextNotAllowed`](crate::writer::EncodeError::TextNotAllowed)
+	TextNotAllowed = 202 => "text_not_allowed",
+	/// [`EncodeError::AttributeNotAllowed`](crate::writer::EncodeError::AttributeNotAllowed)
+	AttributeNotAllowed = 203 => "attribute_not_allowed",
+	/// [`EncodeError::ElementStartNotAllowed`](crate::writer::EncodeError::ElementStartNotAllowed)
+	ElementStartNotAllowed = 204 => "element_start_not_allowed",
+	/// [`EncodeError::ElementFootNotAllowed`](crate::writer::EncodeError::ElementFootNotAllowed)
+	ElementFootNotAllowed = 205 => "element_foot_not_allowed",
+	/// [`EncodeError::NoOpenElement`](crate::writer::EncodeError::NoOpenElement)
+	NoOpenElement = 206 => "no_open_element",
+	/// [`EncodeError::CommentsAndPIsDisabled`](crate::writer::EncodeError::CommentsAndPIsDisabled)
+	CommentsAndPIsDisabled = 207 => "comments_and_pis_disabled",
+	/// [`EncodeError::CommentOrPINotAllowed`](crate::writer::EncodeError::CommentOrPINotAllowed)
+	CommentOrPINotAllowed = 208 => "comment_or_pi_not_allowed",
+	/// [`EncodeError::InvalidComment`](crate::writer::EncodeError::InvalidComment)
+	InvalidComment = 209 => "invalid_comment",
+	/// [`EncodeError::InvalidProcessingInstruction`](crate::writer::EncodeError::InvalidProcessingInstruction)
+	InvalidProcessingInstruction = 210 => "invalid_processing_instruction",
+	/// [`EncodeError::MisplacedNamespaceDeclaration`](crate::writer::EncodeError::MisplacedNamespaceDeclaration)
+	MisplacedNamespaceDeclaration = 211 => "misplaced_namespace_declaration",
+	/// [`EncodeError::InvalidNamespaceDeclaration`](crate::writer::EncodeError::InvalidNamespaceDeclaration)
+	InvalidNamespaceDeclaration = 212 => "invalid_namespace_declaration",
+}
+}
+
+impl fmt::Display for ErrorCode {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+/// Number of bytes of input to include in an excerpt on either side of the
+/// error location.
+const EXCERPT_RADIUS: usize = 32;
+
+/**
+# Error with location and input excerpt
+
+This wraps an [`Error`] together with the byte offset at which it was
+detected and a short excerpt of the input around that offset. It is meant
+for presenting errors to humans: the [`Display`](fmt::Display)
+implementation renders the error message, the offset and the excerpt with
+a marker pointing at the offending location.
+
+The parsers themselves do not keep the input around. The offset can be
+obtained from [`Lexer::position()`](crate::Lexer::position) and the
+excerpt is taken from the input passed to [`LocatedError::new()`].
+
+```
+use rxml::{FeedParser, error::LocatedError};
+
+let doc = b"<?xml version='1.0'?>\n<root><child></root>";
+let mut fp = FeedParser::default();
+let err = fp.parse_all(&mut &doc[..], true, |_| ()).unwrap_err();
+let err = LocatedError::new(err, &doc[..], fp.get_lexer().position());
+assert_eq!(err.offset(), 42);
+assert_eq!(err.excerpt(), "sion='1.0'?> <root><child></root>");
+// xml error: start and end tag do not match at byte 42
+//     sion='1.0'?> <root><child></root>
+//                                     ^
+println!("{}", err);
+```
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocatedError {
+	error: Error,
+	offset: usize,
+	excerpt: String,
+	marker: usize,
+}
+
+impl LocatedError {
+	/// Attach location information to an error.
+	///
+	/// `input` must be the complete input up to (and ideally somewhat beyond)
+	/// `offset`, starting at the first byte passed to the parser. `offset`
+	/// is the number of bytes the lexer had consumed when the error was
+	/// detected; the last of these bytes is marked in the excerpt.
+	///
+	/// Invalid UTF-8 in the excerpt is replaced by U+FFFD and control
+	/// characters (including line breaks) are replaced by spaces, so that
+	/// the excerpt fits on a single line.
+	pub fn new(error: Error, input: &[u8], offset: usize) -> Self {
+		let at = offset.saturating_sub(1).min(input.len());
+		let start = at.saturating_sub(EXCERPT_RADIUS);
+		let end = at.saturating_add(EXCERPT_RADIUS).min(input.len());
+		let before = String::from_utf8_lossy(&input[start..at]);
+		let after = String::from_utf8_lossy(&input[at..end]);
+		let marker = before.chars().count();
+		let excerpt = before
+			.chars()
+			.chain(after.chars())
+			.map(|ch| if ch.is_control() { ' ' } else { ch })
+			.collect();
+		Self {
+			error,
+			offset,
+			excerpt,
+			marker,
 		}
 	}
+
+	/// The wrapped error.
+	pub fn error(&self) -> &Error {
+		&self.error
+	}
+
+	/// Return the stable code of the wrapped error.
+	pub fn code(&self) -> ErrorCode {
+		self.error.code()
+	}
+
+	/// Byte offset at which the error was detected.
+	pub fn offset(&self) -> usize {
+		self.offset
+	}
+
+	/// Excerpt of the input around the error location.
+	pub fn excerpt(&self) -> &str {
+		&self.excerpt
+	}
+
+	/// Discard the location information and return the wrapped error.
+	pub fn into_error(self) -> Error {
+		self.error
+	}
+}
+
+impl fmt::Display for LocatedError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} at byte {}", self.error, self.offset)?;
+		if !self.excerpt.is_empty() {
+			write!(
+				f,
+				"\n    {}\n    {:>width$}",
+				self.excerpt,
+				"^",
+				width = self.marker + 1
+			)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(feature = "std")]
+impl error::Error for LocatedError {
+	fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+		Some(&self.error)
+	}
+}
+
+impl From<LocatedError> for Error {
+	fn from(other: LocatedError) -> Self {
+		other.error
+	}
+}
+
+#[cfg(feature = "miette")]
+impl Error {
+	/// Label for the start of the element at which a namespace error was
+	/// detected, if its position is known.
+	fn element_label(&self) -> Option<miette::LabeledSpan> {
+		match self {
+			Self::Namespace(e) => {
+				let position = usize::try_from(e.position?).ok()?;
+				Some(miette::LabeledSpan::at_offset(position, "in this element"))
+			}
+			_ => None,
+		}
+	}
+}
+
+#[cfg(feature = "miette")]
+#[cfg_attr(docsrs, doc(cfg(feature = "miette")))]
+impl miette::Diagnostic for Error {
+	/// `rxml::` followed by the [name](ErrorCode::name) of the error code.
+	fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+		Some(Box::new(alloc::format!("rxml::{}", self.code())))
+	}
+
+	fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+		match self {
+			Self::RestrictedXml(_) => Some(Box::new(
+				"rxml does not support DTDs, custom entities, comments or processing instructions and enforces configurable limits",
+			)),
+			Self::MemoryBudgetExceeded(_) => Some(Box::new(
+				"the budget can be changed with Parser::set_memory_budget",
+			)),
+			Self::NamespaceDeclarationLimitExceeded(_) => Some(Box::new(
+				"the limits can be changed with Parser::set_max_namespace_declarations_per_element and Parser::set_max_namespace_declarations",
+			)),
+			_ => None,
+		}
+	}
+
+	/// For namespace errors whose position is known, this labels the start
+	/// of the offending element.
+	///
+	/// The source code has to be attached to the report, for instance with
+	/// [`miette::Report::with_source_code`].
+	fn labels(&self) -> Option<Box<dyn Iterator<Item = miette::LabeledSpan> + '_>> {
+		let label = self.element_label()?;
+		Some(Box::new(core::iter::once(label)))
+	}
+}
+
+/**
+# Error with source code, for reporting with miette
+
+This wraps an [`Error`] together with the complete input and the byte
+offset at which the error was detected. Unlike [`LocatedError`], it does not
+render the location as part of its [`Display`](fmt::Display) output, but
+implements [`miette::Diagnostic`] with a label pointing at the offset (and,
+for namespace errors, at the offending element), so that miette can render
+the input around it.
+
+```
+use rxml::{FeedParser, error::SourceError};
+use miette::Diagnostic;
+
+let doc = b"<?xml version='1.0'?>\n<root><child></root>";
+let mut fp = FeedParser::default();
+let err = fp.parse_all(&mut &doc[..], true, |_| ()).unwrap_err();
+let err = SourceError::new(err, &doc[..], fp.get_lexer().position());
+assert_eq!(err.code().unwrap().to_string(), "rxml::element_mismatch");
+assert_eq!(err.labels().unwrap().next().unwrap().offset(), 41);
+// print with the graphical report handler of miette
+println!("{:?}", miette::Report::new(err));
+```
+*/
+#[cfg(feature = "miette")]
+#[cfg_attr(docsrs, doc(cfg(feature = "miette")))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceError {
+	error: Error,
+	source: alloc::vec::Vec<u8>,
+	offset: usize,
+}
+
+#[cfg(feature = "miette")]
+impl SourceError {
+	/// Attach the input to an error.
+	///
+	/// `input` must be the input passed to the parser, starting at its
+	/// first byte. `offset` is the number of bytes the lexer had consumed
+	/// when the error was detected, as for [`LocatedError::new()`].
+	pub fn new(error: Error, input: &[u8], offset: usize) -> Self {
+		Self {
+			error,
+			source: input.to_vec(),
+			offset,
+		}
+	}
+
+	/// The wrapped error.
+	pub fn error(&self) -> &Error {
+		&self.error
+	}
+
+	/// Byte offset at which the error was detected.
+	pub fn offset(&self) -> usize {
+		self.offset
+	}
+
+	/// Discard the source code and return the wrapped error.
+	pub fn into_error(self) -> Error {
+		self.error
+	}
+}
+
+#[cfg(feature = "miette")]
+impl fmt::Display for SourceError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Display::fmt(&self.error, f)
+	}
+}
+
+#[cfg(feature = "miette")]
+impl error::Error for SourceError {
+	fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+		self.error.source()
+	}
+}
+
+#[cfg(feature = "miette")]
+impl miette::Diagnostic for SourceError {
+	fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+		miette::Diagnostic::code(&self.error)
+	}
+
+	fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+		self.error.help()
+	}
+
+	fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+		Some(&self.source)
+	}
+
+	/// Labels the last byte consumed by the lexer and, for namespace
+	/// errors, the start of the offending element.
+	fn labels(&self) -> Option<Box<dyn Iterator<Item = miette::LabeledSpan> + '_>> {
+		let at = self.offset.saturating_sub(1).min(self.source.len());
+		let len = if at < self.source.len() { 1 } else { 0 };
+		let here = miette::LabeledSpan::new_with_span(Some("here".into()), (at, len));
+		Some(Box::new(
+			core::iter::once(here).chain(self.error.element_label()),
+		))
+	}
+}
+
+#[cfg(feature = "miette")]
+impl From<SourceError> for Error {
+	fn from(other: SourceError) -> Self {
+		other.error
+	}
 }
 
 pub(crate) fn add_context<T, E: ErrorWithContext>(
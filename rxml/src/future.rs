@@ -1,20 +1,410 @@
 use std::future::Future;
 use std::io;
+use std::ops::DerefMut;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
+#[cfg(feature = "async")]
 use tokio::io::AsyncBufRead;
+#[cfg(feature = "async")]
+use tokio::sync::mpsc;
 
 #[cfg(feature = "stream")]
-use futures_core::stream::Stream;
+use futures_core::stream::{FusedStream, Stream};
 
 use crate::driver::PushDriver;
 use crate::lexer::{Lexer, LexerOptions};
 use crate::parser::{Parse, Parser};
+use crate::profile::Profile;
 use crate::{Error, Result};
 
 use pin_project_lite::pin_project;
 
+mod private {
+	use super::*;
+
+	/// Sealed abstraction over the buffered asynchronous reader traits of
+	/// tokio and futures-io.
+	pub trait AsyncBufSource {
+		fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>>;
+
+		fn consume(self: Pin<&mut Self>, amt: usize);
+
+		/// Return true if the buffers returned by `poll_fill_buf` are
+		/// always the unread part of a `str`.
+		fn chunk_is_utf8(&self) -> bool {
+			false
+		}
+	}
+
+	#[cfg(feature = "async")]
+	impl<T: AsyncBufRead + ?Sized> AsyncBufSource for T {
+		fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+			AsyncBufRead::poll_fill_buf(self, cx)
+		}
+
+		fn consume(self: Pin<&mut Self>, amt: usize) {
+			AsyncBufRead::consume(self, amt)
+		}
+	}
+
+	#[cfg(feature = "futures")]
+	impl<T: futures_io::AsyncBufRead> AsyncBufSource for FuturesIo<T> {
+		fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+			self.project().inner.poll_fill_buf(cx)
+		}
+
+		fn consume(self: Pin<&mut Self>, amt: usize) {
+			self.project().inner.consume(amt)
+		}
+	}
+
+	#[cfg(feature = "async")]
+	impl<B: AsRef<[u8]>> AsyncBufSource for ChannelSource<B> {
+		fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+			let this = self.get_mut();
+			loop {
+				if let Some(chunk) = this.chunk.as_ref() {
+					if this.offset < chunk.as_ref().len() {
+						break;
+					}
+				}
+				this.chunk = None;
+				this.offset = 0;
+				if this.done {
+					return Poll::Ready(Ok(&[]));
+				}
+				match this.rx.poll_recv(cx) {
+					Poll::Pending => return Poll::Pending,
+					Poll::Ready(Some(chunk)) => this.chunk = Some(chunk),
+					Poll::Ready(None) => this.done = true,
+				}
+			}
+			let chunk = this.chunk.as_ref().unwrap().as_ref();
+			Poll::Ready(Ok(&chunk[this.offset..]))
+		}
+
+		fn consume(self: Pin<&mut Self>, amt: usize) {
+			self.get_mut().offset += amt;
+		}
+	}
+
+	#[cfg(feature = "stream")]
+	impl<S, B, E> AsyncBufSource for ByteStream<S, B>
+	where
+		S: Stream<Item = std::result::Result<B, E>>,
+		B: bytes::Buf,
+		E: Into<io::Error>,
+	{
+		fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+			let mut this = self.project();
+			loop {
+				if this
+					.chunk
+					.as_ref()
+					.map(|x| x.has_remaining())
+					.unwrap_or(false)
+				{
+					break;
+				}
+				*this.chunk = None;
+				if *this.done {
+					return Poll::Ready(Ok(&[]));
+				}
+				match this.inner.as_mut().poll_next(cx) {
+					Poll::Pending => return Poll::Pending,
+					Poll::Ready(Some(Ok(chunk))) => *this.chunk = Some(chunk),
+					Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e.into())),
+					Poll::Ready(None) => *this.done = true,
+				}
+			}
+			let chunk = this.chunk;
+			Poll::Ready(Ok(chunk.as_ref().unwrap().chunk()))
+		}
+
+		fn consume(self: Pin<&mut Self>, amt: usize) {
+			if let Some(chunk) = self.project().chunk.as_mut() {
+				chunk.advance(amt);
+			}
+		}
+	}
+
+	#[cfg(feature = "stream")]
+	impl<S, B, E> AsyncBufSource for TextStream<S, B>
+	where
+		S: Stream<Item = std::result::Result<B, E>>,
+		B: AsRef<str>,
+		E: Into<io::Error>,
+	{
+		fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+			let mut this = self.project();
+			loop {
+				if let Some(chunk) = this.chunk.as_ref() {
+					if *this.offset < chunk.as_ref().len() {
+						break;
+					}
+				}
+				*this.chunk = None;
+				*this.offset = 0;
+				if *this.done {
+					return Poll::Ready(Ok(&[]));
+				}
+				match this.inner.as_mut().poll_next(cx) {
+					Poll::Pending => return Poll::Pending,
+					Poll::Ready(Some(Ok(chunk))) => *this.chunk = Some(chunk),
+					Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e.into())),
+					Poll::Ready(None) => *this.done = true,
+				}
+			}
+			let chunk = this.chunk.as_ref().unwrap().as_ref().as_bytes();
+			Poll::Ready(Ok(&chunk[*this.offset..]))
+		}
+
+		fn consume(self: Pin<&mut Self>, amt: usize) {
+			*self.project().offset += amt;
+		}
+
+		fn chunk_is_utf8(&self) -> bool {
+			true
+		}
+	}
+}
+
+use private::AsyncBufSource;
+
+#[cfg(feature = "stream")]
+pin_project! {
+	/**
+	# Adapter for streams of byte chunks
+
+	Wraps a `Stream` of byte chunks so that it can be used as source for an
+	[`AsyncDriver`] (and thus the [`AsyncParser`]). This is how data arrives
+	from e.g. HTTP bodies or WebSocket messages.
+
+	The stream must yield `Result<B, E>`, where `B` implements
+	[`bytes::Buf`] (e.g. [`bytes::Bytes`]) and `E` converts into an
+	[`std::io::Error`]. Errors from the stream are passed on as
+	[`Error::IO`]; the end of the stream is treated as the end of file.
+	Infallible streams can be adapted by mapping their items with `Ok`.
+
+	Usually, this is constructed implicitly via
+	[`AsyncDriver::from_stream`].
+	*/
+	#[cfg_attr(docsrs, doc(cfg(feature = "stream")))]
+	#[derive(Debug)]
+	pub struct ByteStream<S, B> {
+		#[pin]
+		inner: S,
+		chunk: Option<B>,
+		done: bool,
+	}
+}
+
+#[cfg(feature = "stream")]
+impl<S, B> ByteStream<S, B> {
+	/// Wrap a stream of byte chunks.
+	pub fn new(inner: S) -> Self {
+		Self {
+			inner,
+			chunk: None,
+			done: false,
+		}
+	}
+
+	/// Access the wrapped stream.
+	pub fn get_ref(&self) -> &S {
+		&self.inner
+	}
+
+	/// Access the wrapped stream, mutably.
+	pub fn get_mut(&mut self) -> &mut S {
+		&mut self.inner
+	}
+
+	/// Return the wrapped stream and the remainder of the current chunk,
+	/// if any.
+	pub fn into_inner(self) -> (S, Option<B>) {
+		(self.inner, self.chunk)
+	}
+}
+
+#[cfg(feature = "stream")]
+pin_project! {
+	/**
+	# Adapter for streams of text chunks
+
+	Wraps a `Stream` of text chunks so that it can be used as source for an
+	[`AsyncDriver`] (and thus the [`AsyncParser`]). This is the text
+	counterpart of [`ByteStream`], for sources which already deliver
+	strings, such as WebSocket text messages: as the chunks are known to be
+	valid UTF-8, the lexer does not validate them again.
+
+	The stream must yield `Result<B, E>`, where `B` implements
+	`AsRef<str>` (e.g. `String`) and `E` converts into an
+	[`std::io::Error`]. Errors and the end of the stream are handled like
+	with [`ByteStream`].
+
+	Usually, this is constructed implicitly via
+	[`AsyncDriver::from_text_stream`].
+	*/
+	#[cfg_attr(docsrs, doc(cfg(feature = "stream")))]
+	#[derive(Debug)]
+	pub struct TextStream<S, B> {
+		#[pin]
+		inner: S,
+		chunk: Option<B>,
+		// Number of bytes of `chunk` which have been consumed.
+		offset: usize,
+		done: bool,
+	}
+}
+
+#[cfg(feature = "stream")]
+impl<S, B> TextStream<S, B> {
+	/// Wrap a stream of text chunks.
+	pub fn new(inner: S) -> Self {
+		Self {
+			inner,
+			chunk: None,
+			offset: 0,
+			done: false,
+		}
+	}
+
+	/// Access the wrapped stream.
+	pub fn get_ref(&self) -> &S {
+		&self.inner
+	}
+
+	/// Access the wrapped stream, mutably.
+	pub fn get_mut(&mut self) -> &mut S {
+		&mut self.inner
+	}
+
+	/// Return the wrapped stream and the current chunk together with the
+	/// number of bytes of it which have already been consumed, if any.
+	pub fn into_inner(self) -> (S, Option<(B, usize)>) {
+		let offset = self.offset;
+		(self.inner, self.chunk.map(|chunk| (chunk, offset)))
+	}
+}
+
+/**
+# Adapter for channels of byte chunks
+
+Wraps the receiving half of a [`tokio::sync::mpsc`] channel of byte chunks
+so that it can be used as source for an [`AsyncDriver`] (and thus the
+[`AsyncParser`]). This is useful if the data is produced by another task,
+for instance a TLS layer which hands out decrypted chunks: the producer
+pushes chunks into the channel like it would feed a
+[`FeedParser`](crate::FeedParser), while the parser waits for them
+asynchronously.
+
+Chunks can be of any type which can be viewed as a byte slice, such as
+`Vec<u8>` or [`bytes::Bytes`]. Once all senders have been dropped and all
+chunks have been consumed, the end of file is signalled to the parser.
+
+Usually, this is constructed implicitly via [`AsyncDriver::from_channel`].
+*/
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+#[derive(Debug)]
+pub struct ChannelSource<B> {
+	rx: mpsc::Receiver<B>,
+	chunk: Option<B>,
+	/// Number of bytes of `chunk` which have been consumed.
+	offset: usize,
+	done: bool,
+}
+
+// The chunk is never pinned, so there is no reason to require B: Unpin.
+#[cfg(feature = "async")]
+impl<B> Unpin for ChannelSource<B> {}
+
+#[cfg(feature = "async")]
+impl<B> ChannelSource<B> {
+	/// Wrap the receiving half of a channel.
+	pub fn new(rx: mpsc::Receiver<B>) -> Self {
+		Self {
+			rx,
+			chunk: None,
+			offset: 0,
+			done: false,
+		}
+	}
+
+	/// Access the wrapped receiver.
+	pub fn get_ref(&self) -> &mpsc::Receiver<B> {
+		&self.rx
+	}
+
+	/// Access the wrapped receiver, mutably.
+	pub fn get_mut(&mut self) -> &mut mpsc::Receiver<B> {
+		&mut self.rx
+	}
+
+	/// Return the wrapped receiver and the current chunk together with the
+	/// number of bytes of it which have already been consumed, if any.
+	pub fn into_inner(self) -> (mpsc::Receiver<B>, Option<(B, usize)>) {
+		let offset = self.offset;
+		(self.rx, self.chunk.map(|chunk| (chunk, offset)))
+	}
+}
+
+#[cfg(feature = "futures")]
+pin_project! {
+	/**
+	# Adapter for futures-io readers
+
+	Wraps a [`futures_io::AsyncBufRead`] so that it can be used as source for
+	an [`AsyncDriver`] (and thus the [`AsyncParser`]). This allows using the
+	asynchronous parser with runtimes other than tokio, such as async-std or
+	smol, without depending on tokio.
+
+	## Example
+
+	```
+	use rxml::{AsyncParser, AsyncEventReadExt, FuturesIo, ResolvedEvent};
+	# tokio_test::block_on(async {
+	let doc = &b"<?xml version='1.0'?><hello>World!</hello>"[..];
+	// &[u8] implements futures_io::AsyncBufRead
+	let mut pp = AsyncParser::new(FuturesIo::new(doc));
+	let ev = pp.read().await;
+	assert!(matches!(ev.unwrap().unwrap(), ResolvedEvent::XmlDeclaration(..)));
+	# })
+	```
+	*/
+	#[cfg_attr(docsrs, doc(cfg(feature = "futures")))]
+	#[derive(Debug)]
+	pub struct FuturesIo<T> {
+		#[pin]
+		inner: T,
+	}
+}
+
+#[cfg(feature = "futures")]
+impl<T> FuturesIo<T> {
+	/// Wrap a futures-io reader.
+	pub fn new(inner: T) -> Self {
+		Self { inner }
+	}
+
+	/// Access the wrapped reader.
+	pub fn get_ref(&self) -> &T {
+		&self.inner
+	}
+
+	/// Access the wrapped reader, mutably.
+	pub fn get_mut(&mut self) -> &mut T {
+		&mut self.inner
+	}
+
+	/// Return the wrapped reader.
+	pub fn into_inner(self) -> T {
+		self.inner
+	}
+}
+
 pin_project! {
 	pub struct ReadEvent<T: ?Sized>{
 		#[pin]
@@ -83,21 +473,57 @@ impl<T: AsyncEventRead + Unpin + ?Sized> AsyncEventRead for &mut T {
 	}
 }
 
+impl<P> AsyncEventRead for Pin<P>
+where
+	P: DerefMut + Unpin,
+	P::Target: AsyncEventRead,
+{
+	type Output = <P::Target as AsyncEventRead>::Output;
+
+	fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<Option<Self::Output>>> {
+		self.get_mut().as_mut().poll_read(cx)
+	}
+}
+
 #[cfg(feature = "stream")]
 #[cfg_attr(docsrs, doc(cfg(all(feature = "stream", feature = "async"))))]
-impl<T: AsyncBufRead, P: Parse> Stream for AsyncDriver<T, P> {
+impl<T: AsyncBufSource, P: Parse> Stream for AsyncDriver<T, P> {
 	type Item = Result<P::Output>;
 
-	fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-		match self.poll_read(cx) {
+	/// Poll for the next event.
+	///
+	/// I/O errors are yielded as items and the stream may be polled again to
+	/// retry. After any other error (which is fatal to the parser) has been
+	/// yielded, the stream terminates.
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+		if self.terminated {
+			return Poll::Ready(None);
+		}
+		match self.as_mut().poll_read(cx) {
 			Poll::Pending => Poll::Pending,
 			Poll::Ready(Ok(Some(v))) => Poll::Ready(Some(Ok(v))),
-			Poll::Ready(Ok(None)) => Poll::Ready(None),
-			Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e))),
+			Poll::Ready(Ok(None)) => {
+				*self.project().terminated = true;
+				Poll::Ready(None)
+			}
+			Poll::Ready(Err(e)) => {
+				if !matches!(e, Error::IO(_)) {
+					*self.project().terminated = true;
+				}
+				Poll::Ready(Some(Err(e)))
+			}
 		}
 	}
 }
 
+#[cfg(feature = "stream")]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "stream", feature = "async"))))]
+impl<T: AsyncBufSource, P: Parse> FusedStream for AsyncDriver<T, P> {
+	fn is_terminated(&self) -> bool {
+		self.terminated
+	}
+}
+
 /**
 Helper trait for asynchronous sources of individual XML events
 
@@ -152,16 +578,29 @@ pin_project! {
 
 	In general, it is advised to use the [`AsyncParser`] alias which
 	specializes this struct for use with the default [`Parser`].
+
+	The source `T` can be any [`tokio::io::AsyncBufRead`] (with the `async`
+	feature), including TLS streams, [`tokio::io::BufReader`] wrappers
+	around arbitrary `AsyncRead`s and references to either. Sources which
+	are not [`Unpin`] can be used by pinning the driver (e.g. with
+	[`Box::pin`] or [`std::pin::pin!`]); the source can then be accessed
+	through [`AsyncDriver::get_inner_pin_mut`].
+
+	After parsing, the source can be retrieved with
+	[`AsyncDriver::into_inner`], e.g. to continue using a transport after
+	the XML stream has ended.
 	*/
 	#[project = AsyncDriverProj]
 	pub struct AsyncDriver<T, P: Parse>{
 		#[pin]
 		inner: T,
 		driver: PushDriver<P>,
+		// only used by the Stream implementation
+		terminated: bool,
 	}
 }
 
-impl<T: AsyncBufRead, P: Parse + Default> AsyncDriver<T, P> {
+impl<T, P: Parse + Default> AsyncDriver<T, P> {
 	/// Create a new parser with default options, wrapping the given reader.
 	pub fn new(inner: T) -> Self {
 		Self::with_options(inner, LexerOptions::default())
@@ -174,12 +613,124 @@ impl<T: AsyncBufRead, P: Parse + Default> AsyncDriver<T, P> {
 	}
 }
 
-impl<T: AsyncBufRead, P: Parse> AsyncDriver<T, P> {
+#[cfg(feature = "stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "stream")))]
+impl<S, B, P: Parse + Default> AsyncDriver<ByteStream<S, B>, P> {
+	/// Create a new parser with default options, reading from a stream of
+	/// byte chunks.
+	///
+	/// See [`ByteStream`] for details.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use bytes::Bytes;
+	/// use futures_util::stream;
+	/// use rxml::{AsyncEventReadExt, AsyncParser, ResolvedEvent};
+	/// # tokio_test::block_on(async {
+	/// let chunks = vec![
+	/// 	Ok::<_, std::io::Error>(Bytes::from_static(b"<hel")),
+	/// 	Ok(Bytes::from_static(b"lo>World!</hello>")),
+	/// ];
+	/// let mut pp = AsyncParser::from_stream(stream::iter(chunks));
+	/// let mut events = Vec::new();
+	/// pp.read_all(|ev| events.push(ev)).await.unwrap();
+	/// assert!(matches!(events[0], ResolvedEvent::StartElement(..)));
+	/// # })
+	/// ```
+	pub fn from_stream<E>(inner: S) -> Self
+	where
+		S: Stream<Item = std::result::Result<B, E>>,
+		B: bytes::Buf,
+		E: Into<io::Error>,
+	{
+		Self::new(ByteStream::new(inner))
+	}
+}
+
+#[cfg(feature = "stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "stream")))]
+impl<S, B, P: Parse + Default> AsyncDriver<TextStream<S, B>, P> {
+	/// Create a new parser with default options, reading from a stream of
+	/// text chunks.
+	///
+	/// See [`TextStream`] for details.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use futures_util::stream;
+	/// use rxml::{AsyncEventReadExt, AsyncParser, ResolvedEvent};
+	/// # tokio_test::block_on(async {
+	/// let chunks = vec![
+	/// 	Ok::<_, std::io::Error>(String::from("<hello>Wö")),
+	/// 	Ok(String::from("rld!</hello>")),
+	/// ];
+	/// let mut pp = AsyncParser::from_text_stream(stream::iter(chunks));
+	/// let mut events = Vec::new();
+	/// pp.read_all(|ev| events.push(ev)).await.unwrap();
+	/// match &events[1] {
+	/// 	ResolvedEvent::Text(_, text) => assert_eq!(text, "Wörld!"),
+	/// 	other => panic!("unexpected event: {:?}", other),
+	/// }
+	/// # })
+	/// ```
+	pub fn from_text_stream<E>(inner: S) -> Self
+	where
+		S: Stream<Item = std::result::Result<B, E>>,
+		B: AsRef<str>,
+		E: Into<io::Error>,
+	{
+		Self::new(TextStream::new(inner))
+	}
+}
+
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+impl<B: AsRef<[u8]>, P: Parse + Default> AsyncDriver<ChannelSource<B>, P> {
+	/// Create a new parser with default options, reading chunks from a
+	/// channel.
+	///
+	/// See [`ChannelSource`] for details.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::{AsyncEventReadExt, AsyncParser, ResolvedEvent};
+	/// # tokio_test::block_on(async {
+	/// let (tx, rx) = tokio::sync::mpsc::channel::<Vec<u8>>(4);
+	/// let mut pp = AsyncParser::from_channel(rx);
+	/// tokio::spawn(async move {
+	/// 	tx.send(b"<hel".to_vec()).await.unwrap();
+	/// 	tx.send(b"lo>World!</hello>".to_vec()).await.unwrap();
+	/// 	// dropping the sender signals the end of the document
+	/// });
+	/// let mut events = Vec::new();
+	/// pp.read_all(|ev| events.push(ev)).await.unwrap();
+	/// assert!(matches!(events[0], ResolvedEvent::StartElement(..)));
+	/// assert_eq!(events.len(), 3);
+	/// # })
+	/// ```
+	pub fn from_channel(rx: mpsc::Receiver<B>) -> Self {
+		Self::new(ChannelSource::new(rx))
+	}
+}
+
+impl<T> AsyncDriver<T, Parser> {
+	/// Create a new parser wrapping the given reader, configured according
+	/// to the given [`Profile`].
+	pub fn with_profile(inner: T, profile: Profile) -> Self {
+		Self::wrap(inner, profile.lexer(), profile.parser())
+	}
+}
+
+impl<T, P: Parse> AsyncDriver<T, P> {
 	/// Create a fully customized parser from a lexer and a parser component.
 	pub fn wrap(inner: T, lexer: Lexer, parser: P) -> Self {
 		Self {
 			inner,
 			driver: PushDriver::wrap(lexer, parser),
+			terminated: false,
 		}
 	}
 
@@ -199,6 +750,14 @@ impl<T: AsyncBufRead, P: Parse> AsyncDriver<T, P> {
 		&mut self.inner
 	}
 
+	/// Access the inner AsyncBufRead through a pinned reference to the
+	/// driver.
+	///
+	/// This is useful if the source is not [`Unpin`].
+	pub fn get_inner_pin_mut(self: Pin<&mut Self>) -> Pin<&mut T> {
+		self.project().inner
+	}
+
 	/// Access the lexer
 	pub fn get_lexer(&self) -> &Lexer {
 		self.driver.get_lexer()
@@ -231,14 +790,19 @@ impl<T: AsyncBufRead, P: Parse> AsyncDriver<T, P> {
 }
 
 impl<T, P: Parse> AsyncDriver<T, P> {
+	#[inline]
 	fn parse_step(
 		driver: &mut PushDriver<P>,
 		buf: &mut &[u8],
 		may_eof: bool,
+		utf8: bool,
 	) -> (usize, Poll<Result<Option<P::Output>>>) {
 		let old_len = buf.len();
+		let is_utf8: fn(&&[u8]) -> bool = if utf8 { |_| true } else { |_| false };
 		// need to guard eof with the buf len here, because we only know that we are actually at eof by the fact that we see an empty buffer.
-		let result = driver.parse(buf, may_eof && buf.len() == 0);
+		// SAFETY: utf8 is only set for sources which hand out the unread
+		// parts of strs
+		let result = unsafe { driver.parse_with(buf, may_eof && buf.is_empty(), is_utf8) };
 		let new_len = buf.len();
 		assert!(new_len <= old_len);
 		let read = old_len - new_len;
@@ -252,22 +816,23 @@ impl<T, P: Parse> AsyncDriver<T, P> {
 	}
 }
 
-impl<T: AsyncBufRead, P: Parse> AsyncEventRead for AsyncDriver<T, P> {
+impl<T: AsyncBufSource, P: Parse> AsyncEventRead for AsyncDriver<T, P> {
 	type Output = P::Output;
 
 	fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<Option<P::Output>>> {
 		let mut this = self.project();
+		let utf8 = this.inner.chunk_is_utf8();
 		loop {
 			let mut buf = match this.inner.as_mut().poll_fill_buf(cx) {
 				Poll::Pending => {
 					// a.k.a. WouldBlock
 					// we always try an empty read here because the lexer needs that
-					return Self::parse_step(this.driver, &mut &[][..], false).1;
+					return Self::parse_step(this.driver, &mut &[][..], false, false).1;
 				}
 				Poll::Ready(Ok(buf)) => buf,
 				Poll::Ready(Err(e)) => return Poll::Ready(Err(e.into())),
 			};
-			let (consumed, result) = Self::parse_step(this.driver, &mut buf, true);
+			let (consumed, result) = Self::parse_step(this.driver, &mut buf, true, utf8);
 			this.inner.as_mut().consume(consumed);
 			match result {
 				// if we get a pending here, we need to ask the source for more data!
@@ -279,9 +844,9 @@ impl<T: AsyncBufRead, P: Parse> AsyncEventRead for AsyncDriver<T, P> {
 }
 
 /**
-Tokio-compatible asynchronous parser
+Asynchronous parser
 
-The [`AsyncParser`] allows parsing XML documents from a [`tokio::io::AsyncBufRead`], asynchronously. It operates similarly as the [`PullParser`] does, but instead of blocking the task, it will yield control to other tasks if the backend is not able to supply data immediately.
+The [`AsyncParser`] allows parsing XML documents from a [`tokio::io::AsyncBufRead`] (with the `async` feature) or from a `futures_io::AsyncBufRead` wrapped in [`FuturesIo`] (with the `futures` feature), asynchronously. It operates similarly as the [`PullParser`] does, but instead of blocking the task, it will yield control to other tasks if the backend is not able to supply data immediately.
 
 This is a type alias around a [`AsyncDriver`] and documentation for the API is
 found there.
@@ -295,6 +860,7 @@ The example is a bit pointless because it does not really demonstrate the asynch
 ```
 use rxml::{AsyncParser, Error, ResolvedEvent, XmlVersion, AsyncEventReadExt};
 use tokio::io::AsyncRead;
+# #[cfg(feature = "async")]
 # tokio_test::block_on(async {
 let mut doc = &b"<?xml version='1.0'?><hello>World!</hello>"[..];
 // this converts the doc into an tokio::io::AsyncRead
@@ -305,6 +871,40 @@ assert!(matches!(ev.unwrap().unwrap(), ResolvedEvent::XmlDeclaration(_, XmlVersi
 # })
 ```
 
+## Usage as Stream
+
+With the `stream` feature, the [`AsyncDriver`] implements
+`futures_core::Stream` (and `FusedStream`), yielding `Result`s of events.
+This allows to use it with the combinators from `StreamExt` and in
+`select!` loops. The stream ends at the end of the document and after the
+first fatal (i.e. non-I/O) error.
+
+```
+# #[cfg(feature = "stream")]
+# tokio_test::block_on(async {
+use futures_util::StreamExt;
+use rxml::{AsyncParser, ResolvedEvent};
+
+let mut doc = &b"<hello>World!</hello>"[..];
+let names: Vec<_> = AsyncParser::new(&mut doc)
+	.filter_map(|ev| async move {
+		match ev.unwrap() {
+			ResolvedEvent::StartElement(_, (_, localname), _, _) => Some(localname),
+			_ => None,
+		}
+	})
+	.collect()
+	.await;
+assert_eq!(names, vec!["hello"]);
+# })
+```
+
+## Usage without tokio
+
+With the `futures` feature, any `futures_io::AsyncBufRead` can be used as
+source by wrapping it in [`FuturesIo`]. The `async` feature (and thus tokio)
+is not required for that.
+
 ## Parsing without namespace expansion
 
 To parse an XML document without namespace expansion in blocking mode,
@@ -313,5 +913,6 @@ one can use the [`AsyncDriver`] with a [`RawParser`]. Note the caveats in the
 
    [`RawParser`]: crate::parser::RawParser
    [`PullParser`]: crate::PullParser
+   [`FuturesIo`]: crate::FuturesIo
 */
 pub type AsyncParser<T> = AsyncDriver<T, Parser>;
@@ -0,0 +1,104 @@
+use alloc::vec::Vec;
+
+use super::{Token, TokenMetrics};
+
+/// Record the input bytes of elements at a given depth.
+///
+/// All bytes consumed by the lexer are appended to a buffer. Bytes which
+/// belong to tokens outside of the captured elements are dropped as soon as
+/// the token has been emitted, so that only the bytes which the lexer has
+/// read ahead are kept between captured elements.
+///
+/// Positions are in the coordinates of [`Lexer::position`], i.e. they wrap
+/// around on overflow.
+///
+///    [`Lexer::position`]: super::Lexer::position
+#[derive(Debug, Clone, Default)]
+pub(super) struct RawCapture {
+	/// Bytes consumed since `start`.
+	buf: Vec<u8>,
+	/// Position of the first byte in `buf`.
+	start: usize,
+	/// Number of currently open elements.
+	depth: usize,
+	/// Whether the last element token was the start of a footer.
+	in_footer: bool,
+	/// Position of the element which is currently being captured.
+	element_start: Option<usize>,
+	/// The bytes of the last completed element.
+	completed: Option<Vec<u8>>,
+}
+
+impl RawCapture {
+	/// Record bytes which have been consumed by the lexer.
+	pub(super) fn consumed(&mut self, data: &[u8]) {
+		self.buf.extend_from_slice(data);
+	}
+
+	/// Process a token emitted by the lexer, capturing elements which are
+	/// nested `target` levels deep.
+	pub(super) fn token(&mut self, target: usize, token: &Token) {
+		let metrics = token.metrics();
+		match token {
+			Token::ElementHeadStart(..) => {
+				if self.depth == target {
+					self.element_start = Some(metrics.start());
+				}
+				self.depth += 1;
+			}
+			Token::ElementFootStart(..) => self.in_footer = true,
+			Token::ElementHeadClose(..) => self.close(target, metrics),
+			Token::ElementHFEnd(..) if self.in_footer => {
+				self.in_footer = false;
+				self.close(target, metrics);
+			}
+			_ => (),
+		}
+		self.emitted(metrics);
+	}
+
+	/// Process the end of a token, including text which has been passed on
+	/// without materializing a token.
+	pub(super) fn emitted(&mut self, metrics: &TokenMetrics) {
+		if self.element_start.is_none() {
+			self.discard_until(metrics.end());
+		}
+	}
+
+	fn close(&mut self, target: usize, metrics: &TokenMetrics) {
+		self.depth = self.depth.saturating_sub(1);
+		if self.depth != target {
+			return;
+		}
+		if let Some(start) = self.element_start.take() {
+			let from = start.wrapping_sub(self.start);
+			let to = metrics.end().wrapping_sub(self.start);
+			let raw = &self.buf[from..to];
+			// the header token of the root element includes the whitespace
+			// after the XML declaration
+			let raw = match raw.iter().position(|b| *b == b'<') {
+				Some(i) => &raw[i..],
+				None => raw,
+			};
+			self.completed = Some(raw.to_vec());
+		}
+	}
+
+	fn discard_until(&mut self, pos: usize) {
+		let n = pos.wrapping_sub(self.start);
+		self.buf.drain(..n);
+		self.start = pos;
+	}
+
+	pub(super) fn take_completed(&mut self) -> Option<Vec<u8>> {
+		self.completed.take()
+	}
+
+	pub(super) fn memory_used(&self) -> usize {
+		self.buf.capacity() + self.completed.as_ref().map_or(0, |x| x.capacity())
+	}
+
+	pub(super) fn release_temporaries(&mut self) {
+		self.buf.shrink_to_fit();
+	}
+}
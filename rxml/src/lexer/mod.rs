@@ -1,16 +1,69 @@
 /*!
 # XML 1.0 Lexer
+
+The [`Lexer`] turns bytes into [`Token`]s. Tokens are a lower-level view on
+the document than the events of the parsers: they carry the byte range of
+the input they were derived from (see [`TokenMetrics`]) and are not checked
+against the grammar of the document as a whole, which makes them useful for
+tools which care about the source text, such as syntax highlighters.
+
+Tokens are read through the [`TokenRead`](crate::parser::TokenRead) trait,
+which is implemented by [`LexerAdapter`](crate::LexerAdapter) for
+[`std::io::BufRead`] sources and by
+[`BufferLexerAdapter`](crate::parser::BufferLexerAdapter) for in-memory
+buffers. The parsers consume any [`TokenRead`](crate::parser::TokenRead),
+so it is also possible to filter or replay tokens before parsing them.
+
+The [`Token`] enum, the [`TokenMetrics`] and the
+[`TokenRead`](crate::parser::TokenRead) trait are part of the stable API:
+their meaning only changes with a semver-incompatible release. New token
+types may be added, which is why [`Token`] is `#[non_exhaustive]`.
+
+## Example
+
+```
+use rxml::{Lexer, Token};
+use rxml::parser::{BufferLexerAdapter, TokenRead};
+
+let doc = b"<a b='c'>d</a>";
+let mut lexer = Lexer::new();
+let mut buf = &doc[..];
+let mut tokens = BufferLexerAdapter::new(&mut lexer, &mut buf, true);
+let mut spans = Vec::new();
+while let Some(tok) = tokens.read().unwrap() {
+	let m = tok.metrics();
+	spans.push((tok.name(), &doc[m.start()..m.end()]));
+}
+assert_eq!(spans, vec![
+	(Token::NAME_ELEMENTHEADSTART, &b"<a"[..]),
+	(Token::NAME_NAME, &b"b"[..]),
+	(Token::NAME_EQ, &b"="[..]),
+	(Token::NAME_ATTRIBUTEVALUE, &b"'c'"[..]),
+	(Token::NAME_ELEMENTHFEND, &b">"[..]),
+	(Token::NAME_TEXT, &b"d"[..]),
+	(Token::NAME_ELEMENTFOOTSTART, &b"</a"[..]),
+	(Token::NAME_ELEMENTHFEND, &b">"[..]),
+]);
+```
 */
 // needed for trait bounds
-use std::convert::TryInto;
-use std::fmt;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::fmt;
+#[cfg(feature = "std")]
 use std::io;
 
+mod capture;
 mod ranges;
 mod read;
 
+#[allow(deprecated)]
+use crate::bufq::BufferQueue;
 use crate::errctx::*;
-use crate::error::{Error as CrateError, ErrorWithContext, Result as CrateResult, XmlError};
+#[cfg(feature = "std")]
+use crate::error::Error as CrateError;
+use crate::error::{ErrorWithContext, Result as CrateResult, XmlError};
 use crate::strings::*;
 use ranges::*;
 use read::Endbyte;
@@ -57,9 +110,12 @@ impl TokenMetrics {
 		self.end
 	}
 
-	// for use in parser unit tests
-	#[cfg(test)]
-	pub(crate) const fn new(start: usize, end: usize) -> TokenMetrics {
+	/// Create token metrics for the byte range from `start` (inclusive) to
+	/// `end` (exclusive).
+	///
+	/// This is useful for token sources other than the [`Lexer`], e.g. for
+	/// replaying recorded tokens.
+	pub const fn new(start: usize, end: usize) -> TokenMetrics {
 		TokenMetrics {
 			start: start,
 			end: end,
@@ -79,8 +135,12 @@ Each token has a [`TokenMetrics`] object attached which describes the byte
 range of the input stream from which the token was derived. Note that the
 ranges denoted by the token metrics may not be consecutive, as some whitespace
 within elements and the XML declaration does not generate tokens.
+
+New variants may be added in future versions without a semver-incompatible
+release.
 */
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum Token {
 	/// A freestanding (i.e. not the element name) XML `Name`.
 	///
@@ -112,6 +172,16 @@ pub enum Token {
 	/// header or footer is being lexed.
 	AttributeValue(TokenMetrics, CData),
 
+	/// An attribute value whose references have not been expanded.
+	///
+	/// This token is only emitted if
+	/// [`LexerOptions::lazy_attribute_values`] is enabled, and only for
+	/// values in element headers which contain at least one reference. The
+	/// references have been checked and are kept as written (e.g. `&amp;`
+	/// or `&#x20;`); other values are emitted as
+	/// [`Token::AttributeValue`].
+	UnexpandedAttributeValue(TokenMetrics, CData),
+
 	/// The `?>` sequence.
 	///
 	/// This token is only emitted while the XML declaration is being lexed.
@@ -164,6 +234,27 @@ pub enum Token {
 	/// or footer is being lexed. Stray text eventually leads to an error
 	/// there.
 	Text(TokenMetrics, CData),
+
+	/// Character data consisting only of whitespace.
+	///
+	/// This token is only emitted instead of [`Token::Text`] if
+	/// [`LexerOptions::whitespace_tokens`] is enabled. The same rules as for
+	/// text tokens apply; in particular, a long run of whitespace may be
+	/// split into several tokens.
+	///
+	/// A token is classified by its content after expansion of references
+	/// and CDATA sections, so `&#32;` or `<![CDATA[ ]]>` may also give rise
+	/// to a whitespace token.
+	///
+	/// Each token is classified on its own, not the text node it is part
+	/// of. A text node is split into several tokens at references, at CDATA
+	/// sections and when it exceeds
+	/// [`LexerOptions::max_token_length`], so a text node which contains
+	/// other characters may still start or end with whitespace tokens: for
+	/// ` <![CDATA[x]]> `, a whitespace token, a text token and another
+	/// whitespace token are emitted. A text node is whitespace-only if all
+	/// of its tokens are whitespace tokens.
+	Whitespace(TokenMetrics, CData),
 }
 
 impl Token {
@@ -177,6 +268,7 @@ impl Token {
 	pub const NAME_ELEMENTHEADSTART: &'static str = "'<'";
 	pub const NAME_ELEMENTFOOTSTART: &'static str = "'</'";
 	pub const NAME_TEXT: &'static str = "Text";
+	pub const NAME_WHITESPACE: &'static str = "Whitespace";
 
 	/// Return a static string describing the token type.
 	///
@@ -186,6 +278,7 @@ impl Token {
 			Self::Name(..) => Self::NAME_NAME,
 			Self::Eq(..) => Self::NAME_EQ,
 			Self::AttributeValue(..) => Self::NAME_ATTRIBUTEVALUE,
+			Self::UnexpandedAttributeValue(..) => Self::NAME_ATTRIBUTEVALUE,
 			Self::XMLDeclEnd(..) => Self::NAME_XMLDECLEND,
 			Self::ElementHeadClose(..) => Self::NAME_ELEMENTHEADCLOSE,
 			Self::ElementHFEnd(..) => Self::NAME_ELEMENTHFEND,
@@ -193,22 +286,25 @@ impl Token {
 			Self::ElementHeadStart(..) => Self::NAME_ELEMENTHEADSTART,
 			Self::ElementFootStart(..) => Self::NAME_ELEMENTFOOTSTART,
 			Self::Text(..) => Self::NAME_TEXT,
+			Self::Whitespace(..) => Self::NAME_WHITESPACE,
 		}
 	}
 
 	/// Return a reference to this tokens [`TokenMetrics`].
 	pub fn metrics(&self) -> &TokenMetrics {
 		match self {
-			Self::Name(m, ..) => &m,
-			Self::Eq(m) => &m,
-			Self::AttributeValue(m, ..) => &m,
-			Self::XMLDeclEnd(m) => &m,
-			Self::ElementHeadClose(m) => &m,
-			Self::ElementHFEnd(m) => &m,
-			Self::XMLDeclStart(m) => &m,
-			Self::ElementHeadStart(m, ..) => &m,
-			Self::ElementFootStart(m, ..) => &m,
-			Self::Text(m, ..) => &m,
+			Self::Name(m, ..) => m,
+			Self::Eq(m) => m,
+			Self::AttributeValue(m, ..) => m,
+			Self::UnexpandedAttributeValue(m, ..) => m,
+			Self::XMLDeclEnd(m) => m,
+			Self::ElementHeadClose(m) => m,
+			Self::ElementHFEnd(m) => m,
+			Self::XMLDeclStart(m) => m,
+			Self::ElementHeadStart(m, ..) => m,
+			Self::ElementFootStart(m, ..) => m,
+			Self::Text(m, ..) => m,
+			Self::Whitespace(m, ..) => m,
 		}
 	}
 }
@@ -362,6 +458,28 @@ const TOK_XML_CDATA_START: &'static [u8] = b"<![CDATA[";
 const TOK_XML_CDATA_END: &'static [u8] = b"]]>";
 // const CLASS_XML_NAME_START_CHAR:
 
+/// Strategy for growing the internal buffers of a [`Lexer`].
+///
+/// See [`LexerOptions::buffer_growth`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BufferGrowth {
+	/// Reserve [`LexerOptions::max_token_length`] bytes as soon as any
+	/// token data needs to be buffered.
+	///
+	/// This avoids reallocations while lexing at the cost of holding the
+	/// maximum amount of memory for the lifetime of the lexer. This is the
+	/// default.
+	#[default]
+	Eager,
+
+	/// Grow the buffers only as far as the data actually requires.
+	///
+	/// This keeps the memory use proportional to the largest token seen
+	/// (still bounded by [`LexerOptions::max_token_length`]), at the cost of
+	/// occasional reallocations.
+	OnDemand,
+}
+
 /// Hold options to configure a [`Lexer`].
 ///
 /// See also [`Lexer::with_options()`].
@@ -379,6 +497,112 @@ pub struct LexerOptions {
 	/// split and emitted in parts (and lexing continues), all other tokens
 	/// exceeding this limit will cause an error.
 	pub max_token_length: usize,
+
+	/// Require element and attribute names to be in Unicode Normalization
+	/// Form C.
+	///
+	/// If enabled, names which are not in NFC cause lexing to fail with
+	/// [`Error::RestrictedXml`](crate::Error::RestrictedXml).
+	///
+	/// The check requires the `nfc` feature. Without it, enabling this
+	/// option makes lexing fail at the first name, so that the requirement
+	/// is never silently ignored.
+	pub require_nfc_names: bool,
+
+	/// Require text and attribute values to be in Unicode Normalization
+	/// Form C.
+	///
+	/// If enabled, text which is not in NFC causes lexing to fail with
+	/// [`Error::RestrictedXml`](crate::Error::RestrictedXml).
+	///
+	/// **Note:** Long text is checked in chunks of at most
+	/// [`max_token_length`](Self::max_token_length) bytes. A
+	/// denormalization which spans two chunks (e.g. a base character at the
+	/// end of one chunk followed by a combining character at the start of
+	/// the next) is not detected.
+	///
+	/// Like [`require_nfc_names`](Self::require_nfc_names), this option
+	/// causes lexing to fail if the `nfc` feature is disabled.
+	pub require_nfc_text: bool,
+
+	/// Emit character data which consists only of whitespace as
+	/// [`Token::Whitespace`] instead of [`Token::Text`].
+	///
+	/// This allows tools which work on the token level, such as formatters,
+	/// to tell indentation between tags apart from actual content. It does
+	/// not change the events produced by the parsers, which treat
+	/// whitespace tokens as text.
+	///
+	/// Tokens are classified individually, so that the lexer never has to
+	/// hold back a whole text node; see [`Token::Whitespace`] for what this
+	/// means for text nodes split into several tokens.
+	pub whitespace_tokens: bool,
+
+	/// Pass line endings through as they appear in the document.
+	///
+	/// By default, the lexer normalizes line endings as required by XML 1.0
+	/// § 2.11: `\r\n` and lone `\r` are converted to `\n` in text and
+	/// CDATA sections, and to a single space in attribute values. If
+	/// enabled, carriage returns are kept in text instead, and each `\r` in
+	/// an attribute value becomes a space of its own. This is only useful
+	/// for tools which need to reproduce the input exactly; the resulting
+	/// text is not what an XML processor is supposed to report.
+	pub preserve_line_endings: bool,
+
+	/// Keep references in attribute values as written.
+	///
+	/// Values with references are then emitted as
+	/// [`Token::UnexpandedAttributeValue`], and the parsers leave their
+	/// expansion to [`Attributes`](crate::parser::Attributes) until a value
+	/// is accessed. The references are still checked, so that a document
+	/// with an invalid reference is rejected as usual. Values of namespace
+	/// declarations are always expanded.
+	///
+	/// [`require_nfc_text`](Self::require_nfc_text) applies to such values
+	/// as written.
+	pub lazy_attribute_values: bool,
+
+	/// Number of bytes to allocate for the token buffer when the lexer is
+	/// created.
+	///
+	/// This is capped at [`max_token_length`](Self::max_token_length).
+	pub initial_buffer_capacity: usize,
+
+	/// How the token buffers grow when token data has to be buffered.
+	pub buffer_growth: BufferGrowth,
+
+	/// Capacity above which the token buffers are shrunk again after a token
+	/// has been emitted.
+	///
+	/// When set, a buffer which has grown beyond this many bytes is released
+	/// once it is empty again, and the token buffer is re-allocated with
+	/// [`initial_buffer_capacity`](Self::initial_buffer_capacity). This
+	/// bounds the memory held by long-lived lexers after an occasional large
+	/// token. It is most useful together with [`BufferGrowth::OnDemand`];
+	/// with [`BufferGrowth::Eager`], a threshold below
+	/// [`max_token_length`](Self::max_token_length) causes a reallocation
+	/// for every buffered token.
+	///
+	/// If `None` (the default), buffers are only shrunk by
+	/// [`Lexer::release_temporaries`].
+	pub buffer_shrink_threshold: Option<usize>,
+
+	/// Record the original bytes of elements nested this many levels deep,
+	/// where `0` is the root element.
+	///
+	/// When an element at this depth ends, its bytes, from the `<` of its
+	/// header to the `>` of its footer, are available from
+	/// [`Lexer::take_raw_element`]. This is needed for signature
+	/// verification and bit-exact forwarding, where re-encoding the events
+	/// would not reproduce the input.
+	///
+	/// The bytes of the element which is currently being captured are
+	/// buffered in full, in addition to the events produced from them. For
+	/// the root element of a long-lived stream, this means that the whole
+	/// stream is buffered; capture its children instead.
+	///
+	/// If `None` (the default), nothing is recorded.
+	pub raw_capture_depth: Option<usize>,
 }
 
 impl LexerOptions {
@@ -402,6 +626,156 @@ impl LexerOptions {
 		self.max_token_length = v;
 		self
 	}
+
+	/// Set the [`LexerOptions::require_nfc_names`] value.
+	pub fn require_nfc_names(mut self, v: bool) -> LexerOptions {
+		self.require_nfc_names = v;
+		self
+	}
+
+	/// Set the [`LexerOptions::require_nfc_text`] value.
+	pub fn require_nfc_text(mut self, v: bool) -> LexerOptions {
+		self.require_nfc_text = v;
+		self
+	}
+
+	/// Set the [`LexerOptions::whitespace_tokens`] value.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::{Lexer, LexerOptions, Token};
+	///
+	/// let mut lexer = Lexer::with_options(LexerOptions::default().whitespace_tokens(true));
+	/// let mut doc = &b"<a>\n\t<b/>\n</a>"[..];
+	/// let mut kinds = Vec::new();
+	/// while let Some(tok) = lexer.lex_buffer(&mut doc, true).unwrap() {
+	/// 	kinds.push(tok.name());
+	/// }
+	/// assert_eq!(kinds, vec![
+	/// 	Token::NAME_ELEMENTHEADSTART, Token::NAME_ELEMENTHFEND,
+	/// 	Token::NAME_WHITESPACE,
+	/// 	Token::NAME_ELEMENTHEADSTART, Token::NAME_ELEMENTHEADCLOSE,
+	/// 	Token::NAME_WHITESPACE,
+	/// 	Token::NAME_ELEMENTFOOTSTART, Token::NAME_ELEMENTHFEND,
+	/// ]);
+	/// ```
+	pub fn whitespace_tokens(mut self, v: bool) -> LexerOptions {
+		self.whitespace_tokens = v;
+		self
+	}
+
+	/// Set the [`LexerOptions::preserve_line_endings`] value.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::{Lexer, LexerOptions, Token};
+	///
+	/// let mut lexer = Lexer::with_options(LexerOptions::default().preserve_line_endings(true));
+	/// let mut doc = &b"<a>foo\r\nbar</a>"[..];
+	/// let mut text = String::new();
+	/// while let Some(tok) = lexer.lex_buffer(&mut doc, true).unwrap() {
+	/// 	if let Token::Text(_, t) = tok {
+	/// 		text.push_str(&t);
+	/// 	}
+	/// }
+	/// assert_eq!(text, "foo\r\nbar");
+	/// ```
+	pub fn preserve_line_endings(mut self, v: bool) -> LexerOptions {
+		self.preserve_line_endings = v;
+		self
+	}
+
+	/// Set the [`LexerOptions::lazy_attribute_values`] value.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::{FeedParser, Lexer, LexerOptions, Parser, ResolvedEvent};
+	///
+	/// let lexer = Lexer::with_options(LexerOptions::default().lazy_attribute_values(true));
+	/// let mut fp = FeedParser::wrap(lexer, Parser::default());
+	/// let ev = fp.parse(&mut &b"<a title='Tom &amp; Jerry' id='x'/>"[..], true).unwrap();
+	/// let attrs = match ev {
+	/// 	Some(ResolvedEvent::StartElement(_, _, attrs, _)) => attrs,
+	/// 	other => panic!("unexpected event: {:?}", other),
+	/// };
+	/// assert!(attrs.has_unexpanded_references());
+	/// assert_eq!(&*attrs.attr(None, "title").unwrap(), "Tom & Jerry");
+	/// ```
+	pub fn lazy_attribute_values(mut self, v: bool) -> LexerOptions {
+		self.lazy_attribute_values = v;
+		self
+	}
+
+	/// Set the [`LexerOptions::initial_buffer_capacity`] value.
+	pub fn initial_buffer_capacity(mut self, v: usize) -> LexerOptions {
+		self.initial_buffer_capacity = v;
+		self
+	}
+
+	/// Set the [`LexerOptions::buffer_growth`] value.
+	///
+	/// # Example
+	///
+	/// A lexer for a memory-constrained environment, which only allocates
+	/// what it needs and gives memory back after large tokens:
+	///
+	/// ```
+	/// use rxml::{Lexer, LexerOptions};
+	/// use rxml::lexer::BufferGrowth;
+	///
+	/// let mut lexer = Lexer::with_options(
+	/// 	LexerOptions::default()
+	/// 		.initial_buffer_capacity(64)
+	/// 		.buffer_growth(BufferGrowth::OnDemand)
+	/// 		.buffer_shrink_threshold(Some(1024)),
+	/// );
+	/// ```
+	pub fn buffer_growth(mut self, v: BufferGrowth) -> LexerOptions {
+		self.buffer_growth = v;
+		self
+	}
+
+	/// Set the [`LexerOptions::buffer_shrink_threshold`] value.
+	pub fn buffer_shrink_threshold(mut self, v: Option<usize>) -> LexerOptions {
+		self.buffer_shrink_threshold = v;
+		self
+	}
+
+	/// Set the [`LexerOptions::raw_capture_depth`] value.
+	///
+	/// # Example
+	///
+	/// Forwarding the children of the root element exactly as they were
+	/// received:
+	///
+	/// ```
+	/// # #[cfg(feature = "std")]
+	/// # fn main() {
+	/// use rxml::{EventRead, LexerOptions, PullParser, ResolvedEvent};
+	///
+	/// let doc = &b"<stream><message  to=\"x\"><body>a &amp; b</body></message><presence/></stream>"[..];
+	/// let mut pp = PullParser::with_options(doc, LexerOptions::default().raw_capture_depth(Some(1)));
+	/// let mut forwarded = Vec::new();
+	/// while let Some(ev) = pp.read().unwrap() {
+	/// 	if let ResolvedEvent::EndElement(_) = ev {
+	/// 		forwarded.extend(pp.get_lexer_mut().take_raw_element());
+	/// 	}
+	/// }
+	/// assert_eq!(forwarded, [
+	/// 	&b"<message  to=\"x\"><body>a &amp; b</body></message>"[..],
+	/// 	&b"<presence/>"[..],
+	/// ]);
+	/// # }
+	/// # #[cfg(not(feature = "std"))]
+	/// # fn main() {}
+	/// ```
+	pub fn raw_capture_depth(mut self, v: Option<usize>) -> LexerOptions {
+		self.raw_capture_depth = v;
+		self
+	}
 }
 
 impl Default for LexerOptions {
@@ -411,6 +785,15 @@ impl Default for LexerOptions {
 	fn default() -> Self {
 		Self {
 			max_token_length: 8192,
+			require_nfc_names: false,
+			require_nfc_text: false,
+			whitespace_tokens: false,
+			preserve_line_endings: false,
+			lazy_attribute_values: false,
+			initial_buffer_capacity: 0,
+			buffer_growth: BufferGrowth::Eager,
+			buffer_shrink_threshold: None,
+			raw_capture_depth: None,
 		}
 	}
 }
@@ -427,6 +810,39 @@ fn resolve_named_entity(name: &[u8]) -> Result<u8> {
 	}
 }
 
+/// Expand the references in the value of a
+/// [`Token::UnexpandedAttributeValue`].
+pub(crate) fn expand_attribute_references(value: &CDataStr) -> CData {
+	let mut result = Vec::with_capacity(value.len());
+	let mut rest = value.as_bytes();
+	while let Some(start) = rest.iter().position(|&b| b == b'&') {
+		result.extend_from_slice(&rest[..start]);
+		rest = &rest[start + 1..];
+		let end = rest.iter().position(|&b| b == b';').unwrap();
+		let (reference, radix) = match &rest[..end] {
+			[b'#', b'x', digits @ ..] => (digits, Some(CharRefRadix::Hexadecimal)),
+			[b'#', digits @ ..] => (digits, Some(CharRefRadix::Decimal)),
+			name => (name, None),
+		};
+		let expanded = match radix {
+			// the digits are plain ascii, see lex_reference
+			Some(radix) => resolve_char_reference(
+				unsafe { core::str::from_utf8_unchecked(reference) },
+				radix,
+				&mut result,
+			),
+			None => resolve_named_entity(reference).map(|b| result.push(b)),
+		};
+		// the lexer only keeps references which it could resolve
+		debug_assert!(expanded.is_ok());
+		rest = &rest[end + 1..];
+	}
+	result.extend_from_slice(rest);
+	// SAFETY: references expand to valid characters and the remainder is
+	// taken from valid CData
+	unsafe { CData::from_string_unchecked(String::from_utf8_unchecked(result)) }
+}
+
 fn resolve_char_reference(s: &str, radix: CharRefRadix, into: &mut Vec<u8>) -> Result<()> {
 	let radix = match radix {
 		CharRefRadix::Decimal => 10,
@@ -434,7 +850,7 @@ fn resolve_char_reference(s: &str, radix: CharRefRadix, into: &mut Vec<u8>) -> R
 	};
 	// cannot fail because the string is validated against the alphabet and limited in length by the lexer
 	let codepoint = u32::from_str_radix(s, radix).unwrap();
-	let ch = match std::char::from_u32(codepoint) {
+	let ch = match core::char::from_u32(codepoint) {
 		Some(ch) => ch,
 		None => {
 			return Err(Error::Xml(XmlError::InvalidChar(
@@ -458,6 +874,57 @@ fn resolve_char_reference(s: &str, radix: CharRefRadix, into: &mut Vec<u8>) -> R
 	}
 }
 
+/// Resolve the body of a single entity or character reference (the part
+/// between `&` and `;`) and append the result to `into`.
+///
+/// This applies the same rules as the lexer does for references in text.
+pub(crate) fn resolve_reference(
+	reference: &str,
+	into: &mut Vec<u8>,
+) -> core::result::Result<(), XmlError> {
+	let (digits, radix) = if let Some(digits) = reference.strip_prefix("#x") {
+		(digits, CharRefRadix::Hexadecimal)
+	} else if let Some(digits) = reference.strip_prefix('#') {
+		(digits, CharRefRadix::Decimal)
+	} else {
+		if reference.is_empty() {
+			return Err(XmlError::InvalidSyntax("empty reference"));
+		}
+		if reference.len() > MAX_REFERENCE_LENGTH {
+			return Err(XmlError::UndeclaredEntity);
+		}
+		into.push(unwrap_xml_error(resolve_named_entity(
+			reference.as_bytes(),
+		))?);
+		return Ok(());
+	};
+	let valid: fn(u8) -> bool = match radix {
+		CharRefRadix::Decimal => is_decimal_digit,
+		CharRefRadix::Hexadecimal => is_hexadecimal_digit,
+	};
+	if let Some(b) = digits.bytes().find(|b| !valid(*b)) {
+		return Err(XmlError::UnexpectedByte(ERRCTX_REF, b, Some(&[";"])));
+	}
+	if digits.is_empty() {
+		return Err(XmlError::InvalidSyntax("empty reference"));
+	}
+	if digits.len() > MAX_REFERENCE_LENGTH {
+		return Err(XmlError::UndeclaredEntity);
+	}
+	unwrap_xml_error(add_context(
+		resolve_char_reference(digits, radix, into),
+		ERRCTX_REF,
+	))
+}
+
+fn unwrap_xml_error<T>(r: Result<T>) -> core::result::Result<T, XmlError> {
+	match r {
+		Ok(v) => Ok(v),
+		Err(Error::Xml(e)) => Err(e),
+		Err(other) => unreachable!("non-XML error from reference resolution: {:?}", other),
+	}
+}
+
 fn add_context<T>(r: Result<T>, ctx: &'static str) -> Result<T> {
 	r.or_else(|e| Err(e.with_context(ctx)))
 }
@@ -488,7 +955,7 @@ impl Error {
 		Error::Xml(XmlError::InvalidEof(ctx))
 	}
 
-	fn utf8err(src: &[u8], e: &std::str::Utf8Error) -> Error {
+	fn utf8err(src: &[u8], e: &core::str::Utf8Error) -> Error {
 		Error::InvalidUtf8Byte(src[e.valid_up_to()])
 	}
 }
@@ -513,9 +980,7 @@ impl From<XmlError> for Error {
 impl From<Error> for crate::Error {
 	fn from(other: Error) -> Self {
 		match other {
-			Error::EndOfBuffer => {
-				io::Error::new(io::ErrorKind::WouldBlock, "end of current buffer reached").into()
-			}
+			Error::EndOfBuffer => Self::would_block("end of current buffer reached"),
 			Error::Xml(e) => Self::Xml(e),
 			Error::RestrictedXml(what) => Self::RestrictedXml(what),
 			Error::InvalidUtf8Byte(b) => Self::InvalidUtf8Byte(b),
@@ -523,7 +988,50 @@ impl From<Error> for crate::Error {
 	}
 }
 
-type Result<T> = std::result::Result<T, Error>;
+type Result<T> = core::result::Result<T, Error>;
+
+/// Decode bytes to a str, skipping the UTF-8 validation if the bytes are
+/// known to be valid.
+///
+/// # Safety
+///
+/// If `valid` is true, `bytes` must be valid UTF-8.
+unsafe fn decode_utf8(bytes: &[u8], valid: bool) -> Result<&str> {
+	if valid {
+		debug_assert!(core::str::from_utf8(bytes).is_ok());
+		return Ok(core::str::from_utf8_unchecked(bytes));
+	}
+	match core::str::from_utf8(bytes) {
+		Ok(s) => Ok(s),
+		Err(e) => Err(Error::utf8err(bytes, &e)),
+	}
+}
+
+/// Return the length of the longest prefix of `bytes` which does not end
+/// in the middle of a UTF-8 sequence.
+///
+/// `bytes` must be a prefix of valid UTF-8.
+fn utf8_complete_prefix_len(bytes: &[u8]) -> usize {
+	let len = bytes.len();
+	for i in (len.saturating_sub(4)..len).rev() {
+		let b = bytes[i];
+		if b & 0xc0 == 0x80 {
+			// continuation byte, keep searching for the start
+			continue;
+		}
+		let width = if b < 0x80 {
+			1
+		} else if b >= 0xf0 {
+			4
+		} else if b >= 0xe0 {
+			3
+		} else {
+			2
+		};
+		return if i + width <= len { len } else { i };
+	}
+	len
+}
 
 /**
 # Restricted XML 1.0 lexer
@@ -531,7 +1039,11 @@ type Result<T> = std::result::Result<T, Error>;
 This lexer is able to lex a restricted subset of XML 1.0. For an overview
 of the restrictions (including those imposed by [`Parser`](`crate::Parser`)),
 see [`rxml`](`crate`).
+
+Cloning a lexer yields an independent copy of its complete state, including
+partially lexed tokens.
 */
+#[derive(Clone)]
 pub struct Lexer {
 	state: State,
 	scratchpad: Vec<u8>,
@@ -546,6 +1058,16 @@ pub struct Lexer {
 	last_single_read: Option<u8>,
 	err: Option<Error>,
 	has_eof: bool,
+	/// Whether the data currently being lexed is known to be valid UTF-8.
+	valid_utf8_input: bool,
+	/// Whether the scratchpad or the swap may contain bytes which have not
+	/// been validated as UTF-8.
+	unvalidated_carry: bool,
+	/// Whether the attribute value being lexed contains a reference kept
+	/// for [`LexerOptions::lazy_attribute_values`].
+	unexpanded_references: bool,
+	/// Input recorded for [`LexerOptions::raw_capture_depth`].
+	capture: capture::RawCapture,
 }
 
 impl Lexer {
@@ -558,7 +1080,7 @@ impl Lexer {
 	pub fn with_options(opts: LexerOptions) -> Self {
 		Self {
 			state: State::Content(ContentState::Initial),
-			scratchpad: Vec::new(),
+			scratchpad: Vec::with_capacity(Self::initial_capacity(&opts)),
 			swap: Vec::new(),
 			ctr: 0,
 			last_token_end: 0,
@@ -569,6 +1091,10 @@ impl Lexer {
 			last_single_read: None,
 			err: None,
 			has_eof: false,
+			valid_utf8_input: false,
+			unvalidated_carry: false,
+			unexpanded_references: false,
+			capture: capture::RawCapture::default(),
 		}
 	}
 
@@ -593,9 +1119,15 @@ impl Lexer {
 		self.last_token_end = self.ctr.wrapping_sub(without);
 	}
 
+	fn initial_capacity(opts: &LexerOptions) -> usize {
+		opts.initial_buffer_capacity.min(opts.max_token_length)
+	}
+
 	#[inline]
 	fn prep_scratchpad(&mut self) {
-		if self.scratchpad.capacity() < self.opts.max_token_length {
+		if self.opts.buffer_growth == BufferGrowth::Eager
+			&& self.scratchpad.capacity() < self.opts.max_token_length
+		{
 			// unless there is a bug, we should never exceed the capacity requested by max_token_length, so we go with reserve_exact
 			self.scratchpad
 				.reserve_exact(self.opts.max_token_length - self.scratchpad.capacity())
@@ -646,6 +1178,35 @@ impl Lexer {
 		Ok(last_read)
 	}
 
+	/// Match as much of `literal` as the buffer holds in one step.
+	///
+	/// Returns the number of bytes of `literal` consumed and the first
+	/// mismatching byte, which is consumed, too. An empty buffer is treated
+	/// like in [`Self::read_single`].
+	fn read_literal(&mut self, r: &mut &[u8], literal: &[u8]) -> Result<(usize, Option<u8>)> {
+		if r.is_empty() {
+			return if self.has_eof {
+				Ok((0, None))
+			} else {
+				Err(Error::EndOfBuffer)
+			};
+		}
+		let nmatched = r
+			.iter()
+			.zip(literal.iter())
+			.take_while(|(a, b)| a == b)
+			.count();
+		let mismatch = if nmatched < literal.len() {
+			r.get(nmatched).copied()
+		} else {
+			None
+		};
+		let nconsumed = nmatched + mismatch.is_some() as usize;
+		*r = &r[nconsumed..];
+		self.ctr = self.ctr.wrapping_add(nconsumed);
+		Ok((nmatched, mismatch))
+	}
+
 	#[inline]
 	fn skip_matching<B: ByteSelect>(
 		&mut self,
@@ -667,17 +1228,34 @@ impl Lexer {
 
 	fn drop_scratchpad(&mut self) -> Result<()> {
 		self.scratchpad.clear();
+		self.maybe_shrink_buffers();
 		Ok(())
 	}
 
+	/// Release buffers which are empty and exceed the configured shrink
+	/// threshold.
+	#[inline]
+	fn maybe_shrink_buffers(&mut self) {
+		let threshold = match self.opts.buffer_shrink_threshold {
+			Some(v) => v,
+			None => return,
+		};
+		if self.scratchpad.is_empty() && self.scratchpad.capacity() > threshold {
+			self.scratchpad = Vec::with_capacity(Self::initial_capacity(&self.opts));
+		}
+		if self.swap.is_empty() && self.swap.capacity() > threshold {
+			self.swap = Vec::new();
+		}
+	}
+
 	fn swap_scratchpad(&mut self) -> Result<()> {
-		std::mem::swap(&mut self.scratchpad, &mut self.swap);
+		core::mem::swap(&mut self.scratchpad, &mut self.swap);
 		Ok(())
 	}
 
 	fn read_swap(&mut self) -> Vec<u8> {
 		let mut tmp = Vec::new();
-		std::mem::swap(&mut tmp, &mut self.swap);
+		core::mem::swap(&mut tmp, &mut self.swap);
 		tmp
 	}
 
@@ -691,48 +1269,89 @@ impl Lexer {
 		}
 	}
 
+	/// Return true if the contents of the scratchpad are known to be valid
+	/// UTF-8, up to a possibly incomplete sequence at the end.
+	#[inline]
+	fn scratchpad_is_valid_utf8(&self) -> bool {
+		self.valid_utf8_input && !self.unvalidated_carry
+	}
+
 	fn flush_scratchpad<U, T: FnOnce(&[u8]) -> Result<U>>(&mut self, conv: T) -> Result<U> {
 		let result = conv(&self.scratchpad);
 		self.scratchpad.clear();
+		if self.swap.is_empty() {
+			self.unvalidated_carry = false;
+		}
+		self.maybe_shrink_buffers();
 		result
 	}
 
+	#[cfg(feature = "nfc")]
+	fn check_nfc(enabled: bool, s: &str, what: &'static str) -> Result<()> {
+		if enabled && !unicode_normalization::is_nfc(s) {
+			return Err(Error::RestrictedXml(what));
+		}
+		Ok(())
+	}
+
+	#[cfg(not(feature = "nfc"))]
+	fn check_nfc(enabled: bool, _s: &str, _what: &'static str) -> Result<()> {
+		if enabled {
+			return Err(Error::RestrictedXml(
+				"Unicode normalization checks (requires the nfc feature)",
+			));
+		}
+		Ok(())
+	}
+
 	fn flush_scratchpad_as_name(&mut self) -> Result<Name> {
+		let require_nfc = self.opts.require_nfc_names;
+		let valid = self.scratchpad_is_valid_utf8();
 		self.flush_scratchpad(|bytes| -> Result<Name> {
-			let s = match std::str::from_utf8(bytes) {
-				Ok(s) => Ok(s),
-				Err(e) => Err(Error::utf8err(bytes, &e)),
-			}?;
+			// SAFETY: valid is only true if bytes has been passed as str
+			let s = unsafe { decode_utf8(bytes, valid) }?;
+			Self::check_nfc(require_nfc, s, "name not in NFC")?;
 			Ok(s.try_into()?)
 		})
 	}
 
 	fn flush_scratchpad_as_complete_cdata(&mut self) -> Result<CData> {
+		let require_nfc = self.opts.require_nfc_text;
+		let valid = self.scratchpad_is_valid_utf8();
 		self.flush_scratchpad(|bytes| -> Result<CData> {
-			let s = match std::str::from_utf8(bytes) {
-				Ok(s) => Ok(s),
-				Err(e) => Err(Error::utf8err(bytes, &e)),
-			}?;
+			// SAFETY: valid is only true if bytes has been passed as str
+			let s = unsafe { decode_utf8(bytes, valid) }?;
+			Self::check_nfc(require_nfc, s, "text not in NFC")?;
 			Ok(s.try_into()?)
 		})
 	}
 
 	fn flush_scratchpad_as_partial_cdata(&mut self) -> Result<CData> {
-		let s = match std::str::from_utf8(&self.scratchpad[..]) {
-			Ok(s) => s,
-			Err(e) => {
-				// TODO: this will need refinement...
-				let valid_up_to = e.valid_up_to();
-				if valid_up_to == 0 {
-					// this means that we actually and truly have a broken utf-8 sequence.
-					// return an error.
-					return Err(Error::InvalidUtf8Byte(self.scratchpad[0]));
-				} else {
-					// okay, we can return the stuff up to here and then let the next call deal with it
-					unsafe { std::str::from_utf8_unchecked(&self.scratchpad[..valid_up_to]) }
+		let s = if self.scratchpad_is_valid_utf8() {
+			// the token length limit may have split a sequence, so we
+			// cannot take the whole scratchpad
+			let valid_up_to = utf8_complete_prefix_len(&self.scratchpad[..]);
+			// SAFETY: the scratchpad has been passed as str, and we cut it at
+			// a sequence boundary
+			unsafe { core::str::from_utf8_unchecked(&self.scratchpad[..valid_up_to]) }
+		} else {
+			match core::str::from_utf8(&self.scratchpad[..]) {
+				Ok(s) => s,
+				Err(e) => {
+					// TODO: this will need refinement...
+					let valid_up_to = e.valid_up_to();
+					if valid_up_to == 0 {
+						// this means that we actually and truly have a broken utf-8 sequence.
+						// return an error.
+						return Err(Error::InvalidUtf8Byte(self.scratchpad[0]));
+					} else {
+						// okay, we can return the stuff up to here and then let the next call deal with it
+						unsafe { core::str::from_utf8_unchecked(&self.scratchpad[..valid_up_to]) }
+					}
 				}
 			}
 		};
+		Self::check_nfc(self.opts.require_nfc_text, s, "text not in NFC")?;
 		let result = s.try_into()?;
 		let to_drop = s.len();
 		drop(s);
@@ -745,19 +1364,32 @@ impl Lexer {
 			self.eat_whitespace_metrics(without);
 			Ok(None)
 		} else {
-			Ok(Some(Token::Text(
-				self.metrics(without),
-				self.flush_scratchpad_as_complete_cdata()?,
-			)))
+			let metrics = self.metrics(without);
+			let cdata = self.flush_scratchpad_as_complete_cdata()?;
+			Ok(Some(self.text_token(metrics, cdata)))
+		}
+	}
+
+	fn text_token(&self, metrics: TokenMetrics, cdata: CData) -> Token {
+		if self.opts.whitespace_tokens
+			&& cdata
+				.bytes()
+				.all(|b| b == b' ' || b == b'\t' || b == b'\n' || b == b'\r')
+		{
+			Token::Whitespace(metrics, cdata)
+		} else {
+			Token::Text(metrics, cdata)
 		}
 	}
 
 	fn flush_limited_scratchpad_as_text(&mut self) -> Result<Option<Token>> {
 		if self.scratchpad.len() >= self.opts.max_token_length {
-			Ok(Some(Token::Text(
-				self.metrics(0),
-				self.flush_scratchpad_as_partial_cdata()?,
-			)))
+			let cdata = self.flush_scratchpad_as_partial_cdata()?;
+			// an incomplete UTF-8 sequence may be left in the scratchpad,
+			// which belongs to the next token
+			let leftover = self.scratchpad.len();
+			let metrics = self.metrics(leftover);
+			Ok(Some(self.text_token(metrics, cdata)))
 		} else {
 			Ok(None)
 		}
@@ -771,18 +1403,18 @@ impl Lexer {
 	/// THIS DOES NOT MEAN THAT THE CHAR IS VALID! IT MAY STILL BE A NUL
 	/// BYTE OR SOMESUCH!
 	fn lex_posttext_char(&mut self, b: u8) -> Result<Option<ST>> {
-		match b {
-			b'<' => Ok(Some(ST(
+		match byte_kind(b) {
+			ByteKind::Lt => Ok(Some(ST(
 				State::Content(ContentState::MaybeElement(MaybeElementState::Initial)),
 				self.maybe_flush_scratchpad_as_text(1)?, // 1 == len("<")
 			))),
 			// begin of forbidden CDATA section end sequence (see XML 1.0 § 2.4 [14])
-			b']' => Ok(Some(ST(
+			ByteKind::RBracket => Ok(Some(ST(
 				State::Content(ContentState::MaybeCDataEnd(false, 1)),
 				// no flush here to avoid needless reallocations on false alarm
 				None,
 			))),
-			b'&' => {
+			ByteKind::Amp => {
 				// We need to be careful here! First, we *have* to swap the scratchpad because that is part of the contract with the Reference state.
 				// Second, we have to do this *after* we "maybe" flush the scratchpad as text -- otherwise, we would flush the empty text and then clobber the entity lookup.
 				let tok = self.maybe_flush_scratchpad_as_text(1)?; // 1 == len("&")
@@ -796,7 +1428,12 @@ impl Lexer {
 					tok,
 				)))
 			}
-			b'\r' => {
+			ByteKind::Cr if self.opts.preserve_line_endings => {
+				self.prep_scratchpad();
+				self.scratchpad.push(b'\r');
+				Ok(Some(ST(State::Content(ContentState::Initial), None)))
+			}
+			ByteKind::Cr => {
 				// CRLF needs to be folded to LF, and standalone LF needs, too
 				Ok(Some(ST(
 					State::Content(ContentState::MaybeCRLF(false)),
@@ -810,8 +1447,8 @@ impl Lexer {
 	fn lex_maybe_element(&mut self, state: MaybeElementState, r: &mut &[u8]) -> Result<ST> {
 		match state {
 			MaybeElementState::Initial => match self.read_single(r)? {
-				Some(byte) => match byte {
-					b'?' => {
+				Some(byte) => match byte_kind(byte) {
+					ByteKind::Question => {
 						self.drop_scratchpad()?;
 						Ok(ST(
 							State::Content(ContentState::MaybeElement(
@@ -820,7 +1457,7 @@ impl Lexer {
 							None,
 						))
 					}
-					b'!' => {
+					ByteKind::Bang => {
 						self.drop_scratchpad()?;
 						Ok(ST(
 							State::Content(ContentState::MaybeElement(
@@ -829,7 +1466,7 @@ impl Lexer {
 							None,
 						))
 					}
-					b'/' => {
+					ByteKind::Slash => {
 						self.drop_scratchpad()?;
 						Ok(ST(
 							State::Element {
@@ -839,26 +1476,25 @@ impl Lexer {
 							None,
 						))
 					}
-					byte => {
-						if maybe_name(byte) {
-							// add the first character to the scratchpad, because read_single does not do that
-							self.prep_scratchpad();
-							self.scratchpad.push(byte);
-							Ok(ST(
-								State::Element {
-									kind: ElementKind::Header,
-									state: ElementState::Start,
-								},
-								None,
-							))
-						} else {
-							self.drop_scratchpad()?;
-							Err(Error::Xml(XmlError::UnexpectedByte(
-								ERRCTX_NAMESTART,
-								byte,
-								None,
-							)))
-						}
+					ByteKind::Name => {
+						// add the first character to the scratchpad, because read_single does not do that
+						self.prep_scratchpad();
+						self.scratchpad.push(byte);
+						Ok(ST(
+							State::Element {
+								kind: ElementKind::Header,
+								state: ElementState::Start,
+							},
+							None,
+						))
+					}
+					_ => {
+						self.drop_scratchpad()?;
+						Err(Error::Xml(XmlError::UnexpectedByte(
+							ERRCTX_NAMESTART,
+							byte,
+							None,
+						)))
 					}
 				},
 				None => Err(Error::wfeof(ERRCTX_ELEMENT)),
@@ -866,11 +1502,14 @@ impl Lexer {
 			MaybeElementState::XMLDeclStart(i) => {
 				debug_assert!(i < TOK_XML_DECL_START.len());
 				// note: exploiting that xml decl only consists of ASCII here
-				let b = handle_eof(self.read_single(r)?, ERRCTX_CDATA_SECTION_START)?;
-				if b != TOK_XML_DECL_START[i] {
+				let (nmatched, mismatch) = self.read_literal(r, &TOK_XML_DECL_START[i..])?;
+				if mismatch.is_some() {
 					return Err(Error::RestrictedXml("processing instructions"));
 				}
-				let next = i + 1;
+				if nmatched == 0 {
+					return Err(Error::wfeof(ERRCTX_CDATA_SECTION_START));
+				}
+				let next = i + nmatched;
 				if next == TOK_XML_DECL_START.len() {
 					// eliminate the `xml` from the scratchpad
 					self.drop_scratchpad()?;
@@ -892,15 +1531,20 @@ impl Lexer {
 			}
 			MaybeElementState::CDataSectionStart(i) => {
 				debug_assert!(i < TOK_XML_CDATA_START.len());
-				let b = handle_eof(self.read_single(r)?, ERRCTX_XML_DECL_START)?;
-				if i == 1 && b == b'-' {
-					return Err(Error::RestrictedXml("comments"));
-				} else if b != TOK_XML_CDATA_START[i] {
-					return Err(Error::Xml(XmlError::InvalidSyntax(
-						"malformed cdata section start",
-					)));
+				let (nmatched, mismatch) = self.read_literal(r, &TOK_XML_CDATA_START[i..])?;
+				match mismatch {
+					Some(b'-') if i + nmatched == 2 => {
+						return Err(Error::RestrictedXml("comments"));
+					}
+					Some(_) => {
+						return Err(Error::Xml(XmlError::InvalidSyntax(
+							"malformed cdata section start",
+						)));
+					}
+					None if nmatched == 0 => return Err(Error::wfeof(ERRCTX_XML_DECL_START)),
+					None => (),
 				}
-				let next = i + 1;
+				let next = i + nmatched;
 				if next == TOK_XML_CDATA_START.len() {
 					self.drop_scratchpad()?;
 					Ok(ST(
@@ -1080,11 +1724,12 @@ impl Lexer {
 			// read until next `<` or `&`, which are the only things which
 			// can break us out of this state.
 			ContentState::Initial => {
-				match self.read_validated(r, &maybe_text, self.opts.max_token_length)? {
+				match self.read_validated(r, &MaybeText, self.opts.max_token_length)? {
 					Endbyte::Eof => Ok(ST(State::Eof, self.maybe_flush_scratchpad_as_text(0)?)),
+					// the limit may split a UTF-8 sequence, hence the partial flush
 					Endbyte::Limit => Ok(ST(
 						State::Content(ContentState::Initial),
-						self.maybe_flush_scratchpad_as_text(0)?,
+						self.flush_limited_scratchpad_as_text()?,
 					)),
 					Endbyte::Delimiter(b) => match self.lex_posttext_char(b)? {
 						Some(st) => Ok(st),
@@ -1098,11 +1743,11 @@ impl Lexer {
 				}
 			}
 			ContentState::CDataSection => {
-				match self.read_validated(r, &maybe_cdata_content, self.opts.max_token_length)? {
+				match self.read_validated(r, &MaybeCDataContent, self.opts.max_token_length)? {
 					Endbyte::Eof => Err(Error::wfeof(ERRCTX_CDATA_SECTION)),
 					Endbyte::Limit => Ok(ST(
 						State::Content(ContentState::CDataSection),
-						self.maybe_flush_scratchpad_as_text(0)?,
+						self.flush_limited_scratchpad_as_text()?,
 					)),
 					// -> transition into the "first delimiter found" state
 					Endbyte::Delimiter(b) => match b {
@@ -1110,6 +1755,11 @@ impl Lexer {
 							State::Content(ContentState::MaybeCDataEnd(true, 1)),
 							None,
 						)),
+						b'\r' if self.opts.preserve_line_endings => {
+							self.prep_scratchpad();
+							self.scratchpad.push(b'\r');
+							Ok(ST(State::Content(ContentState::CDataSection), None))
+						}
 						b'\r' => Ok(ST(State::Content(ContentState::MaybeCRLF(true)), None)),
 						_ => Err(Error::Xml(XmlError::InvalidChar(
 							ERRCTX_CDATA_SECTION,
@@ -1119,7 +1769,7 @@ impl Lexer {
 					},
 				}
 			}
-			ContentState::Whitespace => match self.skip_matching(r, &is_space) {
+			ContentState::Whitespace => match self.skip_matching(r, &Space) {
 				(_, Ok(Endbyte::Eof)) | (_, Ok(Endbyte::Limit)) => Ok(ST(State::Eof, None)),
 				(_, Ok(Endbyte::Delimiter(b))) => match b {
 					b'<' => Ok(ST(
@@ -1138,12 +1788,12 @@ impl Lexer {
 	}
 
 	fn lex_element_postblank(&mut self, kind: ElementKind, b: u8) -> Result<ElementState> {
-		match b {
-			b' ' | b'\t' | b'\r' | b'\n' => Ok(ElementState::Blank),
-			b'"' => Ok(ElementState::AttributeValue(b'"', false)),
-			b'\'' => Ok(ElementState::AttributeValue(b'\'', false)),
-			b'=' => Ok(ElementState::Eq),
-			b'>' => match kind {
+		match byte_kind(b) {
+			ByteKind::Space | ByteKind::Cr => Ok(ElementState::Blank),
+			ByteKind::Quot => Ok(ElementState::AttributeValue(b'"', false)),
+			ByteKind::Apos => Ok(ElementState::AttributeValue(b'\'', false)),
+			ByteKind::Eq => Ok(ElementState::Eq),
+			ByteKind::Gt => match kind {
 				ElementKind::Footer | ElementKind::Header => Ok(ElementState::Close),
 				ElementKind::XMLDecl => Err(Error::Xml(XmlError::UnexpectedChar(
 					ERRCTX_XML_DECL,
@@ -1151,7 +1801,7 @@ impl Lexer {
 					Some(&["?"]),
 				))),
 			},
-			b'?' => match kind {
+			ByteKind::Question => match kind {
 				ElementKind::XMLDecl => Ok(ElementState::MaybeXMLDeclEnd),
 				_ => Err(Error::Xml(XmlError::UnexpectedChar(
 					ERRCTX_ELEMENT,
@@ -1159,7 +1809,7 @@ impl Lexer {
 					None,
 				))),
 			},
-			b'/' => match kind {
+			ByteKind::Slash => match kind {
 				ElementKind::Header => Ok(ElementState::MaybeHeadClose),
 				ElementKind::Footer => Err(Error::Xml(XmlError::UnexpectedChar(
 					ERRCTX_ELEMENT_FOOT,
@@ -1172,7 +1822,7 @@ impl Lexer {
 					None,
 				))),
 			},
-			b if maybe_name(b) => {
+			ByteKind::Name => {
 				// write the char to scratchpad because it’ll be needed.
 				self.prep_scratchpad();
 				self.scratchpad.push(b);
@@ -1190,13 +1840,13 @@ impl Lexer {
 	}
 
 	fn lex_attval_next(&mut self, delim: u8, b: u8, element_kind: ElementKind) -> Result<ST> {
-		match b {
-			b'<' => Err(Error::Xml(XmlError::UnexpectedChar(
+		match byte_kind(b) {
+			ByteKind::Lt => Err(Error::Xml(XmlError::UnexpectedChar(
 				ERRCTX_ATTVAL,
 				'<',
 				None,
 			))),
-			b'&' => {
+			ByteKind::Amp => {
 				// must swap scratchpad here to avoid clobbering the
 				// attribute value during entity read
 				self.swap_scratchpad()?;
@@ -1209,7 +1859,20 @@ impl Lexer {
 					None,
 				))
 			}
-			b'\t' | b'\n' => {
+			// a plain space only gets here after a CR, but pushing a space
+			// for it is correct either way
+			ByteKind::Space => {
+				self.prep_scratchpad();
+				self.scratchpad.push(b' ');
+				Ok(ST(
+					State::Element {
+						kind: element_kind,
+						state: ElementState::AttributeValue(delim, false),
+					},
+					None,
+				))
+			}
+			ByteKind::Cr if self.opts.preserve_line_endings => {
 				self.prep_scratchpad();
 				self.scratchpad.push(b' ');
 				Ok(ST(
@@ -1220,36 +1883,54 @@ impl Lexer {
 					None,
 				))
 			}
-			b'\r' => Ok(ST(
+			ByteKind::Cr => Ok(ST(
 				State::Element {
 					kind: element_kind,
 					state: ElementState::AttributeValue(delim, true),
 				},
 				None,
 			)),
-			d if d == delim => Ok(ST(
-				State::Element {
-					kind: element_kind,
-					// require whitespace after attribute as the grammar demands
-					state: ElementState::SpaceRequired,
-				},
-				Some(Token::AttributeValue(
-					self.metrics(0),
-					self.flush_scratchpad_as_complete_cdata()?,
-				)),
-			)),
-			other => Err(Error::Xml(XmlError::InvalidChar(
+			ByteKind::Apos | ByteKind::Quot if b == delim => {
+				let metrics = self.metrics(0);
+				let value = self.flush_scratchpad_as_complete_cdata()?;
+				let token = if core::mem::replace(&mut self.unexpanded_references, false) {
+					Token::UnexpandedAttributeValue(metrics, value)
+				} else {
+					Token::AttributeValue(metrics, value)
+				};
+				Ok(ST(
+					State::Element {
+						kind: element_kind,
+						// require whitespace after attribute as the grammar demands
+						state: ElementState::SpaceRequired,
+					},
+					Some(token),
+				))
+			}
+			ByteKind::Nonchar => Err(Error::Xml(XmlError::InvalidChar(
 				ERRCTX_ATTVAL,
-				other as u32,
+				b as u32,
 				false,
 			))),
+			// only reachable after a CR, which is read byte-wise
+			_ => {
+				self.prep_scratchpad();
+				self.scratchpad.push(b);
+				Ok(ST(
+					State::Element {
+						kind: element_kind,
+						state: ElementState::AttributeValue(delim, false),
+					},
+					None,
+				))
+			}
 		}
 	}
 
 	fn lex_element(&mut self, kind: ElementKind, state: ElementState, r: &mut &[u8]) -> Result<ST> {
 		match state {
 			ElementState::Start | ElementState::Name => {
-				match self.read_validated(r, &maybe_name, self.opts.max_token_length)? {
+				match self.read_validated(r, &MaybeName, self.opts.max_token_length)? {
 					Endbyte::Eof => Err(Error::wfeof(ERRCTX_NAME)),
 					Endbyte::Limit => Err(Self::token_length_error()),
 					Endbyte::Delimiter(ch) => {
@@ -1275,7 +1956,7 @@ impl Lexer {
 				}
 			}
 			ElementState::SpaceRequired | ElementState::Blank => {
-				match self.skip_matching(r, &is_space) {
+				match self.skip_matching(r, &Space) {
 					(_, Ok(Endbyte::Eof)) | (_, Ok(Endbyte::Limit)) => {
 						Err(Error::wfeof(ERRCTX_ELEMENT))
 					}
@@ -1317,12 +1998,12 @@ impl Lexer {
 			}
 			// XML 1.0 §2.3 [10] AttValue
 			ElementState::AttributeValue(delim, false) => {
-				let selector = if delim == b'\'' {
-					&maybe_attval_apos as &dyn Fn(_) -> _
+				let selector: &dyn ByteSelect = if delim == b'\'' {
+					&MaybeAttvalApos
 				} else {
-					&maybe_attval_quot as &dyn Fn(_) -> _
+					&MaybeAttvalQuot
 				};
-				match self.read_validated(r, &selector, self.opts.max_token_length)? {
+				match self.read_validated(r, selector, self.opts.max_token_length)? {
 					Endbyte::Eof => Err(Error::wfeof(ERRCTX_ATTVAL)),
 					Endbyte::Limit => Err(Self::token_length_error()),
 					Endbyte::Delimiter(utf8ch) => self.lex_attval_next(delim, utf8ch, kind),
@@ -1342,8 +2023,14 @@ impl Lexer {
 						},
 						None,
 					))
+				} else if b == b'\n' {
+					// CRLF, which is folded into a single space
+					self.lex_attval_next(delim, b, kind)
 				} else {
-					// not another CR, so we can move on to the default handling
+					// a lone CR is a line ending of its own, so it needs its
+					// space before moving on to the default handling
+					self.prep_scratchpad();
+					self.scratchpad.push(b' ');
 					self.lex_attval_next(delim, b, kind)
 				}
 			}
@@ -1404,7 +2091,7 @@ impl Lexer {
 		r: &mut &[u8],
 	) -> Result<ST> {
 		let result = match kind {
-			RefKind::Entity => self.read_validated(r, &maybe_name, MAX_REFERENCE_LENGTH)?,
+			RefKind::Entity => self.read_validated(r, &MaybeName, MAX_REFERENCE_LENGTH)?,
 			RefKind::Char(CharRefRadix::Decimal) => {
 				self.read_validated(r, &is_decimal_digit, MAX_REFERENCE_LENGTH)?
 			}
@@ -1462,21 +2149,39 @@ impl Lexer {
 					self.swap_scratchpad()?;
 					// the entity reference is now in the swap (which we have to clear now, too)
 					let entity = self.read_swap();
+					let expanded_start = self.scratchpad.len();
 					match kind {
 						RefKind::Entity => {
 							let b = add_context(resolve_named_entity(&entity[..]), ctx)?;
 							self.scratchpad.push(b);
-							Ok(())
 						}
 						RefKind::Char(radix) => {
 							// this is safe because the bytes allowed by the digit byte ranges are all plain ascii
-							let entity = unsafe { std::str::from_utf8_unchecked(&entity[..]) };
-							Ok(add_context(
+							let entity = unsafe { core::str::from_utf8_unchecked(&entity[..]) };
+							add_context(
 								resolve_char_reference(entity, radix, &mut self.scratchpad),
 								ctx,
-							)?)
+							)?;
+						}
+					}
+					if self.opts.lazy_attribute_values
+						&& matches!(ret, RefReturnState::AttributeValue(ElementKind::Header, _))
+					{
+						// the reference is valid, now put it back as written
+						self.scratchpad.truncate(expanded_start);
+						self.scratchpad.push(b'&');
+						match kind {
+							RefKind::Entity => (),
+							RefKind::Char(CharRefRadix::Decimal) => self.scratchpad.push(b'#'),
+							RefKind::Char(CharRefRadix::Hexadecimal) => {
+								self.scratchpad.extend_from_slice(b"#x")
+							}
 						}
+						self.scratchpad.extend_from_slice(&entity);
+						self.scratchpad.push(b';');
+						self.unexpanded_references = true;
 					}
+					Ok(())
 				}
 				c => Err(c),
 			},
@@ -1577,14 +2282,109 @@ impl Lexer {
 		&mut self,
 		r: &mut T,
 		at_eof: bool,
+	) -> CrateResult<Option<Token>> {
+		// SAFETY: no chunk is claimed to be valid UTF-8
+		unsafe { self.lex_buffer_impl(r, at_eof, |_| false) }
+	}
+
+	/// Lex bytes from a [`BufferQueue`] until either an error occurs, a
+	/// valid token is produced or a valid end-of-file situation is
+	/// encountered.
+	///
+	/// This behaves like [`lex_buffer()`](Self::lex_buffer), except that
+	/// buffers which have been added to the queue as text (see
+	/// [`BufferQueue::push_str()`]) are not validated as UTF-8 again. All
+	/// other buffers are validated as usual.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::{BufferQueue, Lexer, Token};
+	///
+	/// let mut bq = BufferQueue::new();
+	/// bq.push_str("<a>Wörld</a>");
+	/// let mut lexer = Lexer::new();
+	/// let mut texts = Vec::new();
+	/// while let Some(tok) = lexer.lex_queue(&mut bq, true).unwrap() {
+	/// 	if let Token::Text(_, text) = tok {
+	/// 		texts.push(text);
+	/// 	}
+	/// }
+	/// assert_eq!(texts, vec!["Wörld"]);
+	/// ```
+	#[allow(deprecated)]
+	pub fn lex_queue(
+		&mut self,
+		r: &mut BufferQueue<'_>,
+		at_eof: bool,
+	) -> CrateResult<Option<Token>> {
+		// SAFETY: the queue only claims chunks which were pushed as str to
+		// be valid
+		unsafe { self.lex_buffer_impl(r, at_eof, BufferQueue::chunk_is_utf8) }
+	}
+
+	/// Lex bytes from a buffer which is known to contain valid UTF-8.
+	///
+	/// The UTF-8 validation is skipped for data passed through this
+	/// function. Data passed to the lexer through other functions is
+	/// still validated, even if it ends up in the same token.
+	///
+	/// # Safety
+	///
+	/// The bytes in `r` must be valid UTF-8, and if `r` is not consumed
+	/// completely, the rest must be passed to this function on the next
+	/// invocation.
+	#[cfg(all(test, feature = "std"))]
+	pub(crate) unsafe fn lex_utf8_buffer<T: bytes::Buf>(
+		&mut self,
+		r: &mut T,
+		at_eof: bool,
+	) -> CrateResult<Option<Token>> {
+		self.lex_buffer_impl(r, at_eof, |_| true)
+	}
+
+	/// Lex from `r`, skipping the UTF-8 validation for chunks for which
+	/// `is_utf8` returns true.
+	///
+	/// # Safety
+	///
+	/// If `is_utf8` returns true, the current chunk of `r` must be a suffix
+	/// of a valid UTF-8 string. Chunks which start in the middle of a UTF-8
+	/// sequence are detected and validated.
+	pub(crate) unsafe fn lex_buffer_impl<T: bytes::Buf, F: Fn(&T) -> bool>(
+		&mut self,
+		r: &mut T,
+		at_eof: bool,
+		is_utf8: F,
 	) -> CrateResult<Option<Token>> {
 		loop {
 			let mut chunk = r.chunk();
+			let data = chunk;
 			let prev_len = chunk.len();
+			// a chunk which starts with a continuation byte has been split
+			// from the preceding bytes and cannot be trusted on its own
+			let valid_utf8 = is_utf8(r) && chunk.first().map(|b| b & 0xc0 != 0x80).unwrap_or(true);
+			self.valid_utf8_input = valid_utf8;
 			// Only consider eof if the current chunk is truly the last one, which can be determined by checking that the chunk contains all remaining bytes.
 			self.has_eof = at_eof && prev_len == r.remaining();
 			let result = self.lex_bytes_raw(&mut chunk);
+			if !valid_utf8 {
+				// bytes from this buffer may have ended up in the
+				// scratchpad (or swap) and those must be validated, no
+				// matter where the rest of the token comes from
+				self.unvalidated_carry = !self.scratchpad.is_empty() || !self.swap.is_empty();
+			} else if utf8_complete_prefix_len(&self.scratchpad) != self.scratchpad.len() {
+				// the rest of the sequence may come from a different
+				// source, so the token has to be validated as a whole
+				self.unvalidated_carry = true;
+			}
 			let new_len = chunk.len();
+			if let Some(depth) = self.opts.raw_capture_depth {
+				self.capture.consumed(&data[..prev_len - new_len]);
+				if let Ok(Some(tok)) = &result {
+					self.capture.token(depth, tok);
+				}
+			}
 			r.advance(prev_len - new_len);
 			match result {
 				Err(Error::EndOfBuffer) => {
@@ -1654,8 +2454,37 @@ impl Lexer {
 	///
 	/// Returns `None` if a valid end of file is reached, a token if a valid
 	/// token is encountered or an error otherwise.
+	#[cfg(feature = "std")]
 	pub fn lex<R: io::BufRead + ?Sized>(&mut self, r: &mut R) -> CrateResult<Option<Token>> {
+		// SAFETY: no chunk is claimed to be valid UTF-8
+		unsafe { self.lex_reader_impl(r, |_| false) }
+	}
+
+	/// Like [`lex()`](Self::lex), but skipping the UTF-8 validation for
+	/// buffers which have been pushed to the queue as text.
+	#[cfg(feature = "std")]
+	#[allow(deprecated)]
+	pub(crate) fn lex_queue_reader(
+		&mut self,
+		r: &mut BufferQueue<'_>,
+	) -> CrateResult<Option<Token>> {
+		// SAFETY: the queue only claims chunks which were pushed as str to
+		// be valid
+		unsafe { self.lex_reader_impl(r, BufferQueue::chunk_is_utf8) }
+	}
+
+	/// # Safety
+	///
+	/// See [`lex_buffer_impl()`](Self::lex_buffer_impl); `is_utf8` refers
+	/// to the buffer returned by the next `fill_buf()` call.
+	#[cfg(feature = "std")]
+	unsafe fn lex_reader_impl<R: io::BufRead + ?Sized, F: Fn(&R) -> bool>(
+		&mut self,
+		r: &mut R,
+		is_utf8: F,
+	) -> CrateResult<Option<Token>> {
 		loop {
+			let valid_utf8 = is_utf8(r);
 			let (mut buf, eof): (&[u8], bool) = match r.fill_buf() {
 				Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
 					// if we have a wouldblock, we need to pretend we had an empty buffer, but without the eof flag
@@ -1667,7 +2496,7 @@ impl Lexer {
 				Ok(b) => (b, b.len() == 0),
 			};
 			let orig_len = buf.len();
-			let result = self.lex_buffer(&mut buf, eof);
+			let result = self.lex_buffer_impl(&mut buf, eof, |_| valid_utf8);
 			let new_len = buf.len();
 			assert!(new_len <= orig_len);
 			r.consume(orig_len - new_len);
@@ -1687,6 +2516,66 @@ impl Lexer {
 		}
 	}
 
+	/// Number of bytes consumed by the lexer so far.
+	///
+	/// After an error, this is the offset just behind the byte or token at
+	/// which the error was detected. It can be used together with
+	/// [`LocatedError`](crate::error::LocatedError) to point the user at the
+	/// problem.
+	///
+	/// The counter wraps around on overflow.
+	pub fn position(&self) -> usize {
+		self.ctr
+	}
+
+	/// Lex a run of character data at the start of `buf` without copying.
+	///
+	/// This only succeeds if the lexer is in element content with nothing
+	/// buffered, so that the text would otherwise be emitted as a single
+	/// [`Token::Text`] too (possibly split differently). The text ends
+	/// before the first delimiter, at the token length limit or at the end
+	/// of `buf`, whichever comes first; it is always cut at a character
+	/// boundary.
+	///
+	/// Returns the metrics of the text and the text itself, which has been
+	/// consumed by the lexer; the caller has to remove `text.len()` bytes
+	/// from the front of its buffer. If the fast path is not applicable,
+	/// for instance because the text is invalid, `None` is returned and
+	/// nothing is consumed, so that the regular lexing functions handle the
+	/// data (and report errors, if any).
+	pub(crate) fn lex_borrowed_text<'x>(
+		&mut self,
+		buf: &'x [u8],
+	) -> Option<(TokenMetrics, &'x CDataStr)> {
+		if self.err.is_some()
+			|| self.state != State::Content(ContentState::Initial)
+			|| !self.scratchpad.is_empty()
+			|| !self.swap.is_empty()
+		{
+			return None;
+		}
+		let window = &buf[..buf.len().min(self.opts.max_token_length)];
+		let len = match MaybeText.find_first_not(window) {
+			Some(n) => n,
+			// the window may end in the middle of a character
+			None => utf8_complete_prefix_len(window),
+		};
+		if len == 0 {
+			return None;
+		}
+		let text: &CDataStr = core::str::from_utf8(&window[..len]).ok()?.try_into().ok()?;
+		if Self::check_nfc(self.opts.require_nfc_text, text, "text not in NFC").is_err() {
+			return None;
+		}
+		self.ctr = self.ctr.wrapping_add(len);
+		let metrics = self.metrics(0);
+		if self.opts.raw_capture_depth.is_some() {
+			self.capture.consumed(&window[..len]);
+			self.capture.emitted(&metrics);
+		}
+		Some((metrics, text))
+	}
+
 	/// Release all temporary buffers
 	///
 	/// This is sensible to call when it is expected that no more data will be
@@ -1695,6 +2584,31 @@ impl Lexer {
 	pub fn release_temporaries(&mut self) {
 		self.scratchpad.shrink_to_fit();
 		self.swap.shrink_to_fit();
+		self.capture.release_temporaries();
+	}
+
+	/// Return the number of bytes allocated by the lexer for buffering
+	/// input, i.e. for tokens which are in progress and for raw element
+	/// capture.
+	pub fn memory_used(&self) -> usize {
+		self.scratchpad.capacity() + self.swap.capacity() + self.capture.memory_used()
+	}
+
+	/// Return the original bytes of the element which has been completed
+	/// last, if any.
+	///
+	/// Elements are only recorded if
+	/// [`LexerOptions::raw_capture_depth`] is set; only the elements at
+	/// that depth are recorded. The bytes are returned only once; if they
+	/// are not taken before the next element at that depth ends, they are
+	/// replaced.
+	///
+	/// The bytes are available as soon as the lexer has emitted the token
+	/// which ends the element, so when using a parser, they can be taken
+	/// after the corresponding [`EndElement`](crate::ResolvedEvent::EndElement)
+	/// event has been read.
+	pub fn take_raw_element(&mut self) -> Option<Vec<u8>> {
+		self.capture.take_completed()
 	}
 }
 
@@ -1710,7 +2624,7 @@ pub trait Sink {
 	fn token(&mut self, token: Token);
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
 	use super::*;
 	use crate::error::Error as CrateError;
@@ -1855,6 +2769,43 @@ mod tests {
 		assert_eq!(sink.dest.len(), 1);
 	}
 
+	#[test]
+	fn lexer_matches_split_literals() {
+		for doc in [
+			&b"<?xml version='1.0'?><a/>"[..],
+			&b"<a><![CDATA[x]]></a>"[..],
+		]
+		.iter()
+		{
+			let (expected, r) = lex(doc, 128);
+			r.unwrap();
+			for split in 1..doc.len() {
+				let (toks, r) = lex_chunked(&[&doc[..split], &doc[split..]], 128);
+				r.unwrap();
+				assert_eq!(toks, expected, "split at {}", split);
+			}
+		}
+	}
+
+	#[test]
+	fn lexer_rejects_split_comments_and_processing_instructions() {
+		for (doc, what) in [
+			(&b"<a><!-- x --></a>"[..], "comments"),
+			(&b"<?foo?>"[..], "processing instructions"),
+			(&b"<?xm?>"[..], "processing instructions"),
+		]
+		.iter()
+		{
+			for split in 1..doc.len() {
+				let (_, r) = lex_chunked(&[&doc[..split], &doc[split..]], 128);
+				match r {
+					Err(CrateError::RestrictedXml(v)) if v == *what => (),
+					other => panic!("unexpected result at split {}: {:?}", split, other),
+				}
+			}
+		}
+	}
+
 	#[test]
 	fn lexer_lex_xml_decl_version_name() {
 		let mut src = "<?xml version=".as_bytes();
@@ -2411,6 +3362,170 @@ mod tests {
 		assert_eq!(next.unwrap().metrics().start(), 62);
 	}
 
+	#[cfg(feature = "nfc")]
+	#[test]
+	fn lexer_rejects_non_nfc_names_if_requested() {
+		// "e" followed by U+0301 COMBINING ACUTE ACCENT, NFC would be U+00E9
+		let src = "<e\u{301}/>".as_bytes();
+		let mut buffered = io::BufReader::new(src);
+		let mut lexer = Lexer::with_options(LexerOptions::default().require_nfc_names(true));
+		let mut sink = VecSink::new(128);
+		let result = stream_to_sink_from_bytes(&mut lexer, &mut buffered, &mut sink);
+		assert!(matches!(result, Err(CrateError::RestrictedXml(_))));
+
+		let mut buffered = io::BufReader::new(src);
+		let mut lexer = Lexer::new();
+		let mut sink = VecSink::new(128);
+		let result = stream_to_sink_from_bytes(&mut lexer, &mut buffered, &mut sink);
+		assert!(result.is_ok());
+	}
+
+	#[cfg(feature = "nfc")]
+	#[test]
+	fn lexer_rejects_non_nfc_text_if_requested() {
+		let src = "<a b='e\u{301}'/>".as_bytes();
+		let mut buffered = io::BufReader::new(src);
+		let mut lexer = Lexer::with_options(LexerOptions::default().require_nfc_text(true));
+		let mut sink = VecSink::new(128);
+		let result = stream_to_sink_from_bytes(&mut lexer, &mut buffered, &mut sink);
+		assert!(matches!(result, Err(CrateError::RestrictedXml(_))));
+
+		let src = "<a>e\u{301}</a>".as_bytes();
+		let mut buffered = io::BufReader::new(src);
+		let mut lexer = Lexer::with_options(LexerOptions::default().require_nfc_text(true));
+		let mut sink = VecSink::new(128);
+		let result = stream_to_sink_from_bytes(&mut lexer, &mut buffered, &mut sink);
+		assert!(matches!(result, Err(CrateError::RestrictedXml(_))));
+	}
+
+	#[cfg(not(feature = "nfc"))]
+	#[test]
+	fn lexer_refuses_nfc_options_without_nfc_feature() {
+		for opts in [
+			LexerOptions::default().require_nfc_names(true),
+			LexerOptions::default().require_nfc_text(true),
+		]
+		.iter()
+		{
+			let mut buffered = io::BufReader::new(&b"<a b='c'>d</a>"[..]);
+			let mut lexer = Lexer::with_options(*opts);
+			let mut sink = VecSink::new(128);
+			let result = stream_to_sink_from_bytes(&mut lexer, &mut buffered, &mut sink);
+			assert!(matches!(result, Err(CrateError::RestrictedXml(_))));
+		}
+	}
+
+	#[cfg(feature = "nfc")]
+	#[test]
+	fn lexer_accepts_nfc_if_requested() {
+		let src = "<\u{e9} a='\u{e9}'>\u{e9}</\u{e9}>".as_bytes();
+		let mut buffered = io::BufReader::new(src);
+		let mut lexer = Lexer::with_options(
+			LexerOptions::default()
+				.require_nfc_names(true)
+				.require_nfc_text(true),
+		);
+		let mut sink = VecSink::new(128);
+		let result = stream_to_sink_from_bytes(&mut lexer, &mut buffered, &mut sink);
+		assert!(result.is_ok());
+	}
+
+	#[test]
+	fn lexer_lex_whitespace_as_text_by_default() {
+		let (toks, r) = lex(&b"<a> <b/> </a>"[..], 128);
+		r.unwrap();
+		assert_eq!(
+			toks[2],
+			Token::Text(TokenMetrics::new(3, 4), " ".try_into().unwrap())
+		);
+		assert!(!toks.iter().any(|tok| matches!(tok, Token::Whitespace(..))));
+	}
+
+	#[test]
+	fn lexer_lex_whitespace_tokens() {
+		let mut src = &b"<a>\r\n\t<b> x </b>  <c/>&#32;</a>\n"[..];
+		let mut lexer = Lexer::with_options(LexerOptions::default().whitespace_tokens(true));
+		let mut sink = VecSink::new(128);
+		stream_to_sink(&mut lexer, &mut src, &mut sink, true).unwrap();
+		let toks = sink.dest;
+		assert_eq!(
+			toks[2],
+			Token::Whitespace(TokenMetrics::new(3, 6), "\n\t".try_into().unwrap())
+		);
+		assert_eq!(
+			toks[5],
+			Token::Text(TokenMetrics::new(9, 12), " x ".try_into().unwrap())
+		);
+		assert_eq!(
+			toks[8],
+			Token::Whitespace(TokenMetrics::new(16, 18), "  ".try_into().unwrap())
+		);
+		// classified after expansion of references
+		assert_eq!(
+			toks[11],
+			Token::Whitespace(TokenMetrics::new(22, 27), " ".try_into().unwrap())
+		);
+		// whitespace after the root element
+		assert_eq!(
+			toks[14],
+			Token::Whitespace(TokenMetrics::new(31, 32), "\n".try_into().unwrap())
+		);
+		assert_eq!(toks.len(), 15);
+	}
+
+	#[test]
+	fn lexer_lex_split_whitespace_tokens() {
+		let mut src = &b"<a>      x</a>"[..];
+		let mut lexer = Lexer::with_options(
+			LexerOptions::default()
+				.max_token_length(4)
+				.whitespace_tokens(true),
+		);
+		let mut sink = VecSink::new(128);
+		stream_to_sink(&mut lexer, &mut src, &mut sink, true).unwrap();
+		// each chunk is classified on its own, even though the text node
+		// as a whole is not whitespace-only
+		assert_eq!(
+			sink.dest[2],
+			Token::Whitespace(TokenMetrics::new(3, 7), "    ".try_into().unwrap())
+		);
+		assert_eq!(
+			sink.dest[3],
+			Token::Text(TokenMetrics::new(7, 10), "  x".try_into().unwrap())
+		);
+		assert_eq!(sink.dest.len(), 6);
+	}
+
+	#[test]
+	fn lexer_lex_whitespace_tokens_around_cdata_sections_and_references() {
+		let mut src = &b"<a> <![CDATA[x]]> </a><b> &#32;x</b>"[..];
+		let mut lexer = Lexer::with_options(LexerOptions::default().whitespace_tokens(true));
+		let mut sink = VecSink::new(128);
+		stream_to_sink(&mut lexer, &mut src, &mut sink, true).unwrap();
+		let toks = sink.dest;
+		assert_eq!(
+			toks[2],
+			Token::Whitespace(TokenMetrics::new(3, 4), " ".try_into().unwrap())
+		);
+		assert_eq!(
+			toks[3],
+			Token::Text(TokenMetrics::new(4, 17), "x".try_into().unwrap())
+		);
+		assert_eq!(
+			toks[4],
+			Token::Whitespace(TokenMetrics::new(17, 18), " ".try_into().unwrap())
+		);
+		assert_eq!(
+			toks[9],
+			Token::Whitespace(TokenMetrics::new(25, 26), " ".try_into().unwrap())
+		);
+		assert_eq!(
+			toks[10],
+			Token::Text(TokenMetrics::new(26, 32), " x".try_into().unwrap())
+		);
+		assert_eq!(toks.len(), 13);
+	}
+
 	#[test]
 	fn lexer_lex_restrict_element_name_by_token_length() {
 		let src = &b"<foobar2342/>"[..];
@@ -3212,6 +4327,42 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn lexer_keeps_references_in_attributes_if_lazy() {
+		let mut src = &b"<a x='1 &lt; 2&#10;&#x3e;' y='plain'>&amp;</a>"[..];
+		let mut lexer = Lexer::with_options(LexerOptions::default().lazy_attribute_values(true));
+		let mut sink = VecSink::new(128);
+		stream_to_sink(&mut lexer, &mut src, &mut sink, true).unwrap();
+		let toks = sink.dest;
+		match &toks[3] {
+			Token::UnexpandedAttributeValue(_, cdata) => {
+				assert_eq!(cdata, "1 &lt; 2&#10;&#x3e;");
+				assert_eq!(expand_attribute_references(cdata), "1 < 2\n>");
+			}
+			other => panic!("unexpected token: {:?}", other),
+		}
+		match &toks[6] {
+			Token::AttributeValue(_, cdata) => assert_eq!(cdata, "plain"),
+			other => panic!("unexpected token: {:?}", other),
+		}
+		// text is expanded as usual
+		match &toks[8] {
+			Token::Text(_, cdata) => assert_eq!(cdata, "&"),
+			other => panic!("unexpected token: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn lexer_checks_references_in_attributes_if_lazy() {
+		for doc in [&b"<a x='&#0;'/>"[..], &b"<a x='&foo;'/>"[..]].iter() {
+			let mut src = *doc;
+			let mut lexer =
+				Lexer::with_options(LexerOptions::default().lazy_attribute_values(true));
+			let mut sink = VecSink::new(128);
+			assert!(stream_to_sink(&mut lexer, &mut src, &mut sink, true).is_err());
+		}
+	}
+
 	#[test]
 	fn lexer_handles_crlf_in_attribute() {
 		// XML 1.0 § 3.3.3
@@ -3230,6 +4381,120 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn lexer_handles_lone_cr_in_attribute() {
+		// XML 1.0 § 2.11 and § 3.3.3
+		for (src, expected) in [
+			(&b"<a x='\r'/>"[..], " "),
+			(&b"<a x='a\rb'/>"[..], "a b"),
+			(&b"<a x='a\r'/>"[..], "a "),
+			(&b"<a x=\"a\r\"/>"[..], "a "),
+			(&b"<a x='a\r&amp;'/>"[..], "a &"),
+			(&b"<a x='a\r\tb'/>"[..], "a  b"),
+			(&b"<a x='\r\xc3\xa4'/>"[..], " \u{e4}"),
+		] {
+			let (toks, r) = lex(src, 128);
+			r.unwrap();
+			match &toks[3] {
+				Token::AttributeValue(_, cdata) => {
+					assert_eq!(cdata, expected);
+				}
+				other => panic!("unexpected token: {:?}", other),
+			}
+		}
+	}
+
+	#[test]
+	fn lexer_handles_cr_in_attribute_across_chunks() {
+		let (toks, r) = lex_chunked(&[&b"<a x='a\r"[..], &b"b\r"[..], &b"\nc'/>"[..]], 128);
+		r.unwrap();
+		match &toks[3] {
+			Token::AttributeValue(_, cdata) => {
+				assert_eq!(cdata, "a b c");
+			}
+			other => panic!("unexpected token: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn lexer_rejects_nonchar_after_cr_in_attribute() {
+		let err = lex_err(b"<a x='\r\x01'/>", 128).unwrap();
+		match err {
+			CrateError::Xml(XmlError::InvalidChar(_, 1, false)) => (),
+			other => panic!("unexpected error: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn lexer_preserves_line_endings_if_requested() {
+		let mut lexer = Lexer::with_options(LexerOptions::default().preserve_line_endings(true));
+		let mut buf = &b"<a x='\r\n'>a\r\nb\rc<![CDATA[\r\n\r]]></a>"[..];
+		let mut attrs = Vec::new();
+		let mut texts = Vec::new();
+		while let Some(tok) = lexer.lex_buffer(&mut buf, true).unwrap() {
+			match tok {
+				Token::AttributeValue(_, v) => attrs.push(v),
+				Token::Text(_, v) => texts.push(v),
+				_ => (),
+			}
+		}
+		// attribute value normalization still applies to each character
+		assert_eq!(attrs, &["  "]);
+		assert_eq!(texts.concat(), "a\r\nb\rc\r\n\r");
+	}
+
+	fn capture_raw(chunks: &[&[u8]], depth: usize) -> Vec<Vec<u8>> {
+		let mut lexer = Lexer::with_options(LexerOptions::default().raw_capture_depth(Some(depth)));
+		let mut captured = Vec::new();
+		for (i, chunk) in chunks.iter().enumerate() {
+			let mut chunk = *chunk;
+			let at_eof = i == chunks.len() - 1;
+			loop {
+				match lexer.lex_buffer(&mut chunk, at_eof) {
+					Ok(Some(_)) => captured.extend(lexer.take_raw_element()),
+					Ok(None) => break,
+					Err(CrateError::IO(e)) if e.kind() == io::ErrorKind::WouldBlock => break,
+					Err(e) => panic!("unexpected error: {:?}", e),
+				}
+			}
+		}
+		captured
+	}
+
+	#[test]
+	fn lexer_captures_raw_elements() {
+		let doc = &b"<?xml version='1.0'?>\n<r><a x = \"&lt;\">t&amp;<![CDATA[<]]>\r\n<b/></a>text<c\n/><a></a ></r>"[..];
+		assert_eq!(
+			capture_raw(&[doc], 1),
+			[
+				&b"<a x = \"&lt;\">t&amp;<![CDATA[<]]>\r\n<b/></a>"[..],
+				&b"<c\n/>"[..],
+				&b"<a></a >"[..],
+			]
+		);
+		assert_eq!(capture_raw(&[doc], 2), [&b"<b/>"[..]]);
+		assert_eq!(capture_raw(&[doc], 0), [&doc[22..]]);
+	}
+
+	#[test]
+	fn lexer_captures_raw_elements_across_chunks() {
+		let doc = &b"<r>x<a y='1'>f\xc3\xb6o</a> <b/></r>"[..];
+		let chunks: Vec<&[u8]> = doc.chunks(1).collect();
+		assert_eq!(
+			capture_raw(&chunks, 1),
+			[&b"<a y='1'>f\xc3\xb6o</a>"[..], &b"<b/>"[..]]
+		);
+	}
+
+	#[test]
+	fn lexer_does_not_capture_by_default() {
+		let mut lexer = Lexer::new();
+		let mut buf = &b"<a><b/></a>"[..];
+		while let Some(_) = lexer.lex_buffer(&mut buf, true).unwrap() {
+			assert!(lexer.take_raw_element().is_none());
+		}
+	}
+
 	#[test]
 	fn lexer_preserves_whitespace_inserted_via_charrefs_in_attributes() {
 		// XML 1.0 § 3.3.3
@@ -3345,6 +4610,149 @@ mod tests {
 		};
 	}
 
+	#[test]
+	fn utf8_complete_prefix_len_cuts_incomplete_sequences() {
+		assert_eq!(utf8_complete_prefix_len(b""), 0);
+		assert_eq!(utf8_complete_prefix_len(b"abc"), 3);
+		assert_eq!(utf8_complete_prefix_len("aä".as_bytes()), 3);
+		assert_eq!(utf8_complete_prefix_len(&"aä".as_bytes()[..2]), 1);
+		assert_eq!(utf8_complete_prefix_len(&"a€".as_bytes()[..3]), 1);
+		assert_eq!(utf8_complete_prefix_len("a€".as_bytes()), 4);
+		assert_eq!(utf8_complete_prefix_len(&"a🎉".as_bytes()[..4]), 1);
+		assert_eq!(utf8_complete_prefix_len("a🎉".as_bytes()), 5);
+	}
+
+	#[test]
+	fn lexer_splits_valid_utf8_input_at_sequence_boundaries() {
+		let mut lexer = Lexer::with_options(LexerOptions::default().max_token_length(4));
+		let mut buf = "<a>äöü€🎉</a>".as_bytes();
+		let mut text = String::new();
+		loop {
+			match unsafe { lexer.lex_utf8_buffer(&mut buf, true) } {
+				Ok(Some(Token::Text(_, cdata))) => {
+					assert!(cdata.len() <= 4);
+					text.push_str(&cdata);
+				}
+				Ok(Some(_)) => (),
+				Ok(None) => break,
+				other => panic!("unexpected result: {:?}", other),
+			}
+		}
+		assert_eq!(text, "äöü€🎉");
+	}
+
+	#[test]
+	fn lexer_splits_long_text_at_utf8_sequence_boundaries() {
+		let mut lexer = Lexer::with_options(LexerOptions::default().max_token_length(4));
+		let mut buf = "<a>äöü€🎉<![CDATA[äöü€🎉]]></a>".as_bytes();
+		let mut texts = Vec::new();
+		loop {
+			match lexer.lex_buffer(&mut buf, true) {
+				Ok(Some(Token::Text(tm, cdata))) => {
+					if texts.len() < 4 {
+						// metrics of the CDATA section include the markup
+						assert_eq!(tm.len(), cdata.len());
+					}
+					texts.push(cdata);
+				}
+				Ok(Some(_)) => (),
+				Ok(None) => break,
+				other => panic!("unexpected result: {:?}", other),
+			}
+		}
+		assert_eq!(texts.len(), 8);
+		assert_eq!(texts[..4].concat(), "äöü€🎉");
+		assert_eq!(texts[4..].concat(), "äöü€🎉");
+	}
+
+	#[test]
+	fn lexer_validates_bytes_carried_into_valid_utf8_input() {
+		let mut lexer = Lexer::new();
+		let mut buf = &b"<a>\xc3"[..];
+		match lexer.lex_buffer(&mut buf, false) {
+			Ok(Some(Token::ElementHeadStart(..))) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match lexer.lex_buffer(&mut buf, false) {
+			Ok(Some(Token::ElementHFEnd(..))) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match lexer.lex_buffer(&mut buf, false) {
+			Err(CrateError::IO(ioerr)) if ioerr.kind() == io::ErrorKind::WouldBlock => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		let mut buf = "(</a>".as_bytes();
+		match unsafe { lexer.lex_utf8_buffer(&mut buf, false) } {
+			Err(CrateError::InvalidUtf8Byte(0xc3)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
+	#[test]
+	#[allow(deprecated)]
+	fn lexer_validates_text_chunk_starting_inside_sequence() {
+		let mut lexer = Lexer::new();
+		let mut buf = &b"<a>"[..];
+		for _ in 0..2 {
+			lexer.lex_buffer(&mut buf, false).unwrap();
+		}
+		let mut bq = BufferQueue::new();
+		bq.push_str("\u{e4}</a>");
+		bytes::Buf::advance(&mut bq, 1);
+		match lexer.lex_queue(&mut bq, true) {
+			Err(CrateError::InvalidUtf8Byte(0xa4)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
+	#[test]
+	#[allow(deprecated)]
+	fn lexer_validates_sequences_completed_by_another_queue() {
+		let mut lexer = Lexer::with_options(LexerOptions::default().max_token_length(4));
+		let mut first = BufferQueue::new();
+		first.push_str("<a>abc\u{e4}</a>");
+		let mut texts = Vec::new();
+		loop {
+			match lexer.lex_queue(&mut first, false) {
+				Ok(Some(Token::Text(_, text))) => {
+					texts.push(text);
+					break;
+				}
+				Ok(Some(_)) => (),
+				other => panic!("unexpected result: {:?}", other),
+			}
+		}
+		assert_eq!(texts, vec!["abc"]);
+		// the lexer now holds the first byte of the \u{e4}
+		let mut second = BufferQueue::new();
+		second.push_str("(</a>");
+		match lexer.lex_queue(&mut second, true) {
+			Err(CrateError::InvalidUtf8Byte(0xc3)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
+	#[test]
+	#[allow(deprecated)]
+	fn lexer_queue_validates_byte_chunks_between_text() {
+		let mut bq = BufferQueue::new();
+		bq.push_str("<a>");
+		bq.push(&b"\xff"[..]);
+		bq.push_str("</a>");
+		let mut lexer = Lexer::new();
+		let mut result = Ok(None);
+		for _ in 0..4 {
+			result = lexer.lex_queue(&mut bq, true);
+			if result.is_err() {
+				break;
+			}
+		}
+		match result {
+			Err(CrateError::InvalidUtf8Byte(0xff)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
 	#[test]
 	fn lexer_detect_eof_in_name() {
 		let err = lex_err(b"<aa", 128).unwrap();
@@ -3446,4 +4854,66 @@ mod tests {
 			other => panic!("unexpected lex result: {:?}", other),
 		}
 	}
+
+	#[test]
+	fn lexer_on_demand_growth_allocates_only_what_is_needed() {
+		let mut lexer = Lexer::with_options(
+			LexerOptions::default()
+				.max_token_length(4096)
+				.buffer_growth(BufferGrowth::OnDemand),
+		);
+		let mut sink = VecSink::new(128);
+		stream_to_sink(&mut lexer, &mut &b"<a>hello</a>"[..], &mut sink, true).unwrap();
+		assert!(lexer.scratchpad.capacity() < 4096);
+		match &sink.dest[2] {
+			Token::Text(_, t) => assert_eq!(t, "hello"),
+			other => panic!("unexpected token: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn lexer_eager_growth_reserves_max_token_length() {
+		let mut lexer = Lexer::with_options(LexerOptions::default().max_token_length(4096));
+		let mut sink = VecSink::new(128);
+		stream_to_sink(&mut lexer, &mut &b"<a>hello</a>"[..], &mut sink, true).unwrap();
+		assert!(lexer.scratchpad.capacity() >= 4096);
+	}
+
+	#[test]
+	fn lexer_initial_buffer_capacity_is_capped() {
+		let lexer = Lexer::with_options(
+			LexerOptions::default()
+				.max_token_length(256)
+				.initial_buffer_capacity(100),
+		);
+		assert!(lexer.scratchpad.capacity() >= 100);
+		let lexer = Lexer::with_options(
+			LexerOptions::default()
+				.max_token_length(256)
+				.initial_buffer_capacity(1 << 20),
+		);
+		assert!(lexer.scratchpad.capacity() < 1 << 20);
+	}
+
+	#[test]
+	fn lexer_shrinks_buffers_above_threshold() {
+		let mut lexer = Lexer::with_options(
+			LexerOptions::default()
+				.initial_buffer_capacity(16)
+				.buffer_growth(BufferGrowth::OnDemand)
+				.buffer_shrink_threshold(Some(64)),
+		);
+		let mut doc = Vec::new();
+		doc.extend_from_slice(b"<a x='");
+		doc.resize(doc.len() + 1000, b'y');
+		doc.extend_from_slice(b"'>");
+		let mut sink = VecSink::new(128);
+		stream_to_sink(&mut lexer, &mut &doc[..], &mut sink, false).ok();
+		match &sink.dest[3] {
+			Token::AttributeValue(_, v) => assert_eq!(v.len(), 1000),
+			other => panic!("unexpected token: {:?}", other),
+		}
+		assert!(lexer.scratchpad.capacity() <= 64);
+		assert!(lexer.swap.capacity() <= 64);
+	}
 }
@@ -3,10 +3,218 @@ use rxml_validation::selectors::{CodepointRange, CodepointRanges};
 
 pub trait ByteSelect {
 	fn select(&self, b: u8) -> bool;
+
+	/// Return the index of the first byte in `src` which is not selected.
+	///
+	/// Implementations may override this to scan in bulk.
+	fn find_first_not(&self, src: &[u8]) -> Option<usize> {
+		src.iter().position(|&b| !self.select(b))
+	}
+}
+
+/// Bytes which end character data (see [`is_text_delimiter`]).
+const CLASS_TEXT_DELIMITER: u8 = 1 << 0;
+/// Bytes which end CDATA section content (see
+/// [`is_cdata_content_delimiter`]).
+const CLASS_CDATA_CONTENT_DELIMITER: u8 = 1 << 1;
+/// Bytes which end `'`-delimited attribute values.
+const CLASS_ATTVAL_APOS_DELIMITER: u8 = 1 << 2;
+/// Bytes which end `"`-delimited attribute values.
+const CLASS_ATTVAL_QUOT_DELIMITER: u8 = 1 << 3;
+/// Bytes which end names.
+const CLASS_NAME_DELIMITER: u8 = 1 << 4;
+/// XML whitespace.
+const CLASS_SPACE: u8 = 1 << 5;
+/// Bytes which never occur in valid XML 1.0 documents.
+const CLASS_NONCHAR: u8 = 1 << 6;
+
+/// Class bits of each byte value.
+///
+/// The lexer spends most of its time deciding whether a byte ends the
+/// current token. Looking the byte up in this table replaces the chains of
+/// comparisons of the individual predicates with a single load and mask.
+static BYTE_CLASSES: [u8; 256] = build_byte_classes();
+
+const fn build_byte_classes() -> [u8; 256] {
+	let mut table = [0u8; 256];
+	let mut i = 0;
+	while i < 256 {
+		let b = i as u8;
+		let mut class = 0;
+		if is_text_delimiter(b) {
+			class |= CLASS_TEXT_DELIMITER;
+		}
+		if is_cdata_content_delimiter(b) {
+			class |= CLASS_CDATA_CONTENT_DELIMITER;
+		}
+		if is_attval_apos_delimiter(b) {
+			class |= CLASS_ATTVAL_APOS_DELIMITER;
+		}
+		if is_attval_quot_delimiter(b) {
+			class |= CLASS_ATTVAL_QUOT_DELIMITER;
+		}
+		if is_name_delimiter(b) {
+			class |= CLASS_NAME_DELIMITER;
+		}
+		if b == b' ' || b == b'\n' || b == b'\t' || b == b'\r' {
+			class |= CLASS_SPACE;
+		}
+		if b <= 0x08 || b == 0x0b || b == 0x0c || (b >= 0x0e && b <= 0x1f) {
+			class |= CLASS_NONCHAR;
+		}
+		table[i] = class;
+		i += 1;
+	}
+	table
+}
+
+#[inline(always)]
+fn has_class(b: u8, class: u8) -> bool {
+	BYTE_CLASSES[b as usize] & class != 0
+}
+
+/// Role of a byte in the transitions of the lexer state machine.
+///
+/// Wherever the lexer has to decide on a single byte what to do next, it
+/// matches on the kind of the byte instead of comparing it against each
+/// special byte in turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ByteKind {
+	/// XML whitespace except `\r`
+	Space,
+	/// `\r`, which needs line ending normalization
+	Cr,
+	/// `<`
+	Lt,
+	/// `>`
+	Gt,
+	/// `&`
+	Amp,
+	/// `'`
+	Apos,
+	/// `"`
+	Quot,
+	/// `=`
+	Eq,
+	/// `/`
+	Slash,
+	/// `?`
+	Question,
+	/// `!`
+	Bang,
+	/// `]`
+	RBracket,
+	/// Bytes which may occur in names (see [`maybe_name`])
+	Name,
+	/// Bytes which never occur in valid XML 1.0 documents
+	Nonchar,
+	/// Any other byte
+	Other,
+}
+
+/// Kind of each byte value.
+static BYTE_KINDS: [ByteKind; 256] = build_byte_kinds();
+
+const fn build_byte_kinds() -> [ByteKind; 256] {
+	let mut table = [ByteKind::Other; 256];
+	let mut i = 0;
+	while i < 256 {
+		let b = i as u8;
+		table[i] = match b {
+			b' ' | b'\t' | b'\n' => ByteKind::Space,
+			b'\r' => ByteKind::Cr,
+			b'<' => ByteKind::Lt,
+			b'>' => ByteKind::Gt,
+			b'&' => ByteKind::Amp,
+			b'\'' => ByteKind::Apos,
+			b'"' => ByteKind::Quot,
+			b'=' => ByteKind::Eq,
+			b'/' => ByteKind::Slash,
+			b'?' => ByteKind::Question,
+			b'!' => ByteKind::Bang,
+			b']' => ByteKind::RBracket,
+			_ if !is_name_delimiter(b) => ByteKind::Name,
+			_ if b < 0x20 => ByteKind::Nonchar,
+			_ => ByteKind::Other,
+		};
+		i += 1;
+	}
+	table
+}
+
+/// Return the [`ByteKind`] of a byte.
+#[inline(always)]
+pub(crate) fn byte_kind(b: u8) -> ByteKind {
+	BYTE_KINDS[b as usize]
 }
 
+/// Find the first byte in `src` which has any of the bits of `class` set.
+///
+/// Four bytes are classified per iteration and only a single branch is
+/// taken for them, unless one of them is a match.
+fn find_first_of_class(src: &[u8], class: u8) -> Option<usize> {
+	let mut chunks = src.chunks_exact(4);
+	let mut offset = 0;
+	for chunk in &mut chunks {
+		let merged = BYTE_CLASSES[chunk[0] as usize]
+			| BYTE_CLASSES[chunk[1] as usize]
+			| BYTE_CLASSES[chunk[2] as usize]
+			| BYTE_CLASSES[chunk[3] as usize];
+		if merged & class != 0 {
+			return chunk
+				.iter()
+				.position(|&b| has_class(b, class))
+				.map(|i| offset + i);
+		}
+		offset += 4;
+	}
+	chunks
+		.remainder()
+		.iter()
+		.position(|&b| has_class(b, class))
+		.map(|i| offset + i)
+}
+
+/// Find the first byte which is rejected by `reject`, assuming that only
+/// bytes below 0x20 are ever rejected.
+///
+/// Blocks are first checked for any such byte without exiting early, which
+/// allows the compiler to vectorize the check; only blocks which contain a
+/// candidate are inspected in detail.
+#[cfg(feature = "simd")]
+fn find_control<F: Fn(u8) -> bool>(src: &[u8], reject: F) -> Option<usize> {
+	const BLOCK_SIZE: usize = 16;
+	let mut offset = 0;
+	for block in src.chunks(BLOCK_SIZE) {
+		if block.iter().fold(false, |acc, &b| acc | (b < 0x20)) {
+			if let Some(i) = block.iter().position(|&b| reject(b)) {
+				return Some(offset + i);
+			}
+		}
+		offset += block.len();
+	}
+	None
+}
+
+/// Find the first delimiter in `src`, given the position of the first
+/// structural delimiter and a predicate for delimiting control characters.
+#[cfg(feature = "simd")]
+fn find_delimiter<F: Fn(u8) -> bool>(
+	src: &[u8],
+	structural: Option<usize>,
+	reject_control: F,
+) -> Option<usize> {
+	let prefix = &src[..structural.unwrap_or(src.len())];
+	match find_control(prefix, reject_control) {
+		Some(i) => Some(i),
+		None => structural,
+	}
+}
+
+#[cfg(test)]
 pub(crate) struct AnyByte();
 
+#[cfg(test)]
 impl ByteSelect for AnyByte {
 	fn select(&self, _b: u8) -> bool {
 		true
@@ -26,12 +234,28 @@ impl<T: Fn(u8) -> bool> ByteSelect for T {
 }
 
 /// XML whitespace
+#[inline]
 pub fn is_space(b: u8) -> bool {
-	b == b' ' || b == b'\n' || b == b'\t' || b == b'\r'
+	has_class(b, CLASS_SPACE)
+}
+
+/// Selector for XML whitespace.
+pub struct Space;
+
+impl ByteSelect for Space {
+	#[inline]
+	fn select(&self, b: u8) -> bool {
+		is_space(b)
+	}
+
+	fn find_first_not(&self, src: &[u8]) -> Option<usize> {
+		// runs of whitespace are usually short, so no batching here
+		src.iter().position(|&b| !is_space(b))
+	}
 }
 
 /// Bytes not valid for XML character data (XML 1.0 § 2.4 [14])
-fn is_text_delimiter(b: u8) -> bool {
+const fn is_text_delimiter(b: u8) -> bool {
 	// - `'\r'`, because that gets folded into a line feed (`\n`) on input
 	// - `'&'`, because that may start an entity or character reference
 	// - `'<'`, because that may start an element or CDATA section
@@ -40,20 +264,56 @@ fn is_text_delimiter(b: u8) -> bool {
 	b == b'\r' || b == b'&' || b == b'<' || b == b']' || b < 0x09 || (b > 0x0a && b < 0x20)
 }
 
-pub fn maybe_text(b: u8) -> bool {
-	!is_text_delimiter(b)
+/// Selector for character data (see [`is_text_delimiter`]).
+pub struct MaybeText;
+
+impl ByteSelect for MaybeText {
+	#[inline]
+	fn select(&self, b: u8) -> bool {
+		!has_class(b, CLASS_TEXT_DELIMITER)
+	}
+
+	#[cfg(feature = "simd")]
+	fn find_first_not(&self, src: &[u8]) -> Option<usize> {
+		find_delimiter(src, memchr::memchr3(b'<', b'&', b']', src), |b| {
+			b < 0x20 && b != b'\t' && b != b'\n'
+		})
+	}
+
+	#[cfg(not(feature = "simd"))]
+	fn find_first_not(&self, src: &[u8]) -> Option<usize> {
+		find_first_of_class(src, CLASS_TEXT_DELIMITER)
+	}
 }
 
 // XML 1.0 § 2.4 [14]
-fn is_cdata_content_delimiter(b: u8) -> bool {
+const fn is_cdata_content_delimiter(b: u8) -> bool {
 	b == b'\r' || b == b']' || b < 0x09 || (b > 0x0a && b < 0x20)
 }
 
-pub fn maybe_cdata_content(b: u8) -> bool {
-	!is_cdata_content_delimiter(b)
+/// Selector for CDATA section contents (see [`is_cdata_content_delimiter`]).
+pub struct MaybeCDataContent;
+
+impl ByteSelect for MaybeCDataContent {
+	#[inline]
+	fn select(&self, b: u8) -> bool {
+		!has_class(b, CLASS_CDATA_CONTENT_DELIMITER)
+	}
+
+	#[cfg(feature = "simd")]
+	fn find_first_not(&self, src: &[u8]) -> Option<usize> {
+		find_delimiter(src, memchr::memchr(b']', src), |b| {
+			b < 0x20 && b != b'\t' && b != b'\n'
+		})
+	}
+
+	#[cfg(not(feature = "simd"))]
+	fn find_first_not(&self, src: &[u8]) -> Option<usize> {
+		find_first_of_class(src, CLASS_CDATA_CONTENT_DELIMITER)
+	}
 }
 
-fn is_name_delimiter(b: u8) -> bool {
+const fn is_name_delimiter(b: u8) -> bool {
 	if b == b':' || b == b'-' || b == b'.' || b == b'_' {
 		return false;
 	}
@@ -72,32 +332,80 @@ fn is_name_delimiter(b: u8) -> bool {
 	true
 }
 
+#[inline]
 pub fn maybe_name(b: u8) -> bool {
-	!is_name_delimiter(b)
+	!has_class(b, CLASS_NAME_DELIMITER)
+}
+
+/// Selector for names (see [`maybe_name`]).
+pub struct MaybeName;
+
+impl ByteSelect for MaybeName {
+	#[inline]
+	fn select(&self, b: u8) -> bool {
+		maybe_name(b)
+	}
+
+	fn find_first_not(&self, src: &[u8]) -> Option<usize> {
+		find_first_of_class(src, CLASS_NAME_DELIMITER)
+	}
 }
 
 // XML 1.0 § 2.3 [10]
-fn is_attval_apos_delimiter(b: u8) -> bool {
+const fn is_attval_apos_delimiter(b: u8) -> bool {
 	// exclude all whitespace except normal space because those get converted into spaces
 	b < 0x20 || b == b'&' || b == b'\'' || b == b'<'
 }
 
-pub fn maybe_attval_apos(b: u8) -> bool {
-	!is_attval_apos_delimiter(b)
+/// Selector for attribute values delimited by `'`.
+pub struct MaybeAttvalApos;
+
+impl ByteSelect for MaybeAttvalApos {
+	#[inline]
+	fn select(&self, b: u8) -> bool {
+		!has_class(b, CLASS_ATTVAL_APOS_DELIMITER)
+	}
+
+	#[cfg(feature = "simd")]
+	fn find_first_not(&self, src: &[u8]) -> Option<usize> {
+		find_delimiter(src, memchr::memchr3(b'<', b'&', b'\'', src), |b| b < 0x20)
+	}
+
+	#[cfg(not(feature = "simd"))]
+	fn find_first_not(&self, src: &[u8]) -> Option<usize> {
+		find_first_of_class(src, CLASS_ATTVAL_APOS_DELIMITER)
+	}
 }
 
 // XML 1.0 § 2.3 [10]
-fn is_attval_quot_delimiter(b: u8) -> bool {
+const fn is_attval_quot_delimiter(b: u8) -> bool {
 	// exclude all whitespace except normal space because those get converted into spaces
 	b < 0x20 || b == b'&' || b == b'"' || b == b'<'
 }
 
-pub fn maybe_attval_quot(b: u8) -> bool {
-	!is_attval_quot_delimiter(b)
+/// Selector for attribute values delimited by `"`.
+pub struct MaybeAttvalQuot;
+
+impl ByteSelect for MaybeAttvalQuot {
+	#[inline]
+	fn select(&self, b: u8) -> bool {
+		!has_class(b, CLASS_ATTVAL_QUOT_DELIMITER)
+	}
+
+	#[cfg(feature = "simd")]
+	fn find_first_not(&self, src: &[u8]) -> Option<usize> {
+		find_delimiter(src, memchr::memchr3(b'<', b'&', b'"', src), |b| b < 0x20)
+	}
+
+	#[cfg(not(feature = "simd"))]
+	fn find_first_not(&self, src: &[u8]) -> Option<usize> {
+		find_first_of_class(src, CLASS_ATTVAL_QUOT_DELIMITER)
+	}
 }
 
+#[inline]
 pub fn is_nonchar_byte(b: u8) -> bool {
-	b <= 0x08 || b == 0x0b || b == 0x0c || (b >= 0x0e && b <= 0x1f)
+	has_class(b, CLASS_NONCHAR)
 }
 
 /// Valid XML decimal characters (for character references)
@@ -171,13 +479,124 @@ static CLASS_XML_CDATA_SECTION_CONTENTS_DELIMITED: CodepointRanges =
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use alloc::vec::Vec;
 	use rxml_validation::selectors::*;
 
+	/// Reference implementation of find_first_not, bypassing any override.
+	fn naive_find_first_not<B: ByteSelect>(s: &B, src: &[u8]) -> Option<usize> {
+		src.iter().position(|&b| !s.select(b))
+	}
+
+	fn check_find_first_not<B: ByteSelect>(s: &B) {
+		let mut buf = Vec::new();
+		// put every byte value at various offsets to cover the block
+		// boundaries of the bulk scan
+		for b in 0..=255u8 {
+			for pos in [0usize, 1, 15, 16, 17, 40].iter() {
+				buf.clear();
+				buf.resize(48, b'x');
+				buf[*pos] = b;
+				assert_eq!(
+					s.find_first_not(&buf[..]),
+					naive_find_first_not(s, &buf[..]),
+					"byte 0x{:02x} at {}",
+					b,
+					pos
+				);
+				// and a later delimiter to check that the first one wins
+				buf[44] = b'<';
+				buf[45] = b']';
+				buf[46] = 0x01;
+				assert_eq!(
+					s.find_first_not(&buf[..]),
+					naive_find_first_not(s, &buf[..]),
+					"byte 0x{:02x} at {} followed by delimiters",
+					b,
+					pos
+				);
+			}
+		}
+		assert_eq!(s.find_first_not(b""), None);
+		for sample in ["foo äöü", "  \t\n x", "a:b-c.d_e"].iter() {
+			assert_eq!(
+				s.find_first_not(sample.as_bytes()),
+				naive_find_first_not(s, sample.as_bytes())
+			);
+		}
+	}
+
+	#[test]
+	fn find_first_not_agrees_with_select() {
+		check_find_first_not(&MaybeText);
+		check_find_first_not(&MaybeCDataContent);
+		check_find_first_not(&MaybeAttvalApos);
+		check_find_first_not(&MaybeAttvalQuot);
+		for content in ["foo äöü", "0123456789abcdef with some more text"].iter() {
+			assert_eq!(MaybeText.find_first_not(content.as_bytes()), None);
+			assert_eq!(MaybeAttvalQuot.find_first_not(content.as_bytes()), None);
+		}
+		check_find_first_not(&MaybeName);
+		check_find_first_not(&Space);
+	}
+
+	#[test]
+	fn byte_kind_table_agrees_with_predicates() {
+		for b in 0..=255u8 {
+			let kind = byte_kind(b);
+			assert_eq!(kind == ByteKind::Name, maybe_name(b), "0x{:02x}", b);
+			assert_eq!(
+				kind == ByteKind::Space || kind == ByteKind::Cr,
+				is_space(b),
+				"0x{:02x}",
+				b
+			);
+			assert_eq!(kind == ByteKind::Nonchar, is_nonchar_byte(b), "0x{:02x}", b);
+		}
+	}
+
+	#[test]
+	fn byte_class_table_agrees_with_predicates() {
+		for b in 0..=255u8 {
+			assert_eq!(MaybeText.select(b), !is_text_delimiter(b), "0x{:02x}", b);
+			assert_eq!(
+				MaybeCDataContent.select(b),
+				!is_cdata_content_delimiter(b),
+				"0x{:02x}",
+				b
+			);
+			assert_eq!(
+				MaybeAttvalApos.select(b),
+				!is_attval_apos_delimiter(b),
+				"0x{:02x}",
+				b
+			);
+			assert_eq!(
+				MaybeAttvalQuot.select(b),
+				!is_attval_quot_delimiter(b),
+				"0x{:02x}",
+				b
+			);
+			assert_eq!(maybe_name(b), !is_name_delimiter(b), "0x{:02x}", b);
+			assert_eq!(
+				is_space(b),
+				b == b' ' || b == b'\n' || b == b'\t' || b == b'\r',
+				"0x{:02x}",
+				b
+			);
+			assert_eq!(
+				is_nonchar_byte(b),
+				b <= 0x08 || b == 0x0b || b == 0x0c || (b >= 0x0e && b <= 0x1f),
+				"0x{:02x}",
+				b
+			);
+		}
+	}
+
 	#[test]
 	fn test_nonchar_byte_range_is_superset_of_nonchar_codepoint_range() {
 		let mut buf = [0u8; 4];
 		for cp in 0x0..=0x10ffffu32 {
-			if let Some(ch) = std::char::from_u32(cp) {
+			if let Some(ch) = core::char::from_u32(cp) {
 				let s = ch.encode_utf8(&mut buf[..]);
 				if !CLASS_XML_NONCHAR.select(ch) {
 					let mut ok = true;
@@ -199,7 +618,7 @@ mod tests {
 		let class = &CodepointRanges(VALID_XML_CDATA_RANGES_TEXT_DELIMITED);
 		let mut buf = [0u8; 4];
 		for cp in 0x0..=0x10ffffu32 {
-			if let Some(ch) = std::char::from_u32(cp) {
+			if let Some(ch) = core::char::from_u32(cp) {
 				let s = ch.encode_utf8(&mut buf[..]);
 				for b in s.as_bytes() {
 					if class.select(ch) && is_text_delimiter(*b) {
@@ -214,7 +633,7 @@ mod tests {
 	fn test_cdata_delimited_byte_range_is_superset_of_codepoint_range() {
 		let mut buf = [0u8; 4];
 		for cp in 0x0..=0x10ffffu32 {
-			if let Some(ch) = std::char::from_u32(cp) {
+			if let Some(ch) = core::char::from_u32(cp) {
 				let s = ch.encode_utf8(&mut buf[..]);
 				for b in s.as_bytes() {
 					if CLASS_XML_CDATA_SECTION_CONTENTS_DELIMITED.select(ch)
@@ -231,7 +650,7 @@ mod tests {
 	fn test_name_byte_range_is_superset_of_codepoint_range() {
 		let mut buf = [0u8; 4];
 		for cp in 0x0..=0x10ffffu32 {
-			if let Some(ch) = std::char::from_u32(cp) {
+			if let Some(ch) = core::char::from_u32(cp) {
 				let s = ch.encode_utf8(&mut buf[..]);
 				for b in s.as_bytes() {
 					if CLASS_XML_NAME.select(ch) && is_name_delimiter(*b) {
@@ -247,7 +666,7 @@ mod tests {
 		let class = CodepointRanges(VALID_XML_CDATA_RANGES_ATT_APOS_DELIMITED);
 		let mut buf = [0u8; 4];
 		for cp in 0x0..=0x10ffffu32 {
-			if let Some(ch) = std::char::from_u32(cp) {
+			if let Some(ch) = core::char::from_u32(cp) {
 				let s = ch.encode_utf8(&mut buf[..]);
 				for b in s.as_bytes() {
 					if class.select(ch) && is_attval_apos_delimiter(*b) {
@@ -263,7 +682,7 @@ mod tests {
 		let class = CodepointRanges(VALID_XML_CDATA_RANGES_ATT_QUOT_DELIMITED);
 		let mut buf = [0u8; 4];
 		for cp in 0x0..=0x10ffffu32 {
-			if let Some(ch) = std::char::from_u32(cp) {
+			if let Some(ch) = core::char::from_u32(cp) {
 				let s = ch.encode_utf8(&mut buf[..]);
 				for b in s.as_bytes() {
 					if class.select(ch) && is_attval_quot_delimiter(*b) {
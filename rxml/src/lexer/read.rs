@@ -1,3 +1,5 @@
+use alloc::vec::Vec;
+
 use super::ranges::ByteSelect;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -7,33 +9,18 @@ pub enum Endbyte {
 	Delimiter(u8),
 }
 
-fn find_first_not<B: ByteSelect + ?Sized>(src: &[u8], s: &B) -> Option<usize> {
-	for i in 0..src.len() {
-		// range check via for loop
-		let b = {
-			#[cfg(debug_assertions)]
-			{
-				src[i]
-			}
-			#[cfg(not(debug_assertions))]
-			*unsafe { src.get_unchecked(i) }
-		};
-		if !s.select(b) {
-			return Some(i);
-		}
-	}
-	None
-}
-
 pub fn read_validated_bytes<B: ByteSelect + ?Sized>(
 	r: &mut &[u8],
 	selector: &B,
 	limit: usize,
 	into: &mut Vec<u8>,
 ) -> Endbyte {
-	let end_pos = match find_first_not(*r, selector) {
-		None => r.len().min(limit),
-		Some(p) => p.min(limit),
+	// only look at the bytes we may consume, so that bulk scanning does
+	// not look at the whole buffer over and over again
+	let window = &r[..r.len().min(limit)];
+	let end_pos = match selector.find_first_not(window) {
+		None => window.len(),
+		Some(p) => p,
 	};
 	let (prefix, delim_suffix) = r.split_at(end_pos);
 	into.extend_from_slice(prefix);
@@ -58,7 +45,7 @@ pub fn skip_matching_bytes<B: ByteSelect + ?Sized>(
 	r: &mut &[u8],
 	selector: &B,
 ) -> (usize, Endbyte) {
-	let end_pos = match find_first_not(*r, selector) {
+	let end_pos = match selector.find_first_not(r) {
 		None => r.len(),
 		Some(p) => p,
 	};
@@ -18,6 +18,28 @@ with namespacing.
 * Streamed encoding
 * Parser can be driven push- and pull-based
 * Tokio-based asynchronicity supported via the `async` feature and [`AsyncParser`].
+* Runtime-agnostic asynchronicity via `futures-io` and the `futures` feature.
+* Optional enforcement of Unicode Normalization Form C via the `nfc` feature
+  (see [`LexerOptions`]).
+* Faster scanning of text and attribute values using `memchr` via the `simd`
+  feature.
+* Usable without `std` (but with `alloc`) by disabling the default `std`
+  feature.
+* C interface for embedding the parser into C programs via the `capi`
+  feature (see [`capi`]).
+* Generation of valid strings, events and items for fuzzing via the
+  `arbitrary` feature.
+* Serialization of strings, events and items with
+  [serde](https://docs.rs/serde) via the `serde` feature, e.g. to pass
+  parsed documents between processes. Namespace names are not interned when
+  deserializing, so that each event holds its own copy.
+* Helpers for testing applications, including `proptest` strategies for
+  valid documents, via the `test-util` feature (see [`test_util`]).
+* Conversion from and to [quick-xml](https://docs.rs/quick-xml) events via
+  the `quick-xml` feature (see [`quickxml`]).
+* Error reports with [miette](https://docs.rs/miette), including labels
+  pointing into the input, via the `miette` feature (see
+  [`error::SourceError`]).
 
 ## Example
 
@@ -32,6 +54,13 @@ let result = rxml::as_eof_flag(fp.parse_all(&mut &doc[..], true, |ev| {
 assert_eq!(result.unwrap(), true);
 ```
 
+For tests and small tools, [`parse_events`] and [`parse_tree`] parse a
+complete document in one call. If only the well-formedness of a document is
+of interest, [`validate`] checks it without collecting any events.
+Documents which are held in memory completely can be parsed with the
+[`SliceParser`], whose [`BorrowedEvent`]s refer to the input instead of
+copying text out of it.
+
 ## High-level parser usage
 
 ### Push-based usage
@@ -40,55 +69,168 @@ The [`FeedParser`] allows to push bits of XML into the parser as they arrive
 in the application and process the resulting [`ResolvedEvent`]s as they
 happen.
 
+For high-throughput applications, events can also be passed to an
+[`EventSink`] with borrowed arguments instead (see
+[`PushDriver::parse_all_into`]), which allows the parser to reuse its
+buffers.
+
+### Rewriting documents
+
+[`transform::copy_events`] forwards the events from a parser to an
+[`Encoder`], passing them through a hook which may rewrite, drop or inject
+events on the way.
+
+### Processing elements as a whole
+
+Small, self-contained parts of a document, such as the stanzas of an XMPP
+stream, can be collected into owned [`tree::Element`]s with the helpers in
+the [`tree`] module, while the rest of the document is processed as a
+stream of events.
+
+### Extracting values by path
+
+The [`path::PathMatcher`] calls back for elements and attributes selected by
+simple path patterns such as `/feed/entry/title` or `//item/@id`, skipping
+everything else without allocating.
+
+### Choosing parser options
+
+Instead of configuring the lexer and the parser option by option, a
+[`Profile`] selects a combination suited to a kind of input, such as
+untrusted XMPP streams or feeds from the web. All frontends can be created
+from a profile, e.g. with [`FeedParser::with_profile`].
+
 ### Pull-based usage
 
 If the parser should block while waiting for more data to arrive, a
 [`PullParser`] can be used instead. The `PullParser` requires a source which
-implements [`io::BufRead`].
+implements [`io::BufRead`]; other [`io::Read`] implementations can be
+buffered with a [`QueueReader`], see [`PullParser::from_reader`].
+
+### Sharing state between threads
+
+Parsers can share interned data through a [`Context`]
+(see [`WithContext`]), which is passed around as
+[`parser::RcPtr`]. `RcPtr` is an [`std::sync::Arc`] and the `Context` uses a
+mutex internally, so that the context can be shared by parsers on different
+threads (for example, by all connections of a connection pool). All parser
+frontends and events are [`Send`], so that they can be moved between the
+tasks of a multi-threaded runtime.
+
+Namespace URIs which an application dispatches on can be registered with the
+context ([`Context::register_namespace`]). The returned [`NamespaceHandle`]
+compares against the namespace names of events by pointer. An [`Encoder`]
+can use the same context (see [`Encoder::set_context`]), so that documents
+which are read and written again share their namespace names.
+
+The `mt` feature, which used to select between thread-safe and
+single-threaded pointers, no longer has any effect.
 
 ### Usage with Tokio
 
 Tokio is supported with the `async` feature. It offers the [`AsyncParser`]
 and the [`AsyncEventRead`] trait, which work similar to the `PullParser`.
 Instead of blocking, however, the async parser will yield control to other
-tasks.
+tasks. If the data arrives through a channel rather than a reader, the
+parser can be fed from it with [`AsyncParser::from_channel`].
+
+With the `sink` feature, [`writer::AsyncWriter`] serializes items or events
+into a [`tokio::io::AsyncWrite`] and implements the `futures_sink::Sink`
+trait. Together with the `stream` feature, events can be forwarded from an
+[`AsyncParser`] to an `AsyncWriter` with `SinkExt::send_all`.
+
+### Usage with other async runtimes
+
+With the `futures` feature, the [`AsyncParser`] can also read from any
+`futures_io::AsyncBufRead` (as used by async-std, smol and others), by
+wrapping the reader in `FuturesIo`. This does not require tokio.
+
+### Usage with tokio-util framed transports
+
+With the `codec` feature, [`codec::Codec`] implements the tokio-util
+`Decoder` and `Encoder` traits, so that it can be used with `Framed`.
+
+### Usage without `std`
+
+The lexer, the parsers and the encoder only need `alloc`. When the default
+`std` feature is disabled, everything built around `std::io` is unavailable:
+the [`PullParser`], [`FeedWriter`], [`LexerAdapter`] and the `io` trait
+implementations of [`BufferQueue`]. Data is instead pushed into a
+[`FeedParser`] and encoded into byte buffers with the [`Encoder`].
+
+Without `std`, the only I/O condition the parser reports is running out of
+buffered data, as [`Error::IO`] holding an [`error::IOErrorWrapper`] of kind
+[`error::IoErrorKind::WouldBlock`]. All features which integrate with
+runtimes or other I/O-based crates imply `std`.
 */
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+// the code generated by the xml! macro refers to the crate by name
+#[cfg(all(test, feature = "macros"))]
+extern crate self as rxml;
+
+#[cfg(feature = "std")]
 #[allow(unused_imports)]
 use std::io;
 
 mod bufq;
+mod collections;
 mod context;
 mod driver;
 mod errctx;
 pub mod error;
 pub mod lexer;
+pub mod mapping;
 pub mod parser;
+pub mod path;
+pub mod profile;
 pub mod strings;
+pub mod transform;
+pub mod tree;
 pub mod writer;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests;
 
+#[cfg(feature = "std")]
+#[doc(inline)]
+pub use bufq::QueueReader;
 #[doc(inline)]
 #[allow(deprecated)]
-pub use bufq::BufferQueue;
-pub use context::Context;
+pub use bufq::{BufferQueue, PushError};
+pub use context::{Context, NamespaceHandle};
+#[doc(inline)]
+pub use driver::{
+	as_eof_flag, parse_events, validate, EventRead, FeedParser, PushDriver, SliceParser,
+};
+#[cfg(feature = "std")]
 #[doc(inline)]
-pub use driver::{as_eof_flag, EventRead, FeedParser, PullDriver, PullParser, PushDriver};
+pub use driver::{FeedWriter, PullDriver, PullParser};
 #[doc(inline)]
 pub use error::{Error, Result};
 #[doc(inline)]
-pub use lexer::{Lexer, LexerOptions};
+pub use lexer::{Lexer, LexerOptions, Token, TokenMetrics};
+#[doc(inline)]
+pub use mapping::{FromXml, IntoXml};
+#[cfg(feature = "std")]
+#[doc(inline)]
+pub use parser::LexerAdapter;
 #[doc(inline)]
 pub use parser::{
-	LexerAdapter, NamespaceResolver, Parse, Parser, RawEvent, RawParser, RawQName, ResolvedEvent,
-	ResolvedQName, WithContext, XmlVersion, XMLNS_XML, XMLNS_XMLNS,
+	Attributes, BorrowedEvent, BufferLexerAdapter, EventSink, ExpandedName, NamespaceResolver,
+	Parse, Parser, QNameRef, RawEvent, RawParser, RawQName, ResolvedEvent, ResolvedQName,
+	TokenRead, WithContext, XmlVersion, XMLNS_XML, XMLNS_XMLNS,
 };
+#[doc(inline)]
+pub use profile::Profile;
 #[allow(deprecated)]
 #[doc(hidden)]
 pub use strings::NCName;
 pub use strings::{CData, CDataStr, Name, NameStr, NcName, NcNameStr};
 #[doc(inline)]
+pub use tree::parse_tree;
+#[doc(inline)]
 pub use writer::{Encoder, Item};
 
 #[cfg(feature = "macros")]
@@ -96,6 +238,12 @@ pub use writer::{Encoder, Item};
 #[doc(hidden)]
 pub use rxml_proc;
 
+/// Derive macros for [`mapping::FromXml`] and [`mapping::IntoXml`], see the
+/// [`mapping`] module for details.
+#[cfg(feature = "macros")]
+#[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
+pub use rxml_proc::{FromXml, IntoXml};
+
 pub use bytes;
 
 /**
@@ -188,13 +336,167 @@ macro_rules! xml_ncname {
 	};
 }
 
-#[cfg(feature = "async")]
+/**
+Compile-time construction of a [`QNameRef`]
+
+Build a namespace-qualified name constant from a namespace URI and a local
+name, asserting their compliance at compile time. With a single argument,
+the name has no namespace.
+
+Such constants can be compared against the names of parsed elements and
+attributes without any allocation, or used as patterns in `match`.
+
+# Example
+
+```rust
+use rxml::{QNameRef, ResolvedQName, xml_qname};
+use std::convert::{TryFrom, TryInto};
+
+const MESSAGE: QNameRef<'static> = xml_qname!("jabber:client", "message");
+const PRESENCE: QNameRef<'static> = xml_qname!("jabber:client", "presence");
+const ID: QNameRef<'static> = xml_qname!("id");
+
+let name: ResolvedQName = (
+	Some(rxml::CData::try_from("jabber:client").unwrap().into()),
+	"presence".try_into().unwrap(),
+);
+let kind = match QNameRef::from(&name) {
+	MESSAGE => "message",
+	PRESENCE => "presence",
+	_ => "other",
+};
+assert_eq!(kind, "presence");
+assert_ne!(ID, name);
+```
+
+Invalid values are rejected at compile-time:
+
+```rust,compile_fail
+# use rxml::{QNameRef, xml_qname};
+const INVALID: QNameRef<'static> = xml_qname!("jabber:client", "stream:stream");
+```
+*/
+#[cfg(feature = "macros")]
+#[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
+#[macro_export]
+macro_rules! xml_qname {
+	($namespace:literal, $local_name:literal) => {
+		$crate::rxml_proc::xml_qname!($namespace, $local_name)
+	};
+	($local_name:literal) => {
+		$crate::rxml_proc::xml_qname!($local_name)
+	};
+}
+
+/**
+Compile-time conversion of XML markup to a sequence of [`Item`]s
+
+Parse a string literal containing XML markup at compile time and expand to
+an array of the [`Item`]s which an [`Encoder`] needs to serialize it. This
+makes fixtures in tests and templates for responses readable, while
+ill-formed markup is rejected at compile time.
+
+Text and attribute values may contain `{}` placeholders, which are
+replaced with the arguments following the template, in order. Each
+argument must dereference to [`CDataStr`] (for instance `&CData` or
+`&CDataStr`) and must outlive the returned items. In attribute values, a
+placeholder must make up the whole value. Literal braces are written as
+`{{` and `}}`.
+
+The template consists of an optional XML declaration and any number of
+elements; whitespace between top-level elements is ignored. Namespace
+prefixes are resolved at compile time, and the namespace declarations of
+the template are passed on to the encoder as
+[`Item::NamespaceDeclaration`]s, so that the output uses the same prefixes.
+Comments, processing instructions and DTDs are not supported, as with the
+parser.
+
+# Example
+
+```rust
+use rxml::{xml, CData, Encoder};
+use std::convert::TryFrom;
+
+let to = CData::try_from("juliet@example.com").unwrap();
+let body = CData::try_from("Wherefore art thou, Romeo & Juliet?").unwrap();
+let items = xml!(
+	"<message xmlns='jabber:client' to='{}' type='chat'><body>{}</body></message>",
+	&to,
+	&body,
+);
+
+let mut enc = Encoder::new();
+let mut buf = Vec::new();
+for item in items {
+	enc.encode(item, &mut buf).unwrap();
+}
+assert_eq!(
+	std::str::from_utf8(&buf).unwrap(),
+	"<message xmlns='jabber:client' to=\"juliet@example.com\" type=\"chat\"><body>Wherefore art thou, Romeo &amp; Juliet?</body></message>",
+);
+```
+
+Ill-formed markup is rejected at compile-time:
+
+```rust,compile_fail
+# use rxml::xml;
+let items = xml!("<a><b></a>");
+```
+
+So are undeclared namespace prefixes:
+
+```rust,compile_fail
+# use rxml::xml;
+let items = xml!("<p:a/>");
+```
+*/
+#[cfg(feature = "macros")]
+#[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
+#[macro_export]
+macro_rules! xml {
+	($($args:tt)*) => {
+		$crate::rxml_proc::xml!($($args)*)
+	};
+}
+
+#[cfg(feature = "codec")]
+#[cfg_attr(docsrs, doc(cfg(feature = "codec")))]
+pub mod codec;
+
+#[cfg(feature = "capi")]
+#[cfg_attr(docsrs, doc(cfg(feature = "capi")))]
+pub mod capi;
+
+#[cfg(feature = "quick-xml")]
+#[cfg_attr(docsrs, doc(cfg(feature = "quick-xml")))]
+pub mod quickxml;
+
+#[cfg(feature = "test-util")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+pub mod test_util;
+
+#[cfg(any(feature = "async", feature = "futures"))]
 mod future;
 
+#[cfg(any(feature = "async", feature = "futures"))]
+#[doc(inline)]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "async", feature = "futures"))))]
+pub use future::{AsyncDriver, AsyncEventRead, AsyncEventReadExt, AsyncParser};
+
 #[cfg(feature = "async")]
 #[doc(inline)]
 #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
-pub use future::{AsyncDriver, AsyncEventRead, AsyncEventReadExt, AsyncParser};
+pub use future::ChannelSource;
+
+#[cfg(feature = "futures")]
+#[doc(inline)]
+#[cfg_attr(docsrs, doc(cfg(feature = "futures")))]
+pub use future::FuturesIo;
+
+#[cfg(feature = "stream")]
+#[doc(inline)]
+#[cfg_attr(docsrs, doc(cfg(feature = "stream")))]
+pub use future::{ByteStream, TextStream};
 
 /// Package version
 pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
@@ -0,0 +1,325 @@
+/*!
+# Mapping Rust types to and from XML elements
+
+The [`FromXml`] and [`IntoXml`] traits convert between Rust values and XML
+elements. Conversion from XML works on [`Element`] trees (see
+[`from_str`] for parsing a document directly), while conversion to XML
+writes the items of the element into an [`Encoder`]. Namespaces are handled
+by rxml itself, so they can be controlled precisely, without going through a
+generic serialization framework.
+
+The traits can be implemented manually, but usually they are derived with
+the `FromXml` and `IntoXml` derive macros (requires the `macros` feature).
+The derive macros are configured with `#[xml(..)]` attributes. On the
+struct, `#[xml(namespace = "..", name = "..")]` sets the name of the
+element; the namespace is optional. On each field, one of the following
+selects how the field is mapped:
+
+* `#[xml(attribute)]` maps the field to an attribute. The attribute name
+  defaults to the field name; it can be set with
+  `#[xml(attribute(name = "..", namespace = ".."))]`. Attributes are not
+  namespaced unless a namespace is given.
+* `#[xml(element)]` maps the field to the text content of a child element.
+  Name and namespace can be set like for attributes; the namespace
+  defaults to the one of the struct.
+* `#[xml(child)]` maps the field to a child element which is itself
+  converted with [`FromXml`]/[`IntoXml`].
+* `#[xml(text)]` maps the field to the text content of the element.
+
+Attribute, element and text fields use the [`FromXmlText`] and
+[`IntoXmlText`] traits for their values, which are implemented for
+strings, numbers and `bool`. Attribute fields may be wrapped in `Option`
+and element and child fields in `Option` or `Vec` to make them optional or
+repeatable. Unknown attributes and child elements are ignored when
+converting from XML.
+
+## Example
+
+```
+# #[cfg(feature = "macros")]
+# {
+use rxml::{FromXml, IntoXml};
+
+#[derive(FromXml, IntoXml, Debug, PartialEq)]
+#[xml(namespace = "urn:example:shop", name = "item")]
+struct Item {
+	#[xml(attribute)]
+	id: u32,
+	#[xml(attribute(name = "sale"))]
+	on_sale: Option<bool>,
+	#[xml(element)]
+	title: String,
+	#[xml(element(name = "tag"))]
+	tags: Vec<String>,
+}
+
+let item: Item = rxml::mapping::from_str(
+	"<item xmlns='urn:example:shop' id='42'><title>Teapot</title><tag>kitchen</tag><tag>tea</tag></item>",
+).unwrap();
+assert_eq!(item, Item {
+	id: 42,
+	on_sale: None,
+	title: "Teapot".to_string(),
+	tags: vec!["kitchen".to_string(), "tea".to_string()],
+});
+
+let xml = rxml::mapping::to_vec(&item).unwrap();
+assert_eq!(
+	std::str::from_utf8(&xml).unwrap(),
+	"<item xmlns='urn:example:shop' id=\"42\"><title>Teapot</title><tag>kitchen</tag><tag>tea</tag></item>",
+);
+# }
+```
+*/
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+
+use bytes::BufMut;
+
+use crate::error::Error;
+use crate::strings::{CData, CDataStr};
+use crate::tree::{Element, Node};
+use crate::writer::{EncodeError, Encoder, TrackNamespace};
+
+/// Error returned when converting an element into a Rust value.
+#[derive(Debug)]
+pub enum FromXmlError {
+	/// The element does not have the name expected by the type.
+	///
+	/// The element is returned unchanged, so that it can be passed on to
+	/// other types. It is boxed to keep the error small.
+	Mismatch(Box<Element>),
+	/// A required attribute is missing.
+	MissingAttribute(&'static str),
+	/// A required child element is missing.
+	MissingElement(&'static str),
+	/// A child element which may only occur once occurs multiple times.
+	DuplicateElement(&'static str),
+	/// The text of an attribute or element could not be converted.
+	InvalidText {
+		/// Name of the attribute or element.
+		name: &'static str,
+		/// Description of the problem.
+		message: String,
+	},
+	/// Parsing the document failed (only returned by [`from_str`]).
+	Parse(Error),
+}
+
+impl fmt::Display for FromXmlError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Mismatch(el) => write!(f, "unexpected element {}", el.name.1),
+			Self::MissingAttribute(name) => write!(f, "missing attribute {}", name),
+			Self::MissingElement(name) => write!(f, "missing child element {}", name),
+			Self::DuplicateElement(name) => write!(f, "duplicate child element {}", name),
+			Self::InvalidText { name, message } => {
+				write!(f, "invalid value for {}: {}", name, message)
+			}
+			Self::Parse(e) => write!(f, "failed to parse document: {}", e),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FromXmlError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Parse(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+impl FromXmlError {
+	/// Return the element as [`FromXmlError::Mismatch`].
+	pub fn mismatch(el: Element) -> Self {
+		Self::Mismatch(Box::new(el))
+	}
+}
+
+impl From<Error> for FromXmlError {
+	fn from(other: Error) -> Self {
+		Self::Parse(other)
+	}
+}
+
+/// Error returned when writing a Rust value as XML.
+#[derive(Debug)]
+pub enum IntoXmlError {
+	/// Encoding an item failed.
+	Encode(EncodeError),
+	/// A value could not be converted into text.
+	InvalidText {
+		/// Name of the attribute or element.
+		name: &'static str,
+		/// Description of the problem.
+		message: String,
+	},
+}
+
+impl fmt::Display for IntoXmlError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Encode(e) => write!(f, "failed to encode item: {}", e),
+			Self::InvalidText { name, message } => {
+				write!(f, "invalid value for {}: {}", name, message)
+			}
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for IntoXmlError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Encode(e) => Some(e),
+			Self::InvalidText { .. } => None,
+		}
+	}
+}
+
+impl From<EncodeError> for IntoXmlError {
+	fn from(other: EncodeError) -> Self {
+		Self::Encode(other)
+	}
+}
+
+/// Conversion of an element into a Rust value.
+pub trait FromXml: Sized {
+	/// Convert an element into `Self`.
+	///
+	/// If the element does not have the name expected by the type, it must
+	/// be returned unchanged as [`FromXmlError::Mismatch`] (see
+	/// [`FromXmlError::mismatch`]).
+	fn from_element(el: Element) -> Result<Self, FromXmlError>;
+}
+
+/// Conversion of a Rust value into an element.
+pub trait IntoXml {
+	/// Write the element representing `self` into `output`, using
+	/// `encoder`.
+	///
+	/// The element is written completely, from its header to its footer.
+	fn write_xml<T: TrackNamespace, O: BufMut>(
+		&self,
+		encoder: &mut Encoder<T>,
+		output: &mut O,
+	) -> Result<(), IntoXmlError>;
+}
+
+/// Conversion of text (attribute values and text content) into a Rust
+/// value.
+pub trait FromXmlText: Sized {
+	/// Convert `text` into `Self`, returning a description of the problem
+	/// on failure.
+	fn from_xml_text(text: &CDataStr) -> Result<Self, String>;
+}
+
+/// Conversion of a Rust value into text (attribute values and text
+/// content).
+pub trait IntoXmlText {
+	/// Convert `self` into text, returning a description of the problem on
+	/// failure.
+	fn to_xml_text(&self) -> Result<CData, String>;
+}
+
+impl FromXmlText for CData {
+	fn from_xml_text(text: &CDataStr) -> Result<Self, String> {
+		Ok(text.to_cdata())
+	}
+}
+
+impl IntoXmlText for CData {
+	fn to_xml_text(&self) -> Result<CData, String> {
+		Ok(self.clone())
+	}
+}
+
+impl FromXmlText for String {
+	fn from_xml_text(text: &CDataStr) -> Result<Self, String> {
+		Ok(text.to_string())
+	}
+}
+
+impl IntoXmlText for String {
+	fn to_xml_text(&self) -> Result<CData, String> {
+		CData::try_from(self.as_str()).map_err(|e| e.to_string())
+	}
+}
+
+impl FromXmlText for bool {
+	/// Accepts the values of the XML Schema `boolean` type: `true`, `false`,
+	/// `1` and `0`.
+	fn from_xml_text(text: &CDataStr) -> Result<Self, String> {
+		match &**text {
+			"true" | "1" => Ok(true),
+			"false" | "0" => Ok(false),
+			other => Err(alloc::format!("invalid boolean: {:?}", other)),
+		}
+	}
+}
+
+impl IntoXmlText for bool {
+	fn to_xml_text(&self) -> Result<CData, String> {
+		Ok(CData::try_from(if *self { "true" } else { "false" }).unwrap())
+	}
+}
+
+macro_rules! text_via_str {
+	($($t:ty),*) => {
+		$(
+			impl FromXmlText for $t {
+				fn from_xml_text(text: &CDataStr) -> Result<Self, String> {
+					text.parse().map_err(|e: <$t as core::str::FromStr>::Err| e.to_string())
+				}
+			}
+
+			impl IntoXmlText for $t {
+				fn to_xml_text(&self) -> Result<CData, String> {
+					CData::try_from(self.to_string()).map_err(|e| e.to_string())
+				}
+			}
+		)*
+	};
+}
+
+text_via_str!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, char);
+
+/// Return true if `el` has the given namespace URI and local name.
+pub fn has_name(el: &Element, namespace: Option<&str>, name: &str) -> bool {
+	el.has_name(namespace, name)
+}
+
+/// Return the concatenated text children of `el`.
+///
+/// Text in descendants of `el` is not included.
+pub fn text_of(el: &Element) -> CData {
+	let mut result = String::new();
+	for child in el.children.iter() {
+		if let Node::Text(text) = child {
+			result.push_str(text);
+		}
+	}
+	// SAFETY: a concatenation of valid CData strings is valid CData
+	unsafe { CData::from_string_unchecked(result) }
+}
+
+/// Parse a complete document and convert its root element with [`FromXml`].
+pub fn from_str<T: FromXml>(data: &str) -> Result<T, FromXmlError> {
+	T::from_element(crate::parse_tree(data)?)
+}
+
+/// Serialize a value with [`IntoXml`] into a new buffer, using a default
+/// [`Encoder`].
+///
+/// No XML declaration is written.
+pub fn to_vec<T: IntoXml + ?Sized>(value: &T) -> Result<Vec<u8>, IntoXmlError> {
+	let mut encoder = Encoder::new();
+	let mut output = Vec::new();
+	value.write_xml(&mut encoder, &mut output)?;
+	Ok(output)
+}
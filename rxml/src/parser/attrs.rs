@@ -0,0 +1,690 @@
+use alloc::borrow::Cow;
+use core::fmt;
+use core::iter::FromIterator;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+use smallvec::SmallVec;
+
+use crate::lexer::expand_attribute_references;
+use crate::strings::{CData, CDataStr};
+
+use super::namespaces::ResolvedQName;
+
+/// Number of attributes which can be stored without a heap allocation.
+const INLINE_ATTRIBUTES: usize = 4;
+
+/// Name, value and whether the value has unexpanded references.
+type Entry = (ResolvedQName, CData, bool);
+
+type Storage = SmallVec<[Entry; INLINE_ATTRIBUTES]>;
+
+fn expanded(value: &CData, unexpanded: bool) -> Cow<'_, CDataStr> {
+	if unexpanded {
+		Cow::Owned(expand_attribute_references(value))
+	} else {
+		Cow::Borrowed(value)
+	}
+}
+
+fn into_expanded((name, value, unexpanded): Entry) -> (ResolvedQName, CData) {
+	if unexpanded {
+		(name, expand_attribute_references(&value))
+	} else {
+		(name, value)
+	}
+}
+
+/**
+# Attributes of an element
+
+Ordered collection of the attributes of an element, as found in
+[`ResolvedEvent::StartElement`](crate::ResolvedEvent::StartElement). The
+attributes are kept in document order and each attribute name occurs at most
+once.
+
+Up to four attributes are stored inline, so that the typical element does not
+cause an allocation for its attributes at all. Lookups by name are linear
+scans, which is faster than hashing for the small numbers of attributes found
+on most elements.
+
+Two sets of attributes compare equal if they contain the same names with the
+same values, independent of their order.
+
+By default, the parsers expand references before values are stored. With
+[`LexerOptions::lazy_attribute_values`](crate::LexerOptions::lazy_attribute_values),
+values which contain references are stored as written instead and only
+expanded when they are read. This is why the accessors return values as
+[`Cow`]: values without references are borrowed, only values with
+references are expanded into a new string on each access. Methods which
+modify or take out a value expand it in place first.
+
+```
+use rxml::parser::Attributes;
+use std::convert::TryInto;
+
+let mut attrs = Attributes::new();
+attrs.insert((None, "id".try_into().unwrap()), "foo".try_into().unwrap());
+attrs.insert((None, "type".try_into().unwrap()), "get".try_into().unwrap());
+assert_eq!(attrs.len(), 2);
+assert_eq!(&*attrs.get(&(None, "type".try_into().unwrap())).unwrap(), "get");
+let names: Vec<_> = attrs.keys().map(|(_, name)| name.as_str()).collect();
+assert_eq!(names, vec!["id", "type"]);
+```
+*/
+#[derive(Clone, Default)]
+pub struct Attributes {
+	inner: Storage,
+}
+
+impl Attributes {
+	/// Create an empty set of attributes.
+	pub fn new() -> Self {
+		Self {
+			inner: SmallVec::new(),
+		}
+	}
+
+	/// Create an empty set of attributes with room for at least `capacity`
+	/// attributes.
+	pub fn with_capacity(capacity: usize) -> Self {
+		Self {
+			inner: SmallVec::with_capacity(capacity),
+		}
+	}
+
+	/// Number of attributes.
+	pub fn len(&self) -> usize {
+		self.inner.len()
+	}
+
+	/// Return true if there are no attributes.
+	pub fn is_empty(&self) -> bool {
+		self.inner.is_empty()
+	}
+
+	fn position(&self, name: &ResolvedQName) -> Option<usize> {
+		self.inner.iter().position(|(k, ..)| k == name)
+	}
+
+	/// Return the value of the attribute with the given name, if it exists.
+	pub fn get(&self, name: &ResolvedQName) -> Option<Cow<'_, CDataStr>> {
+		self.position(name).map(|i| {
+			let (_, value, unexpanded) = &self.inner[i];
+			expanded(value, *unexpanded)
+		})
+	}
+
+	/// Return a mutable reference to the value of the attribute with the
+	/// given name, if it exists.
+	pub fn get_mut(&mut self, name: &ResolvedQName) -> Option<&mut CData> {
+		match self.position(name) {
+			Some(i) => Some(Self::expand_entry(&mut self.inner[i])),
+			None => None,
+		}
+	}
+
+	/// Return the value of the attribute with the given namespace URI and
+	/// local name, if it exists.
+	///
+	/// This is convenient when the namespace name is not at hand as
+	/// [`NamespaceName`](crate::parser::NamespaceName).
+	pub fn get_by_name(
+		&self,
+		namespace: Option<&str>,
+		local_name: &str,
+	) -> Option<Cow<'_, CDataStr>> {
+		self.find_by_name(namespace, local_name)
+			.map(|(_, v, unexpanded)| expanded(v, *unexpanded))
+	}
+
+	fn find_by_name(&self, namespace: Option<&str>, local_name: &str) -> Option<&Entry> {
+		self.inner.iter().find(|((ns, name), ..)| {
+			ns.as_ref().map(|x| x.as_str()) == namespace && name.as_str() == local_name
+		})
+	}
+
+	/// Return a mutable reference to the value of the attribute with the
+	/// given namespace URI and local name, if it exists.
+	pub fn get_by_name_mut(
+		&mut self,
+		namespace: Option<&str>,
+		local_name: &str,
+	) -> Option<&mut CData> {
+		self.inner
+			.iter_mut()
+			.find(|((ns, name), ..)| {
+				ns.as_ref().map(|x| x.as_str()) == namespace && name.as_str() == local_name
+			})
+			.map(Self::expand_entry)
+	}
+
+	fn expand_entry(entry: &mut Entry) -> &mut CData {
+		if entry.2 {
+			entry.1 = expand_attribute_references(&entry.1);
+			entry.2 = false;
+		}
+		&mut entry.1
+	}
+
+	/// Expand the references in all values which are stored as written.
+	pub fn expand_references(&mut self) {
+		for entry in self.inner.iter_mut() {
+			Self::expand_entry(entry);
+		}
+	}
+
+	/// Return true if any value is stored with unexpanded references.
+	pub fn has_unexpanded_references(&self) -> bool {
+		self.inner.iter().any(|(.., unexpanded)| *unexpanded)
+	}
+
+	/// Return the value of the attribute with the given namespace URI and
+	/// local name, if it exists.
+	///
+	/// This is a shorthand for [`get_by_name`](Self::get_by_name).
+	pub fn attr(&self, namespace: Option<&str>, local_name: &str) -> Option<Cow<'_, CDataStr>> {
+		self.get_by_name(namespace, local_name)
+	}
+
+	/// Parse the value of the attribute with the given namespace URI and
+	/// local name using [`FromStr`].
+	///
+	/// If the attribute does not exist, `Ok(None)` is returned. If parsing
+	/// fails, the returned error carries the name and the value of the
+	/// attribute along with the error returned by `T::from_str`, which makes
+	/// for descriptive error messages.
+	///
+	/// ```
+	/// use rxml::{FeedParser, ResolvedEvent};
+	///
+	/// let mut fp = FeedParser::default();
+	/// let ev = fp.parse(&mut &b"<item priority='5' weight='heavy'/>"[..], true).unwrap();
+	/// let attrs = match ev {
+	/// 	Some(ResolvedEvent::StartElement(_, _, attrs, _)) => attrs,
+	/// 	other => panic!("unexpected event: {:?}", other),
+	/// };
+	/// assert_eq!(attrs.attr_parse::<u8>(None, "priority").unwrap(), Some(5));
+	/// assert_eq!(attrs.attr_parse::<u8>(None, "missing").unwrap(), None);
+	/// let err = attrs.attr_parse::<f64>(None, "weight").unwrap_err();
+	/// assert_eq!(
+	/// 	err.to_string(),
+	/// 	"invalid value 'heavy' for attribute weight: invalid float literal",
+	/// );
+	/// ```
+	pub fn attr_parse<T: FromStr>(
+		&self,
+		namespace: Option<&str>,
+		local_name: &str,
+	) -> Result<Option<T>, AttributeParseError<T::Err>> {
+		let (name, value, unexpanded) = match self.find_by_name(namespace, local_name) {
+			Some(v) => v,
+			None => return Ok(None),
+		};
+		let value = expanded(value, *unexpanded);
+		match value.parse() {
+			Ok(v) => Ok(Some(v)),
+			Err(error) => Err(AttributeParseError {
+				name: name.clone(),
+				value: value.into_owned(),
+				error,
+			}),
+		}
+	}
+
+	/// Return true if an attribute with the given name exists.
+	pub fn contains_key(&self, name: &ResolvedQName) -> bool {
+		self.position(name).is_some()
+	}
+
+	/// Set the value of an attribute.
+	///
+	/// If the attribute already exists, its value is replaced in place and
+	/// the old value is returned. Otherwise, the attribute is appended.
+	pub fn insert(&mut self, name: ResolvedQName, value: CData) -> Option<CData> {
+		match self.position(&name) {
+			Some(i) => Some(core::mem::replace(
+				Self::expand_entry(&mut self.inner[i]),
+				value,
+			)),
+			None => {
+				self.inner.push((name, value, false));
+				None
+			}
+		}
+	}
+
+	/// Remove an attribute, returning its value if it existed.
+	///
+	/// The order of the remaining attributes is preserved.
+	pub fn remove(&mut self, name: &ResolvedQName) -> Option<CData> {
+		self.position(name)
+			.map(|i| into_expanded(self.inner.remove(i)).1)
+	}
+
+	/// Remove all attributes, keeping any allocated memory.
+	pub fn clear(&mut self) {
+		self.inner.clear()
+	}
+
+	/// Iterate over the names and values of the attributes in order.
+	pub fn iter(&self) -> Iter<'_> {
+		Iter(self.inner.iter())
+	}
+
+	/// Iterate over the names of the attributes in order.
+	pub fn keys(&self) -> impl Iterator<Item = &ResolvedQName> {
+		self.inner.iter().map(|(k, ..)| k)
+	}
+
+	/// Iterate over the values of the attributes in order.
+	pub fn values(&self) -> impl Iterator<Item = Cow<'_, CDataStr>> {
+		self.inner
+			.iter()
+			.map(|(_, v, unexpanded)| expanded(v, *unexpanded))
+	}
+
+	/// Append an attribute without checking for duplicates.
+	pub(crate) fn push_unchecked(&mut self, name: ResolvedQName, value: CData) {
+		self.inner.push((name, value, false))
+	}
+
+	/// Append an attribute whose value has unexpanded references without
+	/// checking for duplicates.
+	pub(crate) fn push_unexpanded(&mut self, name: ResolvedQName, value: CData) {
+		self.inner.push((name, value, true))
+	}
+}
+
+/**
+# Error from parsing an attribute value
+
+Returned by [`Attributes::attr_parse`], this carries the name and the value
+of the attribute which could not be parsed and the error returned by the
+[`FromStr`] implementation.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeParseError<E> {
+	name: ResolvedQName,
+	value: CData,
+	error: E,
+}
+
+impl<E> AttributeParseError<E> {
+	/// The name of the attribute.
+	pub fn name(&self) -> &ResolvedQName {
+		&self.name
+	}
+
+	/// The value which could not be parsed.
+	pub fn value(&self) -> &CDataStr {
+		&self.value
+	}
+
+	/// The error returned when parsing the value.
+	pub fn error(&self) -> &E {
+		&self.error
+	}
+
+	/// Return the error returned when parsing the value, dropping the
+	/// attribute information.
+	pub fn into_error(self) -> E {
+		self.error
+	}
+}
+
+impl<E: fmt::Display> fmt::Display for AttributeParseError<E> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str("invalid value '")?;
+		f.write_str(&self.value)?;
+		f.write_str("' for attribute ")?;
+		if let Some(ns) = self.name.0.as_ref() {
+			write!(f, "{{{}}}", ns)?;
+		}
+		write!(f, "{}: {}", self.name.1, self.error)
+	}
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for AttributeParseError<E> {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		Some(&self.error)
+	}
+}
+
+impl fmt::Debug for Attributes {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_map().entries(self.iter()).finish()
+	}
+}
+
+impl PartialEq for Attributes {
+	fn eq(&self, other: &Attributes) -> bool {
+		self.len() == other.len()
+			&& self
+				.iter()
+				.all(|(k, v)| other.get(k).map(|ov| ov == v).unwrap_or(false))
+	}
+}
+
+impl Eq for Attributes {}
+
+impl FromIterator<(ResolvedQName, CData)> for Attributes {
+	/// Collect attributes from an iterator.
+	///
+	/// Later values replace earlier values of the same attribute.
+	fn from_iter<I: IntoIterator<Item = (ResolvedQName, CData)>>(iter: I) -> Self {
+		let mut result = Self::new();
+		result.extend(iter);
+		result
+	}
+}
+
+impl Extend<(ResolvedQName, CData)> for Attributes {
+	fn extend<I: IntoIterator<Item = (ResolvedQName, CData)>>(&mut self, iter: I) {
+		for (k, v) in iter {
+			self.insert(k, v);
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl From<HashMap<ResolvedQName, CData>> for Attributes {
+	fn from(other: HashMap<ResolvedQName, CData>) -> Self {
+		let mut result = Self::with_capacity(other.len());
+		for (k, v) in other {
+			result.push_unchecked(k, v);
+		}
+		result
+	}
+}
+
+#[cfg(feature = "std")]
+impl From<Attributes> for HashMap<ResolvedQName, CData> {
+	fn from(other: Attributes) -> Self {
+		other.into_iter().collect()
+	}
+}
+
+/// Serializes the attributes as a sequence of name/value pairs, in document
+/// order.
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+impl serde::Serialize for Attributes {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_seq(self.iter())
+	}
+}
+
+/// Deserializes attributes from a sequence of name/value pairs.
+///
+/// Later values replace earlier values of the same attribute, as with
+/// [`FromIterator`].
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+impl<'de> serde::Deserialize<'de> for Attributes {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let items = alloc::vec::Vec::<(ResolvedQName, CData)>::deserialize(deserializer)?;
+		Ok(items.into_iter().collect())
+	}
+}
+
+/// Generates attributes which can be written to a document.
+///
+/// Unnamespaced attributes named `xmlns` are never generated, as they would
+/// be interpreted as namespace declarations.
+#[cfg(feature = "arbitrary")]
+#[cfg_attr(docsrs, doc(cfg(feature = "arbitrary")))]
+impl<'a> arbitrary::Arbitrary<'a> for Attributes {
+	fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+		let mut result = Self::new();
+		for _ in 0..u.arbitrary_len::<(bool, u8, u8)>()? {
+			let ns = super::namespaces::arbitrary_namespace(u)?;
+			let name: crate::strings::NcName = u.arbitrary()?;
+			if ns.is_none() && name == "xmlns" {
+				continue;
+			}
+			result.insert((ns, name), u.arbitrary()?);
+		}
+		Ok(result)
+	}
+}
+
+/// Iterator over the attributes in an [`Attributes`] collection.
+///
+/// See [`Attributes::iter`].
+pub struct Iter<'x>(core::slice::Iter<'x, Entry>);
+
+impl<'x> Iterator for Iter<'x> {
+	type Item = (&'x ResolvedQName, Cow<'x, CDataStr>);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.0
+			.next()
+			.map(|(k, v, unexpanded)| (k, expanded(v, *unexpanded)))
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		self.0.size_hint()
+	}
+}
+
+impl<'x> DoubleEndedIterator for Iter<'x> {
+	fn next_back(&mut self) -> Option<Self::Item> {
+		self.0
+			.next_back()
+			.map(|(k, v, unexpanded)| (k, expanded(v, *unexpanded)))
+	}
+}
+
+impl<'x> ExactSizeIterator for Iter<'x> {}
+
+impl<'x> IntoIterator for &'x Attributes {
+	type Item = (&'x ResolvedQName, Cow<'x, CDataStr>);
+	type IntoIter = Iter<'x>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.iter()
+	}
+}
+
+/// Owning iterator over the attributes in an [`Attributes`] collection.
+///
+/// References in lazily read values are expanded.
+pub struct IntoIter(smallvec::IntoIter<[Entry; INLINE_ATTRIBUTES]>);
+
+impl Iterator for IntoIter {
+	type Item = (ResolvedQName, CData);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.0.next().map(into_expanded)
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		self.0.size_hint()
+	}
+}
+
+impl DoubleEndedIterator for IntoIter {
+	fn next_back(&mut self) -> Option<Self::Item> {
+		self.0.next_back().map(into_expanded)
+	}
+}
+
+impl ExactSizeIterator for IntoIter {}
+
+impl IntoIterator for Attributes {
+	type Item = (ResolvedQName, CData);
+	type IntoIter = IntoIter;
+
+	fn into_iter(self) -> Self::IntoIter {
+		IntoIter(self.inner.into_iter())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use alloc::string::ToString;
+	use alloc::vec;
+	use alloc::vec::Vec;
+	use core::convert::TryInto;
+
+	use crate::parser::RcPtr;
+
+	fn name(ns: Option<&str>, local: &str) -> ResolvedQName {
+		(
+			ns.map(|x| RcPtr::new(x.try_into().unwrap())),
+			local.try_into().unwrap(),
+		)
+	}
+
+	#[test]
+	fn preserves_insertion_order() {
+		let mut attrs = Attributes::new();
+		for local in ["z", "a", "m", "b", "c", "y"].iter() {
+			attrs.insert(name(None, local), "v".try_into().unwrap());
+		}
+		let order: Vec<_> = attrs.keys().map(|(_, x)| x.as_str()).collect();
+		assert_eq!(order, vec!["z", "a", "m", "b", "c", "y"]);
+	}
+
+	#[test]
+	fn insert_replaces_in_place() {
+		let mut attrs = Attributes::new();
+		attrs.insert(name(None, "a"), "1".try_into().unwrap());
+		attrs.insert(name(None, "b"), "2".try_into().unwrap());
+		assert_eq!(
+			attrs
+				.insert(name(None, "a"), "3".try_into().unwrap())
+				.unwrap(),
+			"1"
+		);
+		let items: Vec<_> = attrs
+			.iter()
+			.map(|((_, k), v)| (k.to_string(), v.to_string()))
+			.collect();
+		assert_eq!(
+			items,
+			vec![
+				("a".to_string(), "3".to_string()),
+				("b".to_string(), "2".to_string())
+			]
+		);
+	}
+
+	#[test]
+	fn namespace_distinguishes_attributes() {
+		let mut attrs = Attributes::new();
+		attrs.insert(name(None, "a"), "1".try_into().unwrap());
+		attrs.insert(name(Some("urn:x"), "a"), "2".try_into().unwrap());
+		assert_eq!(attrs.len(), 2);
+		assert_eq!(&*attrs.get(&name(None, "a")).unwrap(), "1");
+		assert_eq!(&*attrs.get(&name(Some("urn:x"), "a")).unwrap(), "2");
+		assert_eq!(&*attrs.get_by_name(Some("urn:x"), "a").unwrap(), "2");
+		assert!(attrs.get_by_name(Some("urn:y"), "a").is_none());
+	}
+
+	#[test]
+	fn attr_parse_reports_name_and_value() {
+		let attrs: Attributes = vec![
+			(name(None, "a"), "23".try_into().unwrap()),
+			(name(Some("urn:x"), "b"), "x".try_into().unwrap()),
+		]
+		.into_iter()
+		.collect();
+		assert_eq!(&*attrs.attr(None, "a").unwrap(), "23");
+		assert!(attrs.attr(Some("urn:x"), "a").is_none());
+		assert_eq!(attrs.attr_parse::<i32>(None, "a").unwrap(), Some(23));
+		assert_eq!(attrs.attr_parse::<i32>(None, "b").unwrap(), None);
+		let err = attrs.attr_parse::<i32>(Some("urn:x"), "b").unwrap_err();
+		assert_eq!(err.name(), &name(Some("urn:x"), "b"));
+		assert_eq!(err.value(), "x");
+		assert_eq!(
+			err.to_string(),
+			"invalid value 'x' for attribute {urn:x}b: invalid digit found in string"
+		);
+		#[cfg(feature = "std")]
+		assert!(std::error::Error::source(&err).is_some());
+	}
+
+	#[test]
+	fn remove_preserves_order() {
+		let mut attrs: Attributes = vec![
+			(name(None, "a"), "1".try_into().unwrap()),
+			(name(None, "b"), "2".try_into().unwrap()),
+			(name(None, "c"), "3".try_into().unwrap()),
+		]
+		.into_iter()
+		.collect();
+		assert_eq!(attrs.remove(&name(None, "b")).unwrap(), "2");
+		assert!(attrs.remove(&name(None, "b")).is_none());
+		let order: Vec<_> = attrs.keys().map(|(_, x)| x.as_str()).collect();
+		assert_eq!(order, vec!["a", "c"]);
+	}
+
+	#[test]
+	fn equality_ignores_order() {
+		let a: Attributes = vec![
+			(name(None, "a"), "1".try_into().unwrap()),
+			(name(None, "b"), "2".try_into().unwrap()),
+		]
+		.into_iter()
+		.collect();
+		let b: Attributes = vec![
+			(name(None, "b"), "2".try_into().unwrap()),
+			(name(None, "a"), "1".try_into().unwrap()),
+		]
+		.into_iter()
+		.collect();
+		let c: Attributes = vec![
+			(name(None, "b"), "2".try_into().unwrap()),
+			(name(None, "a"), "x".try_into().unwrap()),
+		]
+		.into_iter()
+		.collect();
+		assert_eq!(a, b);
+		assert_ne!(a, c);
+		assert_ne!(a, Attributes::new());
+	}
+
+	#[test]
+	fn expands_unexpanded_values_on_access() {
+		let mut attrs = Attributes::new();
+		attrs.push_unexpanded(name(None, "a"), "x &amp; y".try_into().unwrap());
+		attrs.push_unchecked(name(None, "b"), "&".try_into().unwrap());
+		assert!(attrs.has_unexpanded_references());
+		assert_eq!(&*attrs.get(&name(None, "a")).unwrap(), "x & y");
+		assert_eq!(&*attrs.attr(None, "a").unwrap(), "x & y");
+		let values: Vec<_> = attrs.values().map(|v| v.to_string()).collect();
+		assert_eq!(values, vec!["x & y".to_string(), "&".to_string()]);
+		match attrs.get_by_name(None, "b").unwrap() {
+			Cow::Borrowed(v) => assert_eq!(v, "&"),
+			other => panic!("unexpected value: {:?}", other),
+		}
+
+		let mut expected = Attributes::new();
+		expected.insert(name(None, "a"), "x & y".try_into().unwrap());
+		expected.insert(name(None, "b"), "&".try_into().unwrap());
+		assert_eq!(attrs, expected);
+		let items: Vec<_> = attrs.clone().into_iter().collect();
+		assert_eq!(items[0].1, "x & y");
+
+		assert_eq!(*attrs.get_mut(&name(None, "a")).unwrap(), "x & y");
+		assert!(!attrs.has_unexpanded_references());
+	}
+
+	#[cfg(feature = "std")]
+	#[test]
+	fn converts_to_and_from_hash_map() {
+		let mut map = HashMap::new();
+		map.insert(name(None, "a"), "1".try_into().unwrap());
+		map.insert(name(Some("urn:x"), "b"), "2".try_into().unwrap());
+		let attrs: Attributes = map.clone().into();
+		assert_eq!(attrs.len(), 2);
+		let back: HashMap<_, _> = attrs.into();
+		assert_eq!(back, map);
+	}
+}
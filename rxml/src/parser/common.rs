@@ -1,9 +1,10 @@
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
 use std::io;
-#[cfg(not(feature = "mt"))]
-use std::rc::Rc;
-#[cfg(feature = "mt")]
-use std::sync::Arc;
 
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+use crate::bufq::BufferQueue;
 use crate::context;
 use crate::error::Result;
 use crate::lexer::{Lexer, Token};
@@ -15,6 +16,7 @@ use crate::strings::*;
 Only version 1.0 is supported.
 */
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum XmlVersion {
 	/// XML Version 1.0
 	V1_0,
@@ -22,23 +24,16 @@ pub enum XmlVersion {
 
 /// Wrapper pointer around namespace URIs
 ///
-/// In builds with the `mt` feature, this is a [`Arc`]. In non-`mt` builds,
-/// this is a [`std::rc::Rc`]
-#[cfg(feature = "mt")]
+/// This is an [`Arc`], so that events and parsers can be moved between
+/// threads.
 pub type RcPtr<T> = Arc<T>;
-/// Wrapper pointer around namespace URIs
-///
-/// In builds with the `mt` feature, this is a [`std::sync::Arc`].
-/// In non-`mt` builds, this is a [`Rc`].
-#[cfg(not(feature = "mt"))]
-pub type RcPtr<T> = Rc<T>;
 
 /// XML core namespace URI (for the `xml:` prefix)
 pub const XMLNS_XML: &'static CDataStr =
-	unsafe { std::mem::transmute("http://www.w3.org/XML/1998/namespace") };
+	unsafe { core::mem::transmute("http://www.w3.org/XML/1998/namespace") };
 /// XML namespace URI (for the `xmlns:` prefix)
 pub const XMLNS_XMLNS: &'static CDataStr =
-	unsafe { std::mem::transmute("http://www.w3.org/2000/xmlns/") };
+	unsafe { core::mem::transmute("http://www.w3.org/2000/xmlns/") };
 
 /// Carry measurement information about the event
 ///
@@ -59,6 +54,7 @@ pub const XMLNS_XMLNS: &'static CDataStr =
 ///
 ///   [`Error::RestrictedXml`]: crate::Error::RestrictedXml
 #[derive(Copy, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EventMetrics {
 	pub(super) len: usize,
 }
@@ -91,21 +87,46 @@ pub trait TokenRead {
 	/// Lexer errors and I/O errors from the underlying data source are
 	/// passed through.
 	fn read(&mut self) -> Result<Option<Token>>;
+
+	/// Return the number of bytes of memory held by the source for
+	/// buffering.
+	///
+	/// This is accounted for by [`Parser::set_memory_budget`]. The default
+	/// implementation returns zero.
+	///
+	///   [`Parser::set_memory_budget`]: crate::Parser::set_memory_budget
+	fn memory_used(&self) -> usize {
+		0
+	}
+}
+
+impl<R: TokenRead + ?Sized> TokenRead for &mut R {
+	fn read(&mut self) -> Result<Option<Token>> {
+		(**self).read()
+	}
+
+	fn memory_used(&self) -> usize {
+		(**self).memory_used()
+	}
 }
 
 /// Wrapper around [`Lexer`](crate::Lexer) and [`std::io::BufRead`] to provide
 /// a [`TokenRead`].
+#[cfg(feature = "std")]
 pub struct LexerAdapter<R: io::BufRead> {
 	lexer: Lexer,
 	src: R,
+	lex: fn(&mut Lexer, &mut R) -> Result<Option<Token>>,
 }
 
+#[cfg(feature = "std")]
 impl<R: io::BufRead> LexerAdapter<R> {
 	/// Wraps a lexer and a codepoint source
 	pub fn new(lexer: Lexer, src: R) -> Self {
 		Self {
 			lexer: lexer,
 			src: src,
+			lex: |lexer, src| lexer.lex(src),
 		}
 	}
 
@@ -133,25 +154,76 @@ impl<R: io::BufRead> LexerAdapter<R> {
 	pub fn get_lexer_mut(&mut self) -> &mut Lexer {
 		&mut self.lexer
 	}
+
+	/// Return mutable references to the lexer and the source at once.
+	pub(crate) fn parts_mut(&mut self) -> (&mut Lexer, &mut R) {
+		(&mut self.lexer, &mut self.src)
+	}
 }
 
+#[cfg(feature = "std")]
 impl<R: io::BufRead> TokenRead for LexerAdapter<R> {
 	fn read(&mut self) -> Result<Option<Token>> {
-		self.lexer.lex(&mut self.src)
+		(self.lex)(&mut self.lexer, &mut self.src)
+	}
+
+	fn memory_used(&self) -> usize {
+		self.lexer.memory_used()
+	}
+}
+
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+impl<'x> LexerAdapter<BufferQueue<'x>> {
+	/// Wraps a lexer and a buffer queue, skipping the UTF-8 validation for
+	/// buffers which have been pushed as text.
+	pub(crate) fn with_queue(lexer: Lexer, src: BufferQueue<'x>) -> Self {
+		Self {
+			lexer,
+			src,
+			lex: Lexer::lex_queue_reader,
+		}
 	}
 }
 
 /// Ephemeral wrapper a [`Lexer`](crate::Lexer) and a generic buffer to
 /// provide a copy-free [`TokenRead`].
-pub(crate) struct BufferLexerAdapter<'x, T: bytes::Buf> {
+pub struct BufferLexerAdapter<'x, T: bytes::Buf> {
 	pub(crate) lexer: &'x mut Lexer,
 	pub(crate) buf: &'x mut T,
 	pub(crate) eof: bool,
+	/// Return true if the current chunk of `buf` is known to hold valid
+	/// UTF-8.
+	///
+	/// Must only return true if the chunk is the unread part of a `str`.
+	pub(crate) is_utf8: fn(&T) -> bool,
+}
+
+impl<'x, T: bytes::Buf> BufferLexerAdapter<'x, T> {
+	/// Wrap a lexer and a buffer.
+	///
+	/// Tokens are read from `buf`, which is advanced accordingly. If `eof`
+	/// is false, reaching the end of `buf` is reported as
+	/// [`std::io::ErrorKind::WouldBlock`] error, otherwise it is treated as
+	/// the end of the document. See [`Lexer::lex_buffer`](crate::Lexer::lex_buffer).
+	pub fn new(lexer: &'x mut Lexer, buf: &'x mut T, eof: bool) -> Self {
+		Self {
+			lexer,
+			buf,
+			eof,
+			is_utf8: |_| false,
+		}
+	}
 }
 
 impl<'x, T: bytes::Buf> TokenRead for BufferLexerAdapter<'x, T> {
 	fn read(&mut self) -> Result<Option<Token>> {
-		self.lexer.lex_buffer(self.buf, self.eof)
+		// SAFETY: see the documentation of the is_utf8 field
+		unsafe { self.lexer.lex_buffer_impl(self.buf, self.eof, self.is_utf8) }
+	}
+
+	fn memory_used(&self) -> usize {
+		self.lexer.memory_used()
 	}
 }
 
@@ -177,6 +249,19 @@ pub trait Parse {
 	/// different document).
 	fn parse<R: TokenRead>(&mut self, r: &mut R) -> Result<Option<Self::Output>>;
 
+	/// Return true if the root element has been closed and all events
+	/// belonging to it have been returned.
+	///
+	/// Parsers do not read tokens beyond the end of the root element before
+	/// they are asked for the next event. When this returns true, any data
+	/// after the root element is thus still in the token source (or the
+	/// buffer backing it).
+	///
+	/// The default implementation always returns false.
+	fn is_document_complete(&self) -> bool {
+		false
+	}
+
 	/// Release all temporary buffers or other ephemeral allocations
 	///
 	/// This is sensible to call when it is expected that no more data will be
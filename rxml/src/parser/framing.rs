@@ -0,0 +1,126 @@
+use core::ops::Range;
+
+use crate::strings::{CDataStr, NcNameStr};
+
+use super::attrs::Attributes;
+use super::common::{EventMetrics, XmlVersion};
+use super::namespaces::{Diagnostic, NamespaceName, ResolvedEvent, ResolvedQName};
+use super::sink::EventSink;
+use super::stats::Statistics;
+
+/// Track the stream position of events and the byte ranges of the children
+/// of the root element.
+///
+/// As events are consecutive (see [`EventMetrics`]), the position is simply
+/// the sum of all event lengths seen so far.
+#[derive(Debug, Clone, Default)]
+pub(super) struct ChildRanges {
+	position: u64,
+	depth: usize,
+	child_start: u64,
+	completed: Option<Range<u64>>,
+}
+
+impl ChildRanges {
+	pub(super) fn position(&self) -> u64 {
+		self.position
+	}
+
+	pub(super) fn completed(&self) -> Option<Range<u64>> {
+		self.completed.clone()
+	}
+
+	fn advance(&mut self, metrics: &EventMetrics) {
+		self.completed = None;
+		self.position += metrics.len() as u64;
+	}
+
+	pub(super) fn start_element(&mut self, metrics: &EventMetrics) {
+		if self.depth == 1 {
+			self.child_start = self.position;
+		}
+		self.advance(metrics);
+		self.depth += 1;
+	}
+
+	pub(super) fn end_element(&mut self, metrics: &EventMetrics) {
+		self.advance(metrics);
+		self.depth -= 1;
+		if self.depth == 1 {
+			self.completed = Some(self.child_start..self.position);
+		}
+	}
+
+	pub(super) fn other(&mut self, metrics: &EventMetrics) {
+		self.advance(metrics);
+	}
+
+	pub(super) fn observe(&mut self, ev: &ResolvedEvent) {
+		match ev {
+			ResolvedEvent::StartElement(m, ..) => self.start_element(m),
+			ResolvedEvent::EndElement(m) => self.end_element(m),
+			other => self.other(other.metrics()),
+		}
+	}
+}
+
+/// Sink adapter which updates [`ChildRanges`] and [`Statistics`] before
+/// forwarding events.
+pub(super) struct TrackingSink<'x, S: ?Sized> {
+	pub(super) ranges: &'x mut ChildRanges,
+	pub(super) stats: &'x mut Statistics,
+	pub(super) inner: &'x mut S,
+}
+
+impl<'x, S: EventSink + ?Sized> EventSink for TrackingSink<'x, S> {
+	fn xml_declaration(&mut self, metrics: &EventMetrics, version: XmlVersion) {
+		self.ranges.other(metrics);
+		self.stats.xml_declaration();
+		self.inner.xml_declaration(metrics, version)
+	}
+
+	fn start_element(
+		&mut self,
+		metrics: &EventMetrics,
+		name: &ResolvedQName,
+		attributes: &Attributes,
+		prefix: Option<&NcNameStr>,
+	) {
+		self.ranges.start_element(metrics);
+		self.stats.start_element();
+		self.inner.start_element(metrics, name, attributes, prefix)
+	}
+
+	fn end_element(&mut self, metrics: &EventMetrics) {
+		self.ranges.end_element(metrics);
+		self.stats.end_element();
+		self.inner.end_element(metrics)
+	}
+
+	fn text(&mut self, metrics: &EventMetrics, text: &CDataStr) {
+		self.ranges.other(metrics);
+		self.stats.text(text);
+		self.inner.text(metrics, text)
+	}
+
+	fn start_prefix_mapping(
+		&mut self,
+		metrics: &EventMetrics,
+		prefix: Option<&NcNameStr>,
+		namespace: &NamespaceName,
+	) {
+		self.ranges.other(metrics);
+		self.stats.start_prefix_mapping();
+		self.inner.start_prefix_mapping(metrics, prefix, namespace)
+	}
+
+	fn end_prefix_mapping(&mut self, metrics: &EventMetrics, prefix: Option<&NcNameStr>) {
+		self.ranges.other(metrics);
+		self.stats.end_prefix_mapping();
+		self.inner.end_prefix_mapping(metrics, prefix)
+	}
+
+	fn diagnostic(&mut self, diagnostic: &Diagnostic) {
+		self.inner.diagnostic(diagnostic)
+	}
+}
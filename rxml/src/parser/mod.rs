@@ -22,27 +22,54 @@ structs and emits [`ResolvedEvent`] structs. It is implemented by the
 Together with the validation of the logical stage, this provides full
 conformity checks according to XML 1.0 and Namespaces for XML 1.0.
 
-The downside of using this stage is added processing cost, because
-considerable dynamic allocations need to be performed per-element (for
-attribute hash maps). In addition, information about the prefixes used to
-declare namespaces is lost (but nothing should rely on those anyway).
+The downside of using this stage is added processing cost for resolving
+names and checking attribute uniqueness. Attributes are collected into an
+[`Attributes`] list which stores the common case of few attributes without
+dynamic allocation. In addition, information about the prefixes used to
+declare namespaces is lost (but nothing should rely on those anyway); only
+the prefix of each element name is passed on in
+[`ResolvedEvent::StartElement`].
 
    [`Lexer`]: crate::Lexer
 */
 
+mod attrs;
 mod common;
+mod framing;
 mod namespaces;
+mod qname;
 mod raw;
+mod sink;
+mod stats;
+
+use alloc::vec::Vec;
+use core::ops::Range;
 
 use crate::context;
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::lexer::Lexer;
+use crate::strings::{CData, CDataStr, NcName};
 
+#[doc(inline)]
+pub use attrs::{
+	AttributeParseError, Attributes, IntoIter as AttributesIntoIter, Iter as AttributesIter,
+};
 #[doc(inline)]
 pub use common::*;
+#[cfg(feature = "arbitrary")]
+pub(crate) use namespaces::arbitrary_namespace;
 #[doc(inline)]
-pub use namespaces::{NamespaceName, NamespaceResolver, ResolvedEvent, ResolvedQName};
+pub use namespaces::{
+	BorrowedEvent, Diagnostic, NamespaceName, NamespaceResolver, ResolvedEvent, ResolvedQName,
+};
+#[doc(inline)]
+pub use qname::{ExpandedName, QNameRef};
 #[doc(inline)]
 pub use raw::{RawEvent, RawParser, RawQName};
+#[doc(inline)]
+pub use sink::EventSink;
+#[doc(inline)]
+pub use stats::{ProgressHook, Statistics};
 
 /**
 # Low-level restricted XML 1.0 parser
@@ -59,14 +86,25 @@ documentation for details.
 In contrast to a [`RawParser`], the [`Parser`] enforces well-formedness and
 namespace-well-formedness.
 
+Cloning a parser yields an independent copy of its state. The clone shares
+the [`Context`](crate::Context) with the original, as the context is only
+used to deduplicate data.
+
    [`FeedParser`]: crate::FeedParser
    [`PullParser`]: crate::PullParser
    [`AsyncParser`]: crate::AsyncParser
    [`rxml`]: crate
 */
+#[derive(Clone)]
 pub struct Parser {
 	inner: RawParser,
 	resolver: NamespaceResolver,
+	ranges: framing::ChildRanges,
+	stats: Statistics,
+	progress: Option<stats::Progress>,
+	memory_budget: Option<usize>,
+	/// The budget which has been exceeded, if any.
+	memory_budget_exceeded: Option<usize>,
 }
 
 impl Default for Parser {
@@ -75,11 +113,383 @@ impl Default for Parser {
 	}
 }
 
+impl Parser {
+	/// Declare a namespace prefix binding in the scope enclosing the
+	/// document.
+	///
+	/// This allows parsing fragments which use prefixes bound by an outer
+	/// document. See [`NamespaceResolver::declare_prefix`] for details.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::{FeedParser, ResolvedEvent, as_eof_flag};
+	/// use std::convert::TryInto;
+	///
+	/// let mut fp = FeedParser::default();
+	/// fp.get_parser_mut().declare_prefix(
+	/// 	Some("stream".try_into().unwrap()),
+	/// 	"http://etherx.jabber.org/streams".try_into().unwrap(),
+	/// ).unwrap();
+	/// let mut doc = &b"<stream:features/>"[..];
+	/// let mut events = Vec::new();
+	/// as_eof_flag(fp.parse_all(&mut doc, true, |ev| events.push(ev))).unwrap();
+	/// match &events[0] {
+	/// 	ResolvedEvent::StartElement(_, (Some(ns), localname), _, _) => {
+	/// 		assert_eq!(ns.as_str(), "http://etherx.jabber.org/streams");
+	/// 		assert_eq!(localname, "features");
+	/// 	}
+	/// 	other => panic!("unexpected event: {:?}", other),
+	/// }
+	/// ```
+	pub fn declare_prefix(&mut self, prefix: Option<NcName>, nsuri: CData) -> Result<()> {
+		self.resolver.declare_prefix(prefix, nsuri)
+	}
+
+	/// Access the shared context of the parser.
+	///
+	/// This can be used to inspect and control the memory used by the
+	/// context, even when the parser is wrapped in one of the frontends:
+	///
+	/// ```
+	/// use rxml::FeedParser;
+	///
+	/// let fp = FeedParser::default();
+	/// let ctx = fp.get_parser().context();
+	/// println!("context uses about {} bytes", ctx.memory_used());
+	/// ctx.shrink_to_fit();
+	/// ```
+	pub fn context(&self) -> &RcPtr<context::Context> {
+		self.resolver.context()
+	}
+
+	/// Parse a single event using tokens from `r` and pass it to `sink`.
+	///
+	/// This is the [`EventSink`] counterpart of [`Parse::parse`]: instead of
+	/// returning an event, the corresponding method of `sink` is called. If
+	/// the end of file has been reached after a valid document, false is
+	/// returned, otherwise true.
+	pub fn parse_into<R: TokenRead, S: EventSink + ?Sized>(
+		&mut self,
+		r: &mut R,
+		sink: &mut S,
+	) -> Result<bool> {
+		self.check_memory_budget_exceeded()?;
+		let inner = &mut self.inner;
+		let mut sink = framing::TrackingSink {
+			ranges: &mut self.ranges,
+			stats: &mut self.stats,
+			inner: sink,
+		};
+		let result = self.resolver.next_into(|| inner.parse(r), &mut sink);
+		if let Ok(true) = result {
+			self.report_progress();
+		}
+		self.enforce_memory_budget(r, result)
+	}
+
+	/// Pass character data from the start of `buf` to `sink` without
+	/// copying it, if the lexer and the parser are in element content.
+	///
+	/// Returns the number of bytes consumed from `buf`, which is zero if
+	/// the data has to go through the regular path instead.
+	pub(crate) fn parse_borrowed_text<S: EventSink + ?Sized>(
+		&mut self,
+		lexer: &mut Lexer,
+		buf: &[u8],
+		sink: &mut S,
+	) -> usize {
+		match self.take_borrowed_text(lexer, buf) {
+			Some((metrics, text)) => {
+				sink.text(&metrics, text);
+				text.len()
+			}
+			None => 0,
+		}
+	}
+
+	/// Take character data from the start of `buf` without copying it, if
+	/// the lexer and the parser are in element content.
+	///
+	/// The caller has to remove `text.len()` bytes from the front of its
+	/// buffer. If `None` is returned, nothing has been consumed.
+	pub(crate) fn take_borrowed_text<'b>(
+		&mut self,
+		lexer: &mut Lexer,
+		buf: &'b [u8],
+	) -> Option<(EventMetrics, &'b CDataStr)> {
+		if !self.inner.expects_content() || !self.resolver.passes_text() {
+			return None;
+		}
+		let (tm, text) = lexer.lex_borrowed_text(buf)?;
+		self.resolver.bypassed_text();
+		let metrics = EventMetrics { len: tm.len() };
+		self.ranges.other(&metrics);
+		self.stats.text(text);
+		self.report_progress();
+		Some((metrics, text))
+	}
+
+	/// Enable or disable the emission of prefix mapping events.
+	///
+	/// See [`NamespaceResolver::set_emit_prefix_mappings`] for details.
+	pub fn set_emit_prefix_mappings(&mut self, enable: bool) {
+		self.resolver.set_emit_prefix_mappings(enable)
+	}
+
+	/// Enable or disable reporting namespace declarations as attributes.
+	///
+	/// See [`NamespaceResolver::set_emit_xmlns_attributes`] for details.
+	pub fn set_emit_xmlns_attributes(&mut self, enable: bool) {
+		self.resolver.set_emit_xmlns_attributes(enable)
+	}
+
+	/// Enable or disable checking of `xml:id` attributes.
+	///
+	/// See [`NamespaceResolver::set_check_xml_ids`] for details.
+	pub fn set_check_xml_ids(&mut self, enable: bool) {
+		self.resolver.set_check_xml_ids(enable)
+	}
+
+	/// Enable or disable lenient handling of undeclared namespace prefixes.
+	///
+	/// See [`NamespaceResolver::set_lenient_namespaces`] for details.
+	pub fn set_lenient_namespaces(&mut self, enable: bool) {
+		self.resolver.set_lenient_namespaces(enable)
+	}
+
+	/// Limit the number of namespace declarations on a single element.
+	///
+	/// See [`NamespaceResolver::set_max_namespace_declarations_per_element`]
+	/// for details.
+	pub fn set_max_namespace_declarations_per_element(&mut self, limit: Option<usize>) {
+		self.resolver
+			.set_max_namespace_declarations_per_element(limit)
+	}
+
+	/// Limit the total number of namespace declarations in the document.
+	///
+	/// See [`NamespaceResolver::set_max_namespace_declarations`] for
+	/// details.
+	pub fn set_max_namespace_declarations(&mut self, limit: Option<usize>) {
+		self.resolver.set_max_namespace_declarations(limit)
+	}
+
+	/// Return and clear the diagnostics recorded so far.
+	///
+	/// See [`NamespaceResolver::take_diagnostics`] for details.
+	pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+		self.resolver.take_diagnostics()
+	}
+
+	/// Return the number of bytes of input which correspond to the events
+	/// returned so far.
+	///
+	/// As events are consecutive, this is the offset (relative to the start
+	/// of the document) at which the next event starts.
+	pub fn position(&self) -> u64 {
+		self.ranges.position()
+	}
+
+	/// Return the byte range of a child of the root element which has just
+	/// been completed.
+	///
+	/// If the last event returned by the parser was the end of a direct
+	/// child of the root element (e.g. a stanza in an XMPP stream), this
+	/// returns the range of input bytes, relative to the start of the
+	/// document, from the start of its header to the end of its footer.
+	/// Otherwise, `None` is returned.
+	///
+	/// This allows to slice the original bytes of the child out of a receive
+	/// buffer, for instance for logging or forwarding, without serializing
+	/// it again.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::{FeedParser, ResolvedEvent};
+	///
+	/// let doc = &b"<stream><message to='x'>hi</message> <presence/></stream>"[..];
+	/// let mut fp = FeedParser::default();
+	/// let mut buf = doc;
+	/// let mut children = Vec::new();
+	/// while let Some(_) = fp.parse(&mut buf, true).unwrap() {
+	/// 	if let Some(range) = fp.get_parser().completed_child() {
+	/// 		children.push(&doc[range.start as usize..range.end as usize]);
+	/// 	}
+	/// }
+	/// assert_eq!(children, [&b"<message to='x'>hi</message>"[..], &b"<presence/>"[..]]);
+	/// ```
+	pub fn completed_child(&self) -> Option<Range<u64>> {
+		self.ranges.completed()
+	}
+
+	/// Return statistics about the events returned so far.
+	///
+	/// See [`Statistics`] for details.
+	pub fn statistics(&self) -> Statistics {
+		let mut stats = self.stats;
+		stats.set_namespace_declarations(self.resolver.namespace_declarations());
+		stats
+	}
+
+	/// Call `hook` whenever at least `every` bytes have been consumed since
+	/// the previous call, and once more after the end of the root element.
+	///
+	/// The hook is called from within the parser, right after the event
+	/// which crossed the threshold has been produced. Progress is measured
+	/// in bytes of events returned (see [`position`](Self::position)), so
+	/// it lags behind the reader by the data which is still buffered. This
+	/// allows to render a progress bar for large documents without wrapping
+	/// the data source.
+	///
+	/// Replaces any previously set hook. Clones of the parser share the
+	/// hook.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #[cfg(feature = "std")]
+	/// # fn main() {
+	/// use rxml::{EventRead, PullParser};
+	/// use std::sync::{Arc, Mutex};
+	///
+	/// let reports = Arc::new(Mutex::new(Vec::new()));
+	/// let doc = b"<a><b>some text</b><b>some more text</b></a>";
+	/// let mut pp = PullParser::new(&doc[..]);
+	/// let r = reports.clone();
+	/// pp.get_parser_mut().set_progress_hook(16, move |bytes, stats| {
+	/// 	r.lock().unwrap().push((bytes, stats.events()));
+	/// });
+	/// pp.read_all(|_| ()).unwrap();
+	/// assert_eq!(*reports.lock().unwrap(), [(19, 4), (36, 6), (44, 8)]);
+	/// # }
+	/// # #[cfg(not(feature = "std"))]
+	/// # fn main() {}
+	/// ```
+	pub fn set_progress_hook<F: Fn(u64, &Statistics) + Send + Sync + 'static>(
+		&mut self,
+		every: u64,
+		hook: F,
+	) {
+		self.progress = Some(stats::Progress::new(every, RcPtr::new(hook)));
+	}
+
+	/// Remove the hook set with [`set_progress_hook`](Self::set_progress_hook).
+	pub fn clear_progress_hook(&mut self) {
+		self.progress = None;
+	}
+
+	/// Limit the total memory used for parsing a document to `budget`
+	/// bytes.
+	///
+	/// The budget covers the data buffered by the lexer (including tokens
+	/// which are in progress), the element stack, the namespace
+	/// declarations in scope, the element which is currently being parsed
+	/// and `xml:id` values seen so far. Data held by the source of the
+	/// parser (e.g. a [`QueueReader`](crate::QueueReader) or the buffer of a
+	/// [`std::io::BufRead`]) and the shared [`Context`](crate::Context) are
+	/// not covered.
+	///
+	/// The memory use is estimated and checked after each step of the
+	/// parser. It can thus exceed the budget by the size of a single token,
+	/// which is bounded by [`LexerOptions::max_token_length`]. Note that
+	/// with the default [`BufferGrowth::Eager`], the lexer allocates a
+	/// buffer of that size up front, which counts against the budget.
+	///
+	/// Once the budget is exceeded, [`Error::MemoryBudgetExceeded`] is
+	/// returned and the parser cannot continue. `None`, the default,
+	/// disables the check.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::{Error, FeedParser};
+	///
+	/// let mut fp = FeedParser::default();
+	/// fp.get_parser_mut().set_memory_budget(Some(16 * 1024));
+	/// let doc = "<a>".repeat(1000);
+	/// let mut events = 0;
+	/// match fp.parse_all(&mut doc.as_bytes(), false, |_| events += 1) {
+	/// 	Err(Error::MemoryBudgetExceeded(16384)) => (),
+	/// 	other => panic!("unexpected result: {:?}", other),
+	/// }
+	/// assert!(events > 0 && events < 1000);
+	/// ```
+	///
+	///   [`LexerOptions::max_token_length`]: crate::LexerOptions::max_token_length
+	///   [`BufferGrowth::Eager`]: crate::lexer::BufferGrowth::Eager
+	pub fn set_memory_budget(&mut self, budget: Option<usize>) {
+		self.memory_budget = budget;
+	}
+
+	/// Return the memory budget set with
+	/// [`set_memory_budget`](Self::set_memory_budget).
+	pub fn memory_budget(&self) -> Option<usize> {
+		self.memory_budget
+	}
+
+	/// Estimate the number of bytes of memory used by the parser.
+	///
+	/// This does not include the lexer (see
+	/// [`Lexer::memory_used`](crate::Lexer::memory_used)) and the shared
+	/// context.
+	pub fn memory_used(&self) -> usize {
+		self.inner.memory_used() + self.resolver.memory_used()
+	}
+
+	fn check_memory_budget_exceeded(&self) -> Result<()> {
+		match self.memory_budget_exceeded {
+			Some(budget) => Err(Error::MemoryBudgetExceeded(budget)),
+			None => Ok(()),
+		}
+	}
+
+	/// Check the memory use after a step of the parser which returned
+	/// `result`.
+	fn enforce_memory_budget<T, R: TokenRead>(&mut self, r: &R, result: Result<T>) -> Result<T> {
+		let budget = match self.memory_budget {
+			Some(budget) => budget,
+			None => return result,
+		};
+		match result.as_ref() {
+			// the parser is dead anyway
+			Err(e) if !e.is_io() => return result,
+			_ => (),
+		}
+		if self.memory_used().saturating_add(r.memory_used()) > budget {
+			self.memory_budget_exceeded = Some(budget);
+			return Err(Error::MemoryBudgetExceeded(budget));
+		}
+		result
+	}
+
+	fn report_progress(&mut self) {
+		let position = self.ranges.position();
+		let finished = self.inner.is_document_complete();
+		let hook = match self.progress.as_mut() {
+			Some(progress) => {
+				if !progress.due(position, finished) {
+					return;
+				}
+				RcPtr::clone(&progress.hook)
+			}
+			None => return,
+		};
+		hook(position, &self.statistics());
+	}
+}
+
 impl WithContext for Parser {
 	fn with_context(ctx: RcPtr<context::Context>) -> Self {
 		Self {
 			inner: RawParser::new(),
 			resolver: NamespaceResolver::with_context(ctx),
+			ranges: framing::ChildRanges::default(),
+			stats: Statistics::default(),
+			progress: None,
+			memory_budget: None,
+			memory_budget_exceeded: None,
 		}
 	}
 }
@@ -88,12 +498,28 @@ impl Parse for Parser {
 	type Output = ResolvedEvent;
 
 	fn parse<R: TokenRead>(&mut self, r: &mut R) -> Result<Option<Self::Output>> {
+		self.check_memory_budget_exceeded()?;
 		let inner = &mut self.inner;
-		self.resolver.next(|| inner.parse(r))
+		let position = self.ranges.position();
+		let result = self
+			.resolver
+			.next(|| inner.parse(r))
+			.map_err(|e| e.with_position(position));
+		if let Ok(Some(ev)) = result.as_ref() {
+			self.ranges.observe(ev);
+			self.stats.observe(ev);
+			self.report_progress();
+		}
+		self.enforce_memory_budget(r, result)
+	}
+
+	fn is_document_complete(&self) -> bool {
+		self.inner.is_document_complete() && !self.resolver.has_pending()
 	}
 
 	fn release_temporaries(&mut self) {
 		self.inner.release_temporaries();
+		self.resolver.release_temporaries();
 		self.resolver.context().release_temporaries();
 	}
 }
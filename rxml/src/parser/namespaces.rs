@@ -1,18 +1,38 @@
-use std::borrow::Cow;
-use std::collections::hash_map::Entry;
-use std::collections::HashMap;
-#[cfg(not(feature = "mt"))]
-use std::rc::Rc;
-#[cfg(feature = "mt")]
-use std::sync::Arc;
+use alloc::borrow::Cow;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+use core::mem;
 
+use crate::collections::{set_with_capacity, Entry, Map, Set};
 use crate::context;
 use crate::errctx;
-use crate::error::{add_context, Error, Result, XmlError};
+use crate::error::{Error, NamespaceError, Result, XmlError};
+use crate::lexer::expand_attribute_references;
 use crate::strings::*;
+use crate::writer::PREFIX_XMLNS;
 
-use super::common::{EventMetrics, XmlVersion, XMLNS_XML};
+use super::attrs::Attributes;
+use super::common::{EventMetrics, XmlVersion, XMLNS_XML, XMLNS_XMLNS};
 use super::raw::{RawEvent, RawQName};
+use super::sink::{self, EventSink};
+
+/// Build the error for the use of an undeclared prefix in the name of an
+/// element or attribute.
+fn undeclared_prefix(prefix: NcName, local_name: &NcNameStr, attribute: bool) -> Error {
+	let name = prefix.clone().add_suffix(local_name);
+	let error = if attribute {
+		NamespaceError::new(XmlError::UndeclaredNamespacePrefix(errctx::ERRCTX_ATTNAME))
+			.with_attribute(name)
+	} else {
+		NamespaceError::new(XmlError::UndeclaredNamespacePrefix(errctx::ERRCTX_NAME))
+			.with_element(name)
+	};
+	error.with_prefix(prefix).into()
+}
 
 /// Shared namespace URI
 pub type NamespaceName = RcPtr<CData>;
@@ -23,16 +43,9 @@ pub type ResolvedQName = (Option<NamespaceName>, NcName);
 
 /// Wrapper pointer around namespace URIs
 ///
-/// In builds with the `mt` feature, this is a [`Arc`]. In non-`mt` builds,
-/// this is a [`std::rc::Rc`]
-#[cfg(feature = "mt")]
+/// This is an [`Arc`], so that events and parsers can be moved between
+/// threads.
 pub type RcPtr<T> = Arc<T>;
-/// Wrapper pointer around namespace URIs
-///
-/// In builds with the `mt` feature, this is a [`std::sync::Arc`].
-/// In non-`mt` builds, this is a [`Rc`].
-#[cfg(not(feature = "mt"))]
-pub type RcPtr<T> = Rc<T>;
 
 /**
 # High-level, logical XML document parts
@@ -48,9 +61,19 @@ point (for the caveats about observing a [`RawEvent`], see [`RawParser`]).
 Each event has [`EventMetrics`] attached which give information about the
 number of bytes from the input stream used to generate the event.
 
+New kinds of events may be added in minor releases (as happened with the
+opt-in [`StartPrefixMapping`](Self::StartPrefixMapping) and
+[`EndPrefixMapping`](Self::EndPrefixMapping) events), which is why
+`ResolvedEvent` is `#[non_exhaustive]`. This is a breaking change compared
+to 0.8: a `match` on an event outside this crate needs a wildcard arm.
+
    [`RawParser`]: crate::RawParser
 */
+// the inline attribute storage is what avoids allocations for most elements
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum ResolvedEvent {
 	/// The XML declaration.
 	///
@@ -74,8 +97,17 @@ pub enum ResolvedEvent {
 		/// The namespace URI / localpart pair of the element.
 		ResolvedQName,
 		/// Attributes declared on the element, without XML namespace
-		/// declarations.
-		HashMap<ResolvedQName, CData>,
+		/// declarations, in document order.
+		Attributes,
+		/// The prefix with which the name of the element was written in the
+		/// document, or `None` if it was not prefixed.
+		///
+		/// The prefix is informational only: the namespace URI in the name
+		/// is what identifies the namespace of the element. Together with
+		/// the name, it can be combined into an
+		/// [`ExpandedName`](crate::ExpandedName) with
+		/// [`ExpandedName::with_prefix`](crate::ExpandedName::with_prefix).
+		Option<NcName>,
 	),
 	/// The end of an XML element.
 	///
@@ -111,29 +143,278 @@ pub enum ResolvedEvent {
 		/// character data.
 		CData,
 	),
+
+	/// A namespace prefix mapping comes into scope.
+	///
+	/// These events are only emitted if enabled with
+	/// [`NamespaceResolver::set_emit_prefix_mappings`] (or the equivalent
+	/// method on [`Parser`](crate::Parser)). They are emitted right before
+	/// the [`Self::StartElement`] on which the mapping was declared, one
+	/// event per declaration, with the default namespace first and the
+	/// prefixes in lexicographic order.
+	StartPrefixMapping(
+		/// Number of bytes contributing to this event.
+		///
+		/// This is always zero; the bytes of the declaration are accounted
+		/// for in the corresponding [`Self::StartElement`].
+		EventMetrics,
+		/// The declared prefix, or `None` for the default namespace.
+		Option<NcName>,
+		/// The namespace name (URI) bound to the prefix.
+		///
+		/// This may be empty if the default namespace is reset with
+		/// `xmlns=''`.
+		NamespaceName,
+	),
+
+	/// A namespace prefix mapping goes out of scope.
+	///
+	/// These events are emitted right after the [`Self::EndElement`] of the
+	/// element on which the mapping was declared, in reverse order of the
+	/// corresponding [`Self::StartPrefixMapping`] events.
+	EndPrefixMapping(
+		/// Number of bytes contributing to this event.
+		///
+		/// This is always zero.
+		EventMetrics,
+		/// The prefix which goes out of scope, or `None` for the default
+		/// namespace.
+		Option<NcName>,
+	),
 }
 
 impl ResolvedEvent {
 	/// Return the [`EventMetrics`] of the event
 	pub fn metrics(&self) -> &EventMetrics {
 		match self {
-			Self::XmlDeclaration(m, ..) => &m,
-			Self::StartElement(m, ..) => &m,
-			Self::EndElement(m, ..) => &m,
-			Self::Text(m, ..) => &m,
+			Self::XmlDeclaration(m, ..) => m,
+			Self::StartElement(m, ..) => m,
+			Self::EndElement(m, ..) => m,
+			Self::Text(m, ..) => m,
+			Self::StartPrefixMapping(m, ..) => m,
+			Self::EndPrefixMapping(m, ..) => m,
 		}
 	}
 }
 
+/**
+# XML event borrowing from the input
+
+This is the counterpart of [`ResolvedEvent`] returned by the
+[`SliceParser`](crate::SliceParser), which parses a document which is
+completely held in memory. Text refers to the input wherever it appears
+literally in it; only text which had to be transformed (because of
+references, CDATA sections or line endings) is owned. Element names,
+attribute names and attribute values are owned, exactly as in
+[`ResolvedEvent::StartElement`]; only text borrows from the input.
+
+Use [`BorrowedEvent::into_owned`] to convert the event into a
+[`ResolvedEvent`] which does not borrow from the input.
+
+Like [`ResolvedEvent`], this enum is `#[non_exhaustive]`.
+*/
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum BorrowedEvent<'a> {
+	/// The XML declaration.
+	///
+	/// See [`ResolvedEvent::XmlDeclaration`].
+	XmlDeclaration(EventMetrics, XmlVersion),
+	/// The start of an XML element.
+	///
+	/// See [`ResolvedEvent::StartElement`].
+	StartElement(EventMetrics, ResolvedQName, Attributes, Option<NcName>),
+	/// The end of an XML element.
+	///
+	/// See [`ResolvedEvent::EndElement`].
+	EndElement(EventMetrics),
+	/// Text CData.
+	///
+	/// See [`ResolvedEvent::Text`].
+	Text(EventMetrics, Cow<'a, CDataStr>),
+	/// A namespace prefix mapping comes into scope.
+	///
+	/// See [`ResolvedEvent::StartPrefixMapping`].
+	StartPrefixMapping(EventMetrics, Option<NcName>, NamespaceName),
+	/// A namespace prefix mapping goes out of scope.
+	///
+	/// See [`ResolvedEvent::EndPrefixMapping`].
+	EndPrefixMapping(EventMetrics, Option<NcName>),
+}
+
+impl<'a> BorrowedEvent<'a> {
+	/// Return the [`EventMetrics`] of the event
+	pub fn metrics(&self) -> &EventMetrics {
+		match self {
+			Self::XmlDeclaration(m, ..) => m,
+			Self::StartElement(m, ..) => m,
+			Self::EndElement(m, ..) => m,
+			Self::Text(m, ..) => m,
+			Self::StartPrefixMapping(m, ..) => m,
+			Self::EndPrefixMapping(m, ..) => m,
+		}
+	}
+
+	/// Convert the event into a [`ResolvedEvent`], copying borrowed data.
+	pub fn into_owned(self) -> ResolvedEvent {
+		match self {
+			Self::XmlDeclaration(m, v) => ResolvedEvent::XmlDeclaration(m, v),
+			Self::StartElement(m, name, attrs, prefix) => {
+				ResolvedEvent::StartElement(m, name, attrs, prefix)
+			}
+			Self::EndElement(m) => ResolvedEvent::EndElement(m),
+			Self::Text(m, text) => ResolvedEvent::Text(m, text.into_owned()),
+			Self::StartPrefixMapping(m, prefix, ns) => {
+				ResolvedEvent::StartPrefixMapping(m, prefix, ns)
+			}
+			Self::EndPrefixMapping(m, prefix) => ResolvedEvent::EndPrefixMapping(m, prefix),
+		}
+	}
+}
+
+impl From<ResolvedEvent> for BorrowedEvent<'static> {
+	fn from(other: ResolvedEvent) -> Self {
+		match other {
+			ResolvedEvent::XmlDeclaration(m, v) => Self::XmlDeclaration(m, v),
+			ResolvedEvent::StartElement(m, name, attrs, prefix) => {
+				Self::StartElement(m, name, attrs, prefix)
+			}
+			ResolvedEvent::EndElement(m) => Self::EndElement(m),
+			ResolvedEvent::Text(m, text) => Self::Text(m, Cow::Owned(text)),
+			ResolvedEvent::StartPrefixMapping(m, prefix, ns) => {
+				Self::StartPrefixMapping(m, prefix, ns)
+			}
+			ResolvedEvent::EndPrefixMapping(m, prefix) => Self::EndPrefixMapping(m, prefix),
+		}
+	}
+}
+
+/**
+# Non-fatal problem in a document
+
+Diagnostics are reported instead of errors for violations which the parser
+has been asked to tolerate, such as undeclared namespace prefixes in
+[lenient mode](NamespaceResolver::set_lenient_namespaces). They can be
+obtained with [`NamespaceResolver::take_diagnostics`] (or the equivalent
+method on [`Parser`](crate::Parser)) or are passed to
+[`EventSink::diagnostic`].
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Diagnostic {
+	/// An undeclared namespace prefix was used in the name of an element or
+	/// attribute.
+	///
+	/// The name has been reported without namespace, i.e. as
+	/// `(None, local_name)`.
+	UndeclaredNamespacePrefix {
+		/// The prefix as it occurred in the document.
+		prefix: NcName,
+		/// The local name which was reported.
+		local_name: NcName,
+		/// True if the name is the name of an attribute, false if it is the
+		/// name of an element.
+		attribute: bool,
+	},
+
+	/// An attribute with an undeclared namespace prefix was dropped,
+	/// because its name collided with another attribute of the element
+	/// once the prefix had been removed.
+	///
+	/// This is always preceded by a corresponding
+	/// [`Self::UndeclaredNamespacePrefix`].
+	DroppedAttribute {
+		/// The prefix as it occurred in the document.
+		prefix: NcName,
+		/// The local name of the attribute.
+		local_name: NcName,
+	},
+}
+
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::UndeclaredNamespacePrefix {
+				prefix,
+				local_name,
+				attribute,
+			} => write!(
+				f,
+				"undeclared namespace prefix in {} name {}:{}",
+				if *attribute { "attribute" } else { "element" },
+				prefix,
+				local_name
+			),
+			Self::DroppedAttribute { prefix, local_name } => write!(
+				f,
+				"dropped attribute {}:{} colliding with another attribute",
+				prefix, local_name
+			),
+		}
+	}
+}
+
+/// Generate an optional namespace name which can be declared in a document.
+///
+/// The empty namespace name and the namespace name reserved for `xmlns` are
+/// replaced by `urn:example`.
+#[cfg(feature = "arbitrary")]
+pub(crate) fn arbitrary_namespace(
+	u: &mut arbitrary::Unstructured<'_>,
+) -> arbitrary::Result<Option<NamespaceName>> {
+	if !u.arbitrary::<bool>()? {
+		return Ok(None);
+	}
+	let ns: CData = u.arbitrary()?;
+	if ns.is_empty() || ns == XMLNS_XMLNS {
+		let fallback = CDataStr::from_str("urn:example").unwrap();
+		return Ok(Some(RcPtr::new(fallback.to_cdata())));
+	}
+	Ok(Some(RcPtr::new(ns)))
+}
+
+/// Generates element, text and XML declaration events with zero-length
+/// metrics.
+///
+/// Prefix mapping events are never generated. The events are valid on their
+/// own, but a sequence of them generally does not form a well-formed
+/// document.
+#[cfg(feature = "arbitrary")]
+#[cfg_attr(docsrs, doc(cfg(feature = "arbitrary")))]
+impl<'a> arbitrary::Arbitrary<'a> for ResolvedEvent {
+	fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+		let m = EventMetrics::new(0);
+		Ok(match u.int_in_range(0..=3u8)? {
+			0 => Self::XmlDeclaration(m, XmlVersion::V1_0),
+			1 => Self::StartElement(
+				m,
+				(arbitrary_namespace(u)?, u.arbitrary()?),
+				u.arbitrary()?,
+				None,
+			),
+			2 => Self::EndElement(m),
+			_ => Self::Text(m, u.arbitrary()?),
+		})
+	}
+}
+
+/// Number of attributes up to which duplicates are detected by a linear
+/// scan instead of a hash set.
+const LINEAR_DUPLICATE_CHECK_LIMIT: usize = 16;
+
+#[derive(Clone)]
 enum State {
 	Initial,
 	Element,
 }
 
+#[derive(Clone)]
 struct ElementScratchpad {
 	phyqname: RawQName,
 	default_decl: Option<NamespaceName>,
-	nsdecl: HashMap<NcName, NamespaceName>,
+	nsdecl: Map<NcName, NamespaceName>,
 }
 
 impl ElementScratchpad {
@@ -141,7 +422,7 @@ impl ElementScratchpad {
 		Self {
 			phyqname,
 			default_decl: None,
-			nsdecl: HashMap::new(),
+			nsdecl: Map::new(),
 		}
 	}
 }
@@ -154,21 +435,103 @@ namespace names (URIs), as described in Namespaces for XML 1.0. It takes
 [`RawEvent`] structs and combines/converts them into [`ResolvedEvent`]
 structs.
 
+## Pre-declared namespace prefixes
+
+When parsing fragments of a larger document, prefixes may already be bound
+by an outer scope which is not part of the fragment. Such bindings can be
+declared up front using [`NamespaceResolver::declare_prefix`]; they then
+behave as if they were declared on an (invisible) element enclosing the
+document.
+
+## Prefix mapping events
+
+By default, namespace declarations are consumed by the resolver and only the
+resolved names are reported. Applications which need to observe the
+declarations themselves (e.g. for faithful rewriting or canonicalization) can
+enable [`ResolvedEvent::StartPrefixMapping`] and
+[`ResolvedEvent::EndPrefixMapping`] events with
+[`NamespaceResolver::set_emit_prefix_mappings`].
+
 ## Caveat
 
 This struct does *not* validate that the sequence of [`RawEvent`] structs it
 is fed is actually a well-formed XML document. For instance, it will happily
 forward a [`RawEvent::Text`] right after a [`RawEvent::ElementHeadOpen`].
 */
+#[derive(Clone)]
 pub struct NamespaceResolver {
 	ctx: RcPtr<context::Context>,
 	fixed_xml_namespace: NamespaceName,
-	namespace_stack: Vec<(Option<NamespaceName>, HashMap<NcName, NamespaceName>)>,
+	fixed_xmlns_namespace: NamespaceName,
+	namespace_stack: Vec<NamespaceScope>,
+	/// Estimated heap size of the scopes in `namespace_stack`.
+	namespace_stack_bytes: usize,
 	scratchpad: Option<ElementScratchpad>,
-	phyattributes: Vec<(RawQName, CData)>,
+	/// Attributes of the current element; the flag is set for values with
+	/// unexpanded references.
+	phyattributes: Vec<(RawQName, CData, bool)>,
+	/// Attribute storage reused across elements by [`Self::next_into`].
+	attribute_buffer: Attributes,
 	event_length_accum: usize,
 	state: State,
 	poison: Option<Error>,
+	emit_prefix_mappings: bool,
+	emit_xmlns_attributes: bool,
+	pending: VecDeque<ResolvedEvent>,
+	/// `xml:id` values seen so far, if `xml:id` checking is enabled.
+	xml_ids: Option<Set<CData>>,
+	/// Total length of the values in `xml_ids`.
+	xml_ids_bytes: usize,
+	lenient_namespaces: bool,
+	diagnostics: Vec<Diagnostic>,
+	/// Number of namespace declarations seen in the document.
+	namespace_declarations: u64,
+	max_namespace_declarations_per_element: Option<usize>,
+	max_namespace_declarations: Option<usize>,
+}
+
+type NamespaceScope = (Option<NamespaceName>, Map<NcName, NamespaceName>);
+
+/// Estimate the heap size of a namespace scope.
+///
+/// Namespace names are counted for each declaration, even though they may be
+/// shared with other scopes or parsers.
+fn scope_size(scope: &NamespaceScope) -> usize {
+	let (default_decl, decls) = scope;
+	let entry_size = mem::size_of::<(NcName, NamespaceName)>();
+	default_decl.as_ref().map_or(0, |nsuri| nsuri.len())
+		+ decls
+			.iter()
+			.map(|(prefix, nsuri)| entry_size + prefix.len() + nsuri.len())
+			.sum::<usize>()
+}
+
+/// Intern a local name in the context if it is too long to be stored inline.
+///
+/// Short names are cheaper to copy than to look up.
+fn intern_localname(ctx: &context::Context, name: NcName) -> NcName {
+	if name.len() > smartstring::MAX_INLINE {
+		ctx.intern_ncname(name)
+	} else {
+		name
+	}
+}
+
+/// Return the declarations of a scope in the order in which prefix mapping
+/// events are emitted.
+fn scope_mappings(scope: &NamespaceScope) -> Vec<(Option<NcName>, NamespaceName)> {
+	let (default_decl, decls) = scope;
+	let mut result = Vec::with_capacity(decls.len() + 1);
+	if let Some(nsuri) = default_decl.as_ref() {
+		result.push((None, nsuri.clone()));
+	}
+	let mut prefixed: Vec<_> = decls
+		.iter()
+		.map(|(prefix, nsuri)| (Some(prefix.clone()), nsuri.clone()))
+		.collect();
+	prefixed.sort_by(|a, b| a.0.cmp(&b.0));
+	result.extend(prefixed);
+	result
 }
 
 impl NamespaceResolver {
@@ -181,16 +544,311 @@ impl NamespaceResolver {
 	/// Create a new namespace resolver with the given [`context::Context`].
 	pub fn with_context(ctx: RcPtr<context::Context>) -> Self {
 		let fixed_xml_namespace = ctx.intern_cdata(Cow::Borrowed(XMLNS_XML));
+		let fixed_xmlns_namespace = ctx.intern_cdata(Cow::Borrowed(XMLNS_XMLNS));
 		Self {
 			ctx,
 			fixed_xml_namespace,
-			namespace_stack: Vec::new(),
+			fixed_xmlns_namespace,
+			// the bottom-most entry holds the pre-declared prefixes and is
+			// never popped
+			namespace_stack: vec![(None, Map::new())],
+			namespace_stack_bytes: 0,
 			phyattributes: Vec::new(),
+			attribute_buffer: Attributes::new(),
 			scratchpad: None,
 			event_length_accum: 0,
 			state: State::Initial,
 			poison: None,
+			emit_prefix_mappings: false,
+			emit_xmlns_attributes: false,
+			pending: VecDeque::new(),
+			xml_ids: None,
+			xml_ids_bytes: 0,
+			lenient_namespaces: false,
+			diagnostics: Vec::new(),
+			namespace_declarations: 0,
+			max_namespace_declarations_per_element: None,
+			max_namespace_declarations: None,
+		}
+	}
+
+	/// Enable or disable the emission of
+	/// [`ResolvedEvent::StartPrefixMapping`] and
+	/// [`ResolvedEvent::EndPrefixMapping`] events.
+	///
+	/// This is disabled by default. Changing the setting while inside an
+	/// element may lead to unbalanced start/end prefix mapping events.
+	pub fn set_emit_prefix_mappings(&mut self, enable: bool) {
+		self.emit_prefix_mappings = enable;
+	}
+
+	/// Return whether prefix mapping events are emitted.
+	pub fn emit_prefix_mappings(&self) -> bool {
+		self.emit_prefix_mappings
+	}
+
+	/// Enable or disable reporting namespace declarations as attributes.
+	///
+	/// If enabled, the `xmlns` and `xmlns:*` attributes of an element are
+	/// included in the attributes of its [`ResolvedEvent::StartElement`],
+	/// in addition to being used for resolving names. Following the XML
+	/// Information Set, they are in the [`XMLNS_XMLNS`] namespace: the local
+	/// name of `xmlns:foo` is `foo` and the local name of `xmlns` is
+	/// `xmlns`. The value is the namespace name as declared, which is empty
+	/// for an undeclaration of the default namespace (`xmlns=''`). As
+	/// regular attributes cannot be in that namespace, declarations are
+	/// easy to tell apart from them.
+	///
+	/// The default namespace declaration comes first, followed by the
+	/// prefixed declarations ordered by prefix, followed by the regular
+	/// attributes.
+	///
+	/// This is disabled by default. [`Encoder::encode_event`] skips these
+	/// attributes, as the encoder manages namespace declarations on its
+	/// own.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::{FeedParser, ResolvedEvent, XMLNS_XMLNS};
+	///
+	/// let mut fp = FeedParser::default();
+	/// fp.get_parser_mut().set_emit_xmlns_attributes(true);
+	/// let ev = fp.parse(&mut &b"<a xmlns='urn:a' xmlns:b='urn:b' x='y'/>"[..], true).unwrap();
+	/// match ev {
+	/// 	Some(ResolvedEvent::StartElement(_, _, attrs, _)) => {
+	/// 		assert_eq!(&*attrs.get_by_name(Some(XMLNS_XMLNS), "xmlns").unwrap(), "urn:a");
+	/// 		assert_eq!(&*attrs.get_by_name(Some(XMLNS_XMLNS), "b").unwrap(), "urn:b");
+	/// 		assert_eq!(&*attrs.get_by_name(None, "x").unwrap(), "y");
+	/// 		assert_eq!(attrs.len(), 3);
+	/// 	}
+	/// 	other => panic!("unexpected event: {:?}", other),
+	/// }
+	/// ```
+	///
+	///    [`Encoder::encode_event`]: crate::Encoder::encode_event
+	pub fn set_emit_xmlns_attributes(&mut self, enable: bool) {
+		self.emit_xmlns_attributes = enable;
+	}
+
+	/// Return whether namespace declarations are reported as attributes.
+	pub fn emit_xmlns_attributes(&self) -> bool {
+		self.emit_xmlns_attributes
+	}
+
+	/// Enable or disable checking of `xml:id` attributes.
+	///
+	/// If enabled, the value of each `xml:id` attribute must be a valid
+	/// NCName ([`XmlError::InvalidXmlId`]) and must not have been used by
+	/// any other `xml:id` attribute in the document before
+	/// ([`XmlError::DuplicateXmlId`]), as required by the xml:id
+	/// recommendation. Values are normalized as attributes of type ID
+	/// before checking them, i.e. leading and trailing spaces are ignored.
+	/// The attribute value reported in the event is not modified.
+	///
+	/// This is disabled by default. As all values have to be kept until the
+	/// end of the document, memory use grows with the number of `xml:id`
+	/// attributes; this should be kept in mind when parsing long-lived
+	/// streams. Disabling the check forgets all values seen so far.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::{Error, FeedParser, error::XmlError};
+	///
+	/// let mut fp = FeedParser::default();
+	/// fp.get_parser_mut().set_check_xml_ids(true);
+	/// let mut doc = &b"<a><b xml:id='x'/><c xml:id='x'/></a>"[..];
+	/// match fp.parse_all(&mut doc, true, |_| ()) {
+	/// 	Err(Error::Xml(XmlError::DuplicateXmlId)) => (),
+	/// 	other => panic!("unexpected result: {:?}", other),
+	/// }
+	/// ```
+	pub fn set_check_xml_ids(&mut self, enable: bool) {
+		if !enable {
+			self.xml_ids = None;
+			self.xml_ids_bytes = 0;
+		} else if self.xml_ids.is_none() {
+			self.xml_ids = Some(Set::new());
+		}
+	}
+
+	/// Return whether `xml:id` attributes are checked.
+	pub fn check_xml_ids(&self) -> bool {
+		self.xml_ids.is_some()
+	}
+
+	/// Enable or disable lenient handling of undeclared namespace prefixes.
+	///
+	/// By default, the use of an undeclared prefix is an error
+	/// ([`XmlError::UndeclaredNamespacePrefix`]), as required by Namespaces
+	/// in XML 1.0. In lenient mode, such names are reported without a
+	/// namespace instead, and a [`Diagnostic::UndeclaredNamespacePrefix`]
+	/// preserving the prefix is recorded. This allows to keep processing
+	/// documents from sources which are known to be broken in that way.
+	///
+	/// Dropping the prefix may make attribute names collide (e.g. `a:x` and
+	/// `b:x`, or `a:x` and `x`). Such collisions are resolved as follows:
+	/// attributes whose name could be resolved take precedence, and among
+	/// the attributes with an undeclared prefix, the first one in document
+	/// order is kept. The other attributes are dropped and a
+	/// [`Diagnostic::DroppedAttribute`] is recorded for each of them.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::{FeedParser, ResolvedEvent};
+	/// use rxml::parser::Diagnostic;
+	///
+	/// let mut fp = FeedParser::default();
+	/// fp.get_parser_mut().set_lenient_namespaces(true);
+	/// let ev = fp.parse(&mut &b"<media:thumbnail url='x'/>"[..], true).unwrap();
+	/// match ev {
+	/// 	Some(ResolvedEvent::StartElement(_, (None, name), _, _)) => assert_eq!(name, "thumbnail"),
+	/// 	other => panic!("unexpected event: {:?}", other),
+	/// }
+	/// match &fp.get_parser_mut().take_diagnostics()[..] {
+	/// 	[Diagnostic::UndeclaredNamespacePrefix { prefix, .. }] => assert_eq!(prefix, "media"),
+	/// 	other => panic!("unexpected diagnostics: {:?}", other),
+	/// }
+	/// ```
+	pub fn set_lenient_namespaces(&mut self, enable: bool) {
+		self.lenient_namespaces = enable;
+	}
+
+	/// Return whether undeclared namespace prefixes are tolerated.
+	pub fn lenient_namespaces(&self) -> bool {
+		self.lenient_namespaces
+	}
+
+	/// Limit the number of namespace declarations on a single element.
+	///
+	/// Declarations of the default namespace count as well. If an element
+	/// declares more namespaces, [`Error::NamespaceDeclarationLimitExceeded`]
+	/// is returned before the excess declaration is processed. `None`, the
+	/// default, disables the limit.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::{Error, FeedParser};
+	///
+	/// let mut fp = FeedParser::default();
+	/// fp.get_parser_mut().set_max_namespace_declarations_per_element(Some(2));
+	/// let doc = b"<a xmlns='urn:a' xmlns:b='urn:b' xmlns:c='urn:c'/>";
+	/// match fp.parse_all(&mut &doc[..], true, |_| ()) {
+	/// 	Err(Error::NamespaceDeclarationLimitExceeded(2)) => (),
+	/// 	other => panic!("unexpected result: {:?}", other),
+	/// }
+	/// ```
+	pub fn set_max_namespace_declarations_per_element(&mut self, limit: Option<usize>) {
+		self.max_namespace_declarations_per_element = limit;
+	}
+
+	/// Return the limit set with
+	/// [`set_max_namespace_declarations_per_element`](Self::set_max_namespace_declarations_per_element).
+	pub fn max_namespace_declarations_per_element(&self) -> Option<usize> {
+		self.max_namespace_declarations_per_element
+	}
+
+	/// Limit the total number of namespace declarations in the document.
+	///
+	/// This counts all declarations on all elements processed so far, as
+	/// reported by [`Statistics::namespace_declarations`]; pre-declared
+	/// prefixes do not count. Exceeding the limit causes
+	/// [`Error::NamespaceDeclarationLimitExceeded`]. `None`, the default,
+	/// disables the limit.
+	///
+	///   [`Statistics::namespace_declarations`]: crate::parser::Statistics::namespace_declarations
+	pub fn set_max_namespace_declarations(&mut self, limit: Option<usize>) {
+		self.max_namespace_declarations = limit;
+	}
+
+	/// Return the limit set with
+	/// [`set_max_namespace_declarations`](Self::set_max_namespace_declarations).
+	pub fn max_namespace_declarations(&self) -> Option<usize> {
+		self.max_namespace_declarations
+	}
+
+	/// Return and clear the diagnostics recorded so far.
+	///
+	/// Diagnostics are recorded while resolving the element which caused
+	/// them, so all diagnostics for an element are available once its
+	/// [`ResolvedEvent::StartElement`] has been returned. They accumulate
+	/// until they are taken; applications which enable lenient modes on
+	/// long-lived streams should thus take them regularly.
+	///
+	/// When the events are passed to an [`EventSink`] instead, diagnostics
+	/// are passed to [`EventSink::diagnostic`] right before the
+	/// corresponding [`EventSink::start_element`] and are not recorded.
+	pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+		core::mem::take(&mut self.diagnostics)
+	}
+
+	/// Declare a namespace prefix binding in the scope enclosing the
+	/// document.
+	///
+	/// If `prefix` is `None`, the default namespace is declared. Declarations
+	/// made in the document itself take precedence over pre-declared
+	/// bindings, as usual. Declaring the same prefix again replaces the
+	/// previous binding.
+	///
+	/// The constraints of Namespaces in XML 1.0 on reserved prefixes and
+	/// namespace names apply: the `xmlns` prefix cannot be declared, the
+	/// `xml` prefix can only be bound to its fixed namespace name and no
+	/// other prefix can be bound to the `xml` or `xmlns` namespace names.
+	/// Prefixes cannot be bound to the empty namespace name.
+	pub fn declare_prefix(&mut self, prefix: Option<NcName>, nsuri: CData) -> Result<()> {
+		let violation = match prefix.as_ref().map(|x| x.as_str()) {
+			_ if nsuri == XMLNS_XMLNS => XmlError::ReservedNamespaceName,
+			Some("xmlns") => XmlError::ReservedNamespacePrefix,
+			// implicitly declared anyway
+			Some("xml") if nsuri == XMLNS_XML => return Ok(()),
+			Some("xml") => XmlError::ReservedNamespacePrefix,
+			_ if nsuri == XMLNS_XML => XmlError::ReservedNamespaceName,
+			Some(_) if nsuri.is_empty() => XmlError::EmptyNamespaceUri,
+			Some(_) => {
+				let nsuri = self.ctx.intern_cdata(nsuri);
+				self.update_predeclared(|scope| {
+					scope.1.insert(prefix.unwrap(), nsuri);
+				});
+				return Ok(());
+			}
+			None => {
+				let nsuri = self.ctx.intern_cdata(nsuri);
+				self.update_predeclared(|scope| scope.0 = Some(nsuri));
+				return Ok(());
+			}
+		};
+		let error = NamespaceError::new(violation);
+		Err(match prefix {
+			Some(prefix) => error.with_prefix(prefix),
+			None => error,
 		}
+		.into())
+	}
+
+	/// Modify the scope of the pre-declared prefixes, keeping the memory
+	/// accounting up to date.
+	fn update_predeclared<F: FnOnce(&mut NamespaceScope)>(&mut self, f: F) {
+		self.namespace_stack_bytes -= scope_size(&self.namespace_stack[0]);
+		f(&mut self.namespace_stack[0]);
+		self.namespace_stack_bytes += scope_size(&self.namespace_stack[0]);
+	}
+
+	/// Estimate the number of bytes of memory used by the resolver.
+	///
+	/// This covers the namespace scopes, the attributes and the length of
+	/// the element which are currently in progress, queued events and the
+	/// `xml:id` values seen so far.
+	pub(crate) fn memory_used(&self) -> usize {
+		self.namespace_stack.capacity() * mem::size_of::<NamespaceScope>()
+			+ self.namespace_stack_bytes
+			+ self.phyattributes.capacity() * mem::size_of::<(RawQName, CData, bool)>()
+			+ self.event_length_accum
+			+ self.pending.capacity() * mem::size_of::<ResolvedEvent>()
+			+ self.xml_ids_bytes
+			+ self.diagnostics.capacity() * mem::size_of::<Diagnostic>()
 	}
 
 	fn check_poison(&self) -> Result<()> {
@@ -207,7 +865,32 @@ impl NamespaceResolver {
 		Ok(())
 	}
 
-	fn push_attribute(&mut self, phyqn: RawQName, value: CData) -> Result<()> {
+	/// Check the limits on namespace declarations before a declaration is
+	/// added to the element in the scratchpad.
+	fn check_namespace_declaration_limits(&self) -> Result<()> {
+		if let Some(limit) = self.max_namespace_declarations_per_element {
+			let scratchpad = self.scratchpad.as_ref().unwrap();
+			let declared = scratchpad.nsdecl.len() + scratchpad.default_decl.is_some() as usize;
+			if declared >= limit {
+				return Err(Error::NamespaceDeclarationLimitExceeded(limit));
+			}
+		}
+		if let Some(limit) = self.max_namespace_declarations {
+			if self.namespace_declarations >= limit as u64 {
+				return Err(Error::NamespaceDeclarationLimitExceeded(limit));
+			}
+		}
+		Ok(())
+	}
+
+	fn push_attribute(&mut self, phyqn: RawQName, value: CData, unexpanded: bool) -> Result<()> {
+		let is_declaration = match phyqn.0.as_ref() {
+			Some(prefix) => prefix == "xmlns",
+			None => phyqn.1 == "xmlns",
+		};
+		if is_declaration {
+			self.check_namespace_declaration_limits()?;
+		}
 		let scratchpad = self.scratchpad.as_mut().unwrap();
 		if let Some(prefix) = phyqn.0.as_ref() {
 			if prefix == "xmlns" {
@@ -217,18 +900,20 @@ impl NamespaceResolver {
 					Entry::Occupied(_) => return Err(Error::Xml(XmlError::DuplicateAttribute)),
 					Entry::Vacant(e) => e.insert(self.ctx.intern_cdata(value)),
 				};
+				self.namespace_declarations += 1;
 				return Ok(());
 			}
 		} else if phyqn.1 == "xmlns" {
 			scratchpad.default_decl = Some(self.ctx.intern_cdata(value));
+			self.namespace_declarations += 1;
 			return Ok(());
 		}
-		self.phyattributes.push((phyqn, value));
+		self.phyattributes.push((phyqn, value, unexpanded));
 		Ok(())
 	}
 
 	fn lookup_prefix<'x>(
-		namespace_stack: &'x Vec<(Option<NamespaceName>, HashMap<NcName, NamespaceName>)>,
+		namespace_stack: &'x [NamespaceScope],
 		fixed_xml_namespace: &'x NamespaceName,
 		prefix: Option<&str>,
 	) -> Result<Option<&'x NamespaceName>> {
@@ -265,6 +950,21 @@ impl NamespaceResolver {
 	}
 
 	fn finish_element(&mut self) -> Result<ResolvedEvent> {
+		let mut attributes = Attributes::with_capacity(self.phyattributes.len());
+		let (metrics, qname, prefix) = self.resolve_element(&mut attributes)?;
+		Ok(ResolvedEvent::StartElement(
+			metrics, qname, attributes, prefix,
+		))
+	}
+
+	/// Resolve the element in the scratchpad, appending its attributes to
+	/// `attributes`.
+	///
+	/// Returns the metrics, the resolved name and the prefix of the element.
+	fn resolve_element(
+		&mut self,
+		attributes: &mut Attributes,
+	) -> Result<(EventMetrics, ResolvedQName, Option<NcName>)> {
 		let ElementScratchpad {
 			phyqname,
 			default_decl,
@@ -273,51 +973,139 @@ impl NamespaceResolver {
 		let len = self.event_length_accum;
 		self.event_length_accum = 0;
 
-		self.namespace_stack.push((default_decl, nsdecl));
+		let scope = (default_decl, nsdecl);
+		self.namespace_stack_bytes += scope_size(&scope);
+		self.namespace_stack.push(scope);
 
-		let mut attributes = HashMap::with_capacity(self.phyattributes.len());
-		for (phyqn, value) in self.phyattributes.drain(..) {
+		if self.emit_xmlns_attributes {
+			for (prefix, nsuri) in scope_mappings(self.namespace_stack.last().unwrap()) {
+				let name = prefix.unwrap_or_else(|| PREFIX_XMLNS.to_ncname());
+				// regular attributes can never be in the xmlns namespace, so
+				// these cannot collide with them
+				attributes.push_unchecked(
+					(Some(self.fixed_xmlns_namespace.clone()), name),
+					(*nsuri).clone(),
+				);
+			}
+		}
+
+		// linear duplicate checks are cheaper for the usual handful of
+		// attributes, but must not become quadratic on hostile input
+		let mut seen = if self.phyattributes.len() > LINEAR_DUPLICATE_CHECK_LIMIT {
+			Some(set_with_capacity(self.phyattributes.len()))
+		} else {
+			None
+		};
+		// attributes whose undeclared prefix has been dropped in lenient
+		// mode, with that prefix
+		let mut lenient: Vec<(ResolvedQName, NcName)> = Vec::new();
+		for (phyqn, value, unexpanded) in self.phyattributes.drain(..) {
+			let mut dropped_prefix = None;
 			let nsuri = match phyqn.0 {
-				Some(prefix) => add_context(
-					Self::lookup_prefix(
-						&self.namespace_stack,
-						&self.fixed_xml_namespace,
-						Some(&prefix),
-					),
-					errctx::ERRCTX_ATTNAME,
-				)?
-				.cloned(),
+				Some(prefix) => match Self::lookup_prefix(
+					&self.namespace_stack,
+					&self.fixed_xml_namespace,
+					Some(&prefix),
+				) {
+					Err(_) if self.lenient_namespaces => {
+						self.diagnostics
+							.push(Diagnostic::UndeclaredNamespacePrefix {
+								prefix: prefix.clone(),
+								local_name: phyqn.1.clone(),
+								attribute: true,
+							});
+						dropped_prefix = Some(prefix);
+						None
+					}
+					Err(_) => return Err(undeclared_prefix(prefix, &phyqn.1, true)),
+					Ok(nsuri) => nsuri.cloned(),
+				},
 				None => None,
 			};
-			let qn = (nsuri, phyqn.1);
-			match attributes.entry(qn) {
-				// XML 1.0
-				// Well-formedness constraint: Unique Att Spec
-				// Namespaces in XML 1.0
-				// Namespace constraint: Attributes Unique
-				// We cannot distinguish between the two violations at this point anymore, and the difference is in most cases irrelevant, so we don't.
-				Entry::Occupied(_) => return Err(Error::Xml(XmlError::DuplicateAttribute)),
-				Entry::Vacant(e) => e.insert(value),
+			let qn = (nsuri, intern_localname(&self.ctx, phyqn.1));
+			let duplicate = match seen.as_mut() {
+				Some(seen) => !seen.insert(qn.clone()),
+				None => attributes.contains_key(&qn),
 			};
+			if duplicate {
+				// in lenient mode, attributes with a resolved name win over
+				// those whose prefix was dropped, then the first one wins
+				if let Some(prefix) = dropped_prefix {
+					self.diagnostics.push(Diagnostic::DroppedAttribute {
+						prefix,
+						local_name: qn.1,
+					});
+					continue;
+				}
+				match lenient.iter().position(|(name, _)| *name == qn) {
+					Some(i) => {
+						let (_, prefix) = lenient.swap_remove(i);
+						attributes.remove(&qn);
+						self.diagnostics.push(Diagnostic::DroppedAttribute {
+							prefix,
+							local_name: qn.1.clone(),
+						});
+					}
+					// XML 1.0
+					// Well-formedness constraint: Unique Att Spec
+					// Namespaces in XML 1.0
+					// Namespace constraint: Attributes Unique
+					// We cannot distinguish between the two violations at this point anymore, and the difference is in most cases irrelevant, so we don't.
+					None => return Err(Error::Xml(XmlError::DuplicateAttribute)),
+				}
+			}
+			if let Some(prefix) = dropped_prefix {
+				lenient.push((qn.clone(), prefix));
+			}
+			if let Some(xml_ids) = self.xml_ids.as_mut() {
+				if qn.1 == "id" && qn.0.as_ref() == Some(&self.fixed_xml_namespace) {
+					// xml:id Version 1.0, section 4: Processing xml:id
+					// Attributes
+					let id = if unexpanded {
+						expand_attribute_references(&value)
+					} else {
+						value.clone()
+					};
+					// xml:id values are normalized like values of type
+					// ID, which strips leading and trailing spaces;
+					// spaces within the value make it invalid anyway
+					let id = match NcName::try_from(id.trim_matches(' ')) {
+						Ok(id) => CData::from(id),
+						Err(_) => return Err(Error::Xml(XmlError::InvalidXmlId)),
+					};
+					let len = id.len();
+					if !xml_ids.insert(id) {
+						return Err(Error::Xml(XmlError::DuplicateXmlId));
+					}
+					self.xml_ids_bytes += mem::size_of::<CData>() + len;
+				}
+			}
+			if unexpanded {
+				attributes.push_unexpanded(qn, value);
+			} else {
+				attributes.push_unchecked(qn, value);
+			}
 		}
 
-		let qname = (
-			add_context(
-				Self::lookup_prefix(
-					&self.namespace_stack,
-					&self.fixed_xml_namespace,
-					phyqname.0.as_ref().map(|x| x.as_str()),
-				),
-				errctx::ERRCTX_NAME,
-			)?
-			.cloned(),
-			phyqname.1,
-		);
-		Ok(ResolvedEvent::StartElement(
-			EventMetrics { len },
-			qname,
-			attributes,
-		))
+		let nsuri = match Self::lookup_prefix(
+			&self.namespace_stack,
+			&self.fixed_xml_namespace,
+			phyqname.0.as_ref().map(|x| x.as_str()),
+		) {
+			Err(_) if self.lenient_namespaces => {
+				self.diagnostics
+					.push(Diagnostic::UndeclaredNamespacePrefix {
+						prefix: phyqname.0.clone().unwrap(),
+						local_name: phyqname.1.clone(),
+						attribute: false,
+					});
+				None
+			}
+			Err(_) => return Err(undeclared_prefix(phyqname.0.unwrap(), &phyqname.1, false)),
+			Ok(nsuri) => nsuri.cloned(),
+		};
+		let qname = (nsuri, intern_localname(&self.ctx, phyqname.1));
+		Ok((EventMetrics { len }, qname, phyqname.0))
 	}
 
 	fn process_event(&mut self, ev: RawEvent) -> Result<Option<ResolvedEvent>> {
@@ -333,7 +1121,14 @@ impl NamespaceResolver {
 			},
 			RawEvent::Attribute(_, phyqn, value) => match self.state {
 				State::Element => {
-					self.push_attribute(phyqn, value)?;
+					self.push_attribute(phyqn, value, false)?;
+					Ok(None)
+				}
+				_ => unreachable!(),
+			},
+			RawEvent::UnexpandedAttribute(_, phyqn, value) => match self.state {
+				State::Element => {
+					self.push_attribute(phyqn, value, true)?;
 					Ok(None)
 				}
 				_ => unreachable!(),
@@ -342,12 +1137,39 @@ impl NamespaceResolver {
 				State::Element => {
 					let ev = self.finish_element()?;
 					self.state = State::Initial;
+					if self.emit_prefix_mappings {
+						let mappings = scope_mappings(self.namespace_stack.last().unwrap());
+						if !mappings.is_empty() {
+							for (prefix, nsuri) in mappings {
+								self.pending.push_back(ResolvedEvent::StartPrefixMapping(
+									EventMetrics { len: 0 },
+									prefix,
+									nsuri,
+								));
+							}
+							self.pending.push_back(ev);
+							return Ok(self.pending.pop_front());
+						}
+					}
 					Ok(Some(ev))
 				}
 				_ => unreachable!(),
 			},
 			RawEvent::ElementFoot(em) => {
-				self.namespace_stack.pop();
+				self.event_length_accum = 0;
+				// never pop the scope of pre-declared prefixes
+				if self.namespace_stack.len() > 1 {
+					let scope = self.namespace_stack.pop().unwrap();
+					self.namespace_stack_bytes -= scope_size(&scope);
+					if self.emit_prefix_mappings {
+						for (prefix, _) in scope_mappings(&scope).into_iter().rev() {
+							self.pending.push_back(ResolvedEvent::EndPrefixMapping(
+								EventMetrics { len: 0 },
+								prefix,
+							));
+						}
+					}
+				}
 				Ok(Some(ResolvedEvent::EndElement(em)))
 			}
 			RawEvent::XmlDeclaration(em, v) => {
@@ -376,6 +1198,9 @@ impl NamespaceResolver {
 		mut f: F,
 	) -> Result<Option<ResolvedEvent>> {
 		self.check_poison()?;
+		if let Some(ev) = self.pending.pop_front() {
+			return Ok(Some(ev));
+		}
 		loop {
 			let pev = match f() {
 				Ok(None) => return Ok(None),
@@ -395,16 +1220,121 @@ impl NamespaceResolver {
 		}
 	}
 
+	/// Read [`RawEvent`] structs from the given function until either an
+	/// error occurs or a [`ResolvedEvent`] can be emitted, and pass that
+	/// event to `sink`.
+	///
+	/// Returns true if an event has been passed to the sink and false if
+	/// `f` returned `None`. Error handling is identical to
+	/// [`next()`](Self::next).
+	///
+	/// In contrast to `next()`, the attributes of elements are collected in
+	/// a buffer which is reused for all elements.
+	pub fn next_into<F: FnMut() -> Result<Option<RawEvent>>, S: EventSink + ?Sized>(
+		&mut self,
+		mut f: F,
+		sink: &mut S,
+	) -> Result<bool> {
+		self.check_poison()?;
+		if let Some(ev) = self.pending.pop_front() {
+			sink::dispatch(&ev, sink);
+			return Ok(true);
+		}
+		loop {
+			let pev = match f() {
+				Ok(None) => return Ok(false),
+				Err(e) => return Err(e),
+				Ok(Some(pev)) => pev,
+			};
+			self.event_length_accum += pev.metrics().len();
+			let result = match pev {
+				RawEvent::ElementHeadClose(_) => self.process_element_into(sink).map(|_| true),
+				pev => self.process_event(pev).map(|ev| match ev {
+					Some(ev) => {
+						sink::dispatch(&ev, sink);
+						true
+					}
+					None => false,
+				}),
+			};
+			match result {
+				Err(e) => {
+					self.poison = Some(e.clone());
+					return Err(e);
+				}
+				Ok(true) => return Ok(true),
+				// false does not signal EOF here, but "read more"
+				Ok(false) => (),
+			}
+		}
+	}
+
+	fn process_element_into<S: EventSink + ?Sized>(&mut self, sink: &mut S) -> Result<()> {
+		let mut attributes = core::mem::take(&mut self.attribute_buffer);
+		attributes.clear();
+		let result = self.resolve_element(&mut attributes);
+		let (metrics, qname, prefix) = match result {
+			Ok(v) => v,
+			Err(e) => {
+				self.attribute_buffer = attributes;
+				return Err(e);
+			}
+		};
+		self.state = State::Initial;
+		if self.emit_prefix_mappings {
+			for (prefix, nsuri) in scope_mappings(self.namespace_stack.last().unwrap()) {
+				sink.start_prefix_mapping(&EventMetrics { len: 0 }, prefix.as_deref(), &nsuri);
+			}
+		}
+		for diagnostic in self.diagnostics.drain(..) {
+			sink.diagnostic(&diagnostic);
+		}
+		sink.start_element(&metrics, &qname, &attributes, prefix.as_deref());
+		attributes.clear();
+		self.attribute_buffer = attributes;
+		Ok(())
+	}
+
 	/// Access the inner context
 	pub fn context(&self) -> &RcPtr<context::Context> {
 		&self.ctx
 	}
+
+	/// Return true if a [`RawEvent::Text`] would be passed through as is.
+	pub(crate) fn passes_text(&self) -> bool {
+		self.poison.is_none() && self.pending.is_empty() && matches!(self.state, State::Initial)
+	}
+
+	/// Account for a text event which has bypassed the resolver.
+	pub(crate) fn bypassed_text(&mut self) {
+		self.event_length_accum = 0;
+	}
+
+	/// Drop the attribute buffer used by [`Self::next_into`].
+	pub(crate) fn release_temporaries(&mut self) {
+		self.attribute_buffer = Attributes::new();
+		self.phyattributes.shrink_to_fit();
+	}
+
+	/// Return the number of namespace declarations processed so far.
+	pub(crate) fn namespace_declarations(&self) -> u64 {
+		self.namespace_declarations
+	}
+
+	/// Return true if events are queued which have not been returned from
+	/// [`next()`](Self::next) yet.
+	pub(crate) fn has_pending(&self) -> bool {
+		!self.pending.is_empty()
+	}
 }
 
 #[cfg(test)]
 mod tests {
+	use super::super::qname::ExpandedName;
 	use super::*;
-	use std::convert::TryInto;
+	use alloc::format;
+	use alloc::string::{String, ToString};
+	use core::convert::TryInto;
 
 	const DM: EventMetrics = EventMetrics { len: 0 };
 
@@ -462,12 +1392,18 @@ mod tests {
 		r.unwrap();
 		let mut iter = evs.iter();
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs, _) => {
 				assert_eq!(em.len(), 14);
 				assert!(nsuri.is_none());
 				assert_eq!(localpart, "root");
-				assert_eq!(attrs.get(&(None, "a1".try_into().unwrap())).unwrap(), "v1");
-				assert_eq!(attrs.get(&(None, "a2".try_into().unwrap())).unwrap(), "v2");
+				assert_eq!(
+					&*attrs.get(&(None, "a1".try_into().unwrap())).unwrap(),
+					"v1"
+				);
+				assert_eq!(
+					&*attrs.get(&(None, "a2".try_into().unwrap())).unwrap(),
+					"v2"
+				);
 				assert_eq!(attrs.len(), 2);
 			}
 			other => panic!("unexpected event: {:?}", other),
@@ -500,7 +1436,7 @@ mod tests {
 		r.unwrap();
 		let mut iter = evs.iter();
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs, _) => {
 				assert_eq!(em.len(), 3);
 				assert!(nsuri.is_none());
 				assert_eq!(localpart, "root");
@@ -516,7 +1452,7 @@ mod tests {
 			other => panic!("unexpected event: {:?}", other),
 		}
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs, _) => {
 				assert_eq!(em.len(), 4);
 				assert!(nsuri.is_none());
 				assert_eq!(localpart, "child");
@@ -648,11 +1584,14 @@ mod tests {
 		r.unwrap();
 		let mut iter = evs.iter();
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs, _) => {
 				assert_eq!(em.len(), 14);
 				assert_eq!(**nsuri.as_ref().unwrap(), "foo");
 				assert_eq!(localpart, "root");
-				assert_eq!(attrs.get(&(None, "a1".try_into().unwrap())).unwrap(), "v1");
+				assert_eq!(
+					&*attrs.get(&(None, "a1".try_into().unwrap())).unwrap(),
+					"v1"
+				);
 				assert_eq!(attrs.len(), 1);
 			}
 			other => panic!("unexpected event: {:?}", other),
@@ -692,11 +1631,14 @@ mod tests {
 		r.unwrap();
 		let mut iter = evs.iter();
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs, _) => {
 				assert_eq!(em.len(), 14);
 				assert_eq!(**nsuri.as_ref().unwrap(), "foo");
 				assert_eq!(localpart, "root");
-				assert_eq!(attrs.get(&(None, "a1".try_into().unwrap())).unwrap(), "v1");
+				assert_eq!(
+					&*attrs.get(&(None, "a1".try_into().unwrap())).unwrap(),
+					"v1"
+				);
 				assert_eq!(attrs.len(), 1);
 			}
 			other => panic!("unexpected event: {:?}", other),
@@ -733,12 +1675,12 @@ mod tests {
 		r.unwrap();
 		let mut iter = evs.iter();
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs, _) => {
 				assert_eq!(em.len(), 14);
 				assert!(nsuri.is_none());
 				assert_eq!(localpart, "root");
 				assert_eq!(
-					attrs
+					&*attrs
 						.get(&(
 							Some(RcPtr::new("foo".try_into().unwrap())),
 							"a1".try_into().unwrap()
@@ -796,22 +1738,25 @@ mod tests {
 		r.unwrap();
 		let mut iter = evs.iter();
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs, _) => {
 				assert_eq!(em.len(), 14);
 				assert_eq!(**nsuri.as_ref().unwrap(), "foo");
 				assert_eq!(localpart, "root");
-				assert_eq!(attrs.get(&(None, "a1".try_into().unwrap())).unwrap(), "v1");
+				assert_eq!(
+					&*attrs.get(&(None, "a1".try_into().unwrap())).unwrap(),
+					"v1"
+				);
 				assert_eq!(attrs.len(), 1);
 			}
 			other => panic!("unexpected event: {:?}", other),
 		}
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs, _) => {
 				assert_eq!(em.len(), 6);
 				assert_eq!(**nsuri.as_ref().unwrap(), "foo");
 				assert_eq!(localpart, "child");
 				assert_eq!(
-					attrs
+					&*attrs
 						.get(&(
 							Some(RcPtr::new("foo".try_into().unwrap())),
 							"a2".try_into().unwrap()
@@ -869,17 +1814,24 @@ mod tests {
 		]);
 		let mut iter = evs.iter();
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs, _) => {
 				assert_eq!(em.len(), 14);
 				assert_eq!(**nsuri.as_ref().unwrap(), "foo");
 				assert_eq!(localpart, "root");
-				assert_eq!(attrs.get(&(None, "a1".try_into().unwrap())).unwrap(), "v1");
+				assert_eq!(
+					&*attrs.get(&(None, "a1".try_into().unwrap())).unwrap(),
+					"v1"
+				);
 				assert_eq!(attrs.len(), 1);
 			}
 			other => panic!("unexpected event: {:?}", other),
 		}
 		match r {
-			Err(Error::Xml(XmlError::UndeclaredNamespacePrefix(errctx::ERRCTX_NAME))) => (),
+			Err(Error::Namespace(e))
+				if e.error() == XmlError::UndeclaredNamespacePrefix(errctx::ERRCTX_NAME) =>
+			{
+				()
+			}
 			other => panic!("unexpected result: {:?}", other),
 		}
 		match iter.next() {
@@ -921,17 +1873,24 @@ mod tests {
 		]);
 		let mut iter = evs.iter();
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localpart), attrs, _) => {
 				assert_eq!(em.len(), 14);
 				assert_eq!(**nsuri.as_ref().unwrap(), "foo");
 				assert_eq!(localpart, "root");
-				assert_eq!(attrs.get(&(None, "a1".try_into().unwrap())).unwrap(), "v1");
+				assert_eq!(
+					&*attrs.get(&(None, "a1".try_into().unwrap())).unwrap(),
+					"v1"
+				);
 				assert_eq!(attrs.len(), 1);
 			}
 			other => panic!("unexpected event: {:?}", other),
 		}
 		match r {
-			Err(Error::Xml(XmlError::UndeclaredNamespacePrefix(errctx::ERRCTX_ATTNAME))) => (),
+			Err(Error::Namespace(e))
+				if e.error() == XmlError::UndeclaredNamespacePrefix(errctx::ERRCTX_ATTNAME) =>
+			{
+				()
+			}
 			other => panic!("unexpected result: {:?}", other),
 		}
 		match iter.next() {
@@ -940,6 +1899,166 @@ mod tests {
 		}
 	}
 
+	fn lenient_input() -> Vec<RawEvent> {
+		vec![
+			RawEvent::ElementHeadOpen(
+				DM,
+				(Some("x".try_into().unwrap()), "root".try_into().unwrap()),
+			),
+			RawEvent::Attribute(
+				DM,
+				(Some("y".try_into().unwrap()), "a1".try_into().unwrap()),
+				"v1".try_into().unwrap(),
+			),
+			RawEvent::Attribute(
+				DM,
+				(Some("xml".try_into().unwrap()), "lang".try_into().unwrap()),
+				"de".try_into().unwrap(),
+			),
+			RawEvent::ElementHeadClose(DM),
+			RawEvent::ElementFoot(DM),
+		]
+	}
+
+	#[test]
+	fn namespace_resolver_tolerates_undeclared_prefixes_in_lenient_mode() {
+		let mut nsr = NamespaceResolver::new();
+		nsr.set_lenient_namespaces(true);
+		let mut iter = lenient_input().into_iter();
+		match nsr.next(|| Ok(iter.next())).unwrap() {
+			Some(ResolvedEvent::StartElement(_, (None, localpart), attrs, _)) => {
+				assert_eq!(localpart, "root");
+				assert_eq!(&*attrs.get_by_name(None, "a1").unwrap(), "v1");
+				assert_eq!(&*attrs.get_by_name(Some(XMLNS_XML), "lang").unwrap(), "de");
+			}
+			other => panic!("unexpected event: {:?}", other),
+		}
+		assert_eq!(
+			nsr.take_diagnostics(),
+			vec![
+				Diagnostic::UndeclaredNamespacePrefix {
+					prefix: "y".try_into().unwrap(),
+					local_name: "a1".try_into().unwrap(),
+					attribute: true,
+				},
+				Diagnostic::UndeclaredNamespacePrefix {
+					prefix: "x".try_into().unwrap(),
+					local_name: "root".try_into().unwrap(),
+					attribute: false,
+				},
+			]
+		);
+		assert!(nsr.take_diagnostics().is_empty());
+		match nsr.next(|| Ok(iter.next())).unwrap() {
+			Some(ResolvedEvent::EndElement(_)) => (),
+			other => panic!("unexpected event: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn namespace_resolver_passes_diagnostics_to_sink() {
+		#[derive(Default)]
+		struct Sink(Vec<String>);
+
+		impl EventSink for Sink {
+			fn start_element(
+				&mut self,
+				_: &EventMetrics,
+				name: &ResolvedQName,
+				_: &Attributes,
+				_: Option<&NcNameStr>,
+			) {
+				self.0.push(format!("start {}", name.1));
+			}
+
+			fn end_element(&mut self, _: &EventMetrics) {}
+
+			fn text(&mut self, _: &EventMetrics, _: &CDataStr) {}
+
+			fn diagnostic(&mut self, diagnostic: &Diagnostic) {
+				self.0.push(diagnostic.to_string());
+			}
+		}
+
+		let mut nsr = NamespaceResolver::new();
+		nsr.set_lenient_namespaces(true);
+		let mut sink = Sink::default();
+		let mut iter = lenient_input().into_iter();
+		assert!(nsr.next_into(|| Ok(iter.next()), &mut sink).unwrap());
+		assert_eq!(
+			sink.0,
+			vec![
+				"undeclared namespace prefix in attribute name y:a1",
+				"undeclared namespace prefix in element name x:root",
+				"start root",
+			]
+		);
+		assert!(nsr.take_diagnostics().is_empty());
+	}
+
+	fn lenient_attributes(attrs: &[(Option<&str>, &str, &str)]) -> Vec<RawEvent> {
+		let mut evs = vec![RawEvent::ElementHeadOpen(
+			DM,
+			(None, "root".try_into().unwrap()),
+		)];
+		for (prefix, name, value) in attrs {
+			evs.push(RawEvent::Attribute(
+				DM,
+				(
+					prefix.map(|x| x.try_into().unwrap()),
+					(*name).try_into().unwrap(),
+				),
+				(*value).try_into().unwrap(),
+			));
+		}
+		evs.push(RawEvent::ElementHeadClose(DM));
+		evs
+	}
+
+	#[test]
+	fn namespace_resolver_resolves_collisions_in_lenient_mode() {
+		let cases: &[(&[(Option<&str>, &str, &str)], &str, &str)] = &[
+			// the first attribute with an undeclared prefix is kept
+			(&[(Some("a"), "x", "1"), (Some("b"), "x", "2")], "1", "b"),
+			// attributes without prefix win, independent of the order
+			(&[(Some("a"), "x", "1"), (None, "x", "2")], "2", "a"),
+			(&[(None, "x", "1"), (Some("a"), "x", "2")], "1", "a"),
+		];
+		for (attrs, value, dropped) in cases {
+			let mut nsr = NamespaceResolver::new();
+			nsr.set_lenient_namespaces(true);
+			let mut iter = lenient_attributes(attrs).into_iter();
+			match nsr.next(|| Ok(iter.next())).unwrap() {
+				Some(ResolvedEvent::StartElement(_, _, attrs, _)) => {
+					assert_eq!(attrs.len(), 1);
+					assert_eq!(&*attrs.get_by_name(None, "x").unwrap(), *value);
+				}
+				other => panic!("unexpected event: {:?}", other),
+			}
+			let diagnostics = nsr.take_diagnostics();
+			assert_eq!(
+				diagnostics.last().unwrap(),
+				&Diagnostic::DroppedAttribute {
+					prefix: (*dropped).try_into().unwrap(),
+					local_name: "x".try_into().unwrap(),
+				}
+			);
+		}
+	}
+
+	#[test]
+	fn namespace_resolver_rejects_duplicates_in_lenient_mode() {
+		let mut nsr = NamespaceResolver::new();
+		nsr.set_lenient_namespaces(true);
+		let mut iter =
+			lenient_attributes(&[(Some("a"), "x", "1"), (None, "x", "2"), (None, "x", "3")])
+				.into_iter();
+		match nsr.next(|| Ok(iter.next())) {
+			Err(Error::Xml(XmlError::DuplicateAttribute)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
 	#[test]
 	fn namespace_resolver_rejects_duplicate_attribute_post_namespace_resolution() {
 		let (evs, r) = resolve_all(vec![
@@ -972,7 +2091,7 @@ mod tests {
 		]);
 		let mut iter = evs.iter();
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(_, (nsuri, localpart), attrs) => {
+			ResolvedEvent::StartElement(_, (nsuri, localpart), attrs, _) => {
 				assert!(nsuri.is_none());
 				assert_eq!(localpart, "root");
 				assert_eq!(attrs.len(), 0);
@@ -1071,11 +2190,11 @@ mod tests {
 		r.unwrap();
 		let mut iter = evs.iter();
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(_, (nsuri, localpart), attrs) => {
+			ResolvedEvent::StartElement(_, (nsuri, localpart), attrs, _) => {
 				assert_eq!(**nsuri.as_ref().unwrap(), "foo");
 				assert_eq!(localpart, "root");
 				assert_eq!(
-					attrs
+					&*attrs
 						.get(&(
 							Some(RcPtr::new("foo".try_into().unwrap())),
 							"a".try_into().unwrap()
@@ -1084,7 +2203,7 @@ mod tests {
 					"v1"
 				);
 				assert_eq!(
-					attrs
+					&*attrs
 						.get(&(
 							Some(RcPtr::new("bar".try_into().unwrap())),
 							"a".try_into().unwrap()
@@ -1097,11 +2216,11 @@ mod tests {
 			other => panic!("unexpected event: {:?}", other),
 		}
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(_, (nsuri, localpart), attrs) => {
+			ResolvedEvent::StartElement(_, (nsuri, localpart), attrs, _) => {
 				assert_eq!(**nsuri.as_ref().unwrap(), "baz");
 				assert_eq!(localpart, "child");
 				assert_eq!(
-					attrs
+					&*attrs
 						.get(&(
 							Some(RcPtr::new("foo".try_into().unwrap())),
 							"a".try_into().unwrap()
@@ -1110,7 +2229,7 @@ mod tests {
 					"v1"
 				);
 				assert_eq!(
-					attrs
+					&*attrs
 						.get(&(
 							Some(RcPtr::new("baz".try_into().unwrap())),
 							"a".try_into().unwrap()
@@ -1135,4 +2254,374 @@ mod tests {
 			other => panic!("unexpected event: {:?}", other),
 		}
 	}
+
+	fn resolve_all_with(
+		mut nsr: NamespaceResolver,
+		mut evs: Vec<RawEvent>,
+	) -> (Vec<ResolvedEvent>, Result<()>) {
+		let mut out = Vec::new();
+		let mut iter = evs.drain(..);
+		loop {
+			match nsr.next(|| Ok(iter.next())) {
+				Err(err) => return (out, Err(err)),
+				Ok(Some(ev)) => out.push(ev),
+				Ok(None) => return (out, Ok(())),
+			}
+		}
+	}
+
+	#[test]
+	fn namespace_resolver_uses_predeclared_prefixes() {
+		let mut nsr = NamespaceResolver::new();
+		nsr.declare_prefix(
+			Some("stream".try_into().unwrap()),
+			"urn:stream".try_into().unwrap(),
+		)
+		.unwrap();
+		nsr.declare_prefix(None, "jabber:client".try_into().unwrap())
+			.unwrap();
+		let (evs, r) = resolve_all_with(
+			nsr,
+			vec![
+				RawEvent::ElementHeadOpen(
+					DM,
+					(
+						Some("stream".try_into().unwrap()),
+						"features".try_into().unwrap(),
+					),
+				),
+				RawEvent::ElementHeadClose(DM),
+				RawEvent::ElementHeadOpen(DM, (None, "message".try_into().unwrap())),
+				RawEvent::Attribute(
+					DM,
+					(None, "xmlns".try_into().unwrap()),
+					"urn:other".try_into().unwrap(),
+				),
+				RawEvent::ElementHeadClose(DM),
+				RawEvent::ElementFoot(DM),
+				RawEvent::ElementFoot(DM),
+			],
+		);
+		r.unwrap();
+		let mut iter = evs.iter();
+		match iter.next().unwrap() {
+			ResolvedEvent::StartElement(_, (nsuri, localpart), _, _) => {
+				assert_eq!(nsuri.as_ref().unwrap().as_str(), "urn:stream");
+				assert_eq!(localpart, "features");
+			}
+			other => panic!("unexpected event: {:?}", other),
+		}
+		match iter.next().unwrap() {
+			// declarations in the document take precedence
+			ResolvedEvent::StartElement(_, (nsuri, localpart), _, _) => {
+				assert_eq!(nsuri.as_ref().unwrap().as_str(), "urn:other");
+				assert_eq!(localpart, "message");
+			}
+			other => panic!("unexpected event: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn namespace_resolver_predeclared_prefixes_survive_root_element() {
+		let mut nsr = NamespaceResolver::new();
+		nsr.declare_prefix(Some("p".try_into().unwrap()), "urn:p".try_into().unwrap())
+			.unwrap();
+		let el = || {
+			vec![
+				RawEvent::ElementHeadOpen(
+					DM,
+					(Some("p".try_into().unwrap()), "a".try_into().unwrap()),
+				),
+				RawEvent::ElementHeadClose(DM),
+				RawEvent::ElementFoot(DM),
+			]
+		};
+		let mut evs = el();
+		evs.extend(el());
+		let (evs, r) = resolve_all_with(nsr, evs);
+		r.unwrap();
+		assert_eq!(evs.len(), 4);
+	}
+
+	#[test]
+	fn namespace_resolver_rejects_reserved_predeclarations() {
+		let mut nsr = NamespaceResolver::new();
+		match nsr.declare_prefix(
+			Some("xmlns".try_into().unwrap()),
+			"urn:foo".try_into().unwrap(),
+		) {
+			Err(Error::Namespace(e)) if e.error() == XmlError::ReservedNamespacePrefix => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match nsr.declare_prefix(
+			Some("xml".try_into().unwrap()),
+			"urn:foo".try_into().unwrap(),
+		) {
+			Err(Error::Namespace(e)) if e.error() == XmlError::ReservedNamespacePrefix => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match nsr.declare_prefix(Some("xml".try_into().unwrap()), XMLNS_XML.to_cdata()) {
+			Ok(()) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match nsr.declare_prefix(Some("foo".try_into().unwrap()), XMLNS_XML.to_cdata()) {
+			Err(Error::Namespace(e)) if e.error() == XmlError::ReservedNamespaceName => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match nsr.declare_prefix(None, XMLNS_XMLNS.to_cdata()) {
+			Err(Error::Namespace(e)) if e.error() == XmlError::ReservedNamespaceName => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match nsr.declare_prefix(Some("foo".try_into().unwrap()), "".try_into().unwrap()) {
+			Err(Error::Namespace(e)) if e.error() == XmlError::EmptyNamespaceUri => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn namespace_resolver_emits_prefix_mappings_if_enabled() {
+		let mut nsr = NamespaceResolver::new();
+		nsr.set_emit_prefix_mappings(true);
+		let (evs, r) = resolve_all_with(
+			nsr,
+			vec![
+				RawEvent::ElementHeadOpen(DM, (None, "root".try_into().unwrap())),
+				RawEvent::Attribute(
+					DM,
+					(Some("xmlns".try_into().unwrap()), "b".try_into().unwrap()),
+					"urn:b".try_into().unwrap(),
+				),
+				RawEvent::Attribute(
+					DM,
+					(None, "xmlns".try_into().unwrap()),
+					"urn:default".try_into().unwrap(),
+				),
+				RawEvent::Attribute(
+					DM,
+					(Some("xmlns".try_into().unwrap()), "a".try_into().unwrap()),
+					"urn:a".try_into().unwrap(),
+				),
+				RawEvent::ElementHeadClose(DM),
+				RawEvent::ElementHeadOpen(DM, (None, "child".try_into().unwrap())),
+				RawEvent::ElementHeadClose(DM),
+				RawEvent::ElementFoot(DM),
+				RawEvent::ElementFoot(DM),
+			],
+		);
+		r.unwrap();
+		let mut iter = evs.iter();
+		match iter.next().unwrap() {
+			ResolvedEvent::StartPrefixMapping(em, None, nsuri) => {
+				assert_eq!(em.len(), 0);
+				assert_eq!(nsuri.as_str(), "urn:default");
+			}
+			other => panic!("unexpected event: {:?}", other),
+		}
+		match iter.next().unwrap() {
+			ResolvedEvent::StartPrefixMapping(_, Some(prefix), nsuri) => {
+				assert_eq!(prefix, "a");
+				assert_eq!(nsuri.as_str(), "urn:a");
+			}
+			other => panic!("unexpected event: {:?}", other),
+		}
+		match iter.next().unwrap() {
+			ResolvedEvent::StartPrefixMapping(_, Some(prefix), nsuri) => {
+				assert_eq!(prefix, "b");
+				assert_eq!(nsuri.as_str(), "urn:b");
+			}
+			other => panic!("unexpected event: {:?}", other),
+		}
+		match iter.next().unwrap() {
+			ResolvedEvent::StartElement(_, (_, localname), attrs, _) => {
+				assert_eq!(localname, "root");
+				assert_eq!(attrs.len(), 0);
+			}
+			other => panic!("unexpected event: {:?}", other),
+		}
+		// no declarations on the child
+		match iter.next().unwrap() {
+			ResolvedEvent::StartElement(_, (_, localname), _, _) => {
+				assert_eq!(localname, "child");
+			}
+			other => panic!("unexpected event: {:?}", other),
+		}
+		match iter.next().unwrap() {
+			ResolvedEvent::EndElement(_) => (),
+			other => panic!("unexpected event: {:?}", other),
+		}
+		match iter.next().unwrap() {
+			ResolvedEvent::EndElement(_) => (),
+			other => panic!("unexpected event: {:?}", other),
+		}
+		match iter.next().unwrap() {
+			ResolvedEvent::EndPrefixMapping(_, Some(prefix)) => assert_eq!(prefix, "b"),
+			other => panic!("unexpected event: {:?}", other),
+		}
+		match iter.next().unwrap() {
+			ResolvedEvent::EndPrefixMapping(_, Some(prefix)) => assert_eq!(prefix, "a"),
+			other => panic!("unexpected event: {:?}", other),
+		}
+		match iter.next().unwrap() {
+			ResolvedEvent::EndPrefixMapping(_, None) => (),
+			other => panic!("unexpected event: {:?}", other),
+		}
+		match iter.next() {
+			None => (),
+			other => panic!("unexpected event: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn namespace_resolver_records_prefix_of_last_element() {
+		let mut nsr = NamespaceResolver::new();
+		let mut iter = vec![
+			RawEvent::ElementHeadOpen(DM, (Some("p".try_into().unwrap()), "a".try_into().unwrap())),
+			RawEvent::Attribute(
+				DM,
+				(Some("xmlns".try_into().unwrap()), "p".try_into().unwrap()),
+				"urn:p".try_into().unwrap(),
+			),
+			RawEvent::ElementHeadClose(DM),
+			RawEvent::ElementHeadOpen(DM, (None, "b".try_into().unwrap())),
+			RawEvent::ElementHeadClose(DM),
+		]
+		.into_iter();
+		match nsr.next(|| Ok(iter.next())).unwrap() {
+			Some(ResolvedEvent::StartElement(_, (ns, name), _, prefix)) => {
+				let name = ExpandedName::with_prefix(ns, name, prefix);
+				assert_eq!(name.prefix().unwrap(), "p");
+				assert!(name.matches(Some("urn:p"), "a"));
+			}
+			other => panic!("unexpected event: {:?}", other),
+		}
+		match nsr.next(|| Ok(iter.next())).unwrap() {
+			Some(ResolvedEvent::StartElement(_, _, _, prefix)) => {
+				assert!(prefix.is_none());
+			}
+			other => panic!("unexpected event: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn namespace_resolver_does_not_emit_prefix_mappings_by_default() {
+		let (evs, r) = resolve_all(vec![
+			RawEvent::ElementHeadOpen(DM, (None, "root".try_into().unwrap())),
+			RawEvent::Attribute(
+				DM,
+				(None, "xmlns".try_into().unwrap()),
+				"urn:default".try_into().unwrap(),
+			),
+			RawEvent::ElementHeadClose(DM),
+			RawEvent::ElementFoot(DM),
+		]);
+		r.unwrap();
+		assert_eq!(evs.len(), 2);
+	}
+
+	#[test]
+	fn namespace_resolver_reports_xmlns_attributes_if_enabled() {
+		let mut nsr = NamespaceResolver::new();
+		nsr.set_emit_xmlns_attributes(true);
+		let (evs, r) = resolve_all_with(
+			nsr,
+			vec![
+				RawEvent::ElementHeadOpen(
+					DM,
+					(Some("b".try_into().unwrap()), "root".try_into().unwrap()),
+				),
+				RawEvent::Attribute(
+					DM,
+					(Some("xmlns".try_into().unwrap()), "b".try_into().unwrap()),
+					"urn:b".try_into().unwrap(),
+				),
+				RawEvent::Attribute(DM, (None, "x".try_into().unwrap()), "y".try_into().unwrap()),
+				RawEvent::Attribute(
+					DM,
+					(None, "xmlns".try_into().unwrap()),
+					"".try_into().unwrap(),
+				),
+				RawEvent::ElementHeadClose(DM),
+				RawEvent::ElementHeadOpen(DM, (None, "child".try_into().unwrap())),
+				RawEvent::ElementHeadClose(DM),
+				RawEvent::ElementFoot(DM),
+				RawEvent::ElementFoot(DM),
+			],
+		);
+		r.unwrap();
+		match &evs[0] {
+			ResolvedEvent::StartElement(_, (nsuri, name), attrs, _) => {
+				assert_eq!(nsuri.as_ref().unwrap().as_str(), "urn:b");
+				assert_eq!(name, "root");
+				let attrs: Vec<_> = attrs
+					.iter()
+					.map(|((ns, name), v)| {
+						(
+							ns.as_ref().map(|x| x.as_str()),
+							name.as_str(),
+							v.to_string(),
+						)
+					})
+					.collect();
+				assert_eq!(
+					attrs,
+					&[
+						(Some(&**XMLNS_XMLNS), "xmlns", "".to_string()),
+						(Some(&**XMLNS_XMLNS), "b", "urn:b".to_string()),
+						(None, "x", "y".to_string()),
+					]
+				);
+			}
+			other => panic!("unexpected event: {:?}", other),
+		}
+		match &evs[1] {
+			ResolvedEvent::StartElement(_, _, attrs, _) => assert_eq!(attrs.len(), 0),
+			other => panic!("unexpected event: {:?}", other),
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "shared_ns")]
+	fn namespace_resolver_interns_long_local_names() {
+		let long: &str = "a-rather-long-element-name-which-does-not-fit-inline";
+		let short: &str = "short";
+		let mut raw = Vec::new();
+		for _ in 0..2 {
+			raw.push(RawEvent::ElementHeadOpen(
+				DM,
+				(None, long.try_into().unwrap()),
+			));
+			raw.push(RawEvent::Attribute(
+				DM,
+				(None, long.try_into().unwrap()),
+				"v".try_into().unwrap(),
+			));
+			raw.push(RawEvent::ElementHeadClose(DM));
+			raw.push(RawEvent::ElementHeadOpen(
+				DM,
+				(None, short.try_into().unwrap()),
+			));
+			raw.push(RawEvent::ElementHeadClose(DM));
+			raw.push(RawEvent::ElementFoot(DM));
+			raw.push(RawEvent::ElementFoot(DM));
+		}
+		let (evs, r) = resolve_all(raw);
+		r.unwrap();
+		let mut names = Vec::new();
+		for ev in evs.iter() {
+			if let ResolvedEvent::StartElement(_, (_, name), attrs, _) = ev {
+				names.push(name);
+				for ((_, name), _) in attrs.iter() {
+					names.push(name);
+				}
+			}
+		}
+		assert_eq!(names.len(), 6);
+		// element and attribute names share the same storage
+		for name in [names[1], names[3], names[4]] {
+			assert_eq!(name.as_ptr(), names[0].as_ptr());
+		}
+		// short names are not interned
+		assert_eq!(names[2], names[5]);
+		assert_ne!(names[2].as_ptr(), names[5].as_ptr());
+	}
 }
@@ -0,0 +1,369 @@
+use core::fmt;
+use core::hash::{Hash, Hasher};
+
+use crate::strings::{CDataStr, NcName, NcNameStr};
+
+use super::namespaces::{NamespaceName, ResolvedQName};
+
+/**
+# Expanded name with optional prefix
+
+An [`ExpandedName`] consists of an optional namespace URI and a local name
+(the *expanded name* in the terms of Namespaces in XML 1.0), and it may
+additionally carry the prefix which was used to write the name in a
+document.
+
+Two [`ExpandedName`]s compare equal if and only if their namespace URIs and
+local names are equal; the prefix is only informational and is ignored for
+comparison and hashing.
+
+[`ExpandedName`] converts losslessly from and (dropping the prefix) into a
+[`ResolvedQName`], which is used in the events emitted by the parser. The
+prefix of an element name is passed alongside the name in
+[`ResolvedEvent::StartElement`](crate::ResolvedEvent::StartElement):
+
+```
+use rxml::{ExpandedName, FeedParser, ResolvedEvent};
+
+let mut fp = FeedParser::default();
+let mut doc = &b"<st:stream xmlns:st='http://etherx.jabber.org/streams'>"[..];
+let name = match fp.parse(&mut doc, false).unwrap() {
+	Some(ResolvedEvent::StartElement(_, (ns, name), _, prefix)) => {
+		ExpandedName::with_prefix(ns, name, prefix)
+	}
+	other => panic!("unexpected event: {:?}", other),
+};
+assert_eq!(name.prefix().unwrap(), "st");
+assert!(name.matches(Some("http://etherx.jabber.org/streams"), "stream"));
+assert_eq!(name.to_string(), "{http://etherx.jabber.org/streams}stream");
+```
+*/
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExpandedName {
+	namespace: Option<NamespaceName>,
+	local_name: NcName,
+	prefix: Option<NcName>,
+}
+
+impl ExpandedName {
+	/// Create a new qualified name without prefix information.
+	pub fn new(namespace: Option<NamespaceName>, local_name: NcName) -> Self {
+		Self {
+			namespace,
+			local_name,
+			prefix: None,
+		}
+	}
+
+	/// Create a new qualified name with the prefix used to write it.
+	pub fn with_prefix(
+		namespace: Option<NamespaceName>,
+		local_name: NcName,
+		prefix: Option<NcName>,
+	) -> Self {
+		Self {
+			namespace,
+			local_name,
+			prefix,
+		}
+	}
+
+	/// Namespace URI of the name, if any.
+	pub fn namespace(&self) -> Option<&NamespaceName> {
+		self.namespace.as_ref()
+	}
+
+	/// Namespace URI of the name as string slice, if any.
+	pub fn namespace_str(&self) -> Option<&CDataStr> {
+		self.namespace.as_ref().map(|x| &***x)
+	}
+
+	/// Local part of the name.
+	pub fn local_name(&self) -> &NcNameStr {
+		&self.local_name
+	}
+
+	/// Prefix which was used to write the name, if known.
+	pub fn prefix(&self) -> Option<&NcNameStr> {
+		self.prefix.as_deref()
+	}
+
+	/// Return true if the namespace URI and local name are equal to the
+	/// given values.
+	pub fn matches(&self, namespace: Option<&str>, local_name: &str) -> bool {
+		self.namespace.as_ref().map(|x| x.as_str()) == namespace
+			&& self.local_name.as_str() == local_name
+	}
+
+	/// Borrow the namespace URI and local name as [`QNameRef`].
+	pub fn as_qname_ref(&self) -> QNameRef<'_> {
+		QNameRef::new(self.namespace_str(), &self.local_name)
+	}
+
+	/// Decompose the name into namespace URI, local name and prefix.
+	pub fn into_parts(self) -> (Option<NamespaceName>, NcName, Option<NcName>) {
+		(self.namespace, self.local_name, self.prefix)
+	}
+}
+
+impl PartialEq for ExpandedName {
+	fn eq(&self, other: &ExpandedName) -> bool {
+		self.namespace == other.namespace && self.local_name == other.local_name
+	}
+}
+
+impl Eq for ExpandedName {}
+
+impl Hash for ExpandedName {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		// must be consistent with the hash of ResolvedQName, so that both
+		// can be used interchangably for lookups
+		self.namespace.hash(state);
+		self.local_name.hash(state);
+	}
+}
+
+impl PartialEq<ResolvedQName> for ExpandedName {
+	fn eq(&self, other: &ResolvedQName) -> bool {
+		self.namespace == other.0 && self.local_name == other.1
+	}
+}
+
+impl PartialEq<ExpandedName> for ResolvedQName {
+	fn eq(&self, other: &ExpandedName) -> bool {
+		other == self
+	}
+}
+
+impl From<ResolvedQName> for ExpandedName {
+	fn from(other: ResolvedQName) -> Self {
+		Self::new(other.0, other.1)
+	}
+}
+
+impl From<ExpandedName> for ResolvedQName {
+	fn from(other: ExpandedName) -> Self {
+		(other.namespace, other.local_name)
+	}
+}
+
+impl fmt::Display for ExpandedName {
+	/// Format the name in Clark notation (`{uri}local`), or just the local
+	/// name if it has no namespace.
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.namespace.as_ref() {
+			Some(ns) => write!(f, "{{{}}}{}", ns, self.local_name),
+			None => f.write_str(&self.local_name),
+		}
+	}
+}
+
+/**
+# Borrowed namespace-qualified name
+
+A [`QNameRef`] refers to a namespace URI and a local name without owning
+them. It can be constructed in const context, which makes it suitable for
+constants against which the names of parsed elements and attributes are
+compared without any allocation. With the `macros` feature, the
+`xml_qname!` macro builds such constants from string literals, validated at
+compile time.
+
+```
+use rxml::parser::QNameRef;
+use rxml::{CDataStr, NcNameStr, ResolvedQName};
+use std::convert::{TryFrom, TryInto};
+
+let message = QNameRef::new(
+	Some(CDataStr::from_str("jabber:client").unwrap()),
+	NcNameStr::from_str("message").unwrap(),
+);
+
+let name: ResolvedQName = (
+	Some(rxml::CData::try_from("jabber:client").unwrap().into()),
+	"message".try_into().unwrap(),
+);
+assert_eq!(message, name);
+assert_eq!(message.to_string(), "{jabber:client}message");
+```
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct QNameRef<'x> {
+	// stored as plain str, so that constants can be used as patterns; the
+	// constructor guarantees that the contents are valid
+	namespace: Option<&'x str>,
+	local_name: &'x str,
+}
+
+impl<'x> QNameRef<'x> {
+	/// Create a new borrowed name.
+	pub const fn new(namespace: Option<&'x CDataStr>, local_name: &'x NcNameStr) -> Self {
+		// SAFETY: CDataStr and NcNameStr are transparent wrappers around str
+		unsafe {
+			Self {
+				namespace: match namespace {
+					Some(ns) => Some(core::mem::transmute::<&'x CDataStr, &'x str>(ns)),
+					None => None,
+				},
+				local_name: core::mem::transmute::<&'x NcNameStr, &'x str>(local_name),
+			}
+		}
+	}
+
+	/// Namespace URI of the name, if any.
+	pub fn namespace(&self) -> Option<&'x CDataStr> {
+		// SAFETY: only constructed from a CDataStr in new()
+		self.namespace
+			.map(|x| unsafe { CDataStr::from_str_unchecked(x) })
+	}
+
+	/// Local part of the name.
+	pub fn local_name(&self) -> &'x NcNameStr {
+		// SAFETY: only constructed from a NcNameStr in new()
+		unsafe { NcNameStr::from_str_unchecked(self.local_name) }
+	}
+
+	/// Convert into an owned [`ExpandedName`].
+	///
+	/// This allocates a new [`NamespaceName`]; where possible, compare
+	/// against the borrowed name instead.
+	pub fn to_expanded_name(&self) -> ExpandedName {
+		ExpandedName::new(
+			self.namespace().map(|x| NamespaceName::new(x.to_cdata())),
+			self.local_name().to_ncname(),
+		)
+	}
+}
+
+impl<'x> From<&'x ResolvedQName> for QNameRef<'x> {
+	fn from(other: &'x ResolvedQName) -> Self {
+		Self::new(other.0.as_ref().map(|x| &***x), &other.1)
+	}
+}
+
+impl<'x> From<&'x ExpandedName> for QNameRef<'x> {
+	fn from(other: &'x ExpandedName) -> Self {
+		other.as_qname_ref()
+	}
+}
+
+impl PartialEq<ResolvedQName> for QNameRef<'_> {
+	fn eq(&self, other: &ResolvedQName) -> bool {
+		*self == QNameRef::from(other)
+	}
+}
+
+impl PartialEq<QNameRef<'_>> for ResolvedQName {
+	fn eq(&self, other: &QNameRef<'_>) -> bool {
+		other == self
+	}
+}
+
+impl PartialEq<ExpandedName> for QNameRef<'_> {
+	fn eq(&self, other: &ExpandedName) -> bool {
+		*self == other.as_qname_ref()
+	}
+}
+
+impl PartialEq<QNameRef<'_>> for ExpandedName {
+	fn eq(&self, other: &QNameRef<'_>) -> bool {
+		other == self
+	}
+}
+
+impl fmt::Display for QNameRef<'_> {
+	/// Format the name in Clark notation, like [`ExpandedName`].
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.namespace {
+			Some(ns) => write!(f, "{{{}}}{}", ns, self.local_name),
+			None => f.write_str(self.local_name),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use alloc::string::ToString;
+	use core::convert::{TryFrom, TryInto};
+	#[cfg(feature = "std")]
+	use std::collections::hash_map::DefaultHasher;
+
+	use crate::parser::RcPtr;
+	use crate::strings::CData;
+
+	fn ns1() -> NamespaceName {
+		RcPtr::new(CData::try_from("uri:foo").unwrap())
+	}
+
+	#[cfg(feature = "std")]
+	fn hash_of<T: Hash>(v: &T) -> u64 {
+		let mut h = DefaultHasher::new();
+		v.hash(&mut h);
+		h.finish()
+	}
+
+	#[test]
+	fn prefix_is_ignored_for_equality_and_hash() {
+		let a = ExpandedName::with_prefix(
+			Some(ns1()),
+			"foo".try_into().unwrap(),
+			Some("a".try_into().unwrap()),
+		);
+		let b = ExpandedName::with_prefix(
+			Some(ns1()),
+			"foo".try_into().unwrap(),
+			Some("b".try_into().unwrap()),
+		);
+		assert_eq!(a, b);
+		#[cfg(feature = "std")]
+		assert_eq!(hash_of(&a), hash_of(&b));
+		assert_eq!(a.prefix().unwrap(), "a");
+	}
+
+	#[test]
+	fn namespace_distinguishes_names() {
+		let a = ExpandedName::new(Some(ns1()), "foo".try_into().unwrap());
+		let b = ExpandedName::new(None, "foo".try_into().unwrap());
+		assert_ne!(a, b);
+		assert!(a.matches(Some("uri:foo"), "foo"));
+		assert!(!a.matches(None, "foo"));
+		assert!(b.matches(None, "foo"));
+	}
+
+	#[test]
+	fn converts_to_and_from_resolved_qname() {
+		let rqn: ResolvedQName = (Some(ns1()), "foo".try_into().unwrap());
+		let qn: ExpandedName = rqn.clone().into();
+		assert_eq!(qn, rqn);
+		#[cfg(feature = "std")]
+		assert_eq!(hash_of(&qn), hash_of(&rqn));
+		let back: ResolvedQName = qn.into();
+		assert_eq!(back, rqn);
+	}
+
+	#[test]
+	fn borrowed_name_compares_with_owned_names() {
+		let rqn: ResolvedQName = (Some(ns1()), "foo".try_into().unwrap());
+		let qn = ExpandedName::from(rqn.clone());
+		let borrowed = QNameRef::from(&rqn);
+		assert_eq!(borrowed, rqn);
+		assert_eq!(borrowed, qn);
+		assert_eq!(qn.as_qname_ref(), borrowed);
+		assert_eq!(borrowed.to_expanded_name(), qn);
+		assert_eq!(borrowed.to_string(), "{uri:foo}foo");
+
+		let unnamespaced: ResolvedQName = (None, "foo".try_into().unwrap());
+		assert_ne!(borrowed, unnamespaced);
+		assert_eq!(QNameRef::from(&unnamespaced).namespace(), None);
+	}
+
+	#[test]
+	fn display_uses_clark_notation() {
+		let a = ExpandedName::new(Some(ns1()), "foo".try_into().unwrap());
+		assert_eq!(a.to_string(), "{uri:foo}foo");
+		let b = ExpandedName::new(None, "foo".try_into().unwrap());
+		assert_eq!(b.to_string(), "foo");
+	}
+}
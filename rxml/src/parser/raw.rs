@@ -1,11 +1,14 @@
 /*!
 # Restricted XML 1.0 Parser, sans namespacing
 */
-use std::collections::VecDeque;
-use std::fmt;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
 
 use crate::error::*;
-use crate::lexer::{Token, TokenMetrics};
+use crate::lexer::{expand_attribute_references, Token, TokenMetrics};
 use crate::strings::*;
 
 use super::common::*;
@@ -37,12 +40,18 @@ A well-formed XML document will generate the following sequence of events:
 An *element sequence* consists of:
 
 1. [`Self::ElementHeadOpen`]
-2. Zero or more [`Self::Attribute`]
+2. Zero or more [`Self::Attribute`] or [`Self::UnexpandedAttribute`]
 3. [`Self::ElementHeadClose`]
 4. Zero or more element sequences or [`Self::Text`], mixed arbitrarily
 5. [`Self::ElementFoot`]
+
+Like [`ResolvedEvent`](crate::ResolvedEvent), this enum is
+`#[non_exhaustive]` since [`Self::UnexpandedAttribute`] was added. This is a
+breaking change compared to 0.8.
 */
 #[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum RawEvent {
 	/// The XML declaration.
 	///
@@ -101,6 +110,24 @@ pub enum RawEvent {
 		CData,
 	),
 
+	/// Attribute key/value pair with unexpanded references
+	///
+	/// This is emitted instead of [`Self::Attribute`] for values which
+	/// contain references if
+	/// [`LexerOptions::lazy_attribute_values`](crate::LexerOptions::lazy_attribute_values)
+	/// is enabled. The same constraints apply; namespace declarations are
+	/// always emitted as [`Self::Attribute`].
+	UnexpandedAttribute(
+		/// Number of bytes contributing to this event.
+		///
+		/// See [`Self::Attribute`].
+		EventMetrics,
+		/// Prefix/localpart pair of the attribute name.
+		RawQName,
+		/// Normalized attribute value, with references as written
+		CData,
+	),
+
 	/// End of an XML element header
 	ElementHeadClose(
 		/// Number of bytes contributing to this event.
@@ -148,12 +175,13 @@ impl RawEvent {
 	/// Return the [`EventMetrics`] of the event
 	pub fn metrics(&self) -> &EventMetrics {
 		match self {
-			Self::XmlDeclaration(m, ..) => &m,
-			Self::ElementHeadOpen(m, ..) => &m,
-			Self::Attribute(m, ..) => &m,
-			Self::ElementHeadClose(m, ..) => &m,
-			Self::ElementFoot(m, ..) => &m,
-			Self::Text(m, ..) => &m,
+			Self::XmlDeclaration(m, ..) => m,
+			Self::ElementHeadOpen(m, ..) => m,
+			Self::Attribute(m, ..) => m,
+			Self::UnexpandedAttribute(m, ..) => m,
+			Self::ElementHeadClose(m, ..) => m,
+			Self::ElementFoot(m, ..) => m,
+			Self::Text(m, ..) => m,
 		}
 	}
 }
@@ -225,9 +253,12 @@ which ensures well-formedness and namespace-well-formedness.
    [`NamespaceResolver`]: crate::NamespaceResolver
    [`Parser`]: crate::Parser
 */
+#[derive(Clone)]
 pub struct RawParser {
 	state: State,
 	element_stack: Vec<Name>,
+	/// Total length of the names in `element_stack`.
+	element_stack_bytes: usize,
 	attribute_scratchpad: Option<RawQName>,
 	/// end position of the last token processed in the event
 	event_last_token_end: Option<usize>,
@@ -249,6 +280,7 @@ impl RawParser {
 		Self {
 			state: State::Initial,
 			element_stack: Vec::new(),
+			element_stack_bytes: 0,
 			attribute_scratchpad: None,
 			event_last_token_end: None,
 			event_length: 0,
@@ -287,16 +319,25 @@ impl RawParser {
 	}
 
 	fn read_token<'r, R: TokenRead>(&mut self, r: &'r mut R) -> Result<Option<Token>> {
-		if self.event_last_token_end.is_none() {
-			return r.read();
-		}
-		match r.read()? {
-			Some(tok) => {
-				self.account_token(tok.metrics())?;
-				Ok(Some(tok))
-			}
-			None => Ok(None),
-		}
+		let tok = match r.read()? {
+			// whitespace is character data like any other to the parser
+			Some(Token::Whitespace(tm, s)) => Token::Text(tm, s),
+			Some(tok) => tok,
+			None => return Ok(None),
+		};
+		if self.event_last_token_end.is_some() {
+			self.account_token(tok.metrics())?;
+		}
+		Ok(Some(tok))
+	}
+
+	/// Return true if the next token would be accepted as character data
+	/// in element content and turned into a [`RawEvent::Text`] right away.
+	pub(crate) fn expects_content(&self) -> bool {
+		self.err.is_none()
+			&& self.state == State::Document(DocSt::CData)
+			&& self.eventq.is_empty()
+			&& self.event_last_token_end.is_none()
 	}
 
 	/// Emit an event into the event queue.
@@ -309,6 +350,17 @@ impl RawParser {
 		self.err = Some(Box::new(e))
 	}
 
+	/// Estimate the number of bytes of memory used by the parser.
+	///
+	/// This covers the element stack, queued events and the length of the
+	/// event which is currently in progress.
+	pub(crate) fn memory_used(&self) -> usize {
+		self.element_stack.capacity() * mem::size_of::<Name>()
+			+ self.element_stack_bytes
+			+ self.eventq.capacity() * mem::size_of::<RawEvent>()
+			+ self.event_length
+	}
+
 	/// Check if the parser is poisoned and return the corresponding error.
 	fn check_poison(&self) -> Result<()> {
 		if let Some(e) = self.err.as_ref() {
@@ -322,6 +374,7 @@ impl RawParser {
 	///
 	/// May fail if the name is not namespace-well-formed.
 	fn start_processing_element(&mut self, name: Name) -> Result<RawEvent> {
+		self.element_stack_bytes += name.len();
 		self.element_stack.push(name.clone());
 		let (prefix, localname) = add_context(name.split_name(), ERRCTX_ELEMENT)?;
 		Ok(RawEvent::ElementHeadOpen(
@@ -336,7 +389,9 @@ impl RawParser {
 		let ev = RawEvent::ElementFoot(em);
 		self.emit_event(ev);
 		debug_assert!(self.element_stack.len() > 0);
-		self.element_stack.pop();
+		if let Some(name) = self.element_stack.pop() {
+			self.element_stack_bytes -= name.len();
+		}
 		if self.element_stack.len() == 0 {
 			Ok(State::End)
 		} else {
@@ -501,37 +556,59 @@ impl RawParser {
 	}
 
 	/// Finalize a single attribute and return the event.
-	fn finalize_attribute(&mut self, val: CData) -> Result<RawEvent> {
+	///
+	/// If `unexpanded` is true, `val` comes from a
+	/// [`Token::UnexpandedAttributeValue`].
+	fn finalize_attribute(&mut self, val: CData, unexpanded: bool) -> Result<RawEvent> {
 		let (prefix, localpart) = self.attribute_scratchpad.take().unwrap();
+		let is_declaration = match prefix.as_ref() {
+			Some(prefix) => prefix == "xmlns",
+			None => localpart == "xmlns",
+		};
+		let (val, unexpanded) = if unexpanded && is_declaration {
+			(expand_attribute_references(&val), false)
+		} else {
+			(val, unexpanded)
+		};
 		if let Some(prefix) = prefix.as_ref() {
 			if prefix == "xmlns" {
 				// Namespaces for XML 1.0
 				// Namespace constraint: Reserved Prefixes and Namespace Names
-				if localpart == "xml" {
+				let violation = if localpart == "xml" {
 					if val != XMLNS_XML {
-						return Err(Error::Xml(XmlError::ReservedNamespacePrefix));
+						Some(XmlError::ReservedNamespacePrefix)
+					} else {
+						None
 					}
+				} else if val == XMLNS_XML {
+					Some(XmlError::ReservedNamespaceName)
+				} else if val.is_empty() {
+					// Namespaces for XML 1.0
+					// Namespace constraint: No Prefix Undeclaring
+					Some(XmlError::EmptyNamespaceUri)
 				} else {
-					if val == XMLNS_XML {
-						return Err(Error::Xml(XmlError::ReservedNamespaceName));
-					}
-				}
-				// Namespaces for XML 1.0
-				// Namespace constraint: No Prefix Undeclaring
-				if val.len() == 0 {
-					return Err(Error::Xml(XmlError::EmptyNamespaceUri));
+					None
+				};
+				if let Some(violation) = violation {
+					return Err(NamespaceError::new(violation)
+						.with_prefix(localpart.clone())
+						.with_attribute(prefix.clone().add_suffix(&localpart))
+						.into());
 				}
 			}
 		} else if localpart == "xmlns" && val == XMLNS_XML {
 			// Namespaces for XML 1.0
 			// Namespace constraint: Reserved Prefixes and Namespace Names
-			return Err(Error::Xml(XmlError::ReservedNamespaceName));
+			return Err(NamespaceError::new(XmlError::ReservedNamespaceName)
+				.with_attribute(localpart.into_name())
+				.into());
+		}
+		let em = self.finish_event();
+		if unexpanded {
+			Ok(RawEvent::UnexpandedAttribute(em, (prefix, localpart), val))
+		} else {
+			Ok(RawEvent::Attribute(em, (prefix, localpart), val))
 		}
-		Ok(RawEvent::Attribute(
-			self.finish_event(),
-			(prefix, localpart),
-			val,
-		))
 	}
 
 	/// Element state
@@ -597,7 +674,11 @@ impl RawParser {
 					let (prefix, localname) = add_context(name.split_name(), ERRCTX_ATTNAME)?;
 					if let Some(prefix) = prefix.as_ref() {
 						if prefix == "xmlns" && localname == "xmlns" {
-							return Err(Error::Xml(XmlError::ReservedNamespacePrefix));
+							let name = prefix.clone().add_suffix(&localname);
+							return Err(NamespaceError::new(XmlError::ReservedNamespacePrefix)
+								.with_prefix(localname)
+								.with_attribute(name)
+								.into());
 						}
 					}
 					self.attribute_scratchpad = Some((prefix, localname));
@@ -617,23 +698,12 @@ impl RawParser {
 					None,
 				))),
 			},
-			Some(Token::AttributeValue(tm, val)) => match state {
-				ElementSt::AttrValue => {
-					let ev = self.finalize_attribute(val)?;
-					self.emit_event(ev);
-					// We have to start the event for further attribute names
-					// or for the closing symbol here, in order to account for
-					// whitespace between the things.
-					self.start_event(&tm);
-					self.event_length = 0;
-					Ok(State::Document(DocSt::Element(ElementSt::AttrName)))
-				}
-				_ => Err(Error::Xml(XmlError::UnexpectedToken(
-					ERRCTX_ELEMENT,
-					Token::NAME_EQ,
-					None,
-				))),
-			},
+			Some(Token::AttributeValue(tm, val)) => {
+				self.parse_attribute_value(state, tm, val, false)
+			}
+			Some(Token::UnexpandedAttributeValue(tm, val)) => {
+				self.parse_attribute_value(state, tm, val, true)
+			}
 			Some(tok) => Err(Error::Xml(XmlError::UnexpectedToken(
 				ERRCTX_ELEMENT,
 				tok.name(),
@@ -642,6 +712,35 @@ impl RawParser {
 		}
 	}
 
+	/// Attribute value in the element state
+	///
+	/// See [`Self::parse_element`].
+	fn parse_attribute_value(
+		&mut self,
+		state: ElementSt,
+		tm: TokenMetrics,
+		val: CData,
+		unexpanded: bool,
+	) -> Result<State> {
+		match state {
+			ElementSt::AttrValue => {
+				let ev = self.finalize_attribute(val, unexpanded)?;
+				self.emit_event(ev);
+				// We have to start the event for further attribute names
+				// or for the closing symbol here, in order to account for
+				// whitespace between the things.
+				self.start_event(&tm);
+				self.event_length = 0;
+				Ok(State::Document(DocSt::Element(ElementSt::AttrName)))
+			}
+			_ => Err(Error::Xml(XmlError::UnexpectedToken(
+				ERRCTX_ELEMENT,
+				Token::NAME_EQ,
+				None,
+			))),
+		}
+	}
+
 	/// Document content state
 	///
 	/// See [`State::Document`].
@@ -751,6 +850,13 @@ impl Parse for RawParser {
 		}
 	}
 
+	fn is_document_complete(&self) -> bool {
+		match self.state {
+			State::End | State::Eof => self.eventq.is_empty(),
+			_ => false,
+		}
+	}
+
 	fn release_temporaries(&mut self) {
 		self.eventq.shrink_to_fit();
 		self.element_stack.shrink_to_fit();
@@ -775,8 +881,7 @@ impl fmt::Debug for RawParser {
 mod tests {
 	use super::*;
 	use crate::lexer::TokenMetrics;
-	use std::convert::TryInto;
-	use std::io;
+	use core::convert::TryInto;
 
 	const TEST_NS: &'static str = "urn:uuid:4e1c8b65-ae37-49f8-a250-c27d52827da9";
 
@@ -823,7 +928,6 @@ mod tests {
 				Some(x) => {
 					self.offset += 1;
 					let result = x.clone();
-					println!("returning token {:?}", result);
 					Ok(Some(result))
 				}
 				None => Ok(None),
@@ -835,10 +939,7 @@ mod tests {
 		fn read(&mut self) -> Result<Option<Token>> {
 			if !self.has_blocked {
 				self.has_blocked = true;
-				return Err(Error::io(io::Error::new(
-					io::ErrorKind::WouldBlock,
-					"noise",
-				)));
+				return Err(Error::would_block("noise"));
 			}
 
 			match self.base.get(self.offset) {
@@ -846,7 +947,6 @@ mod tests {
 					self.has_blocked = false;
 					self.offset += 1;
 					let result = x.clone();
-					println!("returning token {:?}", result);
 					Ok(Some(result))
 				}
 				None => Ok(None),
@@ -907,19 +1007,14 @@ mod tests {
 
 		impl TokenRead for DegenerateTokenSource {
 			fn read(&mut self) -> Result<Option<Token>> {
-				Err(Error::io(io::Error::new(
-					io::ErrorKind::WouldBlock,
-					"nevar!",
-				)))
+				Err(Error::would_block("nevar!"))
 			}
 		}
 
 		let mut reader = DegenerateTokenSource();
 		let mut parser = RawParser::new();
 		let r = parser.parse(&mut reader);
-		assert!(
-			matches!(r.err().unwrap(), Error::IO(ioerr) if ioerr.kind() == io::ErrorKind::WouldBlock)
-		);
+		assert!(r.err().unwrap().is_would_block());
 	}
 
 	#[test]
@@ -937,7 +1032,7 @@ mod tests {
 
 		loop {
 			match parser.parse(&mut reader) {
-				Err(Error::IO(ioerr)) if ioerr.kind() == io::ErrorKind::WouldBlock => continue,
+				Err(e) if e.is_would_block() => continue,
 				Err(Error::Xml(XmlError::InvalidEof(ERRCTX_DOCBEGIN))) => break,
 				Err(other) => panic!("unexpected error: {:?}", other),
 				Ok(Some(ev)) => evs.push(ev),
@@ -1217,7 +1312,7 @@ mod tests {
 			ev => panic!("unexpected event: {:?}", ev),
 		}
 		match r {
-			Err(Error::Xml(XmlError::ReservedNamespacePrefix)) => (),
+			Err(Error::Namespace(e)) if e.error() == XmlError::ReservedNamespacePrefix => (),
 			other => panic!("unexpected result: {:?}", other),
 		}
 		assert_eq!(evs.len(), 0);
@@ -1279,7 +1374,7 @@ mod tests {
 			ev => panic!("unexpected event: {:?}", ev),
 		}
 		match r {
-			Err(Error::Xml(XmlError::ReservedNamespacePrefix)) => (),
+			Err(Error::Namespace(e)) if e.error() == XmlError::ReservedNamespacePrefix => (),
 			other => panic!("unexpected result: {:?}", other),
 		}
 		assert_eq!(evs.len(), 0);
@@ -1303,7 +1398,7 @@ mod tests {
 			ev => panic!("unexpected event: {:?}", ev),
 		}
 		match r {
-			Err(Error::Xml(XmlError::ReservedNamespaceName)) => (),
+			Err(Error::Namespace(e)) if e.error() == XmlError::ReservedNamespaceName => (),
 			other => panic!("unexpected result: {:?}", other),
 		}
 		assert_eq!(evs.len(), 0);
@@ -1327,7 +1422,7 @@ mod tests {
 			ev => panic!("unexpected event: {:?}", ev),
 		}
 		match r {
-			Err(Error::Xml(XmlError::ReservedNamespaceName)) => (),
+			Err(Error::Namespace(e)) if e.error() == XmlError::ReservedNamespaceName => (),
 			other => panic!("unexpected result: {:?}", other),
 		}
 		assert_eq!(evs.len(), 0);
@@ -1721,12 +1816,12 @@ mod tests {
 		}
 		let r = parser.parse(&mut reader);
 		match r {
-			Err(Error::Xml(XmlError::ReservedNamespacePrefix)) => (),
+			Err(Error::Namespace(e)) if e.error() == XmlError::ReservedNamespacePrefix => (),
 			other => panic!("unexpected result: {:?}", other),
 		}
 		let r = parser.parse(&mut reader);
 		match r {
-			Err(Error::Xml(XmlError::ReservedNamespacePrefix)) => (),
+			Err(Error::Namespace(e)) if e.error() == XmlError::ReservedNamespacePrefix => (),
 			other => panic!("unexpected result: {:?}", other),
 		}
 	}
@@ -1744,7 +1839,7 @@ mod tests {
 		];
 		let err = parse_err(toks).unwrap();
 		match err {
-			Error::Xml(XmlError::EmptyNamespaceUri) => (),
+			Error::Namespace(e) if e.error() == XmlError::EmptyNamespaceUri => (),
 			other => panic!("unexpected error: {:?}", other),
 		}
 	}
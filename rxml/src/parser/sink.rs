@@ -0,0 +1,132 @@
+use crate::strings::{CDataStr, NcNameStr};
+
+use super::attrs::Attributes;
+use super::common::{EventMetrics, XmlVersion};
+use super::namespaces::{Diagnostic, NamespaceName, ResolvedEvent, ResolvedQName};
+
+/**
+# Push-style receiver for parser events
+
+Instead of returning owned [`ResolvedEvent`]s, the parser can call the
+methods of an [`EventSink`] with borrowed arguments (see
+[`Parser::parse_into`](crate::Parser::parse_into) and the `*_into` methods
+on the frontends). This allows the parser to reuse its buffers: in
+particular, the attributes of an element are collected in a buffer which is
+kept across elements, so that no allocation is needed for them even on
+elements with many attributes.
+
+Only the element and text methods must be implemented; the others default
+to ignoring the event.
+
+```
+use rxml::{EventSink, FeedParser, ResolvedQName};
+use rxml::parser::{Attributes, EventMetrics};
+use rxml::strings::{CDataStr, NcNameStr};
+
+#[derive(Default)]
+struct CountElements {
+	elements: usize,
+	text_bytes: usize,
+}
+
+impl EventSink for CountElements {
+	fn start_element(
+		&mut self,
+		_: &EventMetrics,
+		_: &ResolvedQName,
+		_: &Attributes,
+		_: Option<&NcNameStr>,
+	) {
+		self.elements += 1;
+	}
+
+	fn end_element(&mut self, _: &EventMetrics) {}
+
+	fn text(&mut self, _: &EventMetrics, text: &CDataStr) {
+		self.text_bytes += text.len();
+	}
+}
+
+let mut fp = FeedParser::default();
+let mut counter = CountElements::default();
+let mut doc = &b"<a x='1'><b>foo</b><b y='2'/></a>"[..];
+fp.parse_all_into(&mut doc, true, &mut counter).unwrap();
+assert_eq!(counter.elements, 3);
+assert_eq!(counter.text_bytes, 3);
+```
+*/
+pub trait EventSink {
+	/// The XML declaration has been read.
+	fn xml_declaration(&mut self, metrics: &EventMetrics, version: XmlVersion) {
+		let _ = (metrics, version);
+	}
+
+	/// An element starts.
+	///
+	/// The attributes do not include namespace declarations. The reference
+	/// is only valid for the duration of the call; the parser reuses the
+	/// storage afterwards. `prefix` is the prefix with which the element
+	/// name was written, as in [`ResolvedEvent::StartElement`].
+	fn start_element(
+		&mut self,
+		metrics: &EventMetrics,
+		name: &ResolvedQName,
+		attributes: &Attributes,
+		prefix: Option<&NcNameStr>,
+	);
+
+	/// An element ends.
+	fn end_element(&mut self, metrics: &EventMetrics);
+
+	/// Character data has been read.
+	///
+	/// As with [`ResolvedEvent::Text`], consecutive calls may occur for a
+	/// single piece of text. Where possible, `text` points directly into
+	/// the buffer of the data source instead of a copy.
+	fn text(&mut self, metrics: &EventMetrics, text: &CDataStr);
+
+	/// A namespace prefix mapping comes into scope.
+	///
+	/// See [`ResolvedEvent::StartPrefixMapping`]; this is only called if
+	/// prefix mapping events are enabled on the parser.
+	fn start_prefix_mapping(
+		&mut self,
+		metrics: &EventMetrics,
+		prefix: Option<&NcNameStr>,
+		namespace: &NamespaceName,
+	) {
+		let _ = (metrics, prefix, namespace);
+	}
+
+	/// A namespace prefix mapping goes out of scope.
+	///
+	/// See [`ResolvedEvent::EndPrefixMapping`].
+	fn end_prefix_mapping(&mut self, metrics: &EventMetrics, prefix: Option<&NcNameStr>) {
+		let _ = (metrics, prefix);
+	}
+
+	/// A non-fatal problem has been detected.
+	///
+	/// See [`Diagnostic`]; this is called right before the
+	/// [`start_element`](Self::start_element) call for the element which
+	/// caused the problem.
+	fn diagnostic(&mut self, diagnostic: &Diagnostic) {
+		let _ = diagnostic;
+	}
+}
+
+/// Pass an owned event to the matching method of a sink.
+pub(crate) fn dispatch<S: EventSink + ?Sized>(ev: &ResolvedEvent, sink: &mut S) {
+	match ev {
+		ResolvedEvent::XmlDeclaration(m, v) => sink.xml_declaration(m, *v),
+		ResolvedEvent::StartElement(m, name, attrs, prefix) => {
+			sink.start_element(m, name, attrs, prefix.as_deref())
+		}
+		ResolvedEvent::EndElement(m) => sink.end_element(m),
+		ResolvedEvent::Text(m, text) => sink.text(m, text),
+		ResolvedEvent::StartPrefixMapping(m, prefix, ns) => {
+			sink.start_prefix_mapping(m, prefix.as_deref(), ns)
+		}
+		ResolvedEvent::EndPrefixMapping(m, prefix) => sink.end_prefix_mapping(m, prefix.as_deref()),
+	}
+}
@@ -0,0 +1,197 @@
+use crate::strings::CDataStr;
+
+use super::common::RcPtr;
+use super::namespaces::ResolvedEvent;
+
+/**
+# Statistics about a parsed document
+
+Counters about the events which a [`Parser`](crate::Parser) has returned so
+far, obtained with [`Parser::statistics`](crate::Parser::statistics). They
+are updated as the events are emitted, so they can be sampled at any time,
+for instance to feed metrics or to detect peers sending unusually deep or
+large documents.
+
+```
+use rxml::FeedParser;
+
+let mut fp = FeedParser::default();
+let mut doc = &b"<a xmlns='urn:x'><b>foo</b><b xmlns:p='urn:p'>bar</b></a>"[..];
+fp.parse_all(&mut doc, true, |_| ()).unwrap();
+let stats = fp.get_parser().statistics();
+assert_eq!(stats.start_elements(), 3);
+assert_eq!(stats.max_depth(), 2);
+assert_eq!(stats.text_bytes(), 6);
+assert_eq!(stats.namespace_declarations(), 2);
+```
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Statistics {
+	xml_declarations: u64,
+	start_elements: u64,
+	end_elements: u64,
+	texts: u64,
+	start_prefix_mappings: u64,
+	end_prefix_mappings: u64,
+	text_bytes: u64,
+	namespace_declarations: u64,
+	depth: usize,
+	max_depth: usize,
+}
+
+impl Statistics {
+	/// Total number of events.
+	pub fn events(&self) -> u64 {
+		self.xml_declarations
+			+ self.start_elements
+			+ self.end_elements
+			+ self.texts
+			+ self.start_prefix_mappings
+			+ self.end_prefix_mappings
+	}
+
+	/// Number of [`ResolvedEvent::XmlDeclaration`] events.
+	pub fn xml_declarations(&self) -> u64 {
+		self.xml_declarations
+	}
+
+	/// Number of [`ResolvedEvent::StartElement`] events.
+	pub fn start_elements(&self) -> u64 {
+		self.start_elements
+	}
+
+	/// Number of [`ResolvedEvent::EndElement`] events.
+	pub fn end_elements(&self) -> u64 {
+		self.end_elements
+	}
+
+	/// Number of [`ResolvedEvent::Text`] events.
+	///
+	/// As a single piece of text may be split into multiple events, this is
+	/// not necessarily the number of text nodes in the document.
+	pub fn texts(&self) -> u64 {
+		self.texts
+	}
+
+	/// Number of [`ResolvedEvent::StartPrefixMapping`] events.
+	///
+	/// These are only emitted if enabled, see
+	/// [`Parser::set_emit_prefix_mappings`](crate::Parser::set_emit_prefix_mappings).
+	/// Use [`namespace_declarations`](Self::namespace_declarations) to count
+	/// declarations independently of that setting.
+	pub fn start_prefix_mappings(&self) -> u64 {
+		self.start_prefix_mappings
+	}
+
+	/// Number of [`ResolvedEvent::EndPrefixMapping`] events.
+	pub fn end_prefix_mappings(&self) -> u64 {
+		self.end_prefix_mappings
+	}
+
+	/// Total number of bytes of character data, after expansion of
+	/// references.
+	pub fn text_bytes(&self) -> u64 {
+		self.text_bytes
+	}
+
+	/// Number of namespace declarations (`xmlns` and `xmlns:*` attributes)
+	/// in the document.
+	///
+	/// Prefixes declared up front with
+	/// [`Parser::declare_prefix`](crate::Parser::declare_prefix) are not
+	/// counted.
+	pub fn namespace_declarations(&self) -> u64 {
+		self.namespace_declarations
+	}
+
+	/// Number of currently open elements.
+	pub fn depth(&self) -> usize {
+		self.depth
+	}
+
+	/// Largest number of simultaneously open elements seen so far.
+	pub fn max_depth(&self) -> usize {
+		self.max_depth
+	}
+
+	pub(super) fn set_namespace_declarations(&mut self, n: u64) {
+		self.namespace_declarations = n;
+	}
+
+	pub(super) fn xml_declaration(&mut self) {
+		self.xml_declarations += 1;
+	}
+
+	pub(super) fn start_element(&mut self) {
+		self.start_elements += 1;
+		self.depth += 1;
+		self.max_depth = self.max_depth.max(self.depth);
+	}
+
+	pub(super) fn end_element(&mut self) {
+		self.end_elements += 1;
+		self.depth -= 1;
+	}
+
+	pub(super) fn text(&mut self, text: &CDataStr) {
+		self.texts += 1;
+		self.text_bytes += text.len() as u64;
+	}
+
+	pub(super) fn start_prefix_mapping(&mut self) {
+		self.start_prefix_mappings += 1;
+	}
+
+	pub(super) fn end_prefix_mapping(&mut self) {
+		self.end_prefix_mappings += 1;
+	}
+
+	pub(super) fn observe(&mut self, ev: &ResolvedEvent) {
+		match ev {
+			ResolvedEvent::XmlDeclaration(..) => self.xml_declaration(),
+			ResolvedEvent::StartElement(..) => self.start_element(),
+			ResolvedEvent::EndElement(..) => self.end_element(),
+			ResolvedEvent::Text(_, text) => self.text(text),
+			ResolvedEvent::StartPrefixMapping(..) => self.start_prefix_mapping(),
+			ResolvedEvent::EndPrefixMapping(..) => self.end_prefix_mapping(),
+		}
+	}
+}
+
+/**
+Callback for progress reports, see
+[`Parser::set_progress_hook`](crate::Parser::set_progress_hook).
+
+The hook receives the number of bytes consumed so far (as returned by
+[`Parser::position`](crate::Parser::position)) and the current
+[`Statistics`].
+*/
+pub type ProgressHook = dyn Fn(u64, &Statistics) + Send + Sync;
+
+/// State of a registered [`ProgressHook`].
+#[derive(Clone)]
+pub(super) struct Progress {
+	pub(super) hook: RcPtr<ProgressHook>,
+	every: u64,
+	next: u64,
+}
+
+impl Progress {
+	pub(super) fn new(every: u64, hook: RcPtr<ProgressHook>) -> Self {
+		Self {
+			hook,
+			every,
+			next: every,
+		}
+	}
+
+	/// Return whether the hook should be called at `position` and, if so,
+	/// schedule the next call.
+	pub(super) fn due(&mut self, position: u64, finished: bool) -> bool {
+		if position < self.next && !finished {
+			return false;
+		}
+		self.next = position.saturating_add(self.every);
+		true
+	}
+}
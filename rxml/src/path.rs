@@ -0,0 +1,702 @@
+/*!
+# Extracting parts of a document by path
+
+The [`PathMatcher`] watches the events of a document for elements and
+attributes selected by simple path patterns, similar to a small subset of
+XPath, and passes each match to a callback registered with the pattern.
+Subtrees which cannot contain any match are skipped cheaply: the matcher is
+an [`EventSink`], so that their events are never materialized, and it does
+not keep any state for them beyond a counter. This makes it practical to
+pick a few values out of very large documents.
+
+## Pattern syntax
+
+A pattern is a sequence of steps, each introduced by `/` or `//`:
+
+* `/name` selects child elements with the given local name, `//name`
+  selects descendant elements with that name.
+* `*` instead of a name selects elements with any name.
+* A name may be prefixed with a namespace URI in braces, e.g.
+  `{urn:ietf:params:xml:ns:xmpp-sasl}mechanism`. `{}name` only selects
+  elements without a namespace. Without braces, the namespace is not
+  checked; in contrast to XPath, there are no namespace prefixes.
+* The last step may select attributes instead of elements: `/@name` selects
+  attributes of the element selected by the previous steps, `//@name`
+  attributes of that element and all of its descendants. Attribute names
+  take braces and `*` like element names.
+
+Patterns always start at the document: `/a/b` selects `b` children of the
+root element `a`, while `//b` selects `b` elements anywhere.
+
+## Matches
+
+For an element pattern, the callback receives the complete element as
+[`Match::Element`], once its end has been read. For an attribute pattern,
+it receives the name and value of the attribute as [`Match::Attribute`],
+right when the element's start has been read.
+
+Elements matched by multiple patterns are delivered to each of them.
+Matches within the content of a matched element are reported separately,
+after the callbacks for the inner element have been called.
+
+## Example
+
+Collecting the SASL mechanisms offered in an XMPP stream header and the ids
+of the IQ stanzas which follow it:
+
+```
+# #[cfg(feature = "std")]
+# fn main() {
+use rxml::PullParser;
+use rxml::path::{Match, PathMatcher};
+
+let doc = b"<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'>\
+	<stream:features><mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'>\
+	<mechanism>PLAIN</mechanism><mechanism>SCRAM-SHA-1</mechanism>\
+	</mechanisms></stream:features>\
+	<iq id='a1'><query id='ignored'/></iq><iq id='a2'/>\
+	</stream:stream>";
+
+let mut mechanisms = Vec::new();
+let mut ids = Vec::new();
+let mut matcher = PathMatcher::new();
+matcher.subscribe("/stream/features/mechanisms/mechanism", |m| {
+	if let Match::Element(el) = m {
+		mechanisms.push(rxml::mapping::text_of(&el).to_string());
+	}
+}).unwrap();
+matcher.subscribe("/stream/iq/@id", |m| {
+	if let Match::Attribute(_, value) = m {
+		ids.push(value.to_string());
+	}
+}).unwrap();
+PullParser::new(&doc[..]).read_all_into(&mut matcher).unwrap();
+drop(matcher);
+
+assert_eq!(mechanisms, &["PLAIN", "SCRAM-SHA-1"]);
+assert_eq!(ids, &["a1", "a2"]);
+# }
+# #[cfg(not(feature = "std"))]
+# fn main() {}
+```
+*/
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::parser::{Attributes, EventMetrics, EventSink, ResolvedEvent, ResolvedQName};
+use crate::strings::{CData, CDataStr, NcName, NcNameStr};
+use crate::tree::{Element, ElementBuilder};
+
+/// Error returned when parsing an invalid [`Pattern`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternError {
+	pos: usize,
+	msg: &'static str,
+}
+
+impl PatternError {
+	/// Byte offset in the pattern at which the problem was detected.
+	pub fn position(&self) -> usize {
+		self.pos
+	}
+}
+
+impl fmt::Display for PatternError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} (at byte {})", self.msg, self.pos)
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PatternError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NamespaceTest {
+	Any,
+	None,
+	Uri(String),
+}
+
+/// Test for the name of an element or attribute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NameTest {
+	namespace: NamespaceTest,
+	/// The local name, or `None` for `*`.
+	local: Option<NcName>,
+}
+
+impl NameTest {
+	fn matches(&self, name: &ResolvedQName) -> bool {
+		let namespace_ok = match (&self.namespace, &name.0) {
+			(NamespaceTest::Any, _) => true,
+			(NamespaceTest::None, ns) => ns.is_none(),
+			(NamespaceTest::Uri(uri), Some(ns)) => ns.as_str() == uri,
+			(NamespaceTest::Uri(_), None) => false,
+		};
+		namespace_ok
+			&& match self.local.as_ref() {
+				Some(local) => *local == name.1,
+				None => true,
+			}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Step {
+	/// Whether the step selects descendants instead of children.
+	descendant: bool,
+	test: NameTest,
+}
+
+/**
+# Compiled path pattern
+
+See the [module documentation](self) for the syntax. Patterns are usually
+passed to [`PathMatcher::subscribe`] as strings; parsing them up front
+allows to report syntax errors early and to reuse them.
+
+```
+use rxml::path::Pattern;
+
+let pattern: Pattern = "//item/@id".parse().unwrap();
+assert_eq!(pattern.to_string(), "//item/@id");
+assert!("item".parse::<Pattern>().is_err());
+```
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+	source: String,
+	steps: Vec<Step>,
+	attribute: Option<NameTest>,
+}
+
+struct PatternParser<'x> {
+	s: &'x str,
+	pos: usize,
+}
+
+impl<'x> PatternParser<'x> {
+	fn err<T>(&self, msg: &'static str) -> Result<T, PatternError> {
+		Err(PatternError { pos: self.pos, msg })
+	}
+
+	fn rest(&self) -> &'x str {
+		&self.s[self.pos..]
+	}
+
+	fn eat(&mut self, lit: &str) -> bool {
+		if self.rest().starts_with(lit) {
+			self.pos += lit.len();
+			true
+		} else {
+			false
+		}
+	}
+
+	fn name_test(&mut self) -> Result<NameTest, PatternError> {
+		let namespace = if self.eat("{") {
+			let end = match self.rest().find('}') {
+				Some(end) => end,
+				None => return self.err("unterminated namespace URI"),
+			};
+			let uri = &self.rest()[..end];
+			if <&CDataStr>::try_from(uri).is_err() {
+				return self.err("invalid namespace URI");
+			}
+			self.pos += end + 1;
+			if uri.is_empty() {
+				NamespaceTest::None
+			} else {
+				NamespaceTest::Uri(uri.to_string())
+			}
+		} else {
+			NamespaceTest::Any
+		};
+		if self.eat("*") {
+			return Ok(NameTest {
+				namespace,
+				local: None,
+			});
+		}
+		let len = self.rest().find('/').unwrap_or(self.rest().len());
+		if len == 0 {
+			return self.err("expected a name or `*`");
+		}
+		let local = match NcName::try_from(&self.rest()[..len]) {
+			Ok(local) => local,
+			Err(_) => return self.err("invalid name"),
+		};
+		self.pos += len;
+		Ok(NameTest {
+			namespace,
+			local: Some(local),
+		})
+	}
+
+	fn pattern(mut self) -> Result<Pattern, PatternError> {
+		if !self.rest().starts_with('/') {
+			return self.err("pattern must start with `/` or `//`");
+		}
+		let mut steps = Vec::new();
+		let mut attribute = None;
+		while !self.rest().is_empty() {
+			let descendant = if self.eat("//") {
+				true
+			} else if self.eat("/") {
+				false
+			} else {
+				return self.err("expected `/` or `//`");
+			};
+			if self.eat("@") {
+				if descendant {
+					steps.push(Step {
+						descendant: true,
+						test: NameTest {
+							namespace: NamespaceTest::Any,
+							local: None,
+						},
+					});
+				}
+				attribute = Some(self.name_test()?);
+				if !self.rest().is_empty() {
+					return self.err("attributes can only be selected by the last step");
+				}
+			} else {
+				let test = self.name_test()?;
+				steps.push(Step { descendant, test });
+			}
+		}
+		if steps.is_empty() {
+			return self.err("pattern must select at least one element");
+		}
+		Ok(Pattern {
+			source: self.s.to_string(),
+			steps,
+			attribute,
+		})
+	}
+}
+
+impl FromStr for Pattern {
+	type Err = PatternError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		PatternParser { s, pos: 0 }.pattern()
+	}
+}
+
+impl fmt::Display for Pattern {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.source)
+	}
+}
+
+/// A match reported by a [`PathMatcher`].
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Match {
+	/// An element selected by the pattern, including all of its content.
+	Element(Element),
+	/// An attribute selected by the pattern: its name and its value.
+	Attribute(ResolvedQName, CData),
+}
+
+struct Subscription<'a> {
+	pattern: Pattern,
+	callback: Box<dyn FnMut(Match) + 'a>,
+}
+
+/// An element which is being collected for the subscriptions which matched
+/// it.
+struct Capture {
+	subscriptions: Vec<usize>,
+	builder: ElementBuilder,
+}
+
+/**
+# Matcher for path patterns
+
+The matcher receives the events of a document as an [`EventSink`] (for
+instance via [`PullDriver::read_all_into`](crate::PullDriver::read_all_into)
+or [`PushDriver::parse_all_into`](crate::PushDriver::parse_all_into)) or
+via [`push`](Self::push), and calls the callbacks of all subscriptions
+whose pattern matches. See the [module documentation](self) for details
+and an example.
+
+The matcher tracks the position in a single document. Subscriptions should
+be made before the first event is passed to it; subscriptions made later
+only see elements whose parent started after the subscription.
+*/
+#[derive(Default)]
+pub struct PathMatcher<'a> {
+	subscriptions: Vec<Subscription<'a>>,
+	/// For each open element, the (subscription, number of matched steps)
+	/// pairs which may still lead to a match.
+	levels: Vec<Vec<(usize, usize)>>,
+	/// Number of open elements within a subtree which cannot match.
+	skipping: usize,
+	/// Matched elements which are still open, outermost first.
+	captures: Vec<Capture>,
+}
+
+impl<'a> PathMatcher<'a> {
+	/// Create a matcher without subscriptions.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Parse `pattern` and call `callback` for each match.
+	pub fn subscribe<F: FnMut(Match) + 'a>(
+		&mut self,
+		pattern: &str,
+		callback: F,
+	) -> Result<(), PatternError> {
+		self.subscribe_pattern(pattern.parse()?, callback);
+		Ok(())
+	}
+
+	/// Call `callback` for each match of a parsed `pattern`.
+	pub fn subscribe_pattern<F: FnMut(Match) + 'a>(&mut self, pattern: Pattern, callback: F) {
+		self.subscriptions.push(Subscription {
+			pattern,
+			callback: Box::new(callback),
+		});
+	}
+
+	/// Return true if the matcher currently skips a subtree, because it
+	/// cannot contain any match.
+	pub fn is_skipping(&self) -> bool {
+		self.skipping > 0
+	}
+
+	/// Process an owned event.
+	///
+	/// This is equivalent to calling the corresponding [`EventSink`]
+	/// method.
+	pub fn push(&mut self, ev: &ResolvedEvent) {
+		match ev {
+			ResolvedEvent::StartElement(m, name, attrs, prefix) => {
+				self.start_element(m, name, attrs, prefix.as_deref())
+			}
+			ResolvedEvent::EndElement(m) => self.end_element(m),
+			ResolvedEvent::Text(m, text) => self.text(m, text),
+			ResolvedEvent::XmlDeclaration(..)
+			| ResolvedEvent::StartPrefixMapping(..)
+			| ResolvedEvent::EndPrefixMapping(..) => (),
+		}
+	}
+
+	/// Advance the states of the parent element over the element `name`.
+	///
+	/// Returns the states for the content of the element and the
+	/// subscriptions whose element steps have all been matched.
+	fn advance(&self, name: &ResolvedQName) -> (Vec<(usize, usize)>, Vec<usize>) {
+		let initial;
+		let parent = match self.levels.last() {
+			Some(states) => states,
+			None => {
+				initial = (0..self.subscriptions.len()).map(|i| (i, 0)).collect();
+				&initial
+			}
+		};
+		let mut next = Vec::new();
+		let mut matched = Vec::new();
+		for &(sub, i) in parent.iter() {
+			let steps = &self.subscriptions[sub].pattern.steps;
+			let step = &steps[i];
+			if step.descendant && !next.contains(&(sub, i)) {
+				next.push((sub, i));
+			}
+			if !step.test.matches(name) {
+				continue;
+			}
+			if i + 1 < steps.len() {
+				if !next.contains(&(sub, i + 1)) {
+					next.push((sub, i + 1));
+				}
+			} else if !matched.contains(&sub) {
+				matched.push(sub);
+			}
+		}
+		(next, matched)
+	}
+}
+
+impl<'a> EventSink for PathMatcher<'a> {
+	fn start_element(
+		&mut self,
+		metrics: &EventMetrics,
+		name: &ResolvedQName,
+		attributes: &Attributes,
+		prefix: Option<&NcNameStr>,
+	) {
+		if self.skipping > 0 {
+			self.skipping += 1;
+			return;
+		}
+		for capture in self.captures.iter_mut() {
+			capture.builder.push(ResolvedEvent::StartElement(
+				*metrics,
+				name.clone(),
+				attributes.clone(),
+				prefix.map(|x| x.to_ncname()),
+			));
+		}
+
+		let (next, matched) = self.advance(name);
+		let mut elements = Vec::new();
+		for sub in matched {
+			let subscription = &mut self.subscriptions[sub];
+			match subscription.pattern.attribute {
+				Some(ref test) => {
+					for (attr, value) in attributes.iter() {
+						if test.matches(attr) {
+							(subscription.callback)(Match::Attribute(
+								attr.clone(),
+								value.into_owned(),
+							));
+						}
+					}
+				}
+				None => elements.push(sub),
+			}
+		}
+		if !elements.is_empty() {
+			self.captures.push(Capture {
+				subscriptions: elements,
+				builder: ElementBuilder::with_start(name.clone(), attributes.clone()),
+			});
+		}
+
+		if next.is_empty() && self.captures.is_empty() {
+			self.skipping = 1;
+		} else {
+			self.levels.push(next);
+		}
+	}
+
+	fn end_element(&mut self, metrics: &EventMetrics) {
+		if self.skipping > 0 {
+			self.skipping -= 1;
+			return;
+		}
+		self.levels.pop();
+		let mut finished = None;
+		for capture in self.captures.iter_mut() {
+			if let Some(el) = capture.builder.push(ResolvedEvent::EndElement(*metrics)) {
+				finished = Some(el);
+			}
+		}
+		// only the innermost capture can end with the current element
+		if let Some(el) = finished {
+			let capture = self.captures.pop().unwrap();
+			let (last, others) = capture.subscriptions.split_last().unwrap();
+			for sub in others {
+				(self.subscriptions[*sub].callback)(Match::Element(el.clone()));
+			}
+			(self.subscriptions[*last].callback)(Match::Element(el));
+		}
+	}
+
+	fn text(&mut self, metrics: &EventMetrics, text: &CDataStr) {
+		for capture in self.captures.iter_mut() {
+			capture
+				.builder
+				.push(ResolvedEvent::Text(*metrics, text.to_cdata()));
+		}
+	}
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+	use super::*;
+
+	use std::cell::RefCell;
+
+	use crate::PullParser;
+
+	/// Run `patterns` over `doc` and return the matches, tagged with the
+	/// index of the pattern.
+	fn run(patterns: &[&str], doc: &[u8]) -> Vec<(usize, Match)> {
+		let matches = RefCell::new(Vec::new());
+		let mut matcher = PathMatcher::new();
+		for (i, pattern) in patterns.iter().enumerate() {
+			let matches = &matches;
+			matcher
+				.subscribe(pattern, move |m| matches.borrow_mut().push((i, m)))
+				.unwrap();
+		}
+		PullParser::new(doc).read_all_into(&mut matcher).unwrap();
+		drop(matcher);
+		matches.into_inner()
+	}
+
+	fn names(matches: &[(usize, Match)]) -> Vec<(usize, String)> {
+		matches
+			.iter()
+			.map(|(i, m)| match m {
+				Match::Element(el) => (*i, el.name.1.to_string()),
+				Match::Attribute(name, value) => (*i, format!("@{}={}", name.1, value)),
+			})
+			.collect()
+	}
+
+	#[test]
+	fn pattern_parser_accepts_valid_patterns() {
+		for pattern in [
+			"/a",
+			"/a/b/c",
+			"//a",
+			"/a//b",
+			"/*/b",
+			"/a/@x",
+			"//@x",
+			"/a//@*",
+			"/{urn:x}a/{}b/{urn:y}*",
+			"/a/@{urn:x}y",
+			"/{http://example.com/ns}a",
+		] {
+			let parsed: Pattern = pattern.parse().unwrap();
+			assert_eq!(parsed.to_string(), pattern);
+		}
+	}
+
+	#[test]
+	fn pattern_parser_rejects_invalid_patterns() {
+		for (pattern, pos) in [
+			("", 0),
+			("a/b", 0),
+			("/", 1),
+			("/a/", 3),
+			("///a", 2),
+			("/a b", 1),
+			("/{urn:x", 2),
+			("/@x", 3),
+			("/a/@x/b", 5),
+			("/a/@", 4),
+		] {
+			match pattern.parse::<Pattern>() {
+				Err(e) => assert_eq!(e.position(), pos, "{:?}: {}", pattern, e),
+				Ok(p) => panic!("pattern {:?} accepted: {:?}", pattern, p),
+			}
+		}
+	}
+
+	#[test]
+	fn matches_child_paths() {
+		let matches = run(
+			&["/a/b", "/a/b/c"],
+			b"<a><b><c>1</c></b><x><b/></x><b><c>2</c><c/></b></a>",
+		);
+		assert_eq!(
+			names(&matches),
+			&[
+				(1, "c".to_string()),
+				(0, "b".to_string()),
+				(1, "c".to_string()),
+				(1, "c".to_string()),
+				(0, "b".to_string()),
+			]
+		);
+		match &matches[2].1 {
+			Match::Element(el) => assert_eq!(crate::mapping::text_of(el), "2"),
+			other => panic!("unexpected match: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn matches_descendants_at_any_depth() {
+		let matches = run(
+			&["//item", "/root//leaf"],
+			b"<root><item><item/></item><x><y><item><leaf/></item></y></x><leaf/></root>",
+		);
+		assert_eq!(
+			names(&matches),
+			&[
+				(0, "item".to_string()),
+				(0, "item".to_string()),
+				(1, "leaf".to_string()),
+				(0, "item".to_string()),
+				(1, "leaf".to_string()),
+			]
+		);
+		match &matches[1].1 {
+			Match::Element(el) => assert_eq!(el.children.len(), 1),
+			other => panic!("unexpected match: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn matches_attributes() {
+		let matches = run(
+			&["//item/@id", "/list/@*"],
+			b"<list n='2'><item id='1' x='y'/><group><item id='2'/></group><item/></list>",
+		);
+		assert_eq!(
+			names(&matches),
+			&[
+				(1, "@n=2".to_string()),
+				(0, "@id=1".to_string()),
+				(0, "@id=2".to_string()),
+			]
+		);
+	}
+
+	#[test]
+	fn matches_namespaces() {
+		let matches = run(
+			&["/{urn:a}r/{urn:b}*", "/r/{}c", "/r/c/@{urn:b}x"],
+			b"<r xmlns='urn:a' xmlns:b='urn:b'><c b:x='1' x='2'/><b:c/><c xmlns=''/></r>",
+		);
+		assert_eq!(
+			names(&matches),
+			&[
+				(2, "@x=1".to_string()),
+				(0, "c".to_string()),
+				(1, "c".to_string()),
+			]
+		);
+	}
+
+	#[test]
+	fn delivers_shared_matches_to_all_subscriptions() {
+		let matches = run(&["/a/b", "//b"], b"<a><b>x</b></a>");
+		assert_eq!(matches.len(), 2);
+		assert_eq!(matches[0].1, matches[1].1);
+	}
+
+	#[test]
+	fn skips_subtrees_which_cannot_match() {
+		let mut matcher = PathMatcher::new();
+		matcher.subscribe("/a/b", |_| ()).unwrap();
+		let mut pp = PullParser::new(&b"<a><x><b/></x><b/></a>"[..]);
+		assert!(pp.read_into(&mut matcher).unwrap());
+		assert!(!matcher.is_skipping());
+		assert!(pp.read_into(&mut matcher).unwrap());
+		assert!(matcher.is_skipping());
+		assert!(pp.read_into(&mut matcher).unwrap());
+		assert!(pp.read_into(&mut matcher).unwrap());
+		assert!(matcher.is_skipping());
+		assert!(pp.read_into(&mut matcher).unwrap());
+		assert!(!matcher.is_skipping());
+		assert_eq!(matcher.levels.len(), 1);
+	}
+
+	#[test]
+	fn accepts_owned_events() {
+		let matches = RefCell::new(Vec::new());
+		let mut matcher = PathMatcher::new();
+		matcher
+			.subscribe("/a/b", |m| matches.borrow_mut().push(m))
+			.unwrap();
+		let mut fp = crate::FeedParser::default();
+		fp.parse_all(&mut &b"<a><b>foo</b></a>"[..], true, |ev| matcher.push(&ev))
+			.unwrap();
+		drop(matcher);
+		assert_eq!(matches.into_inner().len(), 1);
+	}
+}
@@ -0,0 +1,203 @@
+/*!
+# Configuration presets for the parser
+
+The lexer and the parser have accumulated a fair number of knobs. A
+[`Profile`] bundles them into combinations which suit common kinds of
+input, so that an application does not have to weigh each option on its
+own. Profiles can be used with every parser frontend, e.g. via
+[`FeedParser::with_profile`](crate::FeedParser::with_profile) or
+[`PullParser::with_profile`](crate::PullParser::with_profile).
+
+A profile only selects initial values. Each option can still be changed
+afterwards through [`get_parser_mut`](crate::PushDriver::get_parser_mut)
+and the setters of the [`Parser`].
+*/
+use crate::lexer::{BufferGrowth, Lexer, LexerOptions};
+use crate::parser::Parser;
+
+/// Namespace declarations allowed on a single element by
+/// [`Profile::StrictXmpp`].
+const XMPP_MAX_NAMESPACE_DECLARATIONS_PER_ELEMENT: usize = 16;
+
+/// Memory budget of the parser under [`Profile::StrictXmpp`].
+const XMPP_MEMORY_BUDGET: usize = 1024 * 1024;
+
+/// Buffer size above which lexer buffers are released again under
+/// [`Profile::StrictXmpp`].
+const XMPP_BUFFER_SHRINK_THRESHOLD: usize = 16 * 1024;
+
+/// Maximum token length under [`Profile::LenientFeed`].
+const FEED_MAX_TOKEN_LENGTH: usize = 64 * 1024;
+
+/**
+# Named set of parser options
+
+Each variant describes the lexer options ([`Profile::lexer_options`]) and
+parser settings ([`Profile::apply`]) which suit a kind of input.
+
+Comments, processing instructions and document type declarations are
+always rejected by this crate, independent of the profile (see the
+[crate documentation](crate)). Profiles cannot relax this.
+
+## Example
+
+```
+use rxml::{FeedParser, Profile, ResolvedEvent, as_eof_flag};
+
+let mut fp = FeedParser::with_profile(Profile::LenientFeed);
+let mut doc = &b"<feed><media:thumbnail/></feed>"[..];
+let mut events = Vec::new();
+as_eof_flag(fp.parse_all(&mut doc, true, |ev| events.push(ev))).unwrap();
+// the undeclared prefix is dropped instead of failing the document
+let ev = &events[1];
+assert!(matches!(ev, ResolvedEvent::StartElement(_, (None, name), _, _) if name == "thumbnail"));
+```
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum Profile {
+	/// The defaults of [`LexerOptions`] and [`Parser`].
+	#[default]
+	Default,
+
+	/// Settings for long-lived XMPP streams from untrusted peers.
+	///
+	/// - Namespace prefixes must be declared and `xml:id` is not checked.
+	/// - At most 16 namespace declarations are allowed per element. The
+	///   number of declarations in the whole stream is not limited, as a
+	///   stream carries an unbounded number of stanzas.
+	/// - The memory used by the parser is limited to 1 MiB (see
+	///   [`Parser::set_memory_budget`]).
+	/// - Lexer buffers grow on demand and are released again once they
+	///   exceed 16 KiB, so that idle connections stay small.
+	///
+	/// Prefixes declared on the stream header by the transport can be
+	/// made known to the parser with [`Parser::declare_prefix`] before
+	/// parsing.
+	StrictXmpp,
+
+	/// Settings for syndication feeds and similar documents found in the
+	/// wild.
+	///
+	/// - Undeclared namespace prefixes are tolerated and recorded as
+	///   diagnostics (see [`Parser::set_lenient_namespaces`]).
+	/// - Tokens may be up to 64 KiB long to accommodate large attribute
+	///   values, with buffers growing on demand.
+	LenientFeed,
+}
+
+impl Profile {
+	/// Return the lexer options of this profile.
+	pub fn lexer_options(&self) -> LexerOptions {
+		let options = LexerOptions::default();
+		match self {
+			Self::Default => options,
+			Self::StrictXmpp => options
+				.buffer_growth(BufferGrowth::OnDemand)
+				.buffer_shrink_threshold(Some(XMPP_BUFFER_SHRINK_THRESHOLD)),
+			Self::LenientFeed => options
+				.max_token_length(FEED_MAX_TOKEN_LENGTH)
+				.buffer_growth(BufferGrowth::OnDemand),
+		}
+	}
+
+	/// Configure a parser according to this profile.
+	///
+	/// All settings covered by the profile are overwritten, including
+	/// those which the profile leaves at their defaults.
+	pub fn apply(&self, parser: &mut Parser) {
+		let (lenient, per_element, budget) = match self {
+			Self::Default => (false, None, None),
+			Self::StrictXmpp => (
+				false,
+				Some(XMPP_MAX_NAMESPACE_DECLARATIONS_PER_ELEMENT),
+				Some(XMPP_MEMORY_BUDGET),
+			),
+			Self::LenientFeed => (true, None, None),
+		};
+		parser.set_lenient_namespaces(lenient);
+		parser.set_check_xml_ids(false);
+		parser.set_max_namespace_declarations_per_element(per_element);
+		parser.set_max_namespace_declarations(None);
+		parser.set_memory_budget(budget);
+	}
+
+	/// Create a lexer configured according to this profile.
+	pub fn lexer(&self) -> Lexer {
+		Lexer::with_options(self.lexer_options())
+	}
+
+	/// Create a parser configured according to this profile.
+	pub fn parser(&self) -> Parser {
+		let mut parser = Parser::default();
+		self.apply(&mut parser);
+		parser
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use alloc::format;
+	use alloc::string::String;
+
+	use crate::error::{Error, ErrorCode};
+	use crate::FeedParser;
+
+	fn parse(profile: Profile, doc: &[u8]) -> crate::Result<bool> {
+		let mut fp = FeedParser::with_profile(profile);
+		crate::as_eof_flag(fp.parse_all(&mut &doc[..], true, |_| ()))
+	}
+
+	#[test]
+	fn default_profile_matches_defaults() {
+		assert_eq!(Profile::default(), Profile::Default);
+		assert_eq!(Profile::Default.parser().memory_budget(), None);
+		let opts = Profile::Default.lexer_options();
+		let defaults = LexerOptions::default();
+		assert_eq!(opts.max_token_length, defaults.max_token_length);
+		assert_eq!(opts.buffer_growth, defaults.buffer_growth);
+	}
+
+	#[test]
+	fn apply_resets_previous_settings() {
+		let mut p = Profile::StrictXmpp.parser();
+		assert_eq!(p.memory_budget(), Some(XMPP_MEMORY_BUDGET));
+		Profile::Default.apply(&mut p);
+		assert_eq!(p.memory_budget(), None);
+	}
+
+	#[test]
+	fn strict_xmpp_limits_namespace_declarations() {
+		let mut doc = String::from("<a");
+		for i in 0..=XMPP_MAX_NAMESPACE_DECLARATIONS_PER_ELEMENT {
+			doc.push_str(&format!(" xmlns:p{}='urn:{}'", i, i));
+		}
+		doc.push_str("/>");
+		match parse(Profile::StrictXmpp, doc.as_bytes()) {
+			Err(Error::NamespaceDeclarationLimitExceeded(
+				XMPP_MAX_NAMESPACE_DECLARATIONS_PER_ELEMENT,
+			)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match parse(Profile::Default, doc.as_bytes()) {
+			Ok(true) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn only_lenient_feed_tolerates_undeclared_prefixes() {
+		for profile in [Profile::Default, Profile::StrictXmpp].iter() {
+			match parse(*profile, b"<x:a/>") {
+				Err(Error::Namespace(e)) if e.code() == ErrorCode::UndeclaredNamespacePrefix => (),
+				other => panic!("unexpected result for {:?}: {:?}", profile, other),
+			}
+		}
+		match parse(Profile::LenientFeed, b"<x:a/>") {
+			Ok(true) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+}
@@ -0,0 +1,611 @@
+/*!
+# Conversion between rxml and quick-xml events
+
+This module allows rxml and [quick-xml](https://docs.rs/quick-xml) to be
+used side by side, for instance to migrate an application incrementally or
+to put rxml's strict parser in front of code which processes quick-xml
+events.
+
+rxml events carry resolved names (namespace URI and local name), while
+quick-xml events carry the names as written, including prefixes and
+namespace declarations. Elements can thus not be converted one event at a
+time without context; the conversion is done by two stateful converters
+instead:
+
+* [`ToQuickXml`] turns [`ResolvedEvent`]s and [`Item`]s into quick-xml
+  [`Event`]s. It uses an [`Encoder`] internally, so prefixes are chosen and
+  declared by its namespace tracker and text is escaped exactly as it would
+  be by the encoder.
+* [`FromQuickXml`] turns quick-xml [`Event`]s into [`ResolvedEvent`]s by
+  feeding their markup to a [`FeedParser`], so that all restrictions of
+  rxml apply to the input.
+
+Pieces which do not depend on context can be converted directly with
+`From` and `TryFrom`: the XML declaration, text and CDATA sections.
+
+## Example
+
+```
+use quick_xml::events::Event;
+use rxml::quickxml::{FromQuickXml, ToQuickXml};
+
+let mut reader = quick_xml::Reader::from_str("<a xmlns='urn:a'><b>x &amp; y</b></a>");
+let mut from = FromQuickXml::new();
+let mut to = ToQuickXml::new();
+let mut writer = quick_xml::Writer::new(Vec::new());
+loop {
+	let ev = reader.read_event().unwrap();
+	let eof = matches!(ev, Event::Eof);
+	from.push(&ev, |ev| {
+		if let Some(ev) = to.convert(&ev).unwrap() {
+			writer.write_event(ev).unwrap();
+		}
+	}).unwrap();
+	if eof {
+		break;
+	}
+}
+assert_eq!(
+	std::str::from_utf8(&writer.into_inner()).unwrap(),
+	"<a xmlns='urn:a'><b>x &amp; y</b></a>",
+);
+```
+*/
+use std::convert::TryFrom;
+use std::fmt;
+
+use quick_xml::events::{BytesCData, BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+
+use crate::driver::{as_eof_flag, FeedParser};
+use crate::error::{Result, XmlError};
+use crate::lexer::{Lexer, LexerOptions};
+use crate::parser::{Parser, ResolvedEvent, XmlVersion};
+use crate::strings::{CData, CDataStr};
+use crate::writer::{EncodeError, Encoder, Item, SimpleNamespaces, TrackNamespace};
+
+/// Error returned when converting quick-xml text into rxml text.
+#[derive(Debug)]
+pub enum ConversionError {
+	/// quick-xml failed to decode the data, for instance because of an
+	/// unknown entity.
+	QuickXml(quick_xml::Error),
+	/// The decoded data is not valid text for rxml.
+	Xml(XmlError),
+}
+
+impl fmt::Display for ConversionError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::QuickXml(e) => write!(f, "failed to decode quick-xml data: {}", e),
+			Self::Xml(e) => write!(f, "invalid text: {}", e),
+		}
+	}
+}
+
+impl std::error::Error for ConversionError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::QuickXml(e) => Some(e),
+			Self::Xml(e) => Some(e),
+		}
+	}
+}
+
+impl From<quick_xml::Error> for ConversionError {
+	fn from(other: quick_xml::Error) -> Self {
+		Self::QuickXml(other)
+	}
+}
+
+impl From<XmlError> for ConversionError {
+	fn from(other: XmlError) -> Self {
+		Self::Xml(other)
+	}
+}
+
+impl From<XmlVersion> for BytesDecl<'static> {
+	/// Create the declaration which the [`Encoder`] writes for the version.
+	fn from(version: XmlVersion) -> Self {
+		match version {
+			XmlVersion::V1_0 => BytesDecl::new("1.0", Some("utf-8"), None),
+		}
+	}
+}
+
+impl<'a> TryFrom<&BytesDecl<'a>> for XmlVersion {
+	type Error = ConversionError;
+
+	/// Extract the version of a declaration.
+	///
+	/// Only XML 1.0 is supported; other versions are rejected with
+	/// [`XmlError::InvalidSyntax`].
+	fn try_from(decl: &BytesDecl<'a>) -> core::result::Result<Self, Self::Error> {
+		match &*decl.version()? {
+			b"1.0" => Ok(XmlVersion::V1_0),
+			_ => Err(XmlError::InvalidSyntax("only XML version 1.0 is allowed").into()),
+		}
+	}
+}
+
+impl<'a> From<&'a CDataStr> for BytesText<'a> {
+	/// Escape the text for quick-xml.
+	fn from(text: &'a CDataStr) -> Self {
+		BytesText::new(text)
+	}
+}
+
+impl<'a> TryFrom<&BytesText<'a>> for CData {
+	type Error = ConversionError;
+
+	/// Unescape and validate the text.
+	fn try_from(text: &BytesText<'a>) -> core::result::Result<Self, Self::Error> {
+		Ok(CData::try_from(text.unescape()?.into_owned())?)
+	}
+}
+
+impl<'a> TryFrom<&BytesCData<'a>> for CData {
+	type Error = ConversionError;
+
+	/// Validate the content of the CDATA section.
+	fn try_from(cdata: &BytesCData<'a>) -> core::result::Result<Self, Self::Error> {
+		let text = std::str::from_utf8(cdata).map_err(quick_xml::Error::from)?;
+		Ok(CData::try_from(text)?)
+	}
+}
+
+/// Kind of event produced by an item, determined before the item is
+/// consumed by the encoder.
+enum ItemKind {
+	HeadEnd,
+	EmptyFoot,
+	Foot,
+	Text,
+	Comment,
+	ProcessingInstruction,
+}
+
+/**
+# Conversion of rxml events and items into quick-xml events
+
+The converter encodes the events or items with an [`Encoder`] and wraps the
+output into quick-xml [`Event`]s. Namespace declarations are thus created
+by the namespace tracker of the encoder, and the converter rejects
+sequences which the encoder would reject.
+
+Events are converted with [`convert()`](Self::convert), items with
+[`convert_item()`](Self::convert_item). Both can be mixed, as long as the
+items of an element header are not interrupted by an event.
+*/
+pub struct ToQuickXml<T = SimpleNamespaces> {
+	encoder: Encoder<T>,
+	buf: Vec<u8>,
+}
+
+impl ToQuickXml<SimpleNamespaces> {
+	/// Create a new converter using a default [`Encoder`].
+	pub fn new() -> Self {
+		Self::wrap(Encoder::new())
+	}
+}
+
+impl Default for ToQuickXml<SimpleNamespaces> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<T: TrackNamespace> ToQuickXml<T> {
+	/// Create a new converter using the given encoder.
+	pub fn wrap(encoder: Encoder<T>) -> Self {
+		Self {
+			encoder,
+			buf: Vec::new(),
+		}
+	}
+
+	/// Access the encoder.
+	pub fn encoder(&self) -> &Encoder<T> {
+		&self.encoder
+	}
+
+	/// Access the encoder, mutably.
+	pub fn encoder_mut(&mut self) -> &mut Encoder<T> {
+		&mut self.encoder
+	}
+
+	/// Release the encoder.
+	pub fn into_inner(self) -> Encoder<T> {
+		self.encoder
+	}
+
+	/// Convert an event into a quick-xml event.
+	///
+	/// Prefix mapping events do not have an equivalent and return `None`.
+	pub fn convert(
+		&mut self,
+		ev: &ResolvedEvent,
+	) -> core::result::Result<Option<Event<'static>>, EncodeError> {
+		self.buf.clear();
+		self.encoder.encode_event(ev, &mut self.buf)?;
+		Ok(match ev {
+			ResolvedEvent::XmlDeclaration(_, version) => Some(Event::Decl((*version).into())),
+			ResolvedEvent::StartElement(..) => Some(self.take_start(false)),
+			ResolvedEvent::EndElement(_) => Some(self.take_end()),
+			ResolvedEvent::Text(..) => Some(Event::Text(self.take_text())),
+			ResolvedEvent::StartPrefixMapping(..) | ResolvedEvent::EndPrefixMapping(..) => None,
+		})
+	}
+
+	/// Convert an item into a quick-xml event.
+	///
+	/// The items of an element header are collected until the header is
+	/// complete; `None` is returned for all but the last of them. An
+	/// [`Item::ElementFoot`] right after the header results in an
+	/// [`Event::Empty`].
+	///
+	/// [`Item::Raw`] is returned as [`Event::Text`] containing the raw data,
+	/// which quick-xml writes verbatim. Invalid UTF-8 in raw data is
+	/// replaced.
+	pub fn convert_item(
+		&mut self,
+		item: Item<'_>,
+	) -> core::result::Result<Option<Event<'static>>, EncodeError> {
+		let in_header = self.buf.first() == Some(&b'<');
+		if !in_header {
+			self.buf.clear();
+		}
+		let kind = match item {
+			Item::XmlDeclaration(version) => {
+				self.encoder.encode(item, &mut self.buf)?;
+				self.buf.clear();
+				return Ok(Some(Event::Decl(version.into())));
+			}
+			Item::ElementHeadStart(..)
+			| Item::ElementHeadStartRef(..)
+			| Item::Attribute(..)
+			| Item::AttributeRef(..)
+			| Item::NamespaceDeclaration(..) => return self.encode_silently(item),
+			Item::ElementHeadEnd => ItemKind::HeadEnd,
+			Item::ElementFoot if in_header => ItemKind::EmptyFoot,
+			Item::ElementFoot => ItemKind::Foot,
+			Item::Text(_) | Item::Raw(_) => ItemKind::Text,
+			Item::Comment(_) => ItemKind::Comment,
+			Item::ProcessingInstruction(..) => ItemKind::ProcessingInstruction,
+		};
+		self.encoder.encode(item, &mut self.buf)?;
+		Ok(Some(match kind {
+			ItemKind::HeadEnd => self.take_start(false),
+			ItemKind::EmptyFoot => self.take_start(true),
+			ItemKind::Foot => self.take_end(),
+			ItemKind::Text => Event::Text(self.take_text()),
+			ItemKind::Comment => Event::Comment(self.take_delimited(4, 3)),
+			ItemKind::ProcessingInstruction => Event::PI(self.take_delimited(2, 2)),
+		}))
+	}
+
+	/// Encode an item which only contributes to a later event.
+	fn encode_silently(
+		&mut self,
+		item: Item<'_>,
+	) -> core::result::Result<Option<Event<'static>>, EncodeError> {
+		self.encoder.encode(item, &mut self.buf)?;
+		Ok(None)
+	}
+
+	fn take_string(&mut self, prefix: usize, suffix: usize) -> String {
+		let end = self.buf.len() - suffix;
+		let s = String::from_utf8_lossy(&self.buf[prefix..end]).into_owned();
+		self.buf.clear();
+		s
+	}
+
+	/// Turn the encoded element header into a start or empty event.
+	fn take_start(&mut self, empty: bool) -> Event<'static> {
+		let content = self.take_string(1, if empty { 2 } else { 1 });
+		let name_len = content.find(' ').unwrap_or(content.len());
+		let start = BytesStart::from_content(content, name_len);
+		if empty {
+			Event::Empty(start)
+		} else {
+			Event::Start(start)
+		}
+	}
+
+	fn take_end(&mut self) -> Event<'static> {
+		Event::End(BytesEnd::new(self.take_string(2, 1)))
+	}
+
+	fn take_text(&mut self) -> BytesText<'static> {
+		BytesText::from_escaped(self.take_string(0, 0))
+	}
+
+	fn take_delimited(&mut self, prefix: usize, suffix: usize) -> BytesText<'static> {
+		BytesText::from_escaped(self.take_string(prefix, suffix))
+	}
+}
+
+/**
+# Conversion of quick-xml events into rxml events
+
+The markup of each quick-xml event is fed to a [`FeedParser`], so the
+events are checked as strictly as a document read by rxml. The events
+produced by the parser are passed to a callback.
+
+Comments and processing instructions have no equivalent in rxml's events
+and are skipped. A document type declaration is passed on to the parser,
+which rejects it. [`Event::Eof`] marks the end of the document; the parser
+then reports an error if the document is incomplete.
+
+As the parser is streaming, text is only passed on once the next event
+shows where it ends.
+*/
+pub struct FromQuickXml {
+	parser: FeedParser,
+	buf: Vec<u8>,
+}
+
+impl FromQuickXml {
+	/// Create a new converter with default options.
+	pub fn new() -> Self {
+		Self::with_options(LexerOptions::default())
+	}
+
+	/// Create a new converter, configuring the lexer with the given options.
+	pub fn with_options(options: LexerOptions) -> Self {
+		Self {
+			parser: FeedParser::wrap(Lexer::with_options(options), Parser::default()),
+			buf: Vec::new(),
+		}
+	}
+
+	/// Access the parser.
+	pub fn get_parser(&self) -> &Parser {
+		self.parser.get_parser()
+	}
+
+	/// Access the parser, mutably.
+	pub fn get_parser_mut(&mut self) -> &mut Parser {
+		self.parser.get_parser_mut()
+	}
+
+	/// Convert a quick-xml event and pass the resulting events to `f`.
+	///
+	/// A single quick-xml event may result in any number of events,
+	/// including none.
+	pub fn push<F: FnMut(ResolvedEvent)>(&mut self, ev: &Event<'_>, f: F) -> Result<()> {
+		self.buf.clear();
+		let at_eof = match ev {
+			Event::Start(start) => {
+				self.buf.push(b'<');
+				self.buf.extend_from_slice(start);
+				self.buf.push(b'>');
+				false
+			}
+			Event::Empty(start) => {
+				self.buf.push(b'<');
+				self.buf.extend_from_slice(start);
+				self.buf.extend_from_slice(b"/>");
+				false
+			}
+			Event::End(end) => {
+				self.buf.extend_from_slice(b"</");
+				self.buf.extend_from_slice(end);
+				self.buf.push(b'>');
+				false
+			}
+			Event::Text(text) => {
+				self.buf.extend_from_slice(text);
+				false
+			}
+			Event::CData(cdata) => {
+				self.buf.extend_from_slice(b"<![CDATA[");
+				self.buf.extend_from_slice(cdata);
+				self.buf.extend_from_slice(b"]]>");
+				false
+			}
+			Event::Decl(decl) => {
+				self.buf.extend_from_slice(b"<?");
+				self.buf.extend_from_slice(decl);
+				self.buf.extend_from_slice(b"?>");
+				false
+			}
+			Event::DocType(doctype) => {
+				self.buf.extend_from_slice(b"<!DOCTYPE ");
+				self.buf.extend_from_slice(doctype);
+				self.buf.push(b'>');
+				false
+			}
+			Event::Comment(_) | Event::PI(_) => return Ok(()),
+			Event::Eof => true,
+		};
+		let result = self.parser.parse_all(&mut &self.buf[..], at_eof, f);
+		if at_eof {
+			result
+		} else {
+			as_eof_flag(result).map(|_| ())
+		}
+	}
+}
+
+impl Default for FromQuickXml {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::convert::TryInto;
+
+	use crate::error::Error;
+
+	fn read_all(doc: &str) -> Result<Vec<ResolvedEvent>> {
+		let mut reader = quick_xml::Reader::from_str(doc);
+		// leave the checks to rxml
+		reader.check_end_names(false);
+		let mut from = FromQuickXml::new();
+		let mut out = Vec::new();
+		loop {
+			let ev = reader.read_event().unwrap();
+			from.push(&ev, |ev| out.push(ev))?;
+			if let Event::Eof = ev {
+				return Ok(out);
+			}
+		}
+	}
+
+	#[test]
+	fn from_quick_xml_matches_parser() {
+		let doc = "<?xml version='1.0'?>\n<a xmlns='urn:a' xmlns:p='urn:p' p:x='&lt;'>t<![CDATA[<c>]]><!-- c --><p:b/>&amp;<?pi x?></a>";
+		let events = read_all(doc).unwrap();
+		let mut expected = Vec::new();
+		FeedParser::default()
+			.parse_all(
+				&mut &b"<?xml version='1.0'?>\n<a xmlns='urn:a' xmlns:p='urn:p' p:x='&lt;'>t<![CDATA[<c>]]><p:b/>&amp;</a>"[..],
+				true,
+				|ev| expected.push(ev),
+			)
+			.unwrap();
+		let strip = |evs: Vec<ResolvedEvent>| -> Vec<ResolvedEvent> {
+			let mut result: Vec<ResolvedEvent> = Vec::new();
+			for ev in evs {
+				match (result.last_mut(), ev) {
+					(Some(ResolvedEvent::Text(_, prev)), ResolvedEvent::Text(_, text)) => {
+						*prev = prev.clone() + &*text;
+					}
+					(_, ResolvedEvent::Text(_, text)) => result.push(ResolvedEvent::Text(
+						crate::parser::EventMetrics::new(0),
+						text,
+					)),
+					(_, ResolvedEvent::StartElement(_, name, attrs, _)) => {
+						result.push(ResolvedEvent::StartElement(
+							crate::parser::EventMetrics::new(0),
+							name,
+							attrs,
+							None,
+						))
+					}
+					(_, ResolvedEvent::EndElement(_)) => result.push(ResolvedEvent::EndElement(
+						crate::parser::EventMetrics::new(0),
+					)),
+					(_, ResolvedEvent::XmlDeclaration(_, v)) => result.push(
+						ResolvedEvent::XmlDeclaration(crate::parser::EventMetrics::new(0), v),
+					),
+					(_, other) => result.push(other),
+				}
+			}
+			result
+		};
+		assert_eq!(strip(events), strip(expected));
+	}
+
+	#[test]
+	fn from_quick_xml_applies_restrictions() {
+		match read_all("<a><b></a>") {
+			Err(Error::Xml(XmlError::ElementMismatch)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match read_all("<a>&nbsp;</a>") {
+			Err(Error::Xml(XmlError::UndeclaredEntity)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match read_all("<!DOCTYPE a><a/>") {
+			Err(Error::Xml(XmlError::InvalidSyntax(_))) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match read_all("<a>") {
+			Err(Error::Xml(XmlError::InvalidEof(_))) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn to_quick_xml_converts_events() {
+		let mut to = ToQuickXml::new();
+		let mut writer = quick_xml::Writer::new(Vec::new());
+		FeedParser::default()
+			.parse_all(
+				&mut &b"<?xml version='1.0'?><a xmlns='urn:a' xmlns:p='urn:p' p:x='&quot;'>t &lt; u<p:b/></a>"[..],
+				true,
+				|ev| {
+					if let Some(ev) = to.convert(&ev).unwrap() {
+						writer.write_event(ev).unwrap();
+					}
+				},
+			)
+			.unwrap();
+		assert_eq!(
+			std::str::from_utf8(&writer.into_inner()).unwrap(),
+			"<?xml version=\"1.0\" encoding=\"utf-8\"?><a xmlns='urn:a' xmlns:tns0='urn:p' tns0:x=\"&#34;\">t &lt; u<tns0:b></tns0:b></a>"
+		);
+	}
+
+	#[test]
+	fn to_quick_xml_converts_items() {
+		let mut to = ToQuickXml::new();
+		let ns = Some(crate::parser::RcPtr::new("urn:a".try_into().unwrap()));
+		assert!(to
+			.convert_item(Item::ElementHeadStart(ns, "a".try_into().unwrap()))
+			.unwrap()
+			.is_none());
+		assert!(to
+			.convert_item(Item::Attribute(
+				None,
+				"x".try_into().unwrap(),
+				"1".try_into().unwrap()
+			))
+			.unwrap()
+			.is_none());
+		match to.convert_item(Item::ElementHeadEnd).unwrap() {
+			Some(Event::Start(start)) => {
+				assert_eq!(start.name().as_ref(), b"a");
+				assert_eq!(&*start, &b"a xmlns='urn:a' x=\"1\""[..]);
+			}
+			other => panic!("unexpected event: {:?}", other),
+		}
+		to.convert_item(Item::ElementHeadStart(None, "b".try_into().unwrap()))
+			.unwrap();
+		match to.convert_item(Item::ElementFoot).unwrap() {
+			Some(Event::Empty(start)) => assert_eq!(start.name().as_ref(), b"b"),
+			other => panic!("unexpected event: {:?}", other),
+		}
+		match to
+			.convert_item(Item::Text("<".try_into().unwrap()))
+			.unwrap()
+		{
+			Some(Event::Text(text)) => assert_eq!(&*text, b"&lt;"),
+			other => panic!("unexpected event: {:?}", other),
+		}
+		match to.convert_item(Item::ElementFoot).unwrap() {
+			Some(Event::End(end)) => assert_eq!(end.name().as_ref(), b"a"),
+			other => panic!("unexpected event: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn converts_text_and_declarations() {
+		let text: CData = (&BytesText::from_escaped("a &amp; b")).try_into().unwrap();
+		assert_eq!(text, "a & b");
+		match CData::try_from(&BytesText::from_escaped("&nbsp;")) {
+			Err(ConversionError::QuickXml(_)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match CData::try_from(&BytesText::from_escaped("&#0;")) {
+			Err(ConversionError::QuickXml(_)) | Err(ConversionError::Xml(_)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		let cdata: CData = (&BytesCData::new("<x>")).try_into().unwrap();
+		assert_eq!(cdata, "<x>");
+		let escaped = BytesText::from(CDataStr::from_str("a < b").unwrap());
+		assert_eq!(&*escaped, b"a &lt; b");
+
+		let decl: BytesDecl = XmlVersion::V1_0.into();
+		assert_eq!(XmlVersion::try_from(&decl).unwrap(), XmlVersion::V1_0);
+		match XmlVersion::try_from(&BytesDecl::new("1.1", None, None)) {
+			Err(ConversionError::Xml(_)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+}
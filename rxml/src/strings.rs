@@ -24,11 +24,27 @@ times.
   Note that [`CData`] strings do not contain references or CDATA sections;
   those are expanded by the lexer.
 
+## Storage
+
+[`Name`] and [`NcName`] are backed by [`smartstring::alias::String`], which
+stores strings of up to 23 bytes inline without a heap allocation. As element
+and attribute names are almost always shorter than that, parsing typically
+does not allocate for names at all. Longer names transparently spill to the
+heap. [`CData`] uses a plain [`String`], as text is frequently long.
+
+Names can also share a single reference-counted allocation. Such names are
+handed out by [`Context::intern_name`](crate::Context::intern_name) and
+[`Context::intern_ncname`](crate::Context::intern_ncname); cloning them only
+increments the reference count. Apart from that, shared names behave exactly
+like any other name.
+
 ## Construction
 
 To construct [`str`]-like references from a literal, you can use the macros
 offered when this crate is built with the `macros` feature: [`xml_name!`],
-[`xml_ncname!`], [`xml_cdata!`].
+[`xml_ncname!`], [`xml_cdata!`]. Without that feature, the `const fn`s
+[`NameStr::from_static`], [`NcNameStr::from_static`] and
+[`CDataStr::from_static`] can be used to the same effect in const contexts.
 
 In general, owned values are constructed using the [`std::convert::TryInto`]
 mechanism, from other string types. Supported source types are:
@@ -47,13 +63,56 @@ are possible through `.into()`:
 (and likewise for the corresponding Str types)
 
 The inverse directions are only available through `try_into`.
+
+For data which is cloned often, e.g. when fanning out parsed data to multiple
+consumers, the `*Str` types can be converted into [`Arc`](std::sync::Arc)
+and [`Rc`](std::rc::Rc) pointers (e.g. `Arc<CDataStr>`) via `.into()`, from
+both the owned and the borrowed types. Cloning those is O(1).
+
+To pass validated data around without forcing an allocation, use
+[`Cow`](std::borrow::Cow) with the `*Str` types, e.g.
+`Cow<'_, CDataStr>`. Both `&CDataStr` and [`CData`] convert into such a
+`Cow` via `.into()`, and [`CDataStr::from_cow`] validates a `Cow<'_, str>`
+without copying (likewise for the other types).
+
+## Sanitization
+
+To derive names from identifiers which are not under control of the
+application (database columns, JSON keys, ...), [`NcNameSanitizer`] (or its
+shorthand [`NcName::sanitize`]) maps arbitrary strings to valid [`NcName`]s.
+
+## Escaping
+
+The values of the types in this module never contain references; those are
+expanded by the parser and re-introduced by the encoder as needed. To do
+the same outside of a parser or encoder, for instance when composing
+attribute values by hand or when processing isolated snippets of XML,
+[`escape_text`], [`escape_attr_value`] and [`unescape`] are available.
+
+## Serialization
+
+With the `serde` feature, all types implement `Serialize` and `Deserialize`
+as plain strings. Deserialization validates the contents; the `*Str` types
+can only be deserialized from borrowed strings.
+
+## Fuzzing
+
+With the `arbitrary` feature, all types implement `arbitrary::Arbitrary`
+(the `*Str` types as references borrowing from the fuzzer input). The
+generated strings are always valid; invalid input is truncated at the first
+character which is not allowed.
 */
 
-use std::borrow::{Borrow, Cow, ToOwned};
-use std::cmp::{Ordering, PartialOrd};
-use std::convert::{TryFrom, TryInto};
-use std::fmt;
-use std::ops::{Add, Deref};
+use alloc::borrow::{Cow, ToOwned};
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp::{Ordering, PartialOrd};
+use core::convert::{TryFrom, TryInto};
+use core::fmt;
+use core::ops::{Add, Deref};
 
 use smartstring::alias::String as SmartString;
 
@@ -65,9 +124,141 @@ use rxml_validation::{
 };
 
 use crate::error::{XmlError, ERRCTX_UNKNOWN};
+use crate::lexer::resolve_reference;
+use crate::writer;
 
 use super::errctx;
 
+/// Storage of [`Name`] and [`NcName`].
+///
+/// Strings are usually stored in a [`SmartString`]. Names obtained from a
+/// [`Context`](crate::Context) share a reference-counted allocation
+/// instead, so that clones of long names do not allocate.
+#[derive(Clone)]
+enum NameBuf {
+	Owned(SmartString),
+	#[cfg(feature = "shared_ns")]
+	Shared(Arc<str>),
+}
+
+impl NameBuf {
+	fn as_str(&self) -> &str {
+		match self {
+			Self::Owned(s) => s.as_str(),
+			#[cfg(feature = "shared_ns")]
+			Self::Shared(s) => s,
+		}
+	}
+
+	fn into_smartstring(self) -> SmartString {
+		match self {
+			Self::Owned(s) => s,
+			#[cfg(feature = "shared_ns")]
+			Self::Shared(s) => (*s).into(),
+		}
+	}
+}
+
+impl Deref for NameBuf {
+	type Target = str;
+
+	fn deref(&self) -> &str {
+		self.as_str()
+	}
+}
+
+impl fmt::Debug for NameBuf {
+	fn fmt<'f>(&self, f: &'f mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self.as_str(), f)
+	}
+}
+
+impl PartialEq for NameBuf {
+	fn eq(&self, other: &NameBuf) -> bool {
+		self.as_str() == other.as_str()
+	}
+}
+
+impl Eq for NameBuf {}
+
+impl PartialEq<str> for NameBuf {
+	fn eq(&self, other: &str) -> bool {
+		self.as_str() == other
+	}
+}
+
+impl PartialEq<&str> for NameBuf {
+	fn eq(&self, other: &&str) -> bool {
+		self.as_str() == *other
+	}
+}
+
+impl PartialEq<NameBuf> for str {
+	fn eq(&self, other: &NameBuf) -> bool {
+		self == other.as_str()
+	}
+}
+
+impl PartialOrd for NameBuf {
+	fn partial_cmp(&self, other: &NameBuf) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for NameBuf {
+	fn cmp(&self, other: &NameBuf) -> Ordering {
+		self.as_str().cmp(other.as_str())
+	}
+}
+
+impl core::hash::Hash for NameBuf {
+	fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+		self.as_str().hash(state)
+	}
+}
+
+impl From<&str> for NameBuf {
+	fn from(other: &str) -> Self {
+		Self::Owned(other.into())
+	}
+}
+
+impl From<String> for NameBuf {
+	fn from(other: String) -> Self {
+		Self::Owned(other.into())
+	}
+}
+
+impl From<SmartString> for NameBuf {
+	fn from(other: SmartString) -> Self {
+		Self::Owned(other)
+	}
+}
+
+impl From<NameBuf> for SmartString {
+	fn from(other: NameBuf) -> Self {
+		other.into_smartstring()
+	}
+}
+
+impl From<NameBuf> for String {
+	fn from(other: NameBuf) -> Self {
+		match other {
+			NameBuf::Owned(s) => s.into(),
+			#[cfg(feature = "shared_ns")]
+			NameBuf::Shared(s) => (*s).into(),
+		}
+	}
+}
+
+impl Add<&str> for NameBuf {
+	type Output = NameBuf;
+
+	fn add(self, rhs: &str) -> NameBuf {
+		Self::Owned(self.into_smartstring() + rhs)
+	}
+}
+
 macro_rules! rxml_unsafe_str_construct_doc {
 	($name:ident, $other:ident) => {
 		concat!(
@@ -108,12 +299,12 @@ macro_rules! rxml_safe_str_construct_doc {
 macro_rules! rxml_custom_string_type {
 	(
 		$(#[$outer:meta])*
-		pub struct $name:ident($string:ty) use $check:ident => $borrowed:ident;
+		pub struct $name:ident($storage:ty as $string:ty) use $check:ident => $borrowed:ident;
 	) => {
 		$(#[$outer])*
 		#[derive(Debug, Clone, PartialEq, Eq, Hash, Ord)]
 		#[repr(transparent)]
-		pub struct $name($string);
+		pub struct $name($storage);
 
 		impl $name {
 			#[deprecated(since = "0.4.0", note = "use the TryFrom<> trait implementation instead")]
@@ -136,7 +327,7 @@ macro_rules! rxml_custom_string_type {
 
 			/// Extract the inner string and return it.
 			pub fn into_inner(self) -> $string {
-				self.0
+				self.0.into()
 			}
 
 			/// Obtain a reference to the inner string slice.
@@ -159,7 +350,7 @@ macro_rules! rxml_custom_string_type {
 				Self(s.into().into())
 			}
 
-			unsafe fn from_native_unchecked(s: $string) -> Self {
+			unsafe fn from_native_unchecked(s: $storage) -> Self {
 				Self(s)
 			}
 		}
@@ -174,12 +365,6 @@ macro_rules! rxml_custom_string_type {
 			}
 		}
 
-		impl Borrow<$string> for $name {
-			fn borrow(&self) -> &$string {
-				&self.0
-			}
-		}
-
 		impl Borrow<$borrowed> for $name {
 			fn borrow(&self) -> &$borrowed {
 				// SAFETY: $borrowed is assumed to use the same check; this is
@@ -194,12 +379,6 @@ macro_rules! rxml_custom_string_type {
 			}
 		}
 
-		impl AsRef<$string> for $name {
-			fn as_ref(&self) -> &$string {
-				&self.0
-			}
-		}
-
 		impl AsRef<$borrowed> for $name {
 			fn as_ref(&self) -> &$borrowed {
 				// SAFETY: $borrowed is assumed to use the same check; this is
@@ -326,7 +505,7 @@ macro_rules! rxml_custom_string_type {
 
 		impl fmt::Display for $name {
 			fn fmt<'f>(&self, f: &'f mut fmt::Formatter) -> fmt::Result {
-				f.write_str(&self.0 as &str)
+				f.write_str(&self.0)
 			}
 		}
 
@@ -341,6 +520,24 @@ macro_rules! rxml_custom_string_type {
 				unsafe { Self::from_native_unchecked(self.0 + &rhs.0) }
 			}
 		}
+
+		#[cfg(feature = "serde")]
+		#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+		impl serde::Serialize for $name {
+			fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+				serializer.serialize_str(&self.0)
+			}
+		}
+
+		#[cfg(feature = "serde")]
+		#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+		impl<'de> serde::Deserialize<'de> for $name {
+			/// Deserialize from a string, validating its contents.
+			fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+				let s = String::deserialize(deserializer)?;
+				s.try_into().map_err(serde::de::Error::custom)
+			}
+		}
 	}
 }
 
@@ -362,7 +559,62 @@ macro_rules! rxml_custom_str_type {
 
 			#[doc = rxml_unsafe_str_construct_doc!($name, str)]
 			pub unsafe fn from_str_unchecked<'x>(s: &'x str) -> &'x Self {
-				std::mem::transmute(s)
+				core::mem::transmute(s)
+			}
+
+			/// Validate a borrowed-or-owned string without copying it.
+			///
+			/// A borrowed string is validated and returned as borrowed
+			/// slice, an owned string is validated and moved into the owned
+			/// type (copying only where the owned type uses a different
+			/// string representation).
+			pub fn from_cow<'x>(s: Cow<'x, str>) -> Result<Cow<'x, Self>, XmlError> {
+				match s {
+					Cow::Borrowed(s) => Ok(Cow::Borrowed(s.try_into()?)),
+					Cow::Owned(s) => Ok(Cow::Owned(s.try_into()?)),
+				}
+			}
+		}
+
+		impl<'x> From<&'x $name> for Cow<'x, $name> {
+			fn from(other: &'x $name) -> Self {
+				Self::Borrowed(other)
+			}
+		}
+
+		impl From<&$name> for Arc<$name> {
+			/// Copy the string into a shared, immutable allocation.
+			///
+			/// Cloning the resulting pointer is O(1).
+			fn from(other: &$name) -> Self {
+				let ptr = Arc::into_raw(Arc::<str>::from(&other.0)) as *const $name;
+				// SAFETY: $name is a repr(transparent) wrapper around str and
+				// the contents have been validated when `other` was created.
+				unsafe { Arc::from_raw(ptr) }
+			}
+		}
+
+		impl From<$owned> for Arc<$name> {
+			fn from(other: $owned) -> Self {
+				(&*other).into()
+			}
+		}
+
+		impl From<&$name> for Rc<$name> {
+			/// Copy the string into a shared, immutable allocation.
+			///
+			/// Cloning the resulting pointer is O(1).
+			fn from(other: &$name) -> Self {
+				let ptr = Rc::into_raw(Rc::<str>::from(&other.0)) as *const $name;
+				// SAFETY: $name is a repr(transparent) wrapper around str and
+				// the contents have been validated when `other` was created.
+				unsafe { Rc::from_raw(ptr) }
+			}
+		}
+
+		impl From<$owned> for Rc<$name> {
+			fn from(other: $owned) -> Self {
+				(&*other).into()
 			}
 		}
 
@@ -427,7 +679,7 @@ macro_rules! rxml_custom_str_type {
 				$check(other)?;
 				// SAFETY: the content check is executed right above and we're
 				// transmuting &str into a repr(transparent) of &str.
-				Ok(unsafe { std::mem::transmute(other) } )
+				Ok(unsafe { core::mem::transmute::<&'x str, &'x $name>(other) } )
 			}
 		}
 
@@ -436,27 +688,76 @@ macro_rules! rxml_custom_str_type {
 				f.write_str(&self.0)
 			}
 		}
+
+		#[cfg(feature = "serde")]
+		#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+		impl serde::Serialize for $name {
+			fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+				serializer.serialize_str(&self.0)
+			}
+		}
+
+		#[cfg(feature = "serde")]
+		#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+		impl<'de: 'x, 'x> serde::Deserialize<'de> for &'x $name {
+			/// Deserialize from a borrowed string, validating its contents.
+			///
+			/// This only works with deserializers which can hand out
+			/// borrowed strings, i.e. where the input does not require
+			/// unescaping.
+			fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+				let s = <&'x str>::deserialize(deserializer)?;
+				s.try_into().map_err(serde::de::Error::custom)
+			}
+		}
 	}
 }
 
 macro_rules! rxml_custom_string_type_pair {
 	(
 		$(#[$ownedmeta:meta])*
-		pub struct $owned:ident($string:ty) use $check:ident;
+		pub struct $owned:ident($storage:ty as $string:ty) use $check:ident;
 
 		$(#[$borrowedmeta:meta])*
 		pub struct $borrowed:ident(str);
 	) => {
 		rxml_custom_string_type!{
 			$(#[$ownedmeta])*
-			pub struct $owned($string) use $check => $borrowed;
+			pub struct $owned($storage as $string) use $check => $borrowed;
 		}
 
 		rxml_custom_str_type!{
 			$(#[$borrowedmeta])*
 			pub struct $borrowed(str) use $check => $owned;
 		}
-	}
+	};
+	(
+		$(#[$ownedmeta:meta])*
+		pub struct $owned:ident($string:ty) use $check:ident;
+
+		$(#[$borrowedmeta:meta])*
+		pub struct $borrowed:ident(str);
+	) => {
+		rxml_custom_string_type_pair!{
+			$(#[$ownedmeta])*
+			pub struct $owned($string as $string) use $check;
+
+			$(#[$borrowedmeta])*
+			pub struct $borrowed(str);
+		}
+
+		impl Borrow<$string> for $owned {
+			fn borrow(&self) -> &$string {
+				&self.0
+			}
+		}
+
+		impl AsRef<$string> for $owned {
+			fn as_ref(&self) -> &$string {
+				&self.0
+			}
+		}
+	};
 }
 
 rxml_custom_string_type_pair! {
@@ -487,7 +788,7 @@ rxml_custom_string_type_pair! {
 	///                        | [#x0300-#x036F] | [#x203F-#x2040]
 	/// [5]  Name          ::= NameStartChar (NameChar)*
 	/// ```
-	pub struct Name(SmartString) use raw_validate_name;
+	pub struct Name(NameBuf as SmartString) use raw_validate_name;
 
 	/// str which conforms to the Name production of XML 1.0.
 	///
@@ -518,15 +819,15 @@ impl Name {
 	/// [`NameStr`] and then cloning the returned parts) by avoiding
 	/// unnecessary copying.
 	pub fn split_name(self) -> Result<(Option<NcName>, NcName), XmlError> {
-		let mut name = self.0;
-		let colon_pos = match name.find(':') {
-			None => return Ok((None, unsafe { NcName::from_smartstring_unchecked(name) })),
+		let colon_pos = match self.0.find(':') {
+			None => return Ok((None, NcName(self.0))),
 			Some(pos) => pos,
 		};
-		if colon_pos == 0 || colon_pos == name.len() - 1 {
+		if colon_pos == 0 || colon_pos == self.0.len() - 1 {
 			return Err(XmlError::EmptyNamePart(ERRCTX_UNKNOWN));
 		}
 
+		let mut name = self.0.into_smartstring();
 		let localname = name.split_off(colon_pos + 1);
 		let mut prefix = name;
 
@@ -551,9 +852,79 @@ impl Name {
 			unsafe { NcName::from_smartstring_unchecked(localname) },
 		))
 	}
+
+	/// Split the name at a colon, if it exists, without consuming it.
+	///
+	/// This is equivalent to [`NameStr::split_name()`]; it exists because
+	/// the consuming [`Name::split_name()`] shadows the borrowing variant in
+	/// method calls on a `Name`. The returned parts borrow from `self`, so no
+	/// allocation or re-validation takes place.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::Name;
+	/// use std::convert::TryFrom;
+	///
+	/// let name = Name::try_from("stream:features").unwrap();
+	/// let (prefix, localname) = name.split_name_ref().unwrap();
+	/// assert_eq!(prefix.unwrap(), "stream");
+	/// assert_eq!(localname, "features");
+	/// // name is still usable
+	/// assert_eq!(name, "stream:features");
+	/// ```
+	pub fn split_name_ref(&self) -> Result<(Option<&'_ NcNameStr>, &'_ NcNameStr), XmlError> {
+		NameStr::split_name(self)
+	}
+
+	/// Construct a `Name` which shares the given allocation, without
+	/// enforcing anything.
+	///
+	/// # Safety
+	///
+	/// The caller is responsible for ensuring that the string is in fact a
+	/// valid `Name`.
+	#[cfg(feature = "shared_ns")]
+	pub(crate) unsafe fn from_shared_unchecked(s: Arc<str>) -> Self {
+		Self(NameBuf::Shared(s))
+	}
 }
 
 impl NameStr {
+	/// Construct a `&'static NameStr` from a string literal, with the
+	/// validity check evaluated at compile time when used in a const
+	/// context.
+	///
+	/// This does not require the `macros` feature.
+	///
+	/// # Example
+	///
+	/// ```rust
+	/// use rxml::NameStr;
+	///
+	/// const NAME: &'static NameStr = NameStr::from_static("stream:features");
+	/// assert_eq!(NAME, "stream:features");
+	/// ```
+	///
+	/// ```rust,compile_fail
+	/// use rxml::NameStr;
+	///
+	/// const NAME: &'static NameStr = NameStr::from_static("foo bar");
+	/// ```
+	///
+	/// # Panics
+	///
+	/// If the string is not valid Name. In a const context, this
+	/// results in a compilation error instead.
+	pub const fn from_static(s: &'static str) -> &'static NameStr {
+		if !rxml_validation::is_valid_name(s) {
+			panic!("string is not valid Name");
+		}
+		// SAFETY: validity has been checked above and NameStr is a
+		// repr(transparent) str.
+		unsafe { core::mem::transmute(s) }
+	}
+
 	/// Create an owned copy of the string as [`Name`].
 	///
 	/// This operation is also available as implementation of the `Into`
@@ -571,6 +942,23 @@ impl NameStr {
 	///
 	/// If neither of the two cases apply or the string on either side of the
 	/// colon is empty, an error is returned.
+	///
+	/// The returned parts borrow from `self`; this function does not
+	/// allocate. As both parts are already known to be valid [`NcNameStr`]
+	/// after the checks, no further validation is needed by the caller.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::NameStr;
+	///
+	/// let name = NameStr::from_static("xml:lang");
+	/// let (prefix, localname) = name.split_name().unwrap();
+	/// assert_eq!(prefix.unwrap(), "xml");
+	/// assert_eq!(localname, "lang");
+	///
+	/// assert!(NameStr::from_static("a:b:c").split_name().is_err());
+	/// ```
 	pub fn split_name(&self) -> Result<(Option<&'_ NcNameStr>, &'_ NcNameStr), XmlError> {
 		let name = &self.0;
 		let colon_pos = match name.find(':') {
@@ -634,7 +1022,7 @@ rxml_custom_string_type_pair! {
 	/// ```text
 	/// [4] NcName ::= Name - (Char* ':' Char*)  /* An XML Name, minus the ":" */
 	/// ```
-	pub struct NcName(SmartString) use raw_validate_ncname;
+	pub struct NcName(NameBuf as SmartString) use raw_validate_ncname;
 
 	/// str which conforms to the NcName production of Namespaces in XML 1.0.
 	///
@@ -651,6 +1039,23 @@ rxml_custom_string_type_pair! {
 }
 
 impl NcName {
+	/// Convert an arbitrary string into a valid [`NcName`], replacing
+	/// characters which are not allowed with `_`.
+	///
+	/// This is a shorthand for [`NcNameSanitizer::sanitize`] with the
+	/// default settings; see there for the exact rules.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use rxml::NcName;
+	/// assert_eq!(NcName::sanitize("order-id"), "order-id");
+	/// assert_eq!(NcName::sanitize("42 items"), "_42_items");
+	/// ```
+	pub fn sanitize(s: &str) -> NcName {
+		NcNameSanitizer::new().sanitize(s).into_owned()
+	}
+
 	/// Compose two [`NcName`] objects to one [`Name`], separating them with
 	/// a colon.
 	///
@@ -684,12 +1089,58 @@ impl NcName {
 	/// This operation is also available as implementation of the `Into`
 	/// trait.
 	pub fn into_name(self) -> Name {
-		// SAFETY: NcName is a strict subset of Name
-		unsafe { Name::from_smartstring_unchecked(self.0) }
+		// NcName is a strict subset of Name
+		Name(self.0)
+	}
+
+	/// Construct an `NcName` which shares the given allocation, without
+	/// enforcing anything.
+	///
+	/// # Safety
+	///
+	/// The caller is responsible for ensuring that the string is in fact a
+	/// valid `NcName`.
+	#[cfg(feature = "shared_ns")]
+	pub(crate) unsafe fn from_shared_unchecked(s: Arc<str>) -> Self {
+		Self(NameBuf::Shared(s))
 	}
 }
 
 impl NcNameStr {
+	/// Construct a `&'static NcNameStr` from a string literal, with the
+	/// validity check evaluated at compile time when used in a const
+	/// context.
+	///
+	/// This does not require the `macros` feature.
+	///
+	/// # Example
+	///
+	/// ```rust
+	/// use rxml::NcNameStr;
+	///
+	/// const NAME: &'static NcNameStr = NcNameStr::from_static("features");
+	/// assert_eq!(NAME, "features");
+	/// ```
+	///
+	/// ```rust,compile_fail
+	/// use rxml::NcNameStr;
+	///
+	/// const NAME: &'static NcNameStr = NcNameStr::from_static("stream:features");
+	/// ```
+	///
+	/// # Panics
+	///
+	/// If the string is not valid NCName. In a const context, this
+	/// results in a compilation error instead.
+	pub const fn from_static(s: &'static str) -> &'static NcNameStr {
+		if !rxml_validation::is_valid_ncname(s) {
+			panic!("string is not valid NCName");
+		}
+		// SAFETY: validity has been checked above and NcNameStr is a
+		// repr(transparent) str.
+		unsafe { core::mem::transmute(s) }
+	}
+
 	/// Create an owned copy of the string as [`NcName`].
 	///
 	/// This operation is also available as implementation of the `Into`
@@ -738,6 +1189,112 @@ impl NcNameStr {
 	}
 }
 
+/**
+# Conversion of arbitrary strings into NcNames
+
+Maps any string to a valid [`NcName`] with the following deterministic
+rules, applied in order:
+
+1. Every character which is not allowed in an NcName (including `:`) is
+   replaced by the replacement character (`_` by default).
+2. If the result is empty or its first character is not allowed at the
+   start of an NcName (for instance a digit, `-` or `.`), the prefix (`_` by
+   default) is prepended.
+
+Strings which already are valid NcNames are returned unchanged, without
+copying. The mapping is not injective: different inputs may produce the
+same name (e.g. `a b` and `a:b` both become `a_b`), so callers which need
+unique names have to disambiguate them on their own.
+
+```
+use rxml::strings::NcNameSanitizer;
+use rxml::NcNameStr;
+
+let sanitizer = NcNameSanitizer::new();
+assert_eq!(&*sanitizer.sanitize("user name"), "user_name");
+assert_eq!(&*sanitizer.sanitize("2fa:enabled"), "_2fa_enabled");
+assert_eq!(&*sanitizer.sanitize(""), "_");
+
+let sanitizer = NcNameSanitizer::new()
+	.with_replacement('-')
+	.with_prefix(NcNameStr::from_static("col"));
+assert_eq!(&*sanitizer.sanitize("price (EUR)"), "price--EUR-");
+assert_eq!(&*sanitizer.sanitize("1st"), "col1st");
+```
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct NcNameSanitizer<'x> {
+	replacement: char,
+	prefix: &'x NcNameStr,
+}
+
+impl NcNameSanitizer<'static> {
+	/// Create a sanitizer which uses `_` as replacement character and as
+	/// prefix.
+	pub fn new() -> Self {
+		Self {
+			replacement: '_',
+			prefix: NcNameStr::from_static("_"),
+		}
+	}
+}
+
+impl Default for NcNameSanitizer<'static> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<'x> NcNameSanitizer<'x> {
+	/// Set the character which replaces characters not allowed in an
+	/// NcName.
+	///
+	/// # Panics
+	///
+	/// If `replacement` is itself not allowed in an NcName.
+	pub fn with_replacement(mut self, replacement: char) -> Self {
+		assert!(
+			replacement != ':' && selectors::CLASS_XML_NAME.select(replacement),
+			"replacement character must be allowed in an NcName"
+		);
+		self.replacement = replacement;
+		self
+	}
+
+	/// Set the prefix which is prepended to names which do not start with
+	/// a character allowed at the start of an NcName.
+	pub fn with_prefix<'p>(self, prefix: &'p NcNameStr) -> NcNameSanitizer<'p> {
+		NcNameSanitizer {
+			replacement: self.replacement,
+			prefix,
+		}
+	}
+
+	/// Convert `s` into a valid NcName, see the type-level documentation
+	/// for the exact rules.
+	pub fn sanitize<'s>(&self, s: &'s str) -> Cow<'s, NcNameStr> {
+		if let Ok(name) = <&NcNameStr>::try_from(s) {
+			return Cow::Borrowed(name);
+		}
+		let mut out = String::with_capacity(s.len() + self.prefix.len());
+		for ch in s.chars() {
+			if ch != ':' && selectors::CLASS_XML_NAME.select(ch) {
+				out.push(ch);
+			} else {
+				out.push(self.replacement);
+			}
+		}
+		match out.chars().next() {
+			Some(ch) if selectors::CLASS_XML_NAMESTART.select(ch) => (),
+			_ => out.insert_str(0, self.prefix),
+		}
+		// SAFETY: all characters are NameChars other than the colon, and the
+		// first character is a NameStartChar (either from the input or from
+		// the prefix, which is a non-empty NcName).
+		Cow::Owned(unsafe { NcName::from_string_unchecked(out) })
+	}
+}
+
 rxml_custom_string_type_pair! {
 	/// String which consists only of XML 1.0 Chars.
 	///
@@ -789,6 +1346,40 @@ rxml_custom_string_type_pair! {
 }
 
 impl CDataStr {
+	/// Construct a `&'static CDataStr` from a string literal, with the
+	/// validity check evaluated at compile time when used in a const
+	/// context.
+	///
+	/// This does not require the `macros` feature.
+	///
+	/// # Example
+	///
+	/// ```rust
+	/// use rxml::CDataStr;
+	///
+	/// const NAME: &'static CDataStr = CDataStr::from_static("urn:example");
+	/// assert_eq!(NAME, "urn:example");
+	/// ```
+	///
+	/// ```rust,compile_fail
+	/// use rxml::CDataStr;
+	///
+	/// const NAME: &'static CDataStr = CDataStr::from_static("\x01");
+	/// ```
+	///
+	/// # Panics
+	///
+	/// If the string is not valid CData. In a const context, this
+	/// results in a compilation error instead.
+	pub const fn from_static(s: &'static str) -> &'static CDataStr {
+		if !rxml_validation::is_valid_cdata(s) {
+			panic!("string is not valid CData");
+		}
+		// SAFETY: validity has been checked above and CDataStr is a
+		// repr(transparent) str.
+		unsafe { core::mem::transmute(s) }
+	}
+
 	/// Create an owned copy of the string as [`CData`].
 	///
 	/// This operation is also available as implementation of the `Into`
@@ -796,6 +1387,20 @@ impl CDataStr {
 	pub fn to_cdata(&self) -> CData {
 		self.into()
 	}
+
+	/// Escape the string for use as text content of an element.
+	///
+	/// See [`escape_text`].
+	pub fn escape_text(&self) -> Cow<'_, CDataStr> {
+		escape_text(self)
+	}
+
+	/// Escape the string for use as attribute value.
+	///
+	/// See [`escape_attr_value`].
+	pub fn escape_attr_value(&self) -> Cow<'_, CDataStr> {
+		escape_attr_value(self)
+	}
 }
 
 impl From<NcName> for CData {
@@ -904,10 +1509,272 @@ pub fn validate_ncname(s: &str) -> Result<(), XmlError> {
 	}
 }
 
+fn escape_with<'x>(s: &'x CDataStr, specials: &'static [u8]) -> Cow<'x, CDataStr> {
+	if !s.bytes().any(|b| specials.contains(&b)) {
+		return Cow::Borrowed(s);
+	}
+	let mut out = Vec::with_capacity(s.len() + 8);
+	writer::escape(&mut out, s.as_bytes(), specials);
+	// SAFETY: escaping only replaces ASCII characters with ASCII
+	// references, so the result is still valid UTF-8 and valid CData.
+	Cow::Owned(unsafe { CData::from_string_unchecked(String::from_utf8_unchecked(out)) })
+}
+
+/**
+Escape a string for use as text content of an element.
+
+`<`, `>` and `&` are replaced by their predefined entities and carriage
+returns by a character reference, exactly as the [`Encoder`](crate::Encoder)
+does for text. If nothing needs to be escaped, the input is returned
+without copying.
+
+# Example
+
+```rust
+use rxml::strings::{escape_text, CDataStr};
+
+let text = CDataStr::from_str("1 < 2 & 'yes'").unwrap();
+assert_eq!(&*escape_text(text), "1 &lt; 2 &amp; 'yes'");
+```
+*/
+pub fn escape_text(s: &CDataStr) -> Cow<'_, CDataStr> {
+	escape_with(s, writer::CDATA_SPECIALS)
+}
+
+/**
+Escape a string for use as attribute value.
+
+In addition to the characters escaped by [`escape_text`], both kinds of
+quotes, line feeds and tabs are replaced by character references, so that
+the value can be enclosed in either kind of quotes and survives attribute
+value normalization unchanged.
+
+# Example
+
+```rust
+use rxml::strings::{escape_attr_value, CDataStr};
+
+let value = CDataStr::from_str("say \"hi\"\n").unwrap();
+assert_eq!(&*escape_attr_value(value), "say &#34;hi&#34;&#xa;");
+```
+*/
+pub fn escape_attr_value(s: &CDataStr) -> Cow<'_, CDataStr> {
+	escape_with(s, writer::ATTR_SPECIALS)
+}
+
+/**
+Expand entity and character references in a string.
+
+The predefined entities (`&amp;`, `&lt;`, `&gt;`, `&apos;` and `&quot;`)
+and decimal and hexadecimal character references are resolved with the
+same rules as the lexer applies. Markup is not interpreted: a `<` is kept
+as-is, as are CDATA section markers. The result is checked to be valid
+CData. If the string contains no references, it is returned without
+copying.
+
+# Example
+
+```rust
+use rxml::error::XmlError;
+use rxml::strings::unescape;
+
+assert_eq!(&*unescape("fish &amp; chips &#x1F41F;").unwrap(), "fish & chips \u{1f41f}");
+assert!(matches!(unescape("&nbsp;"), Err(XmlError::UndeclaredEntity)));
+assert!(matches!(unescape("&#0;"), Err(XmlError::InvalidChar(..))));
+```
+*/
+pub fn unescape(s: &str) -> Result<Cow<'_, CDataStr>, XmlError> {
+	let mut rest = match s.find('&') {
+		None => return CDataStr::from_str(s).map(Cow::Borrowed),
+		Some(_) => s,
+	};
+	let mut out = Vec::with_capacity(s.len());
+	while let Some(start) = rest.find('&') {
+		out.extend_from_slice(&rest.as_bytes()[..start]);
+		rest = &rest[start + 1..];
+		let end = match rest.find(';') {
+			Some(end) => end,
+			None => return Err(XmlError::InvalidEof(errctx::ERRCTX_REF)),
+		};
+		resolve_reference(&rest[..end], &mut out)?;
+		rest = &rest[end + 1..];
+	}
+	out.extend_from_slice(rest.as_bytes());
+	// SAFETY: out is composed of slices of a str, split at ASCII
+	// characters, and of UTF-8 encoded chars.
+	let out = unsafe { String::from_utf8_unchecked(out) };
+	CData::try_from(out).map(Cow::Owned)
+}
+
+/// Generation of valid strings from fuzzer input.
+///
+/// Valid strings are borrowed from the input by cutting it off at the first
+/// character which is not allowed. Names which would end up empty are
+/// replaced by a short name from a fixed list instead.
+#[cfg(feature = "arbitrary")]
+mod fuzzing {
+	use super::*;
+
+	use arbitrary::{Arbitrary, Result, Unstructured};
+
+	const NAME_FALLBACKS: &[&str] = &["a", "b", "c", "x", "y", "foo", "bar", "_"];
+
+	fn cdata_prefix(s: &str) -> &str {
+		match s
+			.char_indices()
+			.find(|(_, ch)| selectors::CLASS_XML_NONCHAR.select(*ch))
+		{
+			Some((i, _)) => &s[..i],
+			None => s,
+		}
+	}
+
+	fn name_prefix(s: &str, allow_colon: bool) -> &str {
+		let end = s.char_indices().find(|&(i, ch)| {
+			let valid = if i == 0 {
+				selectors::CLASS_XML_NAMESTART.select(ch)
+			} else {
+				selectors::CLASS_XML_NAME.select(ch)
+			};
+			!valid || (!allow_colon && ch == ':')
+		});
+		match end {
+			Some((i, _)) => &s[..i],
+			None => s,
+		}
+	}
+
+	fn arbitrary_name<'a>(u: &mut Unstructured<'a>, allow_colon: bool) -> Result<&'a str> {
+		let s = name_prefix(u.arbitrary()?, allow_colon);
+		if s.is_empty() {
+			Ok(u.choose(NAME_FALLBACKS)?)
+		} else {
+			Ok(s)
+		}
+	}
+
+	impl<'a> Arbitrary<'a> for &'a CDataStr {
+		fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+			let s = cdata_prefix(u.arbitrary()?);
+			// SAFETY: cdata_prefix only returns valid CData
+			Ok(unsafe { CDataStr::from_str_unchecked(s) })
+		}
+	}
+
+	impl<'a> Arbitrary<'a> for &'a NameStr {
+		fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+			// SAFETY: arbitrary_name only returns valid Names
+			Ok(unsafe { NameStr::from_str_unchecked(arbitrary_name(u, true)?) })
+		}
+	}
+
+	impl<'a> Arbitrary<'a> for &'a NcNameStr {
+		fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+			// SAFETY: arbitrary_name only returns valid NcNames without
+			// colon
+			Ok(unsafe { NcNameStr::from_str_unchecked(arbitrary_name(u, false)?) })
+		}
+	}
+
+	impl<'a> Arbitrary<'a> for CData {
+		fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+			Ok(<&CDataStr>::arbitrary(u)?.to_cdata())
+		}
+	}
+
+	impl<'a> Arbitrary<'a> for Name {
+		fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+			Ok(<&NameStr>::arbitrary(u)?.to_name())
+		}
+	}
+
+	impl<'a> Arbitrary<'a> for NcName {
+		fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+			Ok(<&NcNameStr>::arbitrary(u)?.to_ncname())
+		}
+	}
+
+	#[cfg(test)]
+	mod tests {
+		use super::*;
+
+		#[test]
+		fn generates_valid_strings_from_any_input() {
+			let mut data = Vec::new();
+			for i in 0..4096u32 {
+				data.extend_from_slice(&i.wrapping_mul(2654435761).to_le_bytes());
+			}
+			let mut u = Unstructured::new(&data);
+			while !u.is_empty() {
+				let cdata = CData::arbitrary(&mut u).unwrap();
+				validate_cdata(&cdata).unwrap();
+				let name = Name::arbitrary(&mut u).unwrap();
+				validate_name(&name).unwrap();
+				let ncname = NcName::arbitrary(&mut u).unwrap();
+				validate_ncname(&ncname).unwrap();
+			}
+		}
+
+		#[test]
+		fn truncates_at_first_invalid_char() {
+			assert_eq!(cdata_prefix("foo\x01bar"), "foo");
+			assert_eq!(name_prefix("foo:bar baz", true), "foo:bar");
+			assert_eq!(name_prefix("foo:bar baz", false), "foo");
+			assert_eq!(name_prefix("1foo", false), "");
+		}
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
 
+	use alloc::string::ToString;
+
+	#[test]
+	fn sanitize_keeps_valid_names_borrowed() {
+		match NcNameSanitizer::new().sanitize("foo.bar-1") {
+			Cow::Borrowed(v) => assert_eq!(v, "foo.bar-1"),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn sanitize_produces_valid_ncnames() {
+		let sanitizer = NcNameSanitizer::new();
+		for (input, expected) in [
+			("", "_"),
+			(":", "_"),
+			("a:b", "a_b"),
+			("-x", "_-x"),
+			(".", "_."),
+			("\u{b7}", "_\u{b7}"),
+			("a\u{0}b\u{fffe}", "a_b_"),
+			("caf\u{e9} cr\u{e8}me", "caf\u{e9}_cr\u{e8}me"),
+		] {
+			let result = sanitizer.sanitize(input);
+			assert_eq!(&*result, expected);
+			validate_ncname(&result).unwrap();
+		}
+	}
+
+	#[test]
+	fn sanitize_uses_configured_replacement_and_prefix() {
+		let prefix = NcNameStr::from_str("n").unwrap();
+		let sanitizer = NcNameSanitizer::new()
+			.with_replacement('.')
+			.with_prefix(prefix);
+		assert_eq!(&*sanitizer.sanitize("a b"), "a.b");
+		assert_eq!(&*sanitizer.sanitize(" b"), "n.b");
+		assert_eq!(&*sanitizer.sanitize("9"), "n9");
+	}
+
+	#[test]
+	#[should_panic(expected = "replacement character")]
+	fn sanitize_rejects_invalid_replacement() {
+		let _ = NcNameSanitizer::new().with_replacement(':');
+	}
+
 	#[test]
 	fn split_name_on_namestr_with_valid_name() {
 		let nm: &NameStr = "foo:bar".try_into().unwrap();
@@ -960,10 +1827,213 @@ mod tests {
 		));
 	}
 
+	#[test]
+	fn split_name_on_namestr_rejects_multiple_colons() {
+		let nm: &NameStr = "foo:bar:baz".try_into().unwrap();
+		let result = nm.split_name();
+		assert!(matches!(result.err().unwrap(), XmlError::MultiColonName(_)));
+	}
+
+	#[test]
+	fn split_name_on_namestr_rejects_empty_parts() {
+		let nm: &NameStr = ":bar".try_into().unwrap();
+		assert!(matches!(
+			nm.split_name().err().unwrap(),
+			XmlError::EmptyNamePart(_)
+		));
+		let nm: &NameStr = "foo:".try_into().unwrap();
+		assert!(matches!(
+			nm.split_name().err().unwrap(),
+			XmlError::EmptyNamePart(_)
+		));
+	}
+
+	#[test]
+	fn split_name_ref_on_name_borrows() {
+		let nm: Name = "foo:bar".try_into().unwrap();
+		let (prefix, localname) = nm.split_name_ref().unwrap();
+		assert_eq!(prefix.unwrap(), "foo");
+		assert_eq!(localname, "bar");
+		assert_eq!(prefix.unwrap().as_ptr(), nm.as_ptr());
+	}
+
+	#[test]
+	fn from_cow_keeps_borrowed_data_borrowed() {
+		let src = "foo bar";
+		match CDataStr::from_cow(Cow::Borrowed(src)) {
+			Ok(Cow::Borrowed(v)) => assert_eq!(v, "foo bar"),
+			other => panic!("unexpected from_cow result: {:?}", other),
+		}
+		match NameStr::from_cow(Cow::Owned("foo:bar".to_string())) {
+			Ok(Cow::Owned(v)) => assert_eq!(v, "foo:bar"),
+			other => panic!("unexpected from_cow result: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn from_cow_validates() {
+		assert!(NcNameStr::from_cow(Cow::Borrowed("foo:bar")).is_err());
+		assert!(NcNameStr::from_cow(Cow::Owned("foo:bar".to_string())).is_err());
+	}
+
+	#[test]
+	fn borrowed_and_owned_convert_to_cow() {
+		let borrowed: &NcNameStr = "foo".try_into().unwrap();
+		let owned: NcName = "foo".try_into().unwrap();
+		let a: Cow<'_, NcNameStr> = borrowed.into();
+		let b: Cow<'_, NcNameStr> = owned.into();
+		assert_eq!(a, b);
+	}
+
+	#[test]
+	fn shared_pointers_clone_shallowly() {
+		let cdata: CData = "foo bar".try_into().unwrap();
+		let a: Arc<CDataStr> = cdata.into();
+		let b = a.clone();
+		assert!(Arc::ptr_eq(&a, &b));
+		assert_eq!(&*b, "foo bar");
+		let nm: &NcNameStr = "foo".try_into().unwrap();
+		let c: Rc<NcNameStr> = nm.into();
+		assert_eq!(&*c, "foo");
+	}
+
+	#[test]
+	fn short_names_are_stored_inline() {
+		let nm: Name = "stream:features".try_into().unwrap();
+		assert!(nm.into_inner().is_inline());
+		let nm: NcName = "features".try_into().unwrap();
+		assert!(nm.into_inner().is_inline());
+	}
+
+	#[test]
+	fn from_static_accepts_valid_strings() {
+		const NAME: &NameStr = NameStr::from_static("foo:bar");
+		const NCNAME: &NcNameStr = NcNameStr::from_static("foo");
+		const CDATA: &CDataStr = CDataStr::from_static("foo bar");
+		assert_eq!(NAME, "foo:bar");
+		assert_eq!(NCNAME, "foo");
+		assert_eq!(CDATA, "foo bar");
+	}
+
+	#[test]
+	#[should_panic(expected = "string is not valid NCName")]
+	fn from_static_panics_on_invalid_string_at_runtime() {
+		NcNameStr::from_static("foo:bar");
+	}
+
 	#[test]
 	fn cdatastr_allows_slashes() {
 		let _: &CDataStr = "http://www.w3.org/XML/1998/namespace".try_into().unwrap();
 	}
+
+	#[test]
+	fn escape_borrows_if_nothing_to_escape() {
+		let s: &CDataStr = "foo 'bar'".try_into().unwrap();
+		assert!(matches!(escape_text(s), Cow::Borrowed(_)));
+		assert!(matches!(escape_attr_value(s), Cow::Owned(_)));
+		assert!(matches!(unescape("foo <bar>").unwrap(), Cow::Borrowed(_)));
+	}
+
+	#[test]
+	fn escape_and_unescape_roundtrip() {
+		let s: &CDataStr = "<a b=\"c\">\r\n\t'&amp;'</a> \u{1f41f}".try_into().unwrap();
+		assert_eq!(
+			&*s.escape_text(),
+			"&lt;a b=\"c\"&gt;&#xd;\n\t'&amp;amp;'&lt;/a&gt; \u{1f41f}"
+		);
+		assert_eq!(&*unescape(&s.escape_text()).unwrap(), s);
+		assert_eq!(&*unescape(&s.escape_attr_value()).unwrap(), s);
+	}
+
+	#[test]
+	fn unescape_resolves_references() {
+		assert_eq!(
+			&*unescape("&lt;&gt;&amp;&apos;&quot;&#65;&#x42;&#x10FFFF;").unwrap(),
+			"<>&'\"AB\u{10ffff}"
+		);
+	}
+
+	#[test]
+	fn unescape_rejects_malformed_references() {
+		match unescape("foo &amp bar;") {
+			Err(XmlError::UndeclaredEntity) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match unescape("foo &amp") {
+			Err(XmlError::InvalidEof(_)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match unescape("&;") {
+			Err(XmlError::InvalidSyntax(_)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match unescape("&#;") {
+			Err(XmlError::InvalidSyntax(_)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match unescape("&#12a;") {
+			Err(XmlError::UnexpectedByte(_, b'a', _)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match unescape("&#x110000;") {
+			Err(XmlError::InvalidChar(..)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match unescape("&#000000000065;") {
+			Err(XmlError::UndeclaredEntity) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match unescape("&foo;") {
+			Err(XmlError::UndeclaredEntity) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match unescape("\x01") {
+			Err(XmlError::UnexpectedChar(..)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn serde_roundtrip_owned() {
+		use serde_test::{assert_tokens, Token};
+		let nm: Name = "foo:bar".try_into().unwrap();
+		assert_tokens(&nm, &[Token::String("foo:bar")]);
+		let nm: NcName = "foo".try_into().unwrap();
+		assert_tokens(&nm, &[Token::String("foo")]);
+		let cdata: CData = "foo bar".try_into().unwrap();
+		assert_tokens(&cdata, &[Token::String("foo bar")]);
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn serde_roundtrip_borrowed() {
+		use serde_test::{assert_tokens, Token};
+		let nm: &NameStr = "foo:bar".try_into().unwrap();
+		assert_tokens(&nm, &[Token::BorrowedStr("foo:bar")]);
+		let nm: &NcNameStr = "foo".try_into().unwrap();
+		assert_tokens(&nm, &[Token::BorrowedStr("foo")]);
+		let cdata: &CDataStr = "foo bar".try_into().unwrap();
+		assert_tokens(&cdata, &[Token::BorrowedStr("foo bar")]);
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn serde_deserialize_validates() {
+		use serde_test::{assert_de_tokens_error, Token};
+		assert_de_tokens_error::<NcName>(
+			&[Token::String("foo:bar")],
+			&NcName::try_from("foo:bar").unwrap_err().to_string(),
+		);
+		assert_de_tokens_error::<&NameStr>(
+			&[Token::BorrowedStr("")],
+			&<&NameStr>::try_from("").unwrap_err().to_string(),
+		);
+		assert_de_tokens_error::<CData>(
+			&[Token::String("\x01")],
+			&CData::try_from("\x01").unwrap_err().to_string(),
+		);
+	}
 }
 
 /// Compatibility alias, use [`NcName`] directly instead.
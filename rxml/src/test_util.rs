@@ -0,0 +1,611 @@
+/*!
+# Utilities for testing code which uses rxml
+
+This module is available with the `test-util` feature. It contains helpers
+which are needed over and over again when testing applications built on top
+of rxml:
+
+* [`parse_chunked`] parses a document fed in chunks of a given size, to
+  check that an application does not depend on how data arrives.
+* [`normalize_events`] and [`assert_same_events`] compare event streams
+  while ignoring differences which only stem from chunking: text may be
+  split across several events and event metrics depend on the input
+  layout.
+* [`describe_events`] and the [`assert_events!`](crate::assert_events)
+  macro allow to write the expected events of a document in a compact,
+  readable form.
+* [`roundtrip`] and [`assert_roundtrip`] check that a document survives
+  parsing, re-encoding and parsing again, showing a diff of the events if
+  it does not.
+* [`documents`] and the other strategies generate valid documents for
+  property-based testing with [`proptest`].
+
+```
+use rxml::assert_events;
+
+assert_events!(
+	b"<?xml version='1.0'?><a xmlns='urn:x' b='c'>foo<d/></a>",
+	[
+		"<?xml version='1.0'?>",
+		"<{urn:x}a b='c'>",
+		"foo",
+		"<{urn:x}d>",
+		"</{urn:x}d>",
+		"</{urn:x}a>",
+	]
+);
+```
+*/
+use std::convert::TryFrom;
+use std::fmt::{self, Write};
+
+use bytes::BytesMut;
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use crate::driver::FeedParser;
+use crate::error::{Error, Result};
+use crate::parser::{Attributes, EventMetrics, NamespaceName, RcPtr, ResolvedEvent, ResolvedQName};
+use crate::strings::{CData, NcName};
+use crate::writer::{EncodeError, Encoder};
+
+/// Parse a complete document, feeding it to the parser in chunks of
+/// `chunk_size` bytes.
+///
+/// A `chunk_size` of zero feeds the document in one go.
+pub fn parse_chunked(input: &[u8], chunk_size: usize) -> Result<Vec<ResolvedEvent>> {
+	let mut fp = FeedParser::default();
+	let mut result = Vec::new();
+	let chunk_size = if chunk_size == 0 {
+		input.len().max(1)
+	} else {
+		chunk_size
+	};
+	let mut chunks = input.chunks(chunk_size).peekable();
+	while let Some(mut chunk) = chunks.next() {
+		let at_eof = chunks.peek().is_none();
+		match fp.parse_all(&mut chunk, at_eof, |ev| result.push(ev)) {
+			Err(e) if !at_eof && e.is_would_block() => (),
+			other => other?,
+		}
+	}
+	if input.is_empty() {
+		fp.parse_all(&mut &input[..], true, |ev| result.push(ev))?;
+	}
+	Ok(result)
+}
+
+/// Bring an event stream into a canonical form.
+///
+/// Adjacent text events are merged, empty text events are removed, all
+/// event metrics are set to zero and element prefixes are dropped. Two
+/// streams which only differ in how the input was chunked or which prefixes
+/// were used are identical after normalization.
+pub fn normalize_events<I: IntoIterator<Item = ResolvedEvent>>(evs: I) -> Vec<ResolvedEvent> {
+	let zero = EventMetrics::new(0);
+	let mut result: Vec<ResolvedEvent> = Vec::new();
+	for ev in evs {
+		let ev = match ev {
+			ResolvedEvent::Text(_, text) => {
+				if text.is_empty() {
+					continue;
+				}
+				if let Some(ResolvedEvent::Text(_, prev)) = result.last_mut() {
+					*prev = prev.clone() + &*text;
+					continue;
+				}
+				ResolvedEvent::Text(zero, text)
+			}
+			ResolvedEvent::XmlDeclaration(_, v) => ResolvedEvent::XmlDeclaration(zero, v),
+			ResolvedEvent::StartElement(_, name, attrs, _) => {
+				ResolvedEvent::StartElement(zero, name, attrs, None)
+			}
+			ResolvedEvent::EndElement(_) => ResolvedEvent::EndElement(zero),
+			ResolvedEvent::StartPrefixMapping(_, prefix, ns) => {
+				ResolvedEvent::StartPrefixMapping(zero, prefix, ns)
+			}
+			ResolvedEvent::EndPrefixMapping(_, prefix) => {
+				ResolvedEvent::EndPrefixMapping(zero, prefix)
+			}
+		};
+		result.push(ev);
+	}
+	result
+}
+
+/// Assert that two event streams are equal after
+/// [normalization](normalize_events).
+///
+/// On mismatch, the panic message shows both streams in the form produced by
+/// [`describe_events`].
+pub fn assert_same_events(actual: &[ResolvedEvent], expected: &[ResolvedEvent]) {
+	let actual = normalize_events(actual.iter().cloned());
+	let expected = normalize_events(expected.iter().cloned());
+	if actual != expected {
+		panic!(
+			"event streams differ:\n  actual:   {:?}\n  expected: {:?}",
+			describe_events(&actual),
+			describe_events(&expected),
+		);
+	}
+}
+
+fn write_name(out: &mut String, (ns, name): &ResolvedQName) {
+	if let Some(ns) = ns {
+		write!(out, "{{{}}}", ns).unwrap();
+	}
+	out.push_str(name);
+}
+
+/// Describe each event of a stream in a compact form.
+///
+/// Adjacent text events are merged first. The descriptions are:
+///
+/// * `<?xml version='1.0'?>` for the XML declaration,
+/// * `<{uri}name attr='value'>` for the start of an element, using Clark
+///   notation for namespaced names and listing the attributes in document
+///   order with their unescaped values,
+/// * `</{uri}name>` for the end of an element,
+/// * the text itself for text,
+/// * `xmlns:prefix='uri'` (or `xmlns='uri'`) and `/xmlns:prefix` (or
+///   `/xmlns`) for prefix mapping events.
+pub fn describe_events(evs: &[ResolvedEvent]) -> Vec<String> {
+	let mut stack = Vec::new();
+	let mut result = Vec::new();
+	for ev in normalize_events(evs.iter().cloned()) {
+		let mut out = String::new();
+		match ev {
+			ResolvedEvent::XmlDeclaration(..) => out.push_str("<?xml version='1.0'?>"),
+			ResolvedEvent::StartElement(_, name, attrs, _) => {
+				out.push('<');
+				write_name(&mut out, &name);
+				for (attr_name, value) in attrs.iter() {
+					out.push(' ');
+					write_name(&mut out, attr_name);
+					write!(out, "='{}'", value).unwrap();
+				}
+				out.push('>');
+				stack.push(name);
+			}
+			ResolvedEvent::EndElement(_) => {
+				out.push_str("</");
+				if let Some(name) = stack.pop() {
+					write_name(&mut out, &name);
+				}
+				out.push('>');
+			}
+			ResolvedEvent::Text(_, text) => out.push_str(&text),
+			ResolvedEvent::StartPrefixMapping(_, prefix, ns) => match prefix {
+				Some(prefix) => write!(out, "xmlns:{}='{}'", prefix, ns).unwrap(),
+				None => write!(out, "xmlns='{}'", ns).unwrap(),
+			},
+			ResolvedEvent::EndPrefixMapping(_, prefix) => match prefix {
+				Some(prefix) => write!(out, "/xmlns:{}", prefix).unwrap(),
+				None => out.push_str("/xmlns"),
+			},
+		}
+		result.push(out);
+	}
+	result
+}
+
+/**
+Assert that a document produces the given events.
+
+The document is parsed completely and the events are compared with the
+expected descriptions in the form produced by [`describe_events`]. In
+addition, the document is parsed once more, fed byte by byte, to check that
+the result does not depend on chunking.
+
+Parsing errors cause a panic.
+
+```
+use rxml::assert_events;
+
+assert_events!(b"<a>foo &amp; bar</a>", ["<a>", "foo & bar", "</a>"]);
+```
+*/
+#[macro_export]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+macro_rules! assert_events {
+	($input:expr, [$($expected:expr),* $(,)?]) => {{
+		let input: &[u8] = &$input[..];
+		let actual = match $crate::test_util::parse_chunked(input, 0) {
+			Ok(evs) => evs,
+			Err(e) => panic!("failed to parse document: {}", e),
+		};
+		let expected: ::std::vec::Vec<::std::string::String> =
+			::std::vec![$(::std::string::String::from($expected)),*];
+		assert_eq!($crate::test_util::describe_events(&actual), expected);
+		match $crate::test_util::parse_chunked(input, 1) {
+			Ok(chunked) => $crate::test_util::assert_same_events(&chunked, &actual),
+			Err(e) => panic!("failed to parse document byte by byte: {}", e),
+		}
+	}};
+}
+
+/// Render the differences between two lists of lines.
+///
+/// Lines only in `actual` are prefixed with `+`, lines only in `expected`
+/// with `-` and common lines with a space.
+fn diff_lines(actual: &[String], expected: &[String]) -> String {
+	// longest common subsequence, by dynamic programming from the end
+	let (n, m) = (expected.len(), actual.len());
+	let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+	for i in (0..n).rev() {
+		for j in (0..m).rev() {
+			lcs[i][j] = if expected[i] == actual[j] {
+				lcs[i + 1][j + 1] + 1
+			} else {
+				lcs[i + 1][j].max(lcs[i][j + 1])
+			};
+		}
+	}
+	let mut out = String::new();
+	let (mut i, mut j) = (0, 0);
+	while i < n || j < m {
+		if i < n && j < m && expected[i] == actual[j] {
+			writeln!(out, "  {}", expected[i]).unwrap();
+			i += 1;
+			j += 1;
+		} else if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+			writeln!(out, "+ {}", actual[j]).unwrap();
+			j += 1;
+		} else {
+			writeln!(out, "- {}", expected[i]).unwrap();
+			i += 1;
+		}
+	}
+	out
+}
+
+/// Failure of a [`roundtrip`].
+#[derive(Debug)]
+pub enum RoundtripError {
+	/// The input could not be parsed.
+	Parse(Error),
+	/// The parsed events could not be encoded.
+	Encode(EncodeError),
+	/// The re-encoded document could not be parsed.
+	Reparse {
+		/// The re-encoded document.
+		output: Vec<u8>,
+		/// The error returned by the parser.
+		error: Error,
+	},
+	/// The re-encoded document does not produce the same events as the
+	/// input.
+	Mismatch {
+		/// The re-encoded document.
+		output: Vec<u8>,
+		/// Line diff between the [described](describe_events) events of the
+		/// input (`-`) and those of the re-encoded document (`+`).
+		diff: String,
+	},
+}
+
+impl fmt::Display for RoundtripError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Parse(e) => write!(f, "failed to parse input: {}", e),
+			Self::Encode(e) => write!(f, "failed to encode events: {}", e),
+			Self::Reparse { output, error } => write!(
+				f,
+				"failed to parse re-encoded document: {}\ndocument: {}",
+				error,
+				String::from_utf8_lossy(output)
+			),
+			Self::Mismatch { output, diff } => write!(
+				f,
+				"re-encoded document produces different events\ndocument: {}\n{}",
+				String::from_utf8_lossy(output),
+				diff
+			),
+		}
+	}
+}
+
+impl std::error::Error for RoundtripError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Parse(e) | Self::Reparse { error: e, .. } => Some(e),
+			Self::Encode(e) => Some(e),
+			Self::Mismatch { .. } => None,
+		}
+	}
+}
+
+/// Parse a document, encode the events with a default [`Encoder`], parse
+/// the result again and compare the events.
+///
+/// The event streams are compared after [normalization](normalize_events),
+/// so that differences in the serialization which do not affect the
+/// events (quoting, prefixes, escaping, CDATA sections, whitespace inside
+/// tags, ...) are ignored.
+///
+/// On success, the re-encoded document is returned.
+///
+/// ```
+/// use rxml::test_util::roundtrip;
+///
+/// let output = roundtrip(b"<a xmlns:p='urn:p' p:b=\"c\"><![CDATA[<x>]]></a >").unwrap();
+/// assert_eq!(output, &b"<a xmlns:tns0='urn:p' tns0:b=\"c\">&lt;x&gt;</a>"[..]);
+/// ```
+pub fn roundtrip(input: &[u8]) -> std::result::Result<Vec<u8>, RoundtripError> {
+	let parsed = parse_chunked(input, 0).map_err(RoundtripError::Parse)?;
+	let mut encoder = Encoder::new();
+	let mut output = BytesMut::new();
+	for ev in parsed.iter() {
+		encoder
+			.encode_event_into_bytes(ev, &mut output)
+			.map_err(RoundtripError::Encode)?;
+	}
+	let output = output.to_vec();
+	let reparsed = match parse_chunked(&output, 0) {
+		Ok(evs) => evs,
+		Err(error) => return Err(RoundtripError::Reparse { output, error }),
+	};
+	let expected = describe_events(&parsed);
+	let actual = describe_events(&reparsed);
+	if normalize_events(parsed) != normalize_events(reparsed) {
+		return Err(RoundtripError::Mismatch {
+			output,
+			diff: diff_lines(&actual, &expected),
+		});
+	}
+	Ok(output)
+}
+
+/// Assert that a document survives a [`roundtrip`].
+///
+/// On failure, the panic message contains the re-encoded document and, if
+/// the events differ, a diff of the events.
+pub fn assert_roundtrip(input: &[u8]) {
+	if let Err(e) = roundtrip(input) {
+		panic!(
+			"roundtrip of {:?} failed: {}",
+			String::from_utf8_lossy(input),
+			e
+		);
+	}
+}
+
+/// A generated document together with the events it is expected to
+/// produce.
+///
+/// See [`documents`].
+#[derive(Debug, Clone)]
+pub struct Document {
+	/// The serialized document.
+	pub serialized: Vec<u8>,
+	/// The events which the document contains, with zero metrics.
+	pub events: Vec<ResolvedEvent>,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+enum Node {
+	Element(ResolvedQName, Attributes, Vec<Node>),
+	Text(CData),
+}
+
+impl Node {
+	fn push_events(self, out: &mut Vec<ResolvedEvent>) {
+		let zero = EventMetrics::new(0);
+		match self {
+			Self::Element(name, attrs, children) => {
+				out.push(ResolvedEvent::StartElement(zero, name, attrs, None));
+				for child in children {
+					child.push_events(out);
+				}
+				out.push(ResolvedEvent::EndElement(zero));
+			}
+			Self::Text(text) => out.push(ResolvedEvent::Text(zero, text)),
+		}
+	}
+}
+
+/// Strategy for NCNames.
+///
+/// The names are short and mostly ASCII, with an occasional non-ASCII
+/// character, so that collisions (e.g. between attribute names) happen from
+/// time to time.
+pub fn ncnames() -> impl Strategy<Value = NcName> {
+	"[a-zA-Z_\u{c0}-\u{d6}\u{4e00}-\u{4e10}][a-zA-Z0-9._\u{b7}\u{c0}-\u{d6}-]{0,6}"
+		.prop_map(|s| NcName::try_from(s).unwrap())
+}
+
+/// Strategy for character data.
+///
+/// This covers characters which need escaping, whitespace of all kinds and
+/// non-ASCII characters.
+pub fn cdata() -> impl Strategy<Value = CData> {
+	"[a-z <>&'\"\t\n\r\u{e4}\u{1f600}\u{fffd}\\]]{0,16}".prop_map(|s| CData::try_from(s).unwrap())
+}
+
+/// Strategy for namespace names.
+///
+/// Namespace names are picked from a small set, so that elements and
+/// attributes from the same namespace are common.
+pub fn namespaces() -> impl Strategy<Value = Option<NamespaceName>> {
+	prop_oneof![
+		2 => Just(None),
+		1 => Just(Some("urn:a")),
+		1 => Just(Some("urn:b")),
+		1 => Just(Some("http://www.w3.org/XML/1998/namespace")),
+	]
+	.prop_map(|ns| ns.map(|ns| RcPtr::new(CData::try_from(ns).unwrap())))
+}
+
+fn element_names() -> impl Strategy<Value = ResolvedQName> {
+	(namespaces(), ncnames()).prop_map(|(ns, name)| {
+		// elements must not be in the XML namespace
+		match ns {
+			Some(ns) if ns.as_str() == crate::XMLNS_XML => (None, name),
+			ns => (ns, name),
+		}
+	})
+}
+
+fn attributes() -> impl Strategy<Value = Attributes> {
+	vec((namespaces(), ncnames(), cdata()), 0..4).prop_map(|attrs| {
+		attrs
+			.into_iter()
+			// unnamespaced xmlns attributes are namespace declarations
+			.filter(|(ns, name, _)| ns.is_some() || name.as_str() != "xmlns")
+			.map(|(ns, name, value)| ((ns, name), value))
+			.collect()
+	})
+}
+
+fn trees() -> impl Strategy<Value = Node> {
+	let leaf = prop_oneof![
+		cdata().prop_map(Node::Text),
+		(element_names(), attributes()).prop_map(|(name, attrs)| Node::Element(
+			name,
+			attrs,
+			Vec::new()
+		)),
+	];
+	leaf.prop_recursive(4, 32, 6, |inner| {
+		(element_names(), attributes(), vec(inner, 0..6))
+			.prop_map(|(name, attrs, children)| Node::Element(name, attrs, children))
+	})
+}
+
+/// Strategy for valid documents.
+///
+/// The documents consist of an optional XML declaration and a tree of
+/// elements with attributes, text and namespaces. They are serialized with
+/// an [`Encoder`].
+pub fn documents() -> impl Strategy<Value = Document> {
+	(
+		any::<bool>(),
+		element_names(),
+		attributes(),
+		vec(trees(), 0..6),
+	)
+		.prop_map(|(declaration, name, attrs, children)| {
+			let mut events = Vec::new();
+			if declaration {
+				events.push(ResolvedEvent::XmlDeclaration(
+					EventMetrics::new(0),
+					crate::XmlVersion::V1_0,
+				));
+			}
+			Node::Element(name, attrs, children).push_events(&mut events);
+			let mut encoder = Encoder::new();
+			let mut buf = BytesMut::new();
+			for ev in events.iter() {
+				encoder.encode_event_into_bytes(ev, &mut buf).unwrap();
+			}
+			Document {
+				serialized: buf.to_vec(),
+				events: normalize_events(events),
+			}
+		})
+}
+
+/// Strategy for chunk sizes to pass to [`parse_chunked`].
+///
+/// Small chunk sizes are favoured, as they are most likely to uncover
+/// problems.
+pub fn chunk_sizes() -> impl Strategy<Value = usize> {
+	prop_oneof![3 => 1usize..8, 1 => 8usize..256, 1 => Just(0usize)]
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn describes_events() {
+		assert_events!(
+			b"<?xml version='1.0'?>\n<a xmlns='urn:x' xmlns:p='urn:p' p:b='c' d='&lt;'>foo<![CDATA[bar]]><p:e/></a>",
+			[
+				"<?xml version='1.0'?>",
+				"<{urn:x}a {urn:p}b='c' d='<'>",
+				"foobar",
+				"<{urn:p}e>",
+				"</{urn:p}e>",
+				"</{urn:x}a>",
+			]
+		);
+	}
+
+	#[test]
+	#[should_panic]
+	fn assert_events_panics_on_mismatch() {
+		assert_events!(b"<a/>", ["<b>", "</b>"]);
+	}
+
+	#[test]
+	fn normalization_ignores_chunking() {
+		let doc = b"<a>foo &amp; bar<b>baz</b></a>";
+		let whole = parse_chunked(doc, 0).unwrap();
+		let chunked = parse_chunked(doc, 3).unwrap();
+		assert_same_events(&chunked, &whole);
+
+		// split the text manually, as the parser may already merge it
+		let mut split = Vec::new();
+		for ev in whole {
+			match ev {
+				ResolvedEvent::Text(m, text) if text.len() > 1 => {
+					let (a, b) = text.split_at(1);
+					split.push(ResolvedEvent::Text(m, CData::try_from(a).unwrap()));
+					split.push(ResolvedEvent::Text(
+						EventMetrics::new(0),
+						CData::try_from(b).unwrap(),
+					));
+				}
+				other => split.push(other),
+			}
+		}
+		assert_same_events(&split, &chunked);
+	}
+
+	#[test]
+	#[should_panic(expected = "event streams differ")]
+	fn assert_same_events_detects_differences() {
+		let a = parse_chunked(b"<a>foo</a>", 0).unwrap();
+		let b = parse_chunked(b"<a>fo0</a>", 0).unwrap();
+		assert_same_events(&a, &b);
+	}
+
+	#[test]
+	fn roundtrip_accepts_equivalent_serializations() {
+		assert_roundtrip(
+			b"<?xml version='1.0'?>\n<a xmlns='urn:x' b='&quot;'>x<![CDATA[&]]>y<c/></a>",
+		);
+		match roundtrip(b"<a><b></a>") {
+			Err(RoundtripError::Parse(_)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn diff_marks_changed_lines() {
+		let lines = |v: &[&str]| v.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+		assert_eq!(
+			diff_lines(
+				&lines(&["<a>", "foo", "<b>", "</a>"]),
+				&lines(&["<a>", "<b>", "bar", "</a>"])
+			),
+			"  <a>\n+ foo\n  <b>\n- bar\n  </a>\n"
+		);
+	}
+
+	proptest! {
+		#[test]
+		fn generated_documents_roundtrip(doc in documents()) {
+			prop_assert!(roundtrip(&doc.serialized).is_ok());
+		}
+
+		#[test]
+		fn generated_documents_parse_to_their_events(doc in documents(), chunk_size in chunk_sizes()) {
+			let parsed = parse_chunked(&doc.serialized, chunk_size).unwrap();
+			prop_assert_eq!(normalize_events(parsed), doc.events);
+		}
+	}
+}
@@ -1,5 +1,6 @@
 use super::*;
 
+use std::borrow::Cow;
 use std::convert::TryFrom;
 
 #[cfg(feature = "async")]
@@ -60,7 +61,7 @@ fn feedparser_can_read_xml_document() {
 			other => panic!("unexpected event: {:?}", other),
 		};
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localname), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localname), attrs, _) => {
 				// note: 77 because of the \n between xml decl and whitespace. see also comment on EventMetrics
 				assert_eq!(em.len(), 77);
 				assert_eq!(
@@ -70,18 +71,18 @@ fn feedparser_can_read_xml_document() {
 				assert_eq!(localname, "root");
 				assert_eq!(attrs.len(), 2);
 				assert_eq!(
-					attrs.get(&(None, NcName::try_from("a").unwrap())).unwrap(),
+					&*attrs.get(&(None, NcName::try_from("a").unwrap())).unwrap(),
 					"foo"
 				);
 				assert_eq!(
-					attrs.get(&(None, NcName::try_from("b").unwrap())).unwrap(),
+					&*attrs.get(&(None, NcName::try_from("b").unwrap())).unwrap(),
 					"bar"
 				);
 			}
 			other => panic!("unexpected event: {:?}", other),
 		};
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localname), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localname), attrs, _) => {
 				assert_eq!(em.len(), 7);
 				assert_eq!(
 					nsuri.as_ref().unwrap().as_str(),
@@ -146,7 +147,7 @@ fn feedparser_can_handle_chunked_input() {
 			other => panic!("unexpected event: {:?}", other),
 		};
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localname), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localname), attrs, _) => {
 				assert_eq!(em.len(), 76);
 				assert_eq!(
 					nsuri.as_ref().unwrap().as_str(),
@@ -155,18 +156,18 @@ fn feedparser_can_handle_chunked_input() {
 				assert_eq!(localname, "root");
 				assert_eq!(attrs.len(), 2);
 				assert_eq!(
-					attrs.get(&(None, NcName::try_from("a").unwrap())).unwrap(),
+					&*attrs.get(&(None, NcName::try_from("a").unwrap())).unwrap(),
 					"foo"
 				);
 				assert_eq!(
-					attrs.get(&(None, NcName::try_from("b").unwrap())).unwrap(),
+					&*attrs.get(&(None, NcName::try_from("b").unwrap())).unwrap(),
 					"bar"
 				);
 			}
 			other => panic!("unexpected event: {:?}", other),
 		};
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localname), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localname), attrs, _) => {
 				assert_eq!(em.len(), 7);
 				assert_eq!(
 					nsuri.as_ref().unwrap().as_str(),
@@ -206,6 +207,715 @@ fn feedparser_can_handle_chunked_input() {
 
 // note that this is just a smoketest... the components of the PullParser
 // are tested extensively in the modules.
+#[test]
+fn feedwriter_parses_written_chunks() {
+	use std::io::Write;
+
+	let doc = b"<?xml version='1.0'?><hello a='b'>World!</hello>";
+	let mut fp = FeedParser::default();
+	let mut events = Vec::new();
+	{
+		let mut w = fp.as_writer(|ev| events.push(ev));
+		for chunk in doc.chunks(3) {
+			assert_eq!(w.write(chunk).unwrap(), chunk.len());
+		}
+		w.flush().unwrap();
+		w.finish().unwrap();
+	}
+	assert_eq!(events.len(), 4);
+	match &events[2] {
+		ResolvedEvent::Text(_, text) => assert_eq!(text, "World!"),
+		other => panic!("unexpected event: {:?}", other),
+	}
+}
+
+#[test]
+fn feedwriter_reports_xml_errors_as_invalid_data() {
+	use std::io::Write;
+
+	let mut fp = FeedParser::default();
+	let mut w = fp.as_writer(|_| ());
+	match w.write(b"<a></b>") {
+		Err(e) if e.kind() == io::ErrorKind::InvalidData => (),
+		other => panic!("unexpected write result: {:?}", other),
+	}
+}
+
+#[test]
+fn feedwriter_finish_detects_truncated_document() {
+	use std::io::Write;
+
+	let mut fp = FeedParser::default();
+	let mut w = fp.as_writer(|_| ());
+	w.write_all(b"<a>foo").unwrap();
+	match w.finish() {
+		Err(Error::Xml(error::XmlError::InvalidEof(_))) => (),
+		other => panic!("unexpected finish result: {:?}", other),
+	}
+}
+
+#[test]
+fn feedparser_leaves_data_after_document_in_buffer() {
+	let mut buf = &b"<a>foo</a><next-frame/>"[..];
+	let mut fp = FeedParser::default();
+	let mut n = 0;
+	while !fp.is_document_complete() {
+		match fp.parse(&mut buf, false) {
+			Ok(Some(_)) => n += 1,
+			other => panic!("unexpected parse result: {:?}", other),
+		}
+	}
+	assert_eq!(n, 3);
+	assert_eq!(buf, b"<next-frame/>");
+}
+
+#[test]
+fn feedparser_document_completion_accounts_for_pending_events() {
+	let mut buf = &b"<a xmlns:x='uri:x'/>trailer"[..];
+	let mut fp = FeedParser::default();
+	fp.get_parser_mut().set_emit_prefix_mappings(true);
+	let mut events = Vec::new();
+	while !fp.is_document_complete() {
+		match fp.parse(&mut buf, false) {
+			Ok(Some(ev)) => events.push(ev),
+			other => panic!("unexpected parse result: {:?}", other),
+		}
+	}
+	match events.last() {
+		Some(ResolvedEvent::EndPrefixMapping(..)) => (),
+		other => panic!("unexpected last event: {:?}", other),
+	}
+	assert_eq!(buf, b"trailer");
+}
+
+#[test]
+#[allow(deprecated)]
+fn feedparser_remainder_can_be_recovered_from_bufferqueue() {
+	let mut bq = BufferQueue::new();
+	bq.push(&b"<a>fo"[..]);
+	bq.push(&b"o</a>HTTP/1.1"[..]);
+	bq.push(&b" 200 OK"[..]);
+	let mut fp = FeedParser::default();
+	while !fp.is_document_complete() {
+		match fp.parse(&mut bq, false) {
+			Ok(Some(_)) => (),
+			other => panic!("unexpected parse result: {:?}", other),
+		}
+	}
+	assert_eq!(&bq.take_remainder()[..], b"HTTP/1.1 200 OK");
+}
+
+#[test]
+fn pullparser_leaves_data_after_document_in_reader() {
+	let mut pp = PullParser::with_capacity(4, &b"<a>foo</a><next-frame/>"[..]);
+	let mut n = 0;
+	while !pp.is_document_complete() {
+		match pp.read() {
+			Ok(Some(_)) => n += 1,
+			other => panic!("unexpected read result: {:?}", other),
+		}
+	}
+	assert_eq!(n, 3);
+	let mut rest = Vec::new();
+	std::io::Read::read_to_end(&mut pp.into_inner(), &mut rest).unwrap();
+	assert_eq!(rest, b"<next-frame/>");
+}
+
+#[test]
+fn pullparser_from_reader_handles_tiny_reads() {
+	let doc = "<?xml version='1.0'?><a b='ä&amp;ö'>Grüße, 🌍!</a>";
+	let mut expected = Vec::new();
+	PullParser::new(doc.as_bytes())
+		.read_all(|ev| expected.push(ev))
+		.unwrap();
+	let mut events = Vec::new();
+	PullParser::from_reader_with_capacity(1, std::io::Cursor::new(doc))
+		.read_all(|ev| events.push(ev))
+		.unwrap();
+	assert_eq!(merge_texts(events), merge_texts(expected));
+}
+
+#[test]
+#[allow(deprecated)]
+fn pullparser_remainder_can_be_recovered_from_bufferqueue() {
+	let mut bq = BufferQueue::new();
+	bq.push(&b"<a xmlns:x='uri:x'/>"[..]);
+	bq.push(&b"trailer"[..]);
+	let mut pp = PullParser::from_queue(bq);
+	while !pp.is_document_complete() {
+		match pp.read() {
+			Ok(Some(_)) => (),
+			other => panic!("unexpected read result: {:?}", other),
+		}
+	}
+	assert_eq!(pp.get_ref().len(), 7);
+	assert_eq!(&pp.into_inner().take_remainder()[..], b"trailer");
+}
+
+#[test]
+fn feedparser_parse_all_str_handles_split_input() {
+	let doc = "<?xml version='1.0'?><a b='ä&amp;ö'>Grüße, 🌍!</a>";
+	let mut fp = FeedParser::default();
+	let mut events = Vec::new();
+	let mut rest = doc;
+	while rest.len() > 0 {
+		let mut n = 1;
+		while !rest.is_char_boundary(n) {
+			n += 1;
+		}
+		let (chunk, tail) = rest.split_at(n);
+		rest = tail;
+		match as_eof_flag(fp.parse_all_str(chunk, false, |ev| events.push(ev))) {
+			Ok(false) => (),
+			other => panic!("unexpected parse result: {:?}", other),
+		}
+	}
+	fp.parse_all_str("", true, |ev| events.push(ev)).unwrap();
+	assert_eq!(events.len(), 4);
+	match &events[1] {
+		ResolvedEvent::StartElement(_, _, attrs, _) => {
+			assert_eq!(&*attrs.values().next().unwrap(), "ä&ö");
+		}
+		other => panic!("unexpected event: {:?}", other),
+	}
+	match &events[2] {
+		ResolvedEvent::Text(_, text) => assert_eq!(text, "Grüße, 🌍!"),
+		other => panic!("unexpected event: {:?}", other),
+	}
+}
+
+#[test]
+fn sliceparser_matches_feedparser() {
+	let docs: &[&[u8]] = &[
+		b"<?xml version='1.0'?>\n<a xmlns='urn:a' xmlns:b='urn:b'><b:c x='1' b:y='2'>text</b:c></a>",
+		b"<a>foo &amp; bar<![CDATA[<baz>]]>\r\nfnord</a>",
+		b"<a><b/><c>\xc3\xa4\xc3\xb6</c></a>",
+		b"<?xml version='1.0'?>  <a>]</a>",
+	];
+	for doc in docs {
+		let expected = merge_texts(parse_events(doc).unwrap());
+		let mut events = Vec::new();
+		SliceParser::new(doc)
+			.read_all(|ev| events.push(ev.into_owned()))
+			.unwrap();
+		assert_eq!(merge_texts(events), expected);
+	}
+}
+
+#[test]
+fn sliceparser_borrows_literal_text() {
+	let doc = b"<p:a xmlns:p='urn:p'><b attr='x'>hello</b></p:a>";
+	let mut events = Vec::new();
+	SliceParser::new(doc)
+		.read_all(|ev| events.push(ev))
+		.unwrap();
+	let mut names = Vec::new();
+	for ev in events.iter() {
+		match ev {
+			BorrowedEvent::StartElement(_, (_, name), _, _) => names.push(name.to_string()),
+			BorrowedEvent::Text(_, text) => {
+				assert!(matches!(text, Cow::Borrowed(_)));
+				assert_eq!(&**text, "hello");
+			}
+			_ => (),
+		}
+	}
+	assert_eq!(names, vec!["a", "b"]);
+}
+
+#[test]
+fn sliceparser_leaves_data_after_document() {
+	let mut sp = SliceParser::new(b"<a/><b/>");
+	while sp.read().unwrap().is_some() {
+		if sp.get_parser().is_document_complete() {
+			break;
+		}
+	}
+	assert_eq!(sp.remainder(), b"<b/>");
+}
+
+#[test]
+fn sliceparser_reports_errors() {
+	let mut sp = SliceParser::new(b"<a>foo");
+	match sp.read_all(|_| ()) {
+		Err(Error::Xml(error::XmlError::InvalidEof(_))) => (),
+		other => panic!("unexpected result: {:?}", other),
+	}
+}
+
+#[test]
+fn feedparser_parse_all_str_still_rejects_invalid_chars() {
+	let mut fp = FeedParser::default();
+	match fp.parse_all_str("<a>\u{1}</a>", true, |_| ()) {
+		Err(Error::Xml(_)) => (),
+		other => panic!("unexpected parse result: {:?}", other),
+	}
+}
+
+#[test]
+fn located_error_marks_offending_byte() {
+	let doc = b"<a>\n<b x='1' x='2'/></a>";
+	let mut fp = FeedParser::default();
+	let err = fp.parse_all(&mut &doc[..], true, |_| ()).unwrap_err();
+	let err = error::LocatedError::new(err, &doc[..], fp.get_lexer().position());
+	// the duplicate is detected at the end of the element header
+	assert_eq!(err.offset(), 20);
+	assert_eq!(err.excerpt(), "<a> <b x='1' x='2'/></a>");
+	assert_eq!(
+		err.to_string(),
+		"xml error: duplicate attribute at byte 20\n    <a> <b x='1' x='2'/></a>\n                       ^"
+	);
+	match err.into_error() {
+		Error::Xml(error::XmlError::DuplicateAttribute) => (),
+		other => panic!("unexpected error: {:?}", other),
+	}
+}
+
+#[test]
+fn located_error_excerpt_is_bounded() {
+	let mut doc = vec![b'<', b'a', b'>'];
+	doc.extend(std::iter::repeat(b'x').take(1000));
+	doc.extend_from_slice(b"\x01");
+	doc.extend(std::iter::repeat(b'y').take(1000));
+	let mut fp = FeedParser::default();
+	let err = fp.parse_all(&mut &doc[..], true, |_| ()).unwrap_err();
+	let err = error::LocatedError::new(err, &doc[..], fp.get_lexer().position());
+	assert_eq!(err.offset(), 1004);
+	let excerpt = err.excerpt();
+	assert!(excerpt.len() <= 64);
+	assert!(excerpt.starts_with("xxx"));
+	assert!(excerpt.ends_with("yyy"));
+}
+
+#[test]
+fn located_error_handles_offset_beyond_input() {
+	let err = error::LocatedError::new(Error::RestrictedXml("foo"), b"<a>", 100);
+	assert_eq!(err.excerpt(), "<a>");
+	assert_eq!(
+		err.to_string(),
+		"restricted xml: foo at byte 100\n    <a>\n       ^"
+	);
+	let err = error::LocatedError::new(Error::RestrictedXml("foo"), b"", 0);
+	assert_eq!(err.excerpt(), "");
+	assert_eq!(err.to_string(), "restricted xml: foo at byte 0");
+}
+
+#[cfg(feature = "miette")]
+#[test]
+fn source_error_labels_error_location_and_element() {
+	use miette::Diagnostic;
+
+	let doc = b"<a>\n<b x:y='1'/></a>";
+	let mut fp = FeedParser::default();
+	let err = fp.parse_all(&mut &doc[..], true, |_| ()).unwrap_err();
+	let err = error::SourceError::new(err, &doc[..], fp.get_lexer().position());
+	assert_eq!(
+		err.code().unwrap().to_string(),
+		"rxml::undeclared_namespace_prefix"
+	);
+	let labels: Vec<_> = err.labels().unwrap().collect();
+	assert_eq!(labels.len(), 2);
+	assert_eq!(labels[0].label(), Some("here"));
+	assert_eq!(labels[0].offset(), err.offset() - 1);
+	assert_eq!(labels[1].label(), Some("in this element"));
+	assert_eq!(labels[1].offset(), 4);
+	assert_eq!(doc[labels[1].offset()], b'<');
+	assert!(err.source_code().is_some());
+
+	let err = error::SourceError::new(Error::RestrictedXml("foo"), b"<a>", 100);
+	assert!(err.help().is_some());
+	let labels: Vec<_> = err.labels().unwrap().collect();
+	assert_eq!(labels.len(), 1);
+	assert_eq!(labels[0].offset(), 3);
+	assert_eq!(labels[0].len(), 0);
+}
+
+#[test]
+fn error_codes_are_unique_and_roundtrip() {
+	use error::ErrorCode;
+	use std::collections::HashSet;
+
+	let mut values = HashSet::new();
+	let mut names = HashSet::new();
+	for code in ErrorCode::ALL.iter() {
+		assert!(values.insert(code.as_u32()), "duplicate value: {:?}", code);
+		assert!(names.insert(code.name()), "duplicate name: {:?}", code);
+		assert_eq!(ErrorCode::from_u32(code.as_u32()), Some(*code));
+	}
+	assert_eq!(ErrorCode::from_u32(0), None);
+}
+
+#[test]
+fn error_codes_are_stable() {
+	use error::ErrorCode;
+
+	// these values must never change
+	assert_eq!(ErrorCode::Io.as_u32(), 1);
+	assert_eq!(ErrorCode::RestrictedXml.as_u32(), 4);
+	assert_eq!(ErrorCode::InvalidEof.as_u32(), 100);
+	assert_eq!(ErrorCode::EmptyNamespaceUri.as_u32(), 115);
+	assert_eq!(ErrorCode::DuplicateXmlId.as_u32(), 117);
+	assert_eq!(ErrorCode::MisplacedXmlDeclaration.as_u32(), 200);
+	assert_eq!(ErrorCode::InvalidProcessingInstruction.as_u32(), 210);
+	assert_eq!(
+		ErrorCode::UndeclaredNamespacePrefix.name(),
+		"undeclared_namespace_prefix"
+	);
+}
+
+#[test]
+fn one_shot_parse_functions_require_complete_documents() {
+	let events = parse_events(b"<?xml version='1.0'?>\n<a>foo<b/></a>").unwrap();
+	assert_eq!(events.len(), 6);
+	match parse_events(b"<a>foo") {
+		Err(Error::Xml(error::XmlError::InvalidEof(_))) => (),
+		other => panic!("unexpected result: {:?}", other),
+	}
+	match parse_events(b"") {
+		Err(Error::Xml(error::XmlError::InvalidEof(_))) => (),
+		other => panic!("unexpected result: {:?}", other),
+	}
+
+	let root = parse_tree("<a xmlns='urn:x'><b>foo</b></a>").unwrap();
+	assert_eq!(root.name.0.as_ref().unwrap().as_str(), "urn:x");
+	assert_eq!(root.children.len(), 1);
+	match parse_tree("<a></b>") {
+		Err(Error::Xml(error::XmlError::ElementMismatch)) => (),
+		other => panic!("unexpected result: {:?}", other),
+	}
+	match parse_tree("<a/><b/>") {
+		Err(_) => (),
+		other => panic!("unexpected result: {:?}", other),
+	}
+}
+
+#[test]
+fn validate_reports_located_errors() {
+	assert!(
+		validate(b"<?xml version='1.0'?>\n<a xmlns:p='urn:p' p:x='1'>foo &amp; <p:b/></a>").is_ok()
+	);
+
+	let doc = b"<?xml version='1.0'?>\n<root><child></root>";
+	let err = validate(&doc[..]).unwrap_err();
+	assert_eq!(err.offset(), 42);
+	match err.error() {
+		Error::Xml(error::XmlError::ElementMismatch) => (),
+		other => panic!("unexpected error: {:?}", other),
+	}
+
+	let err = validate(b"<a p:x='1'/>").unwrap_err();
+	match err.error() {
+		Error::Namespace(e) if e.code() == error::ErrorCode::UndeclaredNamespacePrefix => (),
+		other => panic!("unexpected error: {:?}", other),
+	}
+
+	let err = validate(b"<a>").unwrap_err();
+	assert_eq!(err.offset(), 3);
+	match err.error() {
+		Error::Xml(error::XmlError::InvalidEof(_)) => (),
+		other => panic!("unexpected error: {:?}", other),
+	}
+}
+
+#[test]
+fn recorded_tokens_can_be_replayed_into_a_parser() {
+	struct Replay(std::vec::IntoIter<Token>);
+
+	impl TokenRead for Replay {
+		fn read(&mut self) -> Result<Option<Token>> {
+			Ok(self.0.next())
+		}
+	}
+
+	let doc = &b"<?xml version='1.0'?>\n<a xmlns='urn:x' b='c'>d<e/></a>"[..];
+	let mut lexer = Lexer::new();
+	let mut buf = doc;
+	let mut tokens = Vec::new();
+	{
+		let mut source = BufferLexerAdapter::new(&mut lexer, &mut buf, true);
+		while let Some(tok) = source.read().unwrap() {
+			let m = tok.metrics();
+			assert!(m.start() <= m.end() && m.end() <= doc.len());
+			tokens.push(tok);
+		}
+	}
+	assert!(buf.is_empty());
+
+	let mut replay = Replay(tokens.into_iter());
+	let mut parser = Parser::default();
+	let mut events = Vec::new();
+	// also exercises the implementation for &mut R
+	while let Some(ev) = parser.parse(&mut &mut replay).unwrap() {
+		events.push(ev);
+	}
+	assert_eq!(events, parse_events(doc).unwrap());
+}
+
+#[test]
+fn parser_treats_whitespace_tokens_as_text() {
+	let doc = &b"<?xml version='1.0'?>\n<a>\n\t<b> </b>\n</a>\n"[..];
+	let mut lexer = Lexer::with_options(LexerOptions::default().whitespace_tokens(true));
+	let mut buf = doc;
+	let mut source = BufferLexerAdapter::new(&mut lexer, &mut buf, true);
+	let mut parser = Parser::default();
+	let mut events = Vec::new();
+	while let Some(ev) = parser.parse(&mut source).unwrap() {
+		events.push(ev);
+	}
+	assert_eq!(events, parse_events(doc).unwrap());
+}
+
+#[test]
+fn lazy_attribute_values_match_eager_ones() {
+	let doc = &b"<a xmlns='urn:&#x61;' xmlns:p='urn:p&amp;q' x='1 &lt; 2' p:y='&quot;' xml:id='i&#100;'><b z='&apos;'/></a>"[..];
+	let mut fp = FeedParser::wrap(
+		Lexer::with_options(LexerOptions::default().lazy_attribute_values(true)),
+		Parser::default(),
+	);
+	fp.get_parser_mut().set_check_xml_ids(true);
+	let mut events = Vec::new();
+	fp.parse_all(&mut &doc[..], true, |ev| events.push(ev))
+		.unwrap();
+	match &events[0] {
+		ResolvedEvent::StartElement(_, (ns, _), attrs, _) => {
+			// namespace declarations are always expanded
+			assert_eq!(ns.as_ref().unwrap().as_str(), "urn:a");
+			assert!(attrs.has_unexpanded_references());
+			assert_eq!(&*attrs.attr(None, "x").unwrap(), "1 < 2");
+			assert_eq!(&*attrs.get_by_name(Some("urn:p&q"), "y").unwrap(), "\"");
+		}
+		other => panic!("unexpected event: {:?}", other),
+	}
+	// comparisons use the expanded values
+	assert_eq!(events, parse_events(doc).unwrap());
+
+	let mut encoder = Encoder::new();
+	let mut out = Vec::new();
+	for ev in events.iter() {
+		encoder.encode_event(ev, &mut out).unwrap();
+	}
+	// the writer escapes the expanded values
+	match (&parse_events(&out).unwrap()[0], &events[0]) {
+		(
+			ResolvedEvent::StartElement(_, name, attrs, _),
+			ResolvedEvent::StartElement(_, expected_name, expected_attrs, _),
+		) => {
+			assert_eq!(name, expected_name);
+			assert_eq!(attrs, expected_attrs);
+		}
+		other => panic!("unexpected events: {:?}", other),
+	}
+}
+
+#[test]
+fn lazy_attribute_values_are_checked_as_xml_ids() {
+	let doc = &b"<a xml:id='x'><b xml:id='&#x78;'/></a>"[..];
+	let mut fp = FeedParser::wrap(
+		Lexer::with_options(LexerOptions::default().lazy_attribute_values(true)),
+		Parser::default(),
+	);
+	fp.get_parser_mut().set_check_xml_ids(true);
+	match fp.parse_all(&mut &doc[..], true, |_| ()) {
+		Err(Error::Xml(error::XmlError::DuplicateXmlId)) => (),
+		other => panic!("unexpected result: {:?}", other),
+	}
+}
+
+#[test]
+fn cloned_feed_parser_continues_independently() {
+	let doc = &b"<?xml version='1.0'?>\n<a xmlns='urn:x' xmlns:p='urn:p'><p:b c='d&amp;e'>f&#x67;</p:b><![CDATA[h]]></a>"[..];
+	let expected = parse_events(doc).unwrap();
+	for split in 0..doc.len() {
+		let mut fp = FeedParser::default();
+		let mut head = Vec::new();
+		as_eof_flag(fp.parse_all(&mut &doc[..split], false, |ev| head.push(ev))).unwrap();
+		let mut fork = fp.clone();
+		// the original must not affect the fork
+		let _ = fp.parse_all(&mut &b"</z>"[..], true, |_| ());
+		let mut events = head;
+		fork.parse_all(&mut &doc[split..], true, |ev| events.push(ev))
+			.unwrap();
+		assert_eq!(
+			merge_texts(events),
+			merge_texts(expected.clone()),
+			"split at {}",
+			split
+		);
+	}
+}
+
+#[test]
+fn xml_id_checks_are_opt_in() {
+	let doc = b"<a xml:id='x'><b xml:id='x'/><c xml:id='1'/></a>";
+	let mut fp = FeedParser::default();
+	fp.parse_all(&mut &doc[..], true, |_| ()).unwrap();
+}
+
+#[test]
+fn xml_id_checks_reject_invalid_and_duplicate_values() {
+	let cases: &[(&[u8], Option<error::XmlError>)] = &[
+		(
+			b"<a xml:id='x'><b xml:id='y'/><c id='x' xml:idx='x'/></a>",
+			None,
+		),
+		(
+			b"<a xmlns:foo='urn:foo' xml:id='x'><b foo:id='x'/></a>",
+			None,
+		),
+		(
+			b"<a xml:id='x'><b xml:id='x'/></a>",
+			Some(error::XmlError::DuplicateXmlId),
+		),
+		(
+			b"<a><b xml:id='x'/><b xml:id='x'/></a>",
+			Some(error::XmlError::DuplicateXmlId),
+		),
+		(b"<a xml:id='1x'/>", Some(error::XmlError::InvalidXmlId)),
+		(
+			b"<a xml:id='foo:bar'/>",
+			Some(error::XmlError::InvalidXmlId),
+		),
+		(b"<a xml:id=' foo '/>", None),
+		(
+			b"<a xml:id=' foo '><b xml:id='foo'/></a>",
+			Some(error::XmlError::DuplicateXmlId),
+		),
+		(b"<a xml:id='f oo'/>", Some(error::XmlError::InvalidXmlId)),
+		(b"<a xml:id='  '/>", Some(error::XmlError::InvalidXmlId)),
+		(b"<a xml:id='&#x9;x'/>", Some(error::XmlError::InvalidXmlId)),
+	];
+	for (doc, expected) in cases.iter() {
+		let mut fp = FeedParser::default();
+		fp.get_parser_mut().set_check_xml_ids(true);
+		let result = fp.parse_all(&mut &doc[..], true, |_| ());
+		let mut sink = CollectingSink::default();
+		let mut fp_into = FeedParser::default();
+		fp_into.get_parser_mut().set_check_xml_ids(true);
+		let result_into = fp_into.parse_all_into(&mut &doc[..], true, &mut sink);
+		for result in [result, result_into].iter() {
+			match (result, expected) {
+				(Ok(()), None) => (),
+				(Err(Error::Xml(e)), Some(expected)) if e == expected => (),
+				(other, _) => panic!(
+					"unexpected result for {:?}: {:?}",
+					std::str::from_utf8(doc).unwrap(),
+					other
+				),
+			}
+		}
+	}
+}
+
+#[test]
+fn errors_map_to_codes() {
+	use error::ErrorCode;
+
+	let cases: &[(&[u8], ErrorCode)] = &[
+		(b"<a>", ErrorCode::InvalidEof),
+		(b"<a>\xff</a>", ErrorCode::InvalidUtf8Byte),
+		(b"<a x:y='z'/>", ErrorCode::UndeclaredNamespacePrefix),
+		(
+			b"<?xml version='1.0'?><?xml-stylesheet?><a/>",
+			ErrorCode::RestrictedXml,
+		),
+		(b"<a>&foo;</a>", ErrorCode::UndeclaredEntity),
+	];
+	for (doc, code) in cases.iter() {
+		let mut fp = FeedParser::default();
+		let err = fp.parse_all(&mut &doc[..], true, |_| ()).unwrap_err();
+		assert_eq!(err.code(), *code, "for {:?}: {:?}", doc, err);
+	}
+	assert_eq!(
+		writer::EncodeError::NoOpenElement.code(),
+		ErrorCode::NoOpenElement
+	);
+}
+
+#[test]
+fn namespace_errors_carry_details() {
+	let cases: &[(&[u8], error::XmlError, Option<&str>, &str, u64, &str)] = &[
+		(
+			b"<a><b x:y='z'/></a>",
+			error::XmlError::UndeclaredNamespacePrefix(errctx::ERRCTX_ATTNAME),
+			Some("x"),
+			"x:y",
+			3,
+			"undeclared namespace prefix: prefix `x` of attribute `x:y` at byte 3",
+		),
+		(
+			b"<a xmlns:xml='urn:x'/>",
+			error::XmlError::ReservedNamespacePrefix,
+			Some("xml"),
+			"xmlns:xml",
+			0,
+			"reserved namespace prefix: prefix `xml` of attribute `xmlns:xml` at byte 0",
+		),
+		(
+			b"<a>text<b xmlns:p=''/></a>",
+			error::XmlError::EmptyNamespaceUri,
+			Some("p"),
+			"xmlns:p",
+			7,
+			"namespace URI is empty: prefix `p` of attribute `xmlns:p` at byte 7",
+		),
+		(
+			b"<a xmlns='http://www.w3.org/XML/1998/namespace'/>",
+			error::XmlError::ReservedNamespaceName,
+			None,
+			"xmlns",
+			0,
+			"reserved namespace URI: attribute `xmlns` at byte 0",
+		),
+	];
+	for (doc, kind, prefix, name, position, message) in cases.iter() {
+		let mut fp = FeedParser::default();
+		match fp.parse_all(&mut &doc[..], true, |_| ()) {
+			Err(Error::Namespace(e)) => {
+				assert_eq!(e.error(), *kind);
+				assert_eq!(e.prefix().map(|x| &**x), *prefix);
+				assert_eq!(e.name().unwrap(), *name);
+				assert!(e.is_attribute());
+				assert_eq!(e.position(), Some(*position));
+				assert_eq!(e.to_string(), *message);
+			}
+			other => panic!("unexpected result for {:?}: {:?}", doc, other),
+		}
+	}
+}
+
+#[test]
+fn error_classification() {
+	let mut fp = FeedParser::default();
+	let err = fp.parse_all(&mut &b"<a>"[..], false, |_| ()).unwrap_err();
+	assert!(err.is_io());
+	assert!(err.is_would_block());
+	assert!(!err.is_fatal());
+	assert!(!err.is_wellformedness());
+	assert!(!err.is_restriction());
+
+	let err = Error::io(io::Error::new(io::ErrorKind::ConnectionReset, "reset"));
+	assert!(err.is_io());
+	assert!(!err.is_would_block());
+	assert!(!err.is_fatal());
+
+	for doc in [&b"<a></b>"[..], &b"<a>\xff</a>"[..], &b"<a x:y='z'/>"[..]].iter() {
+		let mut fp = FeedParser::default();
+		let err = fp.parse_all(&mut &doc[..], true, |_| ()).unwrap_err();
+		assert!(err.is_wellformedness(), "{:?}", err);
+		assert!(err.is_fatal());
+		assert!(!err.is_io());
+		assert!(!err.is_restriction());
+	}
+
+	let mut fp = FeedParser::default();
+	let err = fp
+		.parse_all(&mut &b"<?xml version='1.0'?><?foo?><a/>"[..], true, |_| ())
+		.unwrap_err();
+	assert!(err.is_restriction(), "{:?}", err);
+	assert!(err.is_fatal());
+	assert!(!err.is_wellformedness());
+}
+
 #[test]
 fn pullparser_can_read_xml_document() {
 	let mut doc = &b"<?xml version='1.0'?>\n<root xmlns='urn:uuid:fab98e86-7c09-477c-889c-0313d9877bb4' a=\"foo\" b='bar'><child>with some text</child></root>\n"[..];
@@ -226,7 +936,7 @@ fn pullparser_can_read_xml_document() {
 			other => panic!("unexpected event: {:?}", other),
 		};
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localname), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localname), attrs, _) => {
 				// note: 77 because of the \n between xml decl and whitespace. see also comment on EventMetrics
 				assert_eq!(em.len(), 77);
 				assert_eq!(
@@ -236,18 +946,18 @@ fn pullparser_can_read_xml_document() {
 				assert_eq!(localname, "root");
 				assert_eq!(attrs.len(), 2);
 				assert_eq!(
-					attrs.get(&(None, NcName::try_from("a").unwrap())).unwrap(),
+					&*attrs.get(&(None, NcName::try_from("a").unwrap())).unwrap(),
 					"foo"
 				);
 				assert_eq!(
-					attrs.get(&(None, NcName::try_from("b").unwrap())).unwrap(),
+					&*attrs.get(&(None, NcName::try_from("b").unwrap())).unwrap(),
 					"bar"
 				);
 			}
 			other => panic!("unexpected event: {:?}", other),
 		};
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localname), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localname), attrs, _) => {
 				assert_eq!(em.len(), 7);
 				assert_eq!(
 					nsuri.as_ref().unwrap().as_str(),
@@ -293,6 +1003,139 @@ fn run_fuzz_test(mut data: &[u8]) -> Result<()> {
 	}
 }
 
+#[cfg(feature = "macros")]
+#[test]
+fn xml_macro_produces_encodable_items() {
+	let to = CData::try_from("juliet@example.com").unwrap();
+	let text = CData::try_from("a < b").unwrap();
+	let items = xml!(
+		r#"<?xml version="1.0" encoding="UTF-8"?>
+		<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'>
+			<message to='{}' xml:lang='en'><body>{} &amp; {{c}} <![CDATA[<d>]]></body><x xmlns=''/></message>
+		</stream:stream>"#,
+		&to,
+		&text,
+	);
+	let mut enc = Encoder::new();
+	let mut buf = Vec::new();
+	for item in items {
+		enc.encode(item, &mut buf).unwrap();
+	}
+	assert_eq!(
+		std::str::from_utf8(&buf).unwrap(),
+		"<?xml version='1.0' encoding='utf-8'?>\n<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'>\n\t\t\t<message to=\"juliet@example.com\" xml:lang=\"en\"><body>a &lt; b &amp; {c} &lt;d&gt;</body><x xmlns=''/></message>\n\t\t</stream:stream>"
+	);
+}
+
+#[cfg(feature = "macros")]
+mod derive_mapping {
+	use crate::mapping::{self, FromXmlError};
+	use crate::{FromXml, IntoXml};
+
+	#[derive(FromXml, IntoXml, Debug, PartialEq)]
+	#[xml(namespace = "urn:example:feed", name = "entry")]
+	struct Entry {
+		#[xml(attribute)]
+		id: u64,
+		#[xml(attribute(namespace = "urn:example:meta", name = "rank"))]
+		rank: Option<i32>,
+		#[xml(text)]
+		summary: String,
+	}
+
+	#[derive(FromXml, IntoXml, Debug, PartialEq)]
+	#[xml(namespace = "urn:example:feed", name = "feed")]
+	struct Feed {
+		#[xml(element)]
+		title: String,
+		#[xml(element(namespace = "urn:example:meta", name = "updated"))]
+		updated: Option<u32>,
+		#[xml(child)]
+		entries: Vec<Entry>,
+	}
+
+	#[test]
+	fn roundtrip() {
+		let feed = Feed {
+			title: "News & Views".to_string(),
+			updated: Some(1234),
+			entries: vec![
+				Entry {
+					id: 1,
+					rank: Some(-3),
+					summary: "first".to_string(),
+				},
+				Entry {
+					id: 2,
+					rank: None,
+					summary: "second".to_string(),
+				},
+			],
+		};
+		let xml = mapping::to_vec(&feed).unwrap();
+		assert_eq!(
+			std::str::from_utf8(&xml).unwrap(),
+			"<feed xmlns='urn:example:feed'><title>News &amp; Views</title><updated xmlns='urn:example:meta'>1234</updated><entry id=\"1\" xmlns:tns0='urn:example:meta' tns0:rank=\"-3\">first</entry><entry id=\"2\">second</entry></feed>"
+		);
+		let parsed: Feed = mapping::from_str(std::str::from_utf8(&xml).unwrap()).unwrap();
+		assert_eq!(parsed, feed);
+	}
+
+	#[test]
+	fn ignores_unknown_content() {
+		let parsed: Feed = mapping::from_str(
+			"<feed xmlns='urn:example:feed' foo='bar'><other/><title>t</title><entry id='7'>x<b/>y</entry></feed>",
+		)
+		.unwrap();
+		assert_eq!(
+			parsed,
+			Feed {
+				title: "t".to_string(),
+				updated: None,
+				entries: vec![Entry {
+					id: 7,
+					rank: None,
+					summary: "xy".to_string(),
+				}],
+			}
+		);
+	}
+
+	#[test]
+	fn rejects_wrong_root() {
+		match mapping::from_str::<Feed>("<feed xmlns='urn:example:other'/>") {
+			Err(FromXmlError::Mismatch(el)) => assert_eq!(el.name.1, "feed"),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn reports_missing_and_duplicate_content() {
+		match mapping::from_str::<Feed>("<feed xmlns='urn:example:feed'/>") {
+			Err(FromXmlError::MissingElement("title")) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match mapping::from_str::<Feed>(
+			"<feed xmlns='urn:example:feed'><title>a</title><title>b</title></feed>",
+		) {
+			Err(FromXmlError::DuplicateElement("title")) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		match mapping::from_str::<Entry>("<entry xmlns='urn:example:feed'/>") {
+			Err(FromXmlError::MissingAttribute("id")) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn reports_invalid_values() {
+		match mapping::from_str::<Entry>("<entry xmlns='urn:example:feed' id='x'/>") {
+			Err(FromXmlError::InvalidText { name: "id", .. }) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+}
+
 #[cfg(feature = "async")]
 #[tokio::test]
 async fn asyncparser_can_read_xml_document() {
@@ -317,7 +1160,7 @@ async fn asyncparser_can_read_xml_document() {
 			other => panic!("unexpected event: {:?}", other),
 		};
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localname), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localname), attrs, _) => {
 				// note: 77 because of the \n between xml decl and whitespace. see also comment on EventMetrics
 				assert_eq!(em.len(), 77);
 				assert_eq!(
@@ -327,18 +1170,18 @@ async fn asyncparser_can_read_xml_document() {
 				assert_eq!(localname, "root");
 				assert_eq!(attrs.len(), 2);
 				assert_eq!(
-					attrs.get(&(None, NcName::try_from("a").unwrap())).unwrap(),
+					&*attrs.get(&(None, NcName::try_from("a").unwrap())).unwrap(),
 					"foo"
 				);
 				assert_eq!(
-					attrs.get(&(None, NcName::try_from("b").unwrap())).unwrap(),
+					&*attrs.get(&(None, NcName::try_from("b").unwrap())).unwrap(),
 					"bar"
 				);
 			}
 			other => panic!("unexpected event: {:?}", other),
 		};
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localname), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localname), attrs, _) => {
 				assert_eq!(em.len(), 7);
 				assert_eq!(
 					nsuri.as_ref().unwrap().as_str(),
@@ -396,7 +1239,7 @@ async fn asyncparser_can_handle_chunked_input() {
 			other => panic!("unexpected event: {:?}", other),
 		};
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localname), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localname), attrs, _) => {
 				// note: 77 because of the \n between xml decl and whitespace. see also comment on EventMetrics
 				assert_eq!(em.len(), 77);
 				assert_eq!(
@@ -406,18 +1249,18 @@ async fn asyncparser_can_handle_chunked_input() {
 				assert_eq!(localname, "root");
 				assert_eq!(attrs.len(), 2);
 				assert_eq!(
-					attrs.get(&(None, NcName::try_from("a").unwrap())).unwrap(),
+					&*attrs.get(&(None, NcName::try_from("a").unwrap())).unwrap(),
 					"foo"
 				);
 				assert_eq!(
-					attrs.get(&(None, NcName::try_from("b").unwrap())).unwrap(),
+					&*attrs.get(&(None, NcName::try_from("b").unwrap())).unwrap(),
 					"bar"
 				);
 			}
 			other => panic!("unexpected event: {:?}", other),
 		};
 		match iter.next().unwrap() {
-			ResolvedEvent::StartElement(em, (nsuri, localname), attrs) => {
+			ResolvedEvent::StartElement(em, (nsuri, localname), attrs, _) => {
 				assert_eq!(em.len(), 7);
 				assert_eq!(
 					nsuri.as_ref().unwrap().as_str(),
@@ -449,3 +1292,1178 @@ async fn asyncparser_can_handle_chunked_input() {
 		};
 	}
 }
+
+/// futures-io reader which hands out at most `chunk` bytes at a time and
+/// returns `Pending` before each chunk.
+#[cfg(feature = "futures")]
+struct ChunkedFuturesReader<'x> {
+	data: &'x [u8],
+	chunk: usize,
+	ready: bool,
+}
+
+#[cfg(feature = "futures")]
+impl<'x> futures_io::AsyncRead for ChunkedFuturesReader<'x> {
+	fn poll_read(
+		self: std::pin::Pin<&mut Self>,
+		_cx: &mut std::task::Context<'_>,
+		_buf: &mut [u8],
+	) -> std::task::Poll<io::Result<usize>> {
+		unreachable!()
+	}
+}
+
+#[cfg(feature = "futures")]
+impl<'x> futures_io::AsyncBufRead for ChunkedFuturesReader<'x> {
+	fn poll_fill_buf(
+		self: std::pin::Pin<&mut Self>,
+		cx: &mut std::task::Context<'_>,
+	) -> std::task::Poll<io::Result<&[u8]>> {
+		let this = self.get_mut();
+		if !this.ready {
+			this.ready = true;
+			cx.waker().wake_by_ref();
+			return std::task::Poll::Pending;
+		}
+		let n = this.chunk.min(this.data.len());
+		std::task::Poll::Ready(Ok(&this.data[..n]))
+	}
+
+	fn consume(self: std::pin::Pin<&mut Self>, amt: usize) {
+		let this = self.get_mut();
+		this.data = &this.data[amt..];
+		if amt > 0 {
+			this.ready = false;
+		}
+	}
+}
+
+#[cfg(feature = "futures")]
+#[tokio::test]
+async fn asyncparser_can_read_from_futures_io() {
+	let doc = "<?xml version='1.0'?>\n<root a='foo'><child>with some text🐱😸😹</child></root>"
+		.as_bytes();
+
+	let r = ChunkedFuturesReader {
+		data: doc,
+		chunk: 3,
+		ready: false,
+	};
+	let mut ap = AsyncParser::new(FuturesIo::new(r));
+	let mut out = Vec::<ResolvedEvent>::new();
+	let result = ap
+		.read_all(|ev| {
+			out.push(ev);
+		})
+		.await;
+	result.unwrap();
+
+	let mut iter = out.iter();
+	match iter.next().unwrap() {
+		ResolvedEvent::XmlDeclaration(_, XmlVersion::V1_0) => (),
+		other => panic!("unexpected event: {:?}", other),
+	};
+	match iter.next().unwrap() {
+		ResolvedEvent::StartElement(_, (None, localname), attrs, _) => {
+			assert_eq!(localname, "root");
+			assert_eq!(
+				&*attrs.get(&(None, NcName::try_from("a").unwrap())).unwrap(),
+				"foo"
+			);
+		}
+		other => panic!("unexpected event: {:?}", other),
+	};
+	match iter.next().unwrap() {
+		ResolvedEvent::StartElement(_, (None, localname), _, _) => {
+			assert_eq!(localname, "child");
+		}
+		other => panic!("unexpected event: {:?}", other),
+	};
+	let mut text = String::new();
+	loop {
+		match iter.next().unwrap() {
+			ResolvedEvent::Text(_, cdata) => text.push_str(cdata),
+			ResolvedEvent::EndElement(_) => break,
+			other => panic!("unexpected event: {:?}", other),
+		}
+	}
+	assert_eq!(text, "with some text🐱😸😹");
+	match iter.next().unwrap() {
+		ResolvedEvent::EndElement(_) => (),
+		other => panic!("unexpected event: {:?}", other),
+	};
+	assert!(iter.next().is_none());
+
+	let (r, _, _) = ap.into_inner();
+	assert_eq!(r.into_inner().data.len(), 0);
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn asyncparser_can_read_from_channel() {
+	let doc = "<root a='foo'>with some text🐱😸😹<child/></root>".as_bytes();
+	let (tx, rx) = tokio::sync::mpsc::channel::<Vec<u8>>(2);
+	let mut ap = AsyncParser::from_channel(rx);
+	let producer = tokio::spawn(async move {
+		// chunks of three bytes split both markup and multibyte characters
+		for chunk in doc.chunks(3) {
+			tx.send(chunk.to_vec()).await.unwrap();
+		}
+	});
+	let mut out = Vec::<ResolvedEvent>::new();
+	ap.read_all(|ev| out.push(ev)).await.unwrap();
+	producer.await.unwrap();
+
+	let mut iter = out.iter();
+	match iter.next().unwrap() {
+		ResolvedEvent::StartElement(_, (None, localname), attrs, _) => {
+			assert_eq!(localname, "root");
+			assert_eq!(
+				&*attrs.get(&(None, NcName::try_from("a").unwrap())).unwrap(),
+				"foo"
+			);
+		}
+		other => panic!("unexpected event: {:?}", other),
+	};
+	let mut text = String::new();
+	loop {
+		match iter.next().unwrap() {
+			ResolvedEvent::Text(_, cdata) => text.push_str(cdata),
+			ResolvedEvent::StartElement(_, (None, localname), _, _) => {
+				assert_eq!(localname, "child");
+				break;
+			}
+			other => panic!("unexpected event: {:?}", other),
+		}
+	}
+	assert_eq!(text, "with some text🐱😸😹");
+	for _ in 0..2 {
+		match iter.next().unwrap() {
+			ResolvedEvent::EndElement(_) => (),
+			other => panic!("unexpected event: {:?}", other),
+		};
+	}
+	assert!(iter.next().is_none());
+
+	let (source, _, _) = ap.into_inner();
+	assert!(source.into_inner().1.is_none());
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn asyncparser_reports_eof_when_channel_closes_early() {
+	let (tx, rx) = tokio::sync::mpsc::channel::<&'static [u8]>(2);
+	let mut ap = AsyncParser::from_channel(rx);
+	tx.send(&b"<root>"[..]).await.unwrap();
+	tx.send(&b"<chi"[..]).await.unwrap();
+	drop(tx);
+	match ap.read_all(|_| ()).await {
+		Err(Error::Xml(error::XmlError::InvalidEof(_))) => (),
+		other => panic!("unexpected result: {:?}", other),
+	}
+}
+
+#[cfg(feature = "stream")]
+#[tokio::test]
+async fn asyncparser_stream_terminates_after_fatal_error() {
+	use futures_core::stream::FusedStream;
+	use futures_util::StreamExt;
+
+	let mut doc = &b"<a></b>"[..];
+	let mut ap = AsyncParser::new(&mut doc);
+	match ap.next().await {
+		Some(Ok(ResolvedEvent::StartElement(..))) => (),
+		other => panic!("unexpected stream item: {:?}", other),
+	}
+	assert!(!ap.is_terminated());
+	match ap.next().await {
+		Some(Err(Error::Xml(_))) => (),
+		other => panic!("unexpected stream item: {:?}", other),
+	}
+	assert!(ap.is_terminated());
+	match ap.next().await {
+		None => (),
+		other => panic!("unexpected stream item: {:?}", other),
+	}
+}
+
+#[cfg(feature = "stream")]
+#[tokio::test]
+async fn asyncparser_stream_ends_at_eof() {
+	use futures_core::stream::FusedStream;
+	use futures_util::StreamExt;
+
+	let mut doc = &b"<a>foo</a>"[..];
+	let ap = AsyncParser::new(&mut doc);
+	let events: Vec<_> = ap.collect().await;
+	assert_eq!(events.len(), 3);
+	assert!(events.iter().all(|ev| ev.is_ok()));
+
+	let mut doc = &b"<a/>"[..];
+	let mut ap = AsyncParser::new(&mut doc);
+	while let Some(ev) = ap.next().await {
+		ev.unwrap();
+	}
+	assert!(ap.is_terminated());
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn asyncparser_returns_transport_from_into_inner() {
+	use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+	let (mut tx, rx) = tokio::io::duplex(64);
+	tx.write_all(b"<a/>").await.unwrap();
+	let mut ap = AsyncParser::new(tokio::io::BufReader::new(rx));
+	match ap.read().await {
+		Ok(Some(ResolvedEvent::StartElement(..))) => (),
+		other => panic!("unexpected event: {:?}", other),
+	}
+	match ap.read().await {
+		Ok(Some(ResolvedEvent::EndElement(..))) => (),
+		other => panic!("unexpected event: {:?}", other),
+	}
+
+	let (rx, _, _) = ap.into_inner();
+	let mut rx = rx.into_inner();
+	tx.write_all(b"trailer").await.unwrap();
+	drop(tx);
+	let mut rest = Vec::new();
+	rx.read_to_end(&mut rest).await.unwrap();
+	assert_eq!(rest, b"trailer");
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn asyncparser_works_with_pinned_non_unpin_source() {
+	pin_project_lite::pin_project! {
+		struct NotUnpin<T> {
+			#[pin]
+			inner: T,
+			#[pin]
+			_pin: std::marker::PhantomPinned,
+		}
+	}
+
+	impl<T: tokio::io::AsyncRead> tokio::io::AsyncRead for NotUnpin<T> {
+		fn poll_read(
+			self: std::pin::Pin<&mut Self>,
+			cx: &mut std::task::Context<'_>,
+			buf: &mut tokio::io::ReadBuf<'_>,
+		) -> std::task::Poll<io::Result<()>> {
+			self.project().inner.poll_read(cx, buf)
+		}
+	}
+
+	impl<T: tokio::io::AsyncBufRead> tokio::io::AsyncBufRead for NotUnpin<T> {
+		fn poll_fill_buf(
+			self: std::pin::Pin<&mut Self>,
+			cx: &mut std::task::Context<'_>,
+		) -> std::task::Poll<io::Result<&[u8]>> {
+			self.project().inner.poll_fill_buf(cx)
+		}
+
+		fn consume(self: std::pin::Pin<&mut Self>, amt: usize) {
+			self.project().inner.consume(amt)
+		}
+	}
+
+	let src = NotUnpin {
+		inner: &b"<a>foo</a>"[..],
+		_pin: std::marker::PhantomPinned,
+	};
+	let mut ap = Box::pin(AsyncParser::new(src));
+	let mut n = 0;
+	while let Some(_) = ap.as_mut().read().await.unwrap() {
+		n += 1;
+	}
+	assert_eq!(n, 3);
+	assert_eq!(ap.as_mut().get_inner_pin_mut().project().inner.len(), 0);
+}
+
+#[cfg(feature = "stream")]
+#[tokio::test]
+async fn asyncparser_can_read_from_byte_stream() {
+	let doc = "<?xml version='1.0'?><root a='foo'>with some text🐱😸😹</root>".as_bytes();
+	// split into chunks which do not respect token or codepoint boundaries
+	let chunks: Vec<io::Result<bytes::Bytes>> = doc
+		.chunks(5)
+		.map(|x| Ok(bytes::Bytes::copy_from_slice(x)))
+		.collect();
+	let mut ap = AsyncParser::from_stream(futures_util::stream::iter(chunks));
+	let mut text = String::new();
+	let mut n = 0;
+	ap.read_all(|ev| {
+		n += 1;
+		if let ResolvedEvent::Text(_, cdata) = ev {
+			text.push_str(&cdata);
+		}
+	})
+	.await
+	.unwrap();
+	assert_eq!(text, "with some text🐱😸😹");
+	assert!(n >= 4);
+}
+
+#[cfg(feature = "stream")]
+#[tokio::test]
+async fn asyncparser_passes_byte_stream_errors_on() {
+	let chunks: Vec<io::Result<bytes::Bytes>> = vec![
+		Ok(bytes::Bytes::from_static(b"<a>")),
+		Err(io::Error::new(io::ErrorKind::ConnectionReset, "reset")),
+	];
+	let mut ap = AsyncParser::from_stream(futures_util::stream::iter(chunks));
+	match ap.read().await {
+		Ok(Some(ResolvedEvent::StartElement(..))) => (),
+		other => panic!("unexpected event: {:?}", other),
+	}
+	match ap.read().await {
+		Err(Error::IO(e)) if e.kind() == io::ErrorKind::ConnectionReset => (),
+		other => panic!("unexpected event: {:?}", other),
+	}
+	// the stream is exhausted now, which is an unexpected eof
+	match ap.read().await {
+		Err(Error::Xml(_)) => (),
+		other => panic!("unexpected event: {:?}", other),
+	}
+}
+
+#[test]
+fn parsers_and_context_are_thread_safe() {
+	fn assert_send<T: Send>() {}
+	fn assert_sync<T: Sync>() {}
+
+	assert_send::<Context>();
+	assert_sync::<Context>();
+	assert_send::<parser::RcPtr<Context>>();
+	assert_sync::<parser::RcPtr<Context>>();
+	assert_send::<Parser>();
+	assert_send::<FeedParser>();
+	assert_send::<PullParser<&[u8]>>();
+	assert_send::<ResolvedEvent>();
+	assert_send::<Encoder<writer::SimpleNamespaces>>();
+	#[cfg(feature = "async")]
+	assert_send::<AsyncParser<&[u8]>>();
+
+	let ctx = parser::RcPtr::new(Context::new());
+	let threads: Vec<_> = (0..4)
+		.map(|_| {
+			let ctx = ctx.clone();
+			std::thread::spawn(move || {
+				let mut fp = FeedParser::wrap(Lexer::new(), Parser::with_context(ctx));
+				let mut doc = &b"<a xmlns='urn:foo'/>"[..];
+				let mut n = 0;
+				as_eof_flag(fp.parse_all(&mut doc, true, |_| n += 1)).unwrap();
+				n
+			})
+		})
+		.collect();
+	for t in threads {
+		assert_eq!(t.join().unwrap(), 2);
+	}
+}
+
+#[test]
+fn registered_namespaces_are_shared_by_parsers() {
+	let ctx = parser::RcPtr::new(Context::new());
+	let client = ctx.register_namespace(CData::try_from("jabber:client").unwrap());
+	let ping = ctx.register_namespace(CData::try_from("urn:xmpp:ping").unwrap());
+	assert_ne!(client, ping);
+	assert_eq!(
+		ctx.register_namespace(CData::try_from("jabber:client").unwrap()),
+		client
+	);
+
+	let doc =
+		&b"<iq xmlns='jabber:client'><ping xmlns='urn:xmpp:ping'/><x xmlns='urn:other'/></iq>"[..];
+	let mut ids = Vec::new();
+	for _ in 0..2 {
+		let mut fp = FeedParser::wrap(Lexer::new(), Parser::with_context(ctx.clone()));
+		fp.parse_all(&mut &doc[..], true, |ev| match ev {
+			ResolvedEvent::StartElement(_, (Some(ns), _), _, _) => {
+				ids.push(NamespaceHandle::id_of(&ns))
+			}
+			_ => (),
+		})
+		.unwrap();
+	}
+	assert_eq!(ids.len(), 6);
+	assert_eq!(ids[0], client.id());
+	assert_eq!(ids[1], ping.id());
+	assert_ne!(ids[2], client.id());
+	assert_ne!(ids[2], ping.id());
+	assert_eq!(&ids[..2], &ids[3..5]);
+	assert!(!client.matches(None));
+	assert!(!client.matches(Some(&parser::RcPtr::new(
+		CData::try_from("jabber:client").unwrap()
+	))));
+}
+
+#[test]
+fn memory_budget_bounds_element_and_namespace_stacks() {
+	let mut doc = String::new();
+	for i in 0..200 {
+		doc.push_str(&format!(
+			"<e{} xmlns:p{}='urn:some:longer:namespace:{}'>",
+			i, i, i
+		));
+	}
+
+	let mut fp = FeedParser::default();
+	let mut events = 0;
+	as_eof_flag(fp.parse_all(&mut doc.as_bytes(), false, |_| events += 1)).unwrap();
+	assert_eq!(events, 200);
+	let used = fp.get_parser().memory_used();
+	assert!(used > 200 * 30);
+
+	let mut fp = FeedParser::default();
+	fp.get_parser_mut().set_memory_budget(Some(used / 2));
+	let mut events = 0;
+	let mut buf = doc.as_bytes();
+	match fp.parse_all(&mut buf, false, |_| events += 1) {
+		Err(err @ Error::MemoryBudgetExceeded(_)) => {
+			assert!(err.is_restriction());
+			assert_eq!(err.code(), error::ErrorCode::MemoryBudgetExceeded);
+		}
+		other => panic!("unexpected result: {:?}", other),
+	}
+	assert!(events > 0 && events < 200);
+	// the parser is dead now, even if the budget is lifted
+	fp.get_parser_mut().set_memory_budget(None);
+	match fp.parse_all(&mut buf, false, |_| ()) {
+		Err(Error::MemoryBudgetExceeded(budget)) => assert_eq!(budget, used / 2),
+		other => panic!("unexpected result: {:?}", other),
+	}
+}
+
+#[test]
+fn memory_budget_covers_tokens_in_progress() {
+	// with eager growth, the token buffer would be allocated at full size
+	// right away
+	let mut fp = FeedParser::wrap(
+		Lexer::with_options(LexerOptions::default().buffer_growth(lexer::BufferGrowth::OnDemand)),
+		Parser::default(),
+	);
+	fp.get_parser_mut().set_memory_budget(Some(1024));
+	let mut doc = &b"<a>"[..];
+	as_eof_flag(fp.parse_all(&mut doc, false, |_| ())).unwrap();
+	let value = "x".repeat(4000);
+	let mut result = Ok(());
+	for chunk in format!("<b attr='{}'/>", value).as_bytes().chunks(100) {
+		result = fp.parse_all(&mut &chunk[..], false, |_| ());
+		if !matches!(&result, Err(e) if e.is_would_block()) {
+			break;
+		}
+	}
+	match result {
+		Err(Error::MemoryBudgetExceeded(1024)) => (),
+		other => panic!("unexpected result: {:?}", other),
+	}
+
+	let mut fp = FeedParser::default();
+	fp.get_parser_mut().set_memory_budget(Some(64 * 1024));
+	let doc = format!("<a><b attr='{}'/>{}</a>", value, value);
+	fp.parse_all(&mut doc.as_bytes(), true, |_| ()).unwrap();
+}
+
+#[test]
+fn raw_capture_matches_completed_children_with_sink() {
+	let doc = &b"<stream xmlns='jabber:client'><message to='x'>some text &amp; more</message>\n<iq type='get'><ping xmlns='urn:xmpp:ping'/></iq></stream>"[..];
+	let mut expected = Vec::new();
+	FeedParser::default()
+		.parse_all_framed(&mut &doc[..], true, |_, range| {
+			expected.extend(range.map(|r| &doc[r.start as usize..r.end as usize]))
+		})
+		.unwrap();
+	assert_eq!(expected.len(), 2);
+
+	let mut fp = FeedParser::wrap(
+		Lexer::with_options(LexerOptions::default().raw_capture_depth(Some(1))),
+		Parser::default(),
+	);
+	let mut sink = CollectingSink::default();
+	let mut captured = Vec::new();
+	for chunk in doc.chunks(4) {
+		as_eof_flag(fp.parse_all_into(&mut &chunk[..], false, &mut sink)).unwrap();
+		captured.extend(fp.get_lexer_mut().take_raw_element());
+	}
+	fp.parse_all_into(&mut &b""[..], true, &mut sink).unwrap();
+	assert_eq!(captured, expected);
+}
+
+#[test]
+fn xmlns_attributes_are_not_encoded_again() {
+	let doc = &b"<a xmlns='urn:a' xmlns:p='urn:p'><p:b p:x='1'/></a>"[..];
+	let mut fp = FeedParser::default();
+	fp.get_parser_mut().set_emit_xmlns_attributes(true);
+	let mut sink = CollectingSink::default();
+	fp.parse_all_into(&mut &doc[..], true, &mut sink).unwrap();
+	match &sink.0[0] {
+		ResolvedEvent::StartElement(_, _, attrs, _) => assert_eq!(attrs.len(), 2),
+		other => panic!("unexpected event: {:?}", other),
+	}
+	let mut encoder = Encoder::new();
+	let mut out = Vec::new();
+	for ev in sink.0.iter() {
+		encoder.encode_event(ev, &mut out).unwrap();
+	}
+	assert_eq!(
+		std::str::from_utf8(&out).unwrap(),
+		"<a xmlns='urn:a'><b xmlns='urn:p' xmlns:tns0='urn:p' tns0:x=\"1\"></b></a>"
+	);
+}
+
+/// Sink which rebuilds the events it is given, for comparison with the
+/// event based API.
+#[derive(Default)]
+struct CollectingSink(Vec<ResolvedEvent>);
+
+impl EventSink for CollectingSink {
+	fn xml_declaration(&mut self, metrics: &parser::EventMetrics, version: XmlVersion) {
+		self.0
+			.push(ResolvedEvent::XmlDeclaration(*metrics, version));
+	}
+
+	fn start_element(
+		&mut self,
+		metrics: &parser::EventMetrics,
+		name: &ResolvedQName,
+		attributes: &Attributes,
+		prefix: Option<&NcNameStr>,
+	) {
+		self.0.push(ResolvedEvent::StartElement(
+			*metrics,
+			name.clone(),
+			attributes.clone(),
+			prefix.map(|x| x.to_ncname()),
+		));
+	}
+
+	fn end_element(&mut self, metrics: &parser::EventMetrics) {
+		self.0.push(ResolvedEvent::EndElement(*metrics));
+	}
+
+	fn text(&mut self, metrics: &parser::EventMetrics, text: &strings::CDataStr) {
+		self.0.push(ResolvedEvent::Text(*metrics, text.to_cdata()));
+	}
+
+	fn start_prefix_mapping(
+		&mut self,
+		metrics: &parser::EventMetrics,
+		prefix: Option<&strings::NcNameStr>,
+		namespace: &parser::NamespaceName,
+	) {
+		self.0.push(ResolvedEvent::StartPrefixMapping(
+			*metrics,
+			prefix.map(|x| x.to_ncname()),
+			namespace.clone(),
+		));
+	}
+
+	fn end_prefix_mapping(
+		&mut self,
+		metrics: &parser::EventMetrics,
+		prefix: Option<&strings::NcNameStr>,
+	) {
+		self.0.push(ResolvedEvent::EndPrefixMapping(
+			*metrics,
+			prefix.map(|x| x.to_ncname()),
+		));
+	}
+}
+
+/// Merge consecutive text events, as the split of text into events is an
+/// implementation detail.
+fn merge_texts(evs: Vec<ResolvedEvent>) -> Vec<ResolvedEvent> {
+	let mut result: Vec<ResolvedEvent> = Vec::new();
+	for ev in evs {
+		match (result.last_mut(), ev) {
+			(Some(ResolvedEvent::Text(m1, t1)), ResolvedEvent::Text(m2, t2)) => {
+				*m1 = parser::EventMetrics::new(m1.len() + m2.len());
+				*t1 = t1.clone() + &*t2;
+			}
+			(_, ev) => result.push(ev),
+		}
+	}
+	result
+}
+
+const SINK_TEST_DOC: &[u8] = b"<?xml version='1.0'?>\n<a xmlns='urn:a' xmlns:b='urn:b' x='1' b:y='2'><b:c z='3' q='4' r='5' s='6' t='7' u='8'>foo &amp; bar</b:c><![CDATA[baz]]><d/></a>";
+
+#[test]
+fn event_sink_receives_same_events_as_parse_all() {
+	for emit_prefix_mappings in [false, true].iter() {
+		let mut fp = FeedParser::default();
+		fp.get_parser_mut()
+			.set_emit_prefix_mappings(*emit_prefix_mappings);
+		let mut expected = Vec::new();
+		fp.parse_all(&mut &SINK_TEST_DOC[..], true, |ev| expected.push(ev))
+			.unwrap();
+
+		let mut fp = FeedParser::default();
+		fp.get_parser_mut()
+			.set_emit_prefix_mappings(*emit_prefix_mappings);
+		let mut sink = CollectingSink::default();
+		fp.parse_all_into(&mut &SINK_TEST_DOC[..], true, &mut sink)
+			.unwrap();
+		assert_eq!(merge_texts(sink.0), merge_texts(expected));
+	}
+}
+
+#[test]
+fn parser_statistics_are_tracked_for_events_and_sinks() {
+	let mut fp = FeedParser::default();
+	fp.get_parser_mut().set_emit_prefix_mappings(true);
+	fp.parse_all(&mut &SINK_TEST_DOC[..], true, |_| ()).unwrap();
+	let stats = fp.get_parser().statistics();
+	assert_eq!(stats.xml_declarations(), 1);
+	assert_eq!(stats.start_elements(), 3);
+	assert_eq!(stats.end_elements(), 3);
+	assert_eq!(stats.start_prefix_mappings(), 2);
+	assert_eq!(stats.end_prefix_mappings(), 2);
+	assert_eq!(stats.text_bytes(), 12);
+	assert_eq!(stats.namespace_declarations(), 2);
+	assert_eq!(stats.max_depth(), 2);
+	assert_eq!(stats.depth(), 0);
+	assert_eq!(stats.events(), 11 + stats.texts());
+
+	// the split of text into events differs between the two paths
+	let mut fp2 = FeedParser::default();
+	fp2.get_parser_mut().set_emit_prefix_mappings(true);
+	let mut sink = CollectingSink::default();
+	for chunk in SINK_TEST_DOC.chunks(5) {
+		let _ = fp2.parse_all_into(&mut &chunk[..], false, &mut sink);
+	}
+	fp2.parse_all_into(&mut &b""[..], true, &mut sink).unwrap();
+	let stats2 = fp2.get_parser().statistics();
+	assert_eq!(stats2.start_elements(), stats.start_elements());
+	assert_eq!(stats2.end_prefix_mappings(), stats.end_prefix_mappings());
+	assert_eq!(stats2.text_bytes(), stats.text_bytes());
+	assert_eq!(
+		stats2.namespace_declarations(),
+		stats.namespace_declarations()
+	);
+	assert_eq!(stats2.max_depth(), stats.max_depth());
+}
+
+#[test]
+fn progress_hook_reports_consumed_bytes() {
+	use std::sync::{Arc, Mutex};
+
+	let reports = Arc::new(Mutex::new(Vec::new()));
+	let mut fp = FeedParser::default();
+	let r = reports.clone();
+	fp.get_parser_mut()
+		.set_progress_hook(50, move |bytes, stats| {
+			r.lock().unwrap().push((bytes, stats.start_elements()))
+		});
+	let mut sink = CollectingSink::default();
+	fp.parse_all_into(&mut &SINK_TEST_DOC[..], true, &mut sink)
+		.unwrap();
+	let reports = reports.lock().unwrap();
+	assert_eq!(reports.len(), 3);
+	for pair in reports.windows(2) {
+		assert!(pair[1].0 >= pair[0].0 + 50 || pair[1].0 == SINK_TEST_DOC.len() as u64);
+	}
+	// the final report happens at the end of the document, even though
+	// fewer than 50 bytes have been consumed since the previous one
+	assert_eq!(*reports.last().unwrap(), (SINK_TEST_DOC.len() as u64, 3));
+	assert_eq!(fp.get_parser().position(), SINK_TEST_DOC.len() as u64);
+}
+
+#[test]
+fn position_counts_adjacent_element_end_and_start_once() {
+	let doc = b"<a><b>x</b><c/><d></d></a>";
+	let mut pp = PullParser::new(&doc[..]);
+	pp.read_all(|_| ()).unwrap();
+	assert_eq!(pp.get_parser().position(), doc.len() as u64);
+}
+
+#[test]
+fn event_sink_works_with_split_input_and_pull_parser() {
+	let mut expected = Vec::new();
+	FeedParser::default()
+		.parse_all(&mut &SINK_TEST_DOC[..], true, |ev| expected.push(ev))
+		.unwrap();
+
+	let mut fp = FeedParser::default();
+	let mut sink = CollectingSink::default();
+	for chunk in SINK_TEST_DOC.chunks(7) {
+		match fp.parse_all_into(&mut &chunk[..], false, &mut sink) {
+			Err(Error::IO(e)) if e.kind() == io::ErrorKind::WouldBlock => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+	fp.parse_all_into(&mut &b""[..], true, &mut sink).unwrap();
+	assert_eq!(merge_texts(sink.0), merge_texts(expected.clone()));
+
+	let mut pp = PullParser::new(&SINK_TEST_DOC[..]);
+	let mut sink = CollectingSink::default();
+	pp.read_all_into(&mut sink).unwrap();
+	assert!(!pp.read_into(&mut sink).unwrap());
+	assert_eq!(merge_texts(sink.0), merge_texts(expected));
+}
+
+#[test]
+fn event_sink_errors_poison_parser() {
+	let mut fp = FeedParser::default();
+	let mut sink = CollectingSink::default();
+	let mut doc = &b"<a x='1' x='2'/>"[..];
+	match fp.parse_all_into(&mut doc, true, &mut sink) {
+		Err(Error::Xml(error::XmlError::DuplicateAttribute)) => (),
+		other => panic!("unexpected result: {:?}", other),
+	}
+	assert_eq!(sink.0.len(), 0);
+	match fp.parse_all_into(&mut &b""[..], true, &mut sink) {
+		Err(Error::Xml(error::XmlError::DuplicateAttribute)) => (),
+		other => panic!("unexpected result: {:?}", other),
+	}
+}
+
+#[test]
+fn event_sink_text_is_borrowed_from_source_buffer() {
+	struct TextPointers<'x> {
+		buffer: &'x [u8],
+		borrowed: usize,
+		texts: Vec<String>,
+	}
+
+	impl<'x> EventSink for TextPointers<'x> {
+		fn start_element(
+			&mut self,
+			_: &parser::EventMetrics,
+			_: &ResolvedQName,
+			_: &Attributes,
+			_: Option<&NcNameStr>,
+		) {
+		}
+
+		fn end_element(&mut self, _: &parser::EventMetrics) {}
+
+		fn text(&mut self, _: &parser::EventMetrics, text: &strings::CDataStr) {
+			if self.buffer.as_ptr_range().contains(&text.as_ptr()) {
+				self.borrowed += 1;
+			}
+			self.texts.push(text.to_string());
+		}
+	}
+
+	let text = "Grüße aus Köln! ".repeat(1000);
+	let doc = format!("<a>{}<b/>{}</a>", text, text);
+
+	// a slice is its own buffer, so all text can be borrowed
+	let mut pp = PullParser::new(doc.as_bytes());
+	let mut sink = TextPointers {
+		buffer: doc.as_bytes(),
+		borrowed: 0,
+		texts: Vec::new(),
+	};
+	pp.read_all_into(&mut sink).unwrap();
+	assert_eq!(sink.borrowed, sink.texts.len());
+	assert_eq!(sink.texts.concat(), format!("{}{}", text, text));
+
+	let mut fp = FeedParser::default();
+	let mut sink = TextPointers {
+		buffer: doc.as_bytes(),
+		borrowed: 0,
+		texts: Vec::new(),
+	};
+	fp.parse_all_into(&mut doc.as_bytes(), true, &mut sink)
+		.unwrap();
+	assert_eq!(sink.borrowed, sink.texts.len());
+	assert_eq!(sink.texts.concat(), format!("{}{}", text, text));
+
+	// with a small buffer, characters are split between reads
+	let reader = io::BufReader::with_capacity(1021, doc.as_bytes());
+	let mut pp = PullParser::new(reader);
+	let mut sink = CollectingSink::default();
+	pp.read_all_into(&mut sink).unwrap();
+	let texts: Vec<_> = sink
+		.0
+		.iter()
+		.filter_map(|ev| match ev {
+			ResolvedEvent::Text(_, t) => Some(t.as_str()),
+			_ => None,
+		})
+		.collect();
+	assert_eq!(texts.concat(), format!("{}{}", text, text));
+}
+
+#[test]
+fn event_sink_borrowed_text_is_validated() {
+	for (doc, expect_ok) in [
+		(&b"<a>foo\xffbar</a>"[..], false),
+		(&b"<a>foo\x01bar</a>"[..], false),
+		(&b"<a>foo]]>bar</a>"[..], false),
+		(&b"<a>foo\r\nbar</a>"[..], true),
+		(&b"<a>foo]bar</a>"[..], true),
+	]
+	.iter()
+	{
+		let mut pp = PullParser::new(*doc);
+		let mut sink = CollectingSink::default();
+		let result = pp.read_all_into(&mut sink);
+		let mut expected = Vec::new();
+		let expected_result =
+			FeedParser::default().parse_all(&mut &doc[..], true, |ev| expected.push(ev));
+		assert_eq!(result.is_ok(), *expect_ok, "{:?}: {:?}", doc, result);
+		assert_eq!(expected_result.is_ok(), *expect_ok);
+		if *expect_ok {
+			assert_eq!(merge_texts(sink.0), merge_texts(expected));
+		}
+	}
+}
+
+#[test]
+fn completed_child_ranges_cover_the_source_bytes() {
+	let doc = b"<?xml version='1.0'?>\n<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'>\n<message to='a@b'><body>Gr\xc3\xbc\xc3\x9fe &amp; so</body></message>\n\t<presence/><iq type='get' id='1'><query xmlns='jabber:iq:roster'/></iq></stream:stream>";
+	let expected: Vec<&[u8]> = vec![
+		b"<message to='a@b'><body>Gr\xc3\xbc\xc3\x9fe &amp; so</body></message>",
+		b"<presence/>",
+		b"<iq type='get' id='1'><query xmlns='jabber:iq:roster'/></iq>",
+	];
+
+	for chunk_size in [1, 3, 7, 16, doc.len()].iter() {
+		let mut fp = FeedParser::default();
+		let mut children = Vec::new();
+		for (i, mut chunk) in doc.chunks(*chunk_size).enumerate() {
+			let at_eof = (i + 1) * chunk_size >= doc.len();
+			let result = fp.parse_all_framed(&mut chunk, at_eof, |_, range| {
+				if let Some(range) = range {
+					children.push(&doc[range.start as usize..range.end as usize]);
+				}
+			});
+			as_eof_flag(result).unwrap();
+		}
+		assert_eq!(children, expected, "chunk size {}", chunk_size);
+		assert_eq!(fp.get_parser().position(), doc.len() as u64);
+
+		// the sink path passes borrowed text and must account for it, too
+		let mut fp = FeedParser::default();
+		let mut sink = CollectingSink::default();
+		for (i, mut chunk) in doc.chunks(*chunk_size).enumerate() {
+			let at_eof = (i + 1) * chunk_size >= doc.len();
+			as_eof_flag(fp.parse_all_into(&mut chunk, at_eof, &mut sink)).unwrap();
+		}
+		assert_eq!(fp.get_parser().position(), doc.len() as u64);
+		assert_eq!(fp.get_parser().completed_child(), None);
+		assert_eq!(fp.get_parser().position(), doc.len() as u64);
+	}
+}
+
+/// Turn arbitrary events into a well-formed document by dropping the events
+/// which are not allowed where they occur and closing all open elements.
+#[cfg(all(feature = "arbitrary", feature = "test-util"))]
+fn make_well_formed(evs: Vec<ResolvedEvent>) -> Vec<ResolvedEvent> {
+	let mut result = Vec::new();
+	let mut depth = 0usize;
+	let mut had_root = false;
+	for ev in evs {
+		let allowed = match ev {
+			ResolvedEvent::XmlDeclaration(..) => result.is_empty(),
+			ResolvedEvent::StartElement(..) => depth > 0 || !had_root,
+			ResolvedEvent::EndElement(..) | ResolvedEvent::Text(..) => depth > 0,
+			_ => false,
+		};
+		if !allowed {
+			continue;
+		}
+		match ev {
+			ResolvedEvent::StartElement(..) => {
+				depth += 1;
+				had_root = true;
+			}
+			ResolvedEvent::EndElement(..) => depth -= 1,
+			_ => (),
+		}
+		result.push(ev);
+	}
+	for _ in 0..depth {
+		result.push(ResolvedEvent::EndElement(parser::EventMetrics::new(0)));
+	}
+	result
+}
+
+#[cfg(all(feature = "arbitrary", feature = "test-util"))]
+#[test]
+fn arbitrary_events_roundtrip_through_encoder_and_parser() {
+	use crate::test_util::normalize_events;
+	use arbitrary::{Arbitrary, Unstructured};
+
+	let mut state = 0x2545_f491u32;
+	let mut documents = 0;
+	for _ in 0..500 {
+		let data: Vec<u8> = (0..256)
+			.map(|_| {
+				state ^= state << 13;
+				state ^= state >> 17;
+				state ^= state << 5;
+				(state >> 24) as u8
+			})
+			.collect();
+		let mut u = Unstructured::new(&data);
+		let evs = make_well_formed(Vec::<ResolvedEvent>::arbitrary(&mut u).unwrap());
+		if !evs
+			.iter()
+			.any(|ev| matches!(ev, ResolvedEvent::StartElement(..)))
+		{
+			continue;
+		}
+		documents += 1;
+
+		let mut enc = Encoder::new();
+		let mut buf = bytes::BytesMut::new();
+		for ev in evs.iter() {
+			enc.encode_event_into_bytes(ev, &mut buf).unwrap();
+		}
+		let mut parsed = Vec::new();
+		FeedParser::default()
+			.parse_all(&mut &buf[..], true, |ev| parsed.push(ev))
+			.unwrap();
+		assert_eq!(normalize_events(parsed), normalize_events(evs));
+	}
+	assert!(documents > 50);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_roundtrip_resolved_events() {
+	use serde_test::{assert_tokens, Token};
+	use std::convert::TryInto;
+
+	let mut attrs = parser::Attributes::new();
+	attrs.insert((None, "a".try_into().unwrap()), "v".try_into().unwrap());
+	let start = ResolvedEvent::StartElement(
+		parser::EventMetrics::new(7),
+		(
+			Some(parser::RcPtr::new(CData::try_from("uri:foo").unwrap())),
+			"x".try_into().unwrap(),
+		),
+		attrs,
+		Some("p".try_into().unwrap()),
+	);
+	assert_tokens(
+		&start,
+		&[
+			Token::TupleVariant {
+				name: "ResolvedEvent",
+				variant: "StartElement",
+				len: 4,
+			},
+			Token::Struct {
+				name: "EventMetrics",
+				len: 1,
+			},
+			Token::Str("len"),
+			Token::U64(7),
+			Token::StructEnd,
+			Token::Tuple { len: 2 },
+			Token::Some,
+			Token::String("uri:foo"),
+			Token::String("x"),
+			Token::TupleEnd,
+			Token::Seq { len: Some(1) },
+			Token::Tuple { len: 2 },
+			Token::Tuple { len: 2 },
+			Token::None,
+			Token::String("a"),
+			Token::TupleEnd,
+			Token::String("v"),
+			Token::TupleEnd,
+			Token::SeqEnd,
+			Token::Some,
+			Token::String("p"),
+			Token::TupleVariantEnd,
+		],
+	);
+	assert_tokens(
+		&ResolvedEvent::Text(parser::EventMetrics::new(3), "foo".try_into().unwrap()),
+		&[
+			Token::TupleVariant {
+				name: "ResolvedEvent",
+				variant: "Text",
+				len: 2,
+			},
+			Token::Struct {
+				name: "EventMetrics",
+				len: 1,
+			},
+			Token::Str("len"),
+			Token::U64(3),
+			Token::StructEnd,
+			Token::String("foo"),
+			Token::TupleVariantEnd,
+		],
+	);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_deserialize_events_validates() {
+	use serde_test::{assert_de_tokens_error, Token};
+
+	assert_de_tokens_error::<ResolvedEvent>(
+		&[
+			Token::TupleVariant {
+				name: "ResolvedEvent",
+				variant: "Text",
+				len: 2,
+			},
+			Token::Struct {
+				name: "EventMetrics",
+				len: 1,
+			},
+			Token::Str("len"),
+			Token::U64(1),
+			Token::StructEnd,
+			Token::String("\x01"),
+		],
+		&CData::try_from("\x01").unwrap_err().to_string(),
+	);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_serialize_items() {
+	use serde_test::{assert_ser_tokens, Token};
+	use std::convert::TryInto;
+
+	fn assert_deserialize<'de, T: serde::Deserialize<'de>>() {}
+	assert_deserialize::<Item<'_>>();
+
+	let ns: &CDataStr = "uri:foo".try_into().unwrap();
+	assert_ser_tokens(
+		&Item::ElementHeadStartRef(Some(ns), "x".try_into().unwrap()),
+		&[
+			Token::TupleVariant {
+				name: "Item",
+				variant: "ElementHeadStartRef",
+				len: 2,
+			},
+			Token::Some,
+			Token::Str("uri:foo"),
+			Token::Str("x"),
+			Token::TupleVariantEnd,
+		],
+	);
+	assert_ser_tokens(
+		&Item::ElementFoot,
+		&[Token::UnitVariant {
+			name: "Item",
+			variant: "ElementFoot",
+		}],
+	);
+}
+
+#[test]
+fn find_element_skips_to_matching_element() {
+	let doc = &b"<a>text<b n='1'><c/></b><d><b n='2'>x</b></d></a>"[..];
+	let is_second = |name: &ResolvedQName, attrs: &parser::Attributes| {
+		name.1 == "b" && attrs.attr(None, "n").map_or(false, |v| &*v == "2")
+	};
+
+	let mut pp = PullParser::new(doc);
+	let (name, _) = pp.find_element(is_second).unwrap().unwrap();
+	assert_eq!(name.1, "b");
+	match pp.read().unwrap() {
+		Some(ResolvedEvent::Text(_, text)) => assert_eq!(text, "x"),
+		other => panic!("unexpected event: {:?}", other),
+	}
+	assert!(pp.find_element(|_, _| true).unwrap().is_none());
+
+	let mut pp = PullParser::new(doc);
+	let (name, _) = EventRead::find_element(&mut pp, is_second)
+		.unwrap()
+		.unwrap();
+	assert_eq!(name.1, "b");
+	match pp.read().unwrap() {
+		Some(ResolvedEvent::Text(_, text)) => assert_eq!(text, "x"),
+		other => panic!("unexpected event: {:?}", other),
+	}
+	assert!(EventRead::find_element(&mut pp, |_, _| true)
+		.unwrap()
+		.is_none());
+}
+
+#[test]
+fn find_in_children_only_considers_direct_children() {
+	let doc = &b"<a><b><c/></b>text<c n='1'/></a>"[..];
+
+	for fast in [true, false].iter().copied() {
+		let mut pp = PullParser::new(doc);
+		pp.read().unwrap();
+		let mut seen = Vec::new();
+		let mut predicate = |name: &ResolvedQName, _: &parser::Attributes| {
+			seen.push(name.1.to_string());
+			name.1 == "c"
+		};
+		let found = if fast {
+			pp.find_in_children(&mut predicate).unwrap()
+		} else {
+			EventRead::find_in_children(&mut pp, &mut predicate).unwrap()
+		};
+		let (_, attrs) = found.unwrap();
+		assert_eq!(&*attrs.attr(None, "n").unwrap(), "1");
+		assert_eq!(seen, &["b", "c"]);
+
+		// the end of <c/> ends the search within <c/>
+		let found = if fast {
+			pp.find_in_children(|_, _| true).unwrap()
+		} else {
+			EventRead::find_in_children(&mut pp, |_, _| true).unwrap()
+		};
+		assert!(found.is_none());
+		// the end of <a/> ends the search within <a/>
+		let found = if fast {
+			pp.find_in_children(|_, _| true).unwrap()
+		} else {
+			EventRead::find_in_children(&mut pp, |_, _| true).unwrap()
+		};
+		assert!(found.is_none());
+		assert!(pp.read().unwrap().is_none());
+	}
+}
+
+#[test]
+fn namespace_declarations_per_element_are_limited() {
+	let mut fp = FeedParser::default();
+	fp.get_parser_mut()
+		.set_max_namespace_declarations_per_element(Some(2));
+	let mut events = 0;
+	let doc = b"<a xmlns='urn:a' xmlns:b='urn:b'><c xmlns:c='urn:c' xmlns:d='urn:d'/><e xmlns='urn:e' xmlns:f='urn:f' xmlns:g='urn:g'/></a>";
+	match fp.parse_all(&mut &doc[..], true, |_| events += 1) {
+		Err(Error::NamespaceDeclarationLimitExceeded(2)) => (),
+		other => panic!("unexpected result: {:?}", other),
+	}
+	assert_eq!(events, 3);
+	match fp.parse_all(&mut &b""[..], true, |_| ()) {
+		Err(Error::NamespaceDeclarationLimitExceeded(2)) => (),
+		other => panic!("unexpected result: {:?}", other),
+	}
+}
+
+#[test]
+fn namespace_declarations_per_document_are_limited() {
+	let mut fp = FeedParser::default();
+	fp.get_parser_mut().set_max_namespace_declarations(Some(3));
+	let mut events = 0;
+	let doc = b"<a xmlns='urn:a'><b xmlns:b='urn:b'/><c xmlns:c='urn:c'/><d xmlns:d='urn:d'/></a>";
+	let err = fp
+		.parse_all(&mut &doc[..], true, |_| events += 1)
+		.unwrap_err();
+	match err {
+		Error::NamespaceDeclarationLimitExceeded(3) => (),
+		other => panic!("unexpected error: {:?}", other),
+	}
+	assert!(err.is_restriction());
+	assert_eq!(
+		err.code(),
+		error::ErrorCode::NamespaceDeclarationLimitExceeded
+	);
+	assert_eq!(events, 5);
+	assert_eq!(fp.get_parser().statistics().namespace_declarations(), 3);
+
+	let mut fp = FeedParser::default();
+	fp.get_parser_mut().set_max_namespace_declarations(Some(4));
+	fp.parse_all(&mut &doc[..], true, |_| ()).unwrap();
+}
@@ -0,0 +1,522 @@
+/*!
+# Copying events from a parser into an encoder
+
+[`copy_events`] reads all events from an [`EventRead`] and encodes them
+with an [`Encoder`], passing each event through a user-supplied hook first.
+The hook can rewrite events, drop them (including whole subtrees, see
+[`Emitter::skip_element`]) or inject new ones. This is the basis for
+proxies, sanitizers and anonymizers.
+
+As events carry resolved names (namespace URI and local name), renaming a
+namespace only requires changing the names in the events: the encoder takes
+care of declaring prefixes in the output wherever they are needed.
+
+To extract a single element instead of a whole document,
+[`serialize_element`] consumes the subtree of an element whose
+`StartElement` event has just been read and returns it as a self-contained
+fragment. [`copy_element`] does the same into an existing encoder.
+
+## Example
+
+Moving elements from one namespace into another, stripping `<secret/>`
+elements and adding an attribute to the root element:
+
+```
+# #[cfg(feature = "std")]
+# fn main() {
+use rxml::{PullParser, ResolvedEvent, Encoder};
+use rxml::transform::copy_events;
+use std::convert::TryInto;
+
+let mut pp = PullParser::new(
+	&b"<a xmlns='urn:old'><secret>hunter2</secret><b>text</b></a>"[..],
+);
+let mut encoder = Encoder::new();
+let mut out = Vec::new();
+let new_ns: rxml::parser::NamespaceName = rxml::parser::RcPtr::new("urn:new".try_into().unwrap());
+copy_events(&mut pp, &mut encoder, &mut out, |ev, emitter| match ev {
+	ResolvedEvent::StartElement(_, (_, ref name), _, _) if name == "secret" => {
+		emitter.skip_element();
+	}
+	ResolvedEvent::StartElement(m, (Some(ns), name), mut attrs, prefix) if ns.as_str() == "urn:old" => {
+		if emitter.depth() == 0 {
+			attrs.insert((None, "rewritten".try_into().unwrap()), "yes".try_into().unwrap());
+		}
+		emitter.emit(ResolvedEvent::StartElement(m, (Some(new_ns.clone()), name), attrs, prefix));
+	}
+	other => emitter.emit(other),
+}).unwrap();
+assert_eq!(
+	std::str::from_utf8(&out).unwrap(),
+	"<a xmlns='urn:new' rewritten=\"yes\"><b>text</b></a>",
+);
+# }
+# #[cfg(not(feature = "std"))]
+# fn main() {}
+```
+*/
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use bytes::BufMut;
+
+use crate::driver::EventRead;
+use crate::errctx::ERRCTX_ELEMENT;
+use crate::error::Error;
+use crate::parser::{Attributes, EventMetrics, ResolvedEvent, ResolvedQName};
+use crate::writer::{EncodeError, Encoder, TrackNamespace};
+
+/// Error returned by [`copy_events`] and [`copy_element`].
+#[derive(Debug)]
+pub enum CopyError {
+	/// Reading an event failed.
+	Read(Error),
+	/// Encoding an event failed.
+	///
+	/// This generally means that the events emitted by the hook do not form
+	/// a well-formed document, for instance because an element start was
+	/// dropped while its end was not.
+	Encode(EncodeError),
+}
+
+impl fmt::Display for CopyError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Read(e) => write!(f, "failed to read event: {}", e),
+			Self::Encode(e) => write!(f, "failed to encode event: {}", e),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CopyError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Read(e) => Some(e),
+			Self::Encode(e) => Some(e),
+		}
+	}
+}
+
+impl From<EncodeError> for CopyError {
+	fn from(other: EncodeError) -> Self {
+		Self::Encode(other)
+	}
+}
+
+/**
+# Output of a [`copy_events`] hook
+
+The hook is called once for each event read from the input and decides
+what is written to the output through the emitter: events passed to
+[`emit`](Self::emit) are encoded in order, after the hook has returned.
+Events which are not emitted are dropped.
+*/
+#[derive(Debug, Default)]
+pub struct Emitter {
+	queue: Vec<ResolvedEvent>,
+	depth: usize,
+	skip: bool,
+}
+
+impl Emitter {
+	/// Emit an event.
+	///
+	/// This may be called any number of times per hook invocation, for
+	/// instance to inject elements before or after the current event.
+	pub fn emit(&mut self, ev: ResolvedEvent) {
+		self.queue.push(ev);
+	}
+
+	/// Drop the element which starts with the current event, including all
+	/// of its content and its end.
+	///
+	/// The hook is not called for the events of the content and the end of
+	/// the element. Calling this for events other than
+	/// [`ResolvedEvent::StartElement`] has no effect. Events emitted by the
+	/// hook in the same invocation are still encoded.
+	pub fn skip_element(&mut self) {
+		self.skip = true;
+	}
+
+	/// Return the number of elements in the input which enclose the current
+	/// event.
+	///
+	/// For the start and the end of the root element, this is zero.
+	pub fn depth(&self) -> usize {
+		self.depth
+	}
+}
+
+/**
+Copy all events from `reader` to `encoder`, passing them through `hook`.
+
+For each event read, `hook` is called with the event and an [`Emitter`],
+which collects the events to encode in its place; see the [module
+documentation](self) for an example. The encoded data is written to
+`output`.
+
+Copying stops successfully at the end of the input. Errors from the reader
+(including I/O errors, even those which could be retried) and from the
+encoder abort the copy; as the state of the hook invocation is lost in that
+case, `copy_events` is best used with blocking readers such as the
+[`PullParser`](crate::PullParser).
+*/
+pub fn copy_events<R, T, O, F>(
+	reader: &mut R,
+	encoder: &mut Encoder<T>,
+	output: &mut O,
+	mut hook: F,
+) -> Result<(), CopyError>
+where
+	R: EventRead<Output = ResolvedEvent> + ?Sized,
+	T: TrackNamespace,
+	O: BufMut,
+	F: FnMut(ResolvedEvent, &mut Emitter),
+{
+	let mut emitter = Emitter::default();
+	// number of elements being skipped, including the outermost one
+	let mut skipping = 0usize;
+	while let Some(ev) = reader.read().map_err(CopyError::Read)? {
+		let is_start = matches!(ev, ResolvedEvent::StartElement(..));
+		let is_end = matches!(ev, ResolvedEvent::EndElement(..));
+		if is_end {
+			emitter.depth -= 1;
+		}
+		if skipping > 0 {
+			if is_start {
+				skipping += 1;
+				emitter.depth += 1;
+			} else if is_end {
+				skipping -= 1;
+			}
+			continue;
+		}
+		hook(ev, &mut emitter);
+		if is_start {
+			emitter.depth += 1;
+			if emitter.skip {
+				skipping = 1;
+			}
+		}
+		emitter.skip = false;
+		for ev in emitter.queue.drain(..) {
+			encoder.encode_event(&ev, output)?;
+		}
+	}
+	Ok(())
+}
+
+/**
+Copy the remainder of an element from `reader` to `encoder`.
+
+This is to be called after the [`StartElement`] event of the element has
+been read from `reader`; its name and attributes are passed as arguments.
+The element is encoded completely, from its header to its footer, and all
+events up to and including the matching [`EndElement`] are consumed. The
+encoded data is written to `output`.
+
+The encoder determines which namespace declarations are written: with a
+fresh encoder, the element declares all namespaces it uses itself, so that
+the output can be used without the context of the original document (see
+[`serialize_element`]). With the encoder of an enclosing document, prefixes
+declared there are reused.
+
+If `reader` reaches the end of the document before the element is
+complete, [`XmlError::InvalidEof`] is returned as [`CopyError::Read`].
+
+   [`StartElement`]: ResolvedEvent::StartElement
+   [`EndElement`]: ResolvedEvent::EndElement
+   [`XmlError::InvalidEof`]: crate::error::XmlError::InvalidEof
+*/
+pub fn copy_element<R, T, O>(
+	reader: &mut R,
+	encoder: &mut Encoder<T>,
+	output: &mut O,
+	name: ResolvedQName,
+	attributes: Attributes,
+) -> Result<(), CopyError>
+where
+	R: EventRead<Output = ResolvedEvent> + ?Sized,
+	T: TrackNamespace,
+	O: BufMut,
+{
+	encoder.encode_event(
+		&ResolvedEvent::StartElement(EventMetrics::new(0), name, attributes, None),
+		output,
+	)?;
+	let mut depth = 1usize;
+	while depth > 0 {
+		let ev = match reader.read().map_err(CopyError::Read)? {
+			Some(ev) => ev,
+			None => return Err(CopyError::Read(Error::wfeof(ERRCTX_ELEMENT))),
+		};
+		match ev {
+			ResolvedEvent::StartElement(..) => depth += 1,
+			ResolvedEvent::EndElement(..) => depth -= 1,
+			_ => (),
+		}
+		encoder.encode_event(&ev, output)?;
+	}
+	Ok(())
+}
+
+/**
+Serialize the remainder of an element read from `reader` into a new
+buffer.
+
+This uses [`copy_element`] with a fresh [`Encoder`], so that the output is
+a self-contained fragment which declares all namespaces it uses, as needed
+when storing or forwarding a stanza (for instance for message archiving or
+XEP-0297 forwarding). No XML declaration is written.
+
+## Example
+
+```
+# #[cfg(feature = "std")]
+# fn main() {
+use rxml::{EventRead, PullParser, ResolvedEvent};
+use rxml::transform::serialize_element_to_string;
+
+let mut pp = PullParser::new(&b"<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'>\
+	<message to='juliet@example.com'><body>Hi!</body></message>\
+	</stream:stream>"[..]);
+// stream header
+pp.read().unwrap();
+match pp.read().unwrap() {
+	Some(ResolvedEvent::StartElement(_, name, attrs, _)) => {
+		assert_eq!(
+			serialize_element_to_string(&mut pp, name, attrs).unwrap(),
+			"<message xmlns='jabber:client' to=\"juliet@example.com\"><body>Hi!</body></message>",
+		);
+	}
+	other => panic!("unexpected event: {:?}", other),
+}
+// stream footer
+assert!(matches!(pp.read().unwrap(), Some(ResolvedEvent::EndElement(..))));
+# }
+# #[cfg(not(feature = "std"))]
+# fn main() {}
+```
+*/
+pub fn serialize_element<R: EventRead<Output = ResolvedEvent> + ?Sized>(
+	reader: &mut R,
+	name: ResolvedQName,
+	attributes: Attributes,
+) -> Result<Vec<u8>, CopyError> {
+	let mut encoder = Encoder::new();
+	let mut output = Vec::new();
+	copy_element(reader, &mut encoder, &mut output, name, attributes)?;
+	Ok(output)
+}
+
+/// Serialize the remainder of an element read from `reader` into a new
+/// string.
+///
+/// See [`serialize_element`].
+pub fn serialize_element_to_string<R: EventRead<Output = ResolvedEvent> + ?Sized>(
+	reader: &mut R,
+	name: ResolvedQName,
+	attributes: Attributes,
+) -> Result<String, CopyError> {
+	let output = serialize_element(reader, name, attributes)?;
+	// SAFETY: the encoder only writes valid UTF-8
+	Ok(unsafe { String::from_utf8_unchecked(output) })
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+	use super::*;
+
+	use std::convert::TryInto;
+
+	use crate::parser::{EventMetrics, RcPtr};
+	use crate::strings::CData;
+	use crate::PullParser;
+
+	fn copy<F: FnMut(ResolvedEvent, &mut Emitter)>(
+		doc: &[u8],
+		hook: F,
+	) -> Result<String, CopyError> {
+		let mut pp = PullParser::new(doc);
+		let mut encoder = Encoder::new();
+		let mut out = Vec::new();
+		copy_events(&mut pp, &mut encoder, &mut out, hook)?;
+		Ok(String::from_utf8(out).unwrap())
+	}
+
+	#[test]
+	fn copies_events_unchanged_with_identity_hook() {
+		let out = copy(
+			b"<?xml version='1.0'?><a xmlns='urn:x' xmlns:p='urn:p' p:b='c'>x &amp; y<p:d/></a>",
+			|ev, emitter| emitter.emit(ev),
+		)
+		.unwrap();
+		assert_eq!(
+			out,
+			"<?xml version='1.0' encoding='utf-8'?>\n<a xmlns='urn:x' xmlns:tns0='urn:p' tns0:b=\"c\">x &amp; y<tns0:d></tns0:d></a>"
+		);
+	}
+
+	#[test]
+	fn skips_nested_elements_and_tracks_depth() {
+		let mut depths = Vec::new();
+		let out = copy(
+			b"<a><drop><drop><x/></drop>text</drop><keep><drop/></keep></a>",
+			|ev, emitter| {
+				match &ev {
+					ResolvedEvent::StartElement(_, (_, name), _, _) => {
+						depths.push((name.to_string(), emitter.depth()));
+						if name == "drop" {
+							emitter.skip_element();
+							return;
+						}
+					}
+					ResolvedEvent::EndElement(_) => depths.push(("/".to_string(), emitter.depth())),
+					_ => (),
+				}
+				emitter.emit(ev);
+			},
+		)
+		.unwrap();
+		assert_eq!(out, "<a><keep></keep></a>");
+		let expected: Vec<(String, usize)> = vec![
+			("a".to_string(), 0),
+			("drop".to_string(), 1),
+			("keep".to_string(), 1),
+			("drop".to_string(), 2),
+			("/".to_string(), 1),
+			("/".to_string(), 0),
+		];
+		assert_eq!(depths, expected);
+	}
+
+	#[test]
+	fn injects_events() {
+		let ns: RcPtr<CData> = RcPtr::new("urn:extra".try_into().unwrap());
+		let out = copy(b"<a><b/></a>", |ev, emitter| {
+			let is_b_end = matches!(ev, ResolvedEvent::EndElement(_)) && emitter.depth() == 1;
+			emitter.emit(ev);
+			if is_b_end {
+				let m = EventMetrics::new(0);
+				emitter.emit(ResolvedEvent::StartElement(
+					m,
+					(Some(ns.clone()), "c".try_into().unwrap()),
+					Default::default(),
+					None,
+				));
+				emitter.emit(ResolvedEvent::Text(m, "hi".try_into().unwrap()));
+				emitter.emit(ResolvedEvent::EndElement(m));
+			}
+		})
+		.unwrap();
+		assert_eq!(out, "<a><b></b><c xmlns='urn:extra'>hi</c></a>");
+	}
+
+	#[test]
+	fn reports_unbalanced_output() {
+		match copy(b"<a><b/></a>", |ev, emitter| {
+			if !matches!(ev, ResolvedEvent::StartElement(..)) || emitter.depth() == 0 {
+				emitter.emit(ev);
+			}
+		}) {
+			Err(CopyError::Encode(EncodeError::EndOfDocument)) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn reports_read_errors() {
+		match copy(b"<a><b></a>", |ev, emitter| emitter.emit(ev)) {
+			Err(CopyError::Read(Error::Xml(_))) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
+	fn read_start(pp: &mut PullParser<&[u8]>, skip: usize) -> (ResolvedQName, Attributes) {
+		let mut skipped = 0;
+		loop {
+			match pp.read().unwrap() {
+				Some(ResolvedEvent::StartElement(_, name, attrs, _)) => {
+					if skipped == skip {
+						return (name, attrs);
+					}
+					skipped += 1;
+				}
+				Some(_) => (),
+				None => panic!("unexpected end of document"),
+			}
+		}
+	}
+
+	#[test]
+	fn serializes_element_with_namespace_declarations() {
+		let mut pp = PullParser::new(
+			&b"<a xmlns='urn:a' xmlns:p='urn:p'><b p:x='1'>text<p:c/><d xmlns='urn:d'/></b><e/></a>"[..],
+		);
+		let (name, attrs) = read_start(&mut pp, 1);
+		let out = serialize_element_to_string(&mut pp, name, attrs).unwrap();
+		assert_eq!(
+			out,
+			"<b xmlns='urn:a' xmlns:tns0='urn:p' tns0:x=\"1\">text<tns0:c></tns0:c><d xmlns='urn:d'></d></b>"
+		);
+		// the reader is positioned behind the element
+		let (name, _) = read_start(&mut pp, 0);
+		assert_eq!(name.1, "e");
+	}
+
+	#[test]
+	fn serialized_element_parses_to_the_same_tree() {
+		let doc = &b"<r xmlns:p='urn:p'><p:item xmlns='urn:d' a='x &amp; y'><c>&lt;</c>\n<p:c p:a='\"'/></p:item></r>"[..];
+		let mut pp = PullParser::new(doc);
+		let (name, attrs) = read_start(&mut pp, 1);
+		let out = serialize_element_to_string(&mut pp, name.clone(), attrs.clone()).unwrap();
+
+		let mut pp = PullParser::new(doc);
+		read_start(&mut pp, 0);
+		let (name, attrs) = read_start(&mut pp, 0);
+		let expected = crate::tree::read_element(&mut pp, name, attrs).unwrap();
+		assert_eq!(crate::parse_tree(&out).unwrap(), expected);
+	}
+
+	#[test]
+	fn copy_element_reuses_prefixes_of_the_encoder() {
+		let mut encoder = Encoder::new();
+		let mut out = Vec::new();
+		let ns: RcPtr<CData> = RcPtr::new("urn:a".try_into().unwrap());
+		encoder
+			.encode(
+				crate::Item::ElementHeadStart(Some(ns), "outer".try_into().unwrap()),
+				&mut out,
+			)
+			.unwrap();
+		encoder
+			.encode(crate::Item::ElementHeadEnd, &mut out)
+			.unwrap();
+		let mut pp = PullParser::new(&b"<inner xmlns='urn:a'><x/></inner>"[..]);
+		let (name, attrs) = read_start(&mut pp, 0);
+		copy_element(&mut pp, &mut encoder, &mut out, name, attrs).unwrap();
+		assert_eq!(
+			std::str::from_utf8(&out).unwrap(),
+			"<outer xmlns='urn:a'><inner><x></x></inner>"
+		);
+	}
+
+	#[test]
+	#[allow(deprecated)]
+	fn copy_element_reports_eof() {
+		let mut bq = crate::BufferQueue::new();
+		bq.push(&b"<a><b>"[..]);
+		bq.push_eof();
+		let mut pp = PullParser::new(bq);
+		let (name, attrs) = match pp.read().unwrap() {
+			Some(ResolvedEvent::StartElement(_, name, attrs, _)) => (name, attrs),
+			other => panic!("unexpected event: {:?}", other),
+		};
+		match serialize_element(&mut pp, name, attrs) {
+			Err(CopyError::Read(Error::Xml(crate::error::XmlError::InvalidEof(_)))) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+}
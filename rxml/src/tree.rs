@@ -0,0 +1,920 @@
+/*!
+# Owned element trees
+
+While rxml is a streaming parser at heart, it is often convenient to process
+small parts of a document as a whole. A typical example is an XMPP server:
+the stream itself is infinite, but each stanza (a child of the root element)
+is small and self-contained and is best handed to a worker as one unit.
+
+This module provides the [`Element`] tree type along with ways to build it
+from events:
+
+* [`read_element`] reads the remainder of an element from an [`EventRead`]
+  (such as a [`PullParser`](crate::PullParser)) after its start has been
+  seen, and [`read_element_async`] does the same for an
+  [`AsyncEventRead`](crate::AsyncEventRead).
+* [`ElementBuilder`] collects the events pushed into it, which fits the
+  [`FeedParser`](crate::FeedParser).
+
+Trees can also be built in memory and written out again with
+[`Element::serialize`] or [`Element::to_vec`].
+
+## Building trees
+
+Elements can be assembled in code, for instance to build a response:
+
+```
+use rxml::tree::Element;
+use std::convert::TryInto;
+
+let mut iq = Element::named(Some("jabber:client"), "iq").unwrap()
+	.with_attr((None, "type".try_into().unwrap()), "result".try_into().unwrap());
+let mut query = Element::named(Some("jabber:iq:version"), "query").unwrap();
+query.append_child(
+	Element::named(Some("jabber:iq:version"), "name").unwrap()
+		.with_text("rxml".try_into().unwrap()),
+);
+iq.append_child(query);
+iq.set_attr((None, "id".try_into().unwrap()), "v1".try_into().unwrap());
+
+assert_eq!(
+	String::from_utf8(iq.to_vec().unwrap()).unwrap(),
+	"<iq xmlns='jabber:client' type=\"result\" id=\"v1\"><query xmlns='jabber:iq:version'><name>rxml</name></query></iq>",
+);
+```
+
+## Querying trees
+
+Children and attributes are looked up by namespace URI and local name:
+
+```
+let el = rxml::parse_tree(
+	"<message xmlns='jabber:client' type='chat'><body>Hi </body><body>there!</body></message>",
+).unwrap();
+assert!(el.has_name(Some("jabber:client"), "message"));
+assert_eq!(&*el.attr(None, "type").unwrap(), "chat");
+assert_eq!(el.children_named(Some("jabber:client"), "body").count(), 2);
+assert!(el.first_child(Some("urn:other"), "body").is_none());
+assert_eq!(el.text(), "Hi there!");
+```
+*/
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use bytes::BufMut;
+
+use crate::driver::EventRead;
+use crate::errctx::ERRCTX_ELEMENT;
+use crate::error::{Error, Result, XmlError};
+use crate::parser::{Attributes, NamespaceName, RcPtr, ResolvedEvent, ResolvedQName, XMLNS_XMLNS};
+use crate::strings::{CData, CDataStr, NcName};
+use crate::writer::{EncodeError, Encoder, Item, TrackNamespace};
+
+/// A child node of an [`Element`].
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+	/// A child element.
+	Element(Element),
+	/// Character data.
+	///
+	/// Trees built by this module never contain two adjacent text nodes.
+	Text(CData),
+}
+
+/// An owned XML element, including all of its descendants.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Element {
+	/// The namespace URI and local name of the element.
+	pub name: ResolvedQName,
+	/// The attributes of the element.
+	///
+	/// As with the events, namespace declarations are not included.
+	pub attributes: Attributes,
+	/// The child nodes, in document order.
+	pub children: Vec<Node>,
+}
+
+impl Element {
+	/// Create a new element without children.
+	pub fn new(name: ResolvedQName, attributes: Attributes) -> Self {
+		Self {
+			name,
+			attributes,
+			children: Vec::new(),
+		}
+	}
+
+	/// Create a new element without attributes and children from a
+	/// namespace URI and local name.
+	///
+	/// An empty namespace URI is treated like `None`, as in documents.
+	/// Fails if the local name is not a valid NCName or the namespace URI
+	/// contains characters which are not allowed in XML.
+	pub fn named(
+		namespace: Option<&str>,
+		local_name: &str,
+	) -> core::result::Result<Self, XmlError> {
+		let namespace = match namespace {
+			Some(ns) if !ns.is_empty() => Some(RcPtr::new(CData::try_from(ns)?)),
+			_ => None,
+		};
+		Ok(Self::new(
+			(namespace, NcName::try_from(local_name)?),
+			Attributes::new(),
+		))
+	}
+
+	/// Set an attribute and return the element.
+	///
+	/// This is the builder-style variant of [`set_attr`](Self::set_attr).
+	pub fn with_attr(mut self, name: ResolvedQName, value: CData) -> Self {
+		self.set_attr(name, value);
+		self
+	}
+
+	/// Append a child element and return the element.
+	///
+	/// This is the builder-style variant of
+	/// [`append_child`](Self::append_child).
+	pub fn with_child(mut self, child: Element) -> Self {
+		self.append_child(child);
+		self
+	}
+
+	/// Append text and return the element.
+	///
+	/// This is the builder-style variant of
+	/// [`append_text`](Self::append_text).
+	pub fn with_text(mut self, text: CData) -> Self {
+		self.append_text(text);
+		self
+	}
+
+	/// Set the value of an attribute, returning the previous value if the
+	/// attribute existed already.
+	///
+	/// New attributes are added after the existing ones.
+	pub fn set_attr(&mut self, name: ResolvedQName, value: CData) -> Option<CData> {
+		self.attributes.insert(name, value)
+	}
+
+	/// Remove the attribute with the given namespace URI and local name,
+	/// returning its value if it existed.
+	pub fn remove_attr(&mut self, namespace: Option<&str>, local_name: &str) -> Option<CData> {
+		let name = self
+			.attributes
+			.keys()
+			.find(|(ns, name)| {
+				ns.as_ref().map(|x| x.as_str()) == namespace && name.as_str() == local_name
+			})?
+			.clone();
+		self.attributes.remove(&name)
+	}
+
+	/// Append a child element.
+	pub fn append_child(&mut self, child: Element) {
+		self.children.push(Node::Element(child));
+	}
+
+	/// Append text, merging it with a preceding text node.
+	///
+	/// Empty text is ignored.
+	pub fn append_text(&mut self, text: CData) {
+		self.push_text(text)
+	}
+
+	/// Remove the first child element with the given namespace URI and
+	/// local name and return it.
+	///
+	/// Text nodes around the removed element are merged, so that the tree
+	/// keeps not having adjacent text nodes.
+	pub fn remove_child(&mut self, namespace: Option<&str>, local_name: &str) -> Option<Element> {
+		let index = self.children.iter().position(|child| match child {
+			Node::Element(el) => el.has_name(namespace, local_name),
+			Node::Text(_) => false,
+		})?;
+		let removed = match self.children.remove(index) {
+			Node::Element(el) => el,
+			Node::Text(_) => unreachable!(),
+		};
+		if index > 0 && index < self.children.len() {
+			if let (Node::Text(_), Node::Text(_)) =
+				(&self.children[index - 1], &self.children[index])
+			{
+				let next = match self.children.remove(index) {
+					Node::Text(text) => text,
+					Node::Element(_) => unreachable!(),
+				};
+				if let Node::Text(prev) = &mut self.children[index - 1] {
+					*prev = prev.clone() + &*next;
+				}
+			}
+		}
+		Some(removed)
+	}
+
+	/// Remove all children, including text.
+	pub fn clear_children(&mut self) {
+		self.children.clear();
+	}
+
+	/// Set the namespace URI of the element.
+	///
+	/// If `deep` is true, all descendants which are in the same namespace as
+	/// the element was before are moved into the new namespace as well. This
+	/// corresponds to changing the default namespace declaration of the
+	/// element in a document. Attributes are not changed, as unprefixed
+	/// attributes are never in a namespace.
+	pub fn set_namespace(&mut self, namespace: Option<NamespaceName>, deep: bool) {
+		let old = core::mem::replace(&mut self.name.0, namespace.clone());
+		if deep {
+			for child in self.child_elements_mut() {
+				child.move_namespace(&old, &namespace);
+			}
+		}
+	}
+
+	fn move_namespace(&mut self, old: &Option<NamespaceName>, new: &Option<NamespaceName>) {
+		if self.name.0 != *old {
+			return;
+		}
+		self.name.0 = new.clone();
+		for child in self.child_elements_mut() {
+			child.move_namespace(old, new);
+		}
+	}
+
+	/// Write the element, including all of its descendants, into `output`,
+	/// using `encoder`.
+	///
+	/// Namespace declarations are emitted by the encoder where they are
+	/// needed; attributes in the [`XMLNS_XMLNS`] namespace are skipped, as
+	/// with [`Encoder::encode_event`].
+	///
+	/// # Example
+	///
+	/// ```
+	/// use rxml::Encoder;
+	/// use rxml::parser::{Attributes, RcPtr};
+	/// use rxml::tree::{Element, Node};
+	/// use std::convert::TryInto;
+	///
+	/// let ns = Some(RcPtr::new("urn:example".try_into().unwrap()));
+	/// let mut attrs = Attributes::new();
+	/// attrs.insert((None, "id".try_into().unwrap()), "1".try_into().unwrap());
+	/// let mut el = Element::new((ns.clone(), "item".try_into().unwrap()), attrs);
+	/// el.children.push(Node::Text("a < b".try_into().unwrap()));
+	///
+	/// let mut encoder = Encoder::new();
+	/// let mut out = Vec::new();
+	/// el.serialize(&mut encoder, &mut out).unwrap();
+	/// assert_eq!(
+	/// 	std::str::from_utf8(&out).unwrap(),
+	/// 	"<item xmlns='urn:example' id=\"1\">a &lt; b</item>",
+	/// );
+	/// ```
+	pub fn serialize<T: TrackNamespace, O: BufMut>(
+		&self,
+		encoder: &mut Encoder<T>,
+		output: &mut O,
+	) -> core::result::Result<(), EncodeError> {
+		let (ns, name) = &self.name;
+		encoder.encode(Item::ElementHeadStart(ns.clone(), name.as_ref()), output)?;
+		for ((ns, name), value) in self.attributes.iter() {
+			if ns.as_ref().map(|x| &***x) == Some(XMLNS_XMLNS) {
+				continue;
+			}
+			encoder.encode(Item::Attribute(ns.clone(), name.as_ref(), &value), output)?;
+		}
+		encoder.encode(Item::ElementHeadEnd, output)?;
+		for child in self.children.iter() {
+			match child {
+				Node::Element(el) => el.serialize(encoder, output)?,
+				Node::Text(text) => encoder.encode(Item::Text(text.as_ref()), output)?,
+			}
+		}
+		encoder.encode(Item::ElementFoot, output)
+	}
+
+	/// Serialize the element into a new buffer, using a default
+	/// [`Encoder`].
+	///
+	/// The element declares all namespaces it uses. No XML declaration is
+	/// written.
+	pub fn to_vec(&self) -> core::result::Result<Vec<u8>, EncodeError> {
+		let mut encoder = Encoder::new();
+		let mut output = Vec::new();
+		self.serialize(&mut encoder, &mut output)?;
+		Ok(output)
+	}
+
+	/// Return true if the element has the given namespace URI and local
+	/// name.
+	pub fn has_name(&self, namespace: Option<&str>, local_name: &str) -> bool {
+		self.name.0.as_ref().map(|x| x.as_str()) == namespace && self.name.1 == local_name
+	}
+
+	/// Iterate over the child elements, skipping text nodes.
+	pub fn child_elements(&self) -> impl Iterator<Item = &Element> {
+		self.children.iter().filter_map(|child| match child {
+			Node::Element(el) => Some(el),
+			Node::Text(_) => None,
+		})
+	}
+
+	/// Iterate mutably over the child elements, skipping text nodes.
+	pub fn child_elements_mut(&mut self) -> impl Iterator<Item = &mut Element> {
+		self.children.iter_mut().filter_map(|child| match child {
+			Node::Element(el) => Some(el),
+			Node::Text(_) => None,
+		})
+	}
+
+	/// Iterate over the child elements with the given namespace URI and
+	/// local name.
+	pub fn children_named<'x>(
+		&'x self,
+		namespace: Option<&'x str>,
+		local_name: &'x str,
+	) -> impl Iterator<Item = &'x Element> {
+		self.child_elements()
+			.filter(move |el| el.has_name(namespace, local_name))
+	}
+
+	/// Iterate mutably over the child elements with the given namespace URI
+	/// and local name.
+	pub fn children_named_mut<'x>(
+		&'x mut self,
+		namespace: Option<&'x str>,
+		local_name: &'x str,
+	) -> impl Iterator<Item = &'x mut Element> {
+		self.child_elements_mut()
+			.filter(move |el| el.has_name(namespace, local_name))
+	}
+
+	/// Return the first child element with the given namespace URI and
+	/// local name, if any.
+	pub fn first_child(&self, namespace: Option<&str>, local_name: &str) -> Option<&Element> {
+		self.child_elements()
+			.find(|el| el.has_name(namespace, local_name))
+	}
+
+	/// Return the first child element with the given namespace URI and
+	/// local name mutably, if any.
+	pub fn first_child_mut(
+		&mut self,
+		namespace: Option<&str>,
+		local_name: &str,
+	) -> Option<&mut Element> {
+		self.child_elements_mut()
+			.find(|el| el.has_name(namespace, local_name))
+	}
+
+	/// Return the value of the attribute with the given namespace URI and
+	/// local name, if it exists.
+	///
+	/// See [`Attributes::attr`].
+	pub fn attr(&self, namespace: Option<&str>, local_name: &str) -> Option<Cow<'_, CDataStr>> {
+		self.attributes.attr(namespace, local_name)
+	}
+
+	/// Return a mutable reference to the value of the attribute with the
+	/// given namespace URI and local name, if it exists.
+	///
+	/// Use [`Attributes::insert`] on [`attributes`](Self::attributes) to
+	/// add attributes.
+	pub fn attr_mut(&mut self, namespace: Option<&str>, local_name: &str) -> Option<&mut CData> {
+		self.attributes.get_by_name_mut(namespace, local_name)
+	}
+
+	/// Return the text of the element and all of its descendants,
+	/// concatenated in document order.
+	///
+	/// Use [`mapping::text_of`](crate::mapping::text_of) to only get the
+	/// text which is directly contained in the element.
+	pub fn text(&self) -> CData {
+		let mut result = String::new();
+		self.collect_text(&mut result);
+		// SAFETY: a concatenation of valid CData strings is valid CData
+		unsafe { CData::from_string_unchecked(result) }
+	}
+
+	fn collect_text(&self, into: &mut String) {
+		for child in self.children.iter() {
+			match child {
+				Node::Element(el) => el.collect_text(into),
+				Node::Text(text) => into.push_str(text),
+			}
+		}
+	}
+
+	/// Append text to the children, merging it with a preceding text node.
+	fn push_text(&mut self, text: CData) {
+		if text.is_empty() {
+			return;
+		}
+		match self.children.pop() {
+			Some(Node::Text(prev)) => self.children.push(Node::Text(prev + &*text)),
+			Some(other) => {
+				self.children.push(other);
+				self.children.push(Node::Text(text));
+			}
+			None => self.children.push(Node::Text(text)),
+		}
+	}
+}
+
+/**
+# Incremental construction of [`Element`] trees
+
+The builder collects events from a [`StartElement`] up to the matching
+[`EndElement`] and returns the finished [`Element`] once the latter has
+been pushed. The builder can then be reused for the next element.
+
+Events which are pushed while no element is being built, except
+[`StartElement`], are ignored, as are XML declarations and prefix mapping
+events. This allows to feed all events from the inside of a stream's root
+element to the builder, skipping the whitespace between its children.
+
+## Example
+
+Extracting the children of the root element (the stanzas of an XMPP
+stream) with a [`FeedParser`](crate::FeedParser):
+
+```
+use rxml::{FeedParser, ResolvedEvent};
+use rxml::tree::ElementBuilder;
+
+let mut fp = FeedParser::default();
+let mut builder = ElementBuilder::new();
+let mut depth = 0;
+let mut stanzas = Vec::new();
+let doc = &b"<stream><message><body>Hello!</body></message>\n<presence/></stream>"[..];
+for mut chunk in doc.chunks(5) {
+	let result = fp.parse_all(&mut chunk, false, |ev| {
+		match ev {
+			ResolvedEvent::StartElement(..) => depth += 1,
+			ResolvedEvent::EndElement(..) => depth -= 1,
+			_ => (),
+		}
+		// skip the events of the root element itself
+		if depth > 1 || builder.is_building() {
+			if let Some(stanza) = builder.push(ev) {
+				stanzas.push(stanza);
+			}
+		}
+	});
+	rxml::as_eof_flag(result).unwrap();
+}
+assert_eq!(stanzas.len(), 2);
+assert_eq!(stanzas[0].name.1, "message");
+assert_eq!(stanzas[0].children.len(), 1);
+assert_eq!(stanzas[1].name.1, "presence");
+```
+
+   [`StartElement`]: ResolvedEvent::StartElement
+   [`EndElement`]: ResolvedEvent::EndElement
+*/
+#[derive(Debug, Clone, Default)]
+pub struct ElementBuilder {
+	stack: Vec<Element>,
+}
+
+impl ElementBuilder {
+	/// Create a new builder which is not building an element yet.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Create a builder for an element whose [`StartElement`] event has
+	/// already been consumed.
+	///
+	///    [`StartElement`]: ResolvedEvent::StartElement
+	pub fn with_start(name: ResolvedQName, attributes: Attributes) -> Self {
+		let mut stack = Vec::with_capacity(4);
+		stack.push(Element::new(name, attributes));
+		Self { stack }
+	}
+
+	/// Return true if an element has been started, but not finished yet.
+	pub fn is_building(&self) -> bool {
+		!self.stack.is_empty()
+	}
+
+	/// Return the number of elements which have been started, but not
+	/// finished yet.
+	pub fn depth(&self) -> usize {
+		self.stack.len()
+	}
+
+	/// Process an event.
+	///
+	/// If the event finishes the element under construction, it is
+	/// returned.
+	pub fn push(&mut self, ev: ResolvedEvent) -> Option<Element> {
+		match ev {
+			ResolvedEvent::StartElement(_, name, attributes, _) => {
+				self.stack.push(Element::new(name, attributes));
+				None
+			}
+			ResolvedEvent::EndElement(_) => {
+				let el = self.stack.pop()?;
+				match self.stack.last_mut() {
+					Some(parent) => {
+						parent.children.push(Node::Element(el));
+						None
+					}
+					None => Some(el),
+				}
+			}
+			ResolvedEvent::Text(_, text) => {
+				if let Some(el) = self.stack.last_mut() {
+					el.push_text(text);
+				}
+				None
+			}
+			ResolvedEvent::XmlDeclaration(..)
+			| ResolvedEvent::StartPrefixMapping(..)
+			| ResolvedEvent::EndPrefixMapping(..) => None,
+		}
+	}
+}
+
+/**
+Parse a complete document from a string into an [`Element`].
+
+This sets up a [`FeedParser`](crate::FeedParser), feeds it `data` with the
+end-of-file flag set and returns the root element. It fails if `data` is
+not a complete, well-formed document.
+
+```
+use rxml::tree::Node;
+
+let root = rxml::parse_tree("<?xml version='1.0'?><a x='1'><b/>text</a>").unwrap();
+assert_eq!(root.name.1, "a");
+assert_eq!(&*root.attributes.attr(None, "x").unwrap(), "1");
+assert_eq!(root.children.len(), 2);
+assert!(matches!(&root.children[1], Node::Text(t) if t == "text"));
+```
+*/
+pub fn parse_tree(data: &str) -> Result<Element> {
+	let mut fp = crate::FeedParser::default();
+	let mut builder = ElementBuilder::new();
+	let mut root = None;
+	fp.parse_all_str(data, true, |ev| {
+		if let Some(el) = builder.push(ev) {
+			root = Some(el);
+		}
+	})?;
+	// a successful parse at EOF implies a complete root element
+	Ok(root.unwrap())
+}
+
+/**
+Read the remainder of an element into an [`Element`].
+
+This is to be called after the [`StartElement`] event of the element has
+been read from `r`; its name and attributes are passed as arguments. All
+events up to and including the matching [`EndElement`] are consumed.
+
+Errors from `r` are passed through. If `r` reaches the end of the document
+before the element is complete, [`XmlError::InvalidEof`] is returned.
+
+## Example
+
+```
+# #[cfg(feature = "std")]
+# fn main() {
+use rxml::{EventRead, PullParser, ResolvedEvent};
+use rxml::tree;
+
+let mut pp = PullParser::new(&b"<stream><message><body>Hello!</body></message></stream>"[..]);
+// stream header
+pp.read().unwrap();
+match pp.read().unwrap() {
+	Some(ResolvedEvent::StartElement(_, name, attrs, _)) => {
+		let message = tree::read_element(&mut pp, name, attrs).unwrap();
+		assert_eq!(message.name.1, "message");
+		match &message.children[0] {
+			tree::Node::Element(body) => assert_eq!(body.name.1, "body"),
+			other => panic!("unexpected child: {:?}", other),
+		}
+	}
+	other => panic!("unexpected event: {:?}", other),
+}
+// stream footer
+assert!(matches!(pp.read().unwrap(), Some(ResolvedEvent::EndElement(..))));
+# }
+# #[cfg(not(feature = "std"))]
+# fn main() {}
+```
+
+   [`StartElement`]: ResolvedEvent::StartElement
+   [`EndElement`]: ResolvedEvent::EndElement
+   [`XmlError::InvalidEof`]: crate::error::XmlError::InvalidEof
+*/
+pub fn read_element<R: EventRead<Output = ResolvedEvent> + ?Sized>(
+	r: &mut R,
+	name: ResolvedQName,
+	attributes: Attributes,
+) -> Result<Element> {
+	let mut builder = ElementBuilder::with_start(name, attributes);
+	loop {
+		match r.read()? {
+			Some(ev) => {
+				if let Some(el) = builder.push(ev) {
+					return Ok(el);
+				}
+			}
+			None => return Err(Error::wfeof(ERRCTX_ELEMENT)),
+		}
+	}
+}
+
+/// Read the remainder of an element into an [`Element`], asynchronously.
+///
+/// This is the asynchronous counterpart of [`read_element`], for use with
+/// an [`AsyncParser`](crate::AsyncParser) or another
+/// [`AsyncEventRead`](crate::AsyncEventRead).
+#[cfg(any(feature = "async", feature = "futures"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "async", feature = "futures"))))]
+pub async fn read_element_async<R: crate::AsyncEventRead<Output = ResolvedEvent> + Unpin>(
+	r: &mut R,
+	name: ResolvedQName,
+	attributes: Attributes,
+) -> Result<Element> {
+	use crate::AsyncEventReadExt;
+
+	let mut builder = ElementBuilder::with_start(name, attributes);
+	loop {
+		match r.read().await? {
+			Some(ev) => {
+				if let Some(el) = builder.push(ev) {
+					return Ok(el);
+				}
+			}
+			None => return Err(Error::wfeof(ERRCTX_ELEMENT)),
+		}
+	}
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+	use super::*;
+
+	use std::convert::TryInto;
+
+	use crate::error::XmlError;
+	use crate::{as_eof_flag, FeedParser, PullParser};
+
+	fn parse(doc: &[u8]) -> Vec<ResolvedEvent> {
+		let mut fp = FeedParser::default();
+		let mut out = Vec::new();
+		fp.parse_all(&mut &doc[..], true, |ev| out.push(ev))
+			.unwrap();
+		out
+	}
+
+	#[test]
+	fn builder_builds_nested_tree_with_merged_text() {
+		let mut builder = ElementBuilder::new();
+		let mut result = None;
+		for ev in parse(b"<a xmlns='urn:x' b='c'>foo<![CDATA[bar]]><d>baz</d>&amp;<e/></a>") {
+			assert!(result.is_none());
+			result = builder.push(ev);
+		}
+		let a = result.unwrap();
+		assert!(!builder.is_building());
+		assert_eq!(a.name.1, "a");
+		assert_eq!(a.name.0.as_ref().unwrap().as_str(), "urn:x");
+		assert_eq!(&*a.attributes.get_by_name(None, "b").unwrap(), "c");
+		assert_eq!(a.children.len(), 4);
+		match &a.children[0] {
+			Node::Text(t) => assert_eq!(t, "foobar"),
+			other => panic!("unexpected child: {:?}", other),
+		}
+		match &a.children[1] {
+			Node::Element(d) => {
+				assert_eq!(d.name.1, "d");
+				assert_eq!(d.children, vec![Node::Text("baz".try_into().unwrap())]);
+			}
+			other => panic!("unexpected child: {:?}", other),
+		}
+		match &a.children[2] {
+			Node::Text(t) => assert_eq!(t, "&"),
+			other => panic!("unexpected child: {:?}", other),
+		}
+		match &a.children[3] {
+			Node::Element(e) => assert!(e.children.is_empty()),
+			other => panic!("unexpected child: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn builder_ignores_events_outside_of_elements() {
+		let mut builder = ElementBuilder::new();
+		let evs = parse(b"<?xml version='1.0'?><a/>");
+		assert!(builder.push(evs[0].clone()).is_none());
+		let text = ResolvedEvent::Text(
+			crate::parser::EventMetrics::new(0),
+			"\n".try_into().unwrap(),
+		);
+		assert!(builder.push(text).is_none());
+		assert!(builder
+			.push(ResolvedEvent::EndElement(crate::parser::EventMetrics::new(
+				0
+			)))
+			.is_none());
+		assert!(builder.push(evs[1].clone()).is_none());
+		assert!(builder.is_building());
+		assert_eq!(builder.push(evs[2].clone()).unwrap().name.1, "a");
+	}
+
+	#[test]
+	fn read_element_reads_each_stanza() {
+		let mut pp = PullParser::new(
+			&b"<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'><message><body>Hi</body></message> <presence type='probe'/></stream:stream>"[..],
+		);
+		let mut stanzas = Vec::new();
+		let mut depth = 0;
+		while let Some(ev) = pp.read().unwrap() {
+			match ev {
+				ResolvedEvent::StartElement(_, name, attrs, _) if depth == 1 => {
+					stanzas.push(read_element(&mut pp, name, attrs).unwrap());
+				}
+				ResolvedEvent::StartElement(..) => depth += 1,
+				ResolvedEvent::EndElement(..) => depth -= 1,
+				_ => (),
+			}
+		}
+		assert_eq!(depth, 0);
+		assert_eq!(stanzas.len(), 2);
+		assert_eq!(
+			stanzas[0].name.0.as_ref().unwrap().as_str(),
+			"jabber:client"
+		);
+		assert_eq!(
+			&*stanzas[1].attributes.get_by_name(None, "type").unwrap(),
+			"probe"
+		);
+	}
+
+	#[test]
+	fn read_element_rejects_truncated_element() {
+		let mut fp = FeedParser::default();
+		let mut evs = Vec::new();
+		as_eof_flag(fp.parse_all(&mut &b"<a><b>"[..], false, |ev| evs.push(ev))).unwrap();
+		let mut evs = evs.into_iter();
+		struct Events<I>(I);
+		impl<I: Iterator<Item = ResolvedEvent>> EventRead for Events<I> {
+			type Output = ResolvedEvent;
+
+			fn read(&mut self) -> Result<Option<ResolvedEvent>> {
+				Ok(self.0.next())
+			}
+		}
+		let (name, attrs) = match evs.next() {
+			Some(ResolvedEvent::StartElement(_, name, attrs, _)) => (name, attrs),
+			other => panic!("unexpected event: {:?}", other),
+		};
+		match read_element(&mut Events(evs), name, attrs) {
+			Err(Error::Xml(XmlError::InvalidEof(_))) => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
+	#[cfg(feature = "async")]
+	#[tokio::test]
+	async fn read_element_async_reads_element() {
+		use crate::{AsyncEventReadExt, AsyncParser};
+
+		let mut ap = AsyncParser::new(&b"<a><b x='y'>text</b></a>"[..]);
+		ap.read().await.unwrap();
+		let b = match ap.read().await.unwrap() {
+			Some(ResolvedEvent::StartElement(_, name, attrs, _)) => {
+				read_element_async(&mut ap, name, attrs).await.unwrap()
+			}
+			other => panic!("unexpected event: {:?}", other),
+		};
+		assert_eq!(b.name.1, "b");
+		assert_eq!(b.children, vec![Node::Text("text".try_into().unwrap())]);
+		assert!(matches!(
+			ap.read().await.unwrap(),
+			Some(ResolvedEvent::EndElement(..))
+		));
+		assert!(ap.read().await.unwrap().is_none());
+	}
+
+	#[test]
+	fn element_serializes_to_equivalent_tree() {
+		let doc = "<a xmlns='urn:a' xmlns:p='urn:p' p:x='&quot;'>t<p:b xml:lang='de'><c xmlns='urn:c'/></p:b>&amp;<d/></a>";
+		let el = crate::parse_tree(doc).unwrap();
+		let out = String::from_utf8(el.to_vec().unwrap()).unwrap();
+		assert_eq!(crate::parse_tree(&out).unwrap(), el);
+	}
+
+	#[test]
+	fn element_serializes_into_enclosing_document() {
+		let mut encoder = crate::Encoder::new();
+		let mut out = Vec::new();
+		let ns: crate::parser::NamespaceName =
+			crate::parser::RcPtr::new("urn:a".try_into().unwrap());
+		encoder
+			.encode(
+				Item::ElementHeadStart(Some(ns), "root".try_into().unwrap()),
+				&mut out,
+			)
+			.unwrap();
+		encoder.encode(Item::ElementHeadEnd, &mut out).unwrap();
+		let el = crate::parse_tree("<child xmlns='urn:a'><x xmlns='urn:b'/></child>").unwrap();
+		el.serialize(&mut encoder, &mut out).unwrap();
+		encoder.encode(Item::ElementFoot, &mut out).unwrap();
+		assert_eq!(
+			std::str::from_utf8(&out).unwrap(),
+			"<root xmlns='urn:a'><child><x xmlns='urn:b'></x></child></root>"
+		);
+	}
+
+	#[test]
+	fn element_queries_children_and_attributes() {
+		let mut el = crate::parse_tree(
+			"<a xmlns='urn:a' x='1'>t<b n='1'/><c xmlns='urn:c'><b/></c><b n='2'>u<d>v</d></b></a>",
+		)
+		.unwrap();
+		assert!(el.has_name(Some("urn:a"), "a"));
+		assert!(!el.has_name(None, "a"));
+		assert_eq!(el.child_elements().count(), 3);
+		let ns: Vec<_> = el
+			.children_named(Some("urn:a"), "b")
+			.map(|b| b.attr(None, "n").unwrap().to_string())
+			.collect();
+		assert_eq!(ns, ["1", "2"]);
+		assert!(el.first_child(Some("urn:c"), "b").is_none());
+		assert!(el
+			.first_child(Some("urn:c"), "c")
+			.unwrap()
+			.first_child(Some("urn:c"), "b")
+			.is_some());
+		assert_eq!(&*el.attr(None, "x").unwrap(), "1");
+		assert!(el.attr(Some("urn:a"), "x").is_none());
+		assert_eq!(el.text(), "tuv");
+
+		*el.attr_mut(None, "x").unwrap() = "2".try_into().unwrap();
+		assert_eq!(&*el.attr(None, "x").unwrap(), "2");
+		for b in el.children_named_mut(Some("urn:a"), "b") {
+			b.children.clear();
+		}
+		el.first_child_mut(Some("urn:c"), "c")
+			.unwrap()
+			.children
+			.clear();
+		assert_eq!(el.text(), "t");
+	}
+
+	#[test]
+	fn element_builds_and_mutates_tree() {
+		let mut el = Element::named(Some("urn:a"), "a")
+			.unwrap()
+			.with_attr((None, "x".try_into().unwrap()), "1".try_into().unwrap())
+			.with_text("foo".try_into().unwrap())
+			.with_child(Element::named(None, "b").unwrap())
+			.with_text("bar".try_into().unwrap())
+			.with_child(
+				Element::named(Some("urn:a"), "c")
+					.unwrap()
+					.with_child(Element::named(Some("urn:a"), "d").unwrap()),
+			);
+		assert!(Element::named(Some("urn:a"), "a:b").is_err());
+		assert!(Element::named(Some(""), "a").unwrap().has_name(None, "a"));
+
+		assert_eq!(
+			el.set_attr((None, "x".try_into().unwrap()), "2".try_into().unwrap())
+				.unwrap(),
+			"1"
+		);
+		el.set_attr((None, "y".try_into().unwrap()), "3".try_into().unwrap());
+		assert_eq!(el.remove_attr(None, "x").unwrap(), "2");
+		assert!(el.remove_attr(None, "x").is_none());
+
+		assert!(el.remove_child(Some("urn:a"), "b").is_none());
+		assert!(el.remove_child(None, "b").is_some());
+		// the text around the removed child has been merged
+		assert_eq!(el.children.len(), 2);
+		assert_eq!(crate::mapping::text_of(&el), "foobar");
+
+		el.set_namespace(Some(RcPtr::new("urn:b".try_into().unwrap())), true);
+		assert_eq!(
+			String::from_utf8(el.to_vec().unwrap()).unwrap(),
+			"<a xmlns='urn:b' y=\"3\">foobar<c><d></d></c></a>"
+		);
+		el.set_namespace(None, false);
+		assert!(el.has_name(None, "a"));
+		assert!(el.first_child(Some("urn:b"), "c").is_some());
+
+		el.clear_children();
+		assert!(el.children.is_empty());
+	}
+}
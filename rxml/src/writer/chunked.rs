@@ -0,0 +1,357 @@
+use alloc::vec::Vec;
+
+use bytes::{Bytes, BytesMut};
+
+use crate::parser::ResolvedEvent;
+
+use super::{EncodeError, Encoder, Item, SimpleNamespaces, TrackNamespace};
+
+/// Smallest supported chunk size, large enough to hold any UTF-8 encoded
+/// character.
+const MIN_CHUNK_SIZE: usize = 4;
+
+/**
+# Encoder splitting its output into size-limited chunks
+
+The [`ChunkedEncoder`] serializes [`Item`]s or [`ResolvedEvent`]s using an
+[`Encoder`] and hands out the result as [`Bytes`] chunks, none of which is
+larger than the configured maximum chunk size. This is useful for transports
+with bounded frames, such as XMPP over WebSocket.
+
+Chunks are preferably cut between two encoded items, i.e. at a markup
+boundary. If a single item does not fit into a chunk (e.g. a long text
+node), it is cut at the last UTF-8 character boundary which fits instead, so
+that every chunk is valid UTF-8 on its own.
+
+Encoded data is buffered until it is taken out of the encoder, either
+chunk by chunk via [`take_chunk`](Self::take_chunk) and
+[`flush_chunk`](Self::flush_chunk), via the [`chunks`](Self::chunks)
+iterator, or using callbacks with [`encode_with`](Self::encode_with) and
+[`flush`](Self::flush). Items encoded in between are packed into the same
+chunks where possible.
+
+## Example
+
+```
+use rxml::writer::ChunkedEncoder;
+use rxml::Item;
+# use std::convert::TryInto;
+
+let mut enc = ChunkedEncoder::new(8);
+let mut frames = Vec::new();
+enc.encode_with(Item::ElementHeadStart(None, "a".try_into().unwrap()), |c| frames.push(c)).unwrap();
+enc.encode_with(Item::ElementHeadEnd, |c| frames.push(c)).unwrap();
+enc.encode_with(Item::Text("hello world".try_into().unwrap()), |c| frames.push(c)).unwrap();
+enc.encode_with(Item::ElementFoot, |c| frames.push(c)).unwrap();
+enc.flush(|c| frames.push(c));
+assert_eq!(frames, &[&b"<a>"[..], b"hello wo", b"rld</a>"]);
+```
+*/
+pub struct ChunkedEncoder<T = SimpleNamespaces> {
+	encoder: Encoder<T>,
+	buf: BytesMut,
+	/// Offsets into `buf` at which an encoded item ends, in ascending order.
+	boundaries: Vec<usize>,
+	max_chunk_size: usize,
+}
+
+impl ChunkedEncoder<SimpleNamespaces> {
+	/// Create a new chunked encoder with a default encoder.
+	///
+	/// # Panics
+	///
+	/// If `max_chunk_size` is less than four bytes, as a chunk must be able
+	/// to hold any single UTF-8 encoded character.
+	pub fn new(max_chunk_size: usize) -> Self {
+		Self::wrap(Encoder::new(), max_chunk_size)
+	}
+}
+
+impl<T> ChunkedEncoder<T> {
+	/// Create a new chunked encoder using the given encoder.
+	///
+	/// # Panics
+	///
+	/// If `max_chunk_size` is less than four bytes, as a chunk must be able
+	/// to hold any single UTF-8 encoded character.
+	pub fn wrap(encoder: Encoder<T>, max_chunk_size: usize) -> Self {
+		assert!(
+			max_chunk_size >= MIN_CHUNK_SIZE,
+			"chunk size must be at least {} bytes",
+			MIN_CHUNK_SIZE
+		);
+		Self {
+			encoder,
+			buf: BytesMut::new(),
+			boundaries: Vec::new(),
+			max_chunk_size,
+		}
+	}
+
+	/// Access the encoder.
+	pub fn encoder(&self) -> &Encoder<T> {
+		&self.encoder
+	}
+
+	/// Access the encoder, mutably.
+	pub fn encoder_mut(&mut self) -> &mut Encoder<T> {
+		&mut self.encoder
+	}
+
+	/// Maximum size of the returned chunks, in bytes.
+	pub fn max_chunk_size(&self) -> usize {
+		self.max_chunk_size
+	}
+
+	/// Number of bytes which have been encoded, but not yet returned as
+	/// chunk.
+	pub fn buffered(&self) -> usize {
+		self.buf.len()
+	}
+
+	/// Decompose the chunked encoder into the inner encoder.
+	///
+	/// Any data which has not been taken out as chunk is lost.
+	pub fn into_inner(self) -> Encoder<T> {
+		self.encoder
+	}
+
+	/// Return the next chunk if it is complete.
+	///
+	/// A chunk is complete if at least [`max_chunk_size`] bytes are
+	/// buffered, as later items cannot be added to it anymore. Otherwise,
+	/// `None` is returned and the buffered data is kept to be packed
+	/// together with the following items.
+	///
+	///   [`max_chunk_size`]: Self::max_chunk_size
+	pub fn take_chunk(&mut self) -> Option<Bytes> {
+		if self.buf.len() < self.max_chunk_size {
+			return None;
+		}
+		Some(self.split_chunk())
+	}
+
+	/// Return the next chunk, even if it is not complete.
+	///
+	/// Returns `None` if no data is buffered.
+	pub fn flush_chunk(&mut self) -> Option<Bytes> {
+		if self.buf.is_empty() {
+			return None;
+		}
+		Some(self.split_chunk())
+	}
+
+	/// Iterate over all buffered data as chunks.
+	///
+	/// Chunks are taken out of the buffer while iterating, as if by
+	/// [`flush_chunk`](Self::flush_chunk).
+	pub fn chunks(&mut self) -> Chunks<'_, T> {
+		Chunks { inner: self }
+	}
+
+	/// Pass all buffered data to `f`, chunk by chunk.
+	pub fn flush<F: FnMut(Bytes)>(&mut self, mut f: F) {
+		while let Some(chunk) = self.flush_chunk() {
+			f(chunk);
+		}
+	}
+
+	fn split_chunk(&mut self) -> Bytes {
+		let len = self.chunk_len();
+		self.boundaries.retain(|b| *b > len);
+		for b in self.boundaries.iter_mut() {
+			*b -= len;
+		}
+		self.buf.split_to(len).freeze()
+	}
+
+	/// Determine the length of the next chunk.
+	fn chunk_len(&self) -> usize {
+		let max = self.max_chunk_size;
+		if self.buf.len() <= max {
+			return self.buf.len();
+		}
+		if let Some(b) = self.boundaries.iter().rev().find(|b| **b <= max) {
+			return *b;
+		}
+		// no item ends within the chunk, so we have to cut in the middle of
+		// one; step back until we are no longer in the middle of a character
+		let mut len = max;
+		while self.buf[len] & 0xc0 == 0x80 {
+			len -= 1;
+		}
+		len
+	}
+
+	fn mark_boundary(&mut self) {
+		let len = self.buf.len();
+		if len > 0 && self.boundaries.last() != Some(&len) {
+			self.boundaries.push(len);
+		}
+	}
+}
+
+impl<T: TrackNamespace> ChunkedEncoder<T> {
+	/// Encode a single item into the buffer.
+	///
+	/// Use [`take_chunk`](Self::take_chunk) or [`chunks`](Self::chunks) to
+	/// obtain the encoded data.
+	pub fn encode(&mut self, item: Item<'_>) -> Result<(), EncodeError> {
+		self.encoder.encode_into_bytes(item, &mut self.buf)?;
+		self.mark_boundary();
+		Ok(())
+	}
+
+	/// Encode a single event into the buffer.
+	///
+	/// See [`Encoder::encode_event`] for details.
+	pub fn encode_event(&mut self, ev: &ResolvedEvent) -> Result<(), EncodeError> {
+		self.encoder.encode_event_into_bytes(ev, &mut self.buf)?;
+		self.mark_boundary();
+		Ok(())
+	}
+
+	/// Encode a single item and pass all complete chunks to `f`.
+	///
+	/// Data which does not fill a complete chunk yet stays buffered; call
+	/// [`flush`](Self::flush) at the end to obtain it.
+	pub fn encode_with<F: FnMut(Bytes)>(
+		&mut self,
+		item: Item<'_>,
+		mut f: F,
+	) -> Result<(), EncodeError> {
+		self.encode(item)?;
+		while let Some(chunk) = self.take_chunk() {
+			f(chunk);
+		}
+		Ok(())
+	}
+
+	/// Encode a single event and pass all complete chunks to `f`.
+	///
+	/// See [`encode_with`](Self::encode_with) for details.
+	pub fn encode_event_with<F: FnMut(Bytes)>(
+		&mut self,
+		ev: &ResolvedEvent,
+		mut f: F,
+	) -> Result<(), EncodeError> {
+		self.encode_event(ev)?;
+		while let Some(chunk) = self.take_chunk() {
+			f(chunk);
+		}
+		Ok(())
+	}
+}
+
+/// Iterator over the buffered data of a [`ChunkedEncoder`].
+///
+/// See [`ChunkedEncoder::chunks`].
+pub struct Chunks<'x, T> {
+	inner: &'x mut ChunkedEncoder<T>,
+}
+
+impl<'x, T> Iterator for Chunks<'x, T> {
+	type Item = Bytes;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.inner.flush_chunk()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use core::convert::TryInto;
+
+	fn collect<T>(enc: &mut ChunkedEncoder<T>) -> Vec<Bytes> {
+		enc.chunks().collect()
+	}
+
+	#[test]
+	fn packs_items_into_chunks() {
+		let mut enc = ChunkedEncoder::new(10);
+		enc.encode(Item::ElementHeadStart(None, "a".try_into().unwrap()))
+			.unwrap();
+		enc.encode(Item::ElementHeadEnd).unwrap();
+		enc.encode(Item::ElementHeadStart(None, "b".try_into().unwrap()))
+			.unwrap();
+		enc.encode(Item::ElementFoot).unwrap();
+		assert!(enc.take_chunk().is_none());
+		enc.encode(Item::ElementFoot).unwrap();
+		assert_eq!(enc.buffered(), 11);
+		assert_eq!(collect(&mut enc), &[&b"<a><b/>"[..], b"</a>"]);
+		assert_eq!(enc.buffered(), 0);
+	}
+
+	#[test]
+	fn prefers_item_boundaries() {
+		let mut enc = ChunkedEncoder::new(8);
+		enc.encode(Item::ElementHeadStart(None, "a".try_into().unwrap()))
+			.unwrap();
+		enc.encode(Item::Attribute(
+			None,
+			"x".try_into().unwrap(),
+			"0123".try_into().unwrap(),
+		))
+		.unwrap();
+		enc.encode(Item::ElementFoot).unwrap();
+		assert_eq!(collect(&mut enc), &[&b"<a"[..], b" x=\"0123", b"\"/>"]);
+	}
+
+	#[test]
+	fn never_splits_characters() {
+		let mut enc = ChunkedEncoder::new(5);
+		enc.encode(Item::ElementHeadStart(None, "a".try_into().unwrap()))
+			.unwrap();
+		enc.encode(Item::ElementHeadEnd).unwrap();
+		enc.encode(Item::Text("äöü€x".try_into().unwrap())).unwrap();
+		let chunks = collect(&mut enc);
+		assert_eq!(chunks[0], &b"<a>"[..]);
+		for chunk in chunks.iter() {
+			assert!(chunk.len() <= 5);
+			core::str::from_utf8(chunk).unwrap();
+		}
+		assert_eq!(chunks[1..].concat(), "äöü€x".as_bytes());
+	}
+
+	#[test]
+	fn encode_with_only_emits_complete_chunks() {
+		let mut enc = ChunkedEncoder::new(4);
+		let mut chunks = Vec::new();
+		enc.encode_with(Item::ElementHeadStart(None, "a".try_into().unwrap()), |c| {
+			chunks.push(c)
+		})
+		.unwrap();
+		assert!(chunks.is_empty());
+		enc.encode_with(Item::ElementHeadEnd, |c| chunks.push(c))
+			.unwrap();
+		assert!(chunks.is_empty());
+		enc.encode_with(Item::Text("foobar".try_into().unwrap()), |c| chunks.push(c))
+			.unwrap();
+		assert_eq!(chunks, &[&b"<a>"[..], b"foob"]);
+		assert_eq!(enc.buffered(), 2);
+		enc.encode_with(Item::ElementFoot, |c| chunks.push(c))
+			.unwrap();
+		enc.flush(|c| chunks.push(c));
+		assert_eq!(chunks, &[&b"<a>"[..], b"foob", b"ar", b"</a>"]);
+	}
+
+	#[test]
+	fn encodes_events() {
+		let mut doc = &b"<a xmlns='urn:x'>foo</a>"[..];
+		let mut fp = crate::FeedParser::default();
+		let mut enc = ChunkedEncoder::new(16);
+		fp.parse_all(&mut doc, true, |ev| enc.encode_event(&ev).unwrap())
+			.unwrap();
+		let chunks = collect(&mut enc);
+		assert!(chunks.iter().all(|c| c.len() <= 16));
+		assert_eq!(chunks.concat(), b"<a xmlns='urn:x'>foo</a>");
+	}
+
+	#[test]
+	#[should_panic(expected = "chunk size must be at least 4 bytes")]
+	fn rejects_tiny_chunk_size() {
+		ChunkedEncoder::new(3);
+	}
+}
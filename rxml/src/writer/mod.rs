@@ -1,26 +1,46 @@
 /*!
 # Writer for restricted XML 1.0
 */
-use std::collections::hash_map::Entry;
-use std::collections::HashMap;
-use std::collections::HashSet;
-use std::convert::TryInto;
-use std::fmt;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::fmt;
 
 use bytes::{BufMut, BytesMut};
 
-use crate::parser::{NamespaceName, RcPtr, ResolvedEvent, XmlVersion, XMLNS_XML, XMLNS_XMLNS};
-use crate::strings::{CData, CDataStr, Name, NcName, NcNameStr};
+use crate::collections::{Entry, Map, Set};
+use crate::context::Context;
+use crate::error::ErrorCode;
+use crate::parser::{
+	NamespaceName, RcPtr, ResolvedEvent, WithContext, XmlVersion, XMLNS_XML, XMLNS_XMLNS,
+};
+use crate::strings::{CData, CDataStr, Name, NameStr, NcName, NcNameStr};
+
+mod chunked;
+#[cfg(feature = "sink")]
+mod sink;
+#[cfg(feature = "std")]
+mod text;
+
+pub use chunked::{ChunkedEncoder, Chunks};
+
+#[cfg(feature = "sink")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sink")))]
+pub use sink::AsyncWriter;
+
+#[cfg(feature = "std")]
+pub use text::TextWriter;
 
 const XML_DECL: &'static [u8] = b"<?xml version='1.0' encoding='utf-8'?>\n";
-pub const PREFIX_XML: &'static NcNameStr = unsafe { std::mem::transmute("xml") };
-pub const PREFIX_XMLNS: &'static NcNameStr = unsafe { std::mem::transmute("xmlns") };
+pub const PREFIX_XML: &'static NcNameStr = unsafe { core::mem::transmute("xml") };
+pub const PREFIX_XMLNS: &'static NcNameStr = unsafe { core::mem::transmute("xmlns") };
 
-const CDATA_SPECIALS: &'static [u8] = &[b'<', b'>', b'&', b'\r'];
+pub(crate) const CDATA_SPECIALS: &[u8] = b"<>&\r";
 
-const ATTR_SPECIALS: &'static [u8] = &[b'"', b'\'', b'\r', b'\n', b'\t', b'<', b'>', b'&'];
+pub(crate) const ATTR_SPECIALS: &[u8] = b"\"'\r\n\t<>&";
 
-fn escape<'a, B: BufMut>(out: &'a mut B, data: &'a [u8], specials: &'static [u8]) {
+pub(crate) fn escape<'a, B: BufMut>(out: &'a mut B, data: &'a [u8], specials: &'static [u8]) {
 	let mut last_index = 0;
 	for i in 0..data.len() {
 		let ch = data[i];
@@ -52,7 +72,41 @@ fn escape<'a, B: BufMut>(out: &'a mut B, data: &'a [u8], specials: &'static [u8]
 /// items can be borrowed to improve efficiency (as a copy will have to take
 /// place anyway).
 ///
+/// Names and text are always borrowed. Namespace URIs can either be passed
+/// as [`NamespaceName`] (e.g. when re-encoding events) or, using
+/// [`ElementHeadStartRef`] and [`AttributeRef`], as a reference, which
+/// allows encoding from static or otherwise borrowed strings without
+/// allocating:
+///
+/// ```
+/// use rxml::{CDataStr, Encoder, NcNameStr};
+/// use rxml::writer::Item;
+/// use std::convert::TryInto;
+///
+/// let ns: &CDataStr = "jabber:client".try_into().unwrap();
+/// let name: &NcNameStr = "message".try_into().unwrap();
+/// let body: &CDataStr = "hello".try_into().unwrap();
+/// let mut enc = Encoder::new();
+/// let mut buf = Vec::new();
+/// for _ in 0..2 {
+/// 	enc.encode(Item::ElementHeadStartRef(Some(ns), name), &mut buf).unwrap();
+/// 	enc.encode(Item::ElementHeadEnd, &mut buf).unwrap();
+/// 	enc.encode(Item::Text(body), &mut buf).unwrap();
+/// 	enc.encode(Item::ElementFoot, &mut buf).unwrap();
+/// 	// allow a second root element for the sake of the example
+/// 	enc = Encoder::new();
+/// }
+/// assert_eq!(
+/// 	std::str::from_utf8(&buf).unwrap(),
+/// 	"<message xmlns='jabber:client'>hello</message>".repeat(2),
+/// );
+/// ```
+///
 ///   [`ResolvedEvent`]: crate::parser::ResolvedEvent
+///   [`ElementHeadStartRef`]: Self::ElementHeadStartRef
+///   [`AttributeRef`]: Self::AttributeRef
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound(deserialize = "'de: 'x")))]
 pub enum Item<'x> {
 	/// XML declaration
 	XmlDeclaration(XmlVersion),
@@ -75,6 +129,35 @@ pub enum Item<'x> {
 		&'x CDataStr,
 	),
 
+	/// Start of an element header, with a borrowed namespace URI
+	///
+	/// This is equivalent to [`ElementHeadStart`]. If the namespace URI is
+	/// declared already, the encoder reuses the [`NamespaceName`] of the
+	/// declaration instead of allocating a new one.
+	///
+	///   [`ElementHeadStart`]: Self::ElementHeadStart
+	ElementHeadStartRef(
+		/// Namespace URI or None, for unnamespaced elements
+		Option<&'x CDataStr>,
+		/// Local name of the element
+		&'x NcNameStr,
+	),
+
+	/// An attribute key/value pair, with a borrowed namespace URI
+	///
+	/// This is equivalent to [`Attribute`], see [`ElementHeadStartRef`].
+	///
+	///   [`Attribute`]: Self::Attribute
+	///   [`ElementHeadStartRef`]: Self::ElementHeadStartRef
+	AttributeRef(
+		/// Namespace URI or None, for unnamespaced attributes
+		Option<&'x CDataStr>,
+		/// Local name of the attribute
+		&'x NcNameStr,
+		/// Value of the attribute
+		&'x CDataStr,
+	),
+
 	/// End of an element header
 	ElementHeadEnd,
 
@@ -91,6 +174,106 @@ pub enum Item<'x> {
 	///   [`Text`]: Self::Text
 	///   [`ElementHeadEnd`]: Self::ElementHeadEnd
 	ElementFoot,
+
+	/// A comment
+	///
+	/// Comments are only emitted if enabled via
+	/// [`Encoder::allow_comments_and_pis`]. The text must not contain `--`
+	/// and must not end with `-`.
+	Comment(&'x CDataStr),
+
+	/// A processing instruction
+	///
+	/// Processing instructions are only emitted if enabled via
+	/// [`Encoder::allow_comments_and_pis`]. The target must not be `xml` (in
+	/// any capitalization) and the data must not contain `?>`. If the data is
+	/// empty, it is omitted entirely.
+	ProcessingInstruction(
+		/// Target of the processing instruction
+		&'x NcNameStr,
+		/// Data of the processing instruction
+		&'x CDataStr,
+	),
+
+	/// Pre-serialized XML, written to the output verbatim
+	///
+	/// This is an escape hatch to splice trusted, already serialized XML
+	/// fragments into the output without re-parsing them. It is only
+	/// allowed where [`Text`] would be allowed.
+	///
+	/// **No checks whatsoever** are performed on the data: neither for
+	/// well-formedness, nor for UTF-8 validity, nor for balanced elements.
+	/// The encoder does not track any namespace declarations or elements
+	/// contained in the fragment, so the fragment must not rely on prefixes
+	/// declared by the encoder (other than `xml`) and must not change the
+	/// nesting. Passing untrusted data here allows injection of arbitrary
+	/// markup into the document.
+	///
+	///   [`Text`]: Self::Text
+	Raw(&'x [u8]),
+
+	/// Namespace declaration for the next element
+	///
+	/// Declares a prefix (or, with `None`, the default namespace) on the
+	/// element started by the next [`ElementHeadStart`]. The declaration is
+	/// recorded with the namespace tracker, so that the element itself, its
+	/// attributes and (depending on the tracker) its descendants use the
+	/// given prefix instead of an auto-generated one. This allows, for
+	/// instance, to declare the content namespace on a stream header before
+	/// any child element uses it.
+	///
+	/// Any number of declarations may precede an [`ElementHeadStart`], but
+	/// no other items may be placed between them. A namespace name of
+	/// `None` is only allowed for the default namespace, where it
+	/// undeclares the default namespace of the parent element.
+	///
+	/// Declarations which conflict with the namespace tracker's own
+	/// declarations are rejected with
+	/// [`EncodeError::InvalidNamespaceDeclaration`], see
+	/// [`TrackNamespace::can_declare_fixed`]. In particular,
+	/// [`SimpleNamespaces`] does not allow redeclaring prefixes declared on
+	/// the root element.
+	///
+	///   [`ElementHeadStart`]: Self::ElementHeadStart
+	NamespaceDeclaration(
+		/// Prefix to declare, or None for the default namespace
+		Option<&'x NcNameStr>,
+		/// Namespace URI to bind the prefix to
+		Option<NamespaceName>,
+	),
+}
+
+/// Generates the items which are accepted by an encoder with the default
+/// settings.
+///
+/// Comments, processing instructions, raw data and namespace declarations
+/// are never generated, and
+/// namespace names are never empty or the one reserved for `xmlns`. The
+/// items are valid on their own, but a sequence of them generally does not
+/// form a well-formed document.
+#[cfg(feature = "arbitrary")]
+#[cfg_attr(docsrs, doc(cfg(feature = "arbitrary")))]
+impl<'a> arbitrary::Arbitrary<'a> for Item<'a> {
+	fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+		use crate::parser::arbitrary_namespace;
+
+		Ok(match u.int_in_range(0..=5u8)? {
+			0 => Self::XmlDeclaration(XmlVersion::V1_0),
+			1 => Self::ElementHeadStart(arbitrary_namespace(u)?, u.arbitrary()?),
+			2 => {
+				let ns = arbitrary_namespace(u)?;
+				let name: &NcNameStr = u.arbitrary()?;
+				if ns.is_none() && name == "xmlns" {
+					Self::ElementHeadEnd
+				} else {
+					Self::Attribute(ns, name, u.arbitrary()?)
+				}
+			}
+			3 => Self::ElementHeadEnd,
+			4 => Self::Text(u.arbitrary()?),
+			_ => Self::ElementFoot,
+		})
+	}
 }
 
 #[derive(Debug)]
@@ -126,6 +309,17 @@ pub trait TrackNamespace {
 	/// is a programming error and causes a panic.
 	fn declare_fixed(&mut self, prefix: Option<&NcNameStr>, name: Option<NamespaceName>) -> bool;
 
+	/// Return whether `prefix` can be passed to
+	/// [`declare_fixed`](Self::declare_fixed) for the upcoming element
+	/// without conflicting with the declarations already made.
+	///
+	/// The [`Encoder`] uses this to reject conflicting
+	/// [`Item::NamespaceDeclaration`]s with an error instead of a panic. The
+	/// default implementation returns true.
+	fn can_declare_fixed(&self, _prefix: Option<&NcNameStr>) -> bool {
+		true
+	}
+
 	/// Declare a namespace URI with an auto-generated prefix or by using the
 	/// default namespace.
 	///
@@ -192,6 +386,17 @@ pub trait TrackNamespace {
 	fn new_prefix_declarations<'x>(
 		&'x self,
 	) -> Box<dyn Iterator<Item = (&Option<NamespaceName>, &NcNameStr)> + 'x>;
+
+	/// Return the namespace name of a namespace URI which is currently
+	/// declared.
+	///
+	/// This is used to encode [`Item::ElementHeadStartRef`] and
+	/// [`Item::AttributeRef`] without allocating a new namespace name. The
+	/// default implementation returns `None`, so that a new namespace name
+	/// is allocated for each such item.
+	fn lookup_namespace(&self, _uri: &CDataStr) -> Option<NamespaceName> {
+		None
+	}
 }
 
 /// Simple namespace tracker.
@@ -210,30 +415,30 @@ pub trait TrackNamespace {
 #[derive(Debug)]
 pub struct SimpleNamespaces {
 	// persistent state
-	global_ns: HashMap<Option<NamespaceName>, NcName>,
-	global_ns_rev: HashSet<NcName>,
+	global_ns: Map<Option<NamespaceName>, NcName>,
+	global_ns_rev: Set<NcName>,
 	global_ns_ctr: usize,
 	default_ns_stack: Vec<Option<NamespaceName>>,
 
 	// temporary per-element state
 	next_default_ns: Option<Option<NamespaceName>>,
 	temp_ns_ctr: usize,
-	temp_ns: HashMap<Option<NamespaceName>, NcName>,
-	temp_ns_rev: HashSet<NcName>,
+	temp_ns: Map<Option<NamespaceName>, NcName>,
+	temp_ns_rev: Set<NcName>,
 }
 
 impl SimpleNamespaces {
 	pub fn new() -> Self {
 		Self {
-			global_ns: HashMap::new(),
-			global_ns_rev: HashSet::new(),
+			global_ns: Map::new(),
+			global_ns_rev: Set::new(),
 			global_ns_ctr: 0,
 			default_ns_stack: Vec::new(),
 			// default default ns name is empty str
 			next_default_ns: None,
 			temp_ns_ctr: 0,
-			temp_ns: HashMap::new(),
-			temp_ns_rev: HashSet::new(),
+			temp_ns: Map::new(),
+			temp_ns_rev: Set::new(),
 		}
 	}
 
@@ -274,6 +479,20 @@ impl SimpleNamespaces {
 }
 
 impl TrackNamespace for SimpleNamespaces {
+	fn lookup_namespace(&self, uri: &CDataStr) -> Option<NamespaceName> {
+		let defaults = self
+			.next_default_ns
+			.iter()
+			.chain(self.default_ns_stack.last())
+			.filter_map(|x| x.as_ref());
+		let prefixed = self
+			.temp_ns
+			.keys()
+			.chain(self.global_ns.keys())
+			.filter_map(|x| x.as_ref());
+		defaults.chain(prefixed).find(|x| &****x == uri).cloned()
+	}
+
 	fn declare_fixed(&mut self, prefix: Option<&NcNameStr>, name: Option<NamespaceName>) -> bool {
 		match prefix.as_ref() {
 			Some(v) if *v == PREFIX_XML => {
@@ -326,6 +545,15 @@ impl TrackNamespace for SimpleNamespaces {
 		}
 	}
 
+	fn can_declare_fixed(&self, prefix: Option<&NcNameStr>) -> bool {
+		match prefix {
+			Some(prefix) => {
+				!self.global_ns_rev.contains(prefix) && !self.temp_ns_rev.contains(prefix)
+			}
+			None => self.next_default_ns.is_none(),
+		}
+	}
+
 	fn declare_auto(&mut self, name: Option<NamespaceName>) -> (bool, Option<&NcNameStr>) {
 		match name {
 			Some(v) if *v == XMLNS_XML => return (false, Some(PREFIX_XML)),
@@ -453,8 +681,8 @@ impl TrackNamespace for SimpleNamespaces {
 		}
 		if self.default_ns_stack.len() == 1 {
 			// the first element! globalize the declarations
-			std::mem::swap(&mut self.global_ns, &mut self.temp_ns);
-			std::mem::swap(&mut self.global_ns_rev, &mut self.temp_ns_rev);
+			core::mem::swap(&mut self.global_ns, &mut self.temp_ns);
+			core::mem::swap(&mut self.global_ns_rev, &mut self.temp_ns_rev);
 			self.global_ns_ctr = self.temp_ns_ctr;
 		}
 
@@ -491,7 +719,8 @@ pub enum EncodeError {
 	/// Emitted if any content is placed after the end of the last element.
 	EndOfDocument,
 
-	/// Emitted if text is placed inside an element heading
+	/// Emitted if text (or a raw fragment) is placed inside an element
+	/// heading or outside of the root element
 	TextNotAllowed,
 
 	/// Emitted if attribute is placed outside of element heading
@@ -505,6 +734,52 @@ pub enum EncodeError {
 
 	/// Emitted on unbalanced element head start/end
 	NoOpenElement,
+
+	/// Emitted if a comment or processing instruction is encoded without
+	/// having been enabled via [`Encoder::allow_comments_and_pis`].
+	CommentsAndPIsDisabled,
+
+	/// Emitted if a comment or processing instruction is placed inside an
+	/// element header
+	CommentOrPINotAllowed,
+
+	/// Emitted if the text of a comment contains `--` or ends with `-`
+	InvalidComment,
+
+	/// Emitted if a processing instruction uses a reserved target or its
+	/// data contains `?>`
+	InvalidProcessingInstruction,
+
+	/// Emitted if a namespace declaration is placed anywhere but before an
+	/// element header, or if anything but an element header follows it
+	MisplacedNamespaceDeclaration,
+
+	/// Emitted if a namespace declaration uses a reserved prefix or
+	/// namespace name, binds a prefix to the empty namespace, duplicates
+	/// another declaration for the same element or contradicts the
+	/// namespace of that element
+	InvalidNamespaceDeclaration,
+}
+
+impl EncodeError {
+	/// Return the stable code of this error.
+	pub fn code(&self) -> ErrorCode {
+		match self {
+			Self::MisplacedXmlDeclaration => ErrorCode::MisplacedXmlDeclaration,
+			Self::EndOfDocument => ErrorCode::EndOfDocument,
+			Self::TextNotAllowed => ErrorCode::TextNotAllowed,
+			Self::AttributeNotAllowed => ErrorCode::AttributeNotAllowed,
+			Self::ElementStartNotAllowed => ErrorCode::ElementStartNotAllowed,
+			Self::ElementFootNotAllowed => ErrorCode::ElementFootNotAllowed,
+			Self::NoOpenElement => ErrorCode::NoOpenElement,
+			Self::CommentsAndPIsDisabled => ErrorCode::CommentsAndPIsDisabled,
+			Self::CommentOrPINotAllowed => ErrorCode::CommentOrPINotAllowed,
+			Self::InvalidComment => ErrorCode::InvalidComment,
+			Self::InvalidProcessingInstruction => ErrorCode::InvalidProcessingInstruction,
+			Self::MisplacedNamespaceDeclaration => ErrorCode::MisplacedNamespaceDeclaration,
+			Self::InvalidNamespaceDeclaration => ErrorCode::InvalidNamespaceDeclaration,
+		}
+	}
 }
 
 impl fmt::Display for EncodeError {
@@ -523,10 +798,27 @@ impl fmt::Display for EncodeError {
 			Self::ElementFootNotAllowed => f.write_str(
 				"cannot close element while writing the header or before the root element",
 			),
+			Self::CommentsAndPIsDisabled => {
+				f.write_str("comments and processing instructions are not enabled")
+			}
+			Self::CommentOrPINotAllowed => f.write_str(
+				"comments and processing instructions not allowed inside element headers",
+			),
+			Self::InvalidComment => {
+				f.write_str("comment must not contain `--` and must not end with `-`")
+			}
+			Self::InvalidProcessingInstruction => {
+				f.write_str("processing instruction must not use a reserved target or contain `?>`")
+			}
+			Self::MisplacedNamespaceDeclaration => {
+				f.write_str("namespace declarations must directly precede an element header")
+			}
+			Self::InvalidNamespaceDeclaration => f.write_str("invalid namespace declaration"),
 		}
 	}
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for EncodeError {}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -552,11 +844,35 @@ let mut buf = BytesMut::new();
 enc.encode(Item::XmlDeclaration(XmlVersion::V1_0), &mut buf);
 assert_eq!(&buf[..], b"<?xml version='1.0' encoding='utf-8'?>\n");
 ```
+
+The encoder writes into any [`bytes::BufMut`]. When encoding into a
+[`BytesMut`], the serialized data can be split off and frozen into a
+[`bytes::Bytes`] without copying, e.g. to hand a complete stanza to a tokio
+writer while reusing the buffer for the next one:
+
+```rust
+use rxml::{Encoder, Item};
+use bytes::BytesMut;
+# use std::convert::TryInto;
+
+let mut enc = Encoder::new();
+let mut buf = BytesMut::with_capacity(1024);
+enc.encode(Item::ElementHeadStart(None, "stream".try_into().unwrap()), &mut buf).unwrap();
+enc.encode(Item::ElementHeadEnd, &mut buf).unwrap();
+let header: bytes::Bytes = buf.split().freeze();
+assert_eq!(&header[..], b"<stream>");
+assert_eq!(buf.len(), 0);
+```
 */
 pub struct Encoder<T> {
 	state: EncoderState,
 	qname_stack: Vec<Name>,
 	ns: T,
+	allow_comments_and_pis: bool,
+	/// Declarations made via [`Item::NamespaceDeclaration`] for the upcoming
+	/// element.
+	pending_decls: Vec<(Option<NcName>, Option<NamespaceName>)>,
+	ctx: Option<RcPtr<Context>>,
 }
 
 impl Encoder<SimpleNamespaces> {
@@ -570,10 +886,25 @@ impl Encoder<SimpleNamespaces> {
 			state: EncoderState::Start,
 			qname_stack: Vec::new(),
 			ns: SimpleNamespaces::new(),
+			allow_comments_and_pis: false,
+			pending_decls: Vec::new(),
+			ctx: None,
 		}
 	}
 }
 
+impl WithContext for Encoder<SimpleNamespaces> {
+	/// Create a new default encoder which obtains namespace names from the
+	/// given context.
+	///
+	/// See [`Encoder::set_context`] for details.
+	fn with_context(ctx: RcPtr<Context>) -> Self {
+		let mut result = Self::new();
+		result.set_context(Some(ctx));
+		result
+	}
+}
+
 impl<T: TrackNamespace> From<T> for Encoder<T> {
 	/// Create an encoder from a [`TrackNamespace`] instance.
 	///
@@ -583,6 +914,9 @@ impl<T: TrackNamespace> From<T> for Encoder<T> {
 			state: EncoderState::Start,
 			qname_stack: Vec::new(),
 			ns,
+			allow_comments_and_pis: false,
+			pending_decls: Vec::new(),
+			ctx: None,
 		}
 	}
 }
@@ -613,10 +947,123 @@ impl<T: TrackNamespace> Encoder<T> {
 		&self.ns
 	}
 
+	/// Return the number of currently open elements.
+	///
+	/// An element counts as open from its [`Item::ElementHeadStart`] until
+	/// the matching [`Item::ElementFoot`].
+	pub fn depth(&self) -> usize {
+		self.qname_stack.len()
+	}
+
+	/// Iterate over the names of the currently open elements, starting at
+	/// the root element.
+	///
+	/// The names are returned as they were written to the output, i.e.
+	/// including the namespace prefix chosen by the namespace tracker, if
+	/// any.
+	pub fn open_elements(&self) -> impl Iterator<Item = &NameStr> + '_ {
+		self.qname_stack.iter().map(|x| &**x)
+	}
+
+	/// Close all currently open elements.
+	///
+	/// If an element header is currently being written, the element is
+	/// closed using `/>`. Afterwards, element footers are emitted until the
+	/// root element has been closed.
+	///
+	/// If no element is open, this is a no-op.
+	pub fn close_all<O: BufMut>(&mut self, output: &mut O) -> Result<(), EncodeError> {
+		while !self.qname_stack.is_empty() {
+			self.encode(Item::ElementFoot, output)?;
+		}
+		Ok(())
+	}
+
+	/// Enable or disable encoding of [`Item::Comment`] and
+	/// [`Item::ProcessingInstruction`].
+	///
+	/// This is disabled by default, as the parser of this crate rejects
+	/// both comments and processing instructions. Output containing them can
+	/// thus not be read back with rxml.
+	pub fn allow_comments_and_pis(mut self, allow: bool) -> Self {
+		self.allow_comments_and_pis = allow;
+		self
+	}
+
 	pub fn inner_mut(&mut self) -> &mut T {
 		&mut self.ns
 	}
 
+	/// Set the context from which the encoder obtains namespace names.
+	///
+	/// Namespace URIs passed by reference (see [`Item::ElementHeadStartRef`]
+	/// and [`Item::AttributeRef`]) which are not declared yet are interned
+	/// in the context instead of being allocated separately. When the
+	/// context is shared with a parser, documents which are echoed or
+	/// rewritten thus use the same namespace names on both sides, including
+	/// those registered with [`Context::register_namespace`].
+	///
+	/// The context does not record prefixes, so it has no influence on the
+	/// prefixes chosen by the encoder: these are still picked by the
+	/// [`TrackNamespace`] implementation, independent of the prefixes used
+	/// in the parsed document. To keep the prefixes of the input, pass them
+	/// on explicitly with [`Item::NamespaceDeclaration`]: the prefix of an
+	/// element name is part of [`ResolvedEvent::StartElement`], and the
+	/// declarations are reported as
+	/// [prefix mapping events](crate::Parser::set_emit_prefix_mappings).
+	///
+	/// ```
+	/// # #[cfg(feature = "std")]
+	/// # fn main() {
+	/// use rxml::{CDataStr, Context, Encoder, Item, NcNameStr, WithContext};
+	/// use rxml::parser::RcPtr;
+	/// use rxml::writer::TrackNamespace;
+	/// use std::convert::TryInto;
+	///
+	/// let ctx = RcPtr::new(Context::new());
+	/// let ns: &CDataStr = "jabber:client".try_into().unwrap();
+	/// let client = ctx.register_namespace(ns);
+	/// let mut enc = Encoder::with_context(ctx);
+	/// let mut buf = Vec::new();
+	/// let name: &NcNameStr = "message".try_into().unwrap();
+	/// enc.encode(Item::ElementHeadStartRef(Some(ns), name), &mut buf).unwrap();
+	/// let declared = enc.inner().new_default_declaration().unwrap();
+	/// assert!(client.matches(declared));
+	/// # }
+	/// # #[cfg(not(feature = "std"))]
+	/// # fn main() {}
+	/// ```
+	pub fn set_context(&mut self, ctx: Option<RcPtr<Context>>) {
+		self.ctx = ctx;
+	}
+
+	/// Return the context used by the encoder, if any.
+	pub fn context(&self) -> Option<&RcPtr<Context>> {
+		self.ctx.as_ref()
+	}
+
+	/// Obtain a writer which emits the data written to it as text content
+	/// of the current element.
+	///
+	/// This allows to stream large text nodes into `output` without
+	/// materializing them as [`CData`] first. The writer borrows the
+	/// encoder, so that no other items can be encoded until it is dropped.
+	/// See [`TextWriter`] for details.
+	///
+	/// Returns [`EncodeError::TextNotAllowed`] if text is not allowed at the
+	/// current position.
+	#[cfg(feature = "std")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+	pub fn text_writer<'x, O: BufMut>(
+		&'x mut self,
+		output: &'x mut O,
+	) -> Result<TextWriter<'x, T, O>, EncodeError> {
+		match self.state {
+			EncoderState::Content => Ok(TextWriter::new(self, output)),
+			_ => Err(EncodeError::TextNotAllowed),
+		}
+	}
+
 	/// Encode a single item into a buffer.
 	///
 	/// There is no requirement for the buffer to be the same for subsequent
@@ -624,8 +1071,31 @@ impl<T: TrackNamespace> Encoder<T> {
 	/// long-lived, buffers for serialization before sending data over the
 	/// network, for instance.
 	pub fn encode<O: BufMut>(&mut self, item: Item<'_>, output: &mut O) -> Result<(), EncodeError> {
-		if self.state == EncoderState::EndOfDocument {
-			return Err(EncodeError::EndOfDocument);
+		let item = match item {
+			Item::ElementHeadStartRef(nsuri, local_name) => {
+				Item::ElementHeadStart(self.namespace_name(nsuri), local_name)
+			}
+			Item::AttributeRef(nsuri, local_name, value) => {
+				Item::Attribute(self.namespace_name(nsuri), local_name, value)
+			}
+			other => other,
+		};
+
+		if !self.pending_decls.is_empty() {
+			match item {
+				Item::NamespaceDeclaration(..) | Item::ElementHeadStart(..) => (),
+				_ => return Err(EncodeError::MisplacedNamespaceDeclaration),
+			}
+		}
+
+		match item {
+			// comments and PIs may also appear after the root element
+			Item::Comment(_) | Item::ProcessingInstruction(..) => (),
+			_ => {
+				if self.state == EncoderState::EndOfDocument {
+					return Err(EncodeError::EndOfDocument);
+				}
+			}
 		}
 
 		match item {
@@ -637,8 +1107,39 @@ impl<T: TrackNamespace> Encoder<T> {
 				}
 				_ => Err(EncodeError::MisplacedXmlDeclaration),
 			},
+			Item::NamespaceDeclaration(prefix, nsuri) => match self.state {
+				EncoderState::Start | EncoderState::Declared | EncoderState::Content => {
+					self.check_nsdecl(prefix, nsuri.as_ref())?;
+					// the declarations are only passed on to the namespace
+					// tracker with the element, so that nothing needs to be
+					// undone if the element is rejected
+					self.pending_decls
+						.push((prefix.map(|x| x.to_ncname()), nsuri));
+					Ok(())
+				}
+				_ => Err(EncodeError::MisplacedNamespaceDeclaration),
+			},
 			Item::ElementHeadStart(nsuri, local_name) => match self.state {
 				EncoderState::Start | EncoderState::Declared | EncoderState::Content => {
+					// the element cannot be unnamespaced if a default
+					// namespace is explicitly declared on it
+					let invalid = nsuri.is_none()
+						&& self
+							.pending_decls
+							.iter()
+							.any(|(prefix, name)| prefix.is_none() && name.is_some());
+					if invalid
+						|| self
+							.pending_decls
+							.iter()
+							.any(|(prefix, _)| !self.ns.can_declare_fixed(prefix.as_deref()))
+					{
+						self.pending_decls.clear();
+						return Err(EncodeError::InvalidNamespaceDeclaration);
+					}
+					for (prefix, name) in self.pending_decls.drain(..) {
+						self.ns.declare_fixed(prefix.as_deref(), name);
+					}
 					output.put_u8(b'<');
 					let (_, prefix) = self.ns.declare_auto(nsuri.clone());
 					let qname = match prefix {
@@ -711,6 +1212,13 @@ impl<T: TrackNamespace> Encoder<T> {
 				}
 				_ => Err(EncodeError::TextNotAllowed),
 			},
+			Item::Raw(data) => match self.state {
+				EncoderState::Content => {
+					output.put_slice(data);
+					Ok(())
+				}
+				_ => Err(EncodeError::TextNotAllowed),
+			},
 			Item::ElementFoot => match self.state {
 				EncoderState::Content => {
 					self.ns.pop();
@@ -736,6 +1244,103 @@ impl<T: TrackNamespace> Encoder<T> {
 				}
 				_ => Err(EncodeError::ElementFootNotAllowed),
 			},
+			Item::Comment(text) => {
+				self.check_comment_or_pi_allowed()?;
+				if text.contains("--") || text.ends_with('-') {
+					return Err(EncodeError::InvalidComment);
+				}
+				output.put_slice(b"<!--");
+				output.put_slice(text.as_bytes());
+				output.put_slice(b"-->");
+				self.finish_comment_or_pi();
+				Ok(())
+			}
+			Item::ProcessingInstruction(target, data) => {
+				self.check_comment_or_pi_allowed()?;
+				if target.eq_ignore_ascii_case("xml") || data.contains("?>") {
+					return Err(EncodeError::InvalidProcessingInstruction);
+				}
+				output.put_slice(b"<?");
+				output.put_slice(target.as_bytes());
+				if !data.is_empty() {
+					output.put_u8(b' ');
+					output.put_slice(data.as_bytes());
+				}
+				output.put_slice(b"?>");
+				self.finish_comment_or_pi();
+				Ok(())
+			}
+			Item::ElementHeadStartRef(..) | Item::AttributeRef(..) => {
+				unreachable!("borrowed namespace URIs have been resolved above")
+			}
+		}
+	}
+
+	/// Obtain the namespace name for a borrowed namespace URI, reusing the
+	/// one of a matching declaration or of the context if possible.
+	fn namespace_name(&self, nsuri: Option<&CDataStr>) -> Option<NamespaceName> {
+		let nsuri = nsuri?;
+		let pending = self
+			.pending_decls
+			.iter()
+			.filter_map(|(_, name)| name.as_ref())
+			.find(|name| &***name == nsuri);
+		if let Some(name) = pending {
+			return Some(name.clone());
+		}
+		Some(
+			self.ns
+				.lookup_namespace(nsuri)
+				.unwrap_or_else(|| match self.ctx {
+					Some(ref ctx) => ctx.intern_cdata(nsuri),
+					None => RcPtr::new(nsuri.to_cdata()),
+				}),
+		)
+	}
+
+	fn check_nsdecl(
+		&self,
+		prefix: Option<&NcNameStr>,
+		nsuri: Option<&NamespaceName>,
+	) -> Result<(), EncodeError> {
+		if let Some(prefix) = prefix {
+			if prefix == PREFIX_XML || prefix == PREFIX_XMLNS || nsuri.is_none() {
+				return Err(EncodeError::InvalidNamespaceDeclaration);
+			}
+		}
+		if !self.ns.can_declare_fixed(prefix) {
+			return Err(EncodeError::InvalidNamespaceDeclaration);
+		}
+		if let Some(nsuri) = nsuri {
+			if nsuri.is_empty() || **nsuri == XMLNS_XML || **nsuri == XMLNS_XMLNS {
+				return Err(EncodeError::InvalidNamespaceDeclaration);
+			}
+		}
+		for (other_prefix, other_nsuri) in self.pending_decls.iter() {
+			if other_prefix.as_deref() == prefix
+				|| (prefix.is_some() && other_prefix.is_some() && other_nsuri.as_ref() == nsuri)
+			{
+				return Err(EncodeError::InvalidNamespaceDeclaration);
+			}
+		}
+		Ok(())
+	}
+
+	fn check_comment_or_pi_allowed(&self) -> Result<(), EncodeError> {
+		if !self.allow_comments_and_pis {
+			return Err(EncodeError::CommentsAndPIsDisabled);
+		}
+		if self.state == EncoderState::ElementHead {
+			return Err(EncodeError::CommentOrPINotAllowed);
+		}
+		Ok(())
+	}
+
+	fn finish_comment_or_pi(&mut self) {
+		// the XML declaration must come first in the document, so any
+		// comment or PI rules it out
+		if self.state == EncoderState::Start {
+			self.state = EncoderState::Declared;
 		}
 	}
 
@@ -765,6 +1370,12 @@ impl<T: TrackNamespace> Encoder<T> {
 	/// This internally decomposes the event into multiple items and then
 	/// encodes these into the given buffer using [`encode`].
 	///
+	/// Prefix mapping events and attributes in the [`XMLNS_XMLNS`]
+	/// namespace (see
+	/// [`Parser::set_emit_xmlns_attributes`](crate::Parser::set_emit_xmlns_attributes))
+	/// are ignored, as the encoder manages namespace declarations on its own
+	/// via its [`TrackNamespace`] implementation.
+	///
 	///    [`encode`]: Self::encode.
 	pub fn encode_event<O: BufMut>(
 		&mut self,
@@ -775,18 +1386,20 @@ impl<T: TrackNamespace> Encoder<T> {
 			ResolvedEvent::XmlDeclaration(_, version) => {
 				self.encode(Item::XmlDeclaration(*version), output)?;
 			}
-			ResolvedEvent::StartElement(_, (ns, name), attrs) => {
+			ResolvedEvent::StartElement(_, (ns, name), attrs, _) => {
 				self.encode(Item::ElementHeadStart(ns.clone(), name.as_ref()), output)?;
 				for ((ns, name), v) in attrs.iter() {
-					self.encode(
-						Item::Attribute(ns.clone(), name.as_ref(), v.as_ref()),
-						output,
-					)?
+					if ns.as_ref().map(|x| &***x) == Some(XMLNS_XMLNS) {
+						continue;
+					}
+					self.encode(Item::Attribute(ns.clone(), name.as_ref(), &v), output)?
 				}
 				self.encode(Item::ElementHeadEnd, output)?;
 			}
 			ResolvedEvent::EndElement(_) => self.encode(Item::ElementFoot, output)?,
 			ResolvedEvent::Text(_, text) => self.encode(Item::Text(text.as_ref()), output)?,
+			// namespace declarations are managed by the encoder itself
+			ResolvedEvent::StartPrefixMapping(..) | ResolvedEvent::EndPrefixMapping(..) => (),
 		}
 		Ok(())
 	}
@@ -810,7 +1423,7 @@ impl<T: TrackNamespace> Encoder<T> {
 mod tests_simple_namespaces {
 	use super::*;
 
-	use std::convert::TryFrom;
+	use core::convert::TryFrom;
 
 	fn ns1() -> NamespaceName {
 		RcPtr::new(CData::try_from("uri:foo").unwrap())
@@ -844,6 +1457,20 @@ mod tests_simple_namespaces {
 		assert!(prefix.is_none());
 	}
 
+	#[test]
+	fn lookup_namespace_finds_declared_namespace_names() {
+		let mut ns = mk();
+		let uri = ns1();
+		assert!(ns.lookup_namespace(&uri).is_none());
+		ns.declare_auto(Some(uri.clone()));
+		ns.push();
+		assert!(RcPtr::ptr_eq(&ns.lookup_namespace(&ns1()).unwrap(), &uri));
+		let other = ns2();
+		ns.declare_auto(Some(other.clone()));
+		assert!(RcPtr::ptr_eq(&ns.lookup_namespace(&ns2()).unwrap(), &other));
+		assert!(ns.lookup_namespace(&ns3()).is_none());
+	}
+
 	#[test]
 	fn predefined_prefixes_can_be_looked_up() {
 		let ns = mk();
@@ -1148,7 +1775,7 @@ mod tests_simple_namespaces {
 	}
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests_encoder {
 	use super::*;
 
@@ -1230,8 +1857,8 @@ mod tests_encoder {
 				assert_eq!(v1, v2);
 			}
 			(
-				ResolvedEvent::StartElement(_, name1, attrs1),
-				ResolvedEvent::StartElement(_, name2, attrs2),
+				ResolvedEvent::StartElement(_, name1, attrs1, _),
+				ResolvedEvent::StartElement(_, name2, attrs2, _),
 			) => {
 				assert_eq!(name1, name2);
 				assert_eq!(attrs1, attrs2);
@@ -1783,4 +2410,518 @@ mod tests_encoder {
 			b"<?xml version='1.0'?>\n<a xmlns:b='uri:foo' b:a1='baz' a2='fnord' b:a3='foobar'/>",
 		)
 	}
+
+	#[test]
+	fn reject_comment_unless_enabled() {
+		let mut enc = mkencoder();
+		let mut buf = BytesMut::new();
+		match enc.encode(Item::Comment("foo".try_into().unwrap()), &mut buf) {
+			Err(EncodeError::CommentsAndPIsDisabled) => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		};
+		match enc.encode(
+			Item::ProcessingInstruction("foo".try_into().unwrap(), "".try_into().unwrap()),
+			&mut buf,
+		) {
+			Err(EncodeError::CommentsAndPIsDisabled) => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		};
+		assert_eq!(buf.len(), 0);
+	}
+
+	#[test]
+	fn encode_comments_and_pis_around_root() {
+		let mut enc = mkencoder().allow_comments_and_pis(true);
+		let mut buf = BytesMut::new();
+		enc.encode(Item::XmlDeclaration(XmlVersion::V1_0), &mut buf)
+			.unwrap();
+		enc.encode(
+			Item::ProcessingInstruction(
+				"xml-stylesheet".try_into().unwrap(),
+				"href='x.xsl'".try_into().unwrap(),
+			),
+			&mut buf,
+		)
+		.unwrap();
+		enc.encode(Item::Comment(" license ".try_into().unwrap()), &mut buf)
+			.unwrap();
+		enc.encode(
+			Item::ElementHeadStart(None, "x".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		enc.encode(Item::ElementHeadEnd, &mut buf).unwrap();
+		enc.encode(Item::Comment("inner".try_into().unwrap()), &mut buf)
+			.unwrap();
+		enc.encode(Item::ElementFoot, &mut buf).unwrap();
+		enc.encode(
+			Item::ProcessingInstruction("end".try_into().unwrap(), "".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		assert_eq!(
+			&buf[..],
+			&b"<?xml version='1.0' encoding='utf-8'?>\n<?xml-stylesheet href='x.xsl'?><!-- license --><x><!--inner--></x><?end?>"[..]
+		);
+	}
+
+	#[test]
+	fn comment_rules_out_xml_declaration() {
+		let mut enc = mkencoder().allow_comments_and_pis(true);
+		let mut buf = BytesMut::new();
+		enc.encode(Item::Comment("foo".try_into().unwrap()), &mut buf)
+			.unwrap();
+		match enc.encode(Item::XmlDeclaration(XmlVersion::V1_0), &mut buf) {
+			Err(EncodeError::MisplacedXmlDeclaration) => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		};
+	}
+
+	#[test]
+	fn reject_comment_in_element_header() {
+		let mut enc = mkencoder().allow_comments_and_pis(true);
+		let mut buf = BytesMut::new();
+		enc.encode(
+			Item::ElementHeadStart(None, "x".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		match enc.encode(Item::Comment("foo".try_into().unwrap()), &mut buf) {
+			Err(EncodeError::CommentOrPINotAllowed) => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		};
+	}
+
+	#[test]
+	fn reject_ill_formed_comments() {
+		let mut enc = mkencoder().allow_comments_and_pis(true);
+		let mut buf = BytesMut::new();
+		for text in &["foo--bar", "--", "foo-"] {
+			match enc.encode(Item::Comment((*text).try_into().unwrap()), &mut buf) {
+				Err(EncodeError::InvalidComment) => (),
+				other => panic!("unexpected encode result for {:?}: {:?}", text, other),
+			};
+		}
+		assert_eq!(buf.len(), 0);
+	}
+
+	#[test]
+	fn reject_ill_formed_processing_instructions() {
+		let mut enc = mkencoder().allow_comments_and_pis(true);
+		let mut buf = BytesMut::new();
+		match enc.encode(
+			Item::ProcessingInstruction("XmL".try_into().unwrap(), "".try_into().unwrap()),
+			&mut buf,
+		) {
+			Err(EncodeError::InvalidProcessingInstruction) => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		};
+		match enc.encode(
+			Item::ProcessingInstruction("foo".try_into().unwrap(), "a?>b".try_into().unwrap()),
+			&mut buf,
+		) {
+			Err(EncodeError::InvalidProcessingInstruction) => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		};
+		assert_eq!(buf.len(), 0);
+	}
+
+	#[test]
+	fn encode_raw_fragment_verbatim() {
+		let mut enc = mkencoder();
+		let mut buf = BytesMut::new();
+		enc.encode(
+			Item::ElementHeadStart(None, "x".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		enc.encode(Item::ElementHeadEnd, &mut buf).unwrap();
+		enc.encode(Item::Raw(b"<y a='&amp;'>&lt;</y>"), &mut buf)
+			.unwrap();
+		enc.encode(Item::ElementFoot, &mut buf).unwrap();
+		assert_eq!(&buf[..], &b"<x><y a='&amp;'>&lt;</y></x>"[..]);
+	}
+
+	#[test]
+	fn reject_raw_fragment_outside_content() {
+		let mut enc = mkencoder();
+		let mut buf = BytesMut::new();
+		match enc.encode(Item::Raw(b"<x/>"), &mut buf) {
+			Err(EncodeError::TextNotAllowed) => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		};
+		enc.encode(
+			Item::ElementHeadStart(None, "x".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		match enc.encode(Item::Raw(b" a='b'"), &mut buf) {
+			Err(EncodeError::TextNotAllowed) => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		};
+	}
+
+	#[test]
+	fn encode_explicit_namespace_declarations() {
+		let mut enc = mkencoder();
+		let mut buf = BytesMut::new();
+		enc.encode(Item::NamespaceDeclaration(None, Some(ns2())), &mut buf)
+			.unwrap();
+		enc.encode(
+			Item::NamespaceDeclaration(Some("stream".try_into().unwrap()), Some(ns1())),
+			&mut buf,
+		)
+		.unwrap();
+		assert_eq!(buf.len(), 0);
+		enc.encode(
+			Item::ElementHeadStart(Some(ns1()), "stream".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		enc.encode(Item::ElementHeadEnd, &mut buf).unwrap();
+		enc.encode(
+			Item::ElementHeadStart(Some(ns2()), "message".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		enc.encode(Item::ElementFoot, &mut buf).unwrap();
+		assert_eq!(
+			&buf[..],
+			&b"<stream:stream xmlns='uri:bar' xmlns:stream='uri:foo'><message/>"[..]
+		);
+	}
+
+	#[test]
+	fn reject_misplaced_namespace_declarations() {
+		let mut enc = mkencoder();
+		let mut buf = BytesMut::new();
+		enc.encode(
+			Item::ElementHeadStart(None, "x".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		match enc.encode(Item::NamespaceDeclaration(None, Some(ns1())), &mut buf) {
+			Err(EncodeError::MisplacedNamespaceDeclaration) => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		};
+		enc.encode(Item::ElementHeadEnd, &mut buf).unwrap();
+		enc.encode(Item::NamespaceDeclaration(None, Some(ns1())), &mut buf)
+			.unwrap();
+		match enc.encode(Item::Text("foo".try_into().unwrap()), &mut buf) {
+			Err(EncodeError::MisplacedNamespaceDeclaration) => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		};
+		match enc.encode(Item::ElementFoot, &mut buf) {
+			Err(EncodeError::MisplacedNamespaceDeclaration) => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		};
+		assert_eq!(&buf[..], &b"<x>"[..]);
+	}
+
+	#[test]
+	fn reject_invalid_namespace_declarations() {
+		let mut enc = mkencoder();
+		let mut buf = BytesMut::new();
+		let xml = RcPtr::new(CData::try_from(XMLNS_XML).unwrap());
+		let empty = RcPtr::new(CData::try_from("").unwrap());
+		let invalid: [(Option<&NcNameStr>, Option<NamespaceName>); 4] = [
+			(Some(PREFIX_XML), Some(xml.clone())),
+			(None, Some(xml)),
+			(Some("p".try_into().unwrap()), None),
+			(Some("p".try_into().unwrap()), Some(empty)),
+		];
+		for (prefix, nsuri) in invalid.iter() {
+			match enc.encode(Item::NamespaceDeclaration(*prefix, nsuri.clone()), &mut buf) {
+				Err(EncodeError::InvalidNamespaceDeclaration) => (),
+				other => panic!(
+					"unexpected encode result for {:?}: {:?}",
+					(prefix, nsuri),
+					other
+				),
+			};
+		}
+
+		enc.encode(
+			Item::NamespaceDeclaration(Some("p".try_into().unwrap()), Some(ns1())),
+			&mut buf,
+		)
+		.unwrap();
+		match enc.encode(
+			Item::NamespaceDeclaration(Some("p".try_into().unwrap()), Some(ns2())),
+			&mut buf,
+		) {
+			Err(EncodeError::InvalidNamespaceDeclaration) => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		};
+		match enc.encode(
+			Item::NamespaceDeclaration(Some("q".try_into().unwrap()), Some(ns1())),
+			&mut buf,
+		) {
+			Err(EncodeError::InvalidNamespaceDeclaration) => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		};
+		enc.encode(Item::NamespaceDeclaration(None, Some(ns3())), &mut buf)
+			.unwrap();
+		match enc.encode(
+			Item::ElementHeadStart(None, "x".try_into().unwrap()),
+			&mut buf,
+		) {
+			Err(EncodeError::InvalidNamespaceDeclaration) => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		};
+		assert_eq!(buf.len(), 0);
+	}
+
+	#[test]
+	fn reject_redeclaration_of_root_prefix() {
+		let mut pp = crate::PullParser::new(&b"<a xmlns:p='x'><b xmlns:p='y'/></a>"[..]);
+		pp.get_parser_mut().set_emit_prefix_mappings(true);
+		let mut enc = mkencoder();
+		enc.set_context(Some(RcPtr::new(Context::new())));
+		let mut buf = BytesMut::new();
+		let mut result = Ok(());
+		while let Some(ev) = pp.read().unwrap() {
+			result = match ev {
+				ResolvedEvent::StartPrefixMapping(_, ref prefix, ref nsuri) => enc.encode(
+					Item::NamespaceDeclaration(prefix.as_deref(), Some(nsuri.clone())),
+					&mut buf,
+				),
+				ref other => enc.encode_event(other, &mut buf),
+			};
+			if result.is_err() {
+				break;
+			}
+		}
+		match result {
+			Err(EncodeError::InvalidNamespaceDeclaration) => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		};
+		assert_eq!(&buf[..], &b"<a xmlns:p='x'>"[..]);
+	}
+
+	#[test]
+	fn rejected_element_discards_namespace_declarations() {
+		let mut enc = mkencoder();
+		let mut buf = BytesMut::new();
+		enc.encode(
+			Item::NamespaceDeclaration(Some("p".try_into().unwrap()), Some(ns1())),
+			&mut buf,
+		)
+		.unwrap();
+		enc.encode(Item::NamespaceDeclaration(None, Some(ns3())), &mut buf)
+			.unwrap();
+		match enc.encode(
+			Item::ElementHeadStart(None, "x".try_into().unwrap()),
+			&mut buf,
+		) {
+			Err(EncodeError::InvalidNamespaceDeclaration) => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		};
+		// neither the encoder nor the namespace tracker remember the
+		// declarations of the rejected element
+		enc.encode(
+			Item::NamespaceDeclaration(Some("p".try_into().unwrap()), Some(ns2())),
+			&mut buf,
+		)
+		.unwrap();
+		enc.encode(
+			Item::ElementHeadStart(Some(ns2()), "x".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		enc.encode(Item::ElementFoot, &mut buf).unwrap();
+		assert_eq!(&buf[..], &b"<p:x xmlns:p='uri:bar'/>"[..]);
+	}
+
+	#[test]
+	fn track_open_elements() {
+		let mut enc = mkencoder();
+		let mut buf = BytesMut::new();
+		assert_eq!(enc.depth(), 0);
+		enc.inner_mut()
+			.declare_fixed(Some("stream".try_into().unwrap()), Some(ns1()));
+		enc.encode(
+			Item::ElementHeadStart(Some(ns1()), "stream".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		enc.encode(Item::ElementHeadEnd, &mut buf).unwrap();
+		enc.encode(
+			Item::ElementHeadStart(Some(ns2()), "message".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		assert_eq!(enc.depth(), 2);
+		let names: Vec<_> = enc.open_elements().map(|x| &**x).collect();
+		assert_eq!(names, vec!["stream:stream", "message"]);
+		enc.encode(Item::ElementFoot, &mut buf).unwrap();
+		assert_eq!(enc.depth(), 1);
+	}
+
+	#[test]
+	fn close_all_closes_open_elements() {
+		let mut enc = mkencoder();
+		let mut buf = BytesMut::new();
+		enc.encode(
+			Item::ElementHeadStart(None, "a".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		enc.encode(Item::ElementHeadEnd, &mut buf).unwrap();
+		enc.encode(
+			Item::ElementHeadStart(None, "b".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		enc.encode(Item::ElementHeadEnd, &mut buf).unwrap();
+		enc.encode(
+			Item::ElementHeadStart(None, "c".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		enc.close_all(&mut buf).unwrap();
+		assert_eq!(enc.depth(), 0);
+		assert_eq!(&buf[..], &b"<a><b><c/></b></a>"[..]);
+		match enc.encode(
+			Item::ElementHeadStart(None, "a".try_into().unwrap()),
+			&mut buf,
+		) {
+			Err(EncodeError::EndOfDocument) => (),
+			other => panic!("unexpected encode result: {:?}", other),
+		};
+	}
+
+	#[test]
+	fn close_all_without_open_elements_is_noop() {
+		let mut enc = mkencoder();
+		let mut buf = BytesMut::new();
+		enc.close_all(&mut buf).unwrap();
+		assert_eq!(buf.len(), 0);
+	}
+
+	#[test]
+	fn encode_into_split_bytes_mut() {
+		let mut enc = mkencoder();
+		let mut buf = BytesMut::new();
+		enc.encode(
+			Item::ElementHeadStart(None, "x".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		enc.encode(Item::ElementHeadEnd, &mut buf).unwrap();
+		let head = buf.split().freeze();
+		enc.encode_into_bytes(Item::Text("foo".try_into().unwrap()), &mut buf)
+			.unwrap();
+		enc.encode_into_bytes(Item::ElementFoot, &mut buf).unwrap();
+		let rest = buf.freeze();
+		assert_eq!(&head[..], &b"<x>"[..]);
+		assert_eq!(&rest[..], &b"foo</x>"[..]);
+	}
+
+	#[test]
+	fn borrowed_items_encode_like_owned_items() {
+		let ns: &CDataStr = "uri:foo".try_into().unwrap();
+		let mut borrowed = mkencoder();
+		let mut borrowed_buf = BytesMut::new();
+		let mut owned = mkencoder();
+		let mut owned_buf = BytesMut::new();
+		borrowed
+			.encode(
+				Item::ElementHeadStartRef(Some(ns), "x".try_into().unwrap()),
+				&mut borrowed_buf,
+			)
+			.unwrap();
+		borrowed
+			.encode(
+				Item::AttributeRef(Some(ns), "a".try_into().unwrap(), "v".try_into().unwrap()),
+				&mut borrowed_buf,
+			)
+			.unwrap();
+		borrowed
+			.encode(
+				Item::AttributeRef(None, "b".try_into().unwrap(), "w".try_into().unwrap()),
+				&mut borrowed_buf,
+			)
+			.unwrap();
+		borrowed
+			.encode(Item::ElementFoot, &mut borrowed_buf)
+			.unwrap();
+		owned
+			.encode(
+				Item::ElementHeadStart(Some(ns1()), "x".try_into().unwrap()),
+				&mut owned_buf,
+			)
+			.unwrap();
+		owned
+			.encode(
+				Item::Attribute(
+					Some(ns1()),
+					"a".try_into().unwrap(),
+					"v".try_into().unwrap(),
+				),
+				&mut owned_buf,
+			)
+			.unwrap();
+		owned
+			.encode(
+				Item::Attribute(None, "b".try_into().unwrap(), "w".try_into().unwrap()),
+				&mut owned_buf,
+			)
+			.unwrap();
+		owned.encode(Item::ElementFoot, &mut owned_buf).unwrap();
+		assert_eq!(borrowed_buf, owned_buf);
+		assert_eq!(
+			std::str::from_utf8(&borrowed_buf).unwrap(),
+			"<x xmlns='uri:foo' xmlns:tns0='uri:foo' tns0:a=\"v\" b=\"w\"/>",
+		);
+	}
+
+	#[test]
+	fn borrowed_namespaces_are_obtained_from_context() {
+		let ctx = RcPtr::new(Context::new());
+		let ns = ctx.intern_cdata(CData::try_from("uri:foo").unwrap());
+		let mut enc = Encoder::with_context(ctx.clone());
+		assert!(RcPtr::ptr_eq(enc.context().unwrap(), &ctx));
+		let mut buf = BytesMut::new();
+		enc.encode(
+			Item::ElementHeadStartRef(Some(&**ns), "x".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		let declared = enc.inner().new_default_declaration().unwrap().unwrap();
+		#[cfg(feature = "shared_ns")]
+		assert!(RcPtr::ptr_eq(declared, &ns));
+		assert_eq!(declared, &ns);
+		enc.encode(Item::ElementFoot, &mut buf).unwrap();
+		assert_eq!(&buf[..], &b"<x xmlns='uri:foo'/>"[..]);
+	}
+
+	#[test]
+	fn encoder_without_context_has_none() {
+		let mut enc = mkencoder();
+		assert!(enc.context().is_none());
+		enc.set_context(Some(RcPtr::new(Context::new())));
+		assert!(enc.context().is_some());
+	}
+
+	#[test]
+	fn borrowed_element_reuses_declared_namespace() {
+		let ns = ns1();
+		let mut enc = mkencoder();
+		let mut buf = BytesMut::new();
+		enc.encode(
+			Item::ElementHeadStart(Some(ns.clone()), "x".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		enc.encode(Item::ElementHeadEnd, &mut buf).unwrap();
+		enc.encode(
+			Item::ElementHeadStartRef(Some(&**ns), "y".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		enc.encode(Item::ElementFoot, &mut buf).unwrap();
+		enc.encode(Item::ElementFoot, &mut buf).unwrap();
+		assert_eq!(&buf[..], &b"<x xmlns='uri:foo'><y/></x>"[..]);
+	}
 }
@@ -0,0 +1,303 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::{Buf, BytesMut};
+
+use futures_sink::Sink;
+
+use pin_project_lite::pin_project;
+
+use tokio::io::AsyncWrite;
+
+use crate::parser::ResolvedEvent;
+
+use super::{EncodeError, Encoder, Item, SimpleNamespaces, TrackNamespace};
+
+const DEFAULT_HIGH_WATER_MARK: usize = 8192;
+
+fn map_encode_error(e: EncodeError) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidInput, e)
+}
+
+pin_project! {
+	/**
+	# Asynchronous XML writer
+
+	The [`AsyncWriter`] serializes [`Item`]s or [`ResolvedEvent`]s using an
+	[`Encoder`] and writes the result to a [`tokio::io::AsyncWrite`]. It
+	implements [`futures_sink::Sink`] for both, so that it can be used with
+	the combinators from `SinkExt` or be fed directly from an
+	[`AsyncParser`](crate::AsyncParser) used as stream.
+
+	Serialized data is collected in an internal buffer. The buffer is written
+	to the underlying writer when it exceeds the high water mark (see
+	[`AsyncWriter::with_high_water_mark`]) and on flush. Encoding errors are
+	reported as [`std::io::Error`] of kind
+	[`std::io::ErrorKind::InvalidInput`], wrapping the [`EncodeError`].
+
+	## Example
+
+	```
+	use futures_util::SinkExt;
+	use rxml::writer::AsyncWriter;
+	use rxml::Item;
+	# use std::convert::TryInto;
+	# tokio_test::block_on(async {
+	let mut out = Vec::new();
+	let mut w = AsyncWriter::new(&mut out);
+	w.send(Item::ElementHeadStart(None, "hello".try_into().unwrap())).await.unwrap();
+	w.send(Item::ElementHeadEnd).await.unwrap();
+	w.send(Item::Text("World!".try_into().unwrap())).await.unwrap();
+	w.send(Item::ElementFoot).await.unwrap();
+	drop(w);
+	assert_eq!(&out[..], b"<hello>World!</hello>");
+	# })
+	```
+	*/
+	#[cfg_attr(docsrs, doc(cfg(feature = "sink")))]
+	pub struct AsyncWriter<W, T = SimpleNamespaces> {
+		#[pin]
+		inner: W,
+		encoder: Encoder<T>,
+		buf: BytesMut,
+		high_water_mark: usize,
+	}
+}
+
+impl<W: AsyncWrite> AsyncWriter<W, SimpleNamespaces> {
+	/// Create a new writer with a default encoder.
+	pub fn new(inner: W) -> Self {
+		Self::wrap(inner, Encoder::new())
+	}
+}
+
+impl<W: AsyncWrite, T> AsyncWriter<W, T> {
+	/// Create a new writer using the given encoder.
+	pub fn wrap(inner: W, encoder: Encoder<T>) -> Self {
+		Self {
+			inner,
+			encoder,
+			buf: BytesMut::new(),
+			high_water_mark: DEFAULT_HIGH_WATER_MARK,
+		}
+	}
+
+	/// Set the number of buffered bytes above which the buffer is written
+	/// to the underlying writer before accepting more items.
+	///
+	/// A value of zero causes every item to be written out before the next
+	/// one is accepted.
+	pub fn with_high_water_mark(mut self, v: usize) -> Self {
+		self.high_water_mark = v;
+		self
+	}
+
+	/// Access the encoder.
+	pub fn encoder(&self) -> &Encoder<T> {
+		&self.encoder
+	}
+
+	/// Access the encoder, mutably.
+	pub fn encoder_mut(&mut self) -> &mut Encoder<T> {
+		&mut self.encoder
+	}
+
+	/// Access the inner AsyncWrite
+	pub fn get_inner(&self) -> &W {
+		&self.inner
+	}
+
+	/// Access the inner AsyncWrite, mutably
+	///
+	/// Writing to the inner writer directly while data is still buffered
+	/// will reorder the output.
+	pub fn get_inner_mut(&mut self) -> &mut W {
+		&mut self.inner
+	}
+
+	/// Number of bytes which have been encoded, but not yet written to the
+	/// inner writer.
+	pub fn buffered(&self) -> usize {
+		self.buf.len()
+	}
+
+	/// Decompose the writer into the inner writer and the encoder.
+	///
+	/// Any data which has not been flushed is lost.
+	pub fn into_inner(self) -> (W, Encoder<T>) {
+		(self.inner, self.encoder)
+	}
+
+	fn poll_write_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		let mut this = self.project();
+		while !this.buf.is_empty() {
+			match this.inner.as_mut().poll_write(cx, &this.buf[..]) {
+				Poll::Pending => return Poll::Pending,
+				Poll::Ready(Ok(0)) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+				Poll::Ready(Ok(n)) => this.buf.advance(n),
+				Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+			}
+		}
+		Poll::Ready(Ok(()))
+	}
+
+	fn poll_ready_impl(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		if self.buf.len() > self.high_water_mark {
+			return self.poll_write_buf(cx);
+		}
+		Poll::Ready(Ok(()))
+	}
+
+	fn poll_flush_impl(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		match self.as_mut().poll_write_buf(cx) {
+			Poll::Ready(Ok(())) => (),
+			other => return other,
+		}
+		self.project().inner.poll_flush(cx)
+	}
+
+	fn poll_close_impl(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		match self.as_mut().poll_write_buf(cx) {
+			Poll::Ready(Ok(())) => (),
+			other => return other,
+		}
+		self.project().inner.poll_shutdown(cx)
+	}
+}
+
+impl<'x, W: AsyncWrite, T: TrackNamespace> Sink<Item<'x>> for AsyncWriter<W, T> {
+	type Error = io::Error;
+
+	fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		self.poll_ready_impl(cx)
+	}
+
+	fn start_send(self: Pin<&mut Self>, item: Item<'x>) -> io::Result<()> {
+		let this = self.project();
+		this.encoder
+			.encode_into_bytes(item, this.buf)
+			.map_err(map_encode_error)
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		self.poll_flush_impl(cx)
+	}
+
+	fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		self.poll_close_impl(cx)
+	}
+}
+
+impl<'x, W: AsyncWrite, T: TrackNamespace> Sink<&'x ResolvedEvent> for AsyncWriter<W, T> {
+	type Error = io::Error;
+
+	fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		self.poll_ready_impl(cx)
+	}
+
+	fn start_send(self: Pin<&mut Self>, ev: &'x ResolvedEvent) -> io::Result<()> {
+		let this = self.project();
+		this.encoder
+			.encode_event_into_bytes(ev, this.buf)
+			.map_err(map_encode_error)
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		self.poll_flush_impl(cx)
+	}
+
+	fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		self.poll_close_impl(cx)
+	}
+}
+
+impl<W: AsyncWrite, T: TrackNamespace> Sink<ResolvedEvent> for AsyncWriter<W, T> {
+	type Error = io::Error;
+
+	fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		self.poll_ready_impl(cx)
+	}
+
+	fn start_send(self: Pin<&mut Self>, ev: ResolvedEvent) -> io::Result<()> {
+		let this = self.project();
+		this.encoder
+			.encode_event_into_bytes(&ev, this.buf)
+			.map_err(map_encode_error)
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		self.poll_flush_impl(cx)
+	}
+
+	fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		self.poll_close_impl(cx)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::convert::TryInto;
+
+	use futures_util::SinkExt;
+	#[cfg(feature = "stream")]
+	use futures_util::StreamExt;
+
+	#[tokio::test]
+	async fn writes_items_on_flush() {
+		let mut out = Vec::new();
+		let mut w = AsyncWriter::new(&mut out);
+		w.feed(Item::ElementHeadStart(None, "a".try_into().unwrap()))
+			.await
+			.unwrap();
+		w.feed(Item::ElementFoot).await.unwrap();
+		assert_eq!(w.buffered(), 4);
+		SinkExt::<Item>::flush(&mut w).await.unwrap();
+		assert_eq!(w.buffered(), 0);
+		drop(w);
+		assert_eq!(&out[..], b"<a/>");
+	}
+
+	#[tokio::test]
+	async fn writes_early_above_high_water_mark() {
+		let (tx, mut rx) = tokio::io::duplex(64);
+		let mut w = AsyncWriter::new(tx).with_high_water_mark(0);
+		w.feed(Item::ElementHeadStart(None, "a".try_into().unwrap()))
+			.await
+			.unwrap();
+		w.feed(Item::ElementHeadEnd).await.unwrap();
+		// the element head is written while feeding the next item
+		w.feed(Item::Text("foo".try_into().unwrap())).await.unwrap();
+		let mut buf = [0u8; 16];
+		let n = tokio::io::AsyncReadExt::read(&mut rx, &mut buf[..])
+			.await
+			.unwrap();
+		assert_eq!(&buf[..n], b"<a>");
+		assert_eq!(w.buffered(), 3);
+	}
+
+	#[tokio::test]
+	async fn encode_errors_are_invalid_input() {
+		let mut out = Vec::new();
+		let mut w = AsyncWriter::new(&mut out);
+		match w.send(Item::ElementFoot).await {
+			Err(e) if e.kind() == io::ErrorKind::InvalidInput => (),
+			other => panic!("unexpected send result: {:?}", other),
+		}
+	}
+
+	#[cfg(feature = "stream")]
+	#[tokio::test]
+	async fn forwards_parsed_events() {
+		let mut doc = &b"<a x='y'>foo<b/></a>"[..];
+		let mut out = Vec::new();
+		let mut w = AsyncWriter::new(&mut out);
+		let mut events = crate::AsyncParser::new(&mut doc)
+			.map(|ev| ev.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)));
+		w.send_all(&mut events).await.unwrap();
+		drop(w);
+		assert_eq!(&out[..], &b"<a x=\"y\">foo<b></b></a>"[..]);
+	}
+}
@@ -0,0 +1,278 @@
+use core::str;
+use std::io;
+
+use bytes::BufMut;
+
+use rxml_validation::selectors::{CharSelector, CLASS_XML_NONCHAR};
+use rxml_validation::Error as ValidationError;
+
+use super::{escape, Encoder, CDATA_SPECIALS};
+
+/// Return the length of the UTF-8 sequence introduced by a leading byte.
+fn utf8_sequence_len(b: u8) -> usize {
+	if b < 0xe0 {
+		2
+	} else if b < 0xf0 {
+		3
+	} else {
+		4
+	}
+}
+
+/**
+# Write adapter for streaming text content
+
+The [`TextWriter`] implements [`std::io::Write`] and emits all data written
+to it as character data of the current element, escaping it on the fly. It
+is obtained from [`Encoder::text_writer()`] and allows to stream large text
+nodes (e.g. base64-encoded payloads) into the output without first
+materializing them as [`CData`](crate::CData).
+
+The written data must be valid UTF-8 and must only contain characters
+allowed in XML. UTF-8 sequences may be split across writes. Invalid data is
+reported as [`std::io::Error`] of kind [`std::io::ErrorKind::InvalidData`];
+data preceding the offending byte in the same write is emitted nonetheless
+and reported as a short write.
+
+After all data has been written, [`TextWriter::finish()`] should be called
+to detect a truncated UTF-8 sequence at the end of the text. Such trailing
+bytes are silently discarded if the writer is dropped instead.
+
+## Example
+
+```
+use rxml::{Encoder, Item};
+use std::io::Write;
+# use std::convert::TryInto;
+
+let mut enc = Encoder::new();
+let mut buf = Vec::new();
+enc.encode(Item::ElementHeadStart(None, "data".try_into().unwrap()), &mut buf).unwrap();
+enc.encode(Item::ElementHeadEnd, &mut buf).unwrap();
+let mut w = enc.text_writer(&mut buf).unwrap();
+for chunk in &["SGVsbG8", "gV29ybGQh"] {
+	w.write_all(chunk.as_bytes()).unwrap();
+}
+w.write_all(b" & more").unwrap();
+w.finish().unwrap();
+enc.encode(Item::ElementFoot, &mut buf).unwrap();
+assert_eq!(&buf[..], b"<data>SGVsbG8gV29ybGQh &amp; more</data>");
+```
+*/
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub struct TextWriter<'x, T, O> {
+	encoder: &'x Encoder<T>,
+	output: &'x mut O,
+	/// Incomplete UTF-8 sequence at the end of the previous write.
+	partial: [u8; 4],
+	partial_len: usize,
+}
+
+impl<'x, T, O: BufMut> TextWriter<'x, T, O> {
+	pub(super) fn new(encoder: &'x mut Encoder<T>, output: &'x mut O) -> Self {
+		Self {
+			encoder,
+			output,
+			partial: [0; 4],
+			partial_len: 0,
+		}
+	}
+
+	/// Access the encoder
+	pub fn get_encoder(&self) -> &Encoder<T> {
+		self.encoder
+	}
+
+	/// Signal the end of the text.
+	///
+	/// If the data written so far ends with an incomplete UTF-8 sequence,
+	/// an error is returned.
+	pub fn finish(self) -> io::Result<()> {
+		match str::from_utf8(&self.partial[..self.partial_len]) {
+			Ok(_) => Ok(()),
+			Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+		}
+	}
+
+	/// Escape and emit a piece of text, up to the first character which is
+	/// not allowed in XML.
+	///
+	/// Returns the number of bytes emitted.
+	fn emit(&mut self, text: &str) -> io::Result<usize> {
+		let text = match text
+			.char_indices()
+			.find(|(_, ch)| CLASS_XML_NONCHAR.select(*ch))
+		{
+			Some((0, ch)) => {
+				return Err(io::Error::new(
+					io::ErrorKind::InvalidData,
+					ValidationError::InvalidChar(ch),
+				))
+			}
+			Some((i, _)) => &text[..i],
+			None => text,
+		};
+		escape(self.output, text.as_bytes(), CDATA_SPECIALS);
+		Ok(text.len())
+	}
+
+	/// Complete the UTF-8 sequence left over from the previous write.
+	fn complete_partial(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let len = self.partial_len;
+		let need = utf8_sequence_len(self.partial[0]) - len;
+		let take = need.min(buf.len());
+		self.partial[len..len + take].copy_from_slice(&buf[..take]);
+		self.partial_len += take;
+		if take < need {
+			return Ok(take);
+		}
+		let seq = self.partial;
+		let seq = &seq[..self.partial_len];
+		self.partial_len = 0;
+		match str::from_utf8(seq) {
+			Ok(ch) => {
+				self.emit(ch)?;
+				Ok(take)
+			}
+			Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+		}
+	}
+}
+
+impl<'x, T, O: BufMut> io::Write for TextWriter<'x, T, O> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		if buf.is_empty() {
+			return Ok(0);
+		}
+		if self.partial_len > 0 {
+			return self.complete_partial(buf);
+		}
+		let (text, incomplete) = match str::from_utf8(buf) {
+			Ok(text) => (text, &[][..]),
+			Err(e) => {
+				let (valid, rest) = buf.split_at(e.valid_up_to());
+				// valid_up_to guarantees that this is valid UTF-8
+				let text = str::from_utf8(valid).unwrap();
+				match e.error_len() {
+					None => (text, rest),
+					Some(_) if text.is_empty() => {
+						return Err(io::Error::new(io::ErrorKind::InvalidData, e))
+					}
+					Some(_) => (text, &[][..]),
+				}
+			}
+		};
+		let written = self.emit(text)?;
+		if written < text.len() {
+			return Ok(written);
+		}
+		self.partial[..incomplete.len()].copy_from_slice(incomplete);
+		self.partial_len = incomplete.len();
+		Ok(written + incomplete.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::convert::TryInto;
+	use std::io::Write;
+
+	use crate::writer::{EncodeError, Item, SimpleNamespaces};
+
+	fn open(enc: &mut Encoder<SimpleNamespaces>, buf: &mut Vec<u8>) {
+		enc.encode(Item::ElementHeadStart(None, "x".try_into().unwrap()), buf)
+			.unwrap();
+		enc.encode(Item::ElementHeadEnd, buf).unwrap();
+	}
+
+	#[test]
+	fn escapes_written_text() {
+		let mut enc = Encoder::new();
+		let mut buf = Vec::new();
+		open(&mut enc, &mut buf);
+		let mut w = enc.text_writer(&mut buf).unwrap();
+		w.write_all(b"a < b & c > d\r\n").unwrap();
+		w.finish().unwrap();
+		enc.encode(Item::ElementFoot, &mut buf).unwrap();
+		assert_eq!(&buf[..], &b"<x>a &lt; b &amp; c &gt; d&#xd;\n</x>"[..]);
+	}
+
+	#[test]
+	fn reassembles_split_utf8_sequences() {
+		let mut enc = Encoder::new();
+		let mut buf = Vec::new();
+		open(&mut enc, &mut buf);
+		let data = "fööbär 🎉".as_bytes();
+		let mut w = enc.text_writer(&mut buf).unwrap();
+		for b in data {
+			w.write_all(core::slice::from_ref(b)).unwrap();
+		}
+		w.finish().unwrap();
+		assert_eq!(&buf[3..], data);
+	}
+
+	#[test]
+	fn rejects_invalid_utf8() {
+		let mut enc = Encoder::new();
+		let mut buf = Vec::new();
+		open(&mut enc, &mut buf);
+		let mut w = enc.text_writer(&mut buf).unwrap();
+		assert_eq!(w.write(b"ab\xffcd").unwrap(), 2);
+		match w.write(b"\xffcd") {
+			Err(e) if e.kind() == io::ErrorKind::InvalidData => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		assert_eq!(&buf[..], &b"<x>ab"[..]);
+	}
+
+	#[test]
+	fn rejects_characters_not_allowed_in_xml() {
+		let mut enc = Encoder::new();
+		let mut buf = Vec::new();
+		open(&mut enc, &mut buf);
+		let mut w = enc.text_writer(&mut buf).unwrap();
+		match w.write_all(b"ab\x01") {
+			Err(e) if e.kind() == io::ErrorKind::InvalidData => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		assert_eq!(&buf[..], &b"<x>ab"[..]);
+	}
+
+	#[test]
+	fn finish_rejects_truncated_utf8_sequence() {
+		let mut enc = Encoder::new();
+		let mut buf = Vec::new();
+		open(&mut enc, &mut buf);
+		let mut w = enc.text_writer(&mut buf).unwrap();
+		assert_eq!(w.write(b"a\xc3").unwrap(), 2);
+		match w.finish() {
+			Err(e) if e.kind() == io::ErrorKind::InvalidData => (),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn text_writer_requires_element_content() {
+		let mut enc = Encoder::new();
+		let mut buf = Vec::new();
+		match enc.text_writer(&mut buf) {
+			Err(EncodeError::TextNotAllowed) => (),
+			other => panic!("unexpected result: {:?}", other.map(|_| ())),
+		}
+		enc.encode(
+			Item::ElementHeadStart(None, "x".try_into().unwrap()),
+			&mut buf,
+		)
+		.unwrap();
+		match enc.text_writer(&mut buf) {
+			Err(EncodeError::TextNotAllowed) => (),
+			other => panic!("unexpected result: {:?}", other.map(|_| ())),
+		}
+	}
+}
@@ -0,0 +1,553 @@
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use rxml_validation::{validate_cdata, validate_ncname};
+use syn::spanned::Spanned;
+use syn::{
+	Data, DeriveInput, Fields, GenericArgument, Ident, Lit, Meta, NestedMeta, PathArguments, Type,
+};
+
+use super::{cdata_literal, ncname_literal};
+
+/// How a field is repeated.
+enum Multiplicity {
+	/// Exactly once.
+	One,
+	/// Zero or one time (`Option<T>`).
+	Optional,
+	/// Any number of times (`Vec<T>`).
+	Many,
+}
+
+/// What a field maps to.
+enum Kind {
+	Attribute {
+		namespace: Option<String>,
+		name: String,
+	},
+	Element {
+		namespace: Option<String>,
+		name: String,
+	},
+	Child,
+	Text,
+}
+
+struct Field {
+	ident: Ident,
+	kind: Kind,
+	multiplicity: Multiplicity,
+	ty: Type,
+	/// The type without any `Option` or `Vec` wrapper.
+	inner: Type,
+}
+
+struct Struct {
+	ident: Ident,
+	generics: syn::Generics,
+	namespace: Option<String>,
+	name: String,
+	fields: Vec<Field>,
+}
+
+/// Return the nested items of all `#[xml(..)]` attributes.
+fn xml_attrs(attrs: &[syn::Attribute]) -> syn::Result<Vec<NestedMeta>> {
+	let mut result = Vec::new();
+	for attr in attrs {
+		if !attr.path.is_ident("xml") {
+			continue;
+		}
+		match attr.parse_meta()? {
+			Meta::List(list) => result.extend(list.nested),
+			other => {
+				return Err(syn::Error::new(
+					other.span(),
+					"expected #[xml(..)] with a list of options",
+				))
+			}
+		}
+	}
+	Ok(result)
+}
+
+fn string_value(lit: &Lit) -> syn::Result<String> {
+	match lit {
+		Lit::Str(s) => Ok(s.value()),
+		other => Err(syn::Error::new(other.span(), "expected a string literal")),
+	}
+}
+
+fn check_name(name: &str, span: Span) -> syn::Result<()> {
+	validate_ncname(name)
+		.map_err(|e| syn::Error::new(span, format!("invalid NCName string {:?}: {}", name, e)))
+}
+
+fn check_namespace(namespace: &str, span: Span) -> syn::Result<()> {
+	if namespace.is_empty() {
+		return Err(syn::Error::new(span, "namespace URI must not be empty"));
+	}
+	validate_cdata(namespace)
+		.map_err(|e| syn::Error::new(span, format!("invalid CData string {:?}: {}", namespace, e)))
+}
+
+/// Parse `name = ".."` and `namespace = ".."` options.
+fn parse_name_options<'x, I: IntoIterator<Item = &'x NestedMeta>>(
+	nested: I,
+	namespace: &mut Option<String>,
+	name: &mut Option<String>,
+) -> syn::Result<()> {
+	for item in nested {
+		let nv = match item {
+			NestedMeta::Meta(Meta::NameValue(nv)) => nv,
+			other => return Err(syn::Error::new(other.span(), "expected `key = \"value\"`")),
+		};
+		let value = string_value(&nv.lit)?;
+		if nv.path.is_ident("name") {
+			check_name(&value, nv.lit.span())?;
+			*name = Some(value);
+		} else if nv.path.is_ident("namespace") {
+			check_namespace(&value, nv.lit.span())?;
+			*namespace = Some(value);
+		} else {
+			return Err(syn::Error::new(
+				nv.path.span(),
+				"unknown option, expected `name` or `namespace`",
+			));
+		}
+	}
+	Ok(())
+}
+
+/// If `ty` is `wrapper<T>`, return `T`.
+fn unwrap_type<'x>(ty: &'x Type, wrapper: &str) -> Option<&'x Type> {
+	let path = match ty {
+		Type::Path(p) if p.qself.is_none() => &p.path,
+		_ => return None,
+	};
+	let last = path.segments.last()?;
+	if last.ident != wrapper {
+		return None;
+	}
+	match &last.arguments {
+		PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+			GenericArgument::Type(inner) => Some(inner),
+			_ => None,
+		},
+		_ => None,
+	}
+}
+
+fn parse_field(field: &syn::Field, struct_namespace: &Option<String>) -> syn::Result<Field> {
+	let ident = field.ident.clone().unwrap();
+	let options = xml_attrs(&field.attrs)?;
+	let option = match options.len() {
+		1 => &options[0],
+		0 => {
+			return Err(syn::Error::new(
+				field.span(),
+				"field needs an #[xml(attribute)], #[xml(element)], #[xml(child)] or #[xml(text)] annotation",
+			))
+		}
+		_ => {
+			return Err(syn::Error::new(
+				options[1].span(),
+				"only one mapping may be given per field",
+			))
+		}
+	};
+
+	let (path, nested) = match option {
+		NestedMeta::Meta(Meta::Path(path)) => (path, None),
+		NestedMeta::Meta(Meta::List(list)) => (&list.path, Some(&list.nested)),
+		other => return Err(syn::Error::new(other.span(), "unexpected field option")),
+	};
+	let default_name = ident.to_string();
+	let kind = if path.is_ident("attribute") || path.is_ident("element") {
+		let mut namespace = None;
+		let mut name = None;
+		if let Some(nested) = nested {
+			parse_name_options(nested, &mut namespace, &mut name)?;
+		}
+		let name = match name {
+			Some(name) => name,
+			None => {
+				check_name(&default_name, ident.span())?;
+				default_name
+			}
+		};
+		if path.is_ident("attribute") {
+			Kind::Attribute { namespace, name }
+		} else {
+			Kind::Element {
+				namespace: namespace.or_else(|| struct_namespace.clone()),
+				name,
+			}
+		}
+	} else if path.is_ident("child") || path.is_ident("text") {
+		if let Some(nested) = nested {
+			return Err(syn::Error::new(
+				nested.span(),
+				"this mapping does not take any options",
+			));
+		}
+		if path.is_ident("child") {
+			Kind::Child
+		} else {
+			Kind::Text
+		}
+	} else {
+		return Err(syn::Error::new(
+			path.span(),
+			"unknown mapping, expected `attribute`, `element`, `child` or `text`",
+		));
+	};
+
+	let (multiplicity, inner) = if let Some(inner) = unwrap_type(&field.ty, "Option") {
+		(Multiplicity::Optional, inner.clone())
+	} else if let Some(inner) = unwrap_type(&field.ty, "Vec") {
+		(Multiplicity::Many, inner.clone())
+	} else {
+		(Multiplicity::One, field.ty.clone())
+	};
+	match (&kind, &multiplicity) {
+		(Kind::Attribute { .. }, Multiplicity::Many) => {
+			return Err(syn::Error::new(
+				field.ty.span(),
+				"attributes cannot be repeated",
+			))
+		}
+		(Kind::Text, Multiplicity::Optional) | (Kind::Text, Multiplicity::Many) => {
+			return Err(syn::Error::new(
+				field.ty.span(),
+				"text content cannot be optional or repeated",
+			))
+		}
+		_ => (),
+	}
+
+	Ok(Field {
+		ident,
+		kind,
+		multiplicity,
+		ty: field.ty.clone(),
+		inner,
+	})
+}
+
+fn parse_struct(input: DeriveInput) -> syn::Result<Struct> {
+	let mut namespace = None;
+	let mut name = None;
+	parse_name_options(&xml_attrs(&input.attrs)?, &mut namespace, &mut name)?;
+	let name = name.ok_or_else(|| {
+		syn::Error::new(
+			input.ident.span(),
+			"missing #[xml(name = \"..\")] annotation on the struct",
+		)
+	})?;
+
+	let fields = match input.data {
+		Data::Struct(syn::DataStruct {
+			fields: Fields::Named(fields),
+			..
+		}) => fields.named,
+		_ => {
+			return Err(syn::Error::new(
+				input.ident.span(),
+				"only structs with named fields are supported",
+			))
+		}
+	};
+	let fields = fields
+		.iter()
+		.map(|field| parse_field(field, &namespace))
+		.collect::<syn::Result<Vec<_>>>()?;
+	if fields
+		.iter()
+		.filter(|f| matches!(f.kind, Kind::Text))
+		.count()
+		> 1
+	{
+		return Err(syn::Error::new(
+			input.ident.span(),
+			"only one field may map to the text content",
+		));
+	}
+
+	Ok(Struct {
+		ident: input.ident,
+		generics: input.generics,
+		namespace,
+		name,
+		fields,
+	})
+}
+
+fn namespace_str(namespace: &Option<String>) -> TokenStream2 {
+	match namespace {
+		Some(ns) => quote! { Some(#ns) },
+		None => quote! { None },
+	}
+}
+
+fn namespace_ptr(namespace: &Option<String>) -> TokenStream2 {
+	match namespace {
+		Some(ns) => {
+			let ns = cdata_literal(ns);
+			quote! { Some(rxml::parser::RcPtr::new(#ns.to_cdata())) }
+		}
+		None => quote! { None },
+	}
+}
+
+fn local(field: &Field) -> Ident {
+	format_ident!("__rxml_field_{}", field.ident)
+}
+
+fn from_xml_impl(st: &Struct) -> TokenStream2 {
+	let ident = &st.ident;
+	let (impl_generics, ty_generics, where_clause) = st.generics.split_for_impl();
+	let struct_ns = namespace_str(&st.namespace);
+	let struct_name = &st.name;
+
+	let mut prologue = Vec::new();
+	let mut children = Vec::new();
+	let mut inits = Vec::new();
+	for field in st.fields.iter() {
+		let var = local(field);
+		let ty = &field.ty;
+		let inner = &field.inner;
+		let fident = &field.ident;
+		match &field.kind {
+			Kind::Attribute { namespace, name } => {
+				let ns = namespace_str(namespace);
+				let finish = match field.multiplicity {
+					Multiplicity::One => {
+						quote! { .ok_or(rxml::mapping::FromXmlError::MissingAttribute(#name))? }
+					}
+					_ => quote! {},
+				};
+				prologue.push(quote! {
+					let #var = match __rxml_el.attributes.get_by_name(#ns, #name) {
+						Some(v) => Some(
+							<#inner as rxml::mapping::FromXmlText>::from_xml_text(&v)
+								.map_err(|message| rxml::mapping::FromXmlError::InvalidText { name: #name, message })?
+						),
+						None => None,
+					}#finish;
+				});
+				inits.push(quote! { #fident: #var });
+			}
+			Kind::Text => {
+				prologue.push(quote! {
+					let #var = <#inner as rxml::mapping::FromXmlText>::from_xml_text(
+						&rxml::mapping::text_of(&__rxml_el),
+					)
+					.map_err(|message| rxml::mapping::FromXmlError::InvalidText { name: #struct_name, message })?;
+				});
+				inits.push(quote! { #fident: #var });
+			}
+			Kind::Element { .. } | Kind::Child => {
+				let label = match &field.kind {
+					Kind::Element { name, .. } => name.clone(),
+					_ => field.ident.to_string(),
+				};
+				let (decl, store, finish) = match field.multiplicity {
+					Multiplicity::Many => (
+						quote! { let mut #var: #ty = ::core::default::Default::default(); },
+						quote! { #var.push(v); },
+						quote! { #var },
+					),
+					Multiplicity::Optional => (
+						quote! { let mut #var: #ty = None; },
+						quote! {
+							if #var.is_some() {
+								return Err(rxml::mapping::FromXmlError::DuplicateElement(#label));
+							}
+							#var = Some(v);
+						},
+						quote! { #var },
+					),
+					Multiplicity::One => (
+						quote! { let mut #var: Option<#inner> = None; },
+						quote! {
+							if #var.is_some() {
+								return Err(rxml::mapping::FromXmlError::DuplicateElement(#label));
+							}
+							#var = Some(v);
+						},
+						quote! { #var.ok_or(rxml::mapping::FromXmlError::MissingElement(#label))? },
+					),
+				};
+				prologue.push(decl);
+				inits.push(quote! { #fident: #finish });
+				children.push(match &field.kind {
+					Kind::Element { namespace, name } => {
+						let ns = namespace_str(namespace);
+						quote! {
+							if rxml::mapping::has_name(&__rxml_child, #ns, #name) {
+								let v = <#inner as rxml::mapping::FromXmlText>::from_xml_text(
+									&rxml::mapping::text_of(&__rxml_child),
+								)
+								.map_err(|message| rxml::mapping::FromXmlError::InvalidText { name: #name, message })?;
+								#store
+								continue;
+							}
+						}
+					}
+					_ => quote! {
+						let __rxml_child = match <#inner as rxml::mapping::FromXml>::from_element(__rxml_child) {
+							Ok(v) => {
+								#store
+								continue;
+							}
+							Err(rxml::mapping::FromXmlError::Mismatch(el)) => *el,
+							Err(e) => return Err(e),
+						};
+					},
+				});
+			}
+		}
+	}
+
+	let child_loop = if children.is_empty() {
+		quote! {}
+	} else {
+		quote! {
+			for __rxml_child in ::core::mem::take(&mut __rxml_el.children) {
+				let __rxml_child = match __rxml_child {
+					rxml::tree::Node::Element(el) => el,
+					rxml::tree::Node::Text(_) => continue,
+				};
+				#(#children)*
+				let _ = __rxml_child;
+			}
+		}
+	};
+	let binding = if children.is_empty() {
+		quote! { __rxml_el }
+	} else {
+		quote! { mut __rxml_el }
+	};
+
+	quote! {
+		impl #impl_generics rxml::mapping::FromXml for #ident #ty_generics #where_clause {
+			fn from_element(#binding: rxml::tree::Element) -> Result<Self, rxml::mapping::FromXmlError> {
+				if !rxml::mapping::has_name(&__rxml_el, #struct_ns, #struct_name) {
+					return Err(rxml::mapping::FromXmlError::mismatch(__rxml_el));
+				}
+				#(#prologue)*
+				#child_loop
+				Ok(Self {
+					#(#inits),*
+				})
+			}
+		}
+	}
+}
+
+/// Call `body` with `v` bound to each value of the field.
+fn for_each_value(field: &Field, body: TokenStream2) -> TokenStream2 {
+	let fident = &field.ident;
+	match field.multiplicity {
+		Multiplicity::One => quote! { { let v = &self.#fident; #body } },
+		Multiplicity::Optional => quote! { if let Some(v) = &self.#fident { #body } },
+		Multiplicity::Many => quote! { for v in self.#fident.iter() { #body } },
+	}
+}
+
+fn text_value(inner: &Type, name: &str) -> TokenStream2 {
+	quote! {
+		let text = <#inner as rxml::mapping::IntoXmlText>::to_xml_text(v)
+			.map_err(|message| rxml::mapping::IntoXmlError::InvalidText { name: #name, message })?;
+	}
+}
+
+fn into_xml_impl(st: &Struct) -> TokenStream2 {
+	let ident = &st.ident;
+	let (impl_generics, ty_generics, where_clause) = st.generics.split_for_impl();
+	let struct_ns = namespace_ptr(&st.namespace);
+	let struct_name = ncname_literal(&st.name);
+
+	let mut attributes = Vec::new();
+	let mut content = Vec::new();
+	for field in st.fields.iter() {
+		let inner = &field.inner;
+		match &field.kind {
+			Kind::Attribute { namespace, name } => {
+				let text = text_value(inner, name);
+				let ns = namespace_ptr(namespace);
+				let name = ncname_literal(name);
+				attributes.push(for_each_value(
+					field,
+					quote! {
+						#text
+						encoder.encode(rxml::Item::Attribute(#ns, #name, &text), output)?;
+					},
+				));
+			}
+			Kind::Text => {
+				let text = text_value(inner, &st.name);
+				content.push(for_each_value(
+					field,
+					quote! {
+						#text
+						encoder.encode(rxml::Item::Text(&text), output)?;
+					},
+				));
+			}
+			Kind::Element { namespace, name } => {
+				let text = text_value(inner, name);
+				let ns = namespace_ptr(namespace);
+				let name = ncname_literal(name);
+				content.push(for_each_value(
+					field,
+					quote! {
+						#text
+						encoder.encode(rxml::Item::ElementHeadStart(#ns, #name), output)?;
+						encoder.encode(rxml::Item::ElementHeadEnd, output)?;
+						encoder.encode(rxml::Item::Text(&text), output)?;
+						encoder.encode(rxml::Item::ElementFoot, output)?;
+					},
+				));
+			}
+			Kind::Child => {
+				content.push(for_each_value(
+					field,
+					quote! {
+						<#inner as rxml::mapping::IntoXml>::write_xml(v, encoder, output)?;
+					},
+				));
+			}
+		}
+	}
+
+	quote! {
+		impl #impl_generics rxml::mapping::IntoXml for #ident #ty_generics #where_clause {
+			fn write_xml<__RxmlT: rxml::writer::TrackNamespace, __RxmlO: rxml::bytes::BufMut>(
+				&self,
+				encoder: &mut rxml::Encoder<__RxmlT>,
+				output: &mut __RxmlO,
+			) -> Result<(), rxml::mapping::IntoXmlError> {
+				encoder.encode(rxml::Item::ElementHeadStart(#struct_ns, #struct_name), output)?;
+				#(#attributes)*
+				encoder.encode(rxml::Item::ElementHeadEnd, output)?;
+				#(#content)*
+				encoder.encode(rxml::Item::ElementFoot, output)?;
+				Ok(())
+			}
+		}
+	}
+}
+
+pub(crate) fn derive_from_xml(input: DeriveInput) -> TokenStream2 {
+	match parse_struct(input) {
+		Ok(st) => from_xml_impl(&st),
+		Err(e) => e.to_compile_error(),
+	}
+}
+
+pub(crate) fn derive_into_xml(input: DeriveInput) -> TokenStream2 {
+	match parse_struct(input) {
+		Ok(st) => into_xml_impl(&st),
+		Err(e) => e.to_compile_error(),
+	}
+}
@@ -3,6 +3,11 @@
 
 This crate provides macros to check XML string syntax at compile time.
 
+The [`xml!`](xml!) macro goes one step further and converts a piece of markup
+into the items which the `rxml` encoder needs to serialize it, and the
+[`FromXml`](derive@FromXml) and [`IntoXml`](derive@IntoXml) derive macros
+map structs to and from XML elements.
+
 ## Example
 
 ```rust,ignore
@@ -18,10 +23,17 @@ const XML_PREFIX: &'static NCNameStr = xml_ncname!("xml");
 This crate bases on the [`rxml_validation`] crate and it primarily intended
 for use with the [`rxml`](https://docs.rs/rxml) crate.
 */
+mod derive;
+mod markup;
+
 use proc_macro::TokenStream;
-use quote::quote;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
 use rxml_validation::{validate_cdata, validate_name, validate_ncname};
-use syn::{parse_macro_input, LitStr};
+use syn::parse::{Parse, ParseStream};
+use syn::{parse_macro_input, DeriveInput, Expr, LitStr, Token};
+
+use markup::{Piece, Value};
 
 /** XML 1.0 CData compliant string
 
@@ -38,7 +50,7 @@ pub fn xml_cdata(input: TokenStream) -> TokenStream {
 	let data = parse_macro_input!(input as LitStr);
 	let s = data.value();
 	let tokens = match validate_cdata(&s) {
-		Ok(()) => quote! { unsafe { std::mem::transmute::<_, &rxml::CDataStr>(#s) } },
+		Ok(()) => quote! { unsafe { ::core::mem::transmute::<_, &rxml::CDataStr>(#s) } },
 		Err(e) => {
 			let err = format!("invalid CData string {:?}: {}", s, e);
 			quote! { compile_error!(#err) }
@@ -62,7 +74,7 @@ pub fn xml_name(input: TokenStream) -> TokenStream {
 	let data = parse_macro_input!(input as LitStr);
 	let s = data.value();
 	let tokens = match validate_name(&s) {
-		Ok(()) => quote! { unsafe { std::mem::transmute::<_, &rxml::NameStr>(#s) } },
+		Ok(()) => quote! { unsafe { ::core::mem::transmute::<_, &rxml::NameStr>(#s) } },
 		Err(e) => {
 			let err = format!("invalid Name string {:?}: {}", s, e);
 			quote! { compile_error!(#err) }
@@ -86,7 +98,7 @@ pub fn xml_ncname(input: TokenStream) -> TokenStream {
 	let data = parse_macro_input!(input as LitStr);
 	let s = data.value();
 	let tokens = match validate_ncname(&s) {
-		Ok(()) => quote! { unsafe { std::mem::transmute::<_, &rxml::NcNameStr>(#s) } },
+		Ok(()) => quote! { unsafe { ::core::mem::transmute::<_, &rxml::NcNameStr>(#s) } },
 		Err(e) => {
 			let err = format!("invalid NCName string {:?}: {}", s, e);
 			quote! { compile_error!(#err) }
@@ -94,3 +106,272 @@ pub fn xml_ncname(input: TokenStream) -> TokenStream {
 	};
 	tokens.into()
 }
+
+/// Input of the [`xml_qname!`](xml_qname!) macro: an optional namespace
+/// URI, followed by a local name.
+struct QNameInput {
+	namespace: Option<LitStr>,
+	local_name: LitStr,
+}
+
+impl Parse for QNameInput {
+	fn parse(input: ParseStream) -> syn::Result<Self> {
+		let first: LitStr = input.parse()?;
+		if input.is_empty() {
+			return Ok(Self {
+				namespace: None,
+				local_name: first,
+			});
+		}
+		input.parse::<Token![,]>()?;
+		let local_name = input.parse()?;
+		if !input.is_empty() {
+			input.parse::<Token![,]>()?;
+		}
+		Ok(Self {
+			namespace: Some(first),
+			local_name,
+		})
+	}
+}
+
+/** Namespace-qualified name constant
+
+Expands to a `rxml::QNameRef`, after checking that the namespace URI is
+valid CData and that the local name is a valid NCName. With a single
+argument, the name has no namespace.
+
+# Example
+
+```rust,ignore
+use rxml::QNameRef;
+use rxml_proc::xml_qname;
+
+const MESSAGE: QNameRef<'static> = xml_qname!("jabber:client", "message");
+const ID: QNameRef<'static> = xml_qname!("id");
+*/
+#[proc_macro]
+pub fn xml_qname(input: TokenStream) -> TokenStream {
+	let input = parse_macro_input!(input as QNameInput);
+	let namespace = match input.namespace {
+		Some(ns) => {
+			let s = ns.value();
+			if s.is_empty() {
+				return syn::Error::new(ns.span(), "namespace URI must not be empty")
+					.to_compile_error()
+					.into();
+			}
+			if let Err(e) = validate_cdata(&s) {
+				let err = format!("invalid CData string {:?}: {}", s, e);
+				return syn::Error::new(ns.span(), err).to_compile_error().into();
+			}
+			let ns = cdata_literal(&s);
+			quote! { Some(#ns) }
+		}
+		None => quote! { None },
+	};
+	let s = input.local_name.value();
+	if let Err(e) = validate_ncname(&s) {
+		let err = format!("invalid NCName string {:?}: {}", s, e);
+		return syn::Error::new(input.local_name.span(), err)
+			.to_compile_error()
+			.into();
+	}
+	let local_name = ncname_literal(&s);
+	let tokens = quote! { rxml::parser::QNameRef::new(#namespace, #local_name) };
+	tokens.into()
+}
+
+/// Input of the [`xml!`](xml!) macro: a template, followed by the arguments
+/// for its placeholders.
+struct XmlInput {
+	template: LitStr,
+	args: Vec<Expr>,
+}
+
+impl Parse for XmlInput {
+	fn parse(input: ParseStream) -> syn::Result<Self> {
+		let template = input.parse()?;
+		let mut args = Vec::new();
+		while !input.is_empty() {
+			input.parse::<Token![,]>()?;
+			if input.is_empty() {
+				break;
+			}
+			args.push(input.parse()?);
+		}
+		Ok(Self { template, args })
+	}
+}
+
+fn cdata_literal(s: &str) -> TokenStream2 {
+	quote! { unsafe { ::core::mem::transmute::<_, &rxml::CDataStr>(#s) } }
+}
+
+fn ncname_literal(s: &str) -> TokenStream2 {
+	quote! { unsafe { ::core::mem::transmute::<_, &rxml::NcNameStr>(#s) } }
+}
+
+fn namespace(index: Option<usize>) -> TokenStream2 {
+	match index {
+		Some(i) => {
+			let ident = format_ident!("__rxml_ns{}", i);
+			quote! { Some(rxml::parser::RcPtr::clone(&#ident)) }
+		}
+		None => quote! { None },
+	}
+}
+
+fn value(v: &Value) -> TokenStream2 {
+	match v {
+		Value::Literal(s) => cdata_literal(s),
+		Value::Arg(i) => {
+			let ident = format_ident!("__rxml_arg{}", i);
+			quote! { #ident }
+		}
+	}
+}
+
+/** Sequence of encoder items from XML markup
+
+See the documentation of the `xml!` macro in the `rxml` crate for details.
+
+# Example
+
+```rust,ignore
+use rxml::{CData, Encoder};
+use rxml_proc::xml;
+
+let name = CData::try_from("World").unwrap();
+let mut enc = Encoder::new();
+let mut buf = Vec::new();
+for item in xml!("<hello>{}</hello>", &name) {
+	enc.encode(item, &mut buf).unwrap();
+}
+```
+*/
+#[proc_macro]
+pub fn xml(input: TokenStream) -> TokenStream {
+	let input = parse_macro_input!(input as XmlInput);
+	let template = match markup::parse(&input.template.value()) {
+		Ok(v) => v,
+		Err(msg) => {
+			let msg = format!("invalid xml! template: {}", msg);
+			return syn::Error::new(input.template.span(), msg)
+				.to_compile_error()
+				.into();
+		}
+	};
+	if template.nargs != input.args.len() {
+		let msg = format!(
+			"xml! template has {} placeholders, but {} arguments were given",
+			template.nargs,
+			input.args.len()
+		);
+		return syn::Error::new(input.template.span(), msg)
+			.to_compile_error()
+			.into();
+	}
+
+	let args = input.args.iter().enumerate().map(|(i, expr)| {
+		let ident = format_ident!("__rxml_arg{}", i);
+		quote! { let #ident: &rxml::CDataStr = &*(#expr); }
+	});
+	let namespaces = template.namespaces.iter().enumerate().map(|(i, uri)| {
+		let ident = format_ident!("__rxml_ns{}", i);
+		let uri = cdata_literal(uri);
+		quote! { let #ident = rxml::parser::RcPtr::new(#uri.to_cdata()); }
+	});
+	let items = template.pieces.iter().map(|piece| match piece {
+		Piece::XmlDeclaration => {
+			quote! { rxml::Item::XmlDeclaration(rxml::XmlVersion::V1_0) }
+		}
+		Piece::NamespaceDeclaration(prefix, ns) => {
+			let prefix = match prefix {
+				Some(prefix) => {
+					let prefix = ncname_literal(prefix);
+					quote! { Some(#prefix) }
+				}
+				None => quote! { None },
+			};
+			let ns = namespace(*ns);
+			quote! { rxml::Item::NamespaceDeclaration(#prefix, #ns) }
+		}
+		Piece::ElementHeadStart(ns, localname) => {
+			let ns = namespace(*ns);
+			let localname = ncname_literal(localname);
+			quote! { rxml::Item::ElementHeadStart(#ns, #localname) }
+		}
+		Piece::Attribute(ns, localname, v) => {
+			let ns = namespace(*ns);
+			let localname = ncname_literal(localname);
+			let v = value(v);
+			quote! { rxml::Item::Attribute(#ns, #localname, #v) }
+		}
+		Piece::ElementHeadEnd => quote! { rxml::Item::ElementHeadEnd },
+		Piece::Text(v) => {
+			let v = value(v);
+			quote! { rxml::Item::Text(#v) }
+		}
+		Piece::ElementFoot => quote! { rxml::Item::ElementFoot },
+	});
+
+	let tokens = quote! {
+		{
+			#(#args)*
+			#(#namespaces)*
+			[#(#items),*]
+		}
+	};
+	tokens.into()
+}
+
+/** Conversion of an element into a struct
+
+Implements `rxml::mapping::FromXml`. See the documentation of the
+`rxml::mapping` module for the supported `#[xml(..)]` annotations.
+
+# Example
+
+```rust,ignore
+use rxml_proc::FromXml;
+
+#[derive(FromXml)]
+#[xml(namespace = "urn:example", name = "greeting")]
+struct Greeting {
+	#[xml(attribute)]
+	lang: Option<String>,
+	#[xml(text)]
+	text: String,
+}
+```
+*/
+#[proc_macro_derive(FromXml, attributes(xml))]
+pub fn derive_from_xml(input: TokenStream) -> TokenStream {
+	derive::derive_from_xml(parse_macro_input!(input as DeriveInput)).into()
+}
+
+/** Conversion of a struct into an element
+
+Implements `rxml::mapping::IntoXml`. See the documentation of the
+`rxml::mapping` module for the supported `#[xml(..)]` annotations.
+
+# Example
+
+```rust,ignore
+use rxml_proc::IntoXml;
+
+#[derive(IntoXml)]
+#[xml(namespace = "urn:example", name = "greeting")]
+struct Greeting {
+	#[xml(attribute)]
+	lang: Option<String>,
+	#[xml(text)]
+	text: String,
+}
+```
+*/
+#[proc_macro_derive(IntoXml, attributes(xml))]
+pub fn derive_into_xml(input: TokenStream) -> TokenStream {
+	derive::derive_into_xml(parse_macro_input!(input as DeriveInput)).into()
+}
@@ -0,0 +1,492 @@
+/*!
+# Parser for `xml!` templates
+
+The templates are a restricted subset of XML 1.0, parsed at compile time:
+an optional XML declaration followed by any number of elements. Namespace
+prefixes are resolved here, so that the generated items only need to refer
+to namespace names.
+*/
+use rxml_validation::{validate_cdata, validate_ncname};
+
+const XMLNS_XML: &str = "http://www.w3.org/XML/1998/namespace";
+const XMLNS_XMLNS: &str = "http://www.w3.org/2000/xmlns/";
+
+/// Value of a text node or an attribute.
+pub(crate) enum Value {
+	/// Literal text, with references already expanded.
+	Literal(String),
+	/// Placeholder, referring to the argument with the given index.
+	Arg(usize),
+}
+
+/// A single item of the template.
+///
+/// Namespace names are referred to by their index in
+/// [`Template::namespaces`].
+pub(crate) enum Piece {
+	XmlDeclaration,
+	NamespaceDeclaration(Option<String>, Option<usize>),
+	ElementHeadStart(Option<usize>, String),
+	Attribute(Option<usize>, String, Value),
+	ElementHeadEnd,
+	Text(Value),
+	ElementFoot,
+}
+
+pub(crate) struct Template {
+	pub(crate) pieces: Vec<Piece>,
+	pub(crate) namespaces: Vec<String>,
+	pub(crate) nargs: usize,
+}
+
+#[derive(Default)]
+struct Scope {
+	default: Option<Option<usize>>,
+	prefixes: Vec<(String, usize)>,
+}
+
+struct MarkupParser<'x> {
+	s: &'x str,
+	pos: usize,
+	scopes: Vec<Scope>,
+	template: Template,
+}
+
+/// Parse a template.
+///
+/// Errors are returned as human readable messages.
+pub(crate) fn parse(s: &str) -> Result<Template, String> {
+	let mut parser = MarkupParser {
+		s,
+		pos: 0,
+		scopes: Vec::new(),
+		template: Template {
+			pieces: Vec::new(),
+			namespaces: Vec::new(),
+			nargs: 0,
+		},
+	};
+	match parser.document() {
+		Ok(()) => Ok(parser.template),
+		Err(msg) => Err(format!("{} (at byte {})", msg, parser.pos)),
+	}
+}
+
+fn is_space(ch: char) -> bool {
+	matches!(ch, ' ' | '\t' | '\r' | '\n')
+}
+
+fn split_qname(name: &str) -> Result<(Option<&str>, &str), String> {
+	let (prefix, local) = match name.find(':') {
+		Some(i) => (Some(&name[..i]), &name[i + 1..]),
+		None => (None, name),
+	};
+	for part in prefix.iter().chain(Some(&local)) {
+		if let Err(e) = validate_ncname(part) {
+			return Err(format!("invalid name {:?}: {}", name, e));
+		}
+	}
+	Ok((prefix, local))
+}
+
+impl<'x> MarkupParser<'x> {
+	fn rest(&self) -> &'x str {
+		&self.s[self.pos..]
+	}
+
+	fn eat(&mut self, lit: &str) -> bool {
+		if self.rest().starts_with(lit) {
+			self.pos += lit.len();
+			true
+		} else {
+			false
+		}
+	}
+
+	fn expect(&mut self, lit: &str) -> Result<(), String> {
+		if self.eat(lit) {
+			Ok(())
+		} else {
+			Err(format!("expected `{}`", lit))
+		}
+	}
+
+	fn next_char(&mut self) -> Option<char> {
+		let ch = self.rest().chars().next()?;
+		self.pos += ch.len_utf8();
+		Some(ch)
+	}
+
+	/// Skip whitespace and return whether there was any.
+	fn skip_space(&mut self) -> bool {
+		let start = self.pos;
+		while self.rest().starts_with(is_space) {
+			self.pos += 1;
+		}
+		self.pos > start
+	}
+
+	fn name(&mut self) -> Result<&'x str, String> {
+		let rest = self.rest();
+		let len = rest
+			.find(|ch: char| is_space(ch) || "=/><'\"".contains(ch))
+			.unwrap_or(rest.len());
+		if len == 0 {
+			return Err("expected a name".into());
+		}
+		self.pos += len;
+		Ok(&rest[..len])
+	}
+
+	fn next_arg(&mut self) -> usize {
+		self.template.nargs += 1;
+		self.template.nargs - 1
+	}
+
+	fn intern_namespace(&mut self, uri: &str) -> usize {
+		match self.template.namespaces.iter().position(|x| x == uri) {
+			Some(i) => i,
+			None => {
+				self.template.namespaces.push(uri.into());
+				self.template.namespaces.len() - 1
+			}
+		}
+	}
+
+	/// Expand a reference, with the leading `&` already consumed.
+	fn reference(&mut self, into: &mut String) -> Result<(), String> {
+		let rest = self.rest();
+		let end = match rest.find(';') {
+			Some(end) => end,
+			None => return Err("unterminated reference".into()),
+		};
+		let name = &rest[..end];
+		let ch = match name {
+			"amp" => Some('&'),
+			"lt" => Some('<'),
+			"gt" => Some('>'),
+			"apos" => Some('\''),
+			"quot" => Some('"'),
+			_ if name.starts_with("#x") => u32::from_str_radix(&name[2..], 16)
+				.ok()
+				.and_then(char::from_u32),
+			_ if name.starts_with('#') => name[1..].parse().ok().and_then(char::from_u32),
+			_ => return Err(format!("undeclared entity `&{};`", name)),
+		};
+		match ch {
+			Some(ch) => into.push(ch),
+			None => return Err(format!("invalid character reference `&{};`", name)),
+		}
+		self.pos += end + 1;
+		Ok(())
+	}
+
+	fn literal(&self, s: String) -> Result<Value, String> {
+		match validate_cdata(&s) {
+			Ok(()) => Ok(Value::Literal(s)),
+			Err(e) => Err(format!("invalid text {:?}: {}", s, e)),
+		}
+	}
+
+	/// Parse a quoted attribute value.
+	///
+	/// A placeholder is only allowed if it makes up the whole value.
+	fn attribute_value(&mut self) -> Result<Value, String> {
+		let quote = match self.next_char() {
+			Some(ch @ '\'') | Some(ch @ '"') => ch,
+			_ => return Err("expected a quoted value".into()),
+		};
+		if self.eat("{}") {
+			if self.next_char() != Some(quote) {
+				return Err("placeholders must make up the whole attribute value".into());
+			}
+			return Ok(Value::Arg(self.next_arg()));
+		}
+		let mut value = String::new();
+		loop {
+			match self.next_char() {
+				None => return Err("unterminated attribute value".into()),
+				Some(ch) if ch == quote => break,
+				Some('&') => self.reference(&mut value)?,
+				Some('<') => return Err("`<` is not allowed in attribute values".into()),
+				Some('{') if self.eat("{") => value.push('{'),
+				Some('}') if self.eat("}") => value.push('}'),
+				Some('{') | Some('}') => {
+					return Err("placeholders must make up the whole attribute value".into())
+				}
+				Some(ch) => value.push(ch),
+			}
+		}
+		self.literal(value)
+	}
+
+	fn document(&mut self) -> Result<(), String> {
+		self.skip_space();
+		if self.eat("<?xml") {
+			self.xml_declaration()?;
+		}
+		loop {
+			self.skip_space();
+			if self.rest().is_empty() {
+				break;
+			}
+			if self.rest().starts_with("<!") || self.rest().starts_with("<?") {
+				return Err("comments, processing instructions and DTDs are not supported".into());
+			}
+			if !self.eat("<") || self.rest().starts_with('/') {
+				return Err("only elements are allowed at the top level".into());
+			}
+			self.element()?;
+		}
+		if self.template.pieces.is_empty() {
+			return Err("the template must contain at least one element".into());
+		}
+		Ok(())
+	}
+
+	/// Parse an XML declaration, with the leading `<?xml` already consumed.
+	fn xml_declaration(&mut self) -> Result<(), String> {
+		let mut has_version = false;
+		loop {
+			let had_space = self.skip_space();
+			if self.eat("?>") {
+				break;
+			}
+			if !had_space {
+				return Err("expected whitespace".into());
+			}
+			let name = self.name()?;
+			self.skip_space();
+			self.expect("=")?;
+			self.skip_space();
+			let value = match self.attribute_value()? {
+				Value::Literal(v) => v,
+				Value::Arg(_) => {
+					return Err("placeholders are not allowed in the XML declaration".into())
+				}
+			};
+			match name {
+				"version" if value == "1.0" => has_version = true,
+				"encoding" if value.eq_ignore_ascii_case("utf-8") => (),
+				"standalone" if value == "yes" || value == "no" => (),
+				_ => {
+					return Err(format!(
+						"unsupported XML declaration attribute {}={:?}",
+						name, value
+					))
+				}
+			}
+		}
+		if !has_version {
+			return Err("the XML declaration must specify version='1.0'".into());
+		}
+		self.template.pieces.push(Piece::XmlDeclaration);
+		Ok(())
+	}
+
+	fn lookup(&mut self, prefix: Option<&str>) -> Result<Option<usize>, String> {
+		match prefix {
+			None => Ok(self
+				.scopes
+				.iter()
+				.rev()
+				.find_map(|scope| scope.default)
+				.unwrap_or(None)),
+			Some("xml") => Ok(Some(self.intern_namespace(XMLNS_XML))),
+			Some(prefix) => {
+				for scope in self.scopes.iter().rev() {
+					for (declared, index) in scope.prefixes.iter() {
+						if declared == prefix {
+							return Ok(Some(*index));
+						}
+					}
+				}
+				Err(format!("undeclared namespace prefix `{}`", prefix))
+			}
+		}
+	}
+
+	/// Process the namespace declarations among `attributes` into a new
+	/// scope and return the remaining attributes.
+	fn declare(
+		&mut self,
+		attributes: Vec<(&'x str, Value)>,
+	) -> Result<Vec<(&'x str, Value)>, String> {
+		let mut scope = Scope::default();
+		let mut remaining = Vec::new();
+		for (name, value) in attributes {
+			let prefix = if name == "xmlns" {
+				None
+			} else if let Some(prefix) = name.strip_prefix("xmlns:") {
+				Some(prefix)
+			} else {
+				remaining.push((name, value));
+				continue;
+			};
+			let uri = match value {
+				Value::Literal(uri) => uri,
+				Value::Arg(_) => {
+					return Err("placeholders are not allowed in namespace declarations".into())
+				}
+			};
+			match prefix {
+				Some("xml") if uri == XMLNS_XML => continue,
+				Some("xml") | Some("xmlns") => {
+					return Err(format!(
+						"the `{}` prefix cannot be declared",
+						prefix.unwrap()
+					))
+				}
+				Some(prefix) => {
+					if let Err(e) = validate_ncname(prefix) {
+						return Err(format!("invalid namespace prefix {:?}: {}", prefix, e));
+					}
+					if uri.is_empty() {
+						return Err(format!("prefix `{}` cannot be undeclared", prefix));
+					}
+				}
+				None => (),
+			}
+			if uri == XMLNS_XML || uri == XMLNS_XMLNS {
+				return Err(format!("namespace name {:?} cannot be declared", uri));
+			}
+			let index = if uri.is_empty() {
+				None
+			} else {
+				Some(self.intern_namespace(&uri))
+			};
+			match prefix {
+				Some(prefix) => scope.prefixes.push((prefix.into(), index.unwrap())),
+				None => scope.default = Some(index),
+			}
+			self.template
+				.pieces
+				.push(Piece::NamespaceDeclaration(prefix.map(Into::into), index));
+		}
+		self.scopes.push(scope);
+		Ok(remaining)
+	}
+
+	/// Parse an element, with the leading `<` already consumed.
+	fn element(&mut self) -> Result<(), String> {
+		let name = self.name()?;
+		let mut attributes: Vec<(&str, Value)> = Vec::new();
+		let empty = loop {
+			let had_space = self.skip_space();
+			if self.eat("/>") {
+				break true;
+			}
+			if self.eat(">") {
+				break false;
+			}
+			if !had_space {
+				return Err("expected whitespace".into());
+			}
+			let attrname = self.name()?;
+			if attributes.iter().any(|(other, _)| *other == attrname) {
+				return Err(format!("duplicate attribute `{}`", attrname));
+			}
+			self.skip_space();
+			self.expect("=")?;
+			self.skip_space();
+			attributes.push((attrname, self.attribute_value()?));
+		};
+
+		let attributes = self.declare(attributes)?;
+		let (prefix, localname) = split_qname(name)?;
+		let ns = self.lookup(prefix)?;
+		self.template
+			.pieces
+			.push(Piece::ElementHeadStart(ns, localname.into()));
+		let mut seen = Vec::with_capacity(attributes.len());
+		for (attrname, value) in attributes {
+			let (prefix, localname) = split_qname(attrname)?;
+			// unprefixed attributes are never in the default namespace
+			let ns = match prefix {
+				Some(_) => self.lookup(prefix)?,
+				None => None,
+			};
+			if seen.contains(&(ns, localname)) {
+				return Err(format!(
+					"duplicate attribute `{}` after namespace resolution",
+					attrname
+				));
+			}
+			seen.push((ns, localname));
+			self.template
+				.pieces
+				.push(Piece::Attribute(ns, localname.into(), value));
+		}
+
+		if !empty {
+			self.template.pieces.push(Piece::ElementHeadEnd);
+			self.content(name)?;
+		}
+		self.template.pieces.push(Piece::ElementFoot);
+		self.scopes.pop();
+		Ok(())
+	}
+
+	fn flush_text(&mut self, text: &mut String) -> Result<(), String> {
+		if !text.is_empty() {
+			let value = self.literal(core::mem::take(text))?;
+			self.template.pieces.push(Piece::Text(value));
+		}
+		Ok(())
+	}
+
+	/// Parse the content of the element `name` up to and including its end
+	/// tag.
+	fn content(&mut self, name: &str) -> Result<(), String> {
+		let mut text = String::new();
+		loop {
+			if self.rest().is_empty() {
+				return Err(format!("element `{}` is not closed", name));
+			}
+			if self.eat("</") {
+				self.flush_text(&mut text)?;
+				let end = self.name()?;
+				self.skip_space();
+				self.expect(">")?;
+				if end != name {
+					return Err(format!(
+						"end tag `{}` does not match start tag `{}`",
+						end, name
+					));
+				}
+				return Ok(());
+			}
+			if self.eat("<![CDATA[") {
+				match self.rest().find("]]>") {
+					Some(end) => {
+						text.push_str(&self.rest()[..end]);
+						self.pos += end + 3;
+					}
+					None => return Err("unterminated CDATA section".into()),
+				}
+				continue;
+			}
+			if self.rest().starts_with("<!") || self.rest().starts_with("<?") {
+				return Err("comments, processing instructions and DTDs are not supported".into());
+			}
+			if self.eat("<") {
+				self.flush_text(&mut text)?;
+				self.element()?;
+				continue;
+			}
+			if self.eat("{}") {
+				self.flush_text(&mut text)?;
+				let arg = self.next_arg();
+				self.template.pieces.push(Piece::Text(Value::Arg(arg)));
+				continue;
+			}
+			match self.next_char().unwrap() {
+				'&' => self.reference(&mut text)?,
+				'{' if self.eat("{") => text.push('{'),
+				'}' if self.eat("}") => text.push('}'),
+				'{' | '}' => return Err("unmatched `{` or `}`, use `{{` or `}}`".into()),
+				ch => text.push(ch),
+			}
+		}
+	}
+}
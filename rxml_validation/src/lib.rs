@@ -5,8 +5,12 @@ This is a supplementary crate for [`rxml`](https://docs.rs/rxml). It is
 factored out of the main crate to support
 [`rxml_proc`](https://docs.rs/rxml_proc), a crate of macros which allow
 compile-time validation and typing of XML strings.
+
+This crate does not require `std`; disable the default `std` feature to use
+it in `no_std` environments.
 */
-use std::fmt;
+#![cfg_attr(not(feature = "std"), no_std)]
+use core::fmt;
 
 pub mod selectors;
 
@@ -34,6 +38,7 @@ impl fmt::Display for Error {
 	}
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for Error {}
 
 /**
@@ -119,6 +124,104 @@ pub fn validate_cdata(s: &str) -> Result<(), Error> {
 	Ok(())
 }
 
+/// Decode the codepoint starting at byte `i` of a valid UTF-8 sequence.
+///
+/// Returns the codepoint and the index of the next codepoint.
+const fn decode_utf8(b: &[u8], i: usize) -> (u32, usize) {
+	let b0 = b[i] as u32;
+	if b0 < 0x80 {
+		(b0, i + 1)
+	} else if b0 < 0xe0 {
+		(((b0 & 0x1f) << 6) | (b[i + 1] as u32 & 0x3f), i + 2)
+	} else if b0 < 0xf0 {
+		(
+			((b0 & 0x0f) << 12) | ((b[i + 1] as u32 & 0x3f) << 6) | (b[i + 2] as u32 & 0x3f),
+			i + 3,
+		)
+	} else {
+		(
+			((b0 & 0x07) << 18)
+				| ((b[i + 1] as u32 & 0x3f) << 12)
+				| ((b[i + 2] as u32 & 0x3f) << 6)
+				| (b[i + 3] as u32 & 0x3f),
+			i + 4,
+		)
+	}
+}
+
+const fn is_valid_name_impl(s: &str, allow_colon: bool) -> bool {
+	let b = s.as_bytes();
+	if b.is_empty() {
+		return false;
+	}
+	let (cp, mut i) = decode_utf8(b, 0);
+	if !selectors::codepoint_in_ranges(cp, selectors::VALID_XML_NAME_START_RANGES)
+		|| (!allow_colon && cp == ':' as u32)
+	{
+		return false;
+	}
+	while i < b.len() {
+		let (cp, next) = decode_utf8(b, i);
+		if !selectors::codepoint_in_ranges(cp, selectors::VALID_XML_NAME_RANGES)
+			|| (!allow_colon && cp == ':' as u32)
+		{
+			return false;
+		}
+		i = next;
+	}
+	true
+}
+
+/**
+Check whether a str is a valid XML 1.0 Name, in a const context.
+
+This is equivalent to [`validate_name`], but only returns a boolean, which
+makes it usable for compile-time checks.
+
+# Example
+
+```rust
+use rxml_validation::is_valid_name;
+
+const VALID: bool = is_valid_name("foo:bar");
+assert!(VALID);
+assert!(!is_valid_name("foo bar"));
+```
+*/
+pub const fn is_valid_name(s: &str) -> bool {
+	is_valid_name_impl(s, true)
+}
+
+/**
+Check whether a str is a valid XML 1.0 Name without colons, in a const
+context.
+
+This is equivalent to [`validate_ncname`], but only returns a boolean, which
+makes it usable for compile-time checks.
+*/
+pub const fn is_valid_ncname(s: &str) -> bool {
+	is_valid_name_impl(s, false)
+}
+
+/**
+Check whether a str is valid XML 1.0 CData, in a const context.
+
+This is equivalent to [`validate_cdata`], but only returns a boolean, which
+makes it usable for compile-time checks.
+*/
+pub const fn is_valid_cdata(s: &str) -> bool {
+	let b = s.as_bytes();
+	let mut i = 0;
+	while i < b.len() {
+		let (cp, next) = decode_utf8(b, i);
+		if !selectors::codepoint_in_ranges(cp, selectors::VALID_XML_CDATA_RANGES) {
+			return false;
+		}
+		i = next;
+	}
+	true
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -146,4 +249,44 @@ mod tests {
 		assert!(validate_ncname("foo bar baz http://<xyz>").is_err());
 		assert!(validate_ncname("\u{ffff}").is_err());
 	}
+
+	#[test]
+	fn test_const_validators_agree_with_validators() {
+		let samples = &[
+			"",
+			"foobar",
+			"foo:bar",
+			":",
+			"-foo",
+			"foo-bar.baz",
+			"foo bar",
+			"\u{b7}foo",
+			"foo\u{b7}",
+			"ä€𝄞",
+			"\u{10000}x",
+			"\u{effff}",
+			"\u{f0000}",
+			"\u{ffff}",
+			"\x01",
+			"\t\r\n",
+			"http://<xyz>",
+		];
+		for s in samples {
+			assert_eq!(is_valid_name(s), validate_name(s).is_ok(), "{:?}", s);
+			assert_eq!(is_valid_ncname(s), validate_ncname(s).is_ok(), "{:?}", s);
+			assert_eq!(is_valid_cdata(s), validate_cdata(s).is_ok(), "{:?}", s);
+		}
+	}
+
+	#[test]
+	fn test_const_cdata_validator_agrees_on_all_chars() {
+		let mut buf = [0u8; 4];
+		for cp in 0x0..=0x10ffffu32 {
+			if let Some(ch) = core::char::from_u32(cp) {
+				let s = ch.encode_utf8(&mut buf);
+				assert_eq!(is_valid_cdata(s), validate_cdata(s).is_ok(), "U+{:x}", cp);
+				assert_eq!(is_valid_name(s), validate_name(s).is_ok(), "U+{:x}", cp);
+			}
+		}
+	}
 }
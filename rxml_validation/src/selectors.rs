@@ -4,7 +4,7 @@
 The contents of this module are implementation details of `rxml`,
 `rxml_validation` and `rxml_proc` and should not be relied upon.
 */
-use std::fmt;
+use core::fmt;
 
 /**
 # Predicate trait for matching chars
@@ -66,7 +66,7 @@ pub const INVALID_XML_CDATA_RANGES: &'static [CodepointRange] = &[
 	CodepointRange('\u{fffe}', '\u{ffff}'),
 ];
 
-const VALID_XML_NAME_START_RANGES: &'static [CodepointRange] = &[
+pub(crate) const VALID_XML_NAME_START_RANGES: &[CodepointRange] = &[
 	CodepointRange(':', ':'),
 	CodepointRange('A', 'Z'),
 	CodepointRange('_', '_'),
@@ -84,7 +84,7 @@ const VALID_XML_NAME_START_RANGES: &'static [CodepointRange] = &[
 	CodepointRange('\u{10000}', '\u{effff}'),
 ];
 
-const VALID_XML_NAME_RANGES: &'static [CodepointRange] = &[
+pub(crate) const VALID_XML_NAME_RANGES: &[CodepointRange] = &[
 	CodepointRange(':', ':'),
 	CodepointRange('-', '-'),
 	CodepointRange('.', '.'),
@@ -148,6 +148,19 @@ pub fn contained_in_ranges(c: char, rs: &[CodepointRange]) -> bool {
 	false
 }
 
+/// Const-evaluable variant of [`contained_in_ranges`], operating on
+/// codepoints instead of chars.
+pub const fn codepoint_in_ranges(cp: u32, rs: &[CodepointRange]) -> bool {
+	let mut i = 0;
+	while i < rs.len() {
+		if rs[i].0 as u32 <= cp && cp <= rs[i].1 as u32 {
+			return true;
+		}
+		i += 1;
+	}
+	false
+}
+
 impl fmt::Debug for CodepointRanges {
 	fn fmt<'f>(&self, f: &'f mut fmt::Formatter) -> fmt::Result {
 		write!(f, "CodepointRanges(<{} ranges>)", self.0.len())
@@ -162,7 +175,7 @@ impl Clone for CodepointRanges {
 
 impl PartialEq for CodepointRanges {
 	fn eq(&self, other: &CodepointRanges) -> bool {
-		std::ptr::eq(&self.0, &other.0)
+		core::ptr::eq(&self.0, &other.0)
 	}
 }
 
@@ -175,7 +188,7 @@ mod tests {
 		let excluder = CodepointRanges(INVALID_XML_CDATA_RANGES);
 		let includer = CodepointRanges(VALID_XML_CDATA_RANGES);
 		for cp in 0x0..=0x10ffffu32 {
-			if let Some(ch) = std::char::from_u32(cp) {
+			if let Some(ch) = core::char::from_u32(cp) {
 				if !includer.select(ch) != excluder.select(ch) {
 					panic!("INVALID_XML_CDATA_RANGES and VALID_XML_CDATA_RANGES have different opinions about U+{:x}", cp)
 				}